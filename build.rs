@@ -0,0 +1,5 @@
+fn main() {
+	// The gRPC service is generated from the proto definitions shipped in the crate
+	#[cfg(feature = "grpc")]
+	tonic_build::compile_protos("proto/lnnode.proto").expect("failed to compile the gRPC protos");
+}
@@ -1,187 +1,1284 @@
 #[allow(unused_variables)]
+use bitcoin::secp256k1::PublicKey;
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use lightning_invoice::Invoice;
+use lnnode::auth;
+use lnnode::autopilot::AutopilotStatus;
+use lnnode::node_var::{ChannelLabel, ChannelPolicy, NodeAnnouncementInfo, ScorerParams};
 use lnnode::server::{
-	Help, ListChannels, ListPeers, NodeInfo, Payments, ServerInvoice, ServerSuccess,
+	BalanceSummary, ChannelBackupExport, ChannelBalanceHistory, ChannelDiagnostics, ClosedChannels,
+	ClosingChannels, CreatedInvoice, DecodedInvoice, FeeRates, FeeReport, Forwards, Health, Help,
+	Invoices, JournaledEvents, ListChannels, ListPeers, ListUtxos, ListWebhooks, LspResponse,
+	NodeInfo, OnchainAddress, OnchainTransaction, OpenedChannels, PaymentDetails, PaymentResult,
+	Payments, PendingTxs, PhantomRouteHintsInfo, ProbeResult, QueriedRoutes, RouteInfo,
+	SeedMnemonic, SentToRoute, ServerError, ServerSuccess, UnifiedInvoice, VerifiedMessage,
+	WalletBalance, WebhookInfo,
 };
+use lnnode::tls;
 use reqwest;
-use serde::Serialize;
-use std::{collections::HashMap, env};
+use serde::Deserialize;
+use std::env;
+use std::str::FromStr;
+
+/// Command line client of LN-Node's API
+#[derive(Debug, Parser)]
+#[clap(name = "lnnode-cli", version, disable_help_subcommand = true, arg_required_else_help = true)]
+struct Cli {
+	/// Address of the node's API, as `host`, `host:port` or a URL [default: 127.0.0.1]
+	#[clap(long, global = true, value_name = "HOST[:PORT]")]
+	rpcserver: Option<String>,
+	/// Port of the node's API [default: 33335]
+	#[clap(long, global = true)]
+	port: Option<u16>,
+	/// API token, instead of `LNNODE_API_TOKEN` or the token file
+	#[clap(long, global = true)]
+	token: Option<String>,
+	/// Certificate of the node's API [default: ./.ldk/tls.cert]
+	#[clap(long, global = true, value_name = "PATH")]
+	tlscert: Option<String>,
+	/// Reach the node's API over plain HTTP
+	#[clap(long, global = true)]
+	notls: bool,
+	/// Print the server's response as JSON
+	#[clap(long, global = true)]
+	json: bool,
+	#[clap(subcommand)]
+	command: CliCommand,
+}
 
 /// LDK CLI command
-#[derive(Debug, Serialize)]
-struct Command();
-
-impl Command {
-	/// Create a new variation of a Command
-	///
-	/// # Arguments
-	/// * cmd_input (&Vec<String>): a vector of Strings representing the terminal environment
-	///   variables
-	fn new(cmd_input: &Vec<String>) -> HashMap<String, String> {
-		let arg = cmd_input[1].trim().to_lowercase();
-		match arg.as_str() {
-			"openchannel" => {
-				// TODO: parse optional `public` parameter for channel_announcement
-				let channel_info_parts: Vec<&str> = cmd_input[2].split("@").collect();
-				let host_info_parts: Vec<&str> = channel_info_parts[1].split(":").collect();
-				let pub_key = channel_info_parts[0].to_string();
-				let host = host_info_parts[0].to_string();
-				let port = host_info_parts[1].to_string();
-				let channel_amt_satoshis = cmd_input[3].clone();
-
-				let mut map = HashMap::new();
-				map.insert("pubkey".to_string(), pub_key);
-				map.insert("host".to_string(), host);
-				map.insert("port".to_string(), port);
-				map.insert("channel_amt_satoshis".to_string(), channel_amt_satoshis);
-
-				return map;
-			}
-			"sendpayment" => {
-				let invoice = cmd_input[2].to_string();
-
-				let mut map = HashMap::new();
-				map.insert("invoice".to_string(), invoice);
-
-				return map;
-			}
-			"getinvoice" => {
-				let amt_millisatoshis = cmd_input[2].to_string();
-
-				let mut map = HashMap::new();
-				map.insert("amt_millisatoshis".to_string(), amt_millisatoshis);
-
-				return map;
-			}
-			"connectpeer" => {
-				if cmd_input.len() < 3 {
-					println!("-----------------------------------");
-					println!("LN-Node peer connection information:");
-					println!("-----------------------------------");
-					println!("\tError: invalid connectpeer command");
-					println!("\tProvide peer connection details in format: <pubkey@host:port>");
-					let map = HashMap::new();
+#[derive(Debug, Subcommand)]
+enum CliCommand {
+	/// Show the usage of every command, as served by the node
+	Help,
+	/// Show the node's version, alias, network and sync state
+	Nodeinfo,
+	/// Connect to a peer
+	Connectpeer {
+		/// The address may be left out for peers whose address the node knows
+		#[clap(value_name = "PUBKEY[@HOST:PORT]", parse(try_from_str = parse_peer_target))]
+		peer: PeerTarget,
+	},
+	/// List the connected peers
+	Listpeers,
+	/// Label a peer in listpeers and listchannels, or remove its label if none is given
+	Setpeerlabel {
+		#[clap(parse(try_from_str = parse_pubkey))]
+		pubkey: String,
+		label: Option<String>,
+	},
+	/// Open a channel with a peer
+	Openchannel {
+		#[clap(value_name = "PUBKEY@HOST:PORT", parse(try_from_str = parse_peer))]
+		peer: PeerAddress,
+		#[clap(parse(try_from_str = parse_sat_amount))]
+		channel_amt_satoshis: u64,
+		#[clap(parse(try_from_str = parse_msat_amount))]
+		push_msat: Option<u64>,
+		#[clap(flatten)]
+		config: ChannelConfigArgs,
+		/// Fee rate of the funding transaction
+		#[clap(long, value_name = "SAT_PER_VBYTE")]
+		satpervbyte: Option<f64>,
+		/// Confirmation target the funding transaction's fee rate is estimated for
+		#[clap(long, value_name = "BLOCKS", conflicts_with = "satpervbyte")]
+		targetconf: Option<u16>,
+	},
+	/// Open channels with several peers in a single funding transaction
+	Openchannels {
+		/// Peers and channel amounts, as `pubkey@host:port <amt_satoshis>` pairs
+		#[clap(required = true, value_name = "PUBKEY@HOST:PORT AMT_SATOSHIS")]
+		channels: Vec<String>,
+	},
+	/// List the node's channels
+	Listchannels,
+	/// Create an invoice
+	Getinvoice {
+		#[clap(parse(try_from_str = parse_msat_amount))]
+		amt_millisatoshis: u64,
+		description: Option<String>,
+		expiry_secs: Option<u32>,
+		/// Local note stored with the payment, not included in the invoice
+		label: Option<String>,
+	},
+	/// Create an invoice payable to the phantom node
+	Getphantominvoice {
+		#[clap(parse(try_from_str = parse_msat_amount))]
+		amt_millisatoshis: u64,
+		/// Route hints of the other nodes, as served by their `phantomroutehints`
+		route_hints: Vec<String>,
+		#[clap(long)]
+		description: Option<String>,
+		#[clap(long, value_name = "SECS")]
+		expiry: Option<u32>,
+	},
+	/// Show the route hints of the node for phantom invoices
+	Phantomroutehints,
+	/// Withdraw from an LNURL-withdraw service
+	Withdraw {
+		lnurl: String,
+		/// Amount to withdraw [default: the maximum the service allows]
+		amt_msat: Option<u64>,
+	},
+	/// Create a BIP 21 URI payable on-chain or with an invoice
+	Unifiedinvoice {
+		#[clap(parse(try_from_str = parse_msat_amount))]
+		amt_millisatoshis: u64,
+		description: Option<String>,
+		expiry_secs: Option<u32>,
+		label: Option<String>,
+	},
+	/// List the invoices created by the node
+	Listinvoices,
+	/// Cancel an unpaid invoice
+	Cancelinvoice { payment_hash: String },
+	/// Pay an invoice
+	Sendpayment {
+		invoice: String,
+		/// Amount to pay, only used by invoices which don't specify one
+		#[clap(parse(try_from_str = parse_msat_amount))]
+		amt_msat: Option<u64>,
+		/// Routing fee limit
+		#[clap(long, value_name = "MSAT")]
+		maxfee: Option<u64>,
+		/// Routing fee limit, as a percentage of the amount
+		#[clap(long, value_name = "PCT", conflicts_with = "maxfee")]
+		maxfeepercent: Option<f64>,
+		/// Time after which the payment isn't retried anymore, at most 600
+		#[clap(long, value_name = "SECS")]
+		timeout: Option<u64>,
+		/// Wait for the payment to succeed or fail
+		#[clap(long)]
+		wait: bool,
+	},
+	/// Pay an invoice, waiting for the outcome with --wait, and exit with status 1 if it fails
+	Pay {
+		invoice: String,
+		/// Amount to pay, only used by invoices which don't specify one
+		#[clap(parse(try_from_str = parse_msat_amount))]
+		amt_msat: Option<u64>,
+		/// Routing fee limit
+		#[clap(long = "max-fee-sats", value_name = "SATS", parse(try_from_str = parse_sat_amount))]
+		max_fee_sats: Option<u64>,
+		/// Time after which the payment isn't retried anymore, at most 600
+		#[clap(long, value_name = "SECS")]
+		timeout: Option<u64>,
+		/// Wait for the payment to succeed or fail, reporting the failed attempts
+		#[clap(long)]
+		wait: bool,
+	},
+	/// Pay a node without an invoice
+	Keysend {
+		dest_pubkey: String,
+		#[clap(parse(try_from_str = parse_msat_amount))]
+		amt_msat: u64,
+	},
+	/// Decode an invoice
+	Decodeinvoice { invoice: String },
+	/// List the node's payments
+	Listpayments {
+		#[clap(long, possible_values = ["inbound", "outbound"])]
+		direction: Option<String>,
+		#[clap(long, possible_values = ["pending", "succeeded", "failed"])]
+		status: Option<String>,
+		/// Earliest creation time, in seconds since the UNIX epoch
+		#[clap(long, value_name = "TIME")]
+		start: Option<u64>,
+		/// Latest creation time, in seconds since the UNIX epoch
+		#[clap(long, value_name = "TIME")]
+		end: Option<u64>,
+		#[clap(long)]
+		offset: Option<u64>,
+		#[clap(long)]
+		limit: Option<u64>,
+	},
+	/// Close a channel cooperatively
+	Closechannel {
+		channel_id: String,
+		/// Fee rate of the closing transaction
+		#[clap(value_name = "SAT_PER_VBYTE")]
+		satpervbyte: Option<f64>,
+		/// Confirmation target the closing transaction's fee rate is estimated for
+		#[clap(long, value_name = "BLOCKS", conflicts_with = "satpervbyte")]
+		targetconf: Option<u16>,
+	},
+	/// Force-close a channel
+	Forceclosechannel { channel_id: String },
+	/// Close every channel of the node, once confirmed with the token the node answers with
+	Closeallchannels {
+		/// Force-close the channels
+		#[clap(long)]
+		force: bool,
+		/// Confirmation token given by the node
+		#[clap(long, value_name = "TOKEN")]
+		confirm: Option<String>,
+		/// Fee rate of the cooperative closing transactions
+		#[clap(long, value_name = "SAT_PER_VBYTE", conflicts_with = "force")]
+		satpervbyte: Option<f64>,
+		/// Confirmation target the closing transactions' fee rate is estimated for
+		#[clap(long, value_name = "BLOCKS", conflicts_with_all = &["force", "satpervbyte"])]
+		targetconf: Option<u16>,
+	},
+	/// Attach a label and tags to a channel, replacing the current ones
+	Setchannellabel {
+		channel_id: String,
+		label: Option<String>,
+		/// Tag of the channel, which may be given several times
+		#[clap(long = "tag", value_name = "TAG")]
+		tags: Vec<String>,
+	},
+	/// Show the balances of a channel sampled over time
+	Channelhistory {
+		channel_id: String,
+		/// Earliest sample time, in seconds since the UNIX epoch
+		start_time: Option<u64>,
+		/// Latest sample time, in seconds since the UNIX epoch
+		end_time: Option<u64>,
+	},
+	/// Show, enable or disable the autopilot opening channels on its own
+	Autopilot {
+		#[clap(possible_values = ["status", "enable", "disable"])]
+		action: String,
+	},
+	/// Show the channels the configured LSP sells
+	Lspinfo,
+	/// Buy an inbound channel from the configured LSP
+	Lsporder {
+		/// Inbound liquidity to buy
+		#[clap(parse(try_from_str = parse_sat_amount))]
+		lsp_balance_sat: u64,
+		/// Balance of the node in the channel, paid to the LSP along with its fee
+		#[clap(long, value_name = "SAT", parse(try_from_str = parse_sat_amount))]
+		clientbalancesat: Option<u64>,
+		/// Blocks the LSP keeps the channel open for at least
+		#[clap(long, value_name = "BLOCKS")]
+		channelexpiryblocks: Option<u32>,
+		/// Address the LSP refunds a failed order to, a new address of the wallet by default
+		#[clap(long, value_name = "ADDRESS")]
+		refundaddress: Option<String>,
+		/// Announce the channel
+		#[clap(long)]
+		public: bool,
+	},
+	/// Show an order placed with the configured LSP
+	Lsporderstatus { order_id: String },
+	/// Force-close a channel whose funding transaction never confirmed, and abandon the funding
+	/// transaction
+	Abandonchannel { channel_id: String },
+	/// Sign a message with the node's key
+	Signmessage { message: String },
+	/// Verify the signature of a message
+	Verifymessage {
+		message: String,
+		/// zbase32 signature, as given by signmessage
+		signature: String,
+		/// Node expected to have signed the message
+		pubkey: Option<String>,
+	},
+	/// Explain why a channel can or can't send payments
+	Diagnosechannel { channel_id: String },
+	/// Generate an address of the on-chain wallet
+	Newaddress,
+	/// Show the balance of the on-chain wallet
+	Walletbalance,
+	/// Sum the node's funds, on-chain, in channels and in closed channels
+	Balance,
+	/// Send funds of the on-chain wallet
+	Sendonchain { address: String, amt_satoshis: u64, sat_per_vbyte: Option<f64> },
+	/// List the outputs of the on-chain wallet
+	Listutxos,
+	/// List the transactions the node broadcast which aren't buried under enough blocks yet
+	Pendingtxs,
+	/// Stream the node's events
+	Events,
+	/// List the events the node handled, most recent first
+	Eventhistory {
+		/// e.g. payment_failed
+		#[clap(long = "type", value_name = "EVENT_TYPE")]
+		event_type: Option<String>,
+		#[clap(long)]
+		paymenthash: Option<String>,
+		#[clap(long)]
+		channelid: Option<String>,
+		/// Earliest time the event was handled at, in seconds since the UNIX epoch
+		#[clap(long, value_name = "TIME")]
+		start: Option<u64>,
+		/// Latest time the event was handled at, in seconds since the UNIX epoch
+		#[clap(long, value_name = "TIME")]
+		end: Option<u64>,
+		#[clap(long)]
+		offset: Option<u64>,
+		#[clap(long)]
+		limit: Option<u64>,
+	},
+	/// Stream the node's payment or channel events, or all of them
+	Subscribe {
+		#[clap(possible_values = ["payments", "channels", "events"])]
+		topic: String,
+		/// Only stream the events of this payment
+		#[clap(long, value_name = "PAYMENT_HASH")]
+		paymenthash: Option<String>,
+		/// Exit once this many events are streamed
+		#[clap(long)]
+		count: Option<u64>,
+	},
+	/// List the closed channels
+	Closedchannels,
+	/// Show a payment by its payment hash
+	Lookuppayment { payment_hash: String },
+	/// Show a payment by its payment ID
+	Payment { payment_id: String },
+	/// Abandon an in-flight payment
+	Cancelpayment { payment_id: String },
+	/// List the payments forwarded by the node
+	Listforwards {
+		/// Earliest forward time, in seconds since the UNIX epoch
+		start_time: Option<u64>,
+		/// Latest forward time, in seconds since the UNIX epoch
+		end_time: Option<u64>,
+	},
+	/// Sum the routing fees earned by the node
+	Feereport,
+	/// Update the forwarding policy of a channel, or of every channel
+	Updatechannelpolicy {
+		base_fee_msat: u32,
+		fee_proportional_millionths: u32,
+		cltv_expiry_delta: u16,
+		channel_id: Option<String>,
+		/// Minimum value of the HTLCs the counterparty of a new channel may send
+		#[clap(long, value_name = "MSAT")]
+		minhtlc: Option<u64>,
+		/// Blocks the HTLCs paying the node's invoices have to be claimable for
+		#[clap(long, value_name = "BLOCKS")]
+		minfinalcltv: Option<u32>,
+	},
+	/// Set the alias, color and addresses the node announces
+	Setnodeinfo {
+		#[clap(long)]
+		alias: Option<String>,
+		#[clap(long, value_name = "RRGGBB")]
+		color: Option<String>,
+		/// Address to announce, can be repeated
+		#[clap(long = "address", value_name = "IP:PORT", multiple_occurrences = true)]
+		addresses: Vec<String>,
+	},
+	/// Notify a URL of the node's events
+	Addwebhook { url: String, secret: Option<String> },
+	/// List the webhooks notified of the node's events
+	Listwebhooks,
+	/// Stop notifying a webhook
+	Removewebhook { webhook_id: String },
+	/// Write an encrypted backup of the node's channels
+	Exportbackup { output_path: String },
+	/// Write the node's payments to a CSV or JSON lines file
+	Exportpayments {
+		output_path: String,
+		#[clap(flatten)]
+		options: ExportArgs,
+	},
+	/// Write the node's forwarding history to a CSV or JSON lines file
+	Exportforwards {
+		output_path: String,
+		#[clap(flatten)]
+		options: ExportArgs,
+	},
+	/// Show the node's mnemonic, which is deleted from the node afterwards
+	Backupseed {
+		/// Confirm that the mnemonic is written down
+		#[clap(long)]
+		confirm: bool,
+	},
+	/// Show whether the node and its chain source are healthy
+	Health,
+	/// List the fee rates used by the node and bitcoind's estimates
+	Feerates,
+	/// Find a route to a node without paying it
+	Queryroutes { dest_pubkey: String, amt_msat: u64, max_fee_msat: Option<u64> },
+	/// Pay over a given route
+	Sendtoroute {
+		payment_hash: String,
+		amt_msat: u64,
+		/// Short channel ID of the channel to take, or public key of the node it leads to, of
+		/// each hop
+		#[clap(required = true, value_name = "SCID_OR_PUBKEY")]
+		hops: Vec<String>,
+		#[clap(long, value_name = "SECRET")]
+		paymentsecret: Option<String>,
+	},
+	/// Move liquidity from one of the node's channels to another
+	Rebalance { out_scid: String, in_scid: String, amt_msat: u64, max_fee_msat: u64 },
+	/// Test whether a route can carry an amount to a node
+	Probe { dest_pubkey: String, amt_msat: u64 },
+	/// Set the scorer's parameters, and list them
+	Scorerparams {
+		#[clap(long, value_name = "MSAT")]
+		basepenalty: Option<u64>,
+		#[clap(long, value_name = "MSAT")]
+		liquiditypenalty: Option<u64>,
+		#[clap(long, value_name = "SECS")]
+		halflife: Option<u64>,
+		#[clap(long, value_name = "MSAT")]
+		amountpenalty: Option<u64>,
+	},
+}
 
-					return map;
-				}
+/// Channel config flags of an `openchannel` command
+#[derive(Args, Debug)]
+struct ChannelConfigArgs {
+	/// Announce the channel to the network
+	#[clap(long)]
+	public: bool,
+	#[clap(long, value_name = "MSAT")]
+	basefee: Option<u32>,
+	#[clap(long, value_name = "MILLIONTHS")]
+	feeppm: Option<u32>,
+	#[clap(long, value_name = "BLOCKS")]
+	cltvdelta: Option<u16>,
+	#[clap(long, value_name = "MSAT")]
+	minhtlc: Option<u64>,
+	/// Total value of the dust HTLCs the channel may carry
+	#[clap(long, value_name = "MSAT")]
+	maxdust: Option<u64>,
+	/// Number of HTLCs the counterparty may have in flight
+	#[clap(long, value_name = "HTLCS")]
+	maxhtlcs: Option<u16>,
+	/// Percentage of the channel value which can be in flight
+	#[clap(long, value_name = "PCT")]
+	maxinflight: Option<u8>,
+}
+
+/// Options of an `exportpayments` or `exportforwards` command
+#[derive(Args, Debug)]
+struct ExportArgs {
+	#[clap(long, possible_values = ["csv", "jsonl"])]
+	format: Option<String>,
+	/// Earliest time, in seconds since the UNIX epoch
+	#[clap(long, value_name = "TIME")]
+	start: Option<u64>,
+	/// Latest time, in seconds since the UNIX epoch
+	#[clap(long, value_name = "TIME")]
+	end: Option<u64>,
+}
+
+/// Peer given as `pubkey@host:port`
+#[derive(Debug)]
+struct PeerAddress {
+	pubkey: String,
+	host: String,
+	port: u16,
+}
+
+/// Parse a `pubkey@host:port` peer
+fn parse_peer(peer: &str) -> Result<PeerAddress, String> {
+	let invalid = || format!("invalid peer {}, use the format pubkey@host:port", peer);
+	let (pubkey, addr) = peer.split_once("@").ok_or_else(invalid)?;
+	let (host, port) = addr.rsplit_once(":").ok_or_else(invalid)?;
+	PublicKey::from_str(pubkey).map_err(|_| format!("invalid public key {}", pubkey))?;
+	let port = port.parse().map_err(|_| format!("invalid port {}", port))?;
+	if host.is_empty() {
+		return Err(invalid());
+	}
+	Ok(PeerAddress { pubkey: pubkey.to_string(), host: host.to_string(), port })
+}
+
+/// A peer to connect to, whose address the node looks up if it isn't given
+#[derive(Debug)]
+struct PeerTarget {
+	pubkey: String,
+	address: Option<PeerAddress>,
+}
 
-				let channel_info_parts: Vec<&str> = cmd_input[2].split("@").collect();
-				let host_info_parts: Vec<&str> = channel_info_parts[1].split(":").collect();
-				let pub_key = channel_info_parts[0].to_string();
-				let host = host_info_parts[0].to_string();
-				let port = host_info_parts[1].to_string();
+/// Parse a `pubkey` or `pubkey@host:port` peer
+fn parse_peer_target(peer: &str) -> Result<PeerTarget, String> {
+	if peer.contains('@') {
+		let address = parse_peer(peer)?;
+		return Ok(PeerTarget { pubkey: address.pubkey.clone(), address: Some(address) });
+	}
+	Ok(PeerTarget { pubkey: parse_pubkey(peer)?, address: None })
+}
 
-				let mut map = HashMap::new();
+fn parse_pubkey(pubkey: &str) -> Result<String, String> {
+	PublicKey::from_str(pubkey).map_err(|_| format!("invalid public key {}", pubkey))?;
+	Ok(pubkey.to_string())
+}
 
-				map.insert("pubkey".to_string(), pub_key);
-				map.insert("host".to_string(), host);
-				map.insert("port".to_string(), port);
+/// Insert the fields which are set into a request body. The node takes numbers as strings.
+fn optional_fields(fields: Vec<(&str, Option<String>)>) -> serde_json::Value {
+	let mut body = serde_json::Map::new();
+	for (key, value) in fields {
+		if let Some(value) = value {
+			body.insert(key.to_string(), value.into());
+		}
+	}
+	body.into()
+}
 
-				return map;
+impl CliCommand {
+	/// Path of the command's endpoint, relative to the API's URL
+	fn path(&self, name: &str) -> String {
+		match self {
+			CliCommand::Diagnosechannel { channel_id } => {
+				format!("diagnose/channel/{}", channel_id)
 			}
-			"listchannels" => {
-				let map = HashMap::new();
-				return map;
+			CliCommand::Setchannellabel { channel_id, .. } => {
+				format!("channels/{}/label", channel_id)
 			}
-			"listpayments" => {
-				let map = HashMap::new();
-				return map;
+			CliCommand::Channelhistory { channel_id, .. } => {
+				format!("channels/{}/history", channel_id)
 			}
-			"closechannel" => {
-				let channel_id = cmd_input[2].to_string();
-
-				let mut map = HashMap::new();
-				map.insert("channel_id".to_string(), channel_id);
+			CliCommand::Autopilot { action } => format!("autopilot/{}", action),
+			CliCommand::Lspinfo => "lsp/info".to_string(),
+			CliCommand::Lsporder { .. } => "lsp/order".to_string(),
+			CliCommand::Lsporderstatus { order_id } => format!("lsp/order/{}", order_id),
+			CliCommand::Payment { payment_id } => format!("payments/{}", payment_id),
+			CliCommand::Cancelpayment { payment_id } => format!("payments/{}/cancel", payment_id),
+			CliCommand::Backupseed { .. } => "seed/backup".to_string(),
+			CliCommand::Scorerparams { .. } => "scorer/params".to_string(),
+			CliCommand::Exportbackup { .. } => "backup/export".to_string(),
+			CliCommand::Subscribe { .. } => "events".to_string(),
+			CliCommand::Eventhistory { .. } => "events/history".to_string(),
+			CliCommand::Pay { .. } => "sendpayment".to_string(),
+			CliCommand::Exportpayments { .. } => "export/payments".to_string(),
+			CliCommand::Exportforwards { .. } => "export/forwards".to_string(),
+			_ => name.to_string(),
+		}
+	}
 
-				return map;
+	/// Body of the command's request
+	fn body(&self) -> Result<serde_json::Value, String> {
+		let body = match self {
+			CliCommand::Connectpeer { peer } => optional_fields(vec![
+				("pubkey", Some(peer.pubkey.clone())),
+				("host", peer.address.as_ref().map(|address| address.host.clone())),
+				("port", peer.address.as_ref().map(|address| address.port.to_string())),
+			]),
+			CliCommand::Setchannellabel { label, tags, .. } => {
+				serde_json::json!({ "label": label, "tags": tags })
 			}
-			"forceclosechannel" => {
-				let channel_id = cmd_input[2].to_string();
-				let mut map = HashMap::new();
-				map.insert("channel_id".to_string(), channel_id);
-
-				return map;
+			CliCommand::Setpeerlabel { pubkey, label } => {
+				optional_fields(vec![("pubkey", Some(pubkey.clone())), ("label", label.clone())])
+			}
+			CliCommand::Openchannel {
+				peer,
+				channel_amt_satoshis,
+				push_msat,
+				config,
+				satpervbyte,
+				targetconf,
+			} => {
+				let mut body = optional_fields(vec![
+					("pubkey", Some(peer.pubkey.clone())),
+					("host", Some(peer.host.clone())),
+					("port", Some(peer.port.to_string())),
+					("channel_amt_satoshis", Some(channel_amt_satoshis.to_string())),
+					("push_msat", push_msat.map(|msat| msat.to_string())),
+					("sat_per_vbyte", satpervbyte.map(|rate| rate.to_string())),
+					("target_conf", targetconf.map(|target| target.to_string())),
+				]);
+				let config = optional_fields(vec![
+					("announce_channel", config.public.then(|| "true".to_string())),
+					("forwarding_fee_base_msat", config.basefee.map(|fee| fee.to_string())),
+					(
+						"forwarding_fee_proportional_millionths",
+						config.feeppm.map(|fee| fee.to_string()),
+					),
+					("cltv_expiry_delta", config.cltvdelta.map(|delta| delta.to_string())),
+					("htlc_minimum_msat", config.minhtlc.map(|msat| msat.to_string())),
+					("max_inflight_htlc_percent", config.maxinflight.map(|pct| pct.to_string())),
+					("max_accepted_htlcs", config.maxhtlcs.map(|htlcs| htlcs.to_string())),
+					("max_dust_htlc_exposure_msat", config.maxdust.map(|msat| msat.to_string())),
+				]);
+				if config.as_object().map_or(false, |config| !config.is_empty()) {
+					body["config"] = config;
+				}
+				body
+			}
+			CliCommand::Openchannels { channels } => open_channels_body(channels)?,
+			CliCommand::Getinvoice { amt_millisatoshis, description, expiry_secs, label }
+			| CliCommand::Unifiedinvoice { amt_millisatoshis, description, expiry_secs, label } => {
+				optional_fields(vec![
+					("amt_millisatoshis", Some(amt_millisatoshis.to_string())),
+					("description", description.clone()),
+					("expiry_secs", expiry_secs.map(|secs| secs.to_string())),
+					("label", label.clone()),
+				])
+			}
+			CliCommand::Getphantominvoice {
+				amt_millisatoshis,
+				route_hints,
+				description,
+				expiry,
+			} => {
+				serde_json::json!({
+					"amt_millisatoshis": amt_millisatoshis.to_string(),
+					"description": description,
+					"expiry_secs": expiry.map(|secs| secs.to_string()),
+					"route_hints": route_hints,
+				})
+			}
+			CliCommand::Withdraw { lnurl, amt_msat } => optional_fields(vec![
+				("lnurl", Some(lnurl.clone())),
+				("amt_msat", amt_msat.map(|msat| msat.to_string())),
+			]),
+			CliCommand::Cancelinvoice { payment_hash }
+			| CliCommand::Lookuppayment { payment_hash } => {
+				serde_json::json!({ "payment_hash": payment_hash })
 			}
-			"nodeinfo" => {
-				let map = HashMap::new();
-				return map;
+			CliCommand::Sendpayment { invoice, amt_msat, maxfee, maxfeepercent, timeout, wait } => {
+				optional_fields(vec![
+					("invoice", Some(invoice.clone())),
+					("amount_msat", amt_msat.map(|msat| msat.to_string())),
+					("max_fee_msat", maxfee.map(|msat| msat.to_string())),
+					("max_fee_percent", maxfeepercent.map(|pct| pct.to_string())),
+					("timeout_secs", timeout.map(|secs| secs.to_string())),
+					("wait", wait.then(|| "true".to_string())),
+				])
 			}
-			"listpeers" => {
-				let map = HashMap::new();
-				return map;
+			CliCommand::Pay { invoice, amt_msat, max_fee_sats, timeout, wait } => {
+				optional_fields(vec![
+					("invoice", Some(invoice.clone())),
+					("amount_msat", amt_msat.map(|msat| msat.to_string())),
+					("max_fee_msat", max_fee_sats.map(|sats| (sats * 1_000).to_string())),
+					("timeout_secs", timeout.map(|secs| secs.to_string())),
+					("wait", wait.then(|| "true".to_string())),
+				])
 			}
-			"signmessage" => {
-				let message = cmd_input[2].to_string();
+			CliCommand::Keysend { dest_pubkey, amt_msat } => serde_json::json!({
+				"pubkey": dest_pubkey,
+				"amt_msat": amt_msat.to_string(),
+			}),
+			CliCommand::Decodeinvoice { invoice } => serde_json::json!({ "invoice": invoice }),
+			CliCommand::Listpayments { direction, status, start, end, offset, limit } => {
+				optional_fields(vec![
+					("direction", direction.clone()),
+					("status", status.clone()),
+					("start_time", start.map(|time| time.to_string())),
+					("end_time", end.map(|time| time.to_string())),
+					("offset", offset.map(|offset| offset.to_string())),
+					("limit", limit.map(|limit| limit.to_string())),
+				])
+			}
+			CliCommand::Eventhistory {
+				event_type,
+				paymenthash,
+				channelid,
+				start,
+				end,
+				offset,
+				limit,
+			} => optional_fields(vec![
+				("event_type", event_type.clone()),
+				("payment_hash", paymenthash.clone()),
+				("channel_id", channelid.clone()),
+				("start_time", start.map(|time| time.to_string())),
+				("end_time", end.map(|time| time.to_string())),
+				("offset", offset.map(|offset| offset.to_string())),
+				("limit", limit.map(|limit| limit.to_string())),
+			]),
+			CliCommand::Closechannel { channel_id, satpervbyte, targetconf } => {
+				optional_fields(vec![
+					("channel_id", Some(channel_id.clone())),
+					("sat_per_vbyte", satpervbyte.map(|rate| rate.to_string())),
+					("target_conf", targetconf.map(|target| target.to_string())),
+				])
+			}
+			CliCommand::Closeallchannels { force, confirm, satpervbyte, targetconf } => {
+				optional_fields(vec![
+					("force", force.then(|| "true".to_string())),
+					("confirm", confirm.clone()),
+					("sat_per_vbyte", satpervbyte.map(|rate| rate.to_string())),
+					("target_conf", targetconf.map(|target| target.to_string())),
+				])
+			}
+			CliCommand::Forceclosechannel { channel_id }
+			| CliCommand::Abandonchannel { channel_id } => {
+				serde_json::json!({ "channel_id": channel_id })
+			}
+			CliCommand::Signmessage { message } => serde_json::json!({ "message": message }),
+			CliCommand::Verifymessage { message, signature, pubkey } => optional_fields(vec![
+				("message", Some(message.clone())),
+				("signature", Some(signature.clone())),
+				("pubkey", pubkey.clone()),
+			]),
+			CliCommand::Sendonchain { address, amt_satoshis, sat_per_vbyte } => {
+				optional_fields(vec![
+					("address", Some(address.clone())),
+					("amount_sat", Some(amt_satoshis.to_string())),
+					("sat_per_vbyte", sat_per_vbyte.map(|rate| rate.to_string())),
+				])
+			}
+			CliCommand::Lsporder {
+				lsp_balance_sat,
+				clientbalancesat,
+				channelexpiryblocks,
+				refundaddress,
+				public,
+			} => optional_fields(vec![
+				("lsp_balance_sat", Some(lsp_balance_sat.to_string())),
+				("client_balance_sat", clientbalancesat.map(|amount| amount.to_string())),
+				("channel_expiry_blocks", channelexpiryblocks.map(|blocks| blocks.to_string())),
+				("refund_onchain_address", refundaddress.clone()),
+				("announce_channel", Some(public.to_string())),
+			]),
+			CliCommand::Channelhistory { start_time, end_time, .. } => optional_fields(vec![
+				("start_time", start_time.map(|time| time.to_string())),
+				("end_time", end_time.map(|time| time.to_string())),
+			]),
+			CliCommand::Listforwards { start_time, end_time } => optional_fields(vec![
+				("start_time", start_time.map(|time| time.to_string())),
+				("end_time", end_time.map(|time| time.to_string())),
+			]),
+			CliCommand::Updatechannelpolicy {
+				base_fee_msat,
+				fee_proportional_millionths,
+				cltv_expiry_delta,
+				channel_id,
+				minhtlc,
+				minfinalcltv,
+			} => optional_fields(vec![
+				("base_fee_msat", Some(base_fee_msat.to_string())),
+				("fee_proportional_millionths", Some(fee_proportional_millionths.to_string())),
+				("cltv_expiry_delta", Some(cltv_expiry_delta.to_string())),
+				("channel_id", channel_id.clone()),
+				("htlc_minimum_msat", minhtlc.map(|msat| msat.to_string())),
+				("min_final_cltv_expiry", minfinalcltv.map(|blocks| blocks.to_string())),
+			]),
+			CliCommand::Setnodeinfo { alias, color, addresses } => {
+				let mut info =
+					optional_fields(vec![("alias", alias.clone()), ("color", color.clone())]);
+				if !addresses.is_empty() {
+					info["addresses"] = addresses.clone().into();
+				}
+				info
+			}
+			CliCommand::Addwebhook { url, secret } => {
+				optional_fields(vec![("url", Some(url.clone())), ("secret", secret.clone())])
+			}
+			CliCommand::Removewebhook { webhook_id } => serde_json::json!({ "id": webhook_id }),
+			CliCommand::Exportpayments { options, .. }
+			| CliCommand::Exportforwards { options, .. } => optional_fields(vec![
+				("format", options.format.clone()),
+				("start_time", options.start.map(|time| time.to_string())),
+				("end_time", options.end.map(|time| time.to_string())),
+			]),
+			CliCommand::Backupseed { confirm } => {
+				optional_fields(vec![("confirm", confirm.then(|| "true".to_string()))])
+			}
+			CliCommand::Queryroutes { dest_pubkey, amt_msat, max_fee_msat } => {
+				optional_fields(vec![
+					("dest_pubkey", Some(dest_pubkey.clone())),
+					("amt_msat", Some(amt_msat.to_string())),
+					("max_fee_msat", max_fee_msat.map(|msat| msat.to_string())),
+				])
+			}
+			CliCommand::Sendtoroute { payment_hash, amt_msat, hops, paymentsecret } => {
+				serde_json::json!({
+					"payment_hash": payment_hash,
+					"amt_msat": amt_msat.to_string(),
+					"hops": hops,
+					"payment_secret": paymentsecret,
+				})
+			}
+			CliCommand::Rebalance { out_scid, in_scid, amt_msat, max_fee_msat } => {
+				serde_json::json!({
+					"out_scid": out_scid,
+					"in_scid": in_scid,
+					"amt_msat": amt_msat.to_string(),
+					"max_fee_msat": max_fee_msat.to_string(),
+				})
+			}
+			CliCommand::Probe { dest_pubkey, amt_msat } => serde_json::json!({
+				"dest_pubkey": dest_pubkey,
+				"amt_msat": amt_msat.to_string(),
+			}),
+			// The scorer's parameters are numbers rather than strings
+			CliCommand::Scorerparams { basepenalty, liquiditypenalty, halflife, amountpenalty } => {
+				serde_json::json!({
+					"base_penalty_msat": basepenalty,
+					"liquidity_penalty_multiplier_msat": liquiditypenalty,
+					"liquidity_offset_half_life_secs": halflife,
+					"amount_penalty_multiplier_msat": amountpenalty,
+				})
+			}
+			_ => serde_json::json!({}),
+		};
+		Ok(body)
+	}
+
+	/// Path of the file the command writes the response to, if any
+	fn output_path(&self) -> Option<&str> {
+		match self {
+			CliCommand::Exportbackup { output_path }
+			| CliCommand::Exportpayments { output_path, .. }
+			| CliCommand::Exportforwards { output_path, .. } => Some(output_path),
+			_ => None,
+		}
+	}
+}
+
+/// Port the node's API listens on by default
+const DEFAULT_PORT: u16 = 33335;
+
+/// Path of the CLI's config file, relative to the home directory
+const CLI_CONFIG_FILE: &str = ".lnnode/cli.toml";
+
+/// CLI settings, read from `~/.lnnode/cli.toml`. Flags override them.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct CliConfig {
+	/// Address of the node's API, as `host`, `host:port` or a URL
+	rpcserver: Option<String>,
+	/// Port of the node's API, if not given by `rpcserver`
+	port: Option<u16>,
+	/// API token, unless given by `LNNODE_API_TOKEN`
+	token: Option<String>,
+	/// File holding the API token, unless given by `LNNODE_API_TOKEN_FILE`
+	token_file: Option<String>,
+	/// Certificate of the node's API
+	tlscert: Option<String>,
+	/// Whether the node's API is served over plain HTTP
+	notls: bool,
+}
+
+/// Read the CLI settings from `~/.lnnode/cli.toml`, if it exists
+fn load_cli_config() -> Result<CliConfig, String> {
+	let home = match env::var("HOME") {
+		Ok(home) => home,
+		Err(_) => return Ok(CliConfig::default()),
+	};
+	let config_path = format!("{}/{}", home, CLI_CONFIG_FILE);
+	config::Config::builder()
+		.add_source(config::File::new(&config_path, config::FileFormat::Toml).required(false))
+		.build()
+		.and_then(|config| config.try_deserialize())
+		.map_err(|e| format!("invalid CLI config {}: {}", config_path, e))
+}
+
+/// Split `rpcserver`, given as `host`, `host:port` or a URL, into its scheme, host and port
+fn parse_rpcserver(rpcserver: &str) -> Result<(Option<&str>, &str, Option<u16>), String> {
+	let invalid = || format!("invalid --rpcserver {}", rpcserver);
+	let (scheme, address) = match rpcserver.trim_end_matches('/').split_once("://") {
+		Some((scheme, address)) if scheme == "http" || scheme == "https" => (Some(scheme), address),
+		Some(_) => return Err(invalid()),
+		None => (None, rpcserver.trim_end_matches('/')),
+	};
+	// IPv6 hosts are bracketed when followed by a port
+	let port_separator = match address.rfind(']') {
+		Some(end) => address[end..].find(':').map(|idx| end + idx),
+		None if address.matches(':').count() == 1 => address.find(':'),
+		None => None,
+	};
+	let (host, port) = match port_separator {
+		Some(idx) => {
+			let port = address[idx + 1..].parse::<u16>().map_err(|_| invalid())?;
+			(&address[..idx], Some(port))
+		}
+		None => (address, None),
+	};
+	if host.is_empty() || host.contains('/') {
+		return Err(invalid());
+	}
+	Ok((scheme, host, port))
+}
+
+/// Read the node's API token from `LNNODE_API_TOKEN`, or else from the CLI config, or else from
+/// the file at `LNNODE_API_TOKEN_FILE` or at the configured `token_file`. Without any, the token
+/// file of a node storing its data in the current directory is used.
+fn read_api_token(cli_config: &CliConfig) -> Result<String, String> {
+	if let Ok(token) = env::var(auth::API_TOKEN_ENV) {
+		return Ok(token.trim().to_string());
+	}
+	if let Some(token) = &cli_config.token {
+		return Ok(token.trim().to_string());
+	}
+	let token_path = env::var(auth::API_TOKEN_FILE_ENV)
+		.ok()
+		.or(cli_config.token_file.clone())
+		.unwrap_or(format!("./.ldk/{}", auth::API_TOKEN_FILE));
+	auth::read_api_token_file(&token_path).map_err(|e| {
+		format!(
+			"unable to read API token from {}: {}. Set {} or {}",
+			token_path,
+			e,
+			auth::API_TOKEN_ENV,
+			auth::API_TOKEN_FILE_ENV
+		)
+	})
+}
 
-				let mut map = HashMap::new();
-				map.insert("message".to_string(), message);
+/// Parse an amount in `default_unit_msat` units, or with a `btc`, `sat` or `msat` unit and an
+/// optional `k` or `m` multiplier, e.g. `0.01btc`, `250k sats` or `1_000_000msat`, into
+/// millisatoshis
+fn parse_amount(amount: &str, default_unit_msat: u64) -> Result<u64, String> {
+	let invalid = || format!("invalid amount {}, e.g. 0.01btc, 250k sats or 1_000_000msat", amount);
+	let normalized: String = amount
+		.chars()
+		.filter(|c| !c.is_whitespace() && *c != '_')
+		.collect::<String>()
+		.to_lowercase();
+	let number_end =
+		normalized.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(normalized.len());
+	let (number, suffix) = normalized.split_at(number_end);
+	let units = [
+		("btc", 100_000_000_000),
+		("msats", 1),
+		("msat", 1),
+		("sats", 1_000),
+		("sat", 1_000),
+		("", default_unit_msat),
+	];
+	let (multiplier, unit_msat) = units
+		.iter()
+		.find_map(|(unit, unit_msat)| {
+			let multiplier = match suffix.strip_suffix(unit)? {
+				"" => 1,
+				"k" if *unit != "btc" => 1_000,
+				"m" if *unit != "btc" => 1_000_000,
+				_ => return None,
+			};
+			Some((multiplier, *unit_msat))
+		})
+		.ok_or_else(invalid)?;
+
+	let (integer, fraction) = number.split_once('.').unwrap_or((number, ""));
+	if (integer.is_empty() && fraction.is_empty()) || fraction.contains('.') {
+		return Err(invalid());
+	}
+	let scale = 10u128.checked_pow(fraction.len() as u32).ok_or_else(invalid)?;
+	let digits: u128 = format!("{}{}", integer, fraction).parse().map_err(|_| invalid())?;
+	let scaled_msat = digits
+		.checked_mul(multiplier as u128 * unit_msat as u128)
+		.ok_or_else(|| format!("amount {} is too large", amount))?;
+	if scaled_msat % scale != 0 {
+		return Err(format!("amount {} isn't a whole number of millisatoshis", amount));
+	}
+	u64::try_from(scaled_msat / scale).map_err(|_| format!("amount {} is too large", amount))
+}
+
+/// Parse an amount in satoshis unless a unit is given
+fn parse_sat_amount(amount: &str) -> Result<u64, String> {
+	let amount_msat = parse_amount(amount, 1_000)?;
+	if amount_msat % 1_000 != 0 {
+		return Err(format!("amount {} isn't a whole number of satoshis", amount));
+	}
+	Ok(amount_msat / 1_000)
+}
+
+/// Parse an amount in millisatoshis unless a unit is given
+fn parse_msat_amount(amount: &str) -> Result<u64, String> {
+	parse_amount(amount, 1)
+}
+
+/// Format an amount of satoshis along with its value in BTC
+fn format_sat(amount_sat: u64) -> String {
+	format!(
+		"{} sats ({}.{:08} BTC)",
+		amount_sat,
+		amount_sat / 100_000_000,
+		amount_sat % 100_000_000
+	)
+}
+
+/// Format an amount of millisatoshis along with its value in satoshis and BTC
+fn format_msat(amount_msat: u64) -> String {
+	let amount_sat = amount_msat / 1_000;
+	let sats = match amount_msat % 1_000 {
+		0 => amount_sat.to_string(),
+		msat => format!("{}.{:03}", amount_sat, msat),
+	};
+	format!(
+		"{} ({} sats, {}.{:08} BTC)",
+		amount_msat,
+		sats,
+		amount_sat / 100_000_000,
+		amount_sat % 100_000_000
+	)
+}
+
+/// Build the body of an `openchannels` request from `pubkey@host:port <amt_satoshis>` pairs
+fn open_channels_body(pairs: &[String]) -> Result<serde_json::Value, String> {
+	if pairs.len() % 2 != 0 {
+		return Err(format!(
+			"openchannels takes `pubkey@host:port <amt_satoshis>` pairs, e.g. `openchannels pubkey@host:port 100000 pubkey@host:port 200000`"
+		));
+	}
+	let mut channels = Vec::new();
+	for pair in pairs.chunks(2) {
+		let peer = parse_peer(&pair[0])?;
+		let amount = parse_sat_amount(&pair[1])?;
+		channels.push(serde_json::json!({
+			"pubkey": peer.pubkey,
+			"host": peer.host,
+			"port": peer.port.to_string(),
+			"amount": amount.to_string(),
+		}));
+	}
+	Ok(serde_json::json!({ "channels": channels }))
+}
+
+/// Print the totals and hops of a route
+fn print_route(route: RouteInfo) {
+	println!("\ttotal_amt_msat: {}", route.total_amt_msat);
+	println!("\ttotal_fees_msat: {}", route.total_fees_msat);
+	println!("\ttotal_time_lock: {}", route.total_time_lock);
+	println!("\thops: [");
+	for hop in route.hops {
+		println!("\t\t{{");
+		println!("\t\tpubkey: {},", hop.pubkey);
+		println!("\t\tshort_channel_id: {},", hop.short_channel_id);
+		println!("\t\tamt_to_forward_msat: {},", hop.amt_to_forward_msat);
+		println!("\t\tfee_msat: {},", hop.fee_msat);
+		println!("\t\texpiry: {},", hop.expiry);
+		println!("\t\t}},");
+	}
+	println!("\t]");
+}
+
+/// Whether the node event `event` is among the `topic` events of a `subscribe` command, and is
+/// about the payment `payment_hash` if given
+fn is_subscribed(topic: &str, payment_hash: Option<&str>, event: &serde_json::Value) -> bool {
+	let event_type = event["type"].as_str().unwrap_or_default();
+	let in_topic = match topic {
+		"payments" => event_type.starts_with("payment_"),
+		"channels" => {
+			["funding_generation_ready", "channel_closed", "discard_funding"].contains(&event_type)
+		}
+		_ => true,
+	};
+	in_topic && payment_hash.map_or(true, |payment_hash| event["payment_hash"] == payment_hash)
+}
 
-				return map;
+/// Server-sent events of a response of the `/events` stream
+struct EventStream {
+	resp: reqwest::Response,
+	buffer: String,
+}
+
+impl EventStream {
+	fn new(resp: reqwest::Response) -> Self {
+		Self { resp, buffer: String::new() }
+	}
+
+	/// Data of the next event, and whether it is the number of events missed by falling behind
+	/// rather than a node event. Returns `None` once the stream ends.
+	async fn next(&mut self) -> Option<Result<(bool, String), reqwest::Error>> {
+		loop {
+			// Server-sent events are separated by blank lines and may be split across chunks
+			if let Some(end) = self.buffer.find("\n\n") {
+				let message: String = self.buffer.drain(..end + 2).collect();
+				let lagged = message.starts_with("event: lagged");
+				let data: Vec<&str> =
+					message.lines().filter_map(|l| l.strip_prefix("data: ")).collect();
+				return Some(Ok((lagged, data.join("\n"))));
 			}
-			"help" => {
-				let map = HashMap::new();
-				return map;
+			match self.resp.chunk().await {
+				Ok(Some(chunk)) => self.buffer.push_str(&String::from_utf8_lossy(&chunk)),
+				Ok(None) => return None,
+				Err(e) => return Some(Err(e)),
+			}
+		}
+	}
+}
+
+/// Print the node events streamed by `resp` as they arrive, skipping those `filter` rejects,
+/// until `count` events are printed if given
+async fn print_events(
+	resp: reqwest::Response, json_output: bool, filter: impl Fn(&serde_json::Value) -> bool,
+	count: Option<u64>,
+) {
+	let mut events = EventStream::new(resp);
+	let mut printed = 0;
+	while let Some(event) = events.next().await {
+		match event {
+			Ok((true, missed)) if json_output => println!("{{\"lagged\":{}}}", missed),
+			Ok((true, missed)) => println!("\tmissed {} events", missed),
+			Ok((false, data)) => {
+				match serde_json::from_str::<serde_json::Value>(&data) {
+					Ok(event) if !filter(&event) => continue,
+					_ => {}
+				}
+				match json_output {
+					true => println!("{}", data),
+					false => println!("\t{}", data),
+				}
+				printed += 1;
+				if count.map_or(false, |count| printed >= count) {
+					return;
+				}
 			}
-			_ => {
-				let map = HashMap::new();
-				return map;
+			Err(e) => {
+				println!("LN-Node-server error: {}", e);
+				return;
 			}
 		}
 	}
 }
 
+/// Report the failed attempts of the payment of `invoice` on the standard error while the
+/// payment is retried
+async fn watch_payment_attempts(
+	cli_client: &reqwest::Client, node_server_url: &str, api_token: &str, invoice: &str,
+) -> Result<(), String> {
+	let invoice = Invoice::from_str(invoice).map_err(|e| format!("invalid invoice: {}", e))?;
+	let payment_hash = invoice.payment_hash().to_string();
+	let resp = cli_client
+		.post(format!("{}/events", node_server_url))
+		.bearer_auth(api_token)
+		.send()
+		.await
+		.map_err(|e| format!("unable to reach LN-Node at {}: {}", node_server_url, e))?;
+	if !resp.status().is_success() {
+		return Err(format!("unable to follow the payment: {}", resp.status()));
+	}
+	let mut events = EventStream::new(resp);
+	tokio::spawn(async move {
+		let mut attempts = 0;
+		while let Some(Ok((lagged, data))) = events.next().await {
+			let event: serde_json::Value = match serde_json::from_str(&data) {
+				Ok(event) if !lagged => event,
+				_ => continue,
+			};
+			if event["type"] != "payment_path_failed" || event["payment_hash"] != payment_hash {
+				continue;
+			}
+			attempts += 1;
+			if event["rejected_by_dest"] == true {
+				eprintln!("\tattempt {}: rejected by the destination", attempts);
+			} else if let Some(short_channel_id) = event["short_channel_id"].as_u64() {
+				eprintln!(
+					"\tattempt {}: failed at channel {}, retrying",
+					attempts, short_channel_id
+				);
+			} else {
+				eprintln!("\tattempt {}: failed, retrying", attempts);
+			}
+		}
+	});
+	Ok(())
+}
+
+/// Build an HTTPS client trusting the node's certificate at `cert_path`
+fn tls_client(cert_path: &str) -> Result<reqwest::Client, String> {
+	let cert_pem = std::fs::read(cert_path)
+		.map_err(|e| format!("unable to read TLS certificate {}: {}", cert_path, e))?;
+	let cert = reqwest::Certificate::from_pem(&cert_pem)
+		.map_err(|e| format!("invalid TLS certificate {}: {}", cert_path, e))?;
+	reqwest::Client::builder().add_root_certificate(cert).build().map_err(|e| e.to_string())
+}
+
 #[tokio::main]
 async fn main() {
-	let valid_commands: Vec<&str> = vec![
-		"help",
-		"nodeinfo",
-		"connectpeer",
-		"listpeers",
-		"openchannel",
-		"listchannels",
-		"getinvoice",
-		"sendpayment",
-		"listpayments",
-		"closechannel",
-		"forceclosechannel",
-		"signmessage",
-	];
-	// 1. Get argument list/vector from terminal
-	let cmd_args: Vec<String> = env::args().collect();
-	if cmd_args.len() < 2 {
-		println!(
-			"You must provide an argument to the lnnode-cli command, e.g. lnnode-cli nodeinfo"
-		);
-		return;
+	// 1. Parse the command line, exiting with its usage if it's invalid
+	let matches = Cli::command().get_matches();
+	let cli = match Cli::from_arg_matches(&matches) {
+		Ok(cli) => cli,
+		Err(e) => e.exit(),
+	};
+	let path = matches.subcommand_name().unwrap_or_default().to_string();
+	let cli_config = match load_cli_config() {
+		Ok(cli_config) => cli_config,
+		Err(e) => {
+			println!("ERROR: {}", e);
+			return;
+		}
+	};
+	let mut no_tls = cli.notls || cli_config.notls;
+	let json_output = cli.json;
+	let tls_cert_path = cli
+		.tlscert
+		.clone()
+		.or(cli_config.tlscert.clone())
+		.unwrap_or(format!("./.ldk/{}", tls::TLS_CERT_FILE));
+
+	let rpcserver = cli.rpcserver.clone().or(cli_config.rpcserver.clone());
+	let (rpcserver_scheme, host, rpcserver_port) =
+		match parse_rpcserver(rpcserver.as_deref().unwrap_or("127.0.0.1")) {
+			Ok(rpcserver) => rpcserver,
+			Err(e) => {
+				println!("ERROR: {}", e);
+				return;
+			}
+		};
+	let port = cli.port.or(rpcserver_port).or(cli_config.port).unwrap_or(DEFAULT_PORT);
+	if rpcserver_scheme == Some("http") {
+		no_tls = true;
 	}
 
-	// 2. Parse to appropriate command
-	let command = Command::new(&cmd_args);
-	let mut count: u8 = 0;
-	for cmd in valid_commands {
-		if cmd_args[1].to_lowercase().as_str() == cmd {
-			count = count + 1;
-			continue;
+	// The node serves HTTPS with a self-signed certificate unless TLS is disabled in its config
+	let (cli_client, scheme) = if no_tls {
+		(reqwest::Client::new(), "http")
+	} else {
+		match tls_client(&tls_cert_path) {
+			Ok(client) => (client, "https"),
+			Err(e) => {
+				println!("ERROR: {}. Pass the node's certificate with `--tlscert <path>`", e);
+				return;
+			}
+		}
+	};
+	let node_server_url = format!("{}://{}:{}", scheme, host, port);
+
+	// 2. Build the request of the command
+	let url = format!("{}/{}", node_server_url, cli.command.path(&path));
+	let body = match cli.command.body() {
+		Ok(body) => body,
+		Err(e) => {
+			println!("ERROR: {}", e);
+			return;
+		}
+	};
+	let output_path = cli.command.output_path().unwrap_or_default();
+	let wait = matches!(cli.command, CliCommand::Sendpayment { wait: true, .. });
+
+	// 3. Authenticate with the node
+	let api_token = match cli.token.clone().map_or_else(|| read_api_token(&cli_config), Ok) {
+		Ok(token) => token,
+		Err(e) => {
+			println!("ERROR: {}", e);
+			return;
+		}
+	};
+
+	if let CliCommand::Pay { invoice, wait: true, .. } = &cli.command {
+		if let Err(e) =
+			watch_payment_attempts(&cli_client, &node_server_url, &api_token, invoice).await
+		{
+			println!("ERROR: {}", e);
+			std::process::exit(1);
 		}
 	}
-	if count == 0 {
+
+	// 4. Send request to node server
+	let resp = match cli_client.post(url).bearer_auth(api_token).json(&body).send().await {
+		Ok(resp) => resp,
+		Err(e) => {
+			println!("ERROR: unable to reach LN-Node at {}: {}", node_server_url, e);
+			return;
+		}
+	};
+	if resp.status() == reqwest::StatusCode::UNAUTHORIZED {
 		println!("-----------------------------------");
-		println!("Entered an invalid command.");
-		println!("Type `lnnode-cli help` for commands list");
+		println!("LN-Node rejected the API token");
 		println!("-----------------------------------");
 		return;
 	}
-	// 3. Create a request body with matching map
-
-	let cli_client = reqwest::Client::new();
-	let port: u32 = 33335;
-	let node_server_url = format!("http://127.0.0.1:{}", port);
-	let path = cmd_args[1].clone();
-	let url = format!("{}/{}", node_server_url.as_str(), path);
-
-	// let req_body = serde_json::to_string(&command).unwrap();
-
-	// 4. Send request to node server
-	let resp = cli_client.post(url).json(&command).send().await.unwrap();
+	// Exports are still written to their output path, and events still streamed
+	let streamed = path == "events" || path == "subscribe" || path.starts_with("export");
+	if json_output && !streamed {
+		let status = resp.status();
+		match resp.text().await {
+			Ok(text) => match serde_json::from_str::<serde_json::Value>(&text) {
+				Ok(value) => println!("{}", serde_json::to_string_pretty(&value).unwrap()),
+				Err(_) => println!("{}", text),
+			},
+			Err(e) => println!("LN-Node-server error: {}", e),
+		}
+		// Let scripts tell errors apart without parsing the response
+		if !status.is_success() {
+			std::process::exit(1);
+		}
+		return;
+	}
 
 	// 5. Match the response to designed enum types and process accordingly
 	match path.as_str() {
@@ -194,15 +1291,65 @@ async fn main() {
 					println!("-----------------------------------");
 					println!("\tnodeinfo: {:?}", help.nodeinfo);
 					println!("\topenchannel: {:?}", help.openchannel);
+					println!("\topenchannels: {:?}", help.openchannels);
 					println!("\tsendpayment: {:?}", help.sendpayment);
 					println!("\tgetinvoice: {:?}", help.getinvoice);
+					println!("\tgetphantominvoice: {:?}", help.getphantominvoice);
+					println!("\tphantomroutehints: {:?}", help.phantomroutehints);
+					println!("\twithdraw: {:?}", help.withdraw);
+					println!("\tunifiedinvoice: {:?}", help.unifiedinvoice);
+					println!("\tlistinvoices: {:?}", help.listinvoices);
+					println!("\tcancelinvoice: {:?}", help.cancelinvoice);
 					println!("\tconnectpeer: {:?}", help.connectpeer);
 					println!("\tlistchannels: {:?}", help.listchannels);
 					println!("\tlistpeers: {:?}", help.listpeers);
 					println!("\tclosechannel: {:?}", help.closechannel);
 					println!("\tforceclosechannel: {:?}", help.forceclosechannel);
+					println!("\tcloseallchannels: {:?}", help.closeallchannels);
+					println!("\tabandonchannel: {:?}", help.abandonchannel);
+					println!("\tsetpeerlabel: {:?}", help.setpeerlabel);
+					println!("\tsetchannellabel: {:?}", help.setchannellabel);
+					println!("\tchannelhistory: {:?}", help.channelhistory);
+					println!("\tautopilot: {:?}", help.autopilot);
+					println!("\tlspinfo: {:?}", help.lspinfo);
+					println!("\tlsporder: {:?}", help.lsporder);
+					println!("\tlsporderstatus: {:?}", help.lsporderstatus);
 					println!("\tlistpayments: {:?}", help.listpayments);
 					println!("\tsignmessage: {:?}", help.signmessage);
+					println!("\tverifymessage: {:?}", help.verifymessage);
+					println!("\tdiagnosechannel: {:?}", help.diagnosechannel);
+					println!("\tkeysend: {:?}", help.keysend);
+					println!("\tdecodeinvoice: {:?}", help.decodeinvoice);
+					println!("\tnewaddress: {:?}", help.newaddress);
+					println!("\twalletbalance: {:?}", help.walletbalance);
+					println!("\tbalance: {:?}", help.balance);
+					println!("\tsendonchain: {:?}", help.sendonchain);
+					println!("\tlistutxos: {:?}", help.listutxos);
+					println!("\tpendingtxs: {:?}", help.pendingtxs);
+					println!("\tevents: {:?}", help.events);
+					println!("\teventhistory: {:?}", help.eventhistory);
+					println!("\tlookuppayment: {:?}", help.lookuppayment);
+					println!("\tpayment: {:?}", help.payment);
+					println!("\tcancelpayment: {:?}", help.cancelpayment);
+					println!("\tclosedchannels: {:?}", help.closedchannels);
+					println!("\tlistforwards: {:?}", help.listforwards);
+					println!("\tfeereport: {:?}", help.feereport);
+					println!("\tupdatechannelpolicy: {:?}", help.updatechannelpolicy);
+					println!("\tsetnodeinfo: {:?}", help.setnodeinfo);
+					println!("\taddwebhook: {:?}", help.addwebhook);
+					println!("\tlistwebhooks: {:?}", help.listwebhooks);
+					println!("\tremovewebhook: {:?}", help.removewebhook);
+					println!("\texportbackup: {:?}", help.exportbackup);
+					println!("\texportpayments: {:?}", help.exportpayments);
+					println!("\texportforwards: {:?}", help.exportforwards);
+					println!("\tbackupseed: {:?}", help.backupseed);
+					println!("\thealth: {:?}", help.health);
+					println!("\tfeerates: {:?}", help.feerates);
+					println!("\tqueryroutes: {:?}", help.queryroutes);
+					println!("\tsendtoroute: {:?}", help.sendtoroute);
+					println!("\trebalance: {:?}", help.rebalance);
+					println!("\tprobe: {:?}", help.probe);
+					println!("\tscorerparams: {:?}", help.scorerparams);
 				}
 				Err(e) => {
 					println!("LN-Node-server error: {}", e);
@@ -216,10 +1363,33 @@ async fn main() {
 					println!("-----------------------------------");
 					println!("LN-Node node information:");
 					println!("-----------------------------------");
+					println!("\tversion: {}", info.version);
 					println!("\tpubkey: {:?}", format!("{}", info.pubkey));
+					println!("\talias: {}", info.alias);
+					println!("\tcolor: {}", info.color);
+					println!("\tnetwork: {}", info.network);
+					println!("\tblock_height: {}", info.block_height);
+					println!("\tblock_hash: {}", info.block_hash);
+					println!("\tsynced_to_chain: {}", info.synced_to_chain);
+					println!("\tsynced_to_graph: {}", info.synced_to_graph);
+					println!("\taddresses: {:?}", info.addresses);
+					println!("\tlisten_addresses: {:?}", info.listen_addresses);
 					println!("\tchannels_number: {:?}", info.channels_number);
 					println!("\tusable_channels_number: {:?}", info.usable_channels_number);
-					println!("\tlocal_balance_msat: {:?}", info.local_balance_msat);
+					println!("\tlocal_balance_msat: {}", format_msat(info.local_balance_msat));
+					// bitcoind may be unable to report the on-chain balance
+					let onchain_balance = |balance_sat: Option<u64>| match balance_sat {
+						Some(balance_sat) => format_sat(balance_sat),
+						None => "unknown".to_string(),
+					};
+					println!(
+						"\tonchain_confirmed_balance_sat: {}",
+						onchain_balance(info.onchain_confirmed_balance_sat)
+					);
+					println!(
+						"\tonchain_unconfirmed_balance_sat: {}",
+						onchain_balance(info.onchain_unconfirmed_balance_sat)
+					);
 					println!("\tpeers: {:?}", info.peers);
 				}
 				Err(e) => {
@@ -228,6 +1398,13 @@ async fn main() {
 			}
 		}
 		"connectpeer" => {
+			if !resp.status().is_success() {
+				match resp.json::<ServerError>().await {
+					Ok(error) => println!("{}", error),
+					Err(e) => println!("LN-Node-server error: {}", e),
+				}
+				return;
+			}
 			let connectpeer_resp = resp.json::<ServerSuccess>().await;
 			match connectpeer_resp {
 				Ok(peer_msg) => {
@@ -255,6 +1432,59 @@ async fn main() {
 				}
 			}
 		}
+		"openchannels" => {
+			let openchannels_resp = resp.json::<OpenedChannels>().await;
+			match openchannels_resp {
+				Ok(opened) => {
+					println!("-----------------------------------");
+					println!("LN-Node opening payment channels:");
+					println!("-----------------------------------");
+					for result in opened.results {
+						println!("\tpeer: {:?}", result.pubkey);
+						if let Some(msg) = result.msg {
+							println!("\tchannel message: {:?}", msg);
+						}
+						if let Some(error) = result.error {
+							println!("\terror: {}", error);
+						}
+						println!("-----------------------------------");
+					}
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"closeallchannels" => {
+			let status = resp.status();
+			if !status.is_success() {
+				match resp.json::<ServerError>().await {
+					Ok(error) => println!("{}", error),
+					Err(e) => println!("LN-Node-server error: {}", e),
+				}
+				return;
+			}
+			match resp.json::<ClosingChannels>().await {
+				Ok(closing) => {
+					println!("-----------------------------------");
+					println!("LN-Node closing all channels:");
+					println!("-----------------------------------");
+					for result in closing.results {
+						println!("\tchannel_id: {:?}", result.channel_id);
+						if let Some(msg) = result.msg {
+							println!("\tchannel message: {:?}", msg);
+						}
+						if let Some(error) = result.error {
+							println!("\terror: {}", error);
+						}
+						println!("-----------------------------------");
+					}
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
 		"listpeers" => {
 			let listpeers_resp = resp.json::<ListPeers>().await;
 			match listpeers_resp {
@@ -262,12 +1492,31 @@ async fn main() {
 					println!("-----------------------------------");
 					println!("LN-Node peers listing:");
 					println!("-----------------------------------");
-					println!("\tpeers: [");
 					for peer in peers.peers {
-						let peer_str = format!("{}", peer);
-						println!("\t{}", peer_str);
+						println!("\tpubkey: {}", peer.pubkey);
+						println!("\talias: {:?}", peer.alias);
+						if let Some(label) = peer.label {
+							println!("\tlabel: {:?}", label);
+						}
+						println!("\tconnected: {:?}", peer.connected);
+						if let Some(address) = peer.address {
+							println!("\taddress: {}", address);
+						}
+						if let Some(direction) = peer.direction {
+							println!("\tdirection: {:?}", direction);
+						}
+						if let Some(features) = peer.features {
+							println!("\tfeatures: {}", features);
+						}
+						println!("\tchannels: {}", peer.channels);
+						if let Some(connected_since) = peer.connected_since {
+							println!("\tconnected_since: {}", connected_since);
+						}
+						if let Some(last_seen) = peer.last_seen {
+							println!("\tlast_seen: {}", last_seen);
+						}
+						println!("-----------------------------------");
 					}
-					println!("\t]");
 				}
 				Err(e) => {
 					println!("LN-Node-server error: {}", e);
@@ -289,25 +1538,73 @@ async fn main() {
 							println!("\ttx_id: {:?}", channel.tx_id);
 							println!("\tpeer_pubkey: {:?}", format!("{}", channel.peer_pubkey));
 							println!("\tpeer_alias: {:?}", channel.peer_alias);
+							if let Some(label) = channel.peer_label {
+								println!("\tpeer_label: {:?}", label);
+							}
+							if let Some(label) = channel.label {
+								println!("\tlabel: {:?}", label);
+							}
+							if !channel.tags.is_empty() {
+								println!("\ttags: {:?}", channel.tags);
+							}
 							println!("\tis_confirmed_onchain: {:?}", channel.is_confirmed_onchain);
-							println!("\tlocal_balance_msat: {:?}", channel.local_balance_msat);
 							println!(
-								"\tchannel_value_satoshis: {:?}",
-								channel.channel_value_satoshis
+								"\tlocal_balance_msat: {}",
+								format_msat(channel.local_balance_msat)
 							);
 							println!(
-								"\tavailable_balance_for_send_msat: {:?}",
-								channel.available_balance_for_send_msat
+								"\tchannel_value_satoshis: {}",
+								format_sat(channel.channel_value_satoshis)
 							);
 							println!(
-								"\tavailable_balance_for_recv_msat: {:?}",
-								channel.available_balance_for_recv_msat
+								"\tavailable_balance_for_send_msat: {}",
+								format_msat(channel.available_balance_for_send_msat)
+							);
+							println!(
+								"\tavailable_balance_for_recv_msat: {}",
+								format_msat(channel.available_balance_for_recv_msat)
 							);
 							println!(
 								"\tchannel_can_send_payments: {:?}",
 								channel.channel_can_send_payments
 							);
 							println!("\tpublic: {:?}", channel.public);
+							if let (Some(base), Some(proportional), Some(cltv_delta)) = (
+								channel.fee_base_msat,
+								channel.fee_proportional_millionths,
+								channel.cltv_expiry_delta,
+							) {
+								println!(
+									"\tour_policy: {} msat + {} ppm, cltv_expiry_delta {}",
+									base, proportional, cltv_delta
+								);
+							}
+							if let (Some(base), Some(proportional), Some(cltv_delta)) = (
+								channel.counterparty_fee_base_msat,
+								channel.counterparty_fee_proportional_millionths,
+								channel.counterparty_cltv_expiry_delta,
+							) {
+								println!(
+									"\tcounterparty_policy: {} msat + {} ppm, cltv_expiry_delta {}",
+									base, proportional, cltv_delta
+								);
+							}
+							if let Some(htlc_minimum_msat) = channel.htlc_minimum_msat {
+								println!("\thtlc_minimum_msat: {}", htlc_minimum_msat);
+							}
+							if let Some(htlc_maximum_msat) = channel.htlc_maximum_msat {
+								println!("\thtlc_maximum_msat: {}", htlc_maximum_msat);
+							}
+							println!(
+								"\tcounterparty_reserve_satoshis: {}",
+								format_sat(channel.counterparty_reserve_satoshis)
+							);
+							if let Some(reserve) = channel.unspendable_punishment_reserve_satoshis {
+								println!(
+									"\tunspendable_punishment_reserve_satoshis: {}",
+									format_sat(reserve)
+								);
+							}
 							println!("    --------------------");
 						}
 					}
@@ -317,26 +1614,70 @@ async fn main() {
 				}
 			}
 		}
-		"getinvoice" => {
-			let getinvoice_resp = resp.json::<ServerInvoice>().await;
-			match getinvoice_resp {
+		"getphantominvoice" => {
+			let status = resp.status();
+			if !status.is_success() {
+				match resp.json::<ServerError>().await {
+					Ok(error) => println!("{}", error),
+					Err(e) => println!("LN-Node-server error: {}", e),
+				}
+				return;
+			}
+			let getphantominvoice_resp = resp.json::<CreatedInvoice>().await;
+			match getphantominvoice_resp {
 				Ok(invoice) => {
 					println!("-----------------------------------");
-					println!("LN-Node channels listing:");
+					println!("LN-Node phantom invoice:");
 					println!("-----------------------------------");
 					println!("\tinvoice: {:?}", invoice.invoice);
+					println!("\tpayment_hash: {:?}", invoice.payment_hash);
+					println!("\texpiry_secs: {:?}", invoice.expiry_secs);
 				}
 				Err(e) => {
 					println!("LN-Node-server error: {}", e);
 				}
 			}
 		}
-		"sendpayment" => {
-			let sendpayment_resp = resp.json::<ServerSuccess>().await;
-			match sendpayment_resp {
+		"unifiedinvoice" => {
+			let status = resp.status();
+			if !status.is_success() {
+				match resp.json::<ServerError>().await {
+					Ok(error) => println!("{}", error),
+					Err(e) => println!("LN-Node-server error: {}", e),
+				}
+				return;
+			}
+			let unifiedinvoice_resp = resp.json::<UnifiedInvoice>().await;
+			match unifiedinvoice_resp {
+				Ok(invoice) => {
+					println!("-----------------------------------");
+					println!("LN-Node unified invoice:");
+					println!("-----------------------------------");
+					println!("\turi: {}", invoice.uri);
+					println!("\taddress: {}", invoice.address);
+					println!("\tinvoice: {}", invoice.invoice);
+					println!("\tpayment_hash: {}", invoice.payment_hash);
+					println!("\texpiry_secs: {}", invoice.expiry_secs);
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"withdraw" => {
+			let status = resp.status();
+			if !status.is_success() {
+				match resp.json::<ServerError>().await {
+					Ok(error) => println!("{}", error),
+					Err(e) => println!("LN-Node-server error: {}", e),
+				}
+				return;
+			}
+			let withdraw_resp = resp.json::<ServerSuccess>().await;
+			match withdraw_resp {
 				Ok(msg) => {
 					println!("-----------------------------------");
-					println!("LN-Node sending payment:");
+					println!("LN-Node LNURL-withdraw:");
 					println!("-----------------------------------");
 					println!("\tmessage: {:?}", msg.msg);
 				}
@@ -345,21 +1686,59 @@ async fn main() {
 				}
 			}
 		}
-		"listpayments" => {
-			let listpayments_resp = resp.json::<Payments>().await;
-			match listpayments_resp {
-				Ok(payments) => {
+		"phantomroutehints" => {
+			let phantomroutehints_resp = resp.json::<PhantomRouteHintsInfo>().await;
+			match phantomroutehints_resp {
+				Ok(hints) => {
 					println!("-----------------------------------");
-					println!("LN-Node payments listing:");
+					println!("LN-Node phantom route hints:");
 					println!("-----------------------------------");
-					if payments.payments.len() == 0 {
-						println!("\tpayments: []");
+					println!("\troute_hints: {}", hints.route_hints);
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"getinvoice" => {
+			let getinvoice_resp = resp.json::<CreatedInvoice>().await;
+			match getinvoice_resp {
+				Ok(invoice) => {
+					println!("-----------------------------------");
+					println!("LN-Node channels listing:");
+					println!("-----------------------------------");
+					println!("\tinvoice: {:?}", invoice.invoice);
+					println!("\tpayment_hash: {:?}", invoice.payment_hash);
+					println!("\texpiry_secs: {:?}", invoice.expiry_secs);
+					println!("\tlabel: {:?}", invoice.label);
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"listinvoices" => {
+			let listinvoices_resp = resp.json::<Invoices>().await;
+			match listinvoices_resp {
+				Ok(invoices) => {
+					println!("-----------------------------------");
+					println!("LN-Node invoices listing:");
+					println!("-----------------------------------");
+					if invoices.invoices.len() == 0 {
+						println!("\tinvoices: []");
 					} else {
-						for payment in payments.payments {
-							println!("\tamount_millisatoshis: {}", payment.amount_millisatoshis);
-							println!("\tpayment_hash: {}", payment.payment_hash);
-							println!("\thtlc_direction: {}", payment.htlc_direction);
-							println!("\thtlc_status: {}", payment.htlc_status);
+						for invoice in invoices.invoices {
+							println!("\tinvoice: {}", invoice.invoice);
+							println!("\tpayment_hash: {}", invoice.payment_hash);
+							println!("\tstate: {:?}", invoice.state);
+							println!("\tdescription: {:?}", invoice.description);
+							println!("\tamt_msat: {:?}", invoice.amt_msat);
+							if let Some(label) = invoice.label {
+								println!("\tlabel: {}", label);
+							}
+							println!("\tcreated_at: {}", invoice.created_at);
+							println!("\texpires_at: {}", invoice.expires_at);
+							println!("\tsettled_at: {:?}", invoice.settled_at);
 							println!("    --------------------");
 						}
 					}
@@ -369,49 +1748,1125 @@ async fn main() {
 				}
 			}
 		}
-		"closechannel" => {
-			let closechannel_resp = resp.json::<ServerSuccess>().await;
-
-			match closechannel_resp {
+		"cancelinvoice" => {
+			let status = resp.status();
+			if !status.is_success() {
+				match resp.json::<ServerError>().await {
+					Ok(error) => println!("{}", error),
+					Err(e) => println!("LN-Node-server error: {}", e),
+				}
+				return;
+			}
+			let cancelinvoice_resp = resp.json::<ServerSuccess>().await;
+			match cancelinvoice_resp {
 				Ok(msg) => {
 					println!("-----------------------------------");
-					println!("LN-Node close channel message:");
+					println!("LN-Node cancelling invoice:");
 					println!("-----------------------------------");
-					println!("\tclosechannel message: {:?}", msg.msg);
+					println!("\tmessage: {:?}", msg.msg);
 				}
 				Err(e) => {
 					println!("LN-Node-server error: {}", e);
 				}
 			}
 		}
-		"forceclosechannel" => {
-			let forceclosechannel_resp = resp.json::<ServerSuccess>().await;
-
-			match forceclosechannel_resp {
-				Ok(msg) => {
+		"sendpayment" if wait => {
+			let body = resp.text().await.unwrap_or_default();
+			match serde_json::from_str::<PaymentResult>(&body) {
+				Ok(result) => {
 					println!("-----------------------------------");
-					println!("LN-Node force close channel message:");
+					println!("LN-Node payment sent:");
 					println!("-----------------------------------");
-					println!("\tforceclosechannel message: {:?}", msg.msg);
-				}
-				Err(e) => {
-					println!("LN-Node-server error: {}", e);
+					println!("\tpayment_hash: {}", result.payment_hash);
+					println!("\tpayment_preimage: {}", result.payment_preimage);
+					println!("\tfee_paid_msat: {:?}", result.fee_paid_msat);
 				}
+				Err(e) => match serde_json::from_str::<ServerError>(&body) {
+					Ok(error) => println!("LN-Node-server error: {}", error),
+					Err(_) => println!("LN-Node-server error: {}", e),
+				},
 			}
 		}
-		"signmessage" => {
-			let signmessage_resp = resp.json::<ServerSuccess>().await;
-
-			match signmessage_resp {
+		"pay" => {
+			let status = resp.status();
+			let body = resp.text().await.unwrap_or_default();
+			if !status.is_success() {
+				match serde_json::from_str::<ServerError>(&body) {
+					Ok(error) => println!("{}", error),
+					Err(_) => println!("LN-Node-server error: {}", body),
+				}
+				std::process::exit(1);
+			}
+			// The outcome of a payment only follows when waiting for it
+			if let Ok(result) = serde_json::from_str::<PaymentResult>(&body) {
+				println!("-----------------------------------");
+				println!("LN-Node payment sent:");
+				println!("-----------------------------------");
+				println!("\tpayment_hash: {}", result.payment_hash);
+				println!("\tpayment_preimage: {}", result.payment_preimage);
+				match result.fee_paid_msat {
+					Some(fee_paid_msat) => {
+						println!("\tfee_paid_msat: {}", format_msat(fee_paid_msat))
+					}
+					None => println!("\tfee_paid_msat: unknown"),
+				}
+				return;
+			}
+			match serde_json::from_str::<ServerSuccess>(&body) {
 				Ok(msg) => {
 					println!("-----------------------------------");
-					println!("LN-Node sign message message:");
+					println!("LN-Node sending payment:");
 					println!("-----------------------------------");
-					println!("\tsignature: {:?}", msg.msg);
+					println!("\tmessage: {:?}", msg.msg);
 				}
 				Err(e) => {
 					println!("LN-Node-server error: {}", e);
+					std::process::exit(1);
+				}
+			}
+		}
+		"sendpayment" => {
+			let sendpayment_resp = resp.json::<ServerSuccess>().await;
+			match sendpayment_resp {
+				Ok(msg) => {
+					println!("-----------------------------------");
+					println!("LN-Node sending payment:");
+					println!("-----------------------------------");
+					println!("\tmessage: {:?}", msg.msg);
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"decodeinvoice" => {
+			let decodeinvoice_resp = resp.json::<DecodedInvoice>().await;
+			match decodeinvoice_resp {
+				Ok(invoice) => {
+					println!("-----------------------------------");
+					println!("LN-Node decoded invoice:");
+					println!("-----------------------------------");
+					println!("\tdestination: {:?}", invoice.destination);
+					println!("\tamount_msat: {:?}", invoice.amount_msat);
+					println!("\tpayment_hash: {:?}", invoice.payment_hash);
+					println!("\ttimestamp: {:?}", invoice.timestamp);
+					println!("\texpiry_secs: {:?}", invoice.expiry_secs);
+					println!("\tis_expired: {:?}", invoice.is_expired);
+					println!("\tdescription: {:?}", invoice.description);
+					println!("\tdescription_hash: {:?}", invoice.description_hash);
+					println!("\tmin_final_cltv_expiry: {:?}", invoice.min_final_cltv_expiry);
+					println!("\troute_hints: [");
+					for hint in invoice.route_hints {
+						for hop in hint {
+							println!(
+								"\t{} via {} (base {} msat, {} ppm, cltv delta {})",
+								hop.src_node_id,
+								hop.short_channel_id,
+								hop.fee_base_msat,
+								hop.fee_proportional_millionths,
+								hop.cltv_expiry_delta
+							);
+						}
+						println!("    --------------------");
+					}
+					println!("\t]");
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"keysend" => {
+			let keysend_resp = resp.json::<ServerSuccess>().await;
+			match keysend_resp {
+				Ok(msg) => {
+					println!("-----------------------------------");
+					println!("LN-Node sending keysend payment:");
+					println!("-----------------------------------");
+					println!("\tmessage: {:?}", msg.msg);
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"queryroutes" => {
+			let status = resp.status();
+			if !status.is_success() {
+				match resp.json::<ServerError>().await {
+					Ok(error) => println!("{}", error),
+					Err(e) => println!("LN-Node-server error: {}", e),
+				}
+				return;
+			}
+			let queryroutes_resp = resp.json::<QueriedRoutes>().await;
+			match queryroutes_resp {
+				Ok(queried) => {
+					println!("-----------------------------------");
+					println!("LN-Node routes:");
+					println!("-----------------------------------");
+					for route in queried.routes {
+						print_route(route);
+						println!("-----------------------------------");
+					}
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"scorerparams" => {
+			let status = resp.status();
+			if !status.is_success() {
+				match resp.json::<ServerError>().await {
+					Ok(error) => println!("{}", error),
+					Err(e) => println!("LN-Node-server error: {}", e),
+				}
+				return;
+			}
+			match resp.json::<ScorerParams>().await {
+				Ok(params) => {
+					println!("-----------------------------------");
+					println!("LN-Node scorer parameters:");
+					println!("-----------------------------------");
+					println!("\tbase_penalty_msat: {}", params.base_penalty_msat);
+					println!(
+						"\tliquidity_penalty_multiplier_msat: {}",
+						params.liquidity_penalty_multiplier_msat
+					);
+					println!(
+						"\tliquidity_offset_half_life_secs: {}",
+						params.liquidity_offset_half_life_secs
+					);
+					println!(
+						"\tamount_penalty_multiplier_msat: {}",
+						params.amount_penalty_multiplier_msat
+					);
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"probe" => {
+			let status = resp.status();
+			if !status.is_success() {
+				match resp.json::<ServerError>().await {
+					Ok(error) => println!("{}", error),
+					Err(e) => println!("LN-Node-server error: {}", e),
+				}
+				return;
+			}
+			let probe_resp = resp.json::<ProbeResult>().await;
+			match probe_resp {
+				Ok(probe) => {
+					println!("-----------------------------------");
+					println!("LN-Node probe:");
+					println!("-----------------------------------");
+					println!("\tpayment_hash: {}", probe.payment_hash);
+					println!("\treached_destination: {}", probe.reached_destination);
+					if let Some(scid) = probe.failed_short_channel_id {
+						println!("\tfailed_short_channel_id: {}", scid);
+					}
+					print_route(probe.route);
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"sendtoroute" | "rebalance" => {
+			let status = resp.status();
+			if !status.is_success() {
+				match resp.json::<ServerError>().await {
+					Ok(error) => println!("{}", error),
+					Err(e) => println!("LN-Node-server error: {}", e),
+				}
+				return;
+			}
+			let sendtoroute_resp = resp.json::<SentToRoute>().await;
+			match sendtoroute_resp {
+				Ok(sent) => {
+					println!("-----------------------------------");
+					println!("LN-Node sending payment over route:");
+					println!("-----------------------------------");
+					println!("\tpayment_hash: {}", sent.payment_hash);
+					println!("\tpayment_id: {}", sent.payment_id);
+					print_route(sent.route);
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"listpayments" => {
+			let listpayments_resp = resp.json::<Payments>().await;
+			match listpayments_resp {
+				Ok(payments) => {
+					println!("-----------------------------------");
+					println!("LN-Node payments listing:");
+					println!("-----------------------------------");
+					println!(
+						"\tshowing {} of {} payments",
+						payments.payments.len(),
+						payments.total_payments
+					);
+					if payments.payments.len() == 0 {
+						println!("\tpayments: []");
+					} else {
+						for payment in payments.payments {
+							println!("\tamount_millisatoshis: {}", payment.amount_millisatoshis);
+							println!("\tpayment_hash: {}", payment.payment_hash);
+							println!("\thtlc_direction: {}", payment.htlc_direction);
+							println!("\thtlc_status: {}", payment.htlc_status);
+							if let Some(label) = payment.label {
+								println!("\tlabel: {}", label);
+							}
+							if payment.keysend {
+								println!("\tkeysend: true");
+							}
+							if let Some(fee_paid_msat) = payment.fee_paid_msat {
+								println!("\tfee_paid_msat: {}", fee_paid_msat);
+							}
+							println!("\tcreated_at: {:?}", payment.created_at);
+							println!("\tresolved_at: {:?}", payment.resolved_at);
+							for path in payment.paths {
+								println!("\tpath: [");
+								for hop in path {
+									println!(
+										"\t\t{} over {}: {} msat, fee {} msat",
+										hop.pubkey,
+										hop.short_channel_id,
+										hop.amt_to_forward_msat,
+										hop.fee_msat
+									);
+								}
+								println!("\t]");
+							}
+							println!("    --------------------");
+						}
+					}
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"closechannel" => {
+			let closechannel_resp = resp.json::<ServerSuccess>().await;
+
+			match closechannel_resp {
+				Ok(msg) => {
+					println!("-----------------------------------");
+					println!("LN-Node close channel message:");
+					println!("-----------------------------------");
+					println!("\tclosechannel message: {:?}", msg.msg);
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"forceclosechannel" => {
+			let forceclosechannel_resp = resp.json::<ServerSuccess>().await;
+
+			match forceclosechannel_resp {
+				Ok(msg) => {
+					println!("-----------------------------------");
+					println!("LN-Node force close channel message:");
+					println!("-----------------------------------");
+					println!("\tforceclosechannel message: {:?}", msg.msg);
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"setchannellabel" => {
+			if !resp.status().is_success() {
+				match resp.json::<ServerError>().await {
+					Ok(error) => println!("{}", error),
+					Err(e) => println!("LN-Node-server error: {}", e),
+				}
+				return;
+			}
+			match resp.json::<ChannelLabel>().await {
+				Ok(label) => {
+					println!("-----------------------------------");
+					println!("LN-Node channel label:");
+					println!("-----------------------------------");
+					println!("\tlabel: {:?}", label.label);
+					println!("\ttags: {:?}", label.tags);
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"channelhistory" => {
+			if !resp.status().is_success() {
+				match resp.json::<ServerError>().await {
+					Ok(error) => println!("{}", error),
+					Err(e) => println!("LN-Node-server error: {}", e),
+				}
+				return;
+			}
+			match resp.json::<ChannelBalanceHistory>().await {
+				Ok(history) => {
+					println!("-----------------------------------");
+					println!("LN-Node channel history:");
+					println!("-----------------------------------");
+					println!("\tchannel_id: {:?}", history.channel_id);
+					for sample in history.samples {
+						println!("\ttimestamp: {}", sample.timestamp);
+						println!(
+							"\tlocal_balance_msat: {}",
+							format_msat(sample.local_balance_msat)
+						);
+						println!(
+							"\tremote_balance_msat: {}",
+							format_msat(sample.remote_balance_msat)
+						);
+						println!("    --------------------");
+					}
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"autopilot" => {
+			if !resp.status().is_success() {
+				match resp.json::<ServerError>().await {
+					Ok(error) => println!("{}", error),
+					Err(e) => println!("LN-Node-server error: {}", e),
+				}
+				return;
+			}
+			if !matches!(&cli.command, CliCommand::Autopilot { action } if action == "status") {
+				match resp.json::<ServerSuccess>().await {
+					Ok(msg) => println!("\tautopilot message: {:?}", msg.msg),
+					Err(e) => println!("LN-Node-server error: {}", e),
+				}
+				return;
+			}
+			match resp.json::<AutopilotStatus>().await {
+				Ok(status) => {
+					println!("-----------------------------------");
+					println!("LN-Node autopilot:");
+					println!("-----------------------------------");
+					println!("\tenabled: {}", status.enabled);
+					println!(
+						"\tbudget: {} of {} used",
+						format_sat(status.budget_used_sat),
+						format_sat(status.budget_sat)
+					);
+					println!("\tchannel_size: {}", format_sat(status.channel_size_sat));
+					println!("\tmax_channels: {}", status.max_channels);
+					if let Some(last_run) = status.last_run {
+						println!("\tlast_run: {}", last_run);
+					}
+					if let Some(error) = status.last_error {
+						println!("\tlast_error: {}", error);
+					}
+					println!("\tchannels:");
+					for channel in status.channels {
+						println!(
+							"\t\t{}: {}, opened at {}",
+							channel.pubkey,
+							format_sat(channel.amount_sat),
+							channel.opened_at
+						);
+					}
+					println!("\tcandidates:");
+					for candidate in status.candidates {
+						println!(
+							"\t\t{} ({}): score {:.3}, {} channels, {}, uptime {:.0}%",
+							candidate.pubkey,
+							candidate.alias,
+							candidate.score,
+							candidate.channels,
+							format_sat(candidate.capacity_sat),
+							candidate.uptime * 100.0
+						);
+					}
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"lspinfo" | "lsporder" | "lsporderstatus" => {
+			if !resp.status().is_success() {
+				match resp.json::<ServerError>().await {
+					Ok(error) => println!("{}", error),
+					Err(e) => println!("LN-Node-server error: {}", e),
+				}
+				return;
+			}
+			match resp.json::<LspResponse>().await {
+				Ok(response) => {
+					println!("-----------------------------------");
+					println!("LN-Node LSP {}:", response.lsp_pubkey);
+					println!("-----------------------------------");
+					// The LSPS1 objects are printed as the LSP sent them
+					println!("{}", serde_json::to_string_pretty(&response.result).unwrap());
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"setpeerlabel" => {
+			if !resp.status().is_success() {
+				match resp.json::<ServerError>().await {
+					Ok(error) => println!("{}", error),
+					Err(e) => println!("LN-Node-server error: {}", e),
+				}
+				return;
+			}
+			match resp.json::<ServerSuccess>().await {
+				Ok(msg) => {
+					println!("-----------------------------------");
+					println!("LN-Node peer label message:");
+					println!("-----------------------------------");
+					println!("\tsetpeerlabel message: {:?}", msg.msg);
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"abandonchannel" => {
+			let status = resp.status();
+			if !status.is_success() {
+				match resp.json::<ServerError>().await {
+					Ok(error) => println!("{}", error),
+					Err(e) => println!("LN-Node-server error: {}", e),
+				}
+				return;
+			}
+			match resp.json::<ServerSuccess>().await {
+				Ok(msg) => {
+					println!("-----------------------------------");
+					println!("LN-Node abandon channel message:");
+					println!("-----------------------------------");
+					println!("\tabandonchannel message: {:?}", msg.msg);
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"signmessage" => {
+			let signmessage_resp = resp.json::<ServerSuccess>().await;
+
+			match signmessage_resp {
+				Ok(msg) => {
+					println!("-----------------------------------");
+					println!("LN-Node sign message message:");
+					println!("-----------------------------------");
+					println!("\tsignature: {:?}", msg.msg);
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"verifymessage" => {
+			let verifymessage_resp = resp.json::<VerifiedMessage>().await;
+
+			match verifymessage_resp {
+				Ok(verified) => {
+					println!("-----------------------------------");
+					println!("LN-Node verify message:");
+					println!("-----------------------------------");
+					println!("\tvalid: {}", verified.valid);
+					if let Some(pubkey) = verified.pubkey {
+						println!("\tpubkey: {}", pubkey);
+					}
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"newaddress" => {
+			let newaddress_resp = resp.json::<OnchainAddress>().await;
+			match newaddress_resp {
+				Ok(address) => {
+					println!("-----------------------------------");
+					println!("LN-Node new on-chain address:");
+					println!("-----------------------------------");
+					println!("\taddress: {:?}", address.address);
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"walletbalance" => {
+			let walletbalance_resp = resp.json::<WalletBalance>().await;
+			match walletbalance_resp {
+				Ok(balance) => {
+					println!("-----------------------------------");
+					println!("LN-Node wallet balance:");
+					println!("-----------------------------------");
+					println!("\tconfirmed_balance_sat: {:?}", balance.confirmed_balance_sat);
+					println!("\tunconfirmed_balance_sat: {:?}", balance.unconfirmed_balance_sat);
+					println!("\timmature_balance_sat: {:?}", balance.immature_balance_sat);
+					println!("\tlocal_balance_msat: {:?}", balance.local_balance_msat);
+					println!("\ttotal_balance_msat: {:?}", balance.total_balance_msat);
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"balance" => {
+			let balance_resp = resp.json::<BalanceSummary>().await;
+			match balance_resp {
+				Ok(balance) => {
+					println!("-----------------------------------");
+					println!("LN-Node balance:");
+					println!("-----------------------------------");
+					println!("\tonchain_confirmed_sat: {}", balance.onchain_confirmed_sat);
+					println!("\tonchain_unconfirmed_sat: {}", balance.onchain_unconfirmed_sat);
+					println!("\tonchain_immature_sat: {}", balance.onchain_immature_sat);
+					println!(
+						"\tlightning_local_balance_msat: {}",
+						balance.lightning_local_balance_msat
+					);
+					println!("\tlightning_spendable_msat: {}", balance.lightning_spendable_msat);
+					println!("\tlightning_receivable_msat: {}", balance.lightning_receivable_msat);
+					println!("\tchannel_reserve_sat: {}", balance.channel_reserve_sat);
+					println!("\tpending_close_sat: {}", balance.pending_close_sat);
+					println!("\tpending_sweep_sat: {}", balance.pending_sweep_sat);
+					println!("\ttotal_balance_msat: {}", balance.total_balance_msat);
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"sendonchain" => {
+			let sendonchain_resp = resp.json::<OnchainTransaction>().await;
+			match sendonchain_resp {
+				Ok(tx) => {
+					println!("-----------------------------------");
+					println!("LN-Node sending on-chain:");
+					println!("-----------------------------------");
+					println!("\ttxid: {:?}", tx.txid);
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"listutxos" => {
+			let listutxos_resp = resp.json::<ListUtxos>().await;
+			match listutxos_resp {
+				Ok(utxos) => {
+					println!("-----------------------------------");
+					println!("LN-Node utxos listing:");
+					println!("-----------------------------------");
+					if utxos.utxos.len() == 0 {
+						println!("\tutxos: []");
+					} else {
+						for utxo in utxos.utxos {
+							println!("\toutpoint: {}:{}", utxo.txid, utxo.vout);
+							println!("\tamount_sat: {}", utxo.amount_sat);
+							println!("\tconfirmations: {}", utxo.confirmations);
+							println!("\treserved: {}", utxo.reserved);
+							println!("    --------------------");
+						}
+					}
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"pendingtxs" => {
+			let pendingtxs_resp = resp.json::<PendingTxs>().await;
+			match pendingtxs_resp {
+				Ok(txs) => {
+					println!("-----------------------------------");
+					println!("LN-Node pending transactions:");
+					println!("-----------------------------------");
+					if txs.pending_txs.len() == 0 {
+						println!("\tpending_txs: []");
+					} else {
+						for tx in txs.pending_txs {
+							println!("\ttxid: {}", tx.txid);
+							println!("\tkind: {}", tx.kind);
+							println!("\tconfirmations: {}", tx.confirmations);
+							println!("\tbroadcasts: {}", tx.broadcasts);
+							println!("\tfirst_broadcast_at: {}", tx.first_broadcast_at);
+							println!("\tlast_broadcast_at: {}", tx.last_broadcast_at);
+							println!("    --------------------");
+						}
+					}
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"diagnosechannel" => {
+			let diagnosechannel_resp = resp.json::<ChannelDiagnostics>().await;
+
+			match diagnosechannel_resp {
+				Ok(diagnostics) => {
+					println!("-----------------------------------");
+					println!("LN-Node channel diagnostics:");
+					println!("-----------------------------------");
+					println!("\tchannel_id: {:?}", diagnostics.channel_id);
+					println!("\tpeer_pubkey: {:?}", diagnostics.peer_pubkey);
+					println!("\tpeer_connected: {:?}", diagnostics.peer_connected);
+					println!("\tpeer_address: {:?}", diagnostics.peer_address);
+					println!("\tis_outbound: {:?}", diagnostics.is_outbound);
+					println!("\tis_confirmed_onchain: {:?}", diagnostics.is_confirmed_onchain);
+					println!("\tconfirmations_required: {:?}", diagnostics.confirmations_required);
+					println!("\tis_usable: {:?}", diagnostics.is_usable);
+					for balance in diagnostics.claimable_balances {
+						println!(
+							"\tclaimable_balance: {} {} sats (height {:?})",
+							balance.kind, balance.amount_satoshis, balance.height
+						);
+					}
+					println!(
+						"\tfeerate_normal_sat_per_kw: {:?}",
+						diagnostics.feerate_normal_sat_per_kw
+					);
+					println!(
+						"\tfeerate_high_priority_sat_per_kw: {:?}",
+						diagnostics.feerate_high_priority_sat_per_kw
+					);
+					println!("\tissues: [");
+					for issue in diagnostics.issues {
+						println!("\t{}", issue);
+					}
+					println!("\t]");
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"closedchannels" => {
+			let closedchannels_resp = resp.json::<ClosedChannels>().await;
+
+			match closedchannels_resp {
+				Ok(list) => {
+					println!("-----------------------------------");
+					println!("LN-Node closed channels:");
+					println!("-----------------------------------");
+					for channel in list.channels {
+						println!("\tchannel_id: {}", channel.channel_id);
+						println!("\tclosed_at: {}", channel.closed_at);
+						println!("\tclose_reason: {}", channel.close_reason);
+						println!("\tclosing_txid: {:?}", channel.closing_txid);
+						if let Some(state) = channel.last_state {
+							println!("\tpeer_pubkey: {}", state.peer_pubkey);
+							println!("\tchannel_value_satoshis: {}", state.channel_value_satoshis);
+							println!("\tfunding_txid: {:?}", state.funding_txid);
+							println!("\tsettled_balance_msat: {}", state.balance_msat);
+						}
+						println!("    --------------------");
+					}
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"lookuppayment" | "payment" => {
+			let status = resp.status();
+			if !status.is_success() {
+				match resp.json::<ServerError>().await {
+					Ok(error) => println!("{}", error),
+					Err(e) => println!("LN-Node-server error: {}", e),
+				}
+				return;
+			}
+			let lookuppayment_resp = resp.json::<PaymentDetails>().await;
+
+			match lookuppayment_resp {
+				Ok(payment) => {
+					println!("-----------------------------------");
+					println!("LN-Node payment:");
+					println!("-----------------------------------");
+					println!("\tpayment_hash: {}", payment.payment_hash);
+					println!("\tpayment_id: {:?}", payment.payment_id);
+					println!("\thtlc_direction: {}", payment.htlc_direction);
+					println!("\thtlc_status: {}", payment.htlc_status);
+					println!("\tamount_millisatoshis: {}", payment.amount_millisatoshis);
+					println!("\tpreimage: {:?}", payment.preimage);
+					println!("\tlabel: {:?}", payment.label);
+					println!("\tkeysend: {}", payment.keysend);
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"cancelpayment" => {
+			let status = resp.status();
+			if !status.is_success() {
+				match resp.json::<ServerError>().await {
+					Ok(error) => println!("{}", error),
+					Err(e) => println!("LN-Node-server error: {}", e),
+				}
+				return;
+			}
+			let cancelpayment_resp = resp.json::<ServerSuccess>().await;
+			match cancelpayment_resp {
+				Ok(msg) => {
+					println!("-----------------------------------");
+					println!("LN-Node cancelling payment:");
+					println!("-----------------------------------");
+					println!("\tmessage: {:?}", msg.msg);
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"feereport" => {
+			let status = resp.status();
+			if !status.is_success() {
+				match resp.json::<ServerError>().await {
+					Ok(error) => println!("{}", error),
+					Err(e) => println!("LN-Node-server error: {}", e),
+				}
+				return;
+			}
+			let feereport_resp = resp.json::<FeeReport>().await;
+
+			match feereport_resp {
+				Ok(report) => {
+					println!("-----------------------------------");
+					println!("LN-Node fee report:");
+					println!("-----------------------------------");
+					for channel in report.channel_fees {
+						println!("\tchannel_id: {}", channel.channel_id);
+						println!("\tpeer_pubkey: {}", channel.peer_pubkey);
+						println!("\tshort_channel_id: {:?}", channel.short_channel_id);
+						println!("\tbase_fee_msat: {:?}", channel.base_fee_msat);
+						println!(
+							"\tfee_proportional_millionths: {:?}",
+							channel.fee_proportional_millionths
+						);
+						println!("\tday_fee_sum_msat: {}", channel.day_fee_sum_msat);
+						println!("\tweek_fee_sum_msat: {}", channel.week_fee_sum_msat);
+						println!("\tmonth_fee_sum_msat: {}", channel.month_fee_sum_msat);
+						println!("    --------------------");
+					}
+					println!("\tday_fee_sum_msat: {}", report.day_fee_sum_msat);
+					println!("\tweek_fee_sum_msat: {}", report.week_fee_sum_msat);
+					println!("\tmonth_fee_sum_msat: {}", report.month_fee_sum_msat);
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"listforwards" => {
+			let listforwards_resp = resp.json::<Forwards>().await;
+
+			match listforwards_resp {
+				Ok(list) => {
+					println!("-----------------------------------");
+					println!("LN-Node forwarding history:");
+					println!("-----------------------------------");
+					for forward in list.forwards {
+						println!("\ttimestamp: {}", forward.timestamp);
+						println!("\tprev_channel_id: {:?}", forward.prev_channel_id);
+						println!("\tnext_channel_id: {:?}", forward.next_channel_id);
+						println!("\tamt_forwarded_msat: {:?}", forward.amt_forwarded_msat);
+						println!("\tfee_earned_msat: {:?}", forward.fee_earned_msat);
+						println!("\tclaim_from_onchain_tx: {}", forward.claim_from_onchain_tx);
+						println!("    --------------------");
+					}
+					println!("\ttotal_fee_earned_msat: {}", list.total_fee_earned_msat);
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"updatechannelpolicy" => {
+			let updatechannelpolicy_resp = resp.json::<ChannelPolicy>().await;
+
+			match updatechannelpolicy_resp {
+				Ok(policy) => {
+					println!("-----------------------------------");
+					println!("LN-Node channel policy:");
+					println!("-----------------------------------");
+					println!("\tforwarding_fee_base_msat: {}", policy.forwarding_fee_base_msat);
+					println!(
+						"\tforwarding_fee_proportional_millionths: {}",
+						policy.forwarding_fee_proportional_millionths
+					);
+					println!("\tcltv_expiry_delta: {}", policy.cltv_expiry_delta);
+					if let Some(htlc_minimum_msat) = policy.htlc_minimum_msat {
+						println!("\thtlc_minimum_msat: {}", htlc_minimum_msat);
+					}
+					println!("\tmin_final_cltv_expiry: {}", policy.min_final_cltv_expiry);
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"setnodeinfo" => {
+			let setnodeinfo_resp = resp.json::<NodeAnnouncementInfo>().await;
+
+			match setnodeinfo_resp {
+				Ok(info) => {
+					println!("-----------------------------------");
+					println!("LN-Node announcement:");
+					println!("-----------------------------------");
+					println!("\talias: {}", info.alias);
+					println!("\tcolor: #{}", info.color);
+					println!("\taddresses: {:?}", info.addresses);
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"addwebhook" => {
+			let addwebhook_resp = resp.json::<WebhookInfo>().await;
+
+			match addwebhook_resp {
+				Ok(webhook) => {
+					println!("-----------------------------------");
+					println!("LN-Node webhook:");
+					println!("-----------------------------------");
+					println!("\tid: {}", webhook.id);
+					println!("\turl: {}", webhook.url);
+					println!("\tsecret: {}", webhook.secret.unwrap_or_default());
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"listwebhooks" => {
+			let listwebhooks_resp = resp.json::<ListWebhooks>().await;
+
+			match listwebhooks_resp {
+				Ok(list) => {
+					println!("-----------------------------------");
+					println!("LN-Node webhooks:");
+					println!("-----------------------------------");
+					for webhook in list.webhooks {
+						println!("\tid: {}", webhook.id);
+						println!("\turl: {}", webhook.url);
+						println!("    --------------------");
+					}
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"removewebhook" => {
+			let removewebhook_resp = resp.json::<ServerSuccess>().await;
+
+			match removewebhook_resp {
+				Ok(success) => {
+					println!("-----------------------------------");
+					println!("LN-Node webhook removal:");
+					println!("-----------------------------------");
+					println!("\t{}", success.msg);
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"exportbackup" => {
+			let exportbackup_resp = resp.json::<ChannelBackupExport>().await;
+
+			match exportbackup_resp {
+				Ok(export) => {
+					println!("-----------------------------------");
+					println!("LN-Node channel backup:");
+					println!("-----------------------------------");
+					let written =
+						base64::decode(&export.backup).map_err(|e| e.to_string()).and_then(
+							|bundle| std::fs::write(output_path, bundle).map_err(|e| e.to_string()),
+						);
+					match written {
+						Ok(()) => {
+							println!("\tchannels_number: {:?}", export.channels_number);
+							println!("\tbackup written to {}", output_path);
+						}
+						Err(e) => println!("\tERROR: unable to write backup: {}", e),
+					}
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"exportpayments" | "exportforwards" => {
+			let status = resp.status();
+			if !status.is_success() {
+				match resp.json::<ServerError>().await {
+					Ok(error) => println!("{}", error),
+					Err(e) => println!("LN-Node-server error: {}", e),
+				}
+				return;
+			}
+			match resp.text().await {
+				Ok(export) => {
+					println!("-----------------------------------");
+					println!("LN-Node {} export:", &path["export".len()..]);
+					println!("-----------------------------------");
+					match std::fs::write(output_path, export) {
+						Ok(()) => println!("\texport written to {}", output_path),
+						Err(e) => println!("\tERROR: unable to write export: {}", e),
+					}
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"health" => {
+			let health_resp = resp.json::<Health>().await;
+
+			match health_resp {
+				Ok(health) => {
+					println!("-----------------------------------");
+					println!("LN-Node health:");
+					println!("-----------------------------------");
+					println!("\tbitcoind_reachable: {}", health.bitcoind_reachable);
+					if let Some(error) = health.bitcoind_last_error {
+						println!("\tbitcoind_last_error: {}", error);
+					}
+					if let Some(last_contact) = health.bitcoind_last_contact {
+						println!("\tbitcoind_last_contact: {}", last_contact);
+					}
+					if let Some(height) = health.bitcoind_block_height {
+						println!("\tbitcoind_block_height: {}", height);
+					}
+					println!("\tldk_block_height: {}", health.ldk_block_height);
+					if let Some(lag) = health.sync_lag_blocks {
+						println!("\tsync_lag_blocks: {}", lag);
+					}
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"feerates" => {
+			let fee_rates_resp = resp.json::<FeeRates>().await;
+
+			match fee_rates_resp {
+				Ok(fee_rates) => {
+					println!("-----------------------------------");
+					println!("LN-Node fee rates:");
+					println!("-----------------------------------");
+					println!("\tbackground_sat_per_kw: {}", fee_rates.background_sat_per_kw);
+					println!("\tnormal_sat_per_kw: {}", fee_rates.normal_sat_per_kw);
+					println!("\thigh_priority_sat_per_kw: {}", fee_rates.high_priority_sat_per_kw);
+					for estimate in fee_rates.estimates {
+						println!("-----------------------------------");
+						println!("\ttarget_conf: {}", estimate.target_conf);
+						println!("\tsat_per_kw: {}", estimate.sat_per_kw);
+						println!("\tsat_per_vbyte: {:.2}", estimate.sat_per_vbyte);
+					}
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"backupseed" => {
+			let status = resp.status();
+			if !status.is_success() {
+				match resp.json::<ServerError>().await {
+					Ok(error) => println!("{}", error),
+					Err(e) => println!("LN-Node-server error: {}", e),
+				}
+				return;
+			}
+			let backupseed_resp = resp.json::<SeedMnemonic>().await;
+
+			match backupseed_resp {
+				Ok(seed) => {
+					println!("-----------------------------------");
+					println!("LN-Node mnemonic, write it down as it won't be displayed again:");
+					println!("-----------------------------------");
+					for (idx, word) in seed.mnemonic.split_whitespace().enumerate() {
+						println!("\t{:>2}. {}", idx + 1, word);
+					}
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"events" => {
+			if !json_output {
+				println!("-----------------------------------");
+				println!("LN-Node events:");
+				println!("-----------------------------------");
+			}
+			print_events(resp, json_output, |_| true, None).await;
+		}
+		"eventhistory" => {
+			let eventhistory_resp = resp.json::<JournaledEvents>().await;
+			match eventhistory_resp {
+				Ok(history) => {
+					println!("-----------------------------------");
+					println!("LN-Node event history:");
+					println!("-----------------------------------");
+					println!(
+						"\tshowing {} of {} events",
+						history.events.len(),
+						history.total_events
+					);
+					if history.events.len() == 0 {
+						println!("\tevents: []");
+					} else {
+						for entry in history.events {
+							println!("\tid: {}", entry.id);
+							println!("\ttimestamp: {}", entry.timestamp);
+							println!("\tevent_type: {}", entry.event_type);
+							if let Some(payment_hash) = entry.payment_hash {
+								println!("\tpayment_hash: {}", payment_hash);
+							}
+							if let Some(channel_id) = entry.channel_id {
+								println!("\tchannel_id: {}", channel_id);
+							}
+							println!("\tresolution: {}", entry.resolution);
+							println!("\tevent: {}", entry.event);
+							println!("    --------------------");
+						}
+					}
+				}
+				Err(e) => {
+					println!("LN-Node-server error: {}", e);
+				}
+			}
+		}
+		"subscribe" => {
+			if let CliCommand::Subscribe { topic, paymenthash, count } = &cli.command {
+				if !json_output {
+					println!("-----------------------------------");
+					println!("LN-Node {} events:", topic);
+					println!("-----------------------------------");
 				}
+				let filter =
+					|event: &serde_json::Value| is_subscribed(topic, paymenthash.as_deref(), event);
+				print_events(resp, json_output, filter, *count).await;
 			}
 		}
 		_ => {
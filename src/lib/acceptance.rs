@@ -0,0 +1,100 @@
+use crate::hex_utils;
+use crate::node_var::ChannelManager;
+use crate::settings::{ChannelAcceptanceSettings, PrivateChannels};
+use bitcoin::secp256k1::PublicKey;
+use lightning::util::config::UserConfig;
+use std::io;
+
+/// Decides which inbound channels are accepted, as configured in the `[channel_acceptance]`
+/// section of the config file
+pub struct AcceptancePolicy {
+	min_channel_size_sat: Option<u64>,
+	max_channels_per_peer: Option<usize>,
+	allowlist: Vec<PublicKey>,
+	blocklist: Vec<PublicKey>,
+	private_channels: PrivateChannels,
+}
+
+impl AcceptancePolicy {
+	pub(crate) fn new(settings: &ChannelAcceptanceSettings) -> io::Result<Self> {
+		let parse_pubkeys = |pubkeys: &[String]| {
+			pubkeys
+				.iter()
+				.map(|pubkey| {
+					hex_utils::to_compressed_pubkey(pubkey).ok_or_else(|| {
+						io::Error::new(
+							io::ErrorKind::InvalidData,
+							format!("invalid peer public key {}", pubkey),
+						)
+					})
+				})
+				.collect::<io::Result<Vec<_>>>()
+		};
+		Ok(Self {
+			min_channel_size_sat: settings.min_channel_size_sat,
+			max_channels_per_peer: settings.max_channels_per_peer,
+			allowlist: parse_pubkeys(&settings.allowlist)?,
+			blocklist: parse_pubkeys(&settings.blocklist)?,
+			private_channels: settings.private_channels,
+		})
+	}
+
+	/// Have LDK hand inbound channels to `check` and enforce the private channel preference.
+	///
+	/// Whether an inbound channel is announced isn't part of `Event::OpenChannelRequest`, so
+	/// LDK rejects channels announced against our preference itself.
+	pub(crate) fn apply(&self, config: &mut UserConfig) {
+		config.manually_accept_inbound_channels = true;
+		match self.private_channels {
+			PrivateChannels::Accept => {
+				config.peer_channel_config_limits.force_announced_channel_preference = false;
+			}
+			PrivateChannels::Require => {
+				config.peer_channel_config_limits.force_announced_channel_preference = true;
+				config.channel_options.announced_channel = false;
+			}
+			PrivateChannels::Reject => {
+				config.peer_channel_config_limits.force_announced_channel_preference = true;
+				config.channel_options.announced_channel = true;
+			}
+		}
+	}
+
+	/// Check an inbound channel request, returning why the channel is rejected if it is
+	pub(crate) fn check(
+		&self, channel_manager: &ChannelManager, temporary_channel_id: &[u8; 32],
+		counterparty_node_id: &PublicKey, funding_satoshis: u64,
+	) -> Result<(), String> {
+		if self.blocklist.contains(counterparty_node_id) {
+			return Err(format!("peer {} is blocked", counterparty_node_id));
+		}
+		if !self.allowlist.is_empty() && !self.allowlist.contains(counterparty_node_id) {
+			return Err(format!("peer {} isn't allowed to open channels", counterparty_node_id));
+		}
+		if let Some(min_channel_size_sat) = self.min_channel_size_sat {
+			if funding_satoshis < min_channel_size_sat {
+				return Err(format!(
+					"channel of {} sats is below the minimum of {} sats",
+					funding_satoshis, min_channel_size_sat
+				));
+			}
+		}
+		if let Some(max_channels_per_peer) = self.max_channels_per_peer {
+			let peer_channels = channel_manager
+				.list_channels()
+				.iter()
+				.filter(|channel| {
+					channel.counterparty.node_id == *counterparty_node_id
+						&& channel.channel_id != *temporary_channel_id
+				})
+				.count();
+			if peer_channels >= max_channels_per_peer {
+				return Err(format!(
+					"peer {} already has {} channels",
+					counterparty_node_id, peer_channels
+				));
+			}
+		}
+		Ok(())
+	}
+}
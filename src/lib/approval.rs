@@ -0,0 +1,169 @@
+use crate::auth::Caller;
+use crate::hex_utils;
+use crate::node_var::unix_timestamp;
+use crate::server::{Keysend, ServerInvoice};
+use crate::settings::ApprovalSettings;
+use crate::storage::NodeStore;
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::sync::{Arc, Mutex};
+
+pub(crate) const PENDING_APPROVALS_NAMESPACE: &str = "pending_approvals";
+
+/// Request of a payment awaiting approval, sent as it was requested once approved
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PaymentRequest {
+	SendPayment(ServerInvoice),
+	Keysend(Keysend),
+}
+
+/// A payment above the approval threshold, which is only sent once an operator approves it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PendingPayment {
+	pub id: String,
+	pub amt_msat: u64,
+	pub request: PaymentRequest,
+	/// Caller of the request, whose spending limits the payment is counted against once approved
+	pub caller: Caller,
+	pub requested_at: u64,
+}
+
+/// The payments awaiting approval, which are persisted in the node store
+pub struct ApprovalQueue {
+	store: Arc<dyn NodeStore>,
+	threshold_msat: Option<u64>,
+	pending: Mutex<Vec<PendingPayment>>,
+}
+
+impl ApprovalQueue {
+	pub(crate) fn new(store: Arc<dyn NodeStore>, settings: &ApprovalSettings) -> io::Result<Self> {
+		let mut pending = Vec::new();
+		for (_, value) in store.list(PENDING_APPROVALS_NAMESPACE)? {
+			pending.push(
+				serde_json::from_slice::<PendingPayment>(&value)
+					.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+			);
+		}
+		pending.sort_by_key(|payment| payment.requested_at);
+		Ok(Self { store, threshold_msat: settings.threshold_msat, pending: Mutex::new(pending) })
+	}
+
+	/// Whether a payment of `amt_msat` has to be approved before it is sent
+	pub(crate) fn requires_approval(&self, amt_msat: u64) -> bool {
+		match self.threshold_msat {
+			Some(threshold_msat) => amt_msat > threshold_msat,
+			None => false,
+		}
+	}
+
+	/// Hold `request` until it is approved or rejected
+	pub(crate) fn queue(
+		&self, request: PaymentRequest, amt_msat: u64, caller: Caller,
+	) -> io::Result<PendingPayment> {
+		let mut id = [0; 8];
+		thread_rng().fill_bytes(&mut id);
+		let payment = PendingPayment {
+			id: hex_utils::hex_str(&id),
+			amt_msat,
+			request,
+			caller,
+			requested_at: unix_timestamp(),
+		};
+		self.restore(payment.clone())?;
+		Ok(payment)
+	}
+
+	/// Put a payment taken from the queue back under its ID, e.g. because it couldn't be sent
+	pub(crate) fn restore(&self, payment: PendingPayment) -> io::Result<()> {
+		let value = serde_json::to_vec(&payment)
+			.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+		self.store.write(PENDING_APPROVALS_NAMESPACE, &payment.id, &value)?;
+		let mut pending = self.pending.lock().unwrap();
+		let idx = pending.partition_point(|queued| queued.requested_at <= payment.requested_at);
+		pending.insert(idx, payment);
+		Ok(())
+	}
+
+	/// Remove the payment `id` from the queue, to send it or to drop it
+	pub(crate) fn take(&self, id: &str) -> io::Result<Option<PendingPayment>> {
+		let mut pending = self.pending.lock().unwrap();
+		let idx = match pending.iter().position(|payment| payment.id == id) {
+			Some(idx) => idx,
+			None => return Ok(None),
+		};
+		self.store.remove(PENDING_APPROVALS_NAMESPACE, id)?;
+		Ok(Some(pending.remove(idx)))
+	}
+
+	pub(crate) fn list(&self) -> Vec<PendingPayment> {
+		self.pending.lock().unwrap().clone()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::auth::{SpendingLimits, TokenScope};
+	use crate::storage::MemoryStore;
+
+	fn queue_with(store: Arc<dyn NodeStore>) -> ApprovalQueue {
+		ApprovalQueue::new(store, &ApprovalSettings { threshold_msat: Some(100_000) }).unwrap()
+	}
+
+	fn keysend(amt_msat: u64) -> PaymentRequest {
+		PaymentRequest::Keysend(Keysend { pubkey: "02".repeat(33), amt_msat: amt_msat.to_string() })
+	}
+
+	fn caller() -> Caller {
+		Caller { scope: TokenScope::Full, token_id: None, limits: SpendingLimits::default() }
+	}
+
+	#[test]
+	fn requires_approval_above_threshold() {
+		let queue = queue_with(Arc::new(MemoryStore::default()));
+		assert!(!queue.requires_approval(100_000));
+		assert!(queue.requires_approval(100_001));
+
+		let settings = ApprovalSettings { threshold_msat: None };
+		let queue = ApprovalQueue::new(Arc::new(MemoryStore::default()), &settings).unwrap();
+		assert!(!queue.requires_approval(u64::MAX));
+	}
+
+	#[test]
+	fn persists_queued_payments() {
+		let store: Arc<dyn NodeStore> = Arc::new(MemoryStore::default());
+		let queue = queue_with(Arc::clone(&store));
+		let pending = queue.queue(keysend(200_000), 200_000, caller()).unwrap();
+
+		let reloaded = queue_with(Arc::clone(&store));
+		assert_eq!(reloaded.list().len(), 1);
+		assert_eq!(reloaded.list()[0].id, pending.id);
+
+		assert!(reloaded.take(&pending.id).unwrap().is_some());
+		assert!(reloaded.take(&pending.id).unwrap().is_none());
+		assert!(queue_with(store).list().is_empty());
+	}
+
+	#[test]
+	fn restores_payments_failing_once_approved() {
+		let store: Arc<dyn NodeStore> = Arc::new(MemoryStore::default());
+		let queue = queue_with(Arc::clone(&store));
+		let first = queue.queue(keysend(200_000), 200_000, caller()).unwrap();
+		let second = queue.queue(keysend(300_000), 300_000, caller()).unwrap();
+
+		// Approving takes the payment from the queue, and puts it back if it can't be sent
+		let taken = queue.take(&first.id).unwrap().unwrap();
+		assert_eq!(queue.list().len(), 1);
+		queue.restore(taken).unwrap();
+
+		let ids: Vec<_> = queue.list().into_iter().map(|payment| payment.id).collect();
+		assert_eq!(ids.len(), 2);
+		assert!(ids.contains(&first.id) && ids.contains(&second.id));
+		assert_eq!(queue_with(Arc::clone(&store)).list().len(), 2);
+
+		// It can be approved again under the same ID
+		assert_eq!(queue.take(&first.id).unwrap().unwrap().amt_msat, 200_000);
+	}
+}
@@ -0,0 +1,456 @@
+use crate::hex_utils;
+use crate::node_var::unix_timestamp;
+use crate::storage::NodeStore;
+use actix_web::dev::ServiceRequest;
+use actix_web::http::header;
+use bitcoin::hashes::{sha256, Hash};
+use lightning::ln::PaymentHash;
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use utoipa::ToSchema;
+
+/// Name of the file in the node's data directory holding the API token
+pub const API_TOKEN_FILE: &str = "api_token";
+
+/// Environment variable the CLI reads the API token from
+pub const API_TOKEN_ENV: &str = "LNNODE_API_TOKEN";
+
+/// Environment variable the CLI reads the path of the API token file from
+pub const API_TOKEN_FILE_ENV: &str = "LNNODE_API_TOKEN_FILE";
+
+pub(crate) const API_TOKENS_NAMESPACE: &str = "api_tokens";
+pub(crate) const API_TOKEN_SPENDING_NAMESPACE: &str = "api_token_spending";
+
+/// What a token may do. The token of the `api_token` file has full access.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenScope {
+	/// Every endpoint, including those moving funds
+	Full,
+	/// Every endpoint reading the node's state, without changing it
+	ReadOnly,
+	/// Creating, canceling and reading invoices, e.g. for a web shop
+	Invoice,
+}
+
+/// What an endpoint does, deciding which token scopes may call it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Access {
+	InvoiceRead,
+	InvoiceWrite,
+	Read,
+	/// Moving funds or changing the node's configuration
+	Write,
+}
+
+impl TokenScope {
+	pub(crate) fn allows(self, access: Access) -> bool {
+		match self {
+			TokenScope::Full => true,
+			TokenScope::ReadOnly => access == Access::Read || access == Access::InvoiceRead,
+			TokenScope::Invoice => access == Access::InvoiceRead || access == Access::InvoiceWrite,
+		}
+	}
+}
+
+/// Limits on the amounts of the payments sent with an issued token, excluding fees, so that a
+/// compromised integration can't drain the node
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct SpendingLimits {
+	/// Largest amount of a single payment
+	pub max_payment_msat: Option<u64>,
+	/// Largest amount of the payments sent over the last 24 hours
+	pub daily_limit_msat: Option<u64>,
+}
+
+impl SpendingLimits {
+	pub fn is_limited(&self) -> bool {
+		self.max_payment_msat.is_some() || self.daily_limit_msat.is_some()
+	}
+}
+
+/// A token issued through the API. Only the hash of the token is stored, so it is shown once,
+/// when it is issued.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct IssuedToken {
+	pub id: String,
+	pub scope: TokenScope,
+	pub label: Option<String>,
+	pub created_at: u64,
+	#[serde(default)]
+	pub limits: SpendingLimits,
+	token_hash: String,
+}
+
+/// The token a request was authorized with
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Caller {
+	pub scope: TokenScope,
+	/// ID of the issued token, unset for the token of the `api_token` file
+	pub token_id: Option<String>,
+	pub limits: SpendingLimits,
+}
+
+/// A payment counted against the daily limit of a token
+#[derive(Clone, Serialize, Deserialize, Debug)]
+struct Spend {
+	timestamp: u64,
+	amt_msat: u64,
+	/// Hash of the payment, unset for spends persisted before they were keyed by payment
+	#[serde(default)]
+	payment_hash: Option<String>,
+}
+
+/// Why a payment couldn't be counted against the limits of a token
+#[derive(Debug)]
+pub(crate) enum ChargeError {
+	LimitExceeded(String),
+	Persistence(io::Error),
+}
+
+/// Length of the window of the daily limits
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+/// The tokens accepted by the APIs: the token of the `api_token` file, and those issued with a
+/// scope through the API, which are persisted in the node store along with what they spent
+pub struct TokenRegistry {
+	store: Arc<dyn NodeStore>,
+	admin_token: String,
+	issued: Mutex<Vec<IssuedToken>>,
+	spending: Mutex<HashMap<String, Vec<Spend>>>,
+}
+
+impl TokenRegistry {
+	pub(crate) fn new(store: Arc<dyn NodeStore>, admin_token: String) -> io::Result<Self> {
+		let mut issued = Vec::new();
+		for (_, value) in store.list(API_TOKENS_NAMESPACE)? {
+			issued.push(
+				serde_json::from_slice(&value)
+					.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+			);
+		}
+		let mut spending = HashMap::new();
+		for (token_id, value) in store.list(API_TOKEN_SPENDING_NAMESPACE)? {
+			let spends = serde_json::from_slice(&value)
+				.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+			spending.insert(token_id, spends);
+		}
+		Ok(Self { store, admin_token, issued: Mutex::new(issued), spending: Mutex::new(spending) })
+	}
+
+	/// The caller holding `token`, or `None` if it isn't a valid token
+	pub(crate) fn authenticate(&self, token: &str) -> Option<Caller> {
+		if constant_time_eq(token.as_bytes(), self.admin_token.as_bytes()) {
+			return Some(Caller {
+				scope: TokenScope::Full,
+				token_id: None,
+				limits: SpendingLimits::default(),
+			});
+		}
+		let token_hash = hash_token(token);
+		let issued = self.issued.lock().unwrap();
+		issued
+			.iter()
+			.find(|issued| constant_time_eq(issued.token_hash.as_bytes(), token_hash.as_bytes()))
+			.map(|issued| Caller {
+				scope: issued.scope,
+				token_id: Some(issued.id.clone()),
+				limits: issued.limits,
+			})
+	}
+
+	/// Issue a new token of `scope`, returning it along with the token itself
+	pub(crate) fn issue(
+		&self, scope: TokenScope, label: Option<String>, limits: SpendingLimits,
+	) -> io::Result<(IssuedToken, String)> {
+		let mut id = [0; 8];
+		thread_rng().fill_bytes(&mut id);
+		let mut token = [0; 32];
+		thread_rng().fill_bytes(&mut token);
+		let token = hex_utils::hex_str(&token);
+		let issued = IssuedToken {
+			id: hex_utils::hex_str(&id),
+			scope,
+			label,
+			created_at: unix_timestamp(),
+			limits,
+			token_hash: hash_token(&token),
+		};
+		let value = serde_json::to_vec(&issued)
+			.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+		self.store.write(API_TOKENS_NAMESPACE, &issued.id, &value)?;
+		self.issued.lock().unwrap().push(issued.clone());
+		Ok((issued, token))
+	}
+
+	/// Revoke an issued token, returning whether it existed
+	pub(crate) fn revoke(&self, id: &str) -> io::Result<bool> {
+		let mut issued = self.issued.lock().unwrap();
+		let idx = match issued.iter().position(|issued| issued.id == id) {
+			Some(idx) => idx,
+			None => return Ok(false),
+		};
+		self.store.remove(API_TOKENS_NAMESPACE, id)?;
+		issued.remove(idx);
+		if self.spending.lock().unwrap().remove(id).is_some() {
+			self.store.remove(API_TOKEN_SPENDING_NAMESPACE, id)?;
+		}
+		Ok(true)
+	}
+
+	pub(crate) fn list(&self) -> Vec<IssuedToken> {
+		self.issued.lock().unwrap().clone()
+	}
+
+	/// Count the payment of `amt_msat` with hash `payment_hash` against the limits of `caller`,
+	/// failing if it exceeds them
+	pub(crate) fn charge(
+		&self, caller: &Caller, payment_hash: &PaymentHash, amt_msat: u64,
+	) -> Result<(), ChargeError> {
+		let token_id = match &caller.token_id {
+			Some(token_id) if caller.limits.is_limited() => token_id,
+			_ => return Ok(()),
+		};
+		if let Some(max_payment_msat) = caller.limits.max_payment_msat {
+			if amt_msat > max_payment_msat {
+				return Err(ChargeError::LimitExceeded(format!(
+					"payments with this API token are limited to {} msats",
+					max_payment_msat
+				)));
+			}
+		}
+		let mut spending = self.spending.lock().unwrap();
+		let spends = spending.entry(token_id.clone()).or_insert_with(Vec::new);
+		let now = unix_timestamp();
+		spends.retain(|spend| spend.timestamp + DAY_SECS > now);
+		if let Some(daily_limit_msat) = caller.limits.daily_limit_msat {
+			let spent_msat: u64 = spends.iter().map(|spend| spend.amt_msat).sum();
+			if spent_msat + amt_msat > daily_limit_msat {
+				return Err(ChargeError::LimitExceeded(format!(
+					"this API token sent {} of its {} msats over the last 24 hours",
+					spent_msat, daily_limit_msat
+				)));
+			}
+		}
+		let payment_hash = Some(hex_utils::hex_str(&payment_hash.0));
+		spends.push(Spend { timestamp: now, amt_msat, payment_hash });
+		self.persist_spending(token_id, spends).map_err(ChargeError::Persistence)
+	}
+
+	/// Undo the charge of the payment with hash `payment_hash`, which wasn't sent
+	pub(crate) fn refund(&self, caller: &Caller, payment_hash: &PaymentHash) -> io::Result<()> {
+		let token_id = match &caller.token_id {
+			Some(token_id) if caller.limits.is_limited() => token_id,
+			_ => return Ok(()),
+		};
+		let mut spending = self.spending.lock().unwrap();
+		let payment_hash = hex_utils::hex_str(&payment_hash.0);
+		if let Some(spends) = spending.get_mut(token_id) {
+			let charged = spends
+				.iter()
+				.rposition(|spend| spend.payment_hash.as_deref() == Some(payment_hash.as_str()));
+			if let Some(idx) = charged {
+				spends.remove(idx);
+				self.persist_spending(token_id, spends)?;
+			}
+		}
+		Ok(())
+	}
+
+	fn persist_spending(&self, token_id: &str, spends: &[Spend]) -> io::Result<()> {
+		let value = serde_json::to_vec(spends)
+			.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+		self.store.write(API_TOKEN_SPENDING_NAMESPACE, token_id, &value)
+	}
+}
+
+fn hash_token(token: &str) -> String {
+	hex_utils::hex_str(&sha256::Hash::hash(token.as_bytes()).into_inner())
+}
+
+/// Read the API token from `<ldk_data_dir>/api_token`, generating it on first startup.
+///
+/// The token grants full control over the node's funds, so the file is only readable by the
+/// user running the node.
+pub(crate) fn load_or_create_api_token(ldk_data_dir: &str) -> io::Result<String> {
+	let token_path = format!("{}/{}", ldk_data_dir, API_TOKEN_FILE);
+	if let Ok(token) = read_api_token_file(&token_path) {
+		return Ok(token);
+	}
+
+	let mut token_bytes = [0; 32];
+	thread_rng().fill_bytes(&mut token_bytes);
+	let token = hex_utils::hex_str(&token_bytes);
+
+	let mut options = fs::OpenOptions::new();
+	options.write(true).create(true).truncate(true);
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::OpenOptionsExt;
+		options.mode(0o600);
+	}
+	let mut file = options.open(&token_path)?;
+	file.write_all(token.as_bytes())?;
+	file.sync_all()?;
+	Ok(token)
+}
+
+/// Read an API token from `path`, ignoring surrounding whitespace
+pub fn read_api_token_file(path: &str) -> io::Result<String> {
+	let token = fs::read_to_string(path)?.trim().to_string();
+	if token.is_empty() {
+		return Err(io::Error::new(io::ErrorKind::InvalidData, "API token file is empty"));
+	}
+	Ok(token)
+}
+
+/// Caller of a request carrying a valid token as `Authorization: Bearer <token>`
+pub(crate) fn request_caller(req: &ServiceRequest, tokens: &TokenRegistry) -> Option<Caller> {
+	let authorization =
+		req.headers().get(header::AUTHORIZATION).and_then(|value| value.to_str().ok());
+	authorization_caller(authorization, tokens)
+}
+
+/// Caller holding the token of an `Authorization` header, or of the `authorization` metadata of
+/// a gRPC call, of the form `Bearer <token>`
+pub(crate) fn authorization_caller(
+	authorization: Option<&str>, tokens: &TokenRegistry,
+) -> Option<Caller> {
+	authorization
+		.and_then(|value| value.strip_prefix("Bearer "))
+		.and_then(|token| tokens.authenticate(token.trim()))
+}
+
+/// Compare without short-circuiting so response times don't leak how much of a guess matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+	if a.len() != b.len() {
+		return false;
+	}
+	a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::storage::MemoryStore;
+
+	fn registry(store: &Arc<MemoryStore>) -> TokenRegistry {
+		TokenRegistry::new(Arc::clone(store) as Arc<dyn NodeStore>, "admin".to_string()).unwrap()
+	}
+
+	#[test]
+	fn scopes_allow_their_endpoints() {
+		let accesses = [Access::InvoiceRead, Access::InvoiceWrite, Access::Read, Access::Write];
+		let allowed = |scope: TokenScope| {
+			accesses.iter().copied().filter(|access| scope.allows(*access)).collect::<Vec<_>>()
+		};
+		assert_eq!(allowed(TokenScope::Full), accesses);
+		assert_eq!(allowed(TokenScope::ReadOnly), [Access::InvoiceRead, Access::Read]);
+		assert_eq!(allowed(TokenScope::Invoice), [Access::InvoiceRead, Access::InvoiceWrite]);
+	}
+
+	#[test]
+	fn authenticates_issued_tokens_until_revoked() {
+		let store = Arc::new(MemoryStore::default());
+		let tokens = registry(&store);
+		let caller = tokens.authenticate("admin").unwrap();
+		assert_eq!(caller.scope, TokenScope::Full);
+		assert!(caller.token_id.is_none());
+		assert!(tokens.authenticate("guess").is_none());
+
+		let (issued, token) = tokens
+			.issue(TokenScope::Invoice, Some("shop".to_string()), SpendingLimits::default())
+			.unwrap();
+		let caller = tokens.authenticate(&token).unwrap();
+		assert_eq!(caller.scope, TokenScope::Invoice);
+		assert_eq!(caller.token_id.as_deref(), Some(issued.id.as_str()));
+		// Issued tokens are persisted, by their hash only
+		assert!(registry(&store).authenticate(&token).is_some());
+		assert!(store
+			.list(API_TOKENS_NAMESPACE)
+			.unwrap()
+			.iter()
+			.all(|(_, value)| { !String::from_utf8_lossy(value).contains(&token) }));
+
+		assert!(tokens.revoke(&issued.id).unwrap());
+		assert!(!tokens.revoke(&issued.id).unwrap());
+		assert!(tokens.authenticate(&token).is_none());
+		assert!(registry(&store).authenticate(&token).is_none());
+	}
+
+	fn limited_caller(tokens: &TokenRegistry, limits: SpendingLimits) -> Caller {
+		let (_, token) = tokens.issue(TokenScope::Full, None, limits).unwrap();
+		tokens.authenticate(&token).unwrap()
+	}
+
+	#[test]
+	fn enforces_spending_limits() {
+		let store = Arc::new(MemoryStore::default());
+		let tokens = registry(&store);
+		let limits =
+			SpendingLimits { max_payment_msat: Some(5_000), daily_limit_msat: Some(8_000) };
+		let caller = limited_caller(&tokens, limits);
+		assert!(matches!(
+			tokens.charge(&caller, &PaymentHash([1; 32]), 6_000),
+			Err(ChargeError::LimitExceeded(_))
+		));
+		tokens.charge(&caller, &PaymentHash([2; 32]), 5_000).unwrap();
+		assert!(matches!(
+			tokens.charge(&caller, &PaymentHash([3; 32]), 4_000),
+			Err(ChargeError::LimitExceeded(_))
+		));
+		tokens.charge(&caller, &PaymentHash([4; 32]), 3_000).unwrap();
+
+		// What was spent is persisted, so restarting doesn't reset the daily limit
+		let tokens = registry(&store);
+		assert!(matches!(
+			tokens.charge(&caller, &PaymentHash([5; 32]), 1),
+			Err(ChargeError::LimitExceeded(_))
+		));
+		// Unlimited tokens aren't tracked
+		let unlimited = limited_caller(&tokens, SpendingLimits::default());
+		tokens.charge(&unlimited, &PaymentHash([6; 32]), 1_000_000).unwrap();
+		assert!(tokens
+			.spending
+			.lock()
+			.unwrap()
+			.get(unlimited.token_id.as_ref().unwrap())
+			.is_none());
+	}
+
+	#[test]
+	fn refunds_the_charge_of_the_failed_payment() {
+		let store = Arc::new(MemoryStore::default());
+		let tokens = registry(&store);
+		let limits = SpendingLimits { max_payment_msat: None, daily_limit_msat: Some(10_000) };
+		let caller = limited_caller(&tokens, limits);
+		tokens.charge(&caller, &PaymentHash([1; 32]), 6_000).unwrap();
+		tokens.charge(&caller, &PaymentHash([2; 32]), 3_000).unwrap();
+
+		// Refunding the first payment frees its 6000 msats, not the 3000 of the latest one
+		tokens.refund(&caller, &PaymentHash([1; 32])).unwrap();
+		tokens.charge(&caller, &PaymentHash([3; 32]), 7_000).unwrap();
+		// Refunding a payment which wasn't charged changes nothing
+		tokens.refund(&caller, &PaymentHash([4; 32])).unwrap();
+		assert!(matches!(
+			tokens.charge(&caller, &PaymentHash([5; 32]), 1),
+			Err(ChargeError::LimitExceeded(_))
+		));
+		assert!(registry(&store).charge(&caller, &PaymentHash([5; 32]), 1).is_err());
+	}
+
+	#[test]
+	fn reads_bearer_authorization() {
+		let store = Arc::new(MemoryStore::default());
+		let tokens = registry(&store);
+		assert!(authorization_caller(Some("Bearer admin"), &tokens).is_some());
+		assert!(authorization_caller(Some("admin"), &tokens).is_none());
+		assert!(authorization_caller(Some("Bearer wrong"), &tokens).is_none());
+		assert!(authorization_caller(None, &tokens).is_none());
+	}
+}
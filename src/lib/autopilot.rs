@@ -0,0 +1,397 @@
+use crate::cli;
+use crate::disk;
+use crate::hex_utils;
+use crate::node_var::{unix_timestamp, ChannelLimits, ChannelManager, ChannelPolicy, PeerManager};
+use crate::peers::{self, PeerTracker};
+use crate::settings::AutopilotSettings;
+use crate::storage::NodeStore;
+use crate::wallet::Wallet;
+use bitcoin::secp256k1::PublicKey;
+use lightning::ln::msgs::NetAddress;
+use lightning::routing::network_graph::{NetworkGraph, NodeId};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+pub(crate) const AUTOPILOT_NAMESPACE: &str = "autopilot";
+pub(crate) const AUTOPILOT_CHANNELS_NAMESPACE: &str = "autopilot_channels";
+const ENABLED_KEY: &str = "enabled";
+
+/// Nodes which haven't announced themselves or updated their channels for this long are assumed
+/// to be offline
+const MAX_UPDATE_AGE_SECS: u64 = 14 * 24 * 60 * 60;
+/// Time before a node the autopilot failed to open a channel to is considered again
+const FAILED_CANDIDATE_RETRY_SECS: u64 = 24 * 60 * 60;
+/// Candidates tried on each run before giving up until the next one
+const CANDIDATES_PER_RUN: usize = 3;
+/// Candidates reported by the status
+const STATUS_CANDIDATES: usize = 5;
+
+/// A channel the autopilot opened, counted against its budget while the channel is open
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct AutopilotChannel {
+	#[schema(value_type = String)]
+	pub pubkey: PublicKey,
+	pub amount_sat: u64,
+	pub opened_at: u64,
+}
+
+/// A node the autopilot may open a channel to, scored from the network graph
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct AutopilotCandidate {
+	#[schema(value_type = String)]
+	pub pubkey: PublicKey,
+	pub alias: String,
+	pub capacity_sat: u64,
+	pub channels: usize,
+	/// Share of the node's channels it keeps enabled and up to date
+	pub uptime: f64,
+	/// Between 0 and 1, higher is better
+	pub score: f64,
+}
+
+/// State of the autopilot, as reported by `/autopilot/status`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AutopilotStatus {
+	pub enabled: bool,
+	pub budget_sat: u64,
+	/// Size of the open channels the autopilot opened
+	pub budget_used_sat: u64,
+	pub channel_size_sat: u64,
+	pub max_channels: usize,
+	pub channels: Vec<AutopilotChannel>,
+	pub last_run: Option<u64>,
+	pub last_error: Option<String>,
+	/// Best-scored nodes the next channels would be opened to
+	pub candidates: Vec<AutopilotCandidate>,
+}
+
+#[derive(Default)]
+struct RunState {
+	last_run: Option<u64>,
+	last_error: Option<String>,
+}
+
+/// Background task opening channels to well-connected nodes while on-chain funds and its budget
+/// allow. Nodes of the network graph are scored by the capacity and number of their channels,
+/// on a logarithmic scale, and by the share of their channels they keep enabled. The autopilot
+/// opens one channel per run, to the best-scored node it can connect to, and waits for its
+/// funding transaction before opening the next one.
+pub struct Autopilot {
+	settings: AutopilotSettings,
+	channel_policy: Arc<Mutex<ChannelPolicy>>,
+	channel_limits: ChannelLimits,
+	channel_manager: Arc<ChannelManager>,
+	peer_manager: Arc<PeerManager>,
+	network_graph: Arc<NetworkGraph>,
+	store: Arc<dyn NodeStore>,
+	wallet: Arc<Wallet>,
+	peer_tracker: Arc<PeerTracker>,
+	socks_proxy: Option<SocketAddr>,
+	enabled: AtomicBool,
+	channels: Mutex<HashMap<PublicKey, AutopilotChannel>>,
+	/// Time the autopilot last failed to open a channel to a node
+	failed: Mutex<HashMap<PublicKey, u64>>,
+	state: Mutex<RunState>,
+}
+
+impl Autopilot {
+	pub(crate) fn new(
+		settings: &AutopilotSettings, channel_policy: Arc<Mutex<ChannelPolicy>>,
+		channel_limits: ChannelLimits, channel_manager: Arc<ChannelManager>,
+		peer_manager: Arc<PeerManager>, network_graph: Arc<NetworkGraph>,
+		store: Arc<dyn NodeStore>, wallet: Arc<Wallet>, peer_tracker: Arc<PeerTracker>,
+		socks_proxy: Option<SocketAddr>,
+	) -> io::Result<Self> {
+		let invalid_data = |e: serde_json::Error| io::Error::new(io::ErrorKind::InvalidData, e);
+		// Enabling or disabling the autopilot through the API takes precedence over the settings
+		let enabled = match store.read(AUTOPILOT_NAMESPACE, ENABLED_KEY)? {
+			Some(value) => serde_json::from_slice(&value).map_err(invalid_data)?,
+			None => settings.enabled,
+		};
+		let mut channels = HashMap::new();
+		for (_, value) in store.list(AUTOPILOT_CHANNELS_NAMESPACE)? {
+			let channel: AutopilotChannel = serde_json::from_slice(&value).map_err(invalid_data)?;
+			channels.insert(channel.pubkey, channel);
+		}
+		Ok(Self {
+			settings: settings.clone(),
+			channel_policy,
+			channel_limits,
+			channel_manager,
+			peer_manager,
+			network_graph,
+			store,
+			wallet,
+			peer_tracker,
+			socks_proxy,
+			enabled: AtomicBool::new(enabled),
+			channels: Mutex::new(channels),
+			failed: Mutex::new(HashMap::new()),
+			state: Mutex::new(RunState::default()),
+		})
+	}
+
+	pub(crate) fn is_enabled(&self) -> bool {
+		self.enabled.load(Ordering::Acquire)
+	}
+
+	/// Enable or disable the autopilot, until it is enabled or disabled again
+	pub(crate) fn set_enabled(&self, enabled: bool) -> io::Result<()> {
+		let value = serde_json::to_vec(&enabled)
+			.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+		self.store.write(AUTOPILOT_NAMESPACE, ENABLED_KEY, &value)?;
+		self.enabled.store(enabled, Ordering::Release);
+		Ok(())
+	}
+
+	pub(crate) fn status(&self) -> AutopilotStatus {
+		let channels = self.open_channels();
+		let state = self.state.lock().unwrap();
+		AutopilotStatus {
+			enabled: self.is_enabled(),
+			budget_sat: self.settings.budget_sat,
+			budget_used_sat: channels.iter().map(|channel| channel.amount_sat).sum(),
+			channel_size_sat: self.settings.channel_size_sat,
+			max_channels: self.settings.max_channels,
+			channels,
+			last_run: state.last_run,
+			last_error: state.last_error.clone(),
+			candidates: self.candidates().into_iter().take(STATUS_CANDIDATES).collect(),
+		}
+	}
+
+	pub(crate) async fn run(self: Arc<Self>) {
+		let mut interval =
+			tokio::time::interval(Duration::from_secs(self.settings.interval_secs.max(1)));
+		loop {
+			interval.tick().await;
+			if !self.is_enabled() {
+				continue;
+			}
+			let result = self.open_next_channel().await;
+			let mut state = self.state.lock().unwrap();
+			state.last_run = Some(unix_timestamp());
+			state.last_error = match result {
+				Ok(_) => None,
+				Err(e) => {
+					warn!("autopilot failed to open a channel: {}", e);
+					Some(e)
+				}
+			};
+		}
+	}
+
+	/// The channels the autopilot opened which are still open, forgetting the others
+	fn open_channels(&self) -> Vec<AutopilotChannel> {
+		let peers: HashSet<PublicKey> = self
+			.channel_manager
+			.list_channels()
+			.iter()
+			.map(|chan| chan.counterparty.node_id)
+			.collect();
+		let mut channels = self.channels.lock().unwrap();
+		channels.retain(|pubkey, _| {
+			if peers.contains(pubkey) {
+				return true;
+			}
+			let key = hex_utils::hex_str(&pubkey.serialize());
+			if let Err(e) = self.store.remove(AUTOPILOT_CHANNELS_NAMESPACE, &key) {
+				warn!("failed to remove the autopilot channel with {}: {}", pubkey, e);
+			}
+			false
+		});
+		let mut channels: Vec<AutopilotChannel> = channels.values().cloned().collect();
+		channels.sort_by_key(|channel| channel.opened_at);
+		channels
+	}
+
+	/// Open a channel to the best candidate the autopilot can connect to, if funds and the budget
+	/// allow, returning the node it was opened to
+	async fn open_next_channel(&self) -> Result<Option<PublicKey>, String> {
+		let channels = self.channel_manager.list_channels();
+		// The on-chain balance only drops once the funding transaction of a channel is built
+		if channels.iter().any(|chan| chan.is_outbound && chan.funding_txo.is_none()) {
+			return Ok(None);
+		}
+		let opened = self.open_channels();
+		if opened.len() >= self.settings.max_channels {
+			return Ok(None);
+		}
+		let budget_used_sat: u64 = opened.iter().map(|channel| channel.amount_sat).sum();
+		let balance = self
+			.wallet
+			.get_balance()
+			.await
+			.map_err(|e| format!("unable to read the on-chain balance: {}", e))?;
+		let amount_sat = self
+			.settings
+			.channel_size_sat
+			.min(self.settings.budget_sat.saturating_sub(budget_used_sat))
+			.min(balance.confirmed_sat.saturating_sub(self.settings.onchain_reserve_sat));
+		if amount_sat < self.settings.min_channel_size_sat {
+			return Ok(None);
+		}
+		let policy = self.channel_policy.lock().unwrap().clone();
+
+		let candidates = self.candidates();
+		if candidates.is_empty() {
+			return Err("no node of the network graph qualifies".to_string());
+		}
+		for candidate in candidates.into_iter().take(CANDIDATES_PER_RUN) {
+			let pubkey = candidate.pubkey;
+			let mut address = None;
+			for peer_addr in peers::announced_addresses(&self.network_graph, &pubkey) {
+				if peer_addr.is_onion() && self.socks_proxy.is_none() {
+					continue;
+				}
+				if cli::connect_peer_if_necessary(
+					pubkey,
+					peer_addr.clone(),
+					Arc::clone(&self.peer_manager),
+					self.socks_proxy,
+					&self.peer_tracker,
+				)
+				.await
+				.is_ok()
+				{
+					address = Some(peer_addr);
+					break;
+				}
+			}
+			let address = match address {
+				Some(address) => address,
+				None => {
+					self.failed.lock().unwrap().insert(pubkey, unix_timestamp());
+					continue;
+				}
+			};
+			if cli::open_channel(
+				pubkey,
+				amount_sat,
+				0,
+				self.settings.public,
+				&policy,
+				&self.channel_limits,
+				Arc::clone(&self.channel_manager),
+			)
+			.is_err()
+			{
+				self.failed.lock().unwrap().insert(pubkey, unix_timestamp());
+				continue;
+			}
+			let _ = disk::persist_channel_peer(&*self.store, &pubkey, &address);
+
+			let channel = AutopilotChannel { pubkey, amount_sat, opened_at: unix_timestamp() };
+			let value = serde_json::to_vec(&channel).map_err(|e| e.to_string())?;
+			let key = hex_utils::hex_str(&pubkey.serialize());
+			if let Err(e) = self.store.write(AUTOPILOT_CHANNELS_NAMESPACE, &key, &value) {
+				warn!("failed to persist the autopilot channel with {}: {}", pubkey, e);
+			}
+			self.channels.lock().unwrap().insert(pubkey, channel);
+			info!(
+				"autopilot opened a channel of {} sats with {} ({})",
+				amount_sat, pubkey, candidate.alias
+			);
+			return Ok(Some(pubkey));
+		}
+		Err("couldn't open a channel to any of the best candidates".to_string())
+	}
+
+	/// Nodes the autopilot may open a channel to, best first. Nodes the node has a channel with,
+	/// has recently failed to open one to, or which can't be reached are left out.
+	fn candidates(&self) -> Vec<AutopilotCandidate> {
+		let now = unix_timestamp();
+		let our_node_id = NodeId::from_pubkey(&self.channel_manager.get_our_node_id());
+		let channel_peers: HashSet<NodeId> = self
+			.channel_manager
+			.list_channels()
+			.iter()
+			.map(|chan| NodeId::from_pubkey(&chan.counterparty.node_id))
+			.collect();
+		let failed: HashSet<PublicKey> = self
+			.failed
+			.lock()
+			.unwrap()
+			.iter()
+			.filter(|(_, failed_at)| *failed_at + FAILED_CANDIDATE_RETRY_SECS > now)
+			.map(|(pubkey, _)| *pubkey)
+			.collect();
+		let min_update = now.saturating_sub(MAX_UPDATE_AGE_SECS);
+
+		let graph = self.network_graph.read_only();
+		let mut candidates = Vec::new();
+		for (node_id, node) in graph.nodes().iter() {
+			if *node_id == our_node_id || channel_peers.contains(node_id) {
+				continue;
+			}
+			let announcement = match &node.announcement_info {
+				Some(announcement) if announcement.last_update as u64 >= min_update => announcement,
+				_ => continue,
+			};
+			let reachable = announcement.addresses.iter().any(|address| match address {
+				NetAddress::IPv4 { .. } | NetAddress::IPv6 { .. } => true,
+				NetAddress::OnionV3 { .. } => self.socks_proxy.is_some(),
+				_ => false,
+			});
+			let pubkey = match PublicKey::from_slice(node_id.as_slice()) {
+				Ok(pubkey) if reachable && !failed.contains(&pubkey) => pubkey,
+				_ => continue,
+			};
+
+			let mut capacity_sat = 0;
+			let mut channels = 0;
+			let mut enabled_channels = 0;
+			for scid in node.channels.iter() {
+				let info = match graph.channels().get(scid) {
+					Some(info) => info,
+					None => continue,
+				};
+				channels += 1;
+				capacity_sat += info.capacity_sats.unwrap_or(0);
+				let update =
+					if info.node_one == *node_id { &info.one_to_two } else { &info.two_to_one };
+				if let Some(update) = update {
+					if update.enabled && update.last_update as u64 >= min_update {
+						enabled_channels += 1;
+					}
+				}
+			}
+			if channels == 0 {
+				continue;
+			}
+			candidates.push(AutopilotCandidate {
+				pubkey,
+				alias: cli::sanitize_string(&announcement.alias),
+				capacity_sat,
+				channels,
+				uptime: enabled_channels as f64 / channels as f64,
+				score: 0.0,
+			});
+		}
+
+		// A few nodes have most of the capacity and channels, which would otherwise drown out
+		// every other difference
+		let log_scale = |value: f64, max: f64| (1.0 + value).ln() / (1.0 + max).ln().max(1.0);
+		let max_capacity_sat =
+			candidates.iter().map(|candidate| candidate.capacity_sat).max().unwrap_or(0) as f64;
+		let max_channels =
+			candidates.iter().map(|candidate| candidate.channels).max().unwrap_or(0) as f64;
+		for candidate in candidates.iter_mut() {
+			candidate.score = (log_scale(candidate.capacity_sat as f64, max_capacity_sat)
+				+ log_scale(candidate.channels as f64, max_channels)
+				+ candidate.uptime)
+				/ 3.0;
+		}
+		candidates.sort_by(|a, b| {
+			b.score.partial_cmp(&a.score).unwrap().then_with(|| a.pubkey.cmp(&b.pubkey))
+		});
+		candidates
+	}
+}
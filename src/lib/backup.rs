@@ -0,0 +1,357 @@
+use crate::node_var::ChainMonitor;
+use crate::storage::NodeStore;
+use crate::{disk, hex_utils};
+use bitcoin::hashes::{hmac, sha256, Hash, HashEngine};
+use chacha20poly1305::aead::{Aead, NewAead};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use chrono::Utc;
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Number of snapshots kept in the backup directory before the oldest ones are pruned.
+pub(crate) const BACKUP_RETENTION: usize = 10;
+
+/// Snapshot the critical node state (the serialized ChannelManager and every ChannelMonitor)
+/// into `<ldk_data_dir>/backups/<snapshot_id>` and record the snapshot in the audit log.
+///
+/// Snapshots are a last-resort rollback for operator mistakes. Restoring one after the channel
+/// state has advanced means broadcasting revoked state, so they must never be restored blindly.
+pub(crate) fn snapshot(ldk_data_dir: &str, reason: &str) -> io::Result<String> {
+	let snapshot_id = format!("{}-{}", Utc::now().format("%Y%m%dT%H%M%S%.3f"), reason);
+	let backups_dir = format!("{}/backups", ldk_data_dir);
+	let snapshot_dir = format!("{}/{}", backups_dir, snapshot_id);
+	fs::create_dir_all(format!("{}/monitors", snapshot_dir))?;
+
+	let manager_path = format!("{}/manager", ldk_data_dir);
+	if Path::new(&manager_path).exists() {
+		fs::copy(&manager_path, format!("{}/manager", snapshot_dir))?;
+	}
+
+	let monitors_path = format!("{}/monitors", ldk_data_dir);
+	if Path::new(&monitors_path).exists() {
+		for entry in fs::read_dir(&monitors_path)? {
+			let entry = entry?;
+			if !entry.file_type()?.is_file() {
+				continue;
+			}
+			fs::copy(
+				entry.path(),
+				Path::new(&snapshot_dir).join("monitors").join(entry.file_name()),
+			)?;
+		}
+	}
+
+	prune_snapshots(&backups_dir)?;
+	let _ = disk::persist_audit_event(
+		ldk_data_dir,
+		&format!("created backup snapshot {} before {}", snapshot_id, reason),
+	);
+	Ok(snapshot_id)
+}
+
+/// Remove the oldest snapshots so that at most `BACKUP_RETENTION` remain. Snapshot IDs start
+/// with a timestamp, so lexicographic order is chronological order.
+fn prune_snapshots(backups_dir: &str) -> io::Result<()> {
+	let mut snapshots = Vec::new();
+	for entry in fs::read_dir(backups_dir)? {
+		let entry = entry?;
+		if entry.file_type()?.is_dir() {
+			snapshots.push(entry.path());
+		}
+	}
+	snapshots.sort();
+	while snapshots.len() > BACKUP_RETENTION {
+		fs::remove_dir_all(snapshots.remove(0))?;
+	}
+	Ok(())
+}
+
+/// Version byte prefixing channel backups, followed by the nonce and the encrypted bundle
+const CHANNEL_BACKUP_VERSION: u8 = 1;
+
+/// Length of the ChaCha20-Poly1305 nonce of channel backups
+const CHANNEL_BACKUP_NONCE_LEN: usize = 12;
+
+/// Namespace of the monitors restored from a channel backup which still have funds to claim
+pub(crate) const RECOVERED_CHANNELS_NAMESPACE: &str = "recovered_channels";
+
+/// The ChannelMonitors and channel peers needed to recover the funds of the node's channels
+#[derive(Serialize, Deserialize)]
+struct ChannelBackup {
+	created_at: i64,
+	/// Hex serialized ChannelMonitors, by monitor file name
+	monitors: Vec<(String, String)>,
+	/// Socket address of each channel peer, by hex public key
+	peers: Vec<(String, String)>,
+}
+
+/// Derive the key channel backups are encrypted with from the node's key seed, so that only
+/// the node can read its backups
+pub(crate) fn channel_backup_key(keys_seed: &[u8; 32]) -> [u8; 32] {
+	let mut engine = hmac::HmacEngine::<sha256::Hash>::new(keys_seed);
+	engine.input(b"lnnode channel backup");
+	hmac::Hmac::<sha256::Hash>::from_engine(engine).into_inner()
+}
+
+/// Export every ChannelMonitor and channel peer of the node into an encrypted bundle, returning
+/// the bundle and the number of channels it covers
+pub(crate) fn export_channel_backup(
+	ldk_data_dir: &str, store: &dyn NodeStore, key: &[u8; 32],
+) -> io::Result<(Vec<u8>, usize)> {
+	let mut monitors = Vec::new();
+	let monitors_path = format!("{}/monitors", ldk_data_dir);
+	if Path::new(&monitors_path).exists() {
+		for entry in fs::read_dir(&monitors_path)? {
+			let entry = entry?;
+			let name = match entry.file_name().into_string() {
+				Ok(name) => name,
+				Err(_) => continue,
+			};
+			// Skip the temporary files monitors are written through
+			if name.ends_with(".tmp") || !entry.file_type()?.is_file() {
+				continue;
+			}
+			monitors.push((name, hex_utils::hex_str(&fs::read(entry.path())?)));
+		}
+	}
+	let peers = disk::read_channel_peer_data(store)?
+		.into_iter()
+		.map(|(pubkey, addr)| (hex_utils::hex_str(&pubkey.serialize()), addr.to_string()))
+		.collect();
+	let channels_number = monitors.len();
+	let backup = ChannelBackup { created_at: Utc::now().timestamp(), monitors, peers };
+	let plaintext =
+		serde_json::to_vec(&backup).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+	let mut nonce = [0; CHANNEL_BACKUP_NONCE_LEN];
+	thread_rng().fill_bytes(&mut nonce);
+	let ciphertext = ChaCha20Poly1305::new(Key::from_slice(key))
+		.encrypt(Nonce::from_slice(&nonce), plaintext.as_ref())
+		.map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to encrypt channel backup"))?;
+	let mut bundle = vec![CHANNEL_BACKUP_VERSION];
+	bundle.extend_from_slice(&nonce);
+	bundle.extend_from_slice(&ciphertext);
+	let _ = disk::persist_audit_event(
+		ldk_data_dir,
+		&format!("exported channel backup of {} channels", channels_number),
+	);
+	Ok((bundle, channels_number))
+}
+
+/// Restore the ChannelMonitors and channel peers of a channel backup into a node which lost
+/// its data, returning the number of channels restored.
+///
+/// The node starts without the backed up channels, so peers reestablishing them are answered
+/// with an error and force-close them, and the restored monitors claim our funds from the
+/// closing transactions. Monitors are restored as they were when exported, so their commitment
+/// transaction is revoked if a channel's state advanced since: the restored channels are marked
+/// as recovering, which keeps LDK from broadcasting it, until their funds are all claimed.
+pub(crate) fn restore_channel_backup(
+	ldk_data_dir: &str, store: &dyn NodeStore, key: &[u8; 32], bundle: &[u8],
+) -> io::Result<usize> {
+	let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+	if Path::new(&format!("{}/manager", ldk_data_dir)).exists() {
+		return Err(io::Error::new(
+			io::ErrorKind::AlreadyExists,
+			"the node still has its channel state, channel backups only restore nodes which lost theirs",
+		));
+	}
+	if bundle.len() < 1 + CHANNEL_BACKUP_NONCE_LEN || bundle[0] != CHANNEL_BACKUP_VERSION {
+		return Err(invalid("unsupported channel backup format"));
+	}
+	let (nonce, ciphertext) = bundle[1..].split_at(CHANNEL_BACKUP_NONCE_LEN);
+	let plaintext = ChaCha20Poly1305::new(Key::from_slice(key))
+		.decrypt(Nonce::from_slice(nonce), ciphertext)
+		.map_err(|_| invalid("unable to decrypt channel backup, was it exported by this node?"))?;
+	let backup: ChannelBackup = serde_json::from_slice(&plaintext)
+		.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+	let monitors_path = format!("{}/monitors", ldk_data_dir);
+	fs::create_dir_all(&monitors_path)?;
+	for (name, monitor) in backup.monitors.iter() {
+		// Monitor names are funding outpoints, never paths
+		if name.contains('/') || name.contains("..") {
+			return Err(invalid("invalid monitor name in channel backup"));
+		}
+		let monitor = hex_utils::to_vec(monitor).ok_or_else(|| invalid("invalid monitor"))?;
+		let path = format!("{}/{}", monitors_path, name);
+		// Never replace monitors the node still has, they are at least as recent
+		if !Path::new(&path).exists() {
+			store.write(RECOVERED_CHANNELS_NAMESPACE, name, &[])?;
+			fs::write(path, monitor)?;
+		}
+	}
+	for (pubkey, addr) in backup.peers.iter() {
+		let pubkey =
+			hex_utils::to_compressed_pubkey(pubkey).ok_or_else(|| invalid("invalid peer"))?;
+		let addr = addr.parse().map_err(|_| invalid("invalid peer address"))?;
+		disk::persist_channel_peer(store, &pubkey, &addr)?;
+	}
+	let _ = disk::persist_audit_event(
+		ldk_data_dir,
+		&format!(
+			"restored channel backup of {} channels created at {}",
+			backup.monitors.len(),
+			backup.created_at
+		),
+	);
+	Ok(backup.monitors.len())
+}
+
+/// Names of the monitors restored from a channel backup which still have funds to claim
+pub(crate) fn recovering_channels(store: &dyn NodeStore) -> io::Result<HashSet<String>> {
+	Ok(store.list(RECOVERED_CHANNELS_NAMESPACE)?.into_iter().map(|(name, _)| name).collect())
+}
+
+/// Forget the restored channels whose monitor has no balance left to claim, returning the number
+/// of channels still recovering
+pub(crate) fn resolve_recovered_channels(
+	ldk_data_dir: &str, store: &dyn NodeStore, chain_monitor: &ChainMonitor,
+) -> io::Result<usize> {
+	let mut recovering = recovering_channels(store)?;
+	for funding_txo in chain_monitor.list_monitors() {
+		let name = format!("{}_{}", funding_txo.txid, funding_txo.index);
+		if !recovering.contains(&name) {
+			continue;
+		}
+		let resolved = match chain_monitor.get_monitor(funding_txo) {
+			Ok(monitor) => monitor.get_claimable_balances().is_empty(),
+			Err(()) => false,
+		};
+		if resolved {
+			store.remove(RECOVERED_CHANNELS_NAMESPACE, &name)?;
+			recovering.remove(&name);
+			let _ = disk::persist_audit_event(
+				ldk_data_dir,
+				&format!("claimed the funds of channel {} restored from a backup", name),
+			);
+		}
+	}
+	Ok(recovering.len())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::peers::PeerAddr;
+	use crate::storage::{test_dir, MemoryStore};
+
+	const PEER_PUBKEY: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+
+	/// Export the channel backup of a node with the monitor `funding_0` and a channel peer
+	fn exported_backup(key: &[u8; 32]) -> Vec<u8> {
+		let dir = test_dir("export-backup");
+		fs::create_dir_all(format!("{}/monitors", dir)).unwrap();
+		fs::write(format!("{}/monitors/funding_0", dir), b"monitor").unwrap();
+		fs::write(format!("{}/monitors/funding_1.tmp", dir), b"partial").unwrap();
+		let store = MemoryStore::default();
+		let pubkey = hex_utils::to_compressed_pubkey(PEER_PUBKEY).unwrap();
+		let addr: PeerAddr = "127.0.0.1:9735".parse().unwrap();
+		disk::persist_channel_peer(&store, &pubkey, &addr).unwrap();
+
+		let (bundle, channels_number) = export_channel_backup(&dir, &store, key).unwrap();
+		assert_eq!(channels_number, 1);
+		let _ = fs::remove_dir_all(dir);
+		bundle
+	}
+
+	#[test]
+	fn snapshots_channel_state() {
+		let dir = test_dir("snapshot");
+		fs::write(format!("{}/manager", dir), b"manager").unwrap();
+		fs::create_dir_all(format!("{}/monitors", dir)).unwrap();
+		fs::write(format!("{}/monitors/funding_0", dir), b"monitor").unwrap();
+
+		let snapshot_id = snapshot(&dir, "closechannel").unwrap();
+		assert!(snapshot_id.ends_with("-closechannel"));
+		let snapshot_dir = format!("{}/backups/{}", dir, snapshot_id);
+		assert_eq!(fs::read(format!("{}/manager", snapshot_dir)).unwrap(), b"manager");
+		assert_eq!(fs::read(format!("{}/monitors/funding_0", snapshot_dir)).unwrap(), b"monitor");
+		let _ = fs::remove_dir_all(dir);
+	}
+
+	#[test]
+	fn prunes_oldest_snapshots() {
+		let dir = test_dir("prune-snapshots");
+		for idx in 0..BACKUP_RETENTION {
+			fs::create_dir_all(format!("{}/backups/20200101T0000{:02}.000-old", dir, idx)).unwrap();
+		}
+		let snapshot_id = snapshot(&dir, "forceclosechannel").unwrap();
+
+		let mut snapshots: Vec<String> = fs::read_dir(format!("{}/backups", dir))
+			.unwrap()
+			.map(|entry| entry.unwrap().file_name().into_string().unwrap())
+			.collect();
+		snapshots.sort();
+		assert_eq!(snapshots.len(), BACKUP_RETENTION);
+		assert_eq!(snapshots[0], "20200101T000001.000-old");
+		assert_eq!(snapshots.last(), Some(&snapshot_id));
+		let _ = fs::remove_dir_all(dir);
+	}
+
+	#[test]
+	fn restores_channel_backups() {
+		let key = channel_backup_key(&[7; 32]);
+		let bundle = exported_backup(&key);
+		let dir = test_dir("restore-backup");
+		let store = MemoryStore::default();
+		assert_eq!(restore_channel_backup(&dir, &store, &key, &bundle).unwrap(), 1);
+
+		assert_eq!(fs::read(format!("{}/monitors/funding_0", dir)).unwrap(), b"monitor");
+		assert!(!Path::new(&format!("{}/monitors/funding_1.tmp", dir)).exists());
+		let pubkey = hex_utils::to_compressed_pubkey(PEER_PUBKEY).unwrap();
+		assert_eq!(
+			disk::read_channel_peer_data(&store).unwrap()[&pubkey].to_string(),
+			"127.0.0.1:9735"
+		);
+		// The restored channel is recovering until its funds are claimed
+		let recovering = recovering_channels(&store).unwrap();
+		assert_eq!(recovering.into_iter().collect::<Vec<_>>(), ["funding_0"]);
+		let _ = fs::remove_dir_all(dir);
+	}
+
+	#[test]
+	fn keeps_the_monitors_the_node_still_has() {
+		let key = channel_backup_key(&[7; 32]);
+		let bundle = exported_backup(&key);
+		let dir = test_dir("restore-existing-monitor");
+		fs::create_dir_all(format!("{}/monitors", dir)).unwrap();
+		fs::write(format!("{}/monitors/funding_0", dir), b"newer monitor").unwrap();
+		let store = MemoryStore::default();
+		restore_channel_backup(&dir, &store, &key, &bundle).unwrap();
+
+		assert_eq!(fs::read(format!("{}/monitors/funding_0", dir)).unwrap(), b"newer monitor");
+		assert!(recovering_channels(&store).unwrap().is_empty());
+		let _ = fs::remove_dir_all(dir);
+	}
+
+	#[test]
+	fn refuses_backups_it_cannot_restore() {
+		let key = channel_backup_key(&[7; 32]);
+		let bundle = exported_backup(&key);
+		let store = MemoryStore::default();
+
+		// Backups of other nodes can't be decrypted
+		let dir = test_dir("restore-other-node");
+		let other_key = channel_backup_key(&[8; 32]);
+		let e = restore_channel_backup(&dir, &store, &other_key, &bundle).unwrap_err();
+		assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+		let mut tampered = bundle.clone();
+		*tampered.last_mut().unwrap() ^= 1;
+		assert!(restore_channel_backup(&dir, &store, &key, &tampered).is_err());
+		assert!(restore_channel_backup(&dir, &store, &key, &bundle[..5]).is_err());
+		let _ = fs::remove_dir_all(dir);
+
+		// Nodes which still have their channel state aren't restored
+		let dir = test_dir("restore-live-node");
+		fs::write(format!("{}/manager", dir), b"manager").unwrap();
+		let e = restore_channel_backup(&dir, &store, &key, &bundle).unwrap_err();
+		assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+		assert!(!Path::new(&format!("{}/monitors", dir)).exists());
+		let _ = fs::remove_dir_all(dir);
+	}
+}
@@ -0,0 +1,37 @@
+use bitcoin::Address;
+use lightning_invoice::Invoice;
+
+/// BIP 21 URI paying `amount_sat` either on-chain to `address` or over Lightning with `invoice`,
+/// as wallets supporting the `lightning` parameter do. `message` describes the payment.
+pub fn unified_uri(
+	address: &Address, amount_sat: u64, invoice: &Invoice, message: Option<&str>,
+) -> String {
+	let mut uri = format!("bitcoin:{}?amount={}", address, btc_amount(amount_sat));
+	if let Some(message) = message {
+		uri.push_str("&message=");
+		uri.push_str(&percent_encode(message));
+	}
+	uri.push_str("&lightning=");
+	uri.push_str(&invoice.to_string());
+	uri
+}
+
+/// Amount in bitcoins as a decimal number without trailing zeros, as BIP 21 expects
+fn btc_amount(amount_sat: u64) -> String {
+	let amount = format!("{}.{:08}", amount_sat / 100_000_000, amount_sat % 100_000_000);
+	amount.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// Percent-encode every byte of `value` but the unreserved characters of RFC 3986
+fn percent_encode(value: &str) -> String {
+	let mut encoded = String::new();
+	for byte in value.bytes() {
+		match byte {
+			b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+				encoded.push(byte as char)
+			}
+			_ => encoded.push_str(&format!("%{:02X}", byte)),
+		}
+	}
+	encoded
+}
@@ -1,30 +1,55 @@
-use crate::convert::{BlockchainInfo, FeeResponse, FundedTx, NewAddress, RawTx, SignedTx};
+use crate::convert::{
+	AbandonedTx, Balances, BlockchainInfo, FeeResponse, FundedTx, GetTxOut, ListUnspent,
+	LockUnspent, LockedOutpoints, NewAddress, RawTx, SignedTx, TxOutInfo, UnspentOutput,
+};
+use crate::settings::FeeSettings;
 use base64;
 use bitcoin::blockdata::block::Block;
 use bitcoin::blockdata::transaction::Transaction;
 use bitcoin::consensus::encode;
 use bitcoin::hash_types::{BlockHash, Txid};
 use bitcoin::util::address::Address;
+use bitcoin::OutPoint;
 use lightning::chain::chaininterface::{BroadcasterInterface, ConfirmationTarget, FeeEstimator};
-use lightning_block_sync::http::HttpEndpoint;
+use lightning_block_sync::http::{HttpEndpoint, JsonResponse};
 use lightning_block_sync::rpc::RpcClient;
 use lightning_block_sync::{AsyncBlockSourceResult, BlockHeaderData, BlockSource};
 use serde_json;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::str::FromStr;
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 use tokio::sync::Mutex;
+use tracing::{error, warn};
 
 pub struct BitcoindClient {
-	bitcoind_rpc_client: Arc<Mutex<RpcClient>>,
+	rpc: Arc<BitcoindRpc>,
+	fees: Arc<HashMap<Target, AtomicU32>>,
+	/// Economical fee estimates, in satoshis per KW, keyed by confirmation target
+	estimates: Arc<std::sync::Mutex<BTreeMap<u16, u32>>>,
+	handle: tokio::runtime::Handle,
+}
+
+/// Connection to bitcoind's RPC server, shared with the client's background tasks
+struct BitcoindRpc {
+	client: Mutex<RpcClient>,
 	host: String,
 	port: u16,
 	rpc_user: String,
 	rpc_password: String,
-	fees: Arc<HashMap<Target, AtomicU32>>,
-	handle: tokio::runtime::Handle,
+	health: std::sync::Mutex<RpcHealth>,
+}
+
+/// Reachability of bitcoind, as last seen by an RPC call
+#[derive(Clone, Default)]
+pub struct RpcHealth {
+	pub reachable: bool,
+	/// Error of the last call that couldn't reach bitcoind
+	pub last_error: Option<String>,
+	pub last_contact: Option<SystemTime>,
+	/// Height of bitcoind's best block, as of the last health check
+	pub block_height: Option<u32>,
 }
 
 #[derive(Clone, Eq, Hash, PartialEq)]
@@ -39,7 +64,7 @@ impl BlockSource for &BitcoindClient {
 		&'a mut self, header_hash: &'a BlockHash, height_hint: Option<u32>,
 	) -> AsyncBlockSourceResult<'a, BlockHeaderData> {
 		Box::pin(async move {
-			let mut rpc = self.bitcoind_rpc_client.lock().await;
+			let mut rpc = self.rpc.client.lock().await;
 			rpc.get_header(header_hash, height_hint).await
 		})
 	}
@@ -48,25 +73,92 @@ impl BlockSource for &BitcoindClient {
 		&'a mut self, header_hash: &'a BlockHash,
 	) -> AsyncBlockSourceResult<'a, Block> {
 		Box::pin(async move {
-			let mut rpc = self.bitcoind_rpc_client.lock().await;
+			let mut rpc = self.rpc.client.lock().await;
 			rpc.get_block(header_hash).await
 		})
 	}
 
 	fn get_best_block<'a>(&'a mut self) -> AsyncBlockSourceResult<(BlockHash, Option<u32>)> {
 		Box::pin(async move {
-			let mut rpc = self.bitcoind_rpc_client.lock().await;
+			let mut rpc = self.rpc.client.lock().await;
 			rpc.get_best_block().await
 		})
 	}
 }
 
 /// The minimum feerate we are allowed to send, as specify by LDK.
-const MIN_FEERATE: u32 = 253;
+pub(crate) const MIN_FEERATE: u32 = 253;
+
+/// Confirmation targets, in blocks, whose economical fee estimates are cached for `/feerates`
+/// and for transactions given a `target_conf`
+pub const FEE_ESTIMATE_TARGETS: [u16; 10] = [2, 3, 6, 12, 18, 24, 48, 144, 504, 1008];
+
+/// Delays before each reconnection attempt of a call that can't reach bitcoind, e.g. while it
+/// restarts, after which the call fails
+const RECONNECT_BACKOFF_MS: [u64; 5] = [500, 1_000, 2_000, 4_000, 8_000];
+
+impl BitcoindRpc {
+	fn connect(&self) -> std::io::Result<RpcClient> {
+		let http_endpoint = HttpEndpoint::for_host(self.host.clone()).with_port(self.port);
+		let rpc_credentials =
+			base64::encode(format!("{}:{}", self.rpc_user.clone(), self.rpc_password.clone()));
+		RpcClient::new(&rpc_credentials, http_endpoint)
+	}
+
+	/// Call `method`, reconnecting with backoff while bitcoind can't be reached. Errors
+	/// returned by bitcoind itself are not retried.
+	async fn call_method<T>(&self, method: &str, params: &[serde_json::Value]) -> std::io::Result<T>
+	where
+		JsonResponse: TryFrom<Vec<u8>, Error = std::io::Error> + TryInto<T, Error = std::io::Error>,
+	{
+		let mut rpc = self.client.lock().await;
+		let mut backoff = RECONNECT_BACKOFF_MS.iter();
+		loop {
+			let error = match rpc.call_method::<T>(method, params).await {
+				// `RpcClient` reports errors returned by bitcoind as `ErrorKind::Other`
+				Err(e) if e.kind() != std::io::ErrorKind::Other => e,
+				result => {
+					let mut health = self.health.lock().unwrap();
+					health.reachable = true;
+					health.last_contact = Some(SystemTime::now());
+					return result;
+				}
+			};
+			let delay_ms = match backoff.next() {
+				Some(delay_ms) => *delay_ms,
+				None => {
+					let mut health = self.health.lock().unwrap();
+					health.reachable = false;
+					health.last_error = Some(error.to_string());
+					return Err(error);
+				}
+			};
+			tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+			if let Ok(client) = self.connect() {
+				*rpc = client;
+			}
+		}
+	}
+
+	/// Feerate, in satoshis per KW, for a transaction to confirm within `conf_target` blocks,
+	/// if bitcoind has enough data to estimate it
+	async fn estimate_smart_fee(
+		&self, conf_target: u16, estimate_mode: &str,
+	) -> std::io::Result<Option<u32>> {
+		let resp = self
+			.call_method::<FeeResponse>(
+				"estimatesmartfee",
+				&vec![serde_json::json!(conf_target), serde_json::json!(estimate_mode)],
+			)
+			.await?;
+		Ok(resp.feerate_sat_per_kw.map(|feerate| std::cmp::max(feerate, MIN_FEERATE)))
+	}
+}
 
 impl BitcoindClient {
+	/// Connect to bitcoind, estimating fees at the rates of `fee_defaults` until bitcoind can
 	pub async fn new(
-		host: String, port: u16, rpc_user: String, rpc_password: String,
+		host: String, port: u16, rpc_user: String, rpc_password: String, fee_defaults: FeeSettings,
 		handle: tokio::runtime::Handle,
 	) -> std::io::Result<Self> {
 		let http_endpoint = HttpEndpoint::for_host(host.clone()).with_port(port);
@@ -81,156 +173,286 @@ impl BitcoindClient {
 				"Failed to make initial call to bitcoind - please check your RPC user/password and access settings")
 			})?;
 		let mut fees: HashMap<Target, AtomicU32> = HashMap::new();
-		fees.insert(Target::Background, AtomicU32::new(MIN_FEERATE));
-		fees.insert(Target::Normal, AtomicU32::new(2000));
-		fees.insert(Target::HighPriority, AtomicU32::new(5000));
-		let client = Self {
-			bitcoind_rpc_client: Arc::new(Mutex::new(bitcoind_rpc_client)),
+		let fee_defaults = FeeSettings {
+			background_sat_per_kw: std::cmp::max(fee_defaults.background_sat_per_kw, MIN_FEERATE),
+			normal_sat_per_kw: std::cmp::max(fee_defaults.normal_sat_per_kw, MIN_FEERATE),
+			high_priority_sat_per_kw: std::cmp::max(
+				fee_defaults.high_priority_sat_per_kw,
+				MIN_FEERATE,
+			),
+			..fee_defaults
+		};
+		fees.insert(Target::Background, AtomicU32::new(fee_defaults.background_sat_per_kw));
+		fees.insert(Target::Normal, AtomicU32::new(fee_defaults.normal_sat_per_kw));
+		fees.insert(Target::HighPriority, AtomicU32::new(fee_defaults.high_priority_sat_per_kw));
+		let rpc = Arc::new(BitcoindRpc {
+			client: Mutex::new(bitcoind_rpc_client),
 			host,
 			port,
 			rpc_user,
 			rpc_password,
+			health: std::sync::Mutex::new(RpcHealth {
+				reachable: true,
+				last_contact: Some(SystemTime::now()),
+				..Default::default()
+			}),
+		});
+		let client = Self {
+			rpc,
 			fees: Arc::new(fees),
+			estimates: Arc::new(std::sync::Mutex::new(BTreeMap::new())),
 			handle: handle.clone(),
 		};
 		BitcoindClient::poll_for_fee_estimates(
 			client.fees.clone(),
-			client.bitcoind_rpc_client.clone(),
-			handle,
+			client.estimates.clone(),
+			client.rpc.clone(),
+			Duration::from_secs(std::cmp::max(fee_defaults.poll_interval_secs, 1)),
+			&handle,
 		);
+		BitcoindClient::poll_health(client.rpc.clone(), &handle);
 		Ok(client)
 	}
 
+	/// Keep the fee estimates up to date, polling bitcoind every `interval` so that LDK's fee
+	/// queries never wait on an RPC call. An estimate is only replaced by a new one, so the
+	/// last good estimate, or the default, is kept while bitcoind can't estimate fees or is
+	/// unreachable.
 	fn poll_for_fee_estimates(
-		fees: Arc<HashMap<Target, AtomicU32>>, rpc_client: Arc<Mutex<RpcClient>>,
-		handle: tokio::runtime::Handle,
+		fees: Arc<HashMap<Target, AtomicU32>>,
+		estimates: Arc<std::sync::Mutex<BTreeMap<u16, u32>>>, rpc: Arc<BitcoindRpc>,
+		interval: Duration, handle: &tokio::runtime::Handle,
 	) {
 		handle.spawn(async move {
 			loop {
-				let background_estimate = {
-					let mut rpc = rpc_client.lock().await;
-					let background_conf_target = serde_json::json!(144);
-					let background_estimate_mode = serde_json::json!("ECONOMICAL");
-					let resp = rpc
-						.call_method::<FeeResponse>(
-							"estimatesmartfee",
-							&vec![background_conf_target, background_estimate_mode],
-						)
-						.await
-						.unwrap();
-					match resp.feerate_sat_per_kw {
-						Some(feerate) => std::cmp::max(feerate, MIN_FEERATE),
-						None => MIN_FEERATE,
+				for conf_target in FEE_ESTIMATE_TARGETS {
+					if let Ok(Some(feerate)) =
+						rpc.estimate_smart_fee(conf_target, "ECONOMICAL").await
+					{
+						estimates.lock().unwrap().insert(conf_target, feerate);
 					}
+				}
+				// The background and normal estimates are among the cached economical ones
+				let (background, normal) = {
+					let estimates = estimates.lock().unwrap();
+					(estimates.get(&144).copied(), estimates.get(&18).copied())
 				};
-
-				let normal_estimate = {
-					let mut rpc = rpc_client.lock().await;
-					let normal_conf_target = serde_json::json!(18);
-					let normal_estimate_mode = serde_json::json!("ECONOMICAL");
-					let resp = rpc
-						.call_method::<FeeResponse>(
-							"estimatesmartfee",
-							&vec![normal_conf_target, normal_estimate_mode],
-						)
-						.await
-						.unwrap();
-					match resp.feerate_sat_per_kw {
-						Some(feerate) => std::cmp::max(feerate, MIN_FEERATE),
-						None => 2000,
+				let high_priority = rpc.estimate_smart_fee(6, "CONSERVATIVE").await.ok().flatten();
+				let targets = [
+					(Target::Background, background),
+					(Target::Normal, normal),
+					(Target::HighPriority, high_priority),
+				];
+				for (target, feerate) in targets {
+					if let Some(feerate) = feerate {
+						fees.get(&target).unwrap().store(feerate, Ordering::Release);
 					}
-				};
+				}
+				tokio::time::sleep(interval).await;
+			}
+		});
+	}
 
-				let high_prio_estimate = {
-					let mut rpc = rpc_client.lock().await;
-					let high_prio_conf_target = serde_json::json!(6);
-					let high_prio_estimate_mode = serde_json::json!("CONSERVATIVE");
-					let resp = rpc
-						.call_method::<FeeResponse>(
-							"estimatesmartfee",
-							&vec![high_prio_conf_target, high_prio_estimate_mode],
-						)
-						.await
-						.unwrap();
-
-					match resp.feerate_sat_per_kw {
-						Some(feerate) => std::cmp::max(feerate, MIN_FEERATE),
-						None => 5000,
+	/// Check bitcoind's reachability and best block every 10 seconds
+	fn poll_health(rpc: Arc<BitcoindRpc>, handle: &tokio::runtime::Handle) {
+		handle.spawn(async move {
+			loop {
+				let info = rpc.call_method::<BlockchainInfo>("getblockchaininfo", &vec![]).await;
+				match info {
+					Ok(info) => {
+						rpc.health.lock().unwrap().block_height = Some(info.latest_height as u32)
 					}
-				};
-
-				fees.get(&Target::Background)
-					.unwrap()
-					.store(background_estimate, Ordering::Release);
-				fees.get(&Target::Normal).unwrap().store(normal_estimate, Ordering::Release);
-				fees.get(&Target::HighPriority)
-					.unwrap()
-					.store(high_prio_estimate, Ordering::Release);
-				tokio::time::sleep(Duration::from_secs(60)).await;
+					Err(e) => warn!("bitcoind is unreachable: {}", e),
+				}
+				tokio::time::sleep(Duration::from_secs(10)).await;
 			}
 		});
 	}
 
-	pub fn get_new_rpc_client(&self) -> std::io::Result<RpcClient> {
-		let http_endpoint = HttpEndpoint::for_host(self.host.clone()).with_port(self.port);
-		let rpc_credentials =
-			base64::encode(format!("{}:{}", self.rpc_user.clone(), self.rpc_password.clone()));
-		RpcClient::new(&rpc_credentials, http_endpoint)
+	/// Reachability of bitcoind and height of its best block
+	pub fn health(&self) -> RpcHealth {
+		self.rpc.health.lock().unwrap().clone()
 	}
 
-	pub async fn create_raw_transaction(&self, outputs: Vec<HashMap<String, f64>>) -> RawTx {
-		let mut rpc = self.bitcoind_rpc_client.lock().await;
-
+	pub async fn create_raw_transaction(
+		&self, outputs: Vec<HashMap<String, f64>>,
+	) -> std::io::Result<RawTx> {
 		let outputs_json = serde_json::json!(outputs);
-		rpc.call_method::<RawTx>("createrawtransaction", &vec![serde_json::json!([]), outputs_json])
+		self.rpc
+			.call_method::<RawTx>(
+				"createrawtransaction",
+				&vec![serde_json::json!([]), outputs_json],
+			)
 			.await
-			.unwrap()
 	}
 
-	pub async fn fund_raw_transaction(&self, raw_tx: RawTx) -> FundedTx {
-		let mut rpc = self.bitcoind_rpc_client.lock().await;
-
+	/// Fund `raw_tx` from the wallet at `sat_per_kw`, or at the normal priority estimate if
+	/// no fee rate is given
+	pub async fn fund_raw_transaction(
+		&self, raw_tx: RawTx, sat_per_kw: Option<u32>,
+	) -> std::io::Result<FundedTx> {
+		let sat_per_kw = match sat_per_kw {
+			Some(sat_per_kw) => sat_per_kw,
+			None => self.get_est_sat_per_1000_weight(ConfirmationTarget::Normal),
+		};
 		let raw_tx_json = serde_json::json!(raw_tx.0);
 		let options = serde_json::json!({
 			// LDK gives us feerates in satoshis per KW but Bitcoin Core here expects fees
 			// denominated in satoshis per vB. First we need to multiply by 4 to convert weight
 			// units to virtual bytes, then divide by 1000 to convert KvB to vB.
-			"fee_rate": self.get_est_sat_per_1000_weight(ConfirmationTarget::Normal) as f64 / 250.0,
+			"fee_rate": sat_per_kw as f64 / 250.0,
 			// While users could "cancel" a channel open by RBF-bumping and paying back to
 			// themselves, we don't allow it here as its easy to have users accidentally RBF bump
 			// and pay to the channel funding address, which results in loss of funds. Real
 			// LDK-based applications should enable RBF bumping and RBF bump either to a local
 			// change address or to a new channel output negotiated with the same node.
 			"replaceable": false,
+			// Reserve the inputs until the funding transaction is broadcast, or unlocked on
+			// `Event::DiscardFunding`, so they aren't spent or reported as available meanwhile.
+			"lockUnspents": true,
 		});
-		rpc.call_method("fundrawtransaction", &[raw_tx_json, options]).await.unwrap()
+		self.rpc.call_method("fundrawtransaction", &[raw_tx_json, options]).await
 	}
 
 	pub async fn send_raw_transaction(&self, raw_tx: RawTx) {
-		let mut rpc = self.bitcoind_rpc_client.lock().await;
-
 		let raw_tx_json = serde_json::json!(raw_tx.0);
-		rpc.call_method::<Txid>("sendrawtransaction", &[raw_tx_json]).await.unwrap();
+		self.rpc.call_method::<Txid>("sendrawtransaction", &[raw_tx_json]).await.unwrap();
 	}
 
-	pub async fn sign_raw_transaction_with_wallet(&self, tx_hex: String) -> SignedTx {
-		let mut rpc = self.bitcoind_rpc_client.lock().await;
+	/// Broadcast `tx`, returning bitcoind's error if it rejects the transaction
+	pub async fn send_transaction(&self, tx: &Transaction) -> std::io::Result<Txid> {
+		let tx_hex_json = serde_json::json!(encode::serialize_hex(tx));
+		self.rpc.call_method::<Txid>("sendrawtransaction", &[tx_hex_json]).await
+	}
 
+	pub async fn sign_raw_transaction_with_wallet(
+		&self, tx_hex: String,
+	) -> std::io::Result<SignedTx> {
 		let tx_hex_json = serde_json::json!(tx_hex);
-		rpc.call_method("signrawtransactionwithwallet", &vec![tx_hex_json]).await.unwrap()
+		self.rpc.call_method("signrawtransactionwithwallet", &vec![tx_hex_json]).await
 	}
 
 	pub async fn get_new_address(&self) -> Address {
-		let mut rpc = self.bitcoind_rpc_client.lock().await;
-
 		let addr_args = vec![serde_json::json!("LDK output address")];
-		let addr = rpc.call_method::<NewAddress>("getnewaddress", &addr_args).await.unwrap();
+		let addr = self.rpc.call_method::<NewAddress>("getnewaddress", &addr_args).await.unwrap();
 		Address::from_str(addr.0.as_str()).unwrap()
 	}
 
-	pub async fn get_blockchain_info(&self) -> BlockchainInfo {
-		let mut rpc = self.bitcoind_rpc_client.lock().await;
-		rpc.call_method::<BlockchainInfo>("getblockchaininfo", &vec![]).await.unwrap()
+	pub async fn get_new_bech32_address(&self, label: &str) -> std::io::Result<Address> {
+		let addr_args = vec![serde_json::json!(label), serde_json::json!("bech32")];
+		let addr = self.rpc.call_method::<NewAddress>("getnewaddress", &addr_args).await?;
+		Address::from_str(addr.0.as_str())
+			.map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+	}
+
+	/// Build, fund, sign and broadcast a transaction paying `amount_sat` to `address` from the
+	/// bitcoind wallet, returning its txid.
+	pub async fn send_to_address(
+		&self, address: &Address, amount_sat: u64, sat_per_vbyte: Option<f64>,
+	) -> std::io::Result<Txid> {
+		let mut outputs = HashMap::with_capacity(1);
+		outputs.insert(address.to_string(), amount_sat as f64 / 100_000_000.0);
+		let raw_tx = self
+			.rpc
+			.call_method::<RawTx>(
+				"createrawtransaction",
+				&vec![serde_json::json!([]), serde_json::json!([outputs])],
+			)
+			.await?;
+
+		let fee_rate = match sat_per_vbyte {
+			Some(fee_rate) => fee_rate,
+			// LDK feerates are in satoshis per KW, see `fund_raw_transaction`.
+			None => self.get_est_sat_per_1000_weight(ConfirmationTarget::Normal) as f64 / 250.0,
+		};
+		let options = serde_json::json!({ "fee_rate": fee_rate, "replaceable": true });
+		let funded_tx = self
+			.rpc
+			.call_method::<FundedTx>("fundrawtransaction", &[serde_json::json!(raw_tx.0), options])
+			.await?;
+
+		let signed_tx = self
+			.rpc
+			.call_method::<SignedTx>(
+				"signrawtransactionwithwallet",
+				&vec![serde_json::json!(funded_tx.hex)],
+			)
+			.await?;
+		if !signed_tx.complete {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::Other,
+				"bitcoind wallet was unable to fully sign the transaction",
+			));
+		}
+
+		self.rpc
+			.call_method::<Txid>("sendrawtransaction", &[serde_json::json!(signed_tx.hex)])
+			.await
+	}
+
+	/// Estimate the feerate, in satoshis per KW, for a transaction to confirm within
+	/// `conf_target` blocks, from the cached estimate of the closest target not above it, or of
+	/// the lowest target
+	pub fn estimate_fee_sat_per_kw(&self, conf_target: u16) -> std::io::Result<u32> {
+		let estimates = self.estimates.lock().unwrap();
+		match estimates.range(..=conf_target).next_back().or_else(|| estimates.iter().next()) {
+			Some((_, feerate)) => Ok(*feerate),
+			None => Err(std::io::Error::new(
+				std::io::ErrorKind::Other,
+				format!("bitcoind has no fee estimate for {} blocks yet", conf_target),
+			)),
+		}
+	}
+
+	/// Cached economical fee estimates, in satoshis per KW, by confirmation target
+	pub fn fee_estimates(&self) -> Vec<(u16, u32)> {
+		self.estimates.lock().unwrap().iter().map(|(target, feerate)| (*target, *feerate)).collect()
+	}
+
+	pub async fn list_unspent(&self) -> std::io::Result<Vec<UnspentOutput>> {
+		Ok(self.rpc.call_method::<ListUnspent>("listunspent", &vec![serde_json::json!(0)]).await?.0)
+	}
+
+	pub async fn list_lock_unspent(&self) -> std::io::Result<Vec<OutPoint>> {
+		Ok(self.rpc.call_method::<LockedOutpoints>("listlockunspent", &vec![]).await?.0)
+	}
+
+	/// Look up an unspent output, which is reported as spent if `include_mempool` is set and a
+	/// mempool transaction spends it
+	pub async fn get_tx_out(
+		&self, outpoint: &OutPoint, include_mempool: bool,
+	) -> std::io::Result<Option<TxOutInfo>> {
+		let args = vec![
+			serde_json::json!(outpoint.txid.to_string()),
+			serde_json::json!(outpoint.vout),
+			serde_json::json!(include_mempool),
+		];
+		Ok(self.rpc.call_method::<GetTxOut>("gettxout", &args).await?.0)
+	}
+
+	pub async fn unlock_unspent(&self, outpoints: &[OutPoint]) -> std::io::Result<bool> {
+		let outpoints_json: Vec<serde_json::Value> = outpoints
+			.iter()
+			.map(|o| serde_json::json!({ "txid": o.txid.to_string(), "vout": o.vout }))
+			.collect();
+		let args = vec![serde_json::json!(true), serde_json::json!(outpoints_json)];
+		Ok(self.rpc.call_method::<LockUnspent>("lockunspent", &args).await?.0)
+	}
+
+	/// Mark a wallet transaction which isn't confirmed nor in the mempool as abandoned, so that
+	/// its inputs can be spent again
+	pub async fn abandon_transaction(&self, txid: &Txid) -> std::io::Result<()> {
+		let args = vec![serde_json::json!(txid.to_string())];
+		self.rpc.call_method::<AbandonedTx>("abandontransaction", &args).await?;
+		Ok(())
+	}
+
+	pub async fn get_balances(&self) -> std::io::Result<Balances> {
+		self.rpc.call_method::<Balances>("getbalances", &vec![]).await
+	}
+
+	pub async fn get_blockchain_info(&self) -> std::io::Result<BlockchainInfo> {
+		self.rpc.call_method::<BlockchainInfo>("getblockchaininfo", &vec![]).await
 	}
 }
 
@@ -252,16 +474,16 @@ impl FeeEstimator for BitcoindClient {
 
 impl BroadcasterInterface for BitcoindClient {
 	fn broadcast_transaction(&self, tx: &Transaction) {
-		let bitcoind_rpc_client = self.bitcoind_rpc_client.clone();
+		let rpc = self.rpc.clone();
+		let txid = tx.txid();
 		let tx_serialized = serde_json::json!(encode::serialize_hex(tx));
 		self.handle.spawn(async move {
-			let mut rpc = bitcoind_rpc_client.lock().await;
 			// This may error due to RL calling `broadcast_transaction` with the same transaction
 			// multiple times, but the error is safe to ignore.
 			match rpc.call_method::<Txid>("sendrawtransaction", &vec![tx_serialized]).await {
 				Ok(_) => {}
 				Err(e) => {
-					let err_str = e.get_ref().unwrap().to_string();
+					let err_str = e.to_string();
 					if !err_str.contains("Transaction already in block chain")
 						&& !err_str.contains("Inputs missing or spent")
 						&& !err_str.contains("bad-txns-inputs-missingorspent")
@@ -269,7 +491,7 @@ impl BroadcasterInterface for BitcoindClient {
 						&& !err_str.contains("non-BIP68-final")
 						&& !err_str.contains("insufficient fee, rejecting replacement ")
 					{
-						panic!("{}", e);
+						error!("failed to broadcast transaction {}: {}", txid, e);
 					}
 				}
 			}
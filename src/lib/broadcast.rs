@@ -0,0 +1,512 @@
+use crate::chain::{deserialize_hex, TxIndex};
+use crate::node_var::unix_timestamp;
+use crate::storage::NodeStore;
+use bitcoin::blockdata::block::{Block, BlockHeader};
+use bitcoin::blockdata::transaction::Transaction;
+use bitcoin::consensus::encode;
+use bitcoin::hash_types::{BlockHash, Txid};
+use lightning::chain::chaininterface::BroadcasterInterface;
+use lightning::chain::channelmonitor::ANTI_REORG_DELAY;
+use lightning::chain::transaction::OutPoint as LdkOutPoint;
+use lightning::chain::Listen;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+
+pub(crate) const BROADCAST_TXS_NAMESPACE: &str = "broadcast_txs";
+
+/// Kind of a broadcast transaction, as far as the broadcast manager can tell
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BroadcastKind {
+	/// Broadcast by LDK, i.e. a funding, closing, commitment or claim transaction
+	Channel,
+	/// Sweep of the outputs LDK handed over
+	Sweep,
+}
+
+/// A transaction the node broadcast, tracked until it is buried under `ANTI_REORG_DELAY`
+/// blocks
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BroadcastTx {
+	pub txid: String,
+	pub tx_hex: String,
+	pub kind: BroadcastKind,
+	pub first_broadcast_at: u64,
+	pub last_broadcast_at: u64,
+	/// Number of times the transaction was broadcast, by the node or by LDK
+	pub broadcasts: u32,
+	/// Height of the block the transaction was last seen confirmed in
+	pub confirmed_height: Option<u32>,
+}
+
+/// Where a broadcast transaction stands on chain
+enum TxState {
+	/// Neither confirmed nor conflicted, e.g. waiting in the mempool or dropped from it
+	Unconfirmed,
+	/// Confirmed in the block at this height
+	Confirmed(u32),
+	/// Its inputs were spent by a conflicting transaction, or it spends a conflicted one
+	Resolved,
+}
+
+/// Broadcaster of LDK and of the sweeper, recording every transaction it broadcasts so that
+/// none is lost to a mempool eviction or a reorg.
+///
+/// Transactions are persisted when broadcast and checked at every new chain tip: unconfirmed
+/// ones are broadcast again, including those a reorg took out of the chain, until they are
+/// buried under `ANTI_REORG_DELAY` blocks or conflicted. With bitcoind, the blocks are connected
+/// and disconnected as a `Listen`er, else the transaction index is queried at new tips.
+pub struct BroadcastManager {
+	broadcaster: Arc<dyn BroadcasterInterface + Send + Sync>,
+	tx_index: Option<TxIndex>,
+	store: Arc<dyn NodeStore>,
+	/// Serializes the updates of the persisted transactions
+	lock: Mutex<()>,
+	last_tip: Mutex<Option<(BlockHash, u32)>>,
+}
+
+impl BroadcastManager {
+	/// Create a manager broadcasting through `broadcaster`, polling `tx_index` for the chain tip
+	/// if LDK isn't synced from bitcoind
+	pub(crate) fn new(
+		broadcaster: Arc<dyn BroadcasterInterface + Send + Sync>, tx_index: Option<TxIndex>,
+		store: Arc<dyn NodeStore>,
+	) -> Self {
+		Self { broadcaster, tx_index, store, lock: Mutex::new(()), last_tip: Mutex::new(None) }
+	}
+
+	/// Record `tx`, broadcast by the node through another client than its chain source
+	pub(crate) fn record(&self, tx: &Transaction, kind: BroadcastKind) {
+		let _lock = self.lock.lock().unwrap();
+		let txid = tx.txid();
+		let now = unix_timestamp();
+		let record = match self.read(&txid) {
+			Ok(Some(mut record)) => {
+				record.last_broadcast_at = now;
+				record.broadcasts += 1;
+				record
+			}
+			Ok(None) => BroadcastTx {
+				txid: txid.to_string(),
+				tx_hex: encode::serialize_hex(tx),
+				kind,
+				first_broadcast_at: now,
+				last_broadcast_at: now,
+				broadcasts: 1,
+				confirmed_height: None,
+			},
+			Err(e) => {
+				warn!("unable to read broadcast transaction {}: {}", txid, e);
+				return;
+			}
+		};
+		if let Err(e) = self.write(&record) {
+			warn!("failed to persist broadcast transaction {}: {}", txid, e);
+		}
+	}
+
+	/// The transactions still tracked, in the order they were first broadcast
+	pub(crate) fn pending_txs(&self) -> io::Result<Vec<BroadcastTx>> {
+		let mut txs = Vec::new();
+		for (_, value) in self.store.list(BROADCAST_TXS_NAMESPACE)? {
+			txs.push(
+				serde_json::from_slice::<BroadcastTx>(&value)
+					.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+			);
+		}
+		txs.sort_by_key(|tx| tx.first_broadcast_at);
+		Ok(txs)
+	}
+
+	/// Txid of the tracked transaction spending the funding output of channel `channel_id`, i.e.
+	/// the closing or commitment transaction the node broadcast to close it
+	pub(crate) fn closing_txid(&self, channel_id: &[u8; 32]) -> io::Result<Option<Txid>> {
+		for record in self.pending_txs()? {
+			let tx: Transaction = deserialize_hex(&record.tx_hex)?;
+			let spends_funding = tx.input.iter().any(|input| {
+				let outpoint = input.previous_output;
+				u16::try_from(outpoint.vout).map_or(false, |index| {
+					LdkOutPoint { txid: outpoint.txid, index }.to_channel_id() == *channel_id
+				})
+			});
+			if spends_funding {
+				return Ok(Some(tx.txid()));
+			}
+		}
+		Ok(None)
+	}
+
+	/// Height of the chain tip the tracked transactions were last checked at
+	pub(crate) fn tip_height(&self) -> Option<u32> {
+		self.last_tip.lock().unwrap().map(|(_, height)| height)
+	}
+
+	/// Check the tracked transactions against the transaction index if the chain tip changed
+	/// since the last check, broadcasting the unconfirmed ones again and forgetting the buried and
+	/// conflicted ones
+	pub(crate) async fn process_tip(&self) -> io::Result<()> {
+		let tx_index = match &self.tx_index {
+			Some(tx_index) => tx_index,
+			// Blocks are connected by the chain listener
+			None => return Ok(()),
+		};
+		let (tip_hash, tip_height) = tx_index.get_tip().await?;
+		if self.last_tip.lock().unwrap().map(|(hash, _)| hash) == Some(tip_hash) {
+			return Ok(());
+		}
+
+		// Transactions spending conflicted ones, e.g. HTLC claims of a replaced commitment
+		// transaction, are conflicted too. They were broadcast after those they spend.
+		let mut conflicted = HashSet::new();
+		for record in self.pending_txs()? {
+			let tx: Transaction = deserialize_hex(&record.tx_hex)?;
+			let txid = tx.txid();
+			let state =
+				if tx.input.iter().any(|input| conflicted.contains(&input.previous_output.txid)) {
+					TxState::Resolved
+				} else {
+					tx_state(tx_index, &tx).await?
+				};
+			if let TxState::Resolved = state {
+				conflicted.insert(txid);
+			}
+
+			let _lock = self.lock.lock().unwrap();
+			// The record may have been broadcast again meanwhile
+			let record = match self.read(&txid)? {
+				Some(record) => record,
+				None => continue,
+			};
+			self.apply_state(record, &tx, state, tip_height)?;
+		}
+		*self.last_tip.lock().unwrap() = Some((tip_hash, tip_height));
+		Ok(())
+	}
+
+	/// Check the tracked transactions against the block connected at `height`
+	fn connect_block(&self, block: &Block, height: u32) -> io::Result<()> {
+		let _lock = self.lock.lock().unwrap();
+		let confirmed: HashSet<Txid> = block.txdata.iter().map(|tx| tx.txid()).collect();
+		let mut spends = HashMap::new();
+		for tx in &block.txdata {
+			for input in &tx.input {
+				spends.insert(input.previous_output, tx.txid());
+			}
+		}
+		// Transactions spending conflicted ones, e.g. HTLC claims of a replaced commitment
+		// transaction, are conflicted too. They were broadcast after those they spend.
+		let mut conflicted = HashSet::new();
+		for record in self.pending_txs()? {
+			let tx: Transaction = deserialize_hex(&record.tx_hex)?;
+			let txid = tx.txid();
+			let state = if confirmed.contains(&txid) {
+				TxState::Confirmed(height)
+			} else if let Some(confirmed_height) = record.confirmed_height {
+				TxState::Confirmed(confirmed_height)
+			} else if tx.input.iter().any(|input| {
+				conflicted.contains(&input.previous_output.txid)
+					|| spends.get(&input.previous_output).map_or(false, |spend| *spend != txid)
+			}) {
+				conflicted.insert(txid);
+				TxState::Resolved
+			} else {
+				TxState::Unconfirmed
+			};
+			self.apply_state(record, &tx, state, height)?;
+		}
+		*self.last_tip.lock().unwrap() = Some((block.block_hash(), height));
+		Ok(())
+	}
+
+	/// Forget the confirmations of the tracked transactions in the block disconnected at `height`,
+	/// so that they are broadcast again at the next block
+	fn disconnect_block(&self, header: &BlockHeader, height: u32) -> io::Result<()> {
+		let _lock = self.lock.lock().unwrap();
+		for mut record in self.pending_txs()? {
+			match record.confirmed_height {
+				Some(confirmed_height) if confirmed_height >= height => {
+					warn!(
+						"transaction {} confirmed at height {} was reorged out of the chain",
+						record.txid, confirmed_height
+					);
+					record.confirmed_height = None;
+					self.write(&record)?;
+				}
+				_ => {}
+			}
+		}
+		*self.last_tip.lock().unwrap() = Some((header.prev_blockhash, height - 1));
+		Ok(())
+	}
+
+	/// Update the record of `tx` given its state at the chain tip, broadcasting it again if it is
+	/// unconfirmed
+	fn apply_state(
+		&self, mut record: BroadcastTx, tx: &Transaction, state: TxState, tip_height: u32,
+	) -> io::Result<()> {
+		let txid = tx.txid();
+		match state {
+			TxState::Confirmed(height) if tip_height + 1 >= height + ANTI_REORG_DELAY => {
+				info!("transaction {} is buried under {} blocks", txid, ANTI_REORG_DELAY);
+				self.store.remove(BROADCAST_TXS_NAMESPACE, &record.txid)?;
+			}
+			TxState::Confirmed(height) => {
+				if record.confirmed_height != Some(height) {
+					record.confirmed_height = Some(height);
+					self.write(&record)?;
+				}
+			}
+			TxState::Resolved => {
+				info!("transaction {} was spent or conflicted on chain", txid);
+				self.store.remove(BROADCAST_TXS_NAMESPACE, &record.txid)?;
+			}
+			TxState::Unconfirmed => {
+				if let Some(height) = record.confirmed_height.take() {
+					warn!(
+							"transaction {} confirmed at height {} was reorged out of the chain, broadcasting it again",
+							txid, height
+						);
+				}
+				record.last_broadcast_at = unix_timestamp();
+				record.broadcasts += 1;
+				self.write(&record)?;
+				self.broadcaster.broadcast_transaction(tx);
+			}
+		}
+		Ok(())
+	}
+
+	fn read(&self, txid: &Txid) -> io::Result<Option<BroadcastTx>> {
+		match self.store.read(BROADCAST_TXS_NAMESPACE, &txid.to_string())? {
+			Some(value) => serde_json::from_slice(&value)
+				.map(Some)
+				.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+			None => Ok(None),
+		}
+	}
+
+	fn write(&self, record: &BroadcastTx) -> io::Result<()> {
+		let value = serde_json::to_vec(record)
+			.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+		self.store.write(BROADCAST_TXS_NAMESPACE, &record.txid, &value)
+	}
+}
+
+impl BroadcasterInterface for BroadcastManager {
+	fn broadcast_transaction(&self, tx: &Transaction) {
+		self.record(tx, BroadcastKind::Channel);
+		self.broadcaster.broadcast_transaction(tx);
+	}
+}
+
+impl Listen for BroadcastManager {
+	fn block_connected(&self, block: &Block, height: u32) {
+		if let Err(e) = self.connect_block(block, height) {
+			warn!("failed to check the broadcast transactions at height {}: {}", height, e);
+		}
+	}
+
+	fn block_disconnected(&self, header: &BlockHeader, height: u32) {
+		if let Err(e) = self.disconnect_block(header, height) {
+			warn!("failed to check the broadcast transactions at height {}: {}", height, e);
+		}
+	}
+}
+
+/// Where `tx` stands according to the transaction index
+async fn tx_state(tx_index: &TxIndex, tx: &Transaction) -> io::Result<TxState> {
+	let txid = tx.txid();
+	match tx_index {
+		TxIndex::Esplora(client) => {
+			if let Some((_, height)) = client.get_tx_confirmation(&txid).await? {
+				return Ok(TxState::Confirmed(height));
+			}
+			for input in &tx.input {
+				let outpoint = input.previous_output;
+				let spend = client.get_output_spend(&outpoint.txid, outpoint.vout as u16).await?;
+				if spend.map_or(false, |spend| spend != txid) {
+					return Ok(TxState::Resolved);
+				}
+			}
+			Ok(TxState::Unconfirmed)
+		}
+		TxIndex::Electrum(client) => {
+			if let Some((_, height)) = client.get_tx_confirmation(&txid).await? {
+				return Ok(TxState::Confirmed(height));
+			}
+			for input in &tx.input {
+				let outpoint = input.previous_output;
+				// Electrum servers index spends by the script of the spent output
+				let script_pubkey = match client.get_tx(&outpoint.txid).await? {
+					Some(prev_tx) => match prev_tx.output.get(outpoint.vout as usize) {
+						Some(output) => output.script_pubkey.clone(),
+						None => continue,
+					},
+					None => continue,
+				};
+				let spend = client.get_output_spend(&outpoint, &script_pubkey).await?;
+				if spend.map_or(false, |spend| spend != txid) {
+					return Ok(TxState::Resolved);
+				}
+			}
+			Ok(TxState::Unconfirmed)
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::storage::MemoryStore;
+	use bitcoin::blockdata::script::Script;
+	use bitcoin::blockdata::transaction::{OutPoint, TxIn, TxOut};
+	use bitcoin::hashes::Hash;
+
+	#[derive(Default)]
+	struct TestBroadcaster(Mutex<Vec<Txid>>);
+
+	impl BroadcasterInterface for TestBroadcaster {
+		fn broadcast_transaction(&self, tx: &Transaction) {
+			self.0.lock().unwrap().push(tx.txid());
+		}
+	}
+
+	fn manager() -> (BroadcastManager, Arc<TestBroadcaster>) {
+		let broadcaster = Arc::new(TestBroadcaster::default());
+		let manager = BroadcastManager::new(
+			Arc::clone(&broadcaster) as Arc<dyn BroadcasterInterface + Send + Sync>,
+			None,
+			Arc::new(MemoryStore::default()),
+		);
+		(manager, broadcaster)
+	}
+
+	fn spending_tx(previous_output: OutPoint, value: u64) -> Transaction {
+		Transaction {
+			version: 2,
+			lock_time: 0,
+			input: vec![TxIn {
+				previous_output,
+				script_sig: Script::new(),
+				sequence: 0xffffffff,
+				witness: Vec::new(),
+			}],
+			output: vec![TxOut { value, script_pubkey: Script::new() }],
+		}
+	}
+
+	fn funding_outpoint() -> OutPoint {
+		OutPoint { txid: Txid::from_slice(&[1; 32]).unwrap(), vout: 0 }
+	}
+
+	fn block_with(prev_blockhash: BlockHash, txdata: Vec<Transaction>) -> Block {
+		let header = BlockHeader {
+			version: 1,
+			prev_blockhash,
+			merkle_root: Default::default(),
+			time: 0,
+			bits: 0,
+			nonce: 0,
+		};
+		Block { header, txdata }
+	}
+
+	fn broadcasts(broadcaster: &TestBroadcaster, txid: &Txid) -> usize {
+		broadcaster.0.lock().unwrap().iter().filter(|broadcast| *broadcast == txid).count()
+	}
+
+	#[test]
+	fn rebroadcasts_until_buried() {
+		let (manager, broadcaster) = manager();
+		let tx = spending_tx(funding_outpoint(), 10_000);
+		let txid = tx.txid();
+		manager.broadcast_transaction(&tx);
+		assert_eq!(broadcasts(&broadcaster, &txid), 1);
+
+		// Broadcast again at every block until it confirms
+		let mut prev_blockhash = BlockHash::default();
+		for height in 101..103 {
+			let block = block_with(prev_blockhash, Vec::new());
+			manager.block_connected(&block, height);
+			prev_blockhash = block.block_hash();
+		}
+		assert_eq!(broadcasts(&broadcaster, &txid), 3);
+
+		let block = block_with(prev_blockhash, vec![tx.clone()]);
+		manager.block_connected(&block, 103);
+		prev_blockhash = block.block_hash();
+		assert_eq!(manager.pending_txs().unwrap()[0].confirmed_height, Some(103));
+
+		// Tracked without being broadcast again until buried under `ANTI_REORG_DELAY` blocks
+		for height in 104..103 + ANTI_REORG_DELAY - 1 {
+			let block = block_with(prev_blockhash, Vec::new());
+			manager.block_connected(&block, height);
+			prev_blockhash = block.block_hash();
+			assert_eq!(manager.pending_txs().unwrap().len(), 1);
+		}
+		manager
+			.block_connected(&block_with(prev_blockhash, Vec::new()), 103 + ANTI_REORG_DELAY - 1);
+		assert!(manager.pending_txs().unwrap().is_empty());
+		assert_eq!(broadcasts(&broadcaster, &txid), 3);
+	}
+
+	#[test]
+	fn unconfirms_on_block_disconnect() {
+		let (manager, broadcaster) = manager();
+		let tx = spending_tx(funding_outpoint(), 10_000);
+		let txid = tx.txid();
+		manager.broadcast_transaction(&tx);
+
+		let confirming_block = block_with(BlockHash::default(), vec![tx.clone()]);
+		manager.block_connected(&confirming_block, 101);
+		assert_eq!(manager.pending_txs().unwrap()[0].confirmed_height, Some(101));
+		assert_eq!(manager.tip_height(), Some(101));
+
+		manager.block_disconnected(&confirming_block.header, 101);
+		assert_eq!(manager.pending_txs().unwrap()[0].confirmed_height, None);
+		assert_eq!(manager.tip_height(), Some(100));
+		assert_eq!(broadcasts(&broadcaster, &txid), 1);
+
+		// The block replacing the disconnected one doesn't have it, so it's broadcast again
+		manager.block_connected(&block_with(BlockHash::default(), Vec::new()), 101);
+		assert_eq!(manager.pending_txs().unwrap()[0].confirmed_height, None);
+		assert_eq!(broadcasts(&broadcaster, &txid), 2);
+	}
+
+	#[test]
+	fn forgets_conflicted_transactions() {
+		let (manager, broadcaster) = manager();
+		let tx = spending_tx(funding_outpoint(), 10_000);
+		let child = spending_tx(OutPoint { txid: tx.txid(), vout: 0 }, 9_000);
+		manager.broadcast_transaction(&tx);
+		manager.broadcast_transaction(&child);
+
+		// A transaction spending the same output confirms instead, so neither can
+		let conflicting_tx = spending_tx(funding_outpoint(), 9_500);
+		manager.block_connected(&block_with(BlockHash::default(), vec![conflicting_tx]), 101);
+		assert!(manager.pending_txs().unwrap().is_empty());
+		assert_eq!(broadcasts(&broadcaster, &tx.txid()), 1);
+		assert_eq!(broadcasts(&broadcaster, &child.txid()), 1);
+	}
+
+	#[test]
+	fn finds_closing_transactions() {
+		let (manager, _) = manager();
+		let funding_outpoint = funding_outpoint();
+		let channel_id =
+			LdkOutPoint { txid: funding_outpoint.txid, index: funding_outpoint.vout as u16 }
+				.to_channel_id();
+		assert_eq!(manager.closing_txid(&channel_id).unwrap(), None);
+
+		let other_tx = spending_tx(OutPoint { txid: funding_outpoint.txid, vout: 1 }, 10_000);
+		manager.broadcast_transaction(&other_tx);
+		assert_eq!(manager.closing_txid(&channel_id).unwrap(), None);
+
+		let closing_tx = spending_tx(funding_outpoint, 10_000);
+		manager.broadcast_transaction(&closing_tx);
+		assert_eq!(manager.closing_txid(&channel_id).unwrap(), Some(closing_tx.txid()));
+	}
+}
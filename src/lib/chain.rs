@@ -0,0 +1,392 @@
+use crate::bitcoind_client::BitcoindClient;
+use crate::electrum::ElectrumClient;
+use crate::esplora::EsploraClient;
+use crate::hex_utils;
+use bitcoin::blockdata::block::BlockHeader;
+use bitcoin::blockdata::script::Script;
+use bitcoin::blockdata::transaction::Transaction;
+use bitcoin::consensus::encode;
+use bitcoin::hash_types::{BlockHash, Txid};
+use bitcoin::OutPoint as BitcoinOutPoint;
+use lightning::chain::chaininterface::{BroadcasterInterface, ConfirmationTarget, FeeEstimator};
+use lightning::chain::transaction::OutPoint;
+use lightning::chain::{Confirm, Filter, WatchedOutput};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+/// Backend estimating fees and broadcasting transactions for LDK, as selected by the
+/// `[chain]` section of the config file
+pub enum ChainSource {
+	Bitcoind(Arc<BitcoindClient>),
+	Esplora(Arc<EsploraClient>),
+	Electrum(Arc<ElectrumClient>),
+}
+
+impl ChainSource {
+	/// Hash and height of the chain tip
+	pub async fn get_best_block(&self) -> io::Result<(BlockHash, u32)> {
+		match self {
+			ChainSource::Bitcoind(client) => {
+				let info = client.get_blockchain_info().await?;
+				Ok((info.latest_blockhash, info.latest_height as u32))
+			}
+			ChainSource::Esplora(client) => client.get_tip().await,
+			ChainSource::Electrum(client) => client.get_tip().await,
+		}
+	}
+
+	/// Broadcast `tx`, returning the backend's error if it rejects the transaction
+	pub async fn send_transaction(&self, tx: &Transaction) -> io::Result<Txid> {
+		match self {
+			ChainSource::Bitcoind(client) => client.send_transaction(tx).await,
+			ChainSource::Esplora(client) => client.send_transaction(tx).await,
+			ChainSource::Electrum(client) => client.send_transaction(tx).await,
+		}
+	}
+
+	/// Whether `outpoint`, which pays to `script_pubkey`, is spent by a confirmed transaction
+	pub async fn is_spent_on_chain(
+		&self, outpoint: &BitcoinOutPoint, script_pubkey: &Script,
+	) -> io::Result<bool> {
+		match self {
+			// Outputs spent by a confirmed transaction are gone from bitcoind's UTXO set
+			ChainSource::Bitcoind(client) => {
+				Ok(client.get_tx_out(outpoint, false).await?.is_none())
+			}
+			ChainSource::Esplora(client) => {
+				match client.get_output_spend(&outpoint.txid, outpoint.vout as u16).await? {
+					Some(txid) => Ok(client.get_tx_confirmation(&txid).await?.is_some()),
+					None => Ok(false),
+				}
+			}
+			ChainSource::Electrum(client) => {
+				match client.get_output_spend(outpoint, script_pubkey).await? {
+					Some(txid) => Ok(client.get_tx_confirmation(&txid).await?.is_some()),
+					None => Ok(false),
+				}
+			}
+		}
+	}
+
+	/// Whether the transaction creating `outpoint` is confirmed
+	pub async fn is_output_confirmed(&self, outpoint: &BitcoinOutPoint) -> io::Result<bool> {
+		match self {
+			ChainSource::Bitcoind(client) => Ok(client
+				.get_tx_out(outpoint, false)
+				.await?
+				.map_or(false, |txout| txout.confirmations > 0)),
+			ChainSource::Esplora(client) => {
+				Ok(client.get_tx_confirmation(&outpoint.txid).await?.is_some())
+			}
+			ChainSource::Electrum(client) => {
+				Ok(client.get_tx_confirmation(&outpoint.txid).await?.is_some())
+			}
+		}
+	}
+
+	/// Cached fee estimates, in satoshis per KW, by confirmation target. bitcoind estimates fees
+	/// for every target from 2 to 1008 blocks, the chain servers only for those LDK uses.
+	pub fn fee_estimates(&self) -> Vec<(u16, u32)> {
+		match self {
+			ChainSource::Bitcoind(client) => client.fee_estimates(),
+			ChainSource::Esplora(client) => client.fee_estimates(),
+			ChainSource::Electrum(client) => client.fee_estimates(),
+		}
+	}
+
+	/// Estimate the feerate, in satoshis per KW, for a transaction to confirm within
+	/// `conf_target` blocks, from the estimate of the closest target not above it, or of the
+	/// lowest target
+	pub fn estimate_fee_sat_per_kw(&self, conf_target: u16) -> io::Result<u32> {
+		if let ChainSource::Bitcoind(client) = self {
+			return client.estimate_fee_sat_per_kw(conf_target);
+		}
+		let estimates = self.fee_estimates();
+		match estimates.iter().rev().find(|(target, _)| *target <= conf_target) {
+			Some((_, feerate)) => Ok(*feerate),
+			None => estimates.first().map(|(_, feerate)| *feerate).ok_or_else(|| {
+				io::Error::new(io::ErrorKind::Other, "no fee estimate available yet")
+			}),
+		}
+	}
+}
+
+impl FeeEstimator for ChainSource {
+	fn get_est_sat_per_1000_weight(&self, confirmation_target: ConfirmationTarget) -> u32 {
+		match self {
+			ChainSource::Bitcoind(client) => {
+				client.get_est_sat_per_1000_weight(confirmation_target)
+			}
+			ChainSource::Esplora(client) => client.get_est_sat_per_1000_weight(confirmation_target),
+			ChainSource::Electrum(client) => {
+				client.get_est_sat_per_1000_weight(confirmation_target)
+			}
+		}
+	}
+}
+
+impl BroadcasterInterface for ChainSource {
+	fn broadcast_transaction(&self, tx: &Transaction) {
+		match self {
+			ChainSource::Bitcoind(client) => client.broadcast_transaction(tx),
+			ChainSource::Esplora(client) => client.broadcast_transaction(tx),
+			ChainSource::Electrum(client) => client.broadcast_transaction(tx),
+		}
+	}
+}
+
+/// An unspent output found in a transaction index, confirmed at `height` if any
+pub struct ScriptUtxo {
+	pub outpoint: BitcoinOutPoint,
+	pub value: u64,
+	pub height: Option<u32>,
+}
+
+/// Server indexing transactions, which LDK is synced from by `TxSync`
+#[derive(Clone)]
+pub enum TxIndex {
+	Esplora(Arc<EsploraClient>),
+	Electrum(Arc<ElectrumClient>),
+}
+
+impl TxIndex {
+	/// Hash and height of the chain tip
+	pub async fn get_tip(&self) -> io::Result<(BlockHash, u32)> {
+		match self {
+			TxIndex::Esplora(client) => client.get_tip().await,
+			TxIndex::Electrum(client) => client.get_tip().await,
+		}
+	}
+
+	/// Hash of the block at `height` of the best chain
+	pub async fn get_block_hash(&self, height: u32) -> io::Result<BlockHash> {
+		match self {
+			TxIndex::Esplora(client) => client.get_block_hash(height).await,
+			TxIndex::Electrum(client) => client.get_block_hash(height).await,
+		}
+	}
+
+	/// Unspent outputs paying to `script_pubkey`, including those of mempool transactions
+	pub async fn get_script_utxos(&self, script_pubkey: &Script) -> io::Result<Vec<ScriptUtxo>> {
+		match self {
+			TxIndex::Esplora(client) => client.get_script_utxos(script_pubkey).await,
+			TxIndex::Electrum(client) => client.get_script_utxos(script_pubkey).await,
+		}
+	}
+
+	/// Whether any transaction pays to or spends from `script_pubkey`
+	pub async fn has_history(&self, script_pubkey: &Script) -> io::Result<bool> {
+		match self {
+			TxIndex::Esplora(client) => client.has_history(script_pubkey).await,
+			TxIndex::Electrum(client) => client.has_history(script_pubkey).await,
+		}
+	}
+
+	async fn get_header(&self, hash: &BlockHash, height: u32) -> io::Result<BlockHeader> {
+		match self {
+			TxIndex::Esplora(client) => client.get_header(hash).await,
+			TxIndex::Electrum(client) => client.get_header(hash, height).await,
+		}
+	}
+
+	async fn get_tx(&self, txid: &Txid) -> io::Result<Option<Transaction>> {
+		match self {
+			TxIndex::Esplora(client) => client.get_tx(txid).await,
+			TxIndex::Electrum(client) => client.get_tx(txid).await,
+		}
+	}
+
+	async fn get_tx_confirmation(&self, txid: &Txid) -> io::Result<Option<(BlockHash, u32)>> {
+		match self {
+			TxIndex::Esplora(client) => client.get_tx_confirmation(txid).await,
+			TxIndex::Electrum(client) => client.get_tx_confirmation(txid).await,
+		}
+	}
+
+	async fn get_tx_position(&self, txid: &Txid, height: u32) -> io::Result<usize> {
+		match self {
+			TxIndex::Esplora(client) => client.get_tx_position(txid).await,
+			TxIndex::Electrum(client) => client.get_tx_position(txid, height).await,
+		}
+	}
+
+	async fn get_output_spend(
+		&self, outpoint: &OutPoint, script_pubkey: &Script,
+	) -> io::Result<Option<Txid>> {
+		match self {
+			TxIndex::Esplora(client) => {
+				client.get_output_spend(&outpoint.txid, outpoint.index).await
+			}
+			TxIndex::Electrum(client) => {
+				client.get_output_spend(&outpoint.into_bitcoin_outpoint(), script_pubkey).await
+			}
+		}
+	}
+}
+
+pub(crate) fn parse_hash<T: FromStr>(hash: &str) -> io::Result<T> {
+	T::from_str(hash.trim())
+		.map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("invalid hash {}", hash)))
+}
+
+/// Deserialize a block header or transaction served in hex
+pub(crate) fn deserialize_hex<T: encode::Decodable>(data: &str) -> io::Result<T> {
+	hex_utils::to_vec(data.trim())
+		.and_then(|bytes| encode::deserialize(&bytes).ok())
+		.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid hex data"))
+}
+
+/// Syncs LDK from an Esplora or Electrum server through `chain::Confirm`, as neither serves the
+/// chain work `lightning-block-sync` needs to follow blocks.
+///
+/// LDK registers the transactions and outputs it watches through `chain::Filter`. Each sync
+/// unconfirms the transactions reorged out of the chain, confirms the watched transactions and
+/// those spending watched outputs, then moves LDK to the chain tip.
+pub struct TxSync {
+	index: TxIndex,
+	watched: Mutex<WatchedTxs>,
+}
+
+#[derive(Default)]
+struct WatchedTxs {
+	txs: HashSet<Txid>,
+	/// Watched outputs and their scripts, which Electrum servers index spends by
+	outputs: HashMap<OutPoint, Script>,
+	/// Block each transaction was confirmed in, as last given to LDK
+	confirmed: HashMap<Txid, BlockHash>,
+	/// Whether LDK registered a transaction or output since the last sync started
+	registered: bool,
+	synced_tip: Option<BlockHash>,
+}
+
+/// A transaction confirmed in a block
+struct Confirmation {
+	block_hash: BlockHash,
+	height: u32,
+	pos: usize,
+	tx: Transaction,
+}
+
+impl TxSync {
+	pub(crate) fn new(index: TxIndex) -> Self {
+		Self { index, watched: Mutex::new(WatchedTxs::default()) }
+	}
+
+	/// Sync `confirmables`, i.e. the ChannelManager and ChainMonitor, to the chain tip
+	pub(crate) async fn sync(&self, confirmables: &[&(dyn Confirm + Sync)]) -> io::Result<()> {
+		loop {
+			let (tip_hash, tip_height) = self.index.get_tip().await?;
+			{
+				let mut watched = self.watched.lock().unwrap();
+				// Watched transactions only confirm in new blocks
+				if watched.synced_tip == Some(tip_hash) && !watched.registered {
+					return Ok(());
+				}
+				watched.registered = false;
+			}
+
+			// Unconfirm the transactions of blocks which were reorged out of the chain
+			let mut relevant_txids = HashSet::new();
+			for confirmable in confirmables {
+				relevant_txids.extend(confirmable.get_relevant_txids());
+			}
+			for txid in relevant_txids {
+				let block_hash =
+					self.index.get_tx_confirmation(&txid).await?.map(|(block_hash, _)| block_hash);
+				let confirmed_in = self.watched.lock().unwrap().confirmed.get(&txid).copied();
+				if block_hash.is_none() || (confirmed_in.is_some() && block_hash != confirmed_in) {
+					for confirmable in confirmables {
+						confirmable.transaction_unconfirmed(&txid);
+					}
+					self.watched.lock().unwrap().confirmed.remove(&txid);
+				}
+			}
+
+			// Confirm the watched transactions and those spending watched outputs
+			let (txs, outputs) = {
+				let watched = self.watched.lock().unwrap();
+				let txs: Vec<Txid> = watched
+					.txs
+					.iter()
+					.filter(|txid| !watched.confirmed.contains_key(*txid))
+					.copied()
+					.collect();
+				(txs, watched.outputs.clone())
+			};
+			let mut unconfirmed_txids = HashSet::new();
+			unconfirmed_txids.extend(txs);
+			for (outpoint, script_pubkey) in outputs {
+				if let Some(txid) = self.index.get_output_spend(&outpoint, &script_pubkey).await? {
+					if !self.watched.lock().unwrap().confirmed.contains_key(&txid) {
+						unconfirmed_txids.insert(txid);
+					}
+				}
+			}
+			let mut confirmations = Vec::new();
+			for txid in unconfirmed_txids {
+				let (block_hash, height) = match self.index.get_tx_confirmation(&txid).await? {
+					Some(confirmation) => confirmation,
+					None => continue,
+				};
+				// Blocks past the tip are confirmed by the next sync, as LDK drops confirmations
+				// above its best block
+				if height > tip_height {
+					continue;
+				}
+				let tx = match self.index.get_tx(&txid).await? {
+					Some(tx) => tx,
+					None => continue,
+				};
+				let pos = self.index.get_tx_position(&txid, height).await?;
+				confirmations.push(Confirmation { block_hash, height, pos, tx });
+			}
+			// Transactions are confirmed in chain order
+			confirmations.sort_by_key(|confirmation| (confirmation.height, confirmation.pos));
+			for confirmation in confirmations {
+				let header =
+					self.index.get_header(&confirmation.block_hash, confirmation.height).await?;
+				for confirmable in confirmables {
+					confirmable.transactions_confirmed(
+						&header,
+						&[(confirmation.pos, &confirmation.tx)],
+						confirmation.height,
+					);
+				}
+				self.watched
+					.lock()
+					.unwrap()
+					.confirmed
+					.insert(confirmation.tx.txid(), confirmation.block_hash);
+			}
+
+			let tip_header = self.index.get_header(&tip_hash, tip_height).await?;
+			for confirmable in confirmables {
+				confirmable.best_block_updated(&tip_header, tip_height);
+			}
+			let mut watched = self.watched.lock().unwrap();
+			watched.synced_tip = Some(tip_hash);
+			// Confirmed transactions may have had LDK watch more outputs, which are synced now
+			if !watched.registered {
+				return Ok(());
+			}
+		}
+	}
+}
+
+impl Filter for TxSync {
+	fn register_tx(&self, txid: &Txid, _script_pubkey: &Script) {
+		let mut watched = self.watched.lock().unwrap();
+		watched.txs.insert(*txid);
+		watched.registered = true;
+	}
+
+	fn register_output(&self, output: WatchedOutput) -> Option<(usize, Transaction)> {
+		let mut watched = self.watched.lock().unwrap();
+		watched.outputs.insert(output.outpoint, output.script_pubkey);
+		watched.registered = true;
+		// Spends of the output are confirmed by the next sync
+		None
+	}
+}
@@ -1,6 +1,15 @@
 #![allow(unused_variables, unused_assignments, dead_code)]
+use crate::backup;
 use crate::disk;
 use crate::hex_utils;
+use crate::keys::NodeKeysManager;
+use crate::node_var::{unix_timestamp, ChannelLimits, ChannelPolicy};
+use crate::peers::{PeerAddr, PeerDirection, PeerTracker};
+use crate::seed;
+use crate::settings;
+use crate::settings::Settings;
+use crate::storage::{NodeStore, StorageBackend};
+use crate::tor;
 use crate::{
 	ChannelManager, HTLCStatus, InvoicePayer, MillisatAmount, PaymentInfo, PaymentInfoStorage,
 	PeerManager,
@@ -9,8 +18,7 @@ use bitcoin::hashes::sha256::Hash as Sha256;
 use bitcoin::hashes::Hash;
 use bitcoin::network::constants::Network;
 use bitcoin::secp256k1::key::PublicKey;
-use lightning::chain::keysinterface::{KeysInterface, KeysManager, Recipient};
-use lightning::ln::msgs::NetAddress;
+use lightning::chain::keysinterface::{KeysInterface, Recipient};
 use lightning::ln::{PaymentHash, PaymentPreimage};
 use lightning::routing::network_graph::{NetworkGraph, NodeId};
 use lightning::util::config::{ChannelConfig, ChannelHandshakeLimits, UserConfig};
@@ -21,12 +29,12 @@ use lightning_invoice::{utils, Currency, Invoice};
 use std::env;
 use std::io;
 use std::io::{BufRead, Write};
-use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::net::{IpAddr, SocketAddr};
 use std::ops::Deref;
-use std::path::Path;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
+use tracing::{error, info};
 
 pub(crate) struct LdkUserInfo {
 	pub(crate) bitcoind_rpc_username: String,
@@ -35,17 +43,55 @@ pub(crate) struct LdkUserInfo {
 	pub(crate) bitcoind_rpc_host: String,
 	pub(crate) ldk_storage_dir_path: String,
 	pub(crate) ldk_peer_listening_port: u16,
-	pub(crate) ldk_announced_listen_addr: Vec<NetAddress>,
-	pub(crate) ldk_announced_node_name: [u8; 32],
 	pub(crate) network: Network,
+	pub(crate) storage_backend: StorageBackend,
+	pub(crate) recover_from: Option<String>,
+	pub(crate) mnemonic: Option<String>,
+	pub(crate) mnemonic_passphrase: String,
+	pub(crate) encrypt_secrets: bool,
 }
 
-pub(crate) fn parse_startup_args() -> Result<LdkUserInfo, ()> {
-	if env::args().len() < 3 {
-		println!("ldk-tutorial-node requires 3 arguments: `cargo run <bitcoind-rpc-username>:<bitcoind-rpc-password>@<bitcoind-rpc-host>:<bitcoind-rpc-port> ldk_storage_directory_path [<ldk-incoming-peer-listening-port>] [bitcoin-network] [announced-node-name announced-listen-addr*]`");
+/// Startup flags, each overriding a setting of the config file
+const SETTING_FLAGS: [(&str, &str); 8] = [
+	("--datadir", "node.data_dir"),
+	("--network", "node.network"),
+	("--port", "node.listening_port"),
+	("--bitcoind-rpc-host", "bitcoind.rpc_host"),
+	("--bitcoind-rpc-port", "bitcoind.rpc_port"),
+	("--bitcoind-rpc-username", "bitcoind.rpc_username"),
+	("--bitcoind-rpc-password", "bitcoind.rpc_password"),
+	("--alias", "announcement.alias"),
+];
+
+const USAGE: &str = "lnnode takes its settings from a config file, `LNNODE_`-prefixed environment variables and flags: `cargo run -- [--config <path>] [--datadir <ldk_storage_directory_path>] [--network <network>] [--port <ldk-incoming-peer-listening-port>] [--bitcoind-rpc-host <host>] [--bitcoind-rpc-port <port>] [--bitcoind-rpc-username <username>] [--bitcoind-rpc-password <password>] [--alias <announced-node-name>]`. The arguments of previous versions are still accepted: `cargo run <bitcoind-rpc-username>:<bitcoind-rpc-password>@<bitcoind-rpc-host>:<bitcoind-rpc-port> ldk_storage_directory_path [<ldk-incoming-peer-listening-port>] [bitcoin-network] [announced-node-name announced-listen-addr*]`";
+
+/// Remove `flag` and its value from `args`, failing if the value is missing
+fn take_arg_value(
+	args: &mut Vec<String>, flag: &str, value_name: &str,
+) -> Result<Option<String>, ()> {
+	match args.iter().position(|arg| arg == flag) {
+		Some(idx) if idx + 1 < args.len() => {
+			args.remove(idx);
+			Ok(Some(args.remove(idx)))
+		}
+		Some(_) => {
+			println!("ERROR: {} requires {}", flag, value_name);
+			Err(())
+		}
+		None => Ok(None),
+	}
+}
+
+/// Parse the positional arguments of previous versions into setting overrides, returning the
+/// announced listen addresses they give
+fn parse_positional_args(
+	args: &[String], overrides: &mut Vec<(String, String)>,
+) -> Result<Vec<String>, ()> {
+	if args.len() < 3 {
+		println!("{}", USAGE);
 		return Err(());
 	}
-	let bitcoind_rpc_info = env::args().skip(1).next().unwrap();
+	let bitcoind_rpc_info = args.get(1).cloned().unwrap();
 	let bitcoind_rpc_info_parts: Vec<&str> = bitcoind_rpc_info.rsplitn(2, "@").collect();
 	if bitcoind_rpc_info_parts.len() != 2 {
 		println!("ERROR: bad bitcoind RPC URL provided");
@@ -56,96 +102,153 @@ pub(crate) fn parse_startup_args() -> Result<LdkUserInfo, ()> {
 		println!("ERROR: bad bitcoind RPC username/password combo provided");
 		return Err(());
 	}
-	let bitcoind_rpc_username = rpc_user_and_password[0].to_string();
-	let bitcoind_rpc_password = rpc_user_and_password[1].to_string();
 	let bitcoind_rpc_path: Vec<&str> = bitcoind_rpc_info_parts[0].split(":").collect();
 	if bitcoind_rpc_path.len() != 2 {
 		println!("ERROR: bad bitcoind RPC path provided");
 		return Err(());
 	}
-	let bitcoind_rpc_host = bitcoind_rpc_path[0].to_string();
-	let bitcoind_rpc_port = bitcoind_rpc_path[1].parse::<u16>().unwrap();
-
-	let ldk_storage_dir_path = env::args().skip(2).next().unwrap();
+	let mut set = |key: &str, value: &str| overrides.push((key.to_string(), value.to_string()));
+	set("bitcoind.rpc_username", rpc_user_and_password[0]);
+	set("bitcoind.rpc_password", rpc_user_and_password[1]);
+	set("bitcoind.rpc_host", bitcoind_rpc_path[0]);
+	set("bitcoind.rpc_port", bitcoind_rpc_path[1]);
+	set("node.data_dir", &args[2]);
+
+	let mut arg_idx = 3;
+	let mut ldk_peer_listening_port = 9735;
+	if let Some(Ok(port)) = args.get(arg_idx).map(|p| p.parse::<u16>()) {
+		ldk_peer_listening_port = port;
+		set("node.listening_port", &args[arg_idx]);
+		arg_idx += 1;
+	}
+	if let Some(network) = args.get(arg_idx) {
+		set("node.network", network);
+	}
+	if let Some(alias) = args.get(arg_idx + 1) {
+		set("announcement.alias", alias);
+	}
 
-	let mut ldk_peer_port_set = true;
-	let ldk_peer_listening_port: u16 = match env::args().skip(3).next().map(|p| p.parse()) {
-		Some(Ok(p)) => p,
-		Some(Err(_)) => {
-			ldk_peer_port_set = false;
-			9735
+	let mut ldk_announced_listen_addr = Vec::new();
+	for addr in args.iter().skip(arg_idx + 2) {
+		match IpAddr::from_str(addr) {
+			Ok(ip) => ldk_announced_listen_addr
+				.push(SocketAddr::new(ip, ldk_peer_listening_port).to_string()),
+			Err(_) => panic!("Failed to parse announced-listen-addr into an IP address"),
 		}
-		None => {
-			ldk_peer_port_set = false;
-			9735
+	}
+	Ok(ldk_announced_listen_addr)
+}
+
+pub(crate) fn parse_startup_args() -> Result<(LdkUserInfo, Settings), ()> {
+	let mut args: Vec<String> = env::args().collect();
+	// Recovery restores a channel backup into a node which lost its data
+	let recover_from = take_arg_value(&mut args, "--recover", "the path of a channel backup")?;
+	// The keys seed of a new node can be restored from its mnemonic, quoted as a single argument
+	let mnemonic = take_arg_value(&mut args, "--mnemonic", "a quoted BIP39 mnemonic")?;
+	let mnemonic_passphrase = env::var(seed::MNEMONIC_PASSPHRASE_ENV).unwrap_or_default();
+	// Prompt for a password to encrypt the secrets of a node which has none yet
+	let args_len = args.len();
+	args.retain(|arg| arg != "--encrypt");
+	let encrypt_secrets = args.len() != args_len;
+
+	let config_path = take_arg_value(&mut args, "--config", "the path of a config file")?;
+	let mut overrides = Vec::new();
+	for (flag, key) in SETTING_FLAGS {
+		if let Some(value) = take_arg_value(&mut args, flag, "a value")? {
+			overrides.push((key.to_string(), value));
 		}
+	}
+	let legacy_listen_addr =
+		if args.len() > 1 { parse_positional_args(&args, &mut overrides)? } else { Vec::new() };
+
+	// The data directory locates the config file unless it is given explicitly
+	let data_dir = match overrides.iter().find(|(key, _)| key == "node.data_dir") {
+		Some((_, data_dir)) => Some(data_dir.clone()),
+		None => env::var(format!("{}_NODE__DATA_DIR", settings::ENV_PREFIX)).ok(),
 	};
+	let mut settings =
+		match settings::load_settings(config_path.as_deref(), data_dir.as_deref(), &overrides) {
+			Ok(settings) => settings,
+			Err(e) => {
+				println!("ERROR: invalid config file: {}", e);
+				return Err(());
+			}
+		};
+	if !legacy_listen_addr.is_empty() {
+		settings.announcement.addresses = legacy_listen_addr;
+	}
 
-	let mut arg_idx = match ldk_peer_port_set {
-		true => 4,
-		false => 3,
+	let ldk_storage_dir_path = match &settings.node.data_dir {
+		Some(data_dir) => data_dir.clone(),
+		None => {
+			println!("ERROR: the data directory isn't set. {}", USAGE);
+			return Err(());
+		}
 	};
-	let network: Network = match env::args().skip(arg_idx).next().as_ref().map(String::as_str) {
-		Some("testnet") => Network::Testnet,
-		Some("regtest") => Network::Regtest,
-		Some("signet") => Network::Signet,
-		Some(net) => {
-			panic!("Unsupported network provided. Options are: `regtest`, `testnet`, and `signet`. Got {}", net);
-		}
-		None => Network::Testnet,
+	let network: Network = match settings.node.network.as_str() {
+		"testnet" => Network::Testnet,
+		"regtest" => Network::Regtest,
+		"signet" => Network::Signet,
+		net => {
+			println!("ERROR: unsupported network provided. Options are: `regtest`, `testnet`, and `signet`. Got {}", net);
+			return Err(());
+		}
 	};
-
-	let ldk_announced_node_name = match env::args().skip(arg_idx + 1).next().as_ref() {
-		Some(s) => {
-			if s.len() > 32 {
-				panic!("Node Alias can not be longer than 32 bytes");
+	let (bitcoind_rpc_username, bitcoind_rpc_password) =
+		match (&settings.bitcoind.rpc_username, &settings.bitcoind.rpc_password) {
+			(Some(username), Some(password)) => (username.clone(), password.clone()),
+			// bitcoind is only connected to when LDK is synced from it
+			_ if !matches!(settings.chain.backend, settings::ChainBackend::Bitcoind) => {
+				(String::new(), String::new())
+			}
+			_ => {
+				println!("ERROR: the bitcoind RPC username and password aren't set. {}", USAGE);
+				return Err(());
 			}
-			arg_idx += 1;
-			let mut bytes = [0; 32];
-			bytes[..s.len()].copy_from_slice(s.as_bytes());
-			bytes
+		};
+	// Default to bitcoind's RPC port on the network
+	let bitcoind_rpc_port = settings.bitcoind.rpc_port.unwrap_or(match network {
+		Network::Bitcoin => 8332,
+		Network::Testnet => 18332,
+		Network::Regtest => 18443,
+		Network::Signet => 38332,
+	});
+
+	let storage_backend = match env::var("LNNODE_STORAGE_BACKEND").as_ref().map(String::as_str) {
+		Ok("filesystem") | Err(_) => StorageBackend::Filesystem,
+		Ok("sqlite") => StorageBackend::Sqlite,
+		Ok(backend) => {
+			println!(
+				"ERROR: unsupported storage backend {}. Options are: `filesystem` and `sqlite`",
+				backend
+			);
+			return Err(());
 		}
-		None => [0; 32],
 	};
 
-	let mut ldk_announced_listen_addr = Vec::new();
-	loop {
-		match env::args().skip(arg_idx + 1).next().as_ref() {
-			Some(s) => match IpAddr::from_str(s) {
-				Ok(IpAddr::V4(a)) => {
-					ldk_announced_listen_addr
-						.push(NetAddress::IPv4 { addr: a.octets(), port: ldk_peer_listening_port });
-					arg_idx += 1;
-				}
-				Ok(IpAddr::V6(a)) => {
-					ldk_announced_listen_addr
-						.push(NetAddress::IPv6 { addr: a.octets(), port: ldk_peer_listening_port });
-					arg_idx += 1;
-				}
-				Err(_) => panic!("Failed to parse announced-listen-addr into an IP address"),
-			},
-			None => break,
-		}
-	}
-
-	Ok(LdkUserInfo {
+	let user_info = LdkUserInfo {
 		bitcoind_rpc_username,
 		bitcoind_rpc_password,
-		bitcoind_rpc_host,
+		bitcoind_rpc_host: settings.bitcoind.rpc_host.clone(),
 		bitcoind_rpc_port,
 		ldk_storage_dir_path,
-		ldk_peer_listening_port,
-		ldk_announced_listen_addr,
-		ldk_announced_node_name,
+		ldk_peer_listening_port: settings.node.listening_port,
 		network,
-	})
+		storage_backend,
+		recover_from,
+		mnemonic,
+		mnemonic_passphrase,
+		encrypt_secrets,
+	};
+	Ok((user_info, settings))
 }
 
 pub(crate) async fn poll_for_user_input<E: EventHandler>(
 	invoice_payer: Arc<InvoicePayer<E>>, peer_manager: Arc<PeerManager>,
-	channel_manager: Arc<ChannelManager>, keys_manager: Arc<KeysManager>,
+	channel_manager: Arc<ChannelManager>, keys_manager: Arc<NodeKeysManager>,
 	network_graph: Arc<NetworkGraph>, inbound_payments: PaymentInfoStorage,
-	outbound_payments: PaymentInfoStorage, ldk_data_dir: String, network: Network,
+	outbound_payments: PaymentInfoStorage, store: Arc<dyn NodeStore>, network: Network,
+	socks_proxy: Option<SocketAddr>, peer_tracker: Arc<PeerTracker>, ldk_data_dir: String,
 ) {
 	println!("LDK startup successful. To view available commands: \"help\".");
 	println!("LDK logs are available at <your-supplied-ldk-data-dir-path>/.ldk/logs");
@@ -186,9 +289,15 @@ pub(crate) async fn poll_for_user_input<E: EventHandler>(
 						continue;
 					}
 
-					if connect_peer_if_necessary(pubkey, peer_addr, peer_manager.clone())
-						.await
-						.is_err()
+					if connect_peer_if_necessary(
+						pubkey,
+						peer_addr.clone(),
+						peer_manager.clone(),
+						socks_proxy,
+						&peer_tracker,
+					)
+					.await
+					.is_err()
 					{
 						continue;
 					};
@@ -206,16 +315,15 @@ pub(crate) async fn poll_for_user_input<E: EventHandler>(
 					if open_channel(
 						pubkey,
 						chan_amt_sat.unwrap(),
+						0,
 						announce_channel,
+						&disk::read_channel_policy(&*store).ok().flatten().unwrap_or_default(),
+						&ChannelLimits::default(),
 						channel_manager.clone(),
 					)
 					.is_ok()
 					{
-						let peer_data_path = format!("{}/channel_peer_data", ldk_data_dir.clone());
-						let _ = disk::persist_channel_peer(
-							Path::new(&peer_data_path),
-							peer_pubkey_and_ip_addr,
-						);
+						let _ = disk::persist_channel_peer(&*store, &pubkey, &peer_addr);
 					}
 				}
 				"sendpayment" => {
@@ -233,7 +341,7 @@ pub(crate) async fn poll_for_user_input<E: EventHandler>(
 						}
 					};
 
-					send_payment(&*invoice_payer, &invoice, outbound_payments.clone());
+					send_payment(&*invoice_payer, &invoice, outbound_payments.clone()).await;
 				}
 				"keysend" => {
 					let dest_pubkey = match words.next() {
@@ -269,7 +377,8 @@ pub(crate) async fn poll_for_user_input<E: EventHandler>(
 						amt_msat,
 						&*keys_manager,
 						outbound_payments.clone(),
-					);
+					)
+					.await;
 				}
 				"getinvoice" => {
 					let amt_str = words.next();
@@ -289,7 +398,8 @@ pub(crate) async fn poll_for_user_input<E: EventHandler>(
 						channel_manager.clone(),
 						keys_manager.clone(),
 						network,
-					);
+					)
+					.await;
 				}
 				"connectpeer" => {
 					let peer_pubkey_and_ip_addr = words.next();
@@ -305,16 +415,22 @@ pub(crate) async fn poll_for_user_input<E: EventHandler>(
 								continue;
 							}
 						};
-					if connect_peer_if_necessary(pubkey, peer_addr, peer_manager.clone())
-						.await
-						.is_ok()
+					if connect_peer_if_necessary(
+						pubkey,
+						peer_addr,
+						peer_manager.clone(),
+						socks_proxy,
+						&peer_tracker,
+					)
+					.await
+					.is_ok()
 					{
 						println!("SUCCESS: connected to peer {}", pubkey);
 					}
 				}
 				"listchannels" => list_channels(&channel_manager, &network_graph),
 				"listpayments" => {
-					list_payments(inbound_payments.clone(), outbound_payments.clone())
+					list_payments(inbound_payments.clone(), outbound_payments.clone()).await
 				}
 				"closechannel" => {
 					let channel_id_str = words.next();
@@ -329,7 +445,9 @@ pub(crate) async fn poll_for_user_input<E: EventHandler>(
 					}
 					let mut channel_id = [0; 32];
 					channel_id.copy_from_slice(&channel_id_vec.unwrap());
-					close_channel(channel_id, channel_manager.clone());
+					if let Err(e) = close_channel(channel_id, channel_manager.clone()) {
+						println!("ERROR: failed to close channel: {:?}", e);
+					}
 				}
 				"forceclosechannel" => {
 					let channel_id_str = words.next();
@@ -344,7 +462,15 @@ pub(crate) async fn poll_for_user_input<E: EventHandler>(
 					}
 					let mut channel_id = [0; 32];
 					channel_id.copy_from_slice(&channel_id_vec.unwrap());
-					force_close_channel(channel_id, channel_manager.clone());
+					// As with the REST handler, a failed snapshot is reported but doesn't block the
+					// force-close.
+					match backup::snapshot(&ldk_data_dir, "forceclosechannel") {
+						Ok(snapshot_id) => println!("Created backup snapshot {}", snapshot_id),
+						Err(e) => println!("ERROR: failed to snapshot node state: {}", e),
+					}
+					if let Err(e) = force_close_channel(channel_id, channel_manager.clone()) {
+						println!("ERROR: failed to force-close channel: {:?}", e);
+					}
 				}
 				"nodeinfo" => node_info(&channel_manager, &peer_manager),
 				"listpeers" => list_peers(peer_manager.clone()),
@@ -456,9 +582,11 @@ fn list_channels(channel_manager: &Arc<ChannelManager>, network_graph: &Arc<Netw
 	println!("]");
 }
 
-fn list_payments(inbound_payments: PaymentInfoStorage, outbound_payments: PaymentInfoStorage) {
-	let inbound = inbound_payments.lock().unwrap();
-	let outbound = outbound_payments.lock().unwrap();
+async fn list_payments(
+	inbound_payments: PaymentInfoStorage, outbound_payments: PaymentInfoStorage,
+) {
+	let inbound = inbound_payments.read().await;
+	let outbound = outbound_payments.read().await;
 	print!("[");
 	for (payment_hash, payment_info) in inbound.deref() {
 		println!("");
@@ -499,26 +627,30 @@ fn list_payments(inbound_payments: PaymentInfoStorage, outbound_payments: Paymen
 }
 
 pub async fn connect_peer_if_necessary(
-	pubkey: PublicKey, peer_addr: SocketAddr, peer_manager: Arc<PeerManager>,
+	pubkey: PublicKey, peer_addr: PeerAddr, peer_manager: Arc<PeerManager>,
+	socks_proxy: Option<SocketAddr>, peer_tracker: &PeerTracker,
 ) -> Result<(), ()> {
 	for node_pubkey in peer_manager.get_peer_node_ids() {
 		if node_pubkey == pubkey {
 			return Ok(());
 		}
 	}
-	let res = do_connect_peer(pubkey, peer_addr, peer_manager).await;
+	let res = do_connect_peer(pubkey, peer_addr, peer_manager, socks_proxy, peer_tracker).await;
 	if res.is_err() {
-		println!("ERROR: failed to connect to peer");
+		error!("failed to connect to peer");
 	}
 	res
 }
 
+/// Connect to the peer at `peer_addr`, through the SOCKS5 proxy at `socks_proxy` if any
 pub(crate) async fn do_connect_peer(
-	pubkey: PublicKey, peer_addr: SocketAddr, peer_manager: Arc<PeerManager>,
+	pubkey: PublicKey, peer_addr: PeerAddr, peer_manager: Arc<PeerManager>,
+	socks_proxy: Option<SocketAddr>, peer_tracker: &PeerTracker,
 ) -> Result<(), ()> {
-	match lightning_net_tokio::connect_outbound(Arc::clone(&peer_manager), pubkey, peer_addr).await
-	{
-		Some(connection_closed_future) => {
+	match tor::connect(&peer_addr, socks_proxy).await {
+		Ok(stream) => {
+			let connection_closed_future =
+				lightning_net_tokio::setup_outbound(Arc::clone(&peer_manager), pubkey, stream);
 			let mut connection_closed_future = Box::pin(connection_closed_future);
 			loop {
 				match futures::poll!(&mut connection_closed_future) {
@@ -529,20 +661,26 @@ pub(crate) async fn do_connect_peer(
 				}
 				// Avoid blocking the tokio context by sleeping a bit
 				match peer_manager.get_peer_node_ids().iter().find(|id| **id == pubkey) {
-					Some(_) => return Ok(()),
+					Some(_) => {
+						peer_tracker.connected(pubkey, peer_addr, PeerDirection::Outbound);
+						return Ok(());
+					}
 					None => tokio::time::sleep(Duration::from_millis(10)).await,
 				}
 			}
 		}
-		None => Err(()),
+		Err(e) => {
+			error!("unable to connect to {}: {}", peer_addr, e);
+			Err(())
+		}
 	}
 }
 
 pub fn open_channel(
-	peer_pubkey: PublicKey, channel_amt_sat: u64, announced_channel: bool,
-	channel_manager: Arc<ChannelManager>,
-) -> Result<(), ()> {
-	let config = UserConfig {
+	peer_pubkey: PublicKey, channel_amt_sat: u64, push_msat: u64, announced_channel: bool,
+	policy: &ChannelPolicy, limits: &ChannelLimits, channel_manager: Arc<ChannelManager>,
+) -> Result<[u8; 32], ()> {
+	let mut config = UserConfig {
 		peer_channel_config_limits: ChannelHandshakeLimits {
 			// lnd's max to_self_delay is 2016, so we want to be compatible.
 			their_to_self_delay: 2016,
@@ -551,29 +689,31 @@ pub fn open_channel(
 		channel_options: ChannelConfig { announced_channel, ..Default::default() },
 		..Default::default()
 	};
+	policy.apply(&mut config);
+	limits.apply(&mut config);
 
-	match channel_manager.create_channel(peer_pubkey, channel_amt_sat, 0, 0, Some(config)) {
-		Ok(_) => {
-			println!("EVENT: initiated channel with peer {}. ", peer_pubkey);
-			return Ok(());
+	match channel_manager.create_channel(peer_pubkey, channel_amt_sat, push_msat, 0, Some(config)) {
+		Ok(temporary_channel_id) => {
+			info!("initiated channel with peer {}", peer_pubkey);
+			return Ok(temporary_channel_id);
 		}
 		Err(e) => {
-			println!("ERROR: failed to open channel: {:?}", e);
+			error!("failed to open channel: {:?}", e);
 			return Err(());
 		}
 	}
 }
 
-fn send_payment<E: EventHandler>(
+async fn send_payment<E: EventHandler>(
 	invoice_payer: &InvoicePayer<E>, invoice: &Invoice, payment_storage: PaymentInfoStorage,
 ) {
-	let status = match invoice_payer.pay_invoice(invoice) {
-		Ok(_payment_id) => {
+	let (status, payment_id) = match invoice_payer.pay_invoice(invoice) {
+		Ok(payment_id) => {
 			let payee_pubkey = invoice.recover_payee_pub_key();
 			let amt_msat = invoice.amount_milli_satoshis().unwrap();
 			println!("EVENT: initiated sending {} msats to {}", amt_msat, payee_pubkey);
 			print!("> ");
-			HTLCStatus::Pending
+			(HTLCStatus::Pending, Some(payment_id))
 		}
 		Err(PaymentError::Invoice(e)) => {
 			println!("ERROR: invalid invoice: {}", e);
@@ -588,13 +728,13 @@ fn send_payment<E: EventHandler>(
 		Err(PaymentError::Sending(e)) => {
 			println!("ERROR: failed to send payment: {:?}", e);
 			print!("> ");
-			HTLCStatus::Failed
+			(HTLCStatus::Failed, None)
 		}
 	};
 	let payment_hash = PaymentHash(invoice.payment_hash().clone().into_inner());
 	let payment_secret = Some(invoice.payment_secret().clone());
 
-	let mut payments = payment_storage.lock().unwrap();
+	let mut payments = payment_storage.write().await;
 	payments.insert(
 		payment_hash,
 		PaymentInfo {
@@ -602,26 +742,33 @@ fn send_payment<E: EventHandler>(
 			secret: payment_secret,
 			status,
 			amt_msat: MillisatAmount(invoice.amount_milli_satoshis()),
+			label: None,
+			payment_id,
+			fee_paid_msat: None,
+			created_at: Some(unix_timestamp()),
+			resolved_at: None,
+			paths: Vec::new(),
+			keysend: false,
 		},
 	);
 }
 
-fn keysend<E: EventHandler, K: KeysInterface>(
+async fn keysend<E: EventHandler, K: KeysInterface>(
 	invoice_payer: &InvoicePayer<E>, payee_pubkey: PublicKey, amt_msat: u64, keys: &K,
 	payment_storage: PaymentInfoStorage,
 ) {
 	let payment_preimage = keys.get_secure_random_bytes();
 
-	let status = match invoice_payer.pay_pubkey(
+	let (status, payment_id) = match invoice_payer.pay_pubkey(
 		payee_pubkey,
 		PaymentPreimage(payment_preimage),
 		amt_msat,
 		40,
 	) {
-		Ok(_payment_id) => {
+		Ok(payment_id) => {
 			println!("EVENT: initiated sending {} msats to {}", amt_msat, payee_pubkey);
 			print!("> ");
-			HTLCStatus::Pending
+			(HTLCStatus::Pending, Some(payment_id))
 		}
 		Err(PaymentError::Invoice(e)) => {
 			println!("ERROR: invalid payee: {}", e);
@@ -636,11 +783,11 @@ fn keysend<E: EventHandler, K: KeysInterface>(
 		Err(PaymentError::Sending(e)) => {
 			println!("ERROR: failed to send payment: {:?}", e);
 			print!("> ");
-			HTLCStatus::Failed
+			(HTLCStatus::Failed, None)
 		}
 	};
 
-	let mut payments = payment_storage.lock().unwrap();
+	let mut payments = payment_storage.write().await;
 	payments.insert(
 		PaymentHash(Sha256::hash(&payment_preimage).into_inner()),
 		PaymentInfo {
@@ -648,15 +795,22 @@ fn keysend<E: EventHandler, K: KeysInterface>(
 			secret: None,
 			status,
 			amt_msat: MillisatAmount(Some(amt_msat)),
+			label: None,
+			payment_id,
+			fee_paid_msat: None,
+			created_at: Some(unix_timestamp()),
+			resolved_at: None,
+			paths: Vec::new(),
+			keysend: false,
 		},
 	);
 }
 
-pub fn get_invoice(
+pub async fn get_invoice(
 	amt_msat: u64, payment_storage: PaymentInfoStorage, channel_manager: Arc<ChannelManager>,
-	keys_manager: Arc<KeysManager>, network: Network,
+	keys_manager: Arc<NodeKeysManager>, network: Network,
 ) {
-	let mut payments = payment_storage.lock().unwrap();
+	let mut payments = payment_storage.write().await;
 	let currency = match network {
 		Network::Bitcoin => Currency::Bitcoin,
 		Network::Testnet => Currency::BitcoinTestnet,
@@ -688,6 +842,13 @@ pub fn get_invoice(
 			secret: Some(invoice.payment_secret().clone()),
 			status: HTLCStatus::Pending,
 			amt_msat: MillisatAmount(Some(amt_msat)),
+			label: None,
+			payment_id: None,
+			fee_paid_msat: None,
+			created_at: Some(unix_timestamp()),
+			resolved_at: None,
+			paths: Vec::new(),
+			keysend: false,
 		},
 	);
 }
@@ -697,11 +858,11 @@ pub fn close_channel(
 ) -> Result<(), APIError> {
 	match channel_manager.close_channel(&channel_id) {
 		Ok(()) => {
-			println!("EVENT: initiating channel close");
+			info!("initiating channel close");
 			Ok(())
 		}
 		Err(e) => {
-			println!("ERROR: failed to close channel: {:?}", e);
+			error!("failed to close channel: {:?}", e);
 			Err(e)
 		}
 	}
@@ -712,19 +873,20 @@ pub fn force_close_channel(
 ) -> Result<(), APIError> {
 	match channel_manager.force_close_channel(&channel_id) {
 		Ok(()) => {
-			println!("EVENT: initiating channel force-close");
+			info!("initiating channel force-close");
 			Ok(())
 		}
 		Err(e) => {
-			println!("ERROR: failed to force-close channel: {:?}", e);
+			error!("failed to force-close channel: {:?}", e);
 			Err(e)
 		}
 	}
 }
 
+/// Parse `pubkey@host:port`. Host names are only resolved when connecting to the peer.
 pub fn parse_peer_info(
 	peer_pubkey_and_ip_addr: String,
-) -> Result<(PublicKey, SocketAddr), std::io::Error> {
+) -> Result<(PublicKey, PeerAddr), std::io::Error> {
 	let mut pubkey_and_addr = peer_pubkey_and_ip_addr.split("@");
 	let pubkey = pubkey_and_addr.next();
 	let peer_addr_str = pubkey_and_addr.next();
@@ -735,13 +897,15 @@ pub fn parse_peer_info(
 		));
 	}
 
-	let peer_addr = peer_addr_str.unwrap().to_socket_addrs().map(|mut r| r.next());
-	if peer_addr.is_err() || peer_addr.as_ref().unwrap().is_none() {
-		return Err(std::io::Error::new(
-			std::io::ErrorKind::Other,
-			"ERROR: couldn't parse pubkey@host:port into a socket address",
-		));
-	}
+	let peer_addr = match peer_addr_str.unwrap().parse::<PeerAddr>() {
+		Ok(peer_addr) => peer_addr,
+		Err(e) => {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::Other,
+				format!("ERROR: couldn't parse pubkey@host:port: {}", e),
+			))
+		}
+	};
 
 	let pubkey = hex_utils::to_compressed_pubkey(pubkey.unwrap());
 	if pubkey.is_none() {
@@ -751,5 +915,5 @@ pub fn parse_peer_info(
 		));
 	}
 
-	Ok((pubkey.unwrap(), peer_addr.unwrap().unwrap()))
+	Ok((pubkey.unwrap(), peer_addr))
 }
@@ -1,5 +1,5 @@
 use bitcoin::hashes::hex::FromHex;
-use bitcoin::BlockHash;
+use bitcoin::{BlockHash, OutPoint, Txid};
 use lightning_block_sync::http::JsonResponse;
 use std::convert::TryInto;
 
@@ -91,3 +91,122 @@ impl TryInto<BlockchainInfo> for JsonResponse {
 		})
 	}
 }
+
+pub struct Balances {
+	pub trusted_sat: u64,
+	pub untrusted_pending_sat: u64,
+	pub immature_sat: u64,
+}
+
+impl TryInto<Balances> for JsonResponse {
+	type Error = std::io::Error;
+	fn try_into(self) -> std::io::Result<Balances> {
+		// Bitcoin Core reports balances in BTC, which we convert to satoshis.
+		let to_sat = |btc: &serde_json::Value| match btc.as_f64() {
+			Some(btc) => Ok((btc * 100_000_000.0).round() as u64),
+			None => Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidData,
+				"invalid balance in getbalances response",
+			)),
+		};
+		Ok(Balances {
+			trusted_sat: to_sat(&self.0["mine"]["trusted"])?,
+			untrusted_pending_sat: to_sat(&self.0["mine"]["untrusted_pending"])?,
+			immature_sat: to_sat(&self.0["mine"]["immature"])?,
+		})
+	}
+}
+
+pub struct UnspentOutput {
+	pub txid: Txid,
+	pub vout: u32,
+	pub amount_sat: u64,
+	pub confirmations: u32,
+	pub spendable: bool,
+}
+
+pub struct ListUnspent(pub Vec<UnspentOutput>);
+
+impl TryInto<ListUnspent> for JsonResponse {
+	type Error = std::io::Error;
+	fn try_into(self) -> std::io::Result<ListUnspent> {
+		let invalid =
+			|| std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid listunspent response");
+		let mut utxos = Vec::new();
+		for utxo in self.0.as_array().ok_or_else(invalid)? {
+			utxos.push(UnspentOutput {
+				txid: Txid::from_hex(utxo["txid"].as_str().ok_or_else(invalid)?)
+					.map_err(|_| invalid())?,
+				vout: utxo["vout"].as_u64().ok_or_else(invalid)? as u32,
+				amount_sat: (utxo["amount"].as_f64().ok_or_else(invalid)? * 100_000_000.0).round()
+					as u64,
+				confirmations: utxo["confirmations"].as_u64().ok_or_else(invalid)? as u32,
+				spendable: utxo["spendable"].as_bool().unwrap_or(false),
+			});
+		}
+		Ok(ListUnspent(utxos))
+	}
+}
+
+pub struct LockedOutpoints(pub Vec<OutPoint>);
+
+impl TryInto<LockedOutpoints> for JsonResponse {
+	type Error = std::io::Error;
+	fn try_into(self) -> std::io::Result<LockedOutpoints> {
+		let invalid = || {
+			std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid listlockunspent response")
+		};
+		let mut outpoints = Vec::new();
+		for outpoint in self.0.as_array().ok_or_else(invalid)? {
+			outpoints.push(OutPoint {
+				txid: Txid::from_hex(outpoint["txid"].as_str().ok_or_else(invalid)?)
+					.map_err(|_| invalid())?,
+				vout: outpoint["vout"].as_u64().ok_or_else(invalid)? as u32,
+			});
+		}
+		Ok(LockedOutpoints(outpoints))
+	}
+}
+
+pub struct TxOutInfo {
+	pub amount_sat: u64,
+	pub confirmations: u32,
+}
+
+/// `gettxout` returns null for spent outputs
+pub struct GetTxOut(pub Option<TxOutInfo>);
+
+impl TryInto<GetTxOut> for JsonResponse {
+	type Error = std::io::Error;
+	fn try_into(self) -> std::io::Result<GetTxOut> {
+		if self.0.is_null() {
+			return Ok(GetTxOut(None));
+		}
+		let invalid =
+			|| std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid gettxout response");
+		Ok(GetTxOut(Some(TxOutInfo {
+			amount_sat: (self.0["value"].as_f64().ok_or_else(invalid)? * 100_000_000.0).round()
+				as u64,
+			confirmations: self.0["confirmations"].as_u64().ok_or_else(invalid)? as u32,
+		})))
+	}
+}
+
+pub struct LockUnspent(pub bool);
+
+/// `abandontransaction` returns null on success
+pub struct AbandonedTx;
+
+impl TryInto<AbandonedTx> for JsonResponse {
+	type Error = std::io::Error;
+	fn try_into(self) -> std::io::Result<AbandonedTx> {
+		Ok(AbandonedTx)
+	}
+}
+
+impl TryInto<LockUnspent> for JsonResponse {
+	type Error = std::io::Error;
+	fn try_into(self) -> std::io::Result<LockUnspent> {
+		Ok(LockUnspent(self.0.as_bool().unwrap_or(false)))
+	}
+}
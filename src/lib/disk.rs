@@ -1,78 +1,436 @@
 use crate::cli;
+use crate::hex_utils;
+use crate::logging::LogFilters;
+use crate::node_var::{
+	BalanceSample, ChannelLabel, ChannelPolicy, ChannelSnapshot, ClosedChannelInfo, ForwardInfo,
+	InvoiceInfo, NodeAnnouncementInfo, PaymentInfo, ScorerParams, ZapRequestInfo,
+};
+use crate::peers::PeerAddr;
+use crate::storage::NodeStore;
 use bitcoin::secp256k1::key::PublicKey;
-use bitcoin::BlockHash;
+use bitcoin::{BlockHash, Txid};
 use chrono::Utc;
+use lightning::ln::PaymentHash;
 use lightning::routing::network_graph::NetworkGraph;
-use lightning::routing::scoring::{ProbabilisticScorer, ProbabilisticScoringParameters};
-use lightning::util::logger::{Logger, Record};
+use lightning::routing::scoring::ProbabilisticScorer;
+use lightning::util::logger::{Level, Logger, Record};
 use lightning::util::ser::{Readable, ReadableArgs, Writeable, Writer};
+use rand::{thread_rng, Rng};
 use std::collections::HashMap;
 use std::fs;
 use std::fs::File;
-use std::io::{BufRead, BufReader, BufWriter};
-use std::net::SocketAddr;
+use std::io::{BufRead, BufReader, Cursor};
 use std::path::Path;
 use std::sync::Arc;
 
+/// Logger of LDK, forwarding its logs to the node's tracing subscriber under the `ldk` target.
+/// The subscriber writes them to `logs/logs.txt` in the data directory, within the span of the
+/// request or event they were logged for. Logs below the level of the `ldk` target are dropped
+/// before being formatted.
 pub struct FilesystemLogger {
-	data_dir: String,
+	log_filters: Arc<LogFilters>,
 }
+
 impl FilesystemLogger {
-	pub(crate) fn new(data_dir: String) -> Self {
-		let logs_path = format!("{}/logs", data_dir);
-		fs::create_dir_all(logs_path.clone()).unwrap();
-		Self { data_dir: logs_path }
+	pub(crate) fn new(log_filters: Arc<LogFilters>) -> Self {
+		Self { log_filters }
 	}
 }
+
 impl Logger for FilesystemLogger {
 	fn log(&self, record: &Record) {
-		let raw_log = record.args.to_string();
-		let log = format!(
-			"{} {:<5} [{}:{}] {}\n",
-			// Note that a "real" lightning node almost certainly does *not* want subsecond
-			// precision for message-receipt information as it makes log entries a target for
-			// deanonymization attacks. For testing, however, its quite useful.
-			Utc::now().format("%Y-%m-%d %H:%M:%S%.3f"),
-			record.level.to_string(),
-			record.module_path,
-			record.line,
-			raw_log
-		);
-		let logs_file_path = format!("{}/logs.txt", self.data_dir.clone());
-		fs::OpenOptions::new()
-			.create(true)
-			.append(true)
-			.open(logs_file_path)
-			.unwrap()
-			.write_all(log.as_bytes())
-			.unwrap();
+		macro_rules! forward {
+			($level: expr) => {
+				if self.log_filters.ldk_enabled($level) {
+					tracing::event!(
+						target: "ldk",
+						$level,
+						module_path = record.module_path,
+						line = record.line,
+						"{}",
+						record.args
+					)
+				}
+			};
+		}
+		match record.level {
+			Level::Gossip | Level::Trace => forward!(tracing::Level::TRACE),
+			Level::Debug => forward!(tracing::Level::DEBUG),
+			Level::Info => forward!(tracing::Level::INFO),
+			Level::Warn => forward!(tracing::Level::WARN),
+			Level::Error => forward!(tracing::Level::ERROR),
+		}
 	}
 }
-pub(crate) fn persist_channel_peer(path: &Path, peer_info: &str) -> std::io::Result<()> {
-	let mut file = fs::OpenOptions::new().create(true).append(true).open(path)?;
-	file.write_all(format!("{}\n", peer_info).as_bytes())
+
+/// Append `event` to the audit log of the node's data directory, prefixed with the UTC time it
+/// happened at
+pub(crate) fn persist_audit_event(data_dir: &str, event: &str) -> std::io::Result<()> {
+	let mut file =
+		fs::OpenOptions::new().create(true).append(true).open(format!("{}/audit_log", data_dir))?;
+	file.write_all(format!("{} {}\n", Utc::now().format("%Y-%m-%d %H:%M:%S"), event).as_bytes())
+}
+
+pub(crate) const CHANNEL_PEERS_NAMESPACE: &str = "channel_peers";
+pub(crate) const INBOUND_PAYMENTS_NAMESPACE: &str = "inbound_payments";
+pub(crate) const OUTBOUND_PAYMENTS_NAMESPACE: &str = "outbound_payments";
+pub(crate) const FORWARDS_NAMESPACE: &str = "forwards";
+pub(crate) const CHANNEL_SNAPSHOTS_NAMESPACE: &str = "channel_snapshots";
+pub(crate) const CLOSED_CHANNELS_NAMESPACE: &str = "closed_channels";
+pub(crate) const CHANNEL_LABELS_NAMESPACE: &str = "channel_labels";
+pub(crate) const CHANNEL_HISTORY_NAMESPACE: &str = "channel_history";
+const INVOICES_NAMESPACE: &str = "invoices";
+const ZAP_REQUESTS_NAMESPACE: &str = "zap_requests";
+const SETTINGS_NAMESPACE: &str = "settings";
+const CHANNEL_POLICY_KEY: &str = "channel_policy";
+const NODE_ANNOUNCEMENT_KEY: &str = "node_announcement";
+const SCORER_PARAMS_KEY: &str = "scorer_params";
+const SCORER_NAMESPACE: &str = "scorer";
+const SCORER_KEY: &str = "prob_scorer";
+
+fn invalid_data<E: ToString>(e: E) -> std::io::Error {
+	std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+}
+
+pub(crate) fn persist_channel_peer(
+	store: &dyn NodeStore, pubkey: &PublicKey, peer_addr: &PeerAddr,
+) -> std::io::Result<()> {
+	store.write(
+		CHANNEL_PEERS_NAMESPACE,
+		&hex_utils::hex_str(&pubkey.serialize()),
+		peer_addr.to_string().as_bytes(),
+	)
 }
 
 pub(crate) fn read_channel_peer_data(
-	path: &Path,
-) -> Result<HashMap<PublicKey, SocketAddr>, std::io::Error> {
+	store: &dyn NodeStore,
+) -> Result<HashMap<PublicKey, PeerAddr>, std::io::Error> {
 	let mut peer_data = HashMap::new();
-	if !Path::new(&path).exists() {
-		return Ok(HashMap::new());
-	}
-	let file = File::open(path)?;
-	let reader = BufReader::new(file);
-	for line in reader.lines() {
-		match cli::parse_peer_info(line.unwrap()) {
-			Ok((pubkey, socket_addr)) => {
-				peer_data.insert(pubkey, socket_addr);
-			}
-			Err(e) => return Err(e),
-		}
+	for (key, value) in store.list(CHANNEL_PEERS_NAMESPACE)? {
+		let pubkey = hex_utils::to_compressed_pubkey(&key)
+			.ok_or_else(|| invalid_data(format!("invalid channel peer pubkey {}", key)))?;
+		let peer_addr = String::from_utf8(value)
+			.map_err(invalid_data)?
+			.parse::<PeerAddr>()
+			.map_err(invalid_data)?;
+		peer_data.insert(pubkey, peer_addr);
 	}
 	Ok(peer_data)
 }
 
+pub(crate) fn persist_payment(
+	store: &dyn NodeStore, namespace: &str, payment_hash: &PaymentHash, payment: &PaymentInfo,
+) -> std::io::Result<()> {
+	let value = serde_json::to_vec(payment).map_err(invalid_data)?;
+	store.write(namespace, &hex_utils::hex_str(&payment_hash.0), &value)
+}
+
+pub(crate) fn read_payments(
+	store: &dyn NodeStore, namespace: &str,
+) -> std::io::Result<HashMap<PaymentHash, PaymentInfo>> {
+	let mut payments = HashMap::new();
+	for (key, value) in store.list(namespace)? {
+		let payment_hash = payment_hash_key(&key, namespace)?;
+		payments.insert(payment_hash, serde_json::from_slice(&value).map_err(invalid_data)?);
+	}
+	Ok(payments)
+}
+
+pub(crate) fn remove_payment(
+	store: &dyn NodeStore, namespace: &str, payment_hash: &PaymentHash,
+) -> std::io::Result<()> {
+	store.remove(namespace, &hex_utils::hex_str(&payment_hash.0))
+}
+
+/// Parse a key of a namespace keyed by hex payment hash
+fn payment_hash_key(key: &str, namespace: &str) -> std::io::Result<PaymentHash> {
+	match hex_utils::to_vec(key) {
+		Some(bytes) if bytes.len() == 32 => {
+			let mut payment_hash = [0; 32];
+			payment_hash.copy_from_slice(&bytes);
+			Ok(PaymentHash(payment_hash))
+		}
+		_ => Err(invalid_data(format!("invalid payment hash {} in {}", key, namespace))),
+	}
+}
+
+pub(crate) fn persist_invoice(
+	store: &dyn NodeStore, payment_hash: &PaymentHash, invoice: &InvoiceInfo,
+) -> std::io::Result<()> {
+	let value = serde_json::to_vec(invoice).map_err(invalid_data)?;
+	store.write(INVOICES_NAMESPACE, &hex_utils::hex_str(&payment_hash.0), &value)
+}
+
+pub(crate) fn read_invoice(
+	store: &dyn NodeStore, payment_hash: &PaymentHash,
+) -> std::io::Result<Option<InvoiceInfo>> {
+	match store.read(INVOICES_NAMESPACE, &hex_utils::hex_str(&payment_hash.0))? {
+		Some(value) => serde_json::from_slice(&value).map(Some).map_err(invalid_data),
+		None => Ok(None),
+	}
+}
+
+pub(crate) fn persist_zap_request(
+	store: &dyn NodeStore, payment_hash: &PaymentHash, zap_request: &ZapRequestInfo,
+) -> std::io::Result<()> {
+	let value = serde_json::to_vec(zap_request).map_err(invalid_data)?;
+	store.write(ZAP_REQUESTS_NAMESPACE, &hex_utils::hex_str(&payment_hash.0), &value)
+}
+
+pub(crate) fn read_zap_request(
+	store: &dyn NodeStore, payment_hash: &PaymentHash,
+) -> std::io::Result<Option<ZapRequestInfo>> {
+	match store.read(ZAP_REQUESTS_NAMESPACE, &hex_utils::hex_str(&payment_hash.0))? {
+		Some(value) => serde_json::from_slice(&value).map(Some).map_err(invalid_data),
+		None => Ok(None),
+	}
+}
+
+pub(crate) fn remove_zap_request(
+	store: &dyn NodeStore, payment_hash: &PaymentHash,
+) -> std::io::Result<()> {
+	store.remove(ZAP_REQUESTS_NAMESPACE, &hex_utils::hex_str(&payment_hash.0))
+}
+
+/// Read the invoices created by the node, oldest first
+pub(crate) fn read_invoices(
+	store: &dyn NodeStore,
+) -> std::io::Result<Vec<(PaymentHash, InvoiceInfo)>> {
+	let mut invoices = Vec::new();
+	for (key, value) in store.list(INVOICES_NAMESPACE)? {
+		let payment_hash = payment_hash_key(&key, INVOICES_NAMESPACE)?;
+		let invoice: InvoiceInfo = serde_json::from_slice(&value).map_err(invalid_data)?;
+		invoices.push((payment_hash, invoice));
+	}
+	invoices.sort_by(|(a_hash, a), (b_hash, b)| {
+		(a.created_at, a_hash.0).cmp(&(b.created_at, b_hash.0))
+	});
+	Ok(invoices)
+}
+
+/// Append a forward to the forwarding ledger. Keys start with the zero-padded timestamp so the
+/// ledger is listed in chronological order.
+pub(crate) fn persist_forward(store: &dyn NodeStore, forward: &ForwardInfo) -> std::io::Result<()> {
+	let mut nonce = [0; 4];
+	thread_rng().fill_bytes(&mut nonce);
+	let key = format!("{:020}-{}", forward.timestamp, hex_utils::hex_str(&nonce));
+	let value = serde_json::to_vec(forward).map_err(invalid_data)?;
+	store.write(FORWARDS_NAMESPACE, &key, &value)
+}
+
+/// Read the forwards claimed between `start_time` and `end_time` (inclusive, in seconds since
+/// the UNIX epoch), oldest first
+pub(crate) fn read_forwards(
+	store: &dyn NodeStore, start_time: Option<u64>, end_time: Option<u64>,
+) -> std::io::Result<Vec<ForwardInfo>> {
+	let mut forwards = Vec::new();
+	for (_, value) in store.list(FORWARDS_NAMESPACE)? {
+		let forward: ForwardInfo = serde_json::from_slice(&value).map_err(invalid_data)?;
+		if start_time.map_or(true, |start| forward.timestamp >= start)
+			&& end_time.map_or(true, |end| forward.timestamp <= end)
+		{
+			forwards.push(forward);
+		}
+	}
+	Ok(forwards)
+}
+
+pub(crate) fn persist_channel_snapshot(
+	store: &dyn NodeStore, channel_id: &[u8; 32], snapshot: &ChannelSnapshot,
+) -> std::io::Result<()> {
+	let value = serde_json::to_vec(snapshot).map_err(invalid_data)?;
+	store.write(CHANNEL_SNAPSHOTS_NAMESPACE, &hex_utils::hex_str(channel_id), &value)
+}
+
+/// Move the last known state of a channel into the closed channels ledger
+pub(crate) fn persist_closed_channel(
+	store: &dyn NodeStore, channel_id: &[u8; 32], close_reason: String, closed_at: u64,
+	closing_txid: Option<Txid>,
+) -> std::io::Result<()> {
+	let channel_id = hex_utils::hex_str(channel_id);
+	let last_state = match store.read(CHANNEL_SNAPSHOTS_NAMESPACE, &channel_id)? {
+		Some(value) => Some(serde_json::from_slice(&value).map_err(invalid_data)?),
+		None => None,
+	};
+	let closed_channel = ClosedChannelInfo {
+		channel_id: channel_id.clone(),
+		closed_at,
+		close_reason,
+		closing_txid: closing_txid.map(|txid| txid.to_string()),
+		last_state,
+	};
+	let value = serde_json::to_vec(&closed_channel).map_err(invalid_data)?;
+	store.write(CLOSED_CHANNELS_NAMESPACE, &channel_id, &value)?;
+	store.remove(CHANNEL_SNAPSHOTS_NAMESPACE, &channel_id)
+}
+
+/// Read the closed channels ledger, most recently closed first
+pub(crate) fn read_closed_channels(
+	store: &dyn NodeStore,
+) -> std::io::Result<Vec<ClosedChannelInfo>> {
+	let mut closed_channels = Vec::new();
+	for (_, value) in store.list(CLOSED_CHANNELS_NAMESPACE)? {
+		let closed_channel: ClosedChannelInfo =
+			serde_json::from_slice(&value).map_err(invalid_data)?;
+		closed_channels.push(closed_channel);
+	}
+	closed_channels.sort_by(|a, b| b.closed_at.cmp(&a.closed_at));
+	Ok(closed_channels)
+}
+
+/// Append a balance sample to the liquidity history of channel `channel_id`. Keys start with the
+/// channel ID followed by the zero-padded timestamp so each channel's history is listed in
+/// chronological order.
+pub(crate) fn persist_balance_sample(
+	store: &dyn NodeStore, channel_id: &[u8; 32], sample: &BalanceSample,
+) -> std::io::Result<()> {
+	let key = format!("{}-{:020}", hex_utils::hex_str(channel_id), sample.timestamp);
+	let value = serde_json::to_vec(sample).map_err(invalid_data)?;
+	store.write(CHANNEL_HISTORY_NAMESPACE, &key, &value)
+}
+
+/// Read the balance samples of channel `channel_id` taken between `start_time` and `end_time`
+/// (inclusive, in seconds since the UNIX epoch), oldest first
+pub(crate) fn read_channel_history(
+	store: &dyn NodeStore, channel_id: &[u8; 32], start_time: Option<u64>, end_time: Option<u64>,
+) -> std::io::Result<Vec<BalanceSample>> {
+	let prefix = format!("{}-", hex_utils::hex_str(channel_id));
+	let mut samples = Vec::new();
+	for (_, value) in store.list_prefix(CHANNEL_HISTORY_NAMESPACE, &prefix)? {
+		let sample: BalanceSample = serde_json::from_slice(&value).map_err(invalid_data)?;
+		if start_time.map_or(true, |start| sample.timestamp >= start)
+			&& end_time.map_or(true, |end| sample.timestamp <= end)
+		{
+			samples.push(sample);
+		}
+	}
+	Ok(samples)
+}
+
+/// Attach `label` to the channel `channel_id`, or remove its label if it's empty
+pub(crate) fn persist_channel_label(
+	store: &dyn NodeStore, channel_id: &[u8; 32], label: &ChannelLabel,
+) -> std::io::Result<()> {
+	let key = hex_utils::hex_str(channel_id);
+	if label.label.is_none() && label.tags.is_empty() {
+		return store.remove(CHANNEL_LABELS_NAMESPACE, &key);
+	}
+	let value = serde_json::to_vec(label).map_err(invalid_data)?;
+	store.write(CHANNEL_LABELS_NAMESPACE, &key, &value)
+}
+
+/// Read the labels of channels, by hex-encoded channel ID
+pub(crate) fn read_channel_labels(
+	store: &dyn NodeStore,
+) -> std::io::Result<HashMap<String, ChannelLabel>> {
+	let mut labels = HashMap::new();
+	for (channel_id, value) in store.list(CHANNEL_LABELS_NAMESPACE)? {
+		labels.insert(channel_id, serde_json::from_slice(&value).map_err(invalid_data)?);
+	}
+	Ok(labels)
+}
+
+pub(crate) fn persist_channel_policy(
+	store: &dyn NodeStore, policy: &ChannelPolicy,
+) -> std::io::Result<()> {
+	let value = serde_json::to_vec(policy).map_err(invalid_data)?;
+	store.write(SETTINGS_NAMESPACE, CHANNEL_POLICY_KEY, &value)
+}
+
+/// Read the channel policy set through the API, if any
+pub(crate) fn read_channel_policy(store: &dyn NodeStore) -> std::io::Result<Option<ChannelPolicy>> {
+	match store.read(SETTINGS_NAMESPACE, CHANNEL_POLICY_KEY)? {
+		Some(value) => serde_json::from_slice(&value).map(Some).map_err(invalid_data),
+		None => Ok(None),
+	}
+}
+
+pub(crate) fn persist_node_announcement(
+	store: &dyn NodeStore, info: &NodeAnnouncementInfo,
+) -> std::io::Result<()> {
+	let value = serde_json::to_vec(info).map_err(invalid_data)?;
+	store.write(SETTINGS_NAMESPACE, NODE_ANNOUNCEMENT_KEY, &value)
+}
+
+/// Read the node announcement set through the API, if any
+pub(crate) fn read_node_announcement(
+	store: &dyn NodeStore,
+) -> std::io::Result<Option<NodeAnnouncementInfo>> {
+	match store.read(SETTINGS_NAMESPACE, NODE_ANNOUNCEMENT_KEY)? {
+		Some(value) => serde_json::from_slice(&value).map(Some).map_err(invalid_data),
+		None => Ok(None),
+	}
+}
+
+pub(crate) fn persist_scorer_params(
+	store: &dyn NodeStore, params: &ScorerParams,
+) -> std::io::Result<()> {
+	let value = serde_json::to_vec(params).map_err(invalid_data)?;
+	store.write(SETTINGS_NAMESPACE, SCORER_PARAMS_KEY, &value)
+}
+
+/// Read the scorer parameters set through the API, if any
+pub(crate) fn read_scorer_params(store: &dyn NodeStore) -> std::io::Result<Option<ScorerParams>> {
+	match store.read(SETTINGS_NAMESPACE, SCORER_PARAMS_KEY)? {
+		Some(value) => serde_json::from_slice(&value).map(Some).map_err(invalid_data),
+		None => Ok(None),
+	}
+}
+
+/// Move data persisted by previous versions in ad-hoc files of the data directory into `store`.
+///
+/// Legacy files are renamed with a `.migrated` suffix once imported, so this only does something
+/// the first time a node is started with a `NodeStore`.
+pub(crate) fn migrate_legacy_files(
+	ldk_data_dir: &str, store: &dyn NodeStore,
+) -> std::io::Result<()> {
+	let peer_data_path = format!("{}/channel_peer_data", ldk_data_dir);
+	let inbound_payments_path = format!("{}/inbound_payments", ldk_data_dir);
+	let outbound_payments_path = format!("{}/outbound_payments", ldk_data_dir);
+	let scorer_path = format!("{}/prob_scorer", ldk_data_dir);
+	let legacy_paths =
+		[&peer_data_path, &inbound_payments_path, &outbound_payments_path, &scorer_path];
+	if !legacy_paths.iter().any(|path| Path::new(path).is_file()) {
+		return Ok(());
+	}
+	crate::backup::snapshot(ldk_data_dir, "migration")?;
+
+	if Path::new(&peer_data_path).is_file() {
+		let reader = BufReader::new(File::open(&peer_data_path)?);
+		for line in reader.lines() {
+			let (pubkey, peer_addr) = cli::parse_peer_info(line?)?;
+			persist_channel_peer(store, &pubkey, &peer_addr)?;
+		}
+	}
+
+	for (path, namespace) in [
+		(&inbound_payments_path, INBOUND_PAYMENTS_NAMESPACE),
+		(&outbound_payments_path, OUTBOUND_PAYMENTS_NAMESPACE),
+	] {
+		if !Path::new(path).is_file() {
+			continue;
+		}
+		// The payments of a direction used to be stored as a single JSON object keyed by hash
+		let payments_by_hash: HashMap<String, serde_json::Value> =
+			serde_json::from_reader(BufReader::new(File::open(path)?)).map_err(invalid_data)?;
+		for (hash, payment) in payments_by_hash {
+			store.write(namespace, &hash, &serde_json::to_vec(&payment).map_err(invalid_data)?)?;
+		}
+	}
+
+	if Path::new(&scorer_path).is_file() {
+		store.write(SCORER_NAMESPACE, SCORER_KEY, &fs::read(&scorer_path)?)?;
+	}
+
+	for path in legacy_paths {
+		if Path::new(path).is_file() {
+			fs::rename(path, format!("{}.migrated", path))?;
+		}
+	}
+	let _ = persist_audit_event(ldk_data_dir, "migrated legacy data files into the node store");
+	Ok(())
+}
+
 pub(crate) fn read_network(path: &Path, genesis_hash: BlockHash) -> NetworkGraph {
 	if let Ok(file) = File::open(path) {
 		if let Ok(graph) = NetworkGraph::read(&mut BufReader::new(file)) {
@@ -83,30 +441,72 @@ pub(crate) fn read_network(path: &Path, genesis_hash: BlockHash) -> NetworkGraph
 }
 
 pub(crate) fn persist_scorer(
-	path: &Path, scorer: &ProbabilisticScorer<Arc<NetworkGraph>>,
+	store: &dyn NodeStore, scorer: &ProbabilisticScorer<Arc<NetworkGraph>>,
 ) -> std::io::Result<()> {
-	let mut tmp_path = path.to_path_buf().into_os_string();
-	tmp_path.push(".tmp");
-	let file = fs::OpenOptions::new().write(true).create(true).open(&tmp_path)?;
-	let write_res = scorer.write(&mut BufWriter::new(file));
-	if let Err(e) = write_res.and_then(|_| fs::rename(&tmp_path, path)) {
-		let _ = fs::remove_file(&tmp_path);
-		Err(e)
-	} else {
-		Ok(())
-	}
+	store.write(SCORER_NAMESPACE, SCORER_KEY, &scorer.encode())
 }
 
 pub(crate) fn read_scorer(
-	path: &Path, graph: Arc<NetworkGraph>,
+	store: &dyn NodeStore, graph: Arc<NetworkGraph>, params: &ScorerParams,
 ) -> ProbabilisticScorer<Arc<NetworkGraph>> {
-	let params = ProbabilisticScoringParameters::default();
-	if let Ok(file) = File::open(path) {
+	let params = params.to_ldk();
+	if let Ok(Some(bytes)) = store.read(SCORER_NAMESPACE, SCORER_KEY) {
 		if let Ok(scorer) =
-			ProbabilisticScorer::read(&mut BufReader::new(file), (params, Arc::clone(&graph)))
+			ProbabilisticScorer::read(&mut Cursor::new(bytes), (params, Arc::clone(&graph)))
 		{
 			return scorer;
 		}
 	}
 	ProbabilisticScorer::new(params, graph)
 }
+
+/// Rebuild `scorer` with new parameters, keeping what it learned about channel liquidity
+pub(crate) fn rebuild_scorer(
+	scorer: &ProbabilisticScorer<Arc<NetworkGraph>>, graph: Arc<NetworkGraph>,
+	params: &ScorerParams,
+) -> std::io::Result<ProbabilisticScorer<Arc<NetworkGraph>>> {
+	ProbabilisticScorer::read(&mut Cursor::new(scorer.encode()), (params.to_ldk(), graph))
+		.map_err(|e| invalid_data(format!("{:?}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::storage::{test_dir, MemoryStore};
+
+	const PEER_PUBKEY: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+
+	#[test]
+	fn migrates_legacy_files_once() {
+		let dir = test_dir("legacy-files");
+		fs::write(
+			format!("{}/channel_peer_data", dir),
+			format!("{}@127.0.0.1:9735\n", PEER_PUBKEY),
+		)
+		.unwrap();
+		fs::write(format!("{}/inbound_payments", dir), r#"{"00ff": {"status": "succeeded"}}"#)
+			.unwrap();
+		let store = MemoryStore::default();
+		migrate_legacy_files(&dir, &store).unwrap();
+
+		let peers = read_channel_peer_data(&store).unwrap();
+		let pubkey = hex_utils::to_compressed_pubkey(PEER_PUBKEY).unwrap();
+		assert_eq!(peers[&pubkey].to_string(), "127.0.0.1:9735");
+		let payment = store.read(INBOUND_PAYMENTS_NAMESPACE, "00ff").unwrap().unwrap();
+		assert_eq!(
+			serde_json::from_slice::<serde_json::Value>(&payment).unwrap(),
+			serde_json::json!({ "status": "succeeded" })
+		);
+		// The legacy files are set aside, after a snapshot of the node state
+		assert!(!Path::new(&format!("{}/inbound_payments", dir)).exists());
+		assert!(Path::new(&format!("{}/inbound_payments.migrated", dir)).is_file());
+		assert_eq!(fs::read_dir(format!("{}/backups", dir)).unwrap().count(), 1);
+
+		// Nothing is left to migrate on the next start
+		store.remove(INBOUND_PAYMENTS_NAMESPACE, "00ff").unwrap();
+		migrate_legacy_files(&dir, &store).unwrap();
+		assert_eq!(store.read(INBOUND_PAYMENTS_NAMESPACE, "00ff").unwrap(), None);
+		assert_eq!(fs::read_dir(format!("{}/backups", dir)).unwrap().count(), 1);
+		let _ = fs::remove_dir_all(dir);
+	}
+}
@@ -0,0 +1,422 @@
+use crate::bitcoind_client::MIN_FEERATE;
+use crate::chain::{deserialize_hex, parse_hash, ScriptUtxo};
+use crate::hex_utils;
+use crate::settings::FeeSettings;
+use bitcoin::blockdata::block::BlockHeader;
+use bitcoin::blockdata::script::Script;
+use bitcoin::blockdata::transaction::Transaction;
+use bitcoin::consensus::encode;
+use bitcoin::hash_types::{BlockHash, Txid};
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::OutPoint;
+use lightning::chain::chaininterface::{BroadcasterInterface, ConfirmationTarget, FeeEstimator};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::io;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+use tracing::warn;
+
+/// Confirmation targets, in blocks, of the background, normal and high priority estimates,
+/// matching those bitcoind estimates fees for
+const FEE_TARGETS: [u16; 3] = [144, 18, 6];
+
+/// Protocol version negotiated with the server, the first to serve `blockchain.scripthash`
+/// methods and block headers by height
+const PROTOCOL_VERSION: &str = "1.4";
+
+/// Client of an Electrum server, e.g. ElectrumX or Fulcrum, which estimates fees and broadcasts
+/// transactions for LDK, and which LDK is synced from by `TxSync`.
+///
+/// The server is reached over plaintext TCP, so it should run on the node's host or network.
+pub struct ElectrumClient {
+	rpc: Arc<ElectrumRpc>,
+	fees: Arc<[AtomicU32; 3]>,
+	handle: tokio::runtime::Handle,
+}
+
+/// JSON-RPC connection to the server, which is reopened by the next call once it fails
+struct ElectrumRpc {
+	address: String,
+	stream: Mutex<Option<BufReader<TcpStream>>>,
+	next_id: AtomicU64,
+}
+
+#[derive(Deserialize)]
+struct HeaderInfo {
+	height: u32,
+	hex: String,
+}
+
+#[derive(Deserialize)]
+struct HistoryEntry {
+	tx_hash: String,
+	/// Height of the confirming block, or 0 or less for mempool transactions
+	height: i64,
+}
+
+#[derive(Deserialize)]
+struct MerkleProof {
+	pos: usize,
+}
+
+#[derive(Deserialize)]
+struct ScriptUnspent {
+	tx_hash: String,
+	tx_pos: u32,
+	/// Height of the confirming block, or 0 or less for mempool transactions
+	height: i64,
+	value: u64,
+}
+
+impl ElectrumRpc {
+	async fn call<T: DeserializeOwned>(
+		&self, method: &str, params: serde_json::Value,
+	) -> io::Result<T> {
+		let mut stream = self.stream.lock().await;
+		if stream.is_none() {
+			let mut new_stream = BufReader::new(TcpStream::connect(&self.address).await?);
+			let version =
+				self.request("server.version", serde_json::json!(["LN-Node", PROTOCOL_VERSION]));
+			exchange(&mut new_stream, &version).await?;
+			*stream = Some(new_stream);
+		}
+		let request = self.request(method, params);
+		let response = match tokio::time::timeout(
+			Duration::from_secs(30),
+			exchange(stream.as_mut().unwrap(), &request),
+		)
+		.await
+		{
+			Ok(Ok(response)) => response,
+			Ok(Err(e)) => {
+				*stream = None;
+				return Err(e);
+			}
+			Err(_) => {
+				*stream = None;
+				return Err(io::Error::new(
+					io::ErrorKind::TimedOut,
+					format!("Electrum server didn't answer {}", method),
+				));
+			}
+		};
+		if let Some(error) = response.get("error").filter(|error| !error.is_null()) {
+			return Err(io::Error::new(
+				io::ErrorKind::Other,
+				format!("Electrum server failed {}: {}", method, error),
+			));
+		}
+		let result = response.get("result").cloned().unwrap_or(serde_json::Value::Null);
+		serde_json::from_value(result).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+	}
+
+	fn request(&self, method: &str, params: serde_json::Value) -> serde_json::Value {
+		let id = self.next_id.fetch_add(1, Ordering::AcqRel);
+		serde_json::json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+	}
+}
+
+/// Send `request` and wait for its response, skipping subscription notifications
+async fn exchange(
+	stream: &mut BufReader<TcpStream>, request: &serde_json::Value,
+) -> io::Result<serde_json::Value> {
+	let mut line = serde_json::to_string(request)?;
+	line.push('\n');
+	stream.get_mut().write_all(line.as_bytes()).await?;
+	loop {
+		let mut line = String::new();
+		if stream.read_line(&mut line).await? == 0 {
+			return Err(io::Error::new(
+				io::ErrorKind::UnexpectedEof,
+				"Electrum server closed the connection",
+			));
+		}
+		let response: serde_json::Value = serde_json::from_str(&line)?;
+		if response.get("id").is_some() && response.get("id") == request.get("id") {
+			return Ok(response);
+		}
+	}
+}
+
+impl ElectrumClient {
+	/// Connect to the Electrum server at `address`, given as `host:port` or `tcp://host:port`,
+	/// estimating fees at the rates of `fee_defaults` until it can
+	pub async fn new(
+		address: String, fee_defaults: FeeSettings, handle: tokio::runtime::Handle,
+	) -> io::Result<Self> {
+		let interval = Duration::from_secs(std::cmp::max(fee_defaults.poll_interval_secs, 1));
+		let fee_defaults = [
+			std::cmp::max(fee_defaults.background_sat_per_kw, MIN_FEERATE),
+			std::cmp::max(fee_defaults.normal_sat_per_kw, MIN_FEERATE),
+			std::cmp::max(fee_defaults.high_priority_sat_per_kw, MIN_FEERATE),
+		];
+		let client = Self {
+			rpc: Arc::new(ElectrumRpc {
+				address: address.trim_start_matches("tcp://").to_string(),
+				stream: Mutex::new(None),
+				next_id: AtomicU64::new(0),
+			}),
+			fees: Arc::new([
+				AtomicU32::new(fee_defaults[0]),
+				AtomicU32::new(fee_defaults[1]),
+				AtomicU32::new(fee_defaults[2]),
+			]),
+			handle,
+		};
+		client.get_tip().await.map_err(|e| {
+			io::Error::new(
+				e.kind(),
+				format!(
+					"Failed to make initial call to the Electrum server at {}: {}",
+					client.rpc.address, e
+				),
+			)
+		})?;
+		client.poll_for_fee_estimates(fee_defaults, interval);
+		Ok(client)
+	}
+
+	fn poll_for_fee_estimates(&self, fee_defaults: [u32; 3], interval: Duration) {
+		let rpc = Arc::clone(&self.rpc);
+		let fees = Arc::clone(&self.fees);
+		self.handle.spawn(async move {
+			loop {
+				for (idx, target) in FEE_TARGETS.iter().enumerate() {
+					// Estimates are in BTC per kvbyte, or -1 without enough data. A vbyte is 4
+					// weight units, so 1 BTC/kvB is 25,000,000 sat/kw.
+					let feerate = match rpc
+						.call::<f64>("blockchain.estimatefee", serde_json::json!([target]))
+						.await
+					{
+						Ok(btc_per_kvbyte) if btc_per_kvbyte > 0.0 => {
+							std::cmp::max((btc_per_kvbyte * 25_000_000.0) as u32, MIN_FEERATE)
+						}
+						Ok(_) => fee_defaults[idx],
+						Err(e) => {
+							warn!("failed to fetch fee estimates from Electrum: {}", e);
+							break;
+						}
+					};
+					fees[idx].store(feerate, Ordering::Release);
+				}
+				tokio::time::sleep(interval).await;
+			}
+		});
+	}
+
+	/// Cached fee estimates, in satoshis per KW, by confirmation target
+	pub fn fee_estimates(&self) -> Vec<(u16, u32)> {
+		let mut estimates: Vec<(u16, u32)> = FEE_TARGETS
+			.iter()
+			.zip(self.fees.iter())
+			.map(|(target, feerate)| (*target, feerate.load(Ordering::Acquire)))
+			.collect();
+		estimates.sort_unstable();
+		estimates
+	}
+
+	/// Hash and height of the chain tip
+	pub async fn get_tip(&self) -> io::Result<(BlockHash, u32)> {
+		let tip: HeaderInfo =
+			self.rpc.call("blockchain.headers.subscribe", serde_json::json!([])).await?;
+		let header: BlockHeader = deserialize_hex(&tip.hex)?;
+		Ok((header.block_hash(), tip.height))
+	}
+
+	/// Hash of the block at `height` of the best chain
+	pub async fn get_block_hash(&self, height: u32) -> io::Result<BlockHash> {
+		Ok(self.get_header_at(height).await?.block_hash())
+	}
+
+	async fn get_header_at(&self, height: u32) -> io::Result<BlockHeader> {
+		let header_hex: String =
+			self.rpc.call("blockchain.block.header", serde_json::json!([height])).await?;
+		deserialize_hex(&header_hex)
+	}
+
+	/// Header of block `hash` at `height`, failing if the block was reorged out of the chain,
+	/// as Electrum servers only serve headers by height
+	pub async fn get_header(&self, hash: &BlockHash, height: u32) -> io::Result<BlockHeader> {
+		let header = self.get_header_at(height).await?;
+		if header.block_hash() != *hash {
+			return Err(io::Error::new(
+				io::ErrorKind::Other,
+				format!("block {} is no longer in the best chain", hash),
+			));
+		}
+		Ok(header)
+	}
+
+	pub async fn get_tx(&self, txid: &Txid) -> io::Result<Option<Transaction>> {
+		match self
+			.rpc
+			.call::<String>("blockchain.transaction.get", serde_json::json!([txid.to_string()]))
+			.await
+		{
+			Ok(tx_hex) => Ok(Some(deserialize_hex(&tx_hex)?)),
+			// Servers fail requests for unknown transactions
+			Err(e) if e.kind() == io::ErrorKind::Other => Ok(None),
+			Err(e) => Err(e),
+		}
+	}
+
+	/// Hash and height of the block confirming `txid`, if it is confirmed.
+	///
+	/// Electrum servers index transactions by output script, so the transaction is looked up in
+	/// the history of one of its outputs.
+	pub async fn get_tx_confirmation(&self, txid: &Txid) -> io::Result<Option<(BlockHash, u32)>> {
+		let tx = match self.get_tx(txid).await? {
+			Some(tx) => tx,
+			None => return Ok(None),
+		};
+		let script_pubkey =
+			match tx.output.iter().find(|output| !output.script_pubkey.is_provably_unspendable()) {
+				Some(output) => &output.script_pubkey,
+				None => return Ok(None),
+			};
+		for entry in self.get_history(script_pubkey).await? {
+			if entry.height > 0 && parse_hash::<Txid>(&entry.tx_hash)? == *txid {
+				let height = entry.height as u32;
+				return Ok(Some((self.get_block_hash(height).await?, height)));
+			}
+		}
+		Ok(None)
+	}
+
+	/// Position of `txid` in its block at `height`
+	pub async fn get_tx_position(&self, txid: &Txid, height: u32) -> io::Result<usize> {
+		let proof: MerkleProof = self
+			.rpc
+			.call(
+				"blockchain.transaction.get_merkle",
+				serde_json::json!([txid.to_string(), height]),
+			)
+			.await?;
+		Ok(proof.pos)
+	}
+
+	/// Transaction spending `outpoint`, if any, including mempool transactions, found in the
+	/// history of the output's `script_pubkey`
+	pub async fn get_output_spend(
+		&self, outpoint: &OutPoint, script_pubkey: &Script,
+	) -> io::Result<Option<Txid>> {
+		for entry in self.get_history(script_pubkey).await? {
+			let txid: Txid = parse_hash(&entry.tx_hash)?;
+			if txid == outpoint.txid {
+				continue;
+			}
+			if let Some(tx) = self.get_tx(&txid).await? {
+				if tx.input.iter().any(|input| input.previous_output == *outpoint) {
+					return Ok(Some(txid));
+				}
+			}
+		}
+		Ok(None)
+	}
+
+	/// Unspent outputs paying to `script_pubkey`, including those of mempool transactions
+	pub async fn get_script_utxos(&self, script_pubkey: &Script) -> io::Result<Vec<ScriptUtxo>> {
+		let unspents: Vec<ScriptUnspent> = self
+			.rpc
+			.call(
+				"blockchain.scripthash.listunspent",
+				serde_json::json!([script_hash(script_pubkey)]),
+			)
+			.await?;
+		let mut utxos = Vec::with_capacity(unspents.len());
+		for unspent in unspents {
+			utxos.push(ScriptUtxo {
+				outpoint: OutPoint { txid: parse_hash(&unspent.tx_hash)?, vout: unspent.tx_pos },
+				value: unspent.value,
+				height: if unspent.height > 0 { Some(unspent.height as u32) } else { None },
+			});
+		}
+		Ok(utxos)
+	}
+
+	/// Whether any transaction, confirmed or in the mempool, pays to or spends from
+	/// `script_pubkey`
+	pub async fn has_history(&self, script_pubkey: &Script) -> io::Result<bool> {
+		Ok(!self.get_history(script_pubkey).await?.is_empty())
+	}
+
+	/// Broadcast `tx`, returning the server's error if it rejects the transaction
+	pub async fn send_transaction(&self, tx: &Transaction) -> io::Result<Txid> {
+		let txid: String = self
+			.rpc
+			.call(
+				"blockchain.transaction.broadcast",
+				serde_json::json!([encode::serialize_hex(tx)]),
+			)
+			.await?;
+		parse_hash(&txid)
+	}
+
+	async fn get_history(&self, script_pubkey: &Script) -> io::Result<Vec<HistoryEntry>> {
+		self.rpc
+			.call(
+				"blockchain.scripthash.get_history",
+				serde_json::json!([script_hash(script_pubkey)]),
+			)
+			.await
+	}
+}
+
+/// Hash Electrum servers index `script_pubkey` by, i.e. its SHA256 hash in reverse byte order
+fn script_hash(script_pubkey: &Script) -> String {
+	let mut script_hash = sha256::Hash::hash(script_pubkey.as_bytes()).into_inner();
+	script_hash.reverse();
+	hex_utils::hex_str(&script_hash)
+}
+
+impl FeeEstimator for ElectrumClient {
+	fn get_est_sat_per_1000_weight(&self, confirmation_target: ConfirmationTarget) -> u32 {
+		let idx = match confirmation_target {
+			ConfirmationTarget::Background => 0,
+			ConfirmationTarget::Normal => 1,
+			ConfirmationTarget::HighPriority => 2,
+		};
+		self.fees[idx].load(Ordering::Acquire)
+	}
+}
+
+impl BroadcasterInterface for ElectrumClient {
+	fn broadcast_transaction(&self, tx: &Transaction) {
+		let rpc = Arc::clone(&self.rpc);
+		let txid = tx.txid();
+		let tx_hex = encode::serialize_hex(tx);
+		self.handle.spawn(async move {
+			// LDK rebroadcasts transactions which may already be confirmed or replaced, so a
+			// rejection is only reported
+			if let Err(e) = rpc
+				.call::<String>("blockchain.transaction.broadcast", serde_json::json!([tx_hex]))
+				.await
+			{
+				warn!("failed to broadcast transaction {}: {}", txid, e);
+			}
+		});
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn hashes_scripts_in_reverse_byte_order() {
+		// Example of the Electrum protocol documentation, the P2PKH script of
+		// 1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa
+		let script_pubkey = Script::from(
+			hex_utils::to_vec("76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac").unwrap(),
+		);
+		assert_eq!(
+			script_hash(&script_pubkey),
+			"8b01df4e368ea28f8dc0423bcf7a4923e3a12d307c875e47a0cfbf90b5c39161"
+		);
+	}
+}
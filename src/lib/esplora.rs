@@ -0,0 +1,341 @@
+use crate::bitcoind_client::MIN_FEERATE;
+use crate::chain::{deserialize_hex, parse_hash, ScriptUtxo};
+use crate::hex_utils;
+use crate::settings::FeeSettings;
+use bitcoin::blockdata::block::BlockHeader;
+use bitcoin::blockdata::script::Script;
+use bitcoin::blockdata::transaction::Transaction;
+use bitcoin::consensus::encode;
+use bitcoin::hash_types::{BlockHash, Txid};
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::OutPoint;
+use lightning::chain::chaininterface::{BroadcasterInterface, ConfirmationTarget, FeeEstimator};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+/// Confirmation targets, in blocks, of the background, normal and high priority estimates,
+/// matching those bitcoind estimates fees for
+const FEE_TARGETS: [u16; 3] = [144, 18, 6];
+
+/// Client of an Esplora HTTP API, e.g. `https://blockstream.info/testnet/api`, which estimates
+/// fees and broadcasts transactions for LDK, and which LDK is synced from by `TxSync`
+pub struct EsploraClient {
+	client: reqwest::Client,
+	base_url: String,
+	fees: Arc<[AtomicU32; 3]>,
+	handle: tokio::runtime::Handle,
+}
+
+#[derive(Deserialize)]
+struct BlockInfo {
+	height: u32,
+}
+
+#[derive(Deserialize)]
+struct TxStatus {
+	confirmed: bool,
+	block_height: Option<u32>,
+	block_hash: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct MerkleProof {
+	pos: usize,
+}
+
+#[derive(Deserialize)]
+struct OutputSpend {
+	spent: bool,
+	txid: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ScriptUnspent {
+	txid: String,
+	vout: u32,
+	value: u64,
+	status: TxStatus,
+}
+
+#[derive(Deserialize)]
+struct ScriptStats {
+	chain_stats: TxCount,
+	mempool_stats: TxCount,
+}
+
+#[derive(Deserialize)]
+struct TxCount {
+	tx_count: u64,
+}
+
+impl EsploraClient {
+	/// Connect to the Esplora API at `base_url`, estimating fees at the rates of `fee_defaults`
+	/// until it can
+	pub async fn new(
+		base_url: String, fee_defaults: FeeSettings, handle: tokio::runtime::Handle,
+	) -> io::Result<Self> {
+		let client = reqwest::Client::builder()
+			.timeout(Duration::from_secs(30))
+			.build()
+			.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+		let interval = Duration::from_secs(std::cmp::max(fee_defaults.poll_interval_secs, 1));
+		let fee_defaults = [
+			std::cmp::max(fee_defaults.background_sat_per_kw, MIN_FEERATE),
+			std::cmp::max(fee_defaults.normal_sat_per_kw, MIN_FEERATE),
+			std::cmp::max(fee_defaults.high_priority_sat_per_kw, MIN_FEERATE),
+		];
+		let client = Self {
+			client,
+			base_url: base_url.trim_end_matches('/').to_string(),
+			fees: Arc::new([
+				AtomicU32::new(fee_defaults[0]),
+				AtomicU32::new(fee_defaults[1]),
+				AtomicU32::new(fee_defaults[2]),
+			]),
+			handle,
+		};
+		client.get_tip().await.map_err(|e| {
+			io::Error::new(
+				e.kind(),
+				format!("Failed to make initial call to Esplora at {}: {}", client.base_url, e),
+			)
+		})?;
+		client.poll_for_fee_estimates(fee_defaults, interval);
+		Ok(client)
+	}
+
+	fn poll_for_fee_estimates(&self, fee_defaults: [u32; 3], interval: Duration) {
+		let client = self.client.clone();
+		let url = format!("{}/fee-estimates", self.base_url);
+		let fees = Arc::clone(&self.fees);
+		self.handle.spawn(async move {
+			loop {
+				// Estimates are in satoshis per vbyte, keyed by confirmation target
+				let estimates = match client.get(&url).send().await {
+					Ok(response) => response.json::<HashMap<String, f64>>().await.ok(),
+					Err(_) => None,
+				};
+				match estimates {
+					Some(estimates) => {
+						for (idx, target) in FEE_TARGETS.iter().enumerate() {
+							// A vbyte is 4 weight units, so 1 sat/vB is 250 sat/kw
+							let feerate = match estimates.get(&target.to_string()) {
+								Some(sat_per_vbyte) => {
+									std::cmp::max((sat_per_vbyte * 250.0) as u32, MIN_FEERATE)
+								}
+								None => fee_defaults[idx],
+							};
+							fees[idx].store(feerate, Ordering::Release);
+						}
+					}
+					None => warn!("failed to fetch fee estimates from Esplora"),
+				}
+				tokio::time::sleep(interval).await;
+			}
+		});
+	}
+
+	/// Cached fee estimates, in satoshis per KW, by confirmation target
+	pub fn fee_estimates(&self) -> Vec<(u16, u32)> {
+		let mut estimates: Vec<(u16, u32)> = FEE_TARGETS
+			.iter()
+			.zip(self.fees.iter())
+			.map(|(target, feerate)| (*target, feerate.load(Ordering::Acquire)))
+			.collect();
+		estimates.sort_unstable();
+		estimates
+	}
+
+	/// Send a GET request for `path`, returning `None` if the resource doesn't exist
+	async fn get(&self, path: &str) -> io::Result<Option<reqwest::Response>> {
+		let response = self
+			.client
+			.get(format!("{}{}", self.base_url, path))
+			.send()
+			.await
+			.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+		if response.status() == reqwest::StatusCode::NOT_FOUND {
+			return Ok(None);
+		}
+		if !response.status().is_success() {
+			return Err(io::Error::new(
+				io::ErrorKind::Other,
+				format!("Esplora returned {} for {}", response.status(), path),
+			));
+		}
+		Ok(Some(response))
+	}
+
+	async fn get_text(&self, path: &str) -> io::Result<Option<String>> {
+		match self.get(path).await? {
+			Some(response) => Ok(Some(
+				response.text().await.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?,
+			)),
+			None => Ok(None),
+		}
+	}
+
+	async fn get_json<T: DeserializeOwned>(&self, path: &str) -> io::Result<Option<T>> {
+		match self.get(path).await? {
+			Some(response) => Ok(Some(
+				response.json().await.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+			)),
+			None => Ok(None),
+		}
+	}
+
+	/// Hash and height of the chain tip
+	pub async fn get_tip(&self) -> io::Result<(BlockHash, u32)> {
+		let hash = parse_hash(&self.get_text("/blocks/tip/hash").await?.ok_or_else(not_found)?)?;
+		let block: BlockInfo =
+			self.get_json(&format!("/block/{}", hash)).await?.ok_or_else(not_found)?;
+		Ok((hash, block.height))
+	}
+
+	/// Hash of the block at `height` of the best chain
+	pub async fn get_block_hash(&self, height: u32) -> io::Result<BlockHash> {
+		parse_hash(
+			&self.get_text(&format!("/block-height/{}", height)).await?.ok_or_else(not_found)?,
+		)
+	}
+
+	pub async fn get_header(&self, hash: &BlockHash) -> io::Result<BlockHeader> {
+		let header_hex =
+			self.get_text(&format!("/block/{}/header", hash)).await?.ok_or_else(not_found)?;
+		deserialize_hex(&header_hex)
+	}
+
+	/// Hash and height of the block confirming `txid`, if it is confirmed
+	pub async fn get_tx_confirmation(&self, txid: &Txid) -> io::Result<Option<(BlockHash, u32)>> {
+		let status: TxStatus = match self.get_json(&format!("/tx/{}/status", txid)).await? {
+			Some(status) => status,
+			None => return Ok(None),
+		};
+		match (status.confirmed, status.block_hash, status.block_height) {
+			(true, Some(block_hash), Some(block_height)) => {
+				Ok(Some((parse_hash(&block_hash)?, block_height)))
+			}
+			_ => Ok(None),
+		}
+	}
+
+	pub async fn get_tx(&self, txid: &Txid) -> io::Result<Option<Transaction>> {
+		match self.get_text(&format!("/tx/{}/hex", txid)).await? {
+			Some(tx_hex) => Ok(Some(deserialize_hex(&tx_hex)?)),
+			None => Ok(None),
+		}
+	}
+
+	/// Position of the confirmed transaction `txid` in its block
+	pub async fn get_tx_position(&self, txid: &Txid) -> io::Result<usize> {
+		let proof: MerkleProof =
+			self.get_json(&format!("/tx/{}/merkle-proof", txid)).await?.ok_or_else(not_found)?;
+		Ok(proof.pos)
+	}
+
+	/// Transaction spending output `vout` of `txid`, if any, including mempool transactions
+	pub async fn get_output_spend(&self, txid: &Txid, vout: u16) -> io::Result<Option<Txid>> {
+		let spend: OutputSpend =
+			match self.get_json(&format!("/tx/{}/outspend/{}", txid, vout)).await? {
+				Some(spend) => spend,
+				None => return Ok(None),
+			};
+		match (spend.spent, spend.txid) {
+			(true, Some(txid)) => Ok(Some(parse_hash(&txid)?)),
+			_ => Ok(None),
+		}
+	}
+
+	/// Unspent outputs paying to `script_pubkey`, including those of mempool transactions
+	pub async fn get_script_utxos(&self, script_pubkey: &Script) -> io::Result<Vec<ScriptUtxo>> {
+		let path = format!("/scripthash/{}/utxo", script_hash(script_pubkey));
+		let unspents: Vec<ScriptUnspent> = self.get_json(&path).await?.unwrap_or_default();
+		let mut utxos = Vec::with_capacity(unspents.len());
+		for unspent in unspents {
+			utxos.push(ScriptUtxo {
+				outpoint: OutPoint { txid: parse_hash(&unspent.txid)?, vout: unspent.vout },
+				value: unspent.value,
+				height: unspent.status.block_height.filter(|_| unspent.status.confirmed),
+			});
+		}
+		Ok(utxos)
+	}
+
+	/// Whether any transaction, confirmed or in the mempool, pays to or spends from
+	/// `script_pubkey`
+	pub async fn has_history(&self, script_pubkey: &Script) -> io::Result<bool> {
+		let path = format!("/scripthash/{}", script_hash(script_pubkey));
+		Ok(match self.get_json::<ScriptStats>(&path).await? {
+			Some(stats) => stats.chain_stats.tx_count + stats.mempool_stats.tx_count > 0,
+			None => false,
+		})
+	}
+
+	/// Broadcast `tx`, returning Esplora's error if it rejects the transaction
+	pub async fn send_transaction(&self, tx: &Transaction) -> io::Result<Txid> {
+		let response = self
+			.client
+			.post(format!("{}/tx", self.base_url))
+			.body(encode::serialize_hex(tx))
+			.send()
+			.await
+			.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+		let status = response.status();
+		let body = response.text().await.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+		if !status.is_success() {
+			return Err(io::Error::new(
+				io::ErrorKind::Other,
+				format!("Esplora rejected the transaction: {} {}", status, body),
+			));
+		}
+		parse_hash(&body)
+	}
+}
+
+/// Hash Esplora indexes `script_pubkey` by
+fn script_hash(script_pubkey: &Script) -> String {
+	hex_utils::hex_str(&sha256::Hash::hash(script_pubkey.as_bytes()).into_inner())
+}
+
+fn not_found() -> io::Error {
+	io::Error::new(io::ErrorKind::NotFound, "Esplora doesn't know the requested resource")
+}
+
+impl FeeEstimator for EsploraClient {
+	fn get_est_sat_per_1000_weight(&self, confirmation_target: ConfirmationTarget) -> u32 {
+		let idx = match confirmation_target {
+			ConfirmationTarget::Background => 0,
+			ConfirmationTarget::Normal => 1,
+			ConfirmationTarget::HighPriority => 2,
+		};
+		self.fees[idx].load(Ordering::Acquire)
+	}
+}
+
+impl BroadcasterInterface for EsploraClient {
+	fn broadcast_transaction(&self, tx: &Transaction) {
+		let client = self.client.clone();
+		let url = format!("{}/tx", self.base_url);
+		let txid = tx.txid();
+		let tx_hex = encode::serialize_hex(tx);
+		self.handle.spawn(async move {
+			// LDK rebroadcasts transactions which may already be confirmed or replaced, so a
+			// rejection is only reported
+			match client.post(&url).body(tx_hex).send().await {
+				Ok(response) if response.status().is_success() => {}
+				Ok(response) => {
+					let status = response.status();
+					let reason = response.text().await.unwrap_or_default();
+					warn!("esplora rejected transaction {}: {} {}", txid, status, reason);
+				}
+				Err(e) => warn!("failed to broadcast transaction {}: {}", txid, e),
+			}
+		});
+	}
+}
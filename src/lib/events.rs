@@ -0,0 +1,121 @@
+use crate::hex_utils;
+use lightning::util::events::Event;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+/// Number of events buffered for each subscriber. Subscribers falling further behind miss the
+/// oldest events.
+pub(crate) const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+/// Node events pushed to subscribers of the `/events` stream
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NodeEvent {
+	FundingGenerationReady {
+		temporary_channel_id: String,
+		channel_value_sat: u64,
+	},
+	PaymentReceived {
+		payment_hash: String,
+		amount_msat: u64,
+	},
+	PaymentSent {
+		payment_hash: String,
+		payment_preimage: String,
+		fee_paid_msat: Option<u64>,
+	},
+	PaymentPathFailed {
+		payment_hash: String,
+		rejected_by_dest: bool,
+		all_paths_failed: bool,
+		/// Channel the payment failed at, unless it was rejected by the destination
+		short_channel_id: Option<u64>,
+	},
+	PaymentFailed {
+		payment_hash: String,
+	},
+	PaymentForwarded {
+		fee_earned_msat: Option<u64>,
+		claim_from_onchain_tx: bool,
+	},
+	SpendableOutputs {
+		outputs_number: usize,
+	},
+	ChannelClosed {
+		channel_id: String,
+		user_channel_id: u64,
+		reason: String,
+	},
+	DiscardFunding {
+		channel_id: String,
+		txid: String,
+	},
+}
+
+impl NodeEvent {
+	/// Convert an LDK event, returning `None` for events only relevant to the node internals
+	pub(crate) fn from_ldk(event: &Event) -> Option<Self> {
+		match event {
+			Event::FundingGenerationReady {
+				temporary_channel_id, channel_value_satoshis, ..
+			} => Some(NodeEvent::FundingGenerationReady {
+				temporary_channel_id: hex_utils::hex_str(temporary_channel_id),
+				channel_value_sat: *channel_value_satoshis,
+			}),
+			Event::PaymentReceived { payment_hash, amt, .. } => Some(NodeEvent::PaymentReceived {
+				payment_hash: hex_utils::hex_str(&payment_hash.0),
+				amount_msat: *amt,
+			}),
+			Event::PaymentSent { payment_hash, payment_preimage, fee_paid_msat, .. } => {
+				Some(NodeEvent::PaymentSent {
+					payment_hash: hex_utils::hex_str(&payment_hash.0),
+					payment_preimage: hex_utils::hex_str(&payment_preimage.0),
+					fee_paid_msat: *fee_paid_msat,
+				})
+			}
+			Event::PaymentPathFailed {
+				payment_hash,
+				rejected_by_dest,
+				all_paths_failed,
+				short_channel_id,
+				..
+			} => Some(NodeEvent::PaymentPathFailed {
+				payment_hash: hex_utils::hex_str(&payment_hash.0),
+				rejected_by_dest: *rejected_by_dest,
+				all_paths_failed: *all_paths_failed,
+				short_channel_id: *short_channel_id,
+			}),
+			Event::PaymentFailed { payment_hash, .. } => {
+				Some(NodeEvent::PaymentFailed { payment_hash: hex_utils::hex_str(&payment_hash.0) })
+			}
+			Event::PaymentForwarded { fee_earned_msat, claim_from_onchain_tx } => {
+				Some(NodeEvent::PaymentForwarded {
+					fee_earned_msat: *fee_earned_msat,
+					claim_from_onchain_tx: *claim_from_onchain_tx,
+				})
+			}
+			Event::SpendableOutputs { outputs } => {
+				Some(NodeEvent::SpendableOutputs { outputs_number: outputs.len() })
+			}
+			Event::ChannelClosed { channel_id, user_channel_id, reason } => {
+				Some(NodeEvent::ChannelClosed {
+					channel_id: hex_utils::hex_str(channel_id),
+					user_channel_id: *user_channel_id,
+					reason: format!("{:?}", reason),
+				})
+			}
+			Event::DiscardFunding { channel_id, transaction } => Some(NodeEvent::DiscardFunding {
+				channel_id: hex_utils::hex_str(channel_id),
+				txid: transaction.txid().to_string(),
+			}),
+			Event::OpenChannelRequest { .. }
+			| Event::PaymentPathSuccessful { .. }
+			| Event::PendingHTLCsForwardable { .. } => None,
+		}
+	}
+}
+
+/// Create the channel events are published on. Receivers are created with `subscribe`.
+pub(crate) fn event_channel() -> broadcast::Sender<NodeEvent> {
+	broadcast::channel(EVENT_CHANNEL_CAPACITY).0
+}
@@ -0,0 +1,228 @@
+use crate::hex_utils;
+use crate::node_var::{ForwardInfo, HTLCStatus, PaymentInfo};
+use chrono::{TimeZone, Utc};
+use lightning::ln::PaymentHash;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Format of the payments and forwards exported for accounting
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+	Csv,
+	/// One JSON object per line
+	JsonLines,
+}
+
+impl ExportFormat {
+	/// Parse `csv` or `jsonl`, defaulting to CSV
+	pub fn parse(format: Option<&str>) -> Result<Self, String> {
+		match format {
+			Some("csv") | None => Ok(ExportFormat::Csv),
+			Some("jsonl") => Ok(ExportFormat::JsonLines),
+			Some(format) => Err(format!("format must be csv or jsonl: {}", format)),
+		}
+	}
+
+	pub fn content_type(&self) -> &'static str {
+		match self {
+			ExportFormat::Csv => "text/csv",
+			ExportFormat::JsonLines => "application/x-ndjson",
+		}
+	}
+
+	pub fn extension(&self) -> &'static str {
+		match self {
+			ExportFormat::Csv => "csv",
+			ExportFormat::JsonLines => "jsonl",
+		}
+	}
+}
+
+/// A row of an export, which is written as a CSV line or a JSON object
+pub trait ExportRecord: Serialize {
+	const HEADER: &'static [&'static str];
+
+	/// Fields of the CSV line, in the order of `HEADER`
+	fn fields(&self) -> Vec<String>;
+}
+
+/// A payment, as exported for accounting
+#[derive(Serialize)]
+pub struct PaymentRecord {
+	/// Seconds since the UNIX epoch at which the payment was initiated, or its invoice created
+	pub created_at: Option<u64>,
+	/// `created_at` as an RFC 3339 date in UTC
+	pub date: Option<String>,
+	pub resolved_at: Option<u64>,
+	pub direction: String,
+	pub status: String,
+	pub payment_hash: String,
+	pub amount_msat: Option<u64>,
+	pub fee_paid_msat: Option<u64>,
+	/// Destination of a successful outbound payment. The payer of an inbound payment is unknown.
+	pub counterparty: Option<String>,
+	pub label: Option<String>,
+}
+
+impl PaymentRecord {
+	pub fn new(payment_hash: &PaymentHash, direction: &str, payment_info: &PaymentInfo) -> Self {
+		Self {
+			created_at: payment_info.created_at,
+			date: payment_info.created_at.map(rfc3339_date),
+			resolved_at: payment_info.resolved_at,
+			direction: direction.to_string(),
+			status: match payment_info.status {
+				HTLCStatus::Pending => "pending".to_string(),
+				HTLCStatus::Succeeded => "succeeded".to_string(),
+				HTLCStatus::Failed => "failed".to_string(),
+			},
+			payment_hash: hex_utils::hex_str(&payment_hash.0),
+			amount_msat: payment_info.amt_msat.0,
+			fee_paid_msat: payment_info.fee_paid_msat,
+			counterparty: payment_info
+				.paths
+				.first()
+				.and_then(|path| path.last())
+				.map(|hop| hop.pubkey.to_string()),
+			label: payment_info.label.clone(),
+		}
+	}
+}
+
+impl ExportRecord for PaymentRecord {
+	const HEADER: &'static [&'static str] = &[
+		"created_at",
+		"date",
+		"resolved_at",
+		"direction",
+		"status",
+		"payment_hash",
+		"amount_msat",
+		"fee_paid_msat",
+		"counterparty",
+		"label",
+	];
+
+	fn fields(&self) -> Vec<String> {
+		vec![
+			optional_field(&self.created_at),
+			optional_field(&self.date),
+			optional_field(&self.resolved_at),
+			self.direction.clone(),
+			self.status.clone(),
+			self.payment_hash.clone(),
+			optional_field(&self.amount_msat),
+			optional_field(&self.fee_paid_msat),
+			optional_field(&self.counterparty),
+			optional_field(&self.label),
+		]
+	}
+}
+
+/// A forward, as exported for accounting
+#[derive(Serialize)]
+pub struct ForwardRecord {
+	/// Seconds since the UNIX epoch at which the forward was claimed
+	pub timestamp: u64,
+	/// `timestamp` as an RFC 3339 date in UTC
+	pub date: String,
+	pub prev_channel_id: Option<String>,
+	/// Peer of the channel the payment came in from
+	pub prev_peer: Option<String>,
+	pub next_channel_id: Option<String>,
+	/// Peer of the channel the payment went out through
+	pub next_peer: Option<String>,
+	pub amt_forwarded_msat: Option<u64>,
+	pub fee_earned_msat: Option<u64>,
+	pub claim_from_onchain_tx: bool,
+}
+
+impl ForwardRecord {
+	/// Describe `forward`, looking its channels up in `channel_peers`, the peers of the node's
+	/// current and closed channels by channel ID
+	pub fn new(forward: &ForwardInfo, channel_peers: &HashMap<String, String>) -> Self {
+		let peer = |channel_id: &Option<String>| {
+			channel_id.as_ref().and_then(|channel_id| channel_peers.get(channel_id)).cloned()
+		};
+		Self {
+			timestamp: forward.timestamp,
+			date: rfc3339_date(forward.timestamp),
+			prev_channel_id: forward.prev_channel_id.clone(),
+			prev_peer: peer(&forward.prev_channel_id),
+			next_channel_id: forward.next_channel_id.clone(),
+			next_peer: peer(&forward.next_channel_id),
+			amt_forwarded_msat: forward.amt_forwarded_msat,
+			fee_earned_msat: forward.fee_earned_msat,
+			claim_from_onchain_tx: forward.claim_from_onchain_tx,
+		}
+	}
+}
+
+impl ExportRecord for ForwardRecord {
+	const HEADER: &'static [&'static str] = &[
+		"timestamp",
+		"date",
+		"prev_channel_id",
+		"prev_peer",
+		"next_channel_id",
+		"next_peer",
+		"amt_forwarded_msat",
+		"fee_earned_msat",
+		"claim_from_onchain_tx",
+	];
+
+	fn fields(&self) -> Vec<String> {
+		vec![
+			self.timestamp.to_string(),
+			self.date.clone(),
+			optional_field(&self.prev_channel_id),
+			optional_field(&self.prev_peer),
+			optional_field(&self.next_channel_id),
+			optional_field(&self.next_peer),
+			optional_field(&self.amt_forwarded_msat),
+			optional_field(&self.fee_earned_msat),
+			self.claim_from_onchain_tx.to_string(),
+		]
+	}
+}
+
+/// Write `records` in `format`, CSV starting with a header line
+pub fn render<T: ExportRecord>(records: &[T], format: ExportFormat) -> String {
+	let mut output = String::new();
+	match format {
+		ExportFormat::Csv => {
+			output.push_str(&T::HEADER.join(","));
+			output.push_str("\r\n");
+			for record in records {
+				let fields: Vec<String> =
+					record.fields().iter().map(|field| csv_field(field)).collect();
+				output.push_str(&fields.join(","));
+				output.push_str("\r\n");
+			}
+		}
+		ExportFormat::JsonLines => {
+			for record in records {
+				output.push_str(&serde_json::to_string(record).unwrap());
+				output.push('\n');
+			}
+		}
+	}
+	output
+}
+
+fn optional_field<T: ToString>(field: &Option<T>) -> String {
+	field.as_ref().map(|field| field.to_string()).unwrap_or_default()
+}
+
+/// Quote a CSV field if it contains a separator, a quote or a line break, as in RFC 4180
+fn csv_field(field: &str) -> String {
+	if field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+		format!("\"{}\"", field.replace('"', "\"\""))
+	} else {
+		field.to_string()
+	}
+}
+
+fn rfc3339_date(timestamp: u64) -> String {
+	Utc.timestamp(timestamp as i64, 0).to_rfc3339()
+}
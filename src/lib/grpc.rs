@@ -0,0 +1,404 @@
+use crate::approval::PaymentRequest;
+use crate::auth::{self, Access, Caller};
+use crate::events::NodeEvent;
+use crate::hex_utils;
+use crate::node_var::InvoiceState;
+use crate::server::{self, ApiError, ErrorCode, NodeVar, ServerEventHandler};
+use bitcoin::secp256k1::PublicKey;
+use futures::Stream;
+use lightning_invoice::Invoice;
+use std::convert::TryFrom;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tonic::transport::{Identity, Server, ServerTlsConfig};
+use tonic::{Code, Request, Response, Status};
+
+/// Messages and service generated from `proto/lnnode.proto`
+pub mod proto {
+	tonic::include_proto!("lnnode");
+}
+
+use proto::node_event::Event as ProtoEvent;
+use proto::node_server::{Node, NodeServer};
+
+/// gRPC counterpart of the HTTP API, sharing its handlers' logic
+pub struct NodeService {
+	node_var: Arc<NodeVar<ServerEventHandler>>,
+}
+
+/// Serve the gRPC API on `addr`, over TLS with `identity` if given. Calls are rejected unless
+/// they carry an API token, whose scope each call then checks.
+pub async fn run(
+	node_var: Arc<NodeVar<ServerEventHandler>>, addr: SocketAddr, identity: Option<Identity>,
+) -> Result<(), tonic::transport::Error> {
+	let api_tokens = Arc::clone(&node_var.api_tokens);
+	let check_token = move |mut req: Request<()>| {
+		let authorization = req.metadata().get("authorization").and_then(|v| v.to_str().ok());
+		match auth::authorization_caller(authorization, &api_tokens) {
+			Some(caller) => {
+				req.extensions_mut().insert(caller);
+				Ok(req)
+			}
+			None => Err(Status::unauthenticated("missing or invalid API token")),
+		}
+	};
+
+	let mut server = Server::builder();
+	if let Some(identity) = identity {
+		server = server.tls_config(ServerTlsConfig::new().identity(identity))?;
+	}
+	server
+		.add_service(NodeServer::with_interceptor(NodeService { node_var }, check_token))
+		.serve(addr)
+		.await
+}
+
+/// Check that the token of `request` allows `access`, returning its caller
+fn authorize<T>(request: &Request<T>, access: Access) -> Result<Caller, Status> {
+	match request.extensions().get::<Caller>() {
+		Some(caller) if caller.scope.allows(access) => Ok(caller.clone()),
+		_ => Err(Status::permission_denied("the API token's scope doesn't allow this call")),
+	}
+}
+
+/// Answer an API error with the gRPC status closest to its HTTP status
+fn status(error: ApiError) -> Status {
+	let error = error.into_body();
+	let code = match error.code {
+		ErrorCode::InvalidRequest => Code::InvalidArgument,
+		ErrorCode::Unauthorized => Code::Unauthenticated,
+		ErrorCode::Forbidden => Code::PermissionDenied,
+		ErrorCode::NotFound => Code::NotFound,
+		ErrorCode::Conflict | ErrorCode::NoRoute => Code::FailedPrecondition,
+		ErrorCode::PaymentFailed => Code::Aborted,
+		ErrorCode::PeerUnreachable | ErrorCode::UpstreamError => Code::Unavailable,
+		ErrorCode::Timeout => Code::DeadlineExceeded,
+		ErrorCode::NotImplemented => Code::Unimplemented,
+		ErrorCode::Internal => Code::Internal,
+	};
+	let message = match error.detail {
+		Some(detail) => format!("{} ({})", error.message, detail),
+		None => error.message,
+	};
+	Status::new(code, message)
+}
+
+#[tonic::async_trait]
+impl Node for NodeService {
+	async fn get_info(
+		&self, request: Request<proto::GetInfoRequest>,
+	) -> Result<Response<proto::GetInfoResponse>, Status> {
+		authorize(&request, Access::Read)?;
+		let info = server::node_info(&self.node_var).await;
+		Ok(Response::new(proto::GetInfoResponse {
+			version: info.version,
+			pubkey: info.pubkey.to_string(),
+			alias: info.alias,
+			color: info.color,
+			network: info.network,
+			block_height: info.block_height,
+			block_hash: info.block_hash.to_string(),
+			synced_to_chain: info.synced_to_chain,
+			synced_to_graph: info.synced_to_graph,
+			addresses: info.addresses,
+			channels_number: info.channels_number as u64,
+			usable_channels_number: info.usable_channels_number as u64,
+			local_balance_msat: info.local_balance_msat,
+			onchain_confirmed_balance_sat: info.onchain_confirmed_balance_sat,
+			onchain_unconfirmed_balance_sat: info.onchain_unconfirmed_balance_sat,
+			peers: info.peers as u64,
+			listen_addresses: info.listen_addresses,
+		}))
+	}
+
+	async fn list_channels(
+		&self, request: Request<proto::ListChannelsRequest>,
+	) -> Result<Response<proto::ListChannelsResponse>, Status> {
+		authorize(&request, Access::Read)?;
+		let channels = server::channel_list(&self.node_var)
+			.channels
+			.into_iter()
+			.map(|channel| proto::Channel {
+				channel_id: channel.channel_id,
+				funding_txid: channel.tx_id,
+				peer_pubkey: channel.peer_pubkey,
+				peer_alias: channel.peer_alias,
+				short_channel_id: channel.short_channel_id,
+				is_confirmed_onchain: channel.is_confirmed_onchain,
+				local_balance_msat: channel.local_balance_msat,
+				channel_value_sat: channel.channel_value_satoshis,
+				available_balance_for_send_msat: channel.available_balance_for_send_msat,
+				available_balance_for_recv_msat: channel.available_balance_for_recv_msat,
+				channel_can_send_payments: channel.channel_can_send_payments,
+				public: channel.public,
+				peer_label: channel.peer_label,
+				label: channel.label,
+				tags: channel.tags,
+				counterparty_fee_base_msat: channel.counterparty_fee_base_msat,
+				counterparty_fee_proportional_millionths: channel
+					.counterparty_fee_proportional_millionths,
+				counterparty_cltv_expiry_delta: channel
+					.counterparty_cltv_expiry_delta
+					.map(u32::from),
+				fee_base_msat: channel.fee_base_msat,
+				fee_proportional_millionths: channel.fee_proportional_millionths,
+				cltv_expiry_delta: channel.cltv_expiry_delta.map(u32::from),
+				htlc_minimum_msat: channel.htlc_minimum_msat,
+				htlc_maximum_msat: channel.htlc_maximum_msat,
+				counterparty_reserve_sat: channel.counterparty_reserve_satoshis,
+				unspendable_punishment_reserve_sat: channel.unspendable_punishment_reserve_satoshis,
+			})
+			.collect();
+		Ok(Response::new(proto::ListChannelsResponse { channels }))
+	}
+
+	async fn open_channel(
+		&self, request: Request<proto::OpenChannelRequest>,
+	) -> Result<Response<proto::OpenChannelResponse>, Status> {
+		// Funding transactions aren't counted against spending limits
+		if authorize(&request, Access::Write)?.limits.is_limited() {
+			return Err(Status::permission_denied(
+				"tokens with spending limits can't open channels",
+			));
+		}
+		let request = request.into_inner();
+		let pubkey = request
+			.pubkey
+			.parse::<PublicKey>()
+			.map_err(|e| Status::invalid_argument(format!("invalid pubkey: {}", e)))?;
+		let port = u16::try_from(request.port)
+			.map_err(|_| Status::invalid_argument(format!("invalid port: {}", request.port)))?;
+		let req = server::OpenChannel {
+			pubkey,
+			host: request.host,
+			port,
+			channel_amt_satoshis: request.channel_amt_sat,
+			channel_announcement: Some(request.public.to_string()),
+			push_msat: request.push_msat,
+			config: None,
+			sat_per_vbyte: request.sat_per_vbyte.map(|feerate| feerate.to_string()),
+			target_conf: request.target_conf.map(|target| target.to_string()),
+		};
+		let peer_pubkey =
+			server::open_requested_channel(&self.node_var, &req).await.map_err(status)?;
+		Ok(Response::new(proto::OpenChannelResponse { peer_pubkey: peer_pubkey.to_string() }))
+	}
+
+	async fn list_invoices(
+		&self, request: Request<proto::ListInvoicesRequest>,
+	) -> Result<Response<proto::ListInvoicesResponse>, Status> {
+		authorize(&request, Access::InvoiceRead)?;
+		let invoices = server::invoice_list(&self.node_var)
+			.map_err(status)?
+			.invoices
+			.into_iter()
+			.map(|invoice| proto::Invoice {
+				payment_hash: invoice.payment_hash,
+				bolt11: invoice.invoice,
+				state: match invoice.state {
+					InvoiceState::Open => "open",
+					InvoiceState::Settled => "settled",
+					InvoiceState::Expired => "expired",
+					InvoiceState::Canceled => "canceled",
+				}
+				.to_string(),
+				description: invoice.description,
+				amt_msat: invoice.amt_msat,
+				label: invoice.label,
+				created_at: invoice.created_at,
+				expires_at: invoice.expires_at,
+				settled_at: invoice.settled_at,
+			})
+			.collect();
+		Ok(Response::new(proto::ListInvoicesResponse { invoices }))
+	}
+
+	async fn create_invoice(
+		&self, request: Request<proto::CreateInvoiceRequest>,
+	) -> Result<Response<proto::CreateInvoiceResponse>, Status> {
+		authorize(&request, Access::InvoiceWrite)?;
+		let request = request.into_inner();
+		let req = server::GetInvoice {
+			amt_millisatoshis: request.amt_msat,
+			description: request.description,
+			expiry_secs: request.expiry_secs,
+			label: request.label,
+		};
+		let invoice =
+			server::create_requested_invoice(&self.node_var, &req).await.map_err(status)?;
+		Ok(Response::new(proto::CreateInvoiceResponse {
+			bolt11: invoice.invoice,
+			payment_hash: invoice.payment_hash,
+			expiry_secs: invoice.expiry_secs,
+			label: invoice.label,
+		}))
+	}
+
+	async fn list_payments(
+		&self, request: Request<proto::ListPaymentsRequest>,
+	) -> Result<Response<proto::ListPaymentsResponse>, Status> {
+		authorize(&request, Access::Read)?;
+		let request = request.into_inner();
+		let req = server::ListPayments {
+			direction: request.direction,
+			status: request.status,
+			start_time: request.start_time.map(|time| time.to_string()),
+			end_time: request.end_time.map(|time| time.to_string()),
+			offset: request.offset.map(|offset| offset.to_string()),
+			limit: request.limit.map(|limit| limit.to_string()),
+		};
+		let payments = server::payment_list(&self.node_var, &req).await.map_err(status)?;
+		Ok(Response::new(proto::ListPaymentsResponse {
+			payments: payments
+				.payments
+				.into_iter()
+				.map(|payment| proto::Payment {
+					payment_hash: payment.payment_hash,
+					amt_msat: payment.amount_millisatoshis.parse().ok(),
+					direction: payment.htlc_direction,
+					status: payment.htlc_status,
+					label: payment.label,
+					fee_paid_msat: payment.fee_paid_msat,
+					created_at: payment.created_at,
+					resolved_at: payment.resolved_at,
+					paths: payment
+						.paths
+						.into_iter()
+						.map(|path| proto::PaymentPath {
+							hops: path
+								.into_iter()
+								.map(|hop| proto::PaymentHop {
+									pubkey: hop.pubkey.to_string(),
+									short_channel_id: hop.short_channel_id,
+									amt_to_forward_msat: hop.amt_to_forward_msat,
+									fee_msat: hop.fee_msat,
+								})
+								.collect(),
+						})
+						.collect(),
+					keysend: payment.keysend,
+				})
+				.collect(),
+			total_payments: payments.total_payments as u64,
+		}))
+	}
+
+	async fn send_payment(
+		&self, request: Request<proto::SendPaymentRequest>,
+	) -> Result<Response<proto::SendPaymentResponse>, Status> {
+		let caller = authorize(&request, Access::Write)?;
+		let request = request.into_inner();
+		if request.timeout_secs == Some(0) {
+			return Err(Status::invalid_argument("timeout_secs must be at least 1"));
+		}
+		let req = server::ServerInvoice {
+			invoice: request.bolt11,
+			amount_msat: request.amt_msat.map(|amt| amt.to_string()),
+			wait: None,
+			timeout_secs: request.timeout_secs.map(|timeout| timeout.to_string()),
+			max_fee_msat: request.max_fee_msat.map(|fee| fee.to_string()),
+			max_fee_percent: request.max_fee_percent.map(|percent| percent.to_string()),
+		};
+		let invoice = req
+			.invoice
+			.parse::<Invoice>()
+			.map_err(|e| Status::invalid_argument(format!("invalid invoice: {}", e)))?;
+		let amt_msat = server::requested_amount(&invoice, &req).map_err(status)?;
+		if self.node_var.approvals.requires_approval(amt_msat) {
+			let pending = self
+				.node_var
+				.approvals
+				.queue(PaymentRequest::SendPayment(req), amt_msat, caller)
+				.map_err(|e| Status::internal(format!("failed to queue the payment: {}", e)))?;
+			return Ok(Response::new(proto::SendPaymentResponse {
+				amt_msat,
+				approval_id: Some(pending.id),
+				..Default::default()
+			}));
+		}
+		let payment = server::initiate_payment(&self.node_var, &req, &caller, request.timeout_secs)
+			.await
+			.map_err(status)?;
+		Ok(Response::new(proto::SendPaymentResponse {
+			payment_hash: hex_utils::hex_str(&payment.payment_hash.0),
+			payment_id: hex_utils::hex_str(&payment.payment_id.0),
+			amt_msat: payment.amt_msat,
+			payee_pubkey: payment.payee_pubkey.to_string(),
+			approval_id: None,
+		}))
+	}
+
+	type SubscribeEventsStream =
+		Pin<Box<dyn Stream<Item = Result<proto::NodeEvent, Status>> + Send + 'static>>;
+
+	async fn subscribe_events(
+		&self, request: Request<proto::SubscribeEventsRequest>,
+	) -> Result<Response<Self::SubscribeEventsStream>, Status> {
+		authorize(&request, Access::Read)?;
+		let receiver = self.node_var.event_sender.subscribe();
+		let stream = futures::stream::unfold(receiver, |mut receiver| async move {
+			let event = match receiver.recv().await {
+				Ok(event) => proto_event(event),
+				// Let the subscriber know it missed events instead of silently skipping them
+				Err(broadcast::error::RecvError::Lagged(missed)) => ProtoEvent::Lagged(missed),
+				Err(broadcast::error::RecvError::Closed) => return None,
+			};
+			Some((Ok(proto::NodeEvent { event: Some(event) }), receiver))
+		});
+		Ok(Response::new(Box::pin(stream)))
+	}
+}
+
+fn proto_event(event: NodeEvent) -> ProtoEvent {
+	match event {
+		NodeEvent::FundingGenerationReady { temporary_channel_id, channel_value_sat } => {
+			ProtoEvent::FundingGenerationReady(proto::FundingGenerationReady {
+				temporary_channel_id,
+				channel_value_sat,
+			})
+		}
+		NodeEvent::PaymentReceived { payment_hash, amount_msat } => {
+			ProtoEvent::PaymentReceived(proto::PaymentReceived { payment_hash, amount_msat })
+		}
+		NodeEvent::PaymentSent { payment_hash, payment_preimage, fee_paid_msat } => {
+			ProtoEvent::PaymentSent(proto::PaymentSent {
+				payment_hash,
+				payment_preimage,
+				fee_paid_msat,
+			})
+		}
+		NodeEvent::PaymentPathFailed {
+			payment_hash,
+			rejected_by_dest,
+			all_paths_failed,
+			short_channel_id,
+		} => ProtoEvent::PaymentPathFailed(proto::PaymentPathFailed {
+			payment_hash,
+			rejected_by_dest,
+			all_paths_failed,
+			short_channel_id,
+		}),
+		NodeEvent::PaymentFailed { payment_hash } => {
+			ProtoEvent::PaymentFailed(proto::PaymentFailed { payment_hash })
+		}
+		NodeEvent::PaymentForwarded { fee_earned_msat, claim_from_onchain_tx } => {
+			ProtoEvent::PaymentForwarded(proto::PaymentForwarded {
+				fee_earned_msat,
+				claim_from_onchain_tx,
+			})
+		}
+		NodeEvent::SpendableOutputs { outputs_number } => {
+			ProtoEvent::SpendableOutputs(proto::SpendableOutputs {
+				outputs_number: outputs_number as u64,
+			})
+		}
+		NodeEvent::ChannelClosed { channel_id, user_channel_id, reason } => {
+			ProtoEvent::ChannelClosed(proto::ChannelClosed { channel_id, user_channel_id, reason })
+		}
+		NodeEvent::DiscardFunding { channel_id, txid } => {
+			ProtoEvent::DiscardFunding(proto::DiscardFunding { channel_id, txid })
+		}
+	}
+}
@@ -0,0 +1,248 @@
+use crate::disk;
+use crate::keys::NodeKeysManager;
+use crate::node_var::{
+	unix_timestamp, ChannelManager, HTLCStatus, InvoiceInfo, MillisatAmount, PaymentInfo,
+};
+use crate::storage::NodeStore;
+use bech32::ToBase32;
+use bitcoin::hashes::sha256::Hash as Sha256;
+use bitcoin::hashes::Hash;
+use bitcoin::network::constants::Network;
+use lightning::chain::keysinterface::{KeysInterface, Recipient};
+use lightning::ln::channelmanager::{ChannelDetails, PhantomRouteHints, MIN_CLTV_EXPIRY_DELTA};
+use lightning::ln::PaymentHash;
+use lightning::routing::network_graph::RoutingFees;
+use lightning::routing::router::{RouteHint, RouteHintHop};
+use lightning_invoice::{Currency, Invoice, InvoiceBuilder};
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::warn;
+
+/// Expiry of invoices created without an explicit one, matching `lightning-invoice`'s utils
+pub const DEFAULT_INVOICE_EXPIRY_SECS: u32 = 3600;
+
+/// Maximum length of an invoice description, as it has to fit in a single tagged field
+pub const MAX_DESCRIPTION_LEN: usize = 639;
+
+/// Currency of the invoices of a node running on `network`
+pub(crate) fn currency(network: Network) -> Currency {
+	match network {
+		Network::Bitcoin => Currency::Bitcoin,
+		Network::Testnet => Currency::BitcoinTestnet,
+		Network::Regtest => Currency::Regtest,
+		Network::Signet => Currency::Signet,
+	}
+}
+
+/// Create an invoice for a new inbound payment expiring after `expiry_secs`, whose HTLCs have
+/// to expire at least `min_final_cltv_expiry` blocks after they are received.
+///
+/// This mirrors `lightning_invoice::utils::create_invoice_from_channelmanager`, which doesn't
+/// let the expiry be set in lightning-invoice 0.14, including its route hints for private
+/// channels.
+pub(crate) fn create_invoice(
+	channel_manager: &ChannelManager, keys_manager: &NodeKeysManager, currency: Currency,
+	amt_msat: Option<u64>, description: String, expiry_secs: u32, min_final_cltv_expiry: u32,
+) -> Result<Invoice, String> {
+	let route_hints = channel_manager
+		.list_usable_channels()
+		.iter()
+		.filter_map(|channel| channel_hint(channel).map(|hop| RouteHint(vec![hop])))
+		.collect();
+	build_invoice(
+		channel_manager,
+		keys_manager,
+		currency,
+		amt_msat,
+		Description::Direct(description),
+		expiry_secs,
+		min_final_cltv_expiry,
+		route_hints,
+		Recipient::Node,
+	)
+}
+
+/// Create an invoice committing to the hash of its description instead of including it, as
+/// LNURL-pay requires
+pub(crate) fn create_invoice_with_description_hash(
+	channel_manager: &ChannelManager, keys_manager: &NodeKeysManager, currency: Currency,
+	amt_msat: Option<u64>, description_hash: Sha256, expiry_secs: u32, min_final_cltv_expiry: u32,
+) -> Result<Invoice, String> {
+	let route_hints = channel_manager
+		.list_usable_channels()
+		.iter()
+		.filter_map(|channel| channel_hint(channel).map(|hop| RouteHint(vec![hop])))
+		.collect();
+	build_invoice(
+		channel_manager,
+		keys_manager,
+		currency,
+		amt_msat,
+		Description::Hash(description_hash),
+		expiry_secs,
+		min_final_cltv_expiry,
+		route_hints,
+		Recipient::Node,
+	)
+}
+
+/// Track the inbound payment of an invoice created by the node and persist the invoice, whose
+/// `description` is recorded even if the invoice only commits to its hash
+pub(crate) fn record_invoice(
+	store: &dyn NodeStore, payments: &mut HashMap<PaymentHash, PaymentInfo>, inv: &Invoice,
+	amt_msat: u64, description: String, label: Option<String>,
+) -> PaymentHash {
+	let payment_hash = PaymentHash(inv.payment_hash().clone().into_inner());
+	payments.insert(
+		payment_hash,
+		PaymentInfo {
+			preimage: None,
+			secret: Some(inv.payment_secret().clone()),
+			status: HTLCStatus::Pending,
+			amt_msat: MillisatAmount(Some(amt_msat)),
+			label: label.clone(),
+			payment_id: None,
+			fee_paid_msat: None,
+			created_at: Some(unix_timestamp()),
+			resolved_at: None,
+			paths: Vec::new(),
+			keysend: false,
+		},
+	);
+	if disk::persist_payment(
+		store,
+		disk::INBOUND_PAYMENTS_NAMESPACE,
+		&payment_hash,
+		&payments[&payment_hash],
+	)
+	.is_err()
+	{
+		warn!("failed to persist inbound payment, check your disk and permissions");
+	}
+
+	let created_at = unix_timestamp();
+	let invoice_info = InvoiceInfo {
+		bolt11: inv.to_string(),
+		description,
+		amt_msat: Some(amt_msat),
+		label,
+		created_at,
+		expires_at: created_at + inv.expiry_time().as_secs(),
+		settled_at: None,
+		canceled: false,
+	};
+	if disk::persist_invoice(store, &payment_hash, &invoice_info).is_err() {
+		warn!("failed to persist invoice, check your disk and permissions");
+	}
+	payment_hash
+}
+
+/// Create an invoice payable to the phantom node, i.e. to any of the nodes sharing the node's
+/// phantom secret, whose route hints are `phantom_route_hints`. The hints of the node itself
+/// are always included.
+///
+/// This mirrors `lightning_invoice::utils::create_phantom_invoice`, adding the expiry. Each
+/// hint routes over a channel of one of the nodes and then over that node's fake channel to the
+/// phantom node, which the node receives the payment for.
+pub(crate) fn create_phantom_invoice(
+	channel_manager: &ChannelManager, keys_manager: &NodeKeysManager, currency: Currency,
+	amt_msat: Option<u64>, description: String, expiry_secs: u32, min_final_cltv_expiry: u32,
+	mut phantom_route_hints: Vec<PhantomRouteHints>,
+) -> Result<Invoice, String> {
+	phantom_route_hints.push(channel_manager.get_phantom_route_hints());
+	let mut route_hints = Vec::new();
+	for hints in phantom_route_hints {
+		for channel in hints.channels.iter() {
+			if let Some(hop) = channel_hint(channel) {
+				route_hints.push(RouteHint(vec![
+					hop,
+					RouteHintHop {
+						src_node_id: hints.real_node_pubkey,
+						short_channel_id: hints.phantom_scid,
+						fees: RoutingFees { base_msat: 0, proportional_millionths: 0 },
+						cltv_expiry_delta: MIN_CLTV_EXPIRY_DELTA,
+						htlc_minimum_msat: None,
+						htlc_maximum_msat: None,
+					},
+				]));
+			}
+		}
+	}
+	if route_hints.is_empty() {
+		return Err(
+			"none of the nodes has a usable channel the phantom node can be reached through"
+				.to_string(),
+		);
+	}
+	build_invoice(
+		channel_manager,
+		keys_manager,
+		currency,
+		amt_msat,
+		Description::Direct(description),
+		expiry_secs,
+		min_final_cltv_expiry,
+		route_hints,
+		Recipient::PhantomNode,
+	)
+}
+
+/// Hint of the hop from the counterparty of `channel` to the node, if the channel is usable
+fn channel_hint(channel: &ChannelDetails) -> Option<RouteHintHop> {
+	let short_channel_id = channel.short_channel_id?;
+	let forwarding_info = channel.counterparty.forwarding_info.as_ref()?;
+	Some(RouteHintHop {
+		src_node_id: channel.counterparty.node_id,
+		short_channel_id,
+		fees: RoutingFees {
+			base_msat: forwarding_info.fee_base_msat,
+			proportional_millionths: forwarding_info.fee_proportional_millionths,
+		},
+		cltv_expiry_delta: forwarding_info.cltv_expiry_delta,
+		htlc_minimum_msat: None,
+		htlc_maximum_msat: None,
+	})
+}
+
+enum Description {
+	Direct(String),
+	Hash(Sha256),
+}
+
+/// Build an invoice for a new inbound payment with `route_hints`, signed by `recipient`
+fn build_invoice(
+	channel_manager: &ChannelManager, keys_manager: &NodeKeysManager, currency: Currency,
+	amt_msat: Option<u64>, description: Description, expiry_secs: u32, min_final_cltv_expiry: u32,
+	route_hints: Vec<RouteHint>, recipient: Recipient,
+) -> Result<Invoice, String> {
+	// LDK only refuses amounts above the total bitcoin supply
+	let (payment_hash, payment_secret) = channel_manager
+		.create_inbound_payment(amt_msat, expiry_secs)
+		.map_err(|()| "the invoice amount is too large".to_string())?;
+	let invoice = InvoiceBuilder::new(currency);
+	let invoice = match description {
+		Description::Direct(description) => invoice.description(description),
+		Description::Hash(description_hash) => invoice.description_hash(description_hash),
+	};
+	let mut invoice = invoice
+		.current_timestamp()
+		.payment_hash(Sha256::from_slice(&payment_hash.0).unwrap())
+		.payment_secret(payment_secret)
+		.basic_mpp()
+		.min_final_cltv_expiry(min_final_cltv_expiry.into())
+		.expiry_time(Duration::from_secs(expiry_secs.into()));
+	if let Some(amt) = amt_msat {
+		invoice = invoice.amount_milli_satoshis(amt);
+	}
+	for hint in route_hints {
+		invoice = invoice.private_route(hint);
+	}
+
+	let raw_invoice = invoice.build_raw().map_err(|e| format!("{:?}", e))?;
+	let hrp_str = raw_invoice.hrp.to_string();
+	let data_without_signature = raw_invoice.data.to_base32();
+	let signed_raw_invoice = raw_invoice
+		.sign(|_| keys_manager.sign_invoice(hrp_str.as_bytes(), &data_without_signature, recipient))
+		.map_err(|_| "failed to sign invoice".to_string())?;
+	Invoice::from_signed(signed_raw_invoice).map_err(|e| format!("{:?}", e))
+}
@@ -0,0 +1,179 @@
+use crate::events::NodeEvent;
+use crate::hex_utils;
+use crate::node_var::unix_timestamp;
+use crate::storage::NodeStore;
+use lightning::util::events::Event;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::sync::{Arc, Mutex};
+use utoipa::ToSchema;
+
+pub(crate) const EVENT_JOURNAL_NAMESPACE: &str = "event_journal";
+
+/// An LDK event the node handled, and what it did about it
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct JournalEntry {
+	/// Position of the event in the journal, increasing with every event
+	pub id: u64,
+	/// Seconds since the UNIX epoch at which the event was handled
+	pub timestamp: u64,
+	/// Type of the event, e.g. `payment_failed`
+	pub event_type: String,
+	pub payment_hash: Option<String>,
+	/// Channel the event is about, identified by its temporary ID until it is funded
+	pub channel_id: Option<String>,
+	/// Fields of the event, as the `/events` stream pushes them
+	#[schema(value_type = Object)]
+	pub event: serde_json::Value,
+	/// What the node did with the event
+	pub resolution: String,
+}
+
+/// Filters of the journal's entries, all of which have to match
+#[derive(Default)]
+pub(crate) struct JournalFilter {
+	pub event_type: Option<String>,
+	pub payment_hash: Option<String>,
+	pub channel_id: Option<String>,
+	pub start_time: Option<u64>,
+	pub end_time: Option<u64>,
+}
+
+impl JournalFilter {
+	fn matches(&self, entry: &JournalEntry) -> bool {
+		self.event_type.as_ref().map_or(true, |event_type| *event_type == entry.event_type)
+			&& self
+				.payment_hash
+				.as_ref()
+				.map_or(true, |hash| Some(hash) == entry.payment_hash.as_ref())
+			&& self.channel_id.as_ref().map_or(true, |id| Some(id) == entry.channel_id.as_ref())
+			&& self.start_time.map_or(true, |start| entry.timestamp >= start)
+			&& self.end_time.map_or(true, |end| entry.timestamp <= end)
+	}
+}
+
+/// Append-only journal of the LDK events the node handled, so that failed payments and closed
+/// channels can be looked into after the fact. Entries are never rewritten, and are keyed by
+/// their ID so that they list in order.
+pub struct EventJournal {
+	store: Arc<dyn NodeStore>,
+	next_id: Mutex<u64>,
+}
+
+impl EventJournal {
+	pub(crate) fn new(store: Arc<dyn NodeStore>) -> io::Result<Self> {
+		let mut next_id = 0;
+		for (key, _) in store.list(EVENT_JOURNAL_NAMESPACE)? {
+			let id: u64 = key.parse().map_err(|_| {
+				io::Error::new(io::ErrorKind::InvalidData, format!("invalid journal entry {}", key))
+			})?;
+			next_id = next_id.max(id + 1);
+		}
+		Ok(Self { store, next_id: Mutex::new(next_id) })
+	}
+
+	/// Append `event` with its `resolution`
+	pub(crate) fn record(&self, event: &Event, resolution: String) -> io::Result<()> {
+		let mut next_id = self.next_id.lock().unwrap();
+		let (payment_hash, channel_id) = event_subjects(event);
+		let entry = JournalEntry {
+			id: *next_id,
+			timestamp: unix_timestamp(),
+			event_type: event_type(event).to_string(),
+			payment_hash,
+			channel_id,
+			event: event_fields(event),
+			resolution,
+		};
+		let value = serde_json::to_vec(&entry)
+			.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+		self.store.write(EVENT_JOURNAL_NAMESPACE, &format!("{:020}", entry.id), &value)?;
+		*next_id += 1;
+		Ok(())
+	}
+
+	/// The entries matching `filter`, most recent first
+	pub(crate) fn entries(&self, filter: &JournalFilter) -> io::Result<Vec<JournalEntry>> {
+		let mut entries = Vec::new();
+		for (_, value) in self.store.list(EVENT_JOURNAL_NAMESPACE)? {
+			let entry: JournalEntry = serde_json::from_slice(&value)
+				.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+			if filter.matches(&entry) {
+				entries.push(entry);
+			}
+		}
+		entries.sort_by(|a, b| b.id.cmp(&a.id));
+		Ok(entries)
+	}
+}
+
+fn event_type(event: &Event) -> &'static str {
+	match event {
+		Event::FundingGenerationReady { .. } => "funding_generation_ready",
+		Event::PaymentReceived { .. } => "payment_received",
+		Event::PaymentSent { .. } => "payment_sent",
+		Event::PaymentPathFailed { .. } => "payment_path_failed",
+		Event::PaymentFailed { .. } => "payment_failed",
+		Event::PaymentForwarded { .. } => "payment_forwarded",
+		Event::PendingHTLCsForwardable { .. } => "pending_htlcs_forwardable",
+		Event::SpendableOutputs { .. } => "spendable_outputs",
+		Event::ChannelClosed { .. } => "channel_closed",
+		Event::DiscardFunding { .. } => "discard_funding",
+		Event::PaymentPathSuccessful { .. } => "payment_path_successful",
+		Event::OpenChannelRequest { .. } => "open_channel_request",
+	}
+}
+
+/// The payment and the channel `event` is about
+fn event_subjects(event: &Event) -> (Option<String>, Option<String>) {
+	let payment_hash = match event {
+		Event::PaymentReceived { payment_hash, .. }
+		| Event::PaymentSent { payment_hash, .. }
+		| Event::PaymentPathFailed { payment_hash, .. }
+		| Event::PaymentFailed { payment_hash, .. } => Some(hex_utils::hex_str(&payment_hash.0)),
+		Event::PaymentPathSuccessful { payment_hash, .. } => {
+			payment_hash.map(|payment_hash| hex_utils::hex_str(&payment_hash.0))
+		}
+		_ => None,
+	};
+	let channel_id = match event {
+		Event::FundingGenerationReady { temporary_channel_id, .. }
+		| Event::OpenChannelRequest { temporary_channel_id, .. } => {
+			Some(hex_utils::hex_str(temporary_channel_id))
+		}
+		Event::ChannelClosed { channel_id, .. } | Event::DiscardFunding { channel_id, .. } => {
+			Some(hex_utils::hex_str(channel_id))
+		}
+		_ => None,
+	};
+	(payment_hash, channel_id)
+}
+
+/// Fields of `event` worth keeping, leaving out the preimages of received payments
+fn event_fields(event: &Event) -> serde_json::Value {
+	if let Some(node_event) = NodeEvent::from_ldk(event) {
+		return serde_json::to_value(node_event).unwrap_or(serde_json::Value::Null);
+	}
+	match event {
+		Event::OpenChannelRequest {
+			temporary_channel_id,
+			counterparty_node_id,
+			funding_satoshis,
+			push_msat,
+			..
+		} => serde_json::json!({
+			"temporary_channel_id": hex_utils::hex_str(temporary_channel_id),
+			"counterparty_node_id": counterparty_node_id.to_string(),
+			"funding_sat": funding_satoshis,
+			"push_msat": push_msat,
+		}),
+		Event::PaymentPathSuccessful { payment_id, path, .. } => serde_json::json!({
+			"payment_id": hex_utils::hex_str(&payment_id.0),
+			"hops": path.len(),
+		}),
+		Event::PendingHTLCsForwardable { time_forwardable } => serde_json::json!({
+			"time_forwardable_ms": time_forwardable.as_millis() as u64,
+		}),
+		_ => serde_json::Value::Null,
+	}
+}
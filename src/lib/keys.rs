@@ -0,0 +1,128 @@
+use bitcoin::bech32::u5;
+use bitcoin::blockdata::script::Script;
+use bitcoin::blockdata::transaction::{Transaction, TxOut};
+use bitcoin::secp256k1::key::SecretKey;
+use bitcoin::secp256k1::recovery::RecoverableSignature;
+use bitcoin::secp256k1::{Secp256k1, Signing};
+use lightning::chain::keysinterface::{
+	InMemorySigner, KeyMaterial, KeysInterface, KeysManager, PhantomKeysManager, Recipient,
+	SpendableOutputDescriptor,
+};
+use lightning::ln::msgs::DecodeError;
+use lightning::ln::script::ShutdownScript;
+
+/// Keys manager of the node, whose keys are derived from its seed by LDK's `KeysManager`.
+///
+/// `PhantomKeysManager` derives the same keys, except for the key material of inbound payments,
+/// which it derives from the secret shared by the nodes of a phantom node. It is only used that
+/// way once phantom node payments are enabled, so that the invoices created before keep working
+/// otherwise.
+pub struct NodeKeysManager {
+	inner: PhantomKeysManager,
+	/// Key material of inbound payments, unless phantom node payments are enabled
+	inbound_payment_key: Option<KeyMaterial>,
+}
+
+impl NodeKeysManager {
+	/// Derive the keys of the node from `seed`, and those of inbound payments from `phantom_seed`
+	/// if phantom node payments are enabled
+	pub fn new(
+		seed: &[u8; 32], starting_time_secs: u64, starting_time_nanos: u32,
+		phantom_seed: Option<&[u8; 32]>,
+	) -> Self {
+		match phantom_seed {
+			Some(phantom_seed) => Self {
+				inner: PhantomKeysManager::new(
+					seed,
+					starting_time_secs,
+					starting_time_nanos,
+					phantom_seed,
+				),
+				inbound_payment_key: None,
+			},
+			None => {
+				let keys_manager = KeysManager::new(seed, starting_time_secs, starting_time_nanos);
+				Self {
+					inner: PhantomKeysManager::new(
+						seed,
+						starting_time_secs,
+						starting_time_nanos,
+						seed,
+					),
+					inbound_payment_key: Some(keys_manager.get_inbound_payment_key_material()),
+				}
+			}
+		}
+	}
+
+	/// Whether the node receives payments to phantom invoices
+	pub fn phantom_enabled(&self) -> bool {
+		self.inbound_payment_key.is_none()
+	}
+
+	/// See `KeysManager::spend_spendable_outputs`
+	pub fn spend_spendable_outputs<C: Signing>(
+		&self, descriptors: &[&SpendableOutputDescriptor], outputs: Vec<TxOut>,
+		change_destination_script: Script, feerate_sat_per_1000_weight: u32,
+		secp_ctx: &Secp256k1<C>,
+	) -> Result<Transaction, ()> {
+		self.inner.spend_spendable_outputs(
+			descriptors,
+			outputs,
+			change_destination_script,
+			feerate_sat_per_1000_weight,
+			secp_ctx,
+		)
+	}
+}
+
+impl KeysInterface for NodeKeysManager {
+	type Signer = InMemorySigner;
+
+	fn get_node_secret(&self, recipient: Recipient) -> Result<SecretKey, ()> {
+		match recipient {
+			// HTLCs to phantom short channel IDs are failed without the phantom node's key
+			Recipient::PhantomNode if !self.phantom_enabled() => Err(()),
+			_ => self.inner.get_node_secret(recipient),
+		}
+	}
+
+	fn get_inbound_payment_key_material(&self) -> KeyMaterial {
+		match &self.inbound_payment_key {
+			Some(inbound_payment_key) => *inbound_payment_key,
+			None => self.inner.get_inbound_payment_key_material(),
+		}
+	}
+
+	fn get_destination_script(&self) -> Script {
+		self.inner.get_destination_script()
+	}
+
+	fn get_shutdown_scriptpubkey(&self) -> ShutdownScript {
+		self.inner.get_shutdown_scriptpubkey()
+	}
+
+	fn get_channel_signer(&self, inbound: bool, channel_value_satoshis: u64) -> Self::Signer {
+		self.inner.get_channel_signer(inbound, channel_value_satoshis)
+	}
+
+	fn get_secure_random_bytes(&self) -> [u8; 32] {
+		self.inner.get_secure_random_bytes()
+	}
+
+	fn read_chan_signer(&self, reader: &[u8]) -> Result<Self::Signer, DecodeError> {
+		self.inner.read_chan_signer(reader)
+	}
+
+	fn sign_invoice(
+		&self, hrp_bytes: &[u8], invoice_data: &[u5], recipient: Recipient,
+	) -> Result<RecoverableSignature, ()> {
+		// `PhantomKeysManager` always has a key for the phantom node
+		if let Recipient::PhantomNode = recipient {
+			if !self.phantom_enabled() {
+				return Err(());
+			}
+		}
+		self.inner.sign_invoice(hrp_bytes, invoice_data, recipient)
+	}
+}
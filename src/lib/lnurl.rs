@@ -0,0 +1,108 @@
+use bech32::FromBase32;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Parameters of an LNURL-withdraw service, as defined by LUD-03
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WithdrawRequest {
+	/// URL the invoice is submitted to
+	pub callback: String,
+	/// Secret identifying the withdrawal to the service
+	pub k1: String,
+	pub min_withdrawable: u64,
+	pub max_withdrawable: u64,
+	#[serde(default)]
+	pub default_description: String,
+}
+
+/// Status every LNURL response carries, set to `ERROR` along with a reason on failure
+#[derive(Deserialize)]
+struct LnurlStatus {
+	status: Option<String>,
+	reason: Option<String>,
+}
+
+/// Client of LNURL services, which are reached over HTTPS
+pub struct LnurlClient {
+	client: reqwest::Client,
+}
+
+impl LnurlClient {
+	pub fn new() -> Result<Self, String> {
+		let client = reqwest::Client::builder()
+			.timeout(Duration::from_secs(30))
+			.build()
+			.map_err(|e| e.to_string())?;
+		Ok(Self { client })
+	}
+
+	/// Fetch the parameters of the withdrawal `lnurl` points to
+	pub async fn fetch_withdraw_request(&self, lnurl: &str) -> Result<WithdrawRequest, String> {
+		let url = decode_lnurl(lnurl)?;
+		let response = self.get_json(self.client.get(&url)).await?;
+		match response.get("tag").and_then(|tag| tag.as_str()) {
+			Some("withdrawRequest") => {}
+			Some(tag) => return Err(format!("the LNURL is a {}, not a withdrawRequest", tag)),
+			None => return Err("the LNURL service didn't describe a withdrawal".to_string()),
+		}
+		serde_json::from_value(response)
+			.map_err(|e| format!("invalid withdrawal parameters from the LNURL service: {}", e))
+	}
+
+	/// Submit `invoice` to the service of `request` for it to pay
+	pub async fn submit_withdraw_invoice(
+		&self, request: &WithdrawRequest, invoice: &str,
+	) -> Result<(), String> {
+		let query = [("k1", request.k1.as_str()), ("pr", invoice)];
+		self.get_json(self.client.get(&request.callback).query(&query)).await?;
+		Ok(())
+	}
+
+	/// Send `request`, failing with the reason given by the service if it reports an error
+	async fn get_json(
+		&self, request: reqwest::RequestBuilder,
+	) -> Result<serde_json::Value, String> {
+		let response = request
+			.send()
+			.await
+			.map_err(|e| format!("failed to reach the LNURL service: {}", e))?;
+		let response: serde_json::Value = response
+			.json()
+			.await
+			.map_err(|e| format!("invalid response from the LNURL service: {}", e))?;
+		let status = serde_json::from_value::<LnurlStatus>(response.clone())
+			.map_err(|e| format!("invalid response from the LNURL service: {}", e))?;
+		if status.status.as_deref() == Some("ERROR") {
+			return Err(format!(
+				"the LNURL service returned an error: {}",
+				status.reason.unwrap_or_default()
+			));
+		}
+		Ok(response)
+	}
+}
+
+/// URL encoded by `lnurl`, either a bech32 `lnurl1...` string, optionally prefixed with
+/// `lightning:`, an `lnurlw://` URL as in LUD-17, or a plain HTTPS URL
+pub fn decode_lnurl(lnurl: &str) -> Result<String, String> {
+	let lnurl = lnurl.trim();
+	let lnurl = match lnurl.get(.."lightning:".len()) {
+		Some(prefix) if prefix.eq_ignore_ascii_case("lightning:") => &lnurl["lightning:".len()..],
+		_ => lnurl,
+	};
+	let lowercase = lnurl.to_lowercase();
+	if lowercase.starts_with("lnurlw://") {
+		return Ok(format!("https://{}", &lnurl["lnurlw://".len()..]));
+	}
+	if lowercase.starts_with("https://") {
+		return Ok(lnurl.to_string());
+	}
+
+	let (hrp, data, _) = bech32::decode(lnurl).map_err(|e| format!("invalid LNURL: {}", e))?;
+	if hrp != "lnurl" {
+		return Err(format!("invalid LNURL: unexpected prefix {}", hrp));
+	}
+	let bytes = Vec::<u8>::from_base32(&data).map_err(|e| format!("invalid LNURL: {}", e))?;
+	String::from_utf8(bytes).map_err(|_| "invalid LNURL: the URL isn't UTF-8".to_string())
+}
@@ -0,0 +1,232 @@
+use crate::hex_utils;
+use crate::settings::{LogFormat, LoggingSettings};
+use rand::{thread_rng, Rng};
+use serde::Serialize;
+use std::fs;
+use std::future::Future;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::filter::Filtered;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, EnvFilter, Layer, Registry};
+
+tokio::task_local! {
+	/// ID of the HTTP request being handled, set by the API server around each handler
+	static REQUEST_ID: String;
+}
+
+/// Log targets of the HTTP and gRPC servers
+const HTTP_TARGETS: [&str; 5] =
+	["lnnode::server", "lnnode::auth", "lnnode::grpc", "actix_web", "actix_server"];
+
+/// Log targets of the chain sources and of the sweeper of on-chain outputs
+const CHAIN_TARGETS: [&str; 5] = [
+	"lnnode::bitcoind_client",
+	"lnnode::electrum",
+	"lnnode::esplora",
+	"lnnode::sweeper",
+	"lightning_block_sync",
+];
+
+/// Levels of the node's logs. Unset target levels follow `level`, except that the log file gets
+/// every log of LDK unless `ldk` is set.
+#[derive(Clone, Debug, Serialize)]
+pub struct LogLevels {
+	/// Filter of the logs, e.g. `info` or `info,lnnode::zaps=debug`
+	pub level: String,
+	/// Level of LDK's logs
+	pub ldk: Option<String>,
+	/// Level of the HTTP and gRPC servers' logs
+	pub http: Option<String>,
+	/// Level of the chain sources' logs
+	pub chain: Option<String>,
+}
+
+impl LogLevels {
+	/// Filter of the logs written to the standard output, or to the log file if `file`
+	fn filter(&self, file: bool) -> Result<EnvFilter, String> {
+		let mut directives = self.level.clone();
+		let target_levels = [
+			(&self.ldk, &["ldk"][..]),
+			(&self.http, &HTTP_TARGETS[..]),
+			(&self.chain, &CHAIN_TARGETS[..]),
+		];
+		for (level, targets) in target_levels {
+			if let Some(level) = level {
+				level.parse::<LevelFilter>().map_err(|_| format!("invalid log level {}", level))?;
+				for target in targets {
+					directives.push_str(&format!(",{}={}", target, level));
+				}
+			}
+		}
+		if file && self.ldk.is_none() {
+			directives.push_str(",ldk=trace");
+		}
+		EnvFilter::try_new(&directives)
+			.map_err(|e| format!("invalid log filter {}: {}", directives, e))
+	}
+
+	/// Most verbose level of LDK's logs, in either output
+	fn ldk_max_level(&self) -> LevelFilter {
+		match &self.ldk {
+			Some(level) => level.parse().unwrap_or(LevelFilter::TRACE),
+			None => LevelFilter::TRACE,
+		}
+	}
+}
+
+/// An output of the logs with its filter. The whole layer is reloaded, as a per-layer filter
+/// can't be reloaded on its own.
+type FilteredLayer = Filtered<Box<dyn Layer<Registry> + Send + Sync>, EnvFilter, Registry>;
+
+/// Filters of the running subscriber, which can be changed without restarting the node
+pub struct LogFilters {
+	levels: Mutex<LogLevels>,
+	ldk_max_level: RwLock<LevelFilter>,
+	stdout_layer: reload::Handle<FilteredLayer, Registry>,
+	file_layer: reload::Handle<FilteredLayer, Registry>,
+}
+
+impl LogFilters {
+	pub(crate) fn levels(&self) -> LogLevels {
+		self.levels.lock().unwrap().clone()
+	}
+
+	/// Filter the logs by `levels` from now on
+	pub(crate) fn set_levels(&self, levels: LogLevels) -> Result<(), String> {
+		let stdout_filter = levels.filter(false)?;
+		let file_filter = levels.filter(true)?;
+		let mut current_levels = self.levels.lock().unwrap();
+		self.stdout_layer
+			.modify(|layer| *layer.filter_mut() = stdout_filter)
+			.map_err(|e| e.to_string())?;
+		self.file_layer
+			.modify(|layer| *layer.filter_mut() = file_filter)
+			.map_err(|e| e.to_string())?;
+		*self.ldk_max_level.write().unwrap() = levels.ldk_max_level();
+		*current_levels = levels;
+		Ok(())
+	}
+
+	/// Whether LDK's logs of `level` are written anywhere, so that others aren't even formatted
+	pub(crate) fn ldk_enabled(&self, level: tracing::Level) -> bool {
+		level <= *self.ldk_max_level.read().unwrap()
+	}
+}
+
+/// Log to the standard output, and to `<ldk_data_dir>/logs/logs.txt`, rotated once it grows past
+/// the configured size
+pub(crate) fn init(ldk_data_dir: &str, settings: &LoggingSettings) -> io::Result<Arc<LogFilters>> {
+	let logs_dir = format!("{}/logs", ldk_data_dir);
+	fs::create_dir_all(&logs_dir)?;
+	let log_file = RotatingFile::open(
+		format!("{}/logs.txt", logs_dir),
+		settings.max_file_size_mb.map(|size_mb| size_mb * 1024 * 1024),
+		settings.max_files,
+	)?;
+
+	let levels = LogLevels {
+		level: std::env::var("RUST_LOG").unwrap_or_else(|_| settings.level.clone()),
+		ldk: settings.ldk.clone(),
+		http: settings.http.clone(),
+		chain: settings.chain.clone(),
+	};
+	let invalid_levels = |e: String| io::Error::new(io::ErrorKind::InvalidInput, e);
+	let stdout_filter = levels.filter(false).map_err(invalid_levels)?;
+	let file_filter = levels.filter(true).map_err(invalid_levels)?;
+
+	let (stdout_layer, file_layer) = match settings.format {
+		LogFormat::Text => (
+			fmt::layer().boxed(),
+			fmt::layer().with_ansi(false).with_writer(Mutex::new(log_file)).boxed(),
+		),
+		LogFormat::Json => (
+			fmt::layer().json().boxed(),
+			fmt::layer().json().with_writer(Mutex::new(log_file)).boxed(),
+		),
+	};
+	let (stdout_layer, stdout_handle) = reload::Layer::new(stdout_layer.with_filter(stdout_filter));
+	let (file_layer, file_handle) = reload::Layer::new(file_layer.with_filter(file_filter));
+	tracing_subscriber::registry()
+		.with(vec![stdout_layer, file_layer])
+		.try_init()
+		.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+
+	Ok(Arc::new(LogFilters {
+		ldk_max_level: RwLock::new(levels.ldk_max_level()),
+		levels: Mutex::new(levels),
+		stdout_layer: stdout_handle,
+		file_layer: file_handle,
+	}))
+}
+
+/// Log file which is renamed to `<path>.1` once it grows past `max_size` bytes, shifting the
+/// older ones up to `<path>.<max_files>`
+struct RotatingFile {
+	path: String,
+	file: fs::File,
+	size: u64,
+	max_size: Option<u64>,
+	max_files: usize,
+}
+
+impl RotatingFile {
+	fn open(path: String, max_size: Option<u64>, max_files: usize) -> io::Result<Self> {
+		let file = fs::OpenOptions::new().create(true).append(true).open(&path)?;
+		let size = file.metadata()?.len();
+		Ok(Self { path, file, size, max_size, max_files })
+	}
+
+	fn rotate(&mut self) -> io::Result<()> {
+		for i in (1..self.max_files).rev() {
+			let older = format!("{}.{}", self.path, i);
+			if Path::new(&older).exists() {
+				fs::rename(&older, format!("{}.{}", self.path, i + 1))?;
+			}
+		}
+		if self.max_files > 0 {
+			fs::rename(&self.path, format!("{}.1", self.path))?;
+		}
+		self.file =
+			fs::OpenOptions::new().create(true).write(true).truncate(true).open(&self.path)?;
+		self.size = 0;
+		Ok(())
+	}
+}
+
+impl Write for RotatingFile {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		if let Some(max_size) = self.max_size {
+			if self.size > 0 && self.size + buf.len() as u64 > max_size {
+				self.rotate()?;
+			}
+		}
+		let written = self.file.write(buf)?;
+		self.size += written as u64;
+		Ok(written)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.file.flush()
+	}
+}
+
+/// Random ID identifying a request in the logs
+pub(crate) fn new_request_id() -> String {
+	let mut id = [0; 8];
+	thread_rng().fill_bytes(&mut id);
+	hex_utils::hex_str(&id)
+}
+
+/// Run `future` as the handling of the request `request_id`
+pub(crate) async fn with_request_id<F: Future>(request_id: String, future: F) -> F::Output {
+	REQUEST_ID.scope(request_id, future).await
+}
+
+/// ID of the request being handled, if any
+pub(crate) fn current_request_id() -> Option<String> {
+	REQUEST_ID.try_with(|request_id| request_id.clone()).ok()
+}
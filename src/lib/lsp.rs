@@ -0,0 +1,180 @@
+use crate::cli;
+use crate::hex_utils;
+use crate::peers::PeerAddr;
+use crate::settings::LspSettings;
+use bitcoin::secp256k1::PublicKey;
+use lightning::ln::msgs::{DecodeError, ErrorAction, LightningError};
+use lightning::ln::peer_handler::CustomMessageHandler;
+use lightning::ln::wire::{CustomMessageReader, Type};
+use lightning::util::ser::{Writeable, Writer};
+use rand::{thread_rng, Rng};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::sync::Mutex;
+use tokio::sync::oneshot;
+use tracing::{debug, warn};
+
+/// Type of the BOLT 8 messages carrying LSPS0's JSON-RPC requests and responses
+pub(crate) const LSPS_MESSAGE_TYPE: u16 = 37913;
+
+/// An LSPS0 message, whose payload is a JSON-RPC 2.0 object
+#[derive(Debug)]
+pub struct LspsMessage {
+	payload: Vec<u8>,
+}
+
+impl Type for LspsMessage {
+	fn type_id(&self) -> u16 {
+		LSPS_MESSAGE_TYPE
+	}
+}
+
+impl Writeable for LspsMessage {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
+		writer.write_all(&self.payload)
+	}
+}
+
+/// Error the LSP answered a request with
+#[derive(Debug, Deserialize)]
+pub struct LspError {
+	pub code: i64,
+	pub message: String,
+}
+
+impl fmt::Display for LspError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{} (code {})", self.message, self.code)
+	}
+}
+
+#[derive(Deserialize)]
+struct JsonRpcResponse {
+	id: Option<String>,
+	result: Option<serde_json::Value>,
+	error: Option<LspError>,
+}
+
+type PendingRequests = HashMap<String, oneshot::Sender<Result<serde_json::Value, LspError>>>;
+
+/// Client of the LSPS specifications, sending JSON-RPC requests to the configured LSP over
+/// LSPS0 peer messages and matching its responses to them. It is the peer manager's handler of
+/// custom messages, so requests are only sent once the peer manager processes its events.
+pub struct LspClient {
+	lsp: Option<(PublicKey, PeerAddr)>,
+	token: Option<String>,
+	pending_messages: Mutex<Vec<(PublicKey, LspsMessage)>>,
+	pending_requests: Mutex<PendingRequests>,
+}
+
+impl LspClient {
+	pub(crate) fn new(settings: &LspSettings) -> Result<Self, String> {
+		let lsp = match &settings.node {
+			Some(node) => Some(cli::parse_peer_info(node.clone()).map_err(|e| e.to_string())?),
+			None => None,
+		};
+		Ok(Self {
+			lsp,
+			token: settings.token.clone(),
+			pending_messages: Mutex::new(Vec::new()),
+			pending_requests: Mutex::new(HashMap::new()),
+		})
+	}
+
+	/// The configured LSP and the address it is reached at
+	pub(crate) fn lsp(&self) -> Option<(PublicKey, PeerAddr)> {
+		self.lsp.clone()
+	}
+
+	/// Token the LSP gave the node, sent along with orders
+	pub(crate) fn token(&self) -> Option<String> {
+		self.token.clone()
+	}
+
+	/// Queue a `method` request to the LSP, returning its ID and the receiver of its response
+	pub(crate) fn send_request(
+		&self, method: &str, params: serde_json::Value,
+	) -> Result<(String, oneshot::Receiver<Result<serde_json::Value, LspError>>), String> {
+		let (lsp_pubkey, _) =
+			self.lsp.as_ref().ok_or_else(|| "no LSP is configured".to_string())?;
+		let mut id = [0; 16];
+		thread_rng().fill_bytes(&mut id);
+		let id = hex_utils::hex_str(&id);
+		let request = serde_json::json!({
+			"jsonrpc": "2.0",
+			"id": id,
+			"method": method,
+			"params": params,
+		});
+		let payload = serde_json::to_vec(&request).map_err(|e| e.to_string())?;
+		let (sender, receiver) = oneshot::channel();
+		self.pending_requests.lock().unwrap().insert(id.clone(), sender);
+		self.pending_messages.lock().unwrap().push((*lsp_pubkey, LspsMessage { payload }));
+		Ok((id, receiver))
+	}
+
+	/// Stop waiting for the response to request `id`
+	pub(crate) fn forget_request(&self, id: &str) {
+		self.pending_requests.lock().unwrap().remove(id);
+	}
+}
+
+impl CustomMessageReader for LspClient {
+	type CustomMessage = LspsMessage;
+
+	fn read<R: io::Read>(
+		&self, message_type: u16, buffer: &mut R,
+	) -> Result<Option<LspsMessage>, DecodeError> {
+		if message_type != LSPS_MESSAGE_TYPE {
+			return Ok(None);
+		}
+		let mut payload = Vec::new();
+		buffer.read_to_end(&mut payload).map_err(|e| DecodeError::Io(e.kind()))?;
+		Ok(Some(LspsMessage { payload }))
+	}
+}
+
+impl CustomMessageHandler for LspClient {
+	fn handle_custom_message(
+		&self, msg: LspsMessage, sender_node_id: &PublicKey,
+	) -> Result<(), LightningError> {
+		// Only the configured LSP is asked anything, so messages of other peers are ignored
+		match &self.lsp {
+			Some((lsp_pubkey, _)) if lsp_pubkey == sender_node_id => {}
+			_ => {
+				debug!("ignoring an LSPS message from {}", sender_node_id);
+				return Ok(());
+			}
+		}
+		let response: JsonRpcResponse = match serde_json::from_slice(&msg.payload) {
+			Ok(response) => response,
+			Err(e) => {
+				return Err(LightningError {
+					err: format!("invalid LSPS message: {}", e),
+					action: ErrorAction::IgnoreAndLog(lightning::util::logger::Level::Warn),
+				})
+			}
+		};
+		let sender =
+			match response.id.and_then(|id| self.pending_requests.lock().unwrap().remove(&id)) {
+				Some(sender) => sender,
+				None => {
+					warn!("the LSP answered a request which isn't pending anymore");
+					return Ok(());
+				}
+			};
+		let result = match (response.result, response.error) {
+			(_, Some(error)) => Err(error),
+			(Some(result), None) => Ok(result),
+			(None, None) => Ok(serde_json::Value::Null),
+		};
+		let _ = sender.send(result);
+		Ok(())
+	}
+
+	fn get_and_clear_pending_msg(&self) -> Vec<(PublicKey, LspsMessage)> {
+		std::mem::take(&mut *self.pending_messages.lock().unwrap())
+	}
+}
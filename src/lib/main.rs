@@ -1,32 +1,84 @@
+pub mod acceptance;
+pub mod approval;
+pub mod auth;
+pub mod autopilot;
+pub mod backup;
+pub mod bip21;
 #[allow(unused_variables, unused_assignments)]
 pub mod bitcoind_client;
+pub mod broadcast;
+pub mod chain;
 pub mod cli;
 pub mod convert;
 pub mod disk;
+pub mod electrum;
+pub mod esplora;
+pub mod events;
+pub mod export;
+#[cfg(feature = "grpc")]
+pub mod grpc;
 pub mod hex_utils;
+pub mod invoice;
+pub mod journal;
+pub mod keys;
+pub mod lnurl;
+pub mod logging;
+pub mod lsp;
 pub mod node_var;
+pub mod openapi;
+pub mod peers;
+pub mod reconnect;
+pub mod replica;
+pub mod router;
+pub mod secrets;
+pub mod seed;
+pub mod seed_wallet;
 pub mod server;
+pub mod settings;
+pub mod storage;
+pub mod sweeper;
+pub mod tls;
+pub mod tor;
+pub mod wallet;
+pub mod webhooks;
+pub mod zaps;
 
+use crate::acceptance::AcceptancePolicy;
+use crate::approval::ApprovalQueue;
+use crate::autopilot::Autopilot;
 use crate::bitcoind_client::BitcoindClient;
+use crate::broadcast::BroadcastManager;
+use crate::chain::{ChainSource, TxIndex, TxSync};
 use crate::disk::FilesystemLogger;
-use crate::server::{run, NodeVar, ServerEventHandler};
+use crate::electrum::ElectrumClient;
+use crate::esplora::EsploraClient;
+use crate::journal::EventJournal;
+use crate::keys::NodeKeysManager;
+use crate::lsp::LspClient;
+use crate::peers::{InboundLimits, PeerDirection, PeerTracker};
+use crate::reconnect::Reconnector;
+use crate::replica::ReplicatedPersister;
+use crate::router::{LimitedRouter, PaymentLimitsStorage};
+use crate::seed_wallet::SeedWallet;
+use crate::server::{drain_events, run, EventProcessor, NodeVar, ServerEventHandler};
+use crate::settings::{ChainBackend, Settings};
+use crate::storage::NodeStore;
+use crate::sweeper::Sweeper;
+use crate::wallet::Wallet;
+use crate::webhooks::WebhookRegistry;
+use crate::zaps::ZapService;
 use bitcoin::blockdata::constants::genesis_block;
-use bitcoin::blockdata::transaction::Transaction;
-use bitcoin::consensus::encode;
-use bitcoin::network::constants::Network;
-use bitcoin::secp256k1::Secp256k1;
+use bitcoin::secp256k1::PublicKey;
 use bitcoin::BlockHash;
-use bitcoin_bech32::WitnessProgram;
-use lightning::chain;
-use lightning::chain::chaininterface::{BroadcasterInterface, ConfirmationTarget, FeeEstimator};
 use lightning::chain::chainmonitor;
-use lightning::chain::keysinterface::{InMemorySigner, KeysInterface, KeysManager, Recipient};
-use lightning::chain::{BestBlock, Filter, Watch};
+use lightning::chain::keysinterface::{InMemorySigner, KeysInterface, Recipient};
+use lightning::chain::{Access, BestBlock, Confirm, Filter, Listen, Watch};
 use lightning::ln::channelmanager;
 use lightning::ln::channelmanager::{
-	ChainParameters, ChannelManagerReadArgs, SimpleArcChannelManager,
+	ChainParameters, ChannelManagerReadArgs, SimpleArcChannelManager, MIN_CLTV_EXPIRY_DELTA,
+	MIN_FINAL_CLTV_EXPIRY,
 };
-use lightning::ln::peer_handler::{IgnoringMessageHandler, MessageHandler, SimpleArcPeerManager};
+use lightning::ln::peer_handler::{MessageHandler, SimpleArcPeerManager};
 use lightning::ln::{PaymentHash, PaymentPreimage, PaymentSecret};
 use lightning::routing::network_graph::{NetGraphMsgHandler, NetworkGraph};
 use lightning::routing::scoring::ProbabilisticScorer;
@@ -41,32 +93,34 @@ use lightning_block_sync::UnboundedCache;
 use lightning_invoice::payment;
 use lightning_invoice::utils::DefaultRouter;
 use lightning_net_tokio::SocketDescriptor;
-use lightning_persister::FilesystemPersister;
 use rand::{thread_rng, Rng};
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::fmt;
 use std::fs;
-use std::fs::File;
-use std::io;
-use std::io::Write;
+use std::net::SocketAddr;
 use std::ops::Deref;
 use std::path::Path;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, SystemTime};
+use tokio::sync::{mpsc, RwLock};
+use tracing::{debug, error, info, warn};
 
 use node_var::{
-	ChainMonitor, ChannelManager, DataPersister, HTLCStatus, InvoicePayer, MillisatAmount,
-	PaymentInfo, PaymentInfoStorage, PeerManager,
+	payment_hops, unix_timestamp, BalanceSample, ChainMonitor, ChannelLimits, ChannelManager,
+	ChannelPolicy, ChannelSnapshot, DataPersister, ForwardInfo, FundingFeerates, HTLCStatus,
+	InvoicePayer, MillisatAmount, NodeAnnouncementInfo, PaymentInfo, PaymentInfoStorage,
+	PeerManager, ProbeStorage, RequestIdStorage, ScorerParams,
 };
 
 async fn handle_ldk_events(
-	channel_manager: Arc<ChannelManager>, bitcoind_client: Arc<BitcoindClient>,
-	keys_manager: Arc<KeysManager>, inbound_payments: PaymentInfoStorage,
-	outbound_payments: PaymentInfoStorage, network: Network, event: &Event,
-) {
+	channel_manager: Arc<ChannelManager>, wallet: Arc<Wallet>, sweeper: Arc<Sweeper>,
+	broadcaster: Arc<BroadcastManager>, inbound_payments: PaymentInfoStorage,
+	outbound_payments: PaymentInfoStorage, funding_feerates: FundingFeerates,
+	acceptance_policy: Arc<AcceptancePolicy>, store: Arc<dyn NodeStore>, event: &Event,
+) -> String {
 	match event {
 		Event::FundingGenerationReady {
 			temporary_channel_id,
@@ -74,60 +128,73 @@ async fn handle_ldk_events(
 			output_script,
 			..
 		} => {
-			// Construct the raw transaction with one output, that is paid the amount of the
-			// channel.
-			let addr = WitnessProgram::from_scriptpubkey(
-				&output_script[..],
-				match network {
-					Network::Bitcoin => bitcoin_bech32::constants::Network::Bitcoin,
-					Network::Testnet => bitcoin_bech32::constants::Network::Testnet,
-					Network::Regtest => bitcoin_bech32::constants::Network::Regtest,
-					Network::Signet => bitcoin_bech32::constants::Network::Signet,
-				},
-			)
-			.expect("Lightning funding tx should always be to a SegWit output")
-			.to_address();
-			let mut outputs = vec![HashMap::with_capacity(1)];
-			outputs[0].insert(addr, *channel_value_satoshis as f64 / 100_000_000.0);
-			let raw_tx = bitcoind_client.create_raw_transaction(outputs).await;
-
-			// Have your wallet put the inputs into the transaction such that the output is
-			// satisfied.
-			let funded_tx = bitcoind_client.fund_raw_transaction(raw_tx).await;
-
-			// Sign the final funding transaction and broadcast it.
-			let signed_tx = bitcoind_client.sign_raw_transaction_with_wallet(funded_tx.hex).await;
-			assert_eq!(signed_tx.complete, true);
-			let final_tx: Transaction =
-				encode::deserialize(&hex_utils::to_vec(&signed_tx.hex).unwrap()).unwrap();
+			// Have the wallet create a transaction with one output, that is paid the amount of
+			// the channel, at the fee rate requested when opening the channel if any.
+			let sat_per_kw = funding_feerates.lock().unwrap().remove(temporary_channel_id);
+			let final_tx = match wallet
+				.fund_transaction(output_script, *channel_value_satoshis, sat_per_kw)
+				.await
+			{
+				Ok(final_tx) => final_tx,
+				Err(e) => {
+					// The channel can't be funded without the wallet, so it's abandoned
+					error!("failed to fund channel: {}", e);
+					let _ = channel_manager.force_close_channel(temporary_channel_id);
+					return format!("failed to fund the channel, closed it: {}", e);
+				}
+			};
+			let txid = final_tx.txid();
 			// Give the funding transaction back to LDK for opening the channel.
 			if channel_manager
 				.funding_transaction_generated(&temporary_channel_id, final_tx)
 				.is_err()
 			{
-				println!(
-					"\nERROR: Channel went away before we could fund it. The peer disconnected or refused the channel.");
-				print!("> ");
-				io::stdout().flush().unwrap();
+				error!(
+					"channel went away before we could fund it. The peer disconnected or refused the channel.");
+				return "the channel went away before it was funded".to_string();
 			}
+			format!("funded the channel with transaction {}", txid)
 		}
 		Event::PaymentReceived { payment_hash, purpose, amt, .. } => {
-			let mut payments = inbound_payments.lock().unwrap();
+			let mut payments = inbound_payments.write().await;
+			let keysend = matches!(purpose, PaymentPurpose::SpontaneousPayment(_));
 			let (payment_preimage, payment_secret) = match purpose {
 				PaymentPurpose::InvoicePayment { payment_preimage, payment_secret, .. } => {
 					(*payment_preimage, Some(*payment_secret))
 				}
 				PaymentPurpose::SpontaneousPayment(preimage) => (Some(*preimage), None),
 			};
-			let status = match channel_manager.claim_funds(payment_preimage.unwrap()) {
+			// Keysend payments have no invoice to settle
+			let invoice = match disk::read_invoice(&*store, payment_hash) {
+				Ok(invoice) => invoice,
+				Err(e) => {
+					warn!("failed to read invoice: {}", e);
+					None
+				}
+			};
+			if invoice.as_ref().map_or(false, |invoice| invoice.canceled) {
+				channel_manager.fail_htlc_backwards(payment_hash);
+				info!(
+					"failed back payment to canceled invoice with payment hash {}",
+					hex_utils::hex_str(&payment_hash.0)
+				);
+				return "failed back the payment to a canceled invoice".to_string();
+			}
+			let claimed = channel_manager.claim_funds(payment_preimage.unwrap());
+			if let (true, Some(mut invoice)) = (claimed, invoice) {
+				invoice.settled_at = Some(unix_timestamp());
+				if disk::persist_invoice(&*store, payment_hash, &invoice).is_err() {
+					warn!("failed to persist settled invoice, check your disk and permissions");
+				}
+			}
+			let status = match claimed {
 				true => {
-					println!(
-						"\nEVENT: received payment from payment hash {} of {} millisatoshis",
+					info!(
+						"received {}payment from payment hash {} of {} millisatoshis",
+						if keysend { "keysend " } else { "" },
 						hex_utils::hex_str(&payment_hash.0),
 						amt
 					);
-					print!("> ");
-					io::stdout().flush().unwrap();
 					HTLCStatus::Succeeded
 				}
 				_ => HTLCStatus::Failed,
@@ -138,6 +205,7 @@ async fn handle_ldk_events(
 					payment.status = status;
 					payment.preimage = payment_preimage;
 					payment.secret = payment_secret;
+					payment.resolved_at = Some(unix_timestamp());
 				}
 				Entry::Vacant(e) => {
 					e.insert(PaymentInfo {
@@ -145,18 +213,44 @@ async fn handle_ldk_events(
 						secret: payment_secret,
 						status,
 						amt_msat: MillisatAmount(Some(*amt)),
+						label: None,
+						payment_id: None,
+						fee_paid_msat: None,
+						created_at: Some(unix_timestamp()),
+						resolved_at: Some(unix_timestamp()),
+						paths: Vec::new(),
+						keysend,
 					});
 				}
 			}
+			let payment = payments.get(payment_hash).unwrap();
+			if disk::persist_payment(
+				&*store,
+				disk::INBOUND_PAYMENTS_NAMESPACE,
+				payment_hash,
+				payment,
+			)
+			.is_err()
+			{
+				warn!("failed to persist inbound payment, check your disk and permissions");
+			}
+			match claimed {
+				true => "claimed the payment".to_string(),
+				false => "failed to claim the payment".to_string(),
+			}
 		}
 		Event::PaymentSent { payment_preimage, payment_hash, fee_paid_msat, .. } => {
-			let mut payments = outbound_payments.lock().unwrap();
+			let mut payments = outbound_payments.write().await;
+			let mut resolution = "the payment is unknown".to_string();
 			for (hash, payment) in payments.iter_mut() {
 				if *hash == *payment_hash {
 					payment.preimage = Some(*payment_preimage);
 					payment.status = HTLCStatus::Succeeded;
-					println!(
-						"\nEVENT: successfully sent payment of {} millisatoshis{} from \
+					payment.fee_paid_msat = *fee_paid_msat;
+					payment.resolved_at = Some(unix_timestamp());
+					resolution = "recorded the payment as succeeded".to_string();
+					info!(
+						"successfully sent payment of {} millisatoshis{} from \
 								 payment hash {:?} with preimage {:?}",
 						payment.amt_msat,
 						if let Some(fee) = fee_paid_msat {
@@ -167,29 +261,106 @@ async fn handle_ldk_events(
 						hex_utils::hex_str(&payment_hash.0),
 						hex_utils::hex_str(&payment_preimage.0)
 					);
-					print!("> ");
-					io::stdout().flush().unwrap();
+					if disk::persist_payment(
+						&*store,
+						disk::OUTBOUND_PAYMENTS_NAMESPACE,
+						hash,
+						payment,
+					)
+					.is_err()
+					{
+						warn!(
+							"failed to persist outbound payment, check your disk and permissions"
+						);
+					}
+				}
+			}
+			resolution
+		}
+		Event::OpenChannelRequest {
+			temporary_channel_id,
+			counterparty_node_id,
+			funding_satoshis,
+			..
+		} => {
+			match acceptance_policy.check(
+				&channel_manager,
+				temporary_channel_id,
+				counterparty_node_id,
+				*funding_satoshis,
+			) {
+				Ok(()) => match channel_manager.accept_inbound_channel(temporary_channel_id, 0) {
+					Ok(()) => "accepted the channel".to_string(),
+					Err(e) => {
+						error!("failed to accept inbound channel: {:?}", e);
+						format!("failed to accept the channel: {:?}", e)
+					}
+				},
+				Err(reason) => {
+					info!(
+						"rejected inbound channel from peer {}: {}",
+						counterparty_node_id, reason
+					);
+					let _ = channel_manager.force_close_channel(temporary_channel_id);
+					format!("rejected the channel: {}", reason)
 				}
 			}
 		}
-		Event::OpenChannelRequest { .. } => {
-			// Unreachable, we don't set manually_accept_inbound_channels
+		Event::PaymentPathSuccessful { payment_id, payment_hash, path } => {
+			// Each path of a payment succeeds after the payment itself
+			let mut payments = outbound_payments.write().await;
+			let payment = payments.iter_mut().find(|(hash, payment)| {
+				Some(**hash) == *payment_hash || payment.payment_id == Some(*payment_id)
+			});
+			match payment {
+				Some((hash, payment)) => {
+					payment.paths.push(payment_hops(path));
+					if disk::persist_payment(
+						&*store,
+						disk::OUTBOUND_PAYMENTS_NAMESPACE,
+						hash,
+						payment,
+					)
+					.is_err()
+					{
+						warn!(
+							"failed to persist outbound payment, check your disk and permissions"
+						);
+					}
+					"recorded the path of the payment".to_string()
+				}
+				None => "the payment is unknown".to_string(),
+			}
 		}
-		Event::PaymentPathSuccessful { .. } => {}
-		Event::PaymentPathFailed { .. } => {}
+		// The invoice payer retries the payment over other paths before this is handled
+		Event::PaymentPathFailed { all_paths_failed: true, .. } => {
+			"left to the invoice payer, the payment has no path in flight".to_string()
+		}
+		Event::PaymentPathFailed { .. } => "left to the invoice payer to retry".to_string(),
 		Event::PaymentFailed { payment_hash, .. } => {
 			print!(
 				"\nEVENT: Failed to send payment to payment hash {:?}: exhausted payment retry attempts",
 				hex_utils::hex_str(&payment_hash.0)
 			);
-			print!("> ");
-			io::stdout().flush().unwrap();
 
-			let mut payments = outbound_payments.lock().unwrap();
-			if payments.contains_key(&payment_hash) {
-				let payment = payments.get_mut(&payment_hash).unwrap();
-				payment.status = HTLCStatus::Failed;
+			let mut payments = outbound_payments.write().await;
+			if !payments.contains_key(&payment_hash) {
+				return "the payment is unknown".to_string();
+			}
+			let payment = payments.get_mut(&payment_hash).unwrap();
+			payment.status = HTLCStatus::Failed;
+			payment.resolved_at = Some(unix_timestamp());
+			if disk::persist_payment(
+				&*store,
+				disk::OUTBOUND_PAYMENTS_NAMESPACE,
+				payment_hash,
+				payment,
+			)
+			.is_err()
+			{
+				warn!("failed to persist outbound payment, check your disk and permissions");
 			}
+			"recorded the payment as failed, retries are exhausted".to_string()
 		}
 		Event::PaymentForwarded { fee_earned_msat, claim_from_onchain_tx } => {
 			let from_onchain_str = if *claim_from_onchain_tx {
@@ -198,15 +369,26 @@ async fn handle_ldk_events(
 				"from HTLC fulfill message"
 			};
 			if let Some(fee_earned) = fee_earned_msat {
-				println!(
-					"\nEVENT: Forwarded payment, earning {} msat {}",
-					fee_earned, from_onchain_str
-				);
+				info!("forwarded payment, earning {} msat {}", fee_earned, from_onchain_str);
 			} else {
-				println!("\nEVENT: Forwarded payment, claiming onchain {}", from_onchain_str);
+				info!("forwarded payment, claiming onchain {}", from_onchain_str);
 			}
-			print!("> ");
-			io::stdout().flush().unwrap();
+
+			let forward = ForwardInfo {
+				timestamp: SystemTime::now()
+					.duration_since(SystemTime::UNIX_EPOCH)
+					.unwrap()
+					.as_secs(),
+				prev_channel_id: None,
+				next_channel_id: None,
+				amt_forwarded_msat: None,
+				fee_earned_msat: *fee_earned_msat,
+				claim_from_onchain_tx: *claim_from_onchain_tx,
+			};
+			if disk::persist_forward(&*store, &forward).is_err() {
+				warn!("failed to persist forward, check your disk and permissions");
+			}
+			"recorded the forward".to_string()
 		}
 		Event::PendingHTLCsForwardable { time_forwardable } => {
 			let forwarding_channel_manager = channel_manager.clone();
@@ -216,103 +398,388 @@ async fn handle_ldk_events(
 				tokio::time::sleep(Duration::from_millis(millis_to_sleep)).await;
 				forwarding_channel_manager.process_pending_htlc_forwards();
 			});
+			"scheduled the forwarding of the pending HTLCs".to_string()
 		}
-		Event::SpendableOutputs { outputs } => {
-			let destination_address = bitcoind_client.get_new_address().await;
-			let output_descriptors = &outputs.iter().map(|a| a).collect::<Vec<_>>();
-			let tx_feerate =
-				bitcoind_client.get_est_sat_per_1000_weight(ConfirmationTarget::Normal);
-			let spending_tx = keys_manager
-				.spend_spendable_outputs(
-					output_descriptors,
-					Vec::new(),
-					destination_address.script_pubkey(),
-					tx_feerate,
-					&Secp256k1::new(),
-				)
-				.unwrap();
-			bitcoind_client.broadcast_transaction(&spending_tx);
+		Event::SpendableOutputs { .. } => {
+			// The `ServerEventHandler` persisted the outputs already, as LDK handed them over
+			match sweeper.sweep().await {
+				Ok(()) => "swept the outputs".to_string(),
+				Err(e) => {
+					warn!("failed to sweep spendable outputs, retrying later: {}", e);
+					format!("failed to sweep the outputs, retrying later: {}", e)
+				}
+			}
 		}
 		Event::ChannelClosed { channel_id, reason, user_channel_id: _ } => {
-			println!(
-				"\nEVENT: Channel {} closed due to: {:?}",
-				hex_utils::hex_str(channel_id),
-				reason
-			);
-			print!("> ");
-			io::stdout().flush().unwrap();
+			info!("channel {} closed due to: {:?}", hex_utils::hex_str(channel_id), reason);
+
+			// Channels closing before being funded are identified by their temporary ID
+			funding_feerates.lock().unwrap().remove(channel_id);
+
+			let closed_at =
+				SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
+			// Cooperative closes and our force-closes were broadcast before the event was queued
+			let closing_txid = broadcaster.closing_txid(channel_id).unwrap_or_else(|e| {
+				warn!("unable to look up the closing transaction: {}", e);
+				None
+			});
+			let reason = format!("{:?}", reason);
+			if disk::persist_closed_channel(&*store, channel_id, reason, closed_at, closing_txid)
+				.is_err()
+			{
+				warn!("failed to persist closed channel, check your disk and permissions");
+			}
+			"recorded the closed channel".to_string()
+		}
+		Event::DiscardFunding { transaction, .. } => {
+			// The inputs of funding transactions are locked when funded, release them now that
+			// the funding transaction will never be broadcast.
+			let outpoints: Vec<_> = transaction.input.iter().map(|i| i.previous_output).collect();
+			match wallet.unlock_unspent(&outpoints).await {
+				Ok(_) => "unlocked the inputs of the funding transaction".to_string(),
+				Err(e) => {
+					warn!("failed to unlock inputs of discarded funding transaction");
+					format!("failed to unlock the inputs of the funding transaction: {}", e)
+				}
+			}
+		}
+	}
+}
+
+/// Spawn the gRPC server on `listen_address`, served over TLS with the PEM certificate and key
+/// of `tls` if given
+#[cfg(feature = "grpc")]
+fn start_grpc_server(
+	node_var: &Arc<NodeVar<ServerEventHandler>>, listen_address: &str,
+	tls: Option<(Vec<u8>, Vec<u8>)>,
+) {
+	let addr = match listen_address.parse::<SocketAddr>() {
+		Ok(addr) => addr,
+		Err(e) => {
+			error!("invalid gRPC listen_address {}: {}", listen_address, e);
+			return;
+		}
+	};
+	let identity = tls.map(|(cert, key)| tonic::transport::Identity::from_pem(cert, key));
+	let server = grpc::run(Arc::clone(node_var), addr, identity);
+	tokio::spawn(async move {
+		if let Err(e) = server.await {
+			error!("the gRPC server stopped: {}", e);
+		}
+	});
+	info!("serving gRPC on {}", addr);
+}
+
+#[cfg(not(feature = "grpc"))]
+fn start_grpc_server(
+	_node_var: &Arc<NodeVar<ServerEventHandler>>, _listen_address: &str,
+	_tls: Option<(Vec<u8>, Vec<u8>)>,
+) {
+	warn!("the node was built without the grpc feature, so gRPC isn't served");
+}
+
+/// Build the node announcement from the `[announcement]` and `[tor]` sections of the config
+/// file, which the alias and listen addresses given at startup override
+fn default_node_announcement(settings: &Settings) -> NodeAnnouncementInfo {
+	let alias = settings.announcement.alias.clone().unwrap_or_default();
+	let mut addresses = settings.announcement.addresses.clone();
+	if let Some(onion_address) = &settings.tor.onion_address {
+		if !addresses.contains(onion_address) {
+			addresses.push(onion_address.clone());
+		}
+	}
+	NodeAnnouncementInfo {
+		alias,
+		color: settings.announcement.color.clone().unwrap_or("000000".to_string()),
+		addresses,
+	}
+}
+
+/// Build the scorer parameters from the `[scorer]` section of the config file
+fn default_scorer_params(settings: &Settings) -> ScorerParams {
+	let mut params = ScorerParams::default();
+	let scorer = &settings.scorer;
+	if let Some(base_penalty_msat) = scorer.base_penalty_msat {
+		params.base_penalty_msat = base_penalty_msat;
+	}
+	if let Some(liquidity_penalty_multiplier_msat) = scorer.liquidity_penalty_multiplier_msat {
+		params.liquidity_penalty_multiplier_msat = liquidity_penalty_multiplier_msat;
+	}
+	if let Some(liquidity_offset_half_life_secs) = scorer.liquidity_offset_half_life_secs {
+		params.liquidity_offset_half_life_secs = liquidity_offset_half_life_secs;
+	}
+	if let Some(amount_penalty_multiplier_msat) = scorer.amount_penalty_multiplier_msat {
+		params.amount_penalty_multiplier_msat = amount_penalty_multiplier_msat;
+	}
+	params
+}
+
+/// Build the channel policy from the `[channels]` section of the config file
+fn default_channel_policy(settings: &Settings) -> Result<ChannelPolicy, String> {
+	let mut policy = ChannelPolicy::default();
+	let channels = &settings.channels;
+	if let Some(cltv_expiry_delta) = channels.cltv_expiry_delta {
+		// LDK silently raises lower deltas to its minimum, so reject them instead
+		if cltv_expiry_delta < MIN_CLTV_EXPIRY_DELTA {
+			return Err(format!("cltv_expiry_delta is below {}", MIN_CLTV_EXPIRY_DELTA));
 		}
-		Event::DiscardFunding { .. } => {
-			// A "real" node should probably "lock" the UTXOs spent in funding transactions until
-			// the funding transaction either confirms, or this event is generated.
+		policy.cltv_expiry_delta = cltv_expiry_delta;
+	}
+	if let Some(htlc_minimum_msat) = channels.htlc_minimum_msat {
+		if htlc_minimum_msat == 0 {
+			return Err("htlc_minimum_msat has to be positive".to_string());
 		}
+		policy.htlc_minimum_msat = Some(htlc_minimum_msat);
 	}
+	if let Some(min_final_cltv_expiry) = channels.min_final_cltv_expiry {
+		// LDK fails the HTLCs of received payments expiring sooner
+		if min_final_cltv_expiry < MIN_FINAL_CLTV_EXPIRY {
+			return Err(format!("min_final_cltv_expiry is below {}", MIN_FINAL_CLTV_EXPIRY));
+		}
+		policy.min_final_cltv_expiry = min_final_cltv_expiry;
+	}
+	Ok(policy)
 }
 
 pub async fn start_ldk() {
-	let args = match cli::parse_startup_args() {
-		Ok(user_args) => user_args,
+	let (args, settings) = match cli::parse_startup_args() {
+		Ok((user_args, settings)) => (user_args, settings),
 		Err(()) => return,
 	};
 
 	// Initialize the LDK data directory if necessary.
 	let ldk_data_dir = format!("{}/.ldk", args.ldk_storage_dir_path);
 	fs::create_dir_all(ldk_data_dir.clone()).unwrap();
+	let log_filters = match logging::init(&ldk_data_dir, &settings.logging) {
+		Ok(log_filters) => log_filters,
+		Err(e) => {
+			println!("ERROR: unable to set up logging: {}", e);
+			return;
+		}
+	};
+	info!("starting LDK Node");
 
-	// Initialize our bitcoind client.
-	let bitcoind_client = match BitcoindClient::new(
-		args.bitcoind_rpc_host.clone(),
-		args.bitcoind_rpc_port,
-		args.bitcoind_rpc_username.clone(),
-		args.bitcoind_rpc_password.clone(),
-		tokio::runtime::Handle::current(),
-	)
-	.await
-	{
-		Ok(client) => Arc::new(client),
+	// Outbound connections go through the configured SOCKS5 proxy, e.g. Tor's
+	let socks_proxy = match &settings.tor.socks_proxy {
+		Some(proxy) => match proxy.parse::<SocketAddr>() {
+			Ok(proxy) => Some(proxy),
+			Err(e) => {
+				error!("invalid SOCKS5 proxy address {}: {}", proxy, e);
+				return;
+			}
+		},
+		None => None,
+	};
+	// Unlock the secrets in the data directory before anything reads them
+	let secrets_key = match secrets::unlock(&ldk_data_dir, args.encrypt_secrets) {
+		Ok(secrets_key) => secrets_key,
 		Err(e) => {
-			println!("Failed to connect to bitcoind client: {}", e);
+			error!("unable to unlock the node secrets: {}", e);
 			return;
 		}
 	};
 
-	// Check that the bitcoind we've connected to is running the network we expect
-	let bitcoind_chain = bitcoind_client.get_blockchain_info().await.chain;
-	if bitcoind_chain
-		!= match args.network {
-			bitcoin::Network::Bitcoin => "main",
-			bitcoin::Network::Testnet => "test",
-			bitcoin::Network::Regtest => "regtest",
-			bitcoin::Network::Signet => "signet",
-		} {
-		println!(
-			"Chain argument ({}) didn't match bitcoind chain ({})",
-			args.network, bitcoind_chain
-		);
+	// Open the store holding all node data other than channel monitors
+	let store = match storage::open_store(args.storage_backend, &ldk_data_dir) {
+		Ok(store) => store,
+		Err(e) => {
+			error!("unable to open {:?} node store: {}", args.storage_backend, e);
+			return;
+		}
+	};
+	if let Err(e) = disk::migrate_legacy_files(&ldk_data_dir, &*store) {
+		error!("unable to migrate data files into the node store: {}", e);
 		return;
 	}
 
+	// Load the token required by the HTTP API
+	let api_token = match auth::load_or_create_api_token(&ldk_data_dir) {
+		Ok(token) => token,
+		Err(e) => {
+			error!("unable to load the API token: {}", e);
+			return;
+		}
+	};
+	info!("API token is stored at {}/{}", ldk_data_dir, auth::API_TOKEN_FILE);
+	let api_tokens = match auth::TokenRegistry::new(Arc::clone(&store), api_token) {
+		Ok(api_tokens) => Arc::new(api_tokens),
+		Err(e) => {
+			error!("unable to read the issued API tokens: {}", e);
+			return;
+		}
+	};
+
+	let tls_config = if settings.server.tls_enabled {
+		match tls::load_server_config(&ldk_data_dir, &settings.server, secrets_key.as_ref()) {
+			Ok(tls_config) => Some(tls_config),
+			Err(e) => {
+				error!("unable to load the TLS certificate of the API server: {}", e);
+				return;
+			}
+		}
+	} else {
+		warn!("TLS is disabled, the API server will only accept plaintext HTTP");
+		None
+	};
+	// The gRPC server is given the certificate and key of the HTTP API server
+	let grpc_tls = match (&settings.grpc.listen_address, settings.server.tls_enabled) {
+		(Some(_), true) => {
+			match tls::load_cert_and_key(&ldk_data_dir, &settings.server, secrets_key.as_ref()) {
+				Ok(cert_and_key) => Some(cert_and_key),
+				Err(e) => {
+					error!("unable to load the TLS certificate of the gRPC server: {}", e);
+					return;
+				}
+			}
+		}
+		_ => None,
+	};
+
+	// Select the backend LDK is synced from. With Esplora or Electrum, bitcoind isn't connected
+	// to at all, the wallet then being derived from the seed.
+	let (bitcoind_client, chain_source, tx_index) = match settings.chain.backend {
+		ChainBackend::Bitcoind => {
+			// Initialize our bitcoind client.
+			let client = match BitcoindClient::new(
+				args.bitcoind_rpc_host.clone(),
+				args.bitcoind_rpc_port,
+				args.bitcoind_rpc_username.clone(),
+				args.bitcoind_rpc_password.clone(),
+				settings.fees,
+				tokio::runtime::Handle::current(),
+			)
+			.await
+			{
+				Ok(client) => Arc::new(client),
+				Err(e) => {
+					error!("failed to connect to bitcoind client: {}", e);
+					return;
+				}
+			};
+
+			// Check that the bitcoind we've connected to is running the network we expect
+			let bitcoind_chain = match client.get_blockchain_info().await {
+				Ok(info) => info.chain,
+				Err(e) => {
+					error!("failed to connect to bitcoind client: {}", e);
+					return;
+				}
+			};
+			if bitcoind_chain
+				!= match args.network {
+					bitcoin::Network::Bitcoin => "main",
+					bitcoin::Network::Testnet => "test",
+					bitcoin::Network::Regtest => "regtest",
+					bitcoin::Network::Signet => "signet",
+				} {
+				error!(
+					"chain argument ({}) didn't match bitcoind chain ({})",
+					args.network, bitcoind_chain
+				);
+				return;
+			}
+			(Some(Arc::clone(&client)), ChainSource::Bitcoind(client), None)
+		}
+		ChainBackend::Esplora => {
+			let esplora_url = match &settings.chain.esplora_url {
+				Some(esplora_url) => esplora_url.clone(),
+				None => {
+					error!("chain.esplora_url has to be set to sync from Esplora");
+					return;
+				}
+			};
+			match EsploraClient::new(esplora_url, settings.fees, tokio::runtime::Handle::current())
+				.await
+			{
+				Ok(client) => {
+					let client = Arc::new(client);
+					let chain_source = ChainSource::Esplora(Arc::clone(&client));
+					(None, chain_source, Some(TxIndex::Esplora(client)))
+				}
+				Err(e) => {
+					error!("failed to connect to Esplora: {}", e);
+					return;
+				}
+			}
+		}
+		ChainBackend::Electrum => {
+			let electrum_url = match &settings.chain.electrum_url {
+				Some(electrum_url) => electrum_url.clone(),
+				None => {
+					error!("chain.electrum_url has to be set to sync from Electrum");
+					return;
+				}
+			};
+			match ElectrumClient::new(
+				electrum_url,
+				settings.fees,
+				tokio::runtime::Handle::current(),
+			)
+			.await
+			{
+				Ok(client) => {
+					let client = Arc::new(client);
+					let chain_source = ChainSource::Electrum(Arc::clone(&client));
+					(None, chain_source, Some(TxIndex::Electrum(client)))
+				}
+				Err(e) => {
+					error!("failed to connect to the Electrum server: {}", e);
+					return;
+				}
+			}
+		}
+	};
+	let chain_source = Arc::new(chain_source);
+	// Check that the server LDK is synced from is on the network we expect
+	if let Some(tx_index) = &tx_index {
+		match tx_index.get_block_hash(0).await {
+			Ok(hash) if hash == genesis_block(args.network).header.block_hash() => {}
+			Ok(_) => {
+				error!("chain argument ({}) didn't match the chain server's chain", args.network);
+				return;
+			}
+			Err(e) => {
+				error!("failed to connect to the chain server: {}", e);
+				return;
+			}
+		}
+	}
+	let tx_sync = tx_index.clone().map(|tx_index| Arc::new(TxSync::new(tx_index)));
+
 	// ## Setup
 	// Step 1: Initialize the FeeEstimator
 
-	// BitcoindClient implements the FeeEstimator trait, so it'll act as our fee estimator.
-	let fee_estimator = bitcoind_client.clone();
+	// ChainSource implements the FeeEstimator trait, so it'll act as our fee estimator.
+	let fee_estimator = chain_source.clone();
 
 	// Step 2: Initialize the Logger
-	let logger = Arc::new(FilesystemLogger::new(ldk_data_dir.clone()));
+	let logger = Arc::new(FilesystemLogger::new(Arc::clone(&log_filters)));
 
 	// Step 3: Initialize the BroadcasterInterface
 
-	// BitcoindClient implements the BroadcasterInterface trait, so it'll act as our transaction
-	// broadcaster.
-	let broadcaster = bitcoind_client.clone();
+	// The BroadcastManager broadcasts through the ChainSource, recording the transactions so that
+	// unconfirmed ones are broadcast again at new blocks
+	let broadcaster =
+		Arc::new(BroadcastManager::new(chain_source.clone(), tx_index.clone(), Arc::clone(&store)));
 
 	// Step 4: Initialize Persist
-	let persister = Arc::new(FilesystemPersister::new(ldk_data_dir.clone()));
+	// Channel monitors are mirrored to the configured replica, if any
+	let persister = match ReplicatedPersister::new(
+		ldk_data_dir.clone(),
+		settings.monitor_replica.directory.clone(),
+	) {
+		Ok(persister) => Arc::new(persister),
+		Err(e) => {
+			error!("unable to replicate channel monitors: {}", e);
+			return;
+		}
+	};
 
 	// Step 5: Initialize the ChainMonitor
+	// Syncing from Esplora or Electrum requires LDK to register the transactions and outputs it watches
+	let chain_filter = tx_sync.clone().map(|tx_sync| tx_sync as Arc<dyn Filter + Send + Sync>);
 	let chain_monitor: Arc<ChainMonitor> = Arc::new(chainmonitor::ChainMonitor::new(
-		None,
+		chain_filter,
 		broadcaster.clone(),
 		logger.clone(),
 		fee_estimator.clone(),
@@ -324,40 +791,202 @@ pub async fn start_ldk() {
 	// The key seed that we use to derive the node privkey (that corresponds to the node pubkey) and
 	// other secret key material.
 	let keys_seed_path = format!("{}/keys_seed", ldk_data_dir.clone());
-	let keys_seed = if let Ok(seed) = fs::read(keys_seed_path.clone()) {
+	let keys_seed = match secrets::read_secret(&keys_seed_path, secrets_key.as_ref()) {
+		Ok(seed) => seed,
+		Err(e) => {
+			error!("unable to read keys seed file {}: {}", keys_seed_path, e);
+			return;
+		}
+	};
+	let keys_seed = if let Some(seed) = keys_seed {
 		assert_eq!(seed.len(), 32);
 		let mut key = [0; 32];
 		key.copy_from_slice(&seed);
+		if let Some(mnemonic) = &args.mnemonic {
+			match seed::keys_seed_from_mnemonic(mnemonic, &args.mnemonic_passphrase) {
+				Ok(restored) if restored == key => {}
+				Ok(_) => {
+					error!("the mnemonic doesn't match the existing keys seed {}", keys_seed_path);
+					return;
+				}
+				Err(e) => {
+					error!("{}", e);
+					return;
+				}
+			}
+		}
 		key
-	} else {
-		let mut key = [0; 32];
-		thread_rng().fill_bytes(&mut key);
-		match File::create(keys_seed_path.clone()) {
-			Ok(mut f) => {
-				f.write_all(&key).expect("Failed to write node keys seed to disk");
-				f.sync_all().expect("Failed to sync node keys seed to disk");
+	} else if let Some(mnemonic) = &args.mnemonic {
+		let key = match seed::keys_seed_from_mnemonic(mnemonic, &args.mnemonic_passphrase) {
+			Ok(key) => key,
+			Err(e) => {
+				error!("{}", e);
+				return;
 			}
+		};
+		if let Err(e) = secrets::write_secret(&keys_seed_path, &key, secrets_key.as_ref()) {
+			error!("unable to create keys seed file {}: {}", keys_seed_path, e);
+			return;
+		}
+		key
+	} else if args.recover_from.is_some() {
+		// Channel backups can only be decrypted with the seed of the node which exported them
+		error!(
+			"restore the keys seed of the node to {} before recovering its channels",
+			keys_seed_path
+		);
+		return;
+	} else {
+		// New nodes derive their keys seed from a mnemonic, displayed once by `/seed/backup`
+		let key = match seed::generate_keys_seed(
+			&ldk_data_dir,
+			&args.mnemonic_passphrase,
+			secrets_key.as_ref(),
+		) {
+			Ok(key) => key,
 			Err(e) => {
-				println!("ERROR: Unable to create keys seed file {}: {}", keys_seed_path, e);
+				error!("unable to generate the node mnemonic: {}", e);
 				return;
 			}
+		};
+		if let Err(e) = secrets::write_secret(&keys_seed_path, &key, secrets_key.as_ref()) {
+			error!("unable to create keys seed file {}: {}", keys_seed_path, e);
+			return;
 		}
+		info!("generated the node mnemonic, back it up with `lnnode-cli backupseed --confirm`");
 		key
 	};
 	let cur = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap();
-	let keys_manager = Arc::new(KeysManager::new(&keys_seed, cur.as_secs(), cur.subsec_nanos()));
+	// The nodes of a phantom node share the seed of the phantom node's key and of the inbound
+	// payments' secrets, which is the node's own seed unless a phantom secret is configured
+	let phantom_seed = match &settings.phantom.secret {
+		Some(secret) => match hex_utils::to_vec(secret) {
+			Some(bytes) if bytes.len() == 32 => {
+				let mut phantom_seed = [0; 32];
+				phantom_seed.copy_from_slice(&bytes);
+				phantom_seed
+			}
+			_ => {
+				error!("the phantom secret must be 32 bytes as 64 hex digits");
+				return;
+			}
+		},
+		None => keys_seed,
+	};
+	if settings.phantom.secret.is_some() && !settings.phantom.enabled {
+		warn!("phantom.secret is ignored, as phantom.enabled isn't set");
+	}
+	let keys_manager = Arc::new(NodeKeysManager::new(
+		&keys_seed,
+		cur.as_secs(),
+		cur.subsec_nanos(),
+		if settings.phantom.enabled { Some(&phantom_seed) } else { None },
+	));
+
+	// Restore the channel monitors and peers of a channel backup before reading monitors
+	let backup_key = backup::channel_backup_key(&keys_seed);
+	let recovering_channels = match backup::recovering_channels(&*store) {
+		Ok(channels) => channels,
+		Err(e) => {
+			error!("unable to read the channels recovered from a backup: {}", e);
+			return;
+		}
+	};
+	if !recovering_channels.is_empty() {
+		// Starting normally would let the restored channels be mistaken for closed ones
+		if args.recover_from.is_none() {
+			error!(
+				"{} channels restored from a backup still have funds to claim, keep starting the node with --recover <backup_path> until they are claimed",
+				recovering_channels.len()
+			);
+			return;
+		}
+		info!(
+			"resuming the recovery of {} channels restored from a backup",
+			recovering_channels.len()
+		);
+	} else if let Some(backup_path) = &args.recover_from {
+		// Snapshot whatever state the node has left before the backup is written over it
+		match backup::snapshot(&ldk_data_dir, "recover") {
+			Ok(snapshot_id) => info!("created backup snapshot {}", snapshot_id),
+			Err(e) => {
+				error!("failed to snapshot node state before recovering: {}", e);
+				return;
+			}
+		}
+		match fs::read(backup_path).and_then(|bundle| {
+			backup::restore_channel_backup(&ldk_data_dir, &*store, &backup_key, &bundle)
+		}) {
+			Ok(channels_number) => info!(
+				"restored {} channels from {}. Peers force-close them once they reconnect, keep the node running until they are all closed.",
+				channels_number, backup_path
+			),
+			Err(e) => {
+				error!("unable to recover from channel backup {}: {}", backup_path, e);
+				return;
+			}
+		}
+	}
+	let recovering_channels = match backup::recovering_channels(&*store) {
+		Ok(channels) => channels,
+		Err(e) => {
+			error!("unable to read the channels recovered from a backup: {}", e);
+			return;
+		}
+	};
 
 	// Step 7: Read ChannelMonitor state from disk
 	let mut channelmonitors = persister.read_channelmonitors(keys_manager.clone()).unwrap();
 
 	// Step 8: Initialize the ChannelManager
 	let mut user_config = UserConfig::default();
-	user_config.peer_channel_config_limits.force_announced_channel_preference = false;
+	// Inbound channels are accepted according to the configured policy
+	let acceptance_policy = match AcceptancePolicy::new(&settings.channel_acceptance) {
+		Ok(policy) => Arc::new(policy),
+		Err(e) => {
+			error!("invalid channel acceptance policy: {}", e);
+			return;
+		}
+	};
+	acceptance_policy.apply(&mut user_config);
+	let channel_limits = match ChannelLimits::new(&settings.channels) {
+		Ok(limits) => limits,
+		Err(e) => {
+			error!("invalid channel limits: {}", e);
+			return;
+		}
+	};
+	channel_limits.apply(&mut user_config);
+	// The channel policy set through the API takes precedence over the config file. Inbound
+	// channels are created with the policy the node started with.
+	let channel_policy = match disk::read_channel_policy(&*store) {
+		Ok(Some(policy)) => policy,
+		Ok(None) => match default_channel_policy(&settings) {
+			Ok(policy) => policy,
+			Err(e) => {
+				error!("invalid channel policy: {}", e);
+				return;
+			}
+		},
+		Err(e) => {
+			error!("unable to read the channel policy: {}", e);
+			return;
+		}
+	};
+	channel_policy.apply(&mut user_config);
+	let channel_policy = Arc::new(Mutex::new(channel_policy));
 	let mut restarting_node = true;
 	let (channel_manager_blockhash, channel_manager) = {
 		if let Ok(mut f) = fs::File::open(format!("{}/manager", ldk_data_dir.clone())) {
 			let mut channel_monitor_mut_references = Vec::new();
 			for (_, channel_monitor) in channelmonitors.iter_mut() {
+				// LDK broadcasts the commitment transaction of the monitors it is given without a
+				// channel, which is likely revoked for restored ones. Their peers force-close them.
+				let (funding_txo, _) = channel_monitor.get_funding_txo();
+				let name = format!("{}_{}", funding_txo.txid, funding_txo.index);
+				if recovering_channels.contains(&name) {
+					continue;
+				}
 				channel_monitor_mut_references.push(channel_monitor);
 			}
 			let read_args = ChannelManagerReadArgs::new(
@@ -373,14 +1002,11 @@ pub async fn start_ldk() {
 		} else {
 			// We're starting a fresh node.
 			restarting_node = false;
-			let getinfo_resp = bitcoind_client.get_blockchain_info().await;
+			let (best_block_hash, best_block_height) = chain_source.get_best_block().await.unwrap();
 
 			let chain_params = ChainParameters {
 				network: args.network,
-				best_block: BestBlock::new(
-					getinfo_resp.latest_blockhash,
-					getinfo_resp.latest_height as u32,
-				),
+				best_block: BestBlock::new(best_block_hash, best_block_height),
 			};
 			let fresh_channel_manager = channelmanager::ChannelManager::new(
 				fee_estimator.clone(),
@@ -391,17 +1017,21 @@ pub async fn start_ldk() {
 				user_config,
 				chain_params,
 			);
-			(getinfo_resp.latest_blockhash, fresh_channel_manager)
+			(best_block_hash, fresh_channel_manager)
 		}
 	};
 
 	// Step 9: Sync ChannelMonitors and ChannelManager to chain tip
+	// When syncing from Esplora or Electrum, they are synced once the ChainMonitor watches the channels
 	let mut chain_listener_channel_monitors = Vec::new();
 	let mut cache = UnboundedCache::new();
 	let mut chain_tip: Option<poll::ValidatedBlockHeader> = None;
-	if restarting_node {
-		let mut chain_listeners =
-			vec![(channel_manager_blockhash, &channel_manager as &dyn chain::Listen)];
+	if let (true, Some(bitcoind_client)) = (restarting_node, &bitcoind_client) {
+		// The broadcast transactions were checked at every block the ChannelManager saw
+		let mut chain_listeners = vec![
+			(channel_manager_blockhash, &channel_manager as &dyn Listen),
+			(channel_manager_blockhash, &*broadcaster as &dyn Listen),
+		];
 
 		for (blockhash, channel_monitor) in channelmonitors.drain(..) {
 			let outpoint = channel_monitor.get_funding_txo().0;
@@ -414,7 +1044,7 @@ pub async fn start_ldk() {
 
 		for monitor_listener_info in chain_listener_channel_monitors.iter_mut() {
 			chain_listeners
-				.push((monitor_listener_info.0, &monitor_listener_info.1 as &dyn chain::Listen));
+				.push((monitor_listener_info.0, &monitor_listener_info.1 as &dyn Listen));
 		}
 		chain_tip = Some(
 			init::synchronize_listeners(
@@ -434,6 +1064,11 @@ pub async fn start_ldk() {
 		let funding_outpoint = item.2;
 		chain_monitor.watch_channel(funding_outpoint, channel_monitor).unwrap();
 	}
+	// Watching a channel registers its outputs, which is how a `TxSync` finds them
+	for (_, channel_monitor) in channelmonitors.drain(..) {
+		let funding_outpoint = channel_monitor.get_funding_txo().0;
+		chain_monitor.watch_channel(funding_outpoint, channel_monitor).unwrap();
+	}
 
 	// Step 11: Optional: Initialize the NetGraphMsgHandler
 	let genesis = genesis_block(args.network).header.block_hash();
@@ -441,12 +1076,20 @@ pub async fn start_ldk() {
 	let network_graph = Arc::new(disk::read_network(Path::new(&network_graph_path), genesis));
 	let network_gossip = Arc::new(NetGraphMsgHandler::new(
 		Arc::clone(&network_graph),
-		None::<Arc<dyn chain::Access + Send + Sync>>,
+		None::<Arc<dyn Access + Send + Sync>>,
 		logger.clone(),
 	));
 
 	// Step 12: Initialize the PeerManager
 	let channel_manager: Arc<ChannelManager> = Arc::new(channel_manager);
+	// LSPS messages, the only custom messages the node handles, go to the LSP client
+	let lsp_client = match LspClient::new(&settings.lsp) {
+		Ok(lsp_client) => Arc::new(lsp_client),
+		Err(e) => {
+			error!("invalid LSP node: {}", e);
+			return;
+		}
+	};
 	let mut ephemeral_bytes = [0; 32];
 	rand::thread_rng().fill_bytes(&mut ephemeral_bytes);
 	let lightning_msg_handler = MessageHandler {
@@ -458,106 +1101,338 @@ pub async fn start_ldk() {
 		keys_manager.get_node_secret(Recipient::Node).unwrap(),
 		&ephemeral_bytes,
 		logger.clone(),
-		Arc::new(IgnoringMessageHandler {}),
+		Arc::clone(&lsp_client),
 	));
 
 	// ## Running LDK
 	// Step 13: Initialize networking
 
-	let peer_manager_connection_handler = peer_manager.clone();
-	let listening_port = args.ldk_peer_listening_port;
+	let peer_tracker = Arc::new(PeerTracker::new());
 	let stop_listen_connect = Arc::new(AtomicBool::new(false));
-	let stop_listen = Arc::clone(&stop_listen_connect);
-	tokio::spawn(async move {
-		let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", listening_port))
-			.await
-			.expect("Failed to bind to listen port - is something else already listening on it?");
-		loop {
-			let peer_mgr = peer_manager_connection_handler.clone();
-			let tcp_stream = listener.accept().await.unwrap().0;
-			if stop_listen.load(Ordering::Acquire) {
-				return;
+	let inbound_limits = InboundLimits::new(&settings.p2p);
+	let mut listeners = Vec::new();
+	if settings.p2p.accept_inbound {
+		let listen_addresses = if settings.p2p.listen_addresses.is_empty() {
+			vec![format!("0.0.0.0:{}", args.ldk_peer_listening_port)]
+		} else {
+			settings.p2p.listen_addresses.clone()
+		};
+		for listen_address in listen_addresses {
+			match tokio::net::TcpListener::bind(&listen_address).await {
+				Ok(listener) => {
+					info!("listening for peers on {}", listen_address);
+					listeners.push(listener);
+				}
+				Err(e) => {
+					error!("failed to listen for peers on {}: {}", listen_address, e);
+					return;
+				}
 			}
-			tokio::spawn(async move {
-				lightning_net_tokio::setup_inbound(
-					peer_mgr.clone(),
-					tcp_stream.into_std().unwrap(),
-				)
-				.await;
-			});
 		}
-	});
+	} else {
+		info!("not accepting inbound peer connections");
+	}
+	let listen_addresses: Vec<SocketAddr> =
+		listeners.iter().filter_map(|listener| listener.local_addr().ok()).collect();
+	for listener in listeners {
+		let peer_manager_connection_handler = peer_manager.clone();
+		let listen_tracker = Arc::clone(&peer_tracker);
+		let stop_listen = Arc::clone(&stop_listen_connect);
+		let inbound_limits = Arc::clone(&inbound_limits);
+		tokio::spawn(async move {
+			loop {
+				let peer_mgr = peer_manager_connection_handler.clone();
+				let tracker = Arc::clone(&listen_tracker);
+				let (tcp_stream, address) = listener.accept().await.unwrap();
+				if stop_listen.load(Ordering::Acquire) {
+					return;
+				}
+				// Connections over the limits are dropped before the handshake
+				let permit = match inbound_limits.acquire(address.ip()) {
+					Ok(permit) => permit,
+					Err(e) => {
+						debug!("refused inbound connection from {}: {}", address, e);
+						continue;
+					}
+				};
+				let peers_before = peer_mgr.get_peer_node_ids();
+				let stream = tcp_stream.into_std().unwrap();
+				let connection = lightning_net_tokio::setup_inbound(peer_mgr.clone(), stream);
+				tokio::spawn(async move {
+					connection.await;
+					drop(permit);
+				});
+				// The peer manager doesn't tell which peer a connection belongs to, so the address is
+				// matched to the peer which completes the handshake next. It is left unknown if
+				// several peers do at once.
+				tokio::spawn(async move {
+					for _ in 0..1000 {
+						tokio::time::sleep(Duration::from_millis(10)).await;
+						let new_peers: Vec<PublicKey> = peer_mgr
+							.get_peer_node_ids()
+							.into_iter()
+							.filter(|id| !peers_before.contains(id) && !tracker.has_address(id))
+							.collect();
+						match new_peers.len() {
+							0 => continue,
+							1 => tracker.connected(
+								new_peers[0],
+								address.into(),
+								PeerDirection::Inbound,
+							),
+							_ => {}
+						}
+						return;
+					}
+				});
+			}
+		});
+	}
 
 	// Step 14: Connect and Disconnect Blocks
-	if chain_tip.is_none() {
-		chain_tip =
-			Some(init::validate_best_block_header(&mut bitcoind_client.deref()).await.unwrap());
-	}
 	let channel_manager_listener = channel_manager.clone();
 	let chain_monitor_listener = chain_monitor.clone();
-	let bitcoind_block_source = bitcoind_client.clone();
-	let network = args.network;
+	let broadcast_listener = Arc::clone(&broadcaster);
+	match tx_sync {
+		Some(tx_sync) => {
+			// Esplora and Electrum servers are polled less often than bitcoind, as a sync makes
+			// requests per watched transaction and output
+			tokio::spawn(async move {
+				loop {
+					let confirmables: [&(dyn Confirm + Sync); 2] =
+						[&*channel_manager_listener, &*chain_monitor_listener];
+					if let Err(e) = tx_sync.sync(&confirmables).await {
+						warn!("failed to sync from the chain server, retrying later: {}", e);
+					}
+					tokio::time::sleep(Duration::from_secs(30)).await;
+				}
+			});
+		}
+		None => {
+			let bitcoind_client = bitcoind_client
+				.clone()
+				.expect("LDK is synced from bitcoind without a chain server");
+			if chain_tip.is_none() {
+				chain_tip = Some(
+					init::validate_best_block_header(&mut bitcoind_client.deref()).await.unwrap(),
+				);
+			}
+			let bitcoind_block_source = bitcoind_client.clone();
+			let network = args.network;
+			tokio::spawn(async move {
+				let mut derefed = bitcoind_block_source.deref();
+				let chain_poller = poll::ChainPoller::new(&mut derefed, network);
+				let ldk_listener = (chain_monitor_listener, channel_manager_listener);
+				let chain_listener = (&ldk_listener, broadcast_listener);
+				let mut spv_client =
+					SpvClient::new(chain_tip.unwrap(), chain_poller, &mut cache, &chain_listener);
+				// bitcoind is polled less often while it can't be reached, e.g. while it restarts
+				let mut poll_interval_secs = 1;
+				loop {
+					match spv_client.poll_best_tip().await {
+						Ok(_) => poll_interval_secs = 1,
+						Err(e) => {
+							warn!("failed to poll bitcoind for new blocks: {:?}", e);
+							poll_interval_secs = std::cmp::min(poll_interval_secs * 2, 60);
+						}
+					}
+					tokio::time::sleep(Duration::from_secs(poll_interval_secs)).await;
+				}
+			});
+		}
+	}
+
+	// Broadcast transactions are checked against the Esplora or Electrum server at every new
+	// chain tip, bitcoind's blocks being connected to the BroadcastManager as they come
+	let broadcast_manager = Arc::clone(&broadcaster);
 	tokio::spawn(async move {
-		let mut derefed = bitcoind_block_source.deref();
-		let chain_poller = poll::ChainPoller::new(&mut derefed, network);
-		let chain_listener = (chain_monitor_listener, channel_manager_listener);
-		let mut spv_client =
-			SpvClient::new(chain_tip.unwrap(), chain_poller, &mut cache, &chain_listener);
+		let mut interval = tokio::time::interval(Duration::from_secs(30));
 		loop {
-			spv_client.poll_best_tip().await.unwrap();
-			tokio::time::sleep(Duration::from_secs(1)).await;
+			interval.tick().await;
+			if let Err(e) = broadcast_manager.process_tip().await {
+				warn!("failed to check the broadcast transactions, retrying later: {}", e);
+			}
 		}
 	});
 
-	// Step 15: Handle LDK Events
-	let channel_manager_event_listener = channel_manager.clone();
-	let keys_manager_listener = keys_manager.clone();
-	// TODO: persist payment info to disk
-	let inbound_payments: PaymentInfoStorage = Arc::new(Mutex::new(HashMap::new()));
-	let outbound_payments: PaymentInfoStorage = Arc::new(Mutex::new(HashMap::new()));
-	let inbound_pmts_for_events = inbound_payments.clone();
-	let outbound_pmts_for_events = outbound_payments.clone();
-	let network = args.network;
-	let bitcoind_rpc = bitcoind_client.clone();
-	let handle = tokio::runtime::Handle::current();
-
-	let event_handler = ServerEventHandler {
-		tokio_handle: handle.clone(),
-		channel_manager: Arc::clone(&channel_manager),
-		bitcoind_client: Arc::clone(&bitcoind_client),
-		keys_manager: Arc::clone(&keys_manager),
-		inbound_payments: inbound_payments.clone(),
-		outbound_payments: outbound_payments.clone(),
-		network,
+	// Step 15: Initialize routing ProbabilisticScorer
+	// The parameters set through the API take precedence over the config file
+	let scorer_params = match disk::read_scorer_params(&*store) {
+		Ok(Some(params)) => params,
+		Ok(None) => default_scorer_params(&settings),
+		Err(e) => {
+			error!("unable to read the scorer parameters: {}", e);
+			return;
+		}
 	};
-
-	// Step 16: Initialize routing ProbabilisticScorer
-	let scorer_path = format!("{}/prob_scorer", ldk_data_dir.clone());
 	let scorer = Arc::new(Mutex::new(disk::read_scorer(
-		Path::new(&scorer_path),
+		&*store,
 		Arc::clone(&network_graph),
+		&scorer_params,
 	)));
+	let scorer_params = Arc::new(Mutex::new(scorer_params));
 	let scorer_persist = Arc::clone(&scorer);
+	let scorer_store = Arc::clone(&store);
 	tokio::spawn(async move {
 		let mut interval = tokio::time::interval(Duration::from_secs(600));
 		loop {
 			interval.tick().await;
-			if disk::persist_scorer(Path::new(&scorer_path), &scorer_persist.lock().unwrap())
-				.is_err()
-			{
+			if disk::persist_scorer(&*scorer_store, &scorer_persist.lock().unwrap()).is_err() {
 				// Persistence errors here are non-fatal as channels will be re-scored as payments
 				// fail, but they may indicate a disk error which could be fatal elsewhere.
-				eprintln!("Warning: Failed to persist scorer, check your disk and permissions");
+				warn!("failed to persist scorer, check your disk and permissions");
 			}
 		}
 	});
 
+	// Step 16: Handle LDK Events
+	let channel_manager_event_listener = channel_manager.clone();
+	let keys_manager_listener = keys_manager.clone();
+	let inbound_payments: PaymentInfoStorage =
+		match disk::read_payments(&*store, disk::INBOUND_PAYMENTS_NAMESPACE) {
+			Ok(payments) => Arc::new(RwLock::new(payments)),
+			Err(e) => {
+				error!("unable to read inbound payments from disk: {}", e);
+				return;
+			}
+		};
+	let outbound_payments: PaymentInfoStorage =
+		match disk::read_payments(&*store, disk::OUTBOUND_PAYMENTS_NAMESPACE) {
+			Ok(payments) => Arc::new(RwLock::new(payments)),
+			Err(e) => {
+				error!("unable to read outbound payments from disk: {}", e);
+				return;
+			}
+		};
+	let inbound_pmts_for_events = inbound_payments.clone();
+	let outbound_pmts_for_events = outbound_payments.clone();
+	let funding_feerates: FundingFeerates = Arc::new(Mutex::new(HashMap::new()));
+	let probes: ProbeStorage = Arc::new(Mutex::new(HashSet::new()));
+	let payment_limits: PaymentLimitsStorage = Arc::new(Mutex::new(HashMap::new()));
+	let request_ids: RequestIdStorage = Arc::new(Mutex::new(HashMap::new()));
+
+	// Initialize the on-chain wallet, which outputs claimable from channels are swept to unless
+	// another address is configured. Without bitcoind, its keys are derived from the seed.
+	let wallet = match (&bitcoind_client, tx_index) {
+		(Some(bitcoind_client), _) => Wallet::new(Arc::clone(bitcoind_client), args.network),
+		(None, Some(tx_index)) => {
+			match SeedWallet::new(
+				&keys_seed,
+				args.network,
+				tx_index,
+				Arc::clone(&chain_source),
+				Arc::clone(&store),
+			) {
+				Ok(seed_wallet) => Wallet::from_seed_wallet(seed_wallet, args.network),
+				Err(e) => {
+					error!("unable to load the on-chain wallet: {}", e);
+					return;
+				}
+			}
+		}
+		(None, None) => unreachable!("a chain server is connected to when bitcoind isn't"),
+	};
+	let wallet = Arc::new(wallet);
+	let sweeper = match Sweeper::new(
+		Arc::clone(&store),
+		Arc::clone(&keys_manager),
+		Arc::clone(&chain_source),
+		Arc::clone(&wallet),
+		Arc::clone(&broadcaster),
+		&settings.sweeper.destination_address,
+	) {
+		Ok(sweeper) => Arc::new(sweeper),
+		Err(e) => {
+			error!("invalid sweeper destination address: {}", e);
+			return;
+		}
+	};
+	// Channels restored from a backup are recovering until their monitors have nothing left to claim
+	if !recovering_channels.is_empty() {
+		let recovery_data_dir = ldk_data_dir.clone();
+		let recovery_store = Arc::clone(&store);
+		let recovery_chain_monitor = Arc::clone(&chain_monitor);
+		tokio::spawn(async move {
+			let mut interval = tokio::time::interval(Duration::from_secs(600));
+			loop {
+				interval.tick().await;
+				match backup::resolve_recovered_channels(
+					&recovery_data_dir,
+					&*recovery_store,
+					&recovery_chain_monitor,
+				) {
+					Ok(0) => {
+						info!("claimed the funds of every channel restored from a backup, the node can start without --recover");
+						break;
+					}
+					Ok(_) => {}
+					Err(e) => warn!("failed to check the recovered channels: {}", e),
+				}
+			}
+		});
+	}
+	// Outputs are swept again on every attempt until they are spent by a confirmed transaction
+	let sweeper_retry = Arc::clone(&sweeper);
+	tokio::spawn(async move {
+		let mut interval = tokio::time::interval(Duration::from_secs(600));
+		loop {
+			interval.tick().await;
+			if let Err(e) = sweeper_retry.sweep().await {
+				warn!("failed to sweep spendable outputs, retrying later: {}", e);
+			}
+		}
+	});
+	let event_sender = events::event_channel();
+	let (event_queue, queued_events) = mpsc::unbounded_channel();
+	let pending_events = Arc::new(AtomicUsize::new(0));
+	let event_journal = match EventJournal::new(Arc::clone(&store)) {
+		Ok(journal) => Arc::new(journal),
+		Err(e) => {
+			error!("unable to read the event journal: {}", e);
+			return;
+		}
+	};
+	let event_processor = EventProcessor {
+		channel_manager: Arc::clone(&channel_manager),
+		wallet: Arc::clone(&wallet),
+		sweeper: Arc::clone(&sweeper),
+		broadcaster: Arc::clone(&broadcaster),
+		inbound_payments: inbound_payments.clone(),
+		outbound_payments: outbound_payments.clone(),
+		funding_feerates: Arc::clone(&funding_feerates),
+		acceptance_policy: Arc::clone(&acceptance_policy),
+		store: Arc::clone(&store),
+		event_sender: event_sender.clone(),
+		request_ids: Arc::clone(&request_ids),
+		event_journal: Arc::clone(&event_journal),
+		pending_events: Arc::clone(&pending_events),
+	};
+	let event_processing = tokio::spawn(event_processor.run(queued_events));
+	let event_handler = ServerEventHandler {
+		event_queue,
+		pending_events: Arc::clone(&pending_events),
+		sweeper: Arc::clone(&sweeper),
+		scorer: Arc::clone(&scorer),
+		probes: Arc::clone(&probes),
+		payment_limits: Arc::clone(&payment_limits),
+	};
+
 	// Step 17: Create InvoicePayer
-	let router = DefaultRouter::new(
+	// Payments are retried over routes within their fee limit until their deadline
+	let router = LimitedRouter::new(
+		DefaultRouter::new(
+			network_graph.clone(),
+			logger.clone(),
+			keys_manager.get_secure_random_bytes(),
+		),
+		Arc::clone(&payment_limits),
+	);
+	// The server finds routes for `/queryroutes` with its own router, as `InvoicePayer` owns this one
+	let server_router = Arc::new(DefaultRouter::new(
 		network_graph.clone(),
 		logger.clone(),
 		keys_manager.get_secure_random_bytes(),
-	);
+	));
 	let invoice_payer = Arc::new(InvoicePayer::new(
 		channel_manager.clone(),
 		router,
@@ -581,85 +1456,226 @@ pub async fn start_ldk() {
 		logger.clone(),
 	);
 
-	// Regularly reconnect to channel peers.
-	let connect_cm = Arc::clone(&channel_manager);
-	let connect_pm = Arc::clone(&peer_manager);
-	let peer_data_path = format!("{}/channel_peer_data", ldk_data_dir.clone());
-	let stop_connect = Arc::clone(&stop_listen_connect);
+	// Regularly record the state of open channels, so it can still be reported once they close
+	let snapshot_cm = Arc::clone(&channel_manager);
+	let snapshot_store = Arc::clone(&store);
 	tokio::spawn(async move {
-		let mut interval = tokio::time::interval(Duration::from_secs(1));
+		let mut recorded: HashMap<[u8; 32], ChannelSnapshot> = HashMap::new();
+		let mut interval = tokio::time::interval(Duration::from_secs(10));
 		loop {
 			interval.tick().await;
-			match disk::read_channel_peer_data(Path::new(&peer_data_path)) {
-				Ok(info) => {
-					let peers = connect_pm.get_peer_node_ids();
-					for node_id in connect_cm
-						.list_channels()
-						.iter()
-						.map(|chan| chan.counterparty.node_id)
-						.filter(|id| !peers.contains(id))
-					{
-						if stop_connect.load(Ordering::Acquire) {
-							return;
-						}
-						for (pubkey, peer_addr) in info.iter() {
-							if *pubkey == node_id {
-								let _ = cli::do_connect_peer(
-									*pubkey,
-									peer_addr.clone(),
-									Arc::clone(&connect_pm),
-								)
-								.await;
-							}
-						}
-					}
+			let channels = snapshot_cm.list_channels();
+			recorded.retain(|id, _| channels.iter().any(|chan| chan.channel_id == *id));
+			for chan in channels {
+				let snapshot = ChannelSnapshot {
+					peer_pubkey: hex_utils::hex_str(&chan.counterparty.node_id.serialize()),
+					channel_value_satoshis: chan.channel_value_satoshis,
+					funding_txid: chan.funding_txo.map(|txo| txo.txid.to_string()),
+					balance_msat: chan.balance_msat,
+					is_outbound: chan.is_outbound,
+					is_public: chan.is_public,
+				};
+				if recorded.get(&chan.channel_id) == Some(&snapshot) {
+					continue;
+				}
+				if disk::persist_channel_snapshot(&*snapshot_store, &chan.channel_id, &snapshot)
+					.is_ok()
+				{
+					recorded.insert(chan.channel_id, snapshot);
+				}
+			}
+		}
+	});
+
+	// Sample the balances of open channels into their liquidity history
+	let history_cm = Arc::clone(&channel_manager);
+	let history_store = Arc::clone(&store);
+	tokio::spawn(async move {
+		let mut interval = tokio::time::interval(Duration::from_secs(600));
+		loop {
+			interval.tick().await;
+			let timestamp = unix_timestamp();
+			for chan in history_cm.list_channels() {
+				let sample = BalanceSample {
+					timestamp,
+					local_balance_msat: chan.balance_msat,
+					remote_balance_msat: (chan.channel_value_satoshis * 1000)
+						.saturating_sub(chan.balance_msat),
+				};
+				if let Err(e) =
+					disk::persist_balance_sample(&*history_store, &chan.channel_id, &sample)
+				{
+					warn!("failed to record the balance of a channel: {}", e);
 				}
-				Err(e) => println!("ERROR: errored reading channel peer info from disk: {:?}", e),
 			}
 		}
 	});
 
+	// Reconnect to channel peers whenever their connection drops
+	let reconnector = Reconnector {
+		channel_manager: Arc::clone(&channel_manager),
+		peer_manager: Arc::clone(&peer_manager),
+		network_graph: Arc::clone(&network_graph),
+		store: Arc::clone(&store),
+		peer_tracker: Arc::clone(&peer_tracker),
+		socks_proxy,
+		stop: Arc::clone(&stop_listen_connect),
+	};
+	tokio::spawn(reconnector.run());
+
+	// The node announcement set through the API takes precedence over the config file, which
+	// takes precedence over the startup arguments
+	let node_announcement = match disk::read_node_announcement(&*store) {
+		Ok(Some(info)) => info,
+		Ok(None) => default_node_announcement(&settings),
+		Err(e) => {
+			error!("unable to read the node announcement: {}", e);
+			return;
+		}
+	};
+	if let Err(e) = node_announcement.to_announcement() {
+		error!("invalid node announcement: {}", e);
+		return;
+	}
+	let node_announcement = Arc::new(Mutex::new(node_announcement));
+
 	// Regularly broadcast our node_announcement. This is only required (or possible) if we have
 	// some public channels, and is only useful if we have public listen address(es) to announce.
 	// In a production environment, this should occur only after the announcement of new channels
 	// to avoid churn in the global network graph.
 	let chan_manager = Arc::clone(&channel_manager);
 	let network = args.network;
-	if !args.ldk_announced_listen_addr.is_empty() {
-		tokio::spawn(async move {
-			let mut interval = tokio::time::interval(Duration::from_secs(60));
-			loop {
-				interval.tick().await;
-				chan_manager.broadcast_node_announcement(
-					[0; 3],
-					args.ldk_announced_node_name,
-					args.ldk_announced_listen_addr.clone(),
-				);
+	let announcement = Arc::clone(&node_announcement);
+	tokio::spawn(async move {
+		let mut interval = tokio::time::interval(Duration::from_secs(60));
+		loop {
+			interval.tick().await;
+			// The announcement is validated whenever it is set
+			let (color, alias, addresses) = announcement.lock().unwrap().to_announcement().unwrap();
+			chan_manager.broadcast_node_announcement(color, alias, addresses);
+		}
+	});
+
+	// Notify webhooks of node events
+	let webhooks = Arc::new(WebhookRegistry::new(Arc::clone(&store), &settings.webhooks));
+	let approvals = match ApprovalQueue::new(Arc::clone(&store), &settings.approval) {
+		Ok(approvals) => Arc::new(approvals),
+		Err(e) => {
+			error!("unable to read the payments awaiting approval: {}", e);
+			return;
+		}
+	};
+	tokio::spawn(webhooks::run_dispatcher(Arc::clone(&webhooks), event_sender.subscribe()));
+
+	// Open channels on the node's behalf, once the autopilot is enabled
+	let autopilot = match Autopilot::new(
+		&settings.autopilot,
+		Arc::clone(&channel_policy),
+		channel_limits.clone(),
+		Arc::clone(&channel_manager),
+		Arc::clone(&peer_manager),
+		Arc::clone(&network_graph),
+		Arc::clone(&store),
+		Arc::clone(&wallet),
+		Arc::clone(&peer_tracker),
+		socks_proxy,
+	) {
+		Ok(autopilot) => Arc::new(autopilot),
+		Err(e) => {
+			error!("unable to read the autopilot state: {}", e);
+			return;
+		}
+	};
+	tokio::spawn(Arc::clone(&autopilot).run());
+
+	// Receive Nostr zaps on the lightning address, if configured
+	let zap_service = match ZapService::new(
+		&settings.zaps,
+		Arc::clone(&channel_manager),
+		Arc::clone(&keys_manager),
+		Arc::clone(&inbound_payments),
+		Arc::clone(&store),
+		Arc::clone(&channel_policy),
+		args.network,
+	) {
+		Ok(zap_service) => zap_service,
+		Err(e) => {
+			error!("invalid zap settings: {}", e);
+			return;
+		}
+	};
+	if let Some(zap_service) = zap_service {
+		let zap_service = Arc::new(zap_service);
+		tokio::spawn(zaps::run_publisher(Arc::clone(&zap_service), event_sender.subscribe()));
+		match zaps::run_server(zap_service, &settings.zaps.listen_address) {
+			Ok(server) => {
+				info!("serving zaps on {}", settings.zaps.listen_address);
+				tokio::spawn(server);
 			}
-		});
+			Err(e) => {
+				error!("failed to start the zap server: {}", e);
+				return;
+			}
+		}
 	}
 
 	// Start server here
-	let node_var = NodeVar {
+	let node_var = Arc::new(NodeVar {
 		invoice_payer: Arc::clone(&invoice_payer),
 		peer_manager: Arc::clone(&peer_manager),
 		channel_manager: Arc::clone(&channel_manager),
+		chain_monitor: Arc::clone(&chain_monitor),
+		bitcoind_client: bitcoind_client.clone(),
+		chain_source: Arc::clone(&chain_source),
+		broadcaster: Arc::clone(&broadcaster),
+		wallet: Arc::clone(&wallet),
 		keys_manager: Arc::clone(&keys_manager),
 		network_graph: Arc::clone(&network_graph),
+		router: server_router,
+		scorer: Arc::clone(&scorer),
+		scorer_params,
 		inbound_payments,
 		outbound_payments,
+		funding_feerates,
+		probes,
+		payment_limits,
+		request_ids,
+		store: Arc::clone(&store),
+		event_sender,
+		webhooks,
 		ldk_data_dir: ldk_data_dir.clone(),
+		backup_key,
+		secrets_key,
+		socks_proxy,
+		peer_tracker,
+		listen_addresses,
+		node_announcement,
+		log_filters,
+		api_tokens,
+		approvals,
+		autopilot,
+		lsp_client,
+		sweeper,
+		event_journal,
+		channel_policy,
+		channel_limits,
 		network,
-	};
+	});
+
+	// Serve the gRPC API alongside the HTTP API, if configured
+	if let Some(listen_address) = &settings.grpc.listen_address {
+		start_grpc_server(&node_var, listen_address, grpc_tls);
+	}
 
-	let server_port: u32 = 33335;
-	match run(node_var, format!("127.0.0.1:{}", server_port).as_str()) {
+	match run(node_var, &settings.server.listen_address, tls_config) {
 		Ok(server) => {
-			println!("Starting node server");
-			server.await;
+			info!("starting node server");
+			if let Err(e) = server.await {
+				error!("node server stopped with an error: {}", e);
+			}
 		}
 		Err(e) => {
-			println!("Failed to start server: {}", e);
+			error!("failed to start server: {}", e);
 			// Stop the node application
 		}
 	}
@@ -669,13 +1685,22 @@ pub async fn start_ldk() {
 	stop_listen_connect.store(true, Ordering::Release);
 	peer_manager.disconnect_all_peers();
 
+	// Handle the queued events, so that what they changed is in the channel manager the background
+	// processor persists as it stops
+	info!("handling the pending events before shutting down");
+	drain_events(&pending_events).await;
+
 	// Stop the background processor.
 	background_processor.stop().unwrap();
+
+	// Handle the events processed as the background processor stopped, which dropped the
+	// event handler and so closed the queue
+	if let Err(e) = event_processing.await {
+		error!("event processing failed: {}", e);
+	}
 }
 
 #[tokio::main]
 pub async fn main() {
-	println!("Starting LDK Node");
-
 	start_ldk().await;
 }
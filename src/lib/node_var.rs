@@ -1,23 +1,40 @@
-use crate::bitcoind_client::BitcoindClient;
+use crate::broadcast::BroadcastManager;
+use crate::chain::ChainSource;
 use crate::disk::FilesystemLogger;
+use crate::hex_utils;
+use crate::keys::NodeKeysManager;
+use crate::lsp::LspClient;
+use crate::replica::ReplicatedPersister;
+use crate::router::LimitedRouter;
+use crate::settings::ChannelSettings;
+use crate::tor;
+use bitcoin::secp256k1::PublicKey;
 use lightning::chain;
 use lightning::chain::chainmonitor;
-use lightning::chain::keysinterface::{InMemorySigner, KeysInterface, KeysManager, Recipient};
+use lightning::chain::keysinterface::InMemorySigner;
 use lightning::chain::Filter;
-use lightning::ln::channelmanager::SimpleArcChannelManager;
-use lightning::ln::peer_handler::SimpleArcPeerManager;
+use lightning::ln::channelmanager::{self, PaymentId, MIN_FINAL_CLTV_EXPIRY};
+use lightning::ln::msgs::NetAddress;
+use lightning::ln::peer_handler;
 use lightning::ln::{PaymentHash, PaymentPreimage, PaymentSecret};
-use lightning::routing::network_graph::NetworkGraph;
-use lightning::routing::scoring::ProbabilisticScorer;
+use lightning::routing::network_graph::{NetGraphMsgHandler, NetworkGraph};
+use lightning::routing::router::RouteHop;
+use lightning::routing::scoring::{ProbabilisticScorer, ProbabilisticScoringParameters};
+use lightning::util::config::{ChannelConfig, UserConfig};
 use lightning_background_processor::Persister;
 use lightning_invoice::payment;
 use lightning_invoice::utils::DefaultRouter;
 use lightning_net_tokio::SocketDescriptor;
 use lightning_persister::FilesystemPersister;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::sync::RwLock;
+use tracing::warn;
+use utoipa::ToSchema;
 
 /// Defines the status variations of an HTLC
 #[derive(Debug, Deserialize, Serialize)]
@@ -40,39 +57,458 @@ impl fmt::Display for MillisatAmount {
 }
 
 /// Defines the information about a payment
+#[derive(Deserialize, Serialize)]
 pub struct PaymentInfo {
+	#[serde(serialize_with = "serialize_preimage", deserialize_with = "deserialize_preimage")]
 	pub preimage: Option<PaymentPreimage>,
+	#[serde(serialize_with = "serialize_secret", deserialize_with = "deserialize_secret")]
 	pub secret: Option<PaymentSecret>,
 	pub status: HTLCStatus,
 	pub amt_msat: MillisatAmount,
+	/// Local note set when creating an invoice
+	#[serde(default)]
+	pub label: Option<String>,
+	/// ID LDK tracks an outbound payment by, until it succeeds or is abandoned
+	#[serde(
+		default,
+		serialize_with = "serialize_payment_id",
+		deserialize_with = "deserialize_payment_id"
+	)]
+	pub payment_id: Option<PaymentId>,
+	/// Routing fees paid for a successful outbound payment
+	#[serde(default)]
+	pub fee_paid_msat: Option<u64>,
+	/// Seconds since the UNIX epoch at which the payment was initiated, or its invoice created
+	#[serde(default)]
+	pub created_at: Option<u64>,
+	/// Seconds since the UNIX epoch at which the payment succeeded or failed
+	#[serde(default)]
+	pub resolved_at: Option<u64>,
+	/// Paths a successful outbound payment took, more than one if it was split
+	#[serde(default)]
+	pub paths: Vec<Vec<PaymentHop>>,
+	/// Whether the payment is an inbound keysend payment, whose preimage came in the onion
+	/// instead of from one of the node's invoices. LDK 0.0.106 doesn't pass the onion's custom
+	/// TLV records on, so messages sent along with it can't be kept.
+	#[serde(default)]
+	pub keysend: bool,
 }
 
-pub type PaymentInfoStorage = Arc<Mutex<HashMap<PaymentHash, PaymentInfo>>>;
+/// Hop of the path of a payment, in the terms `lncli` uses
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct PaymentHop {
+	#[schema(value_type = String)]
+	pub pubkey: PublicKey,
+	pub short_channel_id: u64,
+	/// Amount the hop's node forwards, or receives if it is the destination
+	pub amt_to_forward_msat: u64,
+	/// Fee the hop's node charges for forwarding
+	pub fee_msat: u64,
+}
+
+/// Describe a path of LDK's, whose hops each carry the fee of the next hop's node, except the
+/// last one, which carries the amount paid
+pub fn payment_hops(path: &[RouteHop]) -> Vec<PaymentHop> {
+	let mut hops = Vec::new();
+	let mut amt_to_forward_msat = 0;
+	for (idx, hop) in path.iter().enumerate().rev() {
+		let fee_msat = if idx == path.len() - 1 {
+			amt_to_forward_msat = hop.fee_msat;
+			0
+		} else {
+			hop.fee_msat
+		};
+		hops.push(PaymentHop {
+			pubkey: hop.pubkey,
+			short_channel_id: hop.short_channel_id,
+			amt_to_forward_msat,
+			fee_msat,
+		});
+		amt_to_forward_msat += fee_msat;
+	}
+	hops.reverse();
+	hops
+}
+
+/// Seconds since the UNIX epoch, which payments are timestamped in
+pub fn unix_timestamp() -> u64 {
+	SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs()
+}
+
+fn serialize_preimage<S: Serializer>(
+	preimage: &Option<PaymentPreimage>, serializer: S,
+) -> Result<S::Ok, S::Error> {
+	preimage.map(|preimage| hex_utils::hex_str(&preimage.0)).serialize(serializer)
+}
+
+fn deserialize_preimage<'de, D: Deserializer<'de>>(
+	deserializer: D,
+) -> Result<Option<PaymentPreimage>, D::Error> {
+	Ok(deserialize_bytes32(deserializer)?.map(PaymentPreimage))
+}
+
+fn serialize_secret<S: Serializer>(
+	secret: &Option<PaymentSecret>, serializer: S,
+) -> Result<S::Ok, S::Error> {
+	secret.map(|secret| hex_utils::hex_str(&secret.0)).serialize(serializer)
+}
+
+fn deserialize_secret<'de, D: Deserializer<'de>>(
+	deserializer: D,
+) -> Result<Option<PaymentSecret>, D::Error> {
+	Ok(deserialize_bytes32(deserializer)?.map(PaymentSecret))
+}
+
+fn serialize_payment_id<S: Serializer>(
+	payment_id: &Option<PaymentId>, serializer: S,
+) -> Result<S::Ok, S::Error> {
+	payment_id.map(|payment_id| hex_utils::hex_str(&payment_id.0)).serialize(serializer)
+}
+
+fn deserialize_payment_id<'de, D: Deserializer<'de>>(
+	deserializer: D,
+) -> Result<Option<PaymentId>, D::Error> {
+	Ok(deserialize_bytes32(deserializer)?.map(PaymentId))
+}
+
+fn deserialize_bytes32<'de, D: Deserializer<'de>>(
+	deserializer: D,
+) -> Result<Option<[u8; 32]>, D::Error> {
+	match Option::<String>::deserialize(deserializer)? {
+		Some(hex) => match hex_utils::to_vec(&hex) {
+			Some(bytes) if bytes.len() == 32 => {
+				let mut res = [0; 32];
+				res.copy_from_slice(&bytes);
+				Ok(Some(res))
+			}
+			_ => Err(serde::de::Error::custom("expected 32 hex-encoded bytes")),
+		},
+		None => Ok(None),
+	}
+}
+
+/// Payments by payment hash. The lock is async as the payments are accessed by the server's
+/// handlers and the event processor, so that waiting for it doesn't block their worker.
+pub type PaymentInfoStorage = Arc<RwLock<HashMap<PaymentHash, PaymentInfo>>>;
+
+/// Fee rates in satoshis per 1000 weight units requested for the funding transactions of
+/// channels being opened, by temporary channel ID
+pub type FundingFeerates = Arc<Mutex<HashMap<[u8; 32], u32>>>;
+
+/// Payment hashes of the probes in flight, which no node knows the preimage of
+pub type ProbeStorage = Arc<Mutex<HashSet<PaymentHash>>>;
+
+/// IDs of the API requests which initiated the outbound payments in flight, by payment hash, so
+/// that the logs of the payments' events carry them
+pub type RequestIdStorage = Arc<Mutex<HashMap<PaymentHash, String>>>;
+
+/// Defines the information about a forwarded payment
+///
+/// LDK 0.0.106 doesn't report the channels or the amount of a forward in `PaymentForwarded`, so
+/// these are only known for forwards recorded by future versions.
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct ForwardInfo {
+	/// Seconds since the UNIX epoch at which the forward was claimed
+	pub timestamp: u64,
+	pub prev_channel_id: Option<String>,
+	pub next_channel_id: Option<String>,
+	pub amt_forwarded_msat: Option<u64>,
+	pub fee_earned_msat: Option<u64>,
+	pub claim_from_onchain_tx: bool,
+}
+
+/// Defines the last known state of an open channel, kept so it can be reported after the
+/// channel is closed and disappears from `ChannelManager::list_channels`
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+pub struct ChannelSnapshot {
+	pub peer_pubkey: String,
+	pub channel_value_satoshis: u64,
+	pub funding_txid: Option<String>,
+	pub balance_msat: u64,
+	pub is_outbound: bool,
+	pub is_public: bool,
+}
+
+/// Defines a sample of the balances of a channel in its liquidity history
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct BalanceSample {
+	pub timestamp: u64,
+	pub local_balance_msat: u64,
+	pub remote_balance_msat: u64,
+}
+
+/// Defines an invoice created by the node, kept apart from its inbound payment so that it can
+/// still be listed once paid or expired
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct InvoiceInfo {
+	/// BOLT 11 encoding of the invoice
+	pub bolt11: String,
+	pub description: String,
+	pub amt_msat: Option<u64>,
+	pub label: Option<String>,
+	/// Seconds since the UNIX epoch at which the invoice was created
+	pub created_at: u64,
+	/// Seconds since the UNIX epoch after which the invoice can't be paid anymore
+	pub expires_at: u64,
+	/// Seconds since the UNIX epoch at which a payment to the invoice was claimed
+	pub settled_at: Option<u64>,
+	#[serde(default)]
+	pub canceled: bool,
+}
+
+/// Defines a zap request whose invoice hasn't been paid yet, kept to publish its zap receipt
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ZapRequestInfo {
+	/// Zap request event, as the JSON the invoice's description hash commits to
+	pub zap_request: String,
+	/// BOLT 11 encoding of the invoice
+	pub bolt11: String,
+}
+
+/// State of an invoice, as `lncli listinvoices` reports it
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum InvoiceState {
+	Open,
+	Settled,
+	Expired,
+	Canceled,
+}
+
+impl InvoiceInfo {
+	pub fn state(&self, now: u64) -> InvoiceState {
+		if self.settled_at.is_some() {
+			InvoiceState::Settled
+		} else if self.canceled {
+			InvoiceState::Canceled
+		} else if now >= self.expires_at {
+			InvoiceState::Expired
+		} else {
+			InvoiceState::Open
+		}
+	}
+}
+
+/// Defines the information about a closed channel
+///
+/// LDK 0.0.106 doesn't report the closing transaction in `ChannelClosed`, so `closing_txid` is
+/// taken from the transactions the node broadcast. It's unknown for channels the counterparty
+/// force-closed.
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct ClosedChannelInfo {
+	pub channel_id: String,
+	/// Seconds since the UNIX epoch at which the channel was closed
+	pub closed_at: u64,
+	pub close_reason: String,
+	pub closing_txid: Option<String>,
+	/// Last known state of the channel, unless it closed before its state was first recorded
+	pub last_state: Option<ChannelSnapshot>,
+}
+
+/// Label and tags an operator attached to a channel
+#[derive(Clone, Debug, Default, Deserialize, Serialize, ToSchema)]
+pub struct ChannelLabel {
+	pub label: Option<String>,
+	pub tags: Vec<String>,
+}
+
+/// Defines the forwarding policy of the node's channels, and the HTLCs they and its invoices
+/// accept. Fields missing from a policy persisted by an older version keep their default.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ChannelPolicy {
+	pub forwarding_fee_base_msat: u32,
+	pub forwarding_fee_proportional_millionths: u32,
+	pub cltv_expiry_delta: u16,
+	/// Minimum value of the HTLCs the counterparty may send, LDK's default if unset
+	pub htlc_minimum_msat: Option<u64>,
+	pub min_final_cltv_expiry: u32,
+}
+
+impl Default for ChannelPolicy {
+	fn default() -> Self {
+		let config = ChannelConfig::default();
+		Self {
+			forwarding_fee_base_msat: config.forwarding_fee_base_msat,
+			forwarding_fee_proportional_millionths: config.forwarding_fee_proportional_millionths,
+			cltv_expiry_delta: config.cltv_expiry_delta,
+			htlc_minimum_msat: None,
+			min_final_cltv_expiry: MIN_FINAL_CLTV_EXPIRY,
+		}
+	}
+}
+
+impl ChannelPolicy {
+	/// Apply the policy to the config channels are created with
+	pub(crate) fn apply(&self, config: &mut UserConfig) {
+		config.channel_options.forwarding_fee_base_msat = self.forwarding_fee_base_msat;
+		config.channel_options.forwarding_fee_proportional_millionths =
+			self.forwarding_fee_proportional_millionths;
+		config.channel_options.cltv_expiry_delta = self.cltv_expiry_delta;
+		if let Some(htlc_minimum_msat) = self.htlc_minimum_msat {
+			config.own_channel_config.our_htlc_minimum_msat = htlc_minimum_msat;
+		}
+	}
+}
+
+/// Defines the limits of the HTLCs carried by the node's new channels
+#[derive(Clone, Debug)]
+pub struct ChannelLimits {
+	pub max_dust_htlc_exposure_msat: u64,
+}
+
+impl Default for ChannelLimits {
+	fn default() -> Self {
+		Self { max_dust_htlc_exposure_msat: ChannelConfig::default().max_dust_htlc_exposure_msat }
+	}
+}
+
+impl ChannelLimits {
+	/// The limits set in the node's settings.
+	///
+	/// LDK 0.0.106 caps the value in flight towards us at 10% of the channel value and the
+	/// HTLCs in flight towards us at 50, so these limits are rejected rather than ignored.
+	pub(crate) fn new(settings: &ChannelSettings) -> Result<Self, String> {
+		if settings.max_inflight_htlc_percent.is_some() {
+			return Err(
+				"max_inflight_htlc_percent isn't supported yet, LDK fixes it at 10%".to_string()
+			);
+		}
+		if settings.max_accepted_htlcs.is_some() {
+			return Err("max_accepted_htlcs isn't supported yet, LDK fixes it at 50".to_string());
+		}
+		let mut limits = Self::default();
+		if let Some(max_dust_htlc_exposure_msat) = settings.max_dust_htlc_exposure_msat {
+			limits.max_dust_htlc_exposure_msat = max_dust_htlc_exposure_msat;
+		}
+		Ok(limits)
+	}
+
+	/// Apply the limits to the config channels are created with
+	pub(crate) fn apply(&self, config: &mut UserConfig) {
+		config.channel_options.max_dust_htlc_exposure_msat = self.max_dust_htlc_exposure_msat;
+	}
+}
+
+/// Defines the parameters of the routing scorer, trading off fees against reliability: higher
+/// penalties favor routes likely to succeed over cheaper ones
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ScorerParams {
+	/// Fixed penalty of every channel, favoring shorter routes
+	pub base_penalty_msat: u64,
+	/// Penalty of a channel as its liquidity becomes less likely to suffice
+	pub liquidity_penalty_multiplier_msat: u64,
+	/// Time after which half of what is learned about a channel's liquidity is forgotten
+	pub liquidity_offset_half_life_secs: u64,
+	/// Penalty of a channel per amount sent over it, scaled like the liquidity penalty
+	pub amount_penalty_multiplier_msat: u64,
+}
+
+impl Default for ScorerParams {
+	fn default() -> Self {
+		let params = ProbabilisticScoringParameters::default();
+		Self {
+			base_penalty_msat: params.base_penalty_msat,
+			liquidity_penalty_multiplier_msat: params.liquidity_penalty_multiplier_msat,
+			liquidity_offset_half_life_secs: params.liquidity_offset_half_life.as_secs(),
+			amount_penalty_multiplier_msat: params.amount_penalty_multiplier_msat,
+		}
+	}
+}
+
+impl ScorerParams {
+	pub(crate) fn to_ldk(&self) -> ProbabilisticScoringParameters {
+		ProbabilisticScoringParameters {
+			base_penalty_msat: self.base_penalty_msat,
+			liquidity_penalty_multiplier_msat: self.liquidity_penalty_multiplier_msat,
+			liquidity_offset_half_life: Duration::from_secs(self.liquidity_offset_half_life_secs),
+			amount_penalty_multiplier_msat: self.amount_penalty_multiplier_msat,
+		}
+	}
+}
+
+/// Defines what the node announces about itself in its node announcements
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct NodeAnnouncementInfo {
+	pub alias: String,
+	/// RGB color as 6 hex digits, e.g. `3399ff`
+	pub color: String,
+	/// Public listening addresses, as `ip:port` or `<v3 address>.onion:port`
+	pub addresses: Vec<String>,
+}
+
+impl NodeAnnouncementInfo {
+	/// Convert into the RGB color, alias and addresses `broadcast_node_announcement` takes,
+	/// failing if any of them is invalid
+	pub(crate) fn to_announcement(&self) -> Result<([u8; 3], [u8; 32], Vec<NetAddress>), String> {
+		if self.alias.len() > 32 {
+			return Err(format!("alias can't be longer than 32 bytes: {}", self.alias));
+		}
+		let mut alias = [0; 32];
+		alias[..self.alias.len()].copy_from_slice(self.alias.as_bytes());
+
+		let color = match hex_utils::to_vec(&self.color) {
+			Some(color) if color.len() == 3 => [color[0], color[1], color[2]],
+			_ => return Err(format!("color must be 6 hex digits: {}", self.color)),
+		};
+
+		let mut addresses = Vec::new();
+		for address in &self.addresses {
+			if address.ends_with(".onion") || address.contains(".onion:") {
+				addresses.push(tor::parse_onion_address(address)?);
+				continue;
+			}
+			match address.parse::<SocketAddr>() {
+				Ok(SocketAddr::V4(addr)) => {
+					addresses.push(NetAddress::IPv4 { addr: addr.ip().octets(), port: addr.port() })
+				}
+				Ok(SocketAddr::V6(addr)) => {
+					addresses.push(NetAddress::IPv6 { addr: addr.ip().octets(), port: addr.port() })
+				}
+				Err(_) => return Err(format!("invalid address, expected ip:port: {}", address)),
+			}
+		}
+		Ok((color, alias, addresses))
+	}
+}
 
 pub type ChainMonitor = chainmonitor::ChainMonitor<
 	InMemorySigner,
 	Arc<dyn Filter + Send + Sync>,
-	Arc<BitcoindClient>,
-	Arc<BitcoindClient>,
+	Arc<BroadcastManager>,
+	Arc<ChainSource>,
 	Arc<FilesystemLogger>,
-	Arc<FilesystemPersister>,
+	Arc<ReplicatedPersister>,
 >;
 
-pub(crate) type PeerManager = SimpleArcPeerManager<
+pub(crate) type PeerManager = peer_handler::PeerManager<
 	SocketDescriptor,
-	ChainMonitor,
-	BitcoindClient,
-	BitcoindClient,
-	dyn chain::Access + Send + Sync,
-	FilesystemLogger,
+	Arc<ChannelManager>,
+	Arc<
+		NetGraphMsgHandler<
+			Arc<NetworkGraph>,
+			Arc<dyn chain::Access + Send + Sync>,
+			Arc<FilesystemLogger>,
+		>,
+	>,
+	Arc<FilesystemLogger>,
+	Arc<LspClient>,
 >;
 
-pub type ChannelManager =
-	SimpleArcChannelManager<ChainMonitor, BitcoindClient, BitcoindClient, FilesystemLogger>;
+/// Channel manager of the node. Its keys manager is a `NodeKeysManager`, so that the node can
+/// take part in a phantom node, see `invoice::create_phantom_invoice`.
+pub type ChannelManager = channelmanager::ChannelManager<
+	InMemorySigner,
+	Arc<ChainMonitor>,
+	Arc<BroadcastManager>,
+	Arc<NodeKeysManager>,
+	Arc<ChainSource>,
+	Arc<FilesystemLogger>,
+>;
 
 pub type InvoicePayer<E> = payment::InvoicePayer<
 	Arc<ChannelManager>,
-	Router,
+	LimitedRouter,
 	Arc<Mutex<ProbabilisticScorer<Arc<NetworkGraph>>>>,
 	Arc<FilesystemLogger>,
 	E,
@@ -88,9 +524,9 @@ impl
 	Persister<
 		InMemorySigner,
 		Arc<ChainMonitor>,
-		Arc<BitcoindClient>,
-		Arc<KeysManager>,
-		Arc<BitcoindClient>,
+		Arc<BroadcastManager>,
+		Arc<NodeKeysManager>,
+		Arc<ChainSource>,
 		Arc<FilesystemLogger>,
 	> for DataPersister
 {
@@ -103,7 +539,7 @@ impl
 		{
 			// Persistence errors here are non-fatal as we can just fetch the routing graph
 			// again later, but they may indicate a disk error which could be fatal elsewhere.
-			eprintln!("Warning: Failed to persist network graph, check your disk and permissions");
+			warn!("failed to persist network graph, check your disk and permissions");
 		}
 
 		Ok(())
@@ -0,0 +1,174 @@
+use crate::autopilot::{AutopilotCandidate, AutopilotChannel, AutopilotStatus};
+use crate::journal::JournalEntry;
+use crate::node_var::{
+	BalanceSample, ChannelLabel, ChannelSnapshot, ClosedChannelInfo, ForwardInfo, InvoiceState,
+	PaymentHop,
+};
+use crate::peers::PeerDirection;
+use crate::server;
+use actix_web::web;
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+/// Path the OpenAPI document is served at
+pub const OPENAPI_PATH: &str = "/openapi.json";
+
+/// Path the Swagger UI is served under, with the `swagger-ui` feature
+pub const SWAGGER_UI_PATH: &str = "/swagger-ui/";
+
+/// OpenAPI 3 description of the v1 API
+#[derive(OpenApi)]
+#[openapi(
+	paths(
+		server::help,
+		server::nodeinfo,
+		server::health,
+		server::fee_rates,
+		server::list_peers,
+		server::connect_peer,
+		server::list_channels,
+		server::open_channel,
+		server::closed_channels,
+		server::diagnose_channel,
+		server::set_channel_label,
+		server::v1_channel_history,
+		server::list_invoices,
+		server::get_invoice,
+		server::v1_decode_invoice,
+		server::v1_cancel_invoice,
+		server::v1_list_payments,
+		server::send_payment,
+		server::v1_lookup_payment,
+		server::pending_approvals,
+		server::approve_payment,
+		server::autopilot_status,
+		server::enable_autopilot,
+		server::disable_autopilot,
+		server::lsp_info,
+		server::create_lsp_order,
+		server::lsp_order,
+		server::reject_payment,
+		server::payment_status,
+		server::cancel_payment,
+		server::v1_query_routes,
+		server::v1_list_forwards,
+		server::fee_report,
+		server::v1_export_payments,
+		server::v1_export_forwards,
+		server::wallet_balance,
+		server::balance,
+		server::list_utxos,
+		server::pending_txs,
+		server::new_address,
+		server::list_webhooks,
+		server::events,
+		server::v1_event_history,
+		server::phantom_route_hints,
+	),
+	components(schemas(
+		server::ServerError,
+		server::ErrorCode,
+		server::ServerSuccess,
+		server::Help,
+		server::NodeInfo,
+		server::Health,
+		server::FeeRates,
+		server::FeeRateEstimate,
+		server::ListPeers,
+		server::PeerDetails,
+		PeerDirection,
+		server::ConnectPeer,
+		server::SetPeerLabel,
+		server::ListChannels,
+		server::RedefinedChannelDetails,
+		server::OpenChannel,
+		server::OpenChannelConfig,
+		server::ClosedChannels,
+		ClosedChannelInfo,
+		ChannelSnapshot,
+		ChannelLabel,
+		server::SetChannelLabel,
+		server::ChannelHistory,
+		server::ChannelBalanceHistory,
+		BalanceSample,
+		server::ChannelDiagnostics,
+		server::DiagnosedBalance,
+		server::Invoices,
+		server::InvoiceDetails,
+		InvoiceState,
+		server::GetInvoice,
+		server::CreatedInvoice,
+		server::DecodedInvoice,
+		server::DecodedRouteHintHop,
+		server::ServerInvoice,
+		server::PaymentResult,
+		server::Payments,
+		server::Payment,
+		PaymentHop,
+		server::PaymentDetails,
+		server::PendingApprovals,
+		AutopilotStatus,
+		AutopilotChannel,
+		AutopilotCandidate,
+		server::LspOrder,
+		server::LspResponse,
+		server::PendingApproval,
+		server::QueriedRoutes,
+		server::RouteInfo,
+		server::RouteHopInfo,
+		server::Forwards,
+		ForwardInfo,
+		server::FeeReport,
+		server::ChannelFeeReport,
+		server::WalletBalance,
+		server::BalanceSummary,
+		server::ListUtxos,
+		server::Utxo,
+		server::PendingTxs,
+		server::PendingTx,
+		server::OnchainAddress,
+		server::ListWebhooks,
+		server::WebhookInfo,
+		server::JournaledEvents,
+		JournalEntry,
+		server::PhantomRouteHintsInfo,
+	)),
+	modifiers(&ApiTokenScheme),
+	security(("api_token" = []))
+)]
+pub struct ApiDoc;
+
+/// Declare the bearer token every endpoint requires
+struct ApiTokenScheme;
+
+impl Modify for ApiTokenScheme {
+	fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+		if let Some(components) = openapi.components.as_mut() {
+			components.add_security_scheme(
+				"api_token",
+				SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+			);
+		}
+	}
+}
+
+/// Whether `path` only describes the API, so that it can be fetched without the API token
+pub(crate) fn is_public_path(path: &str) -> bool {
+	path == OPENAPI_PATH || path.starts_with(SWAGGER_UI_PATH)
+}
+
+/// Serve the OpenAPI document, and the Swagger UI with the `swagger-ui` feature
+pub(crate) fn configure(cfg: &mut web::ServiceConfig) {
+	#[cfg(feature = "swagger-ui")]
+	cfg.service(
+		utoipa_swagger_ui::SwaggerUi::new(format!("{}{{_:.*}}", SWAGGER_UI_PATH))
+			.url(OPENAPI_PATH, ApiDoc::openapi()),
+	);
+	#[cfg(not(feature = "swagger-ui"))]
+	cfg.route(OPENAPI_PATH, web::get().to(openapi_json));
+}
+
+#[cfg(not(feature = "swagger-ui"))]
+async fn openapi_json() -> actix_web::HttpResponse {
+	actix_web::HttpResponse::Ok().json(ApiDoc::openapi())
+}
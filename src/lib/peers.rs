@@ -0,0 +1,286 @@
+use crate::disk;
+use crate::hex_utils;
+use crate::node_var::unix_timestamp;
+use crate::settings::P2pSettings;
+use crate::storage::NodeStore;
+use crate::tor;
+use bitcoin::secp256k1::PublicKey;
+use lightning::ln::msgs::NetAddress;
+use lightning::routing::network_graph::{NetworkGraph, NodeId};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use utoipa::ToSchema;
+
+pub(crate) const PEER_LABELS_NAMESPACE: &str = "peer_labels";
+
+/// Address of a peer: an IP address, or a DNS or onion host name which is only resolved when
+/// connecting to the peer
+#[derive(Clone, Debug, PartialEq)]
+pub struct PeerAddr {
+	pub host: String,
+	pub port: u16,
+}
+
+impl PeerAddr {
+	pub(crate) fn new(host: &str, port: u16) -> Result<Self, String> {
+		let host = host.trim_start_matches('[').trim_end_matches(']');
+		if host.is_empty() || host.contains(|c: char| c.is_whitespace() || c == '/' || c == '@') {
+			return Err(format!("invalid host {}", host));
+		}
+		let peer_addr = PeerAddr { host: host.to_lowercase(), port };
+		if peer_addr.is_onion() {
+			tor::parse_onion_address(&peer_addr.to_string())?;
+		}
+		Ok(peer_addr)
+	}
+
+	pub(crate) fn ip(&self) -> Option<IpAddr> {
+		self.host.parse().ok()
+	}
+
+	pub(crate) fn is_onion(&self) -> bool {
+		self.host.ends_with(".onion")
+	}
+}
+
+impl FromStr for PeerAddr {
+	type Err = String;
+	fn from_str(s: &str) -> Result<Self, String> {
+		let (host, port) = s
+			.rsplit_once(':')
+			.ok_or_else(|| format!("invalid address {}, expected host:port", s))?;
+		let port = port.parse().map_err(|_| format!("invalid port {}", port))?;
+		PeerAddr::new(host, port)
+	}
+}
+
+impl fmt::Display for PeerAddr {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.ip() {
+			Some(IpAddr::V6(_)) => write!(f, "[{}]:{}", self.host, self.port),
+			_ => write!(f, "{}:{}", self.host, self.port),
+		}
+	}
+}
+
+impl From<SocketAddr> for PeerAddr {
+	fn from(addr: SocketAddr) -> Self {
+		PeerAddr { host: addr.ip().to_string(), port: addr.port() }
+	}
+}
+
+/// Which side opened the connection to a peer
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PeerDirection {
+	Inbound,
+	Outbound,
+}
+
+/// A connection to a peer, as seen by the node
+#[derive(Clone, Debug)]
+pub struct PeerConnection {
+	/// Unset if the peer connected to us before its address could be matched to it
+	pub address: Option<PeerAddr>,
+	pub direction: PeerDirection,
+	pub connected_since: u64,
+}
+
+/// Connection details of the node's peers, which LDK's peer manager doesn't keep. Outbound
+/// connections are recorded as they are made, and peers which show up in the peer manager
+/// otherwise are inbound ones.
+#[derive(Default)]
+pub struct PeerTracker {
+	connections: Mutex<HashMap<PublicKey, PeerConnection>>,
+	/// Last time disconnected peers were seen connected
+	last_seen: Mutex<HashMap<PublicKey, u64>>,
+}
+
+impl PeerTracker {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Record the connection to `pubkey` at `address`
+	pub(crate) fn connected(&self, pubkey: PublicKey, address: PeerAddr, direction: PeerDirection) {
+		let mut connections = self.connections.lock().unwrap();
+		let connection = connections.entry(pubkey).or_insert_with(|| PeerConnection {
+			address: None,
+			direction,
+			connected_since: unix_timestamp(),
+		});
+		connection.address = Some(address);
+		connection.direction = direction;
+	}
+
+	/// Bring the connections in line with the peers the peer manager is connected to
+	pub(crate) fn refresh(&self, connected: &[PublicKey]) {
+		let now = unix_timestamp();
+		let mut connections = self.connections.lock().unwrap();
+		let mut last_seen = self.last_seen.lock().unwrap();
+		connections.retain(|pubkey, _| {
+			if connected.contains(pubkey) {
+				return true;
+			}
+			last_seen.insert(*pubkey, now);
+			false
+		});
+		for pubkey in connected {
+			connections.entry(*pubkey).or_insert_with(|| PeerConnection {
+				address: None,
+				direction: PeerDirection::Inbound,
+				connected_since: now,
+			});
+			last_seen.remove(pubkey);
+		}
+	}
+
+	/// Whether the connection to `pubkey` is recorded with its address
+	pub(crate) fn has_address(&self, pubkey: &PublicKey) -> bool {
+		match self.connections.lock().unwrap().get(pubkey) {
+			Some(connection) => connection.address.is_some(),
+			None => false,
+		}
+	}
+
+	pub(crate) fn connection(&self, pubkey: &PublicKey) -> Option<PeerConnection> {
+		self.connections.lock().unwrap().get(pubkey).cloned()
+	}
+
+	/// Last time `pubkey` was seen connected, if it is disconnected now
+	pub(crate) fn last_seen(&self, pubkey: &PublicKey) -> Option<u64> {
+		self.last_seen.lock().unwrap().get(pubkey).copied()
+	}
+}
+
+/// Limits of the inbound connections the node keeps at once, overall and per IP address
+pub(crate) struct InboundLimits {
+	max_connections: Option<usize>,
+	max_connections_per_ip: Option<usize>,
+	connections: Mutex<HashMap<IpAddr, usize>>,
+}
+
+/// An inbound connection counted against the limits until it is dropped
+pub(crate) struct InboundPermit {
+	limits: Arc<InboundLimits>,
+	ip: IpAddr,
+}
+
+impl InboundLimits {
+	pub(crate) fn new(settings: &P2pSettings) -> Arc<Self> {
+		Arc::new(Self {
+			max_connections: settings.max_inbound_connections,
+			max_connections_per_ip: settings.max_inbound_connections_per_ip,
+			connections: Mutex::new(HashMap::new()),
+		})
+	}
+
+	/// Count a connection from `ip`, unless it would exceed a limit
+	pub(crate) fn acquire(self: &Arc<Self>, ip: IpAddr) -> Result<InboundPermit, String> {
+		let mut connections = self.connections.lock().unwrap();
+		if let Some(max_connections) = self.max_connections {
+			if connections.values().sum::<usize>() >= max_connections {
+				return Err(format!("already {} inbound connections", max_connections));
+			}
+		}
+		if let Some(max_connections_per_ip) = self.max_connections_per_ip {
+			if connections.get(&ip).copied().unwrap_or(0) >= max_connections_per_ip {
+				return Err(format!(
+					"already {} inbound connections from {}",
+					max_connections_per_ip, ip
+				));
+			}
+		}
+		*connections.entry(ip).or_insert(0) += 1;
+		Ok(InboundPermit { limits: Arc::clone(self), ip })
+	}
+}
+
+impl Drop for InboundPermit {
+	fn drop(&mut self) {
+		let mut connections = self.limits.connections.lock().unwrap();
+		if let Some(ip_connections) = connections.get_mut(&self.ip) {
+			*ip_connections -= 1;
+			if *ip_connections == 0 {
+				connections.remove(&self.ip);
+			}
+		}
+	}
+}
+
+/// Addresses `pubkey` announced in the gossip
+pub(crate) fn announced_addresses(
+	network_graph: &NetworkGraph, pubkey: &PublicKey,
+) -> Vec<PeerAddr> {
+	let graph = network_graph.read_only();
+	let announcement = match graph.nodes().get(&NodeId::from_pubkey(pubkey)) {
+		Some(node) => match &node.announcement_info {
+			Some(announcement) => announcement,
+			None => return Vec::new(),
+		},
+		None => return Vec::new(),
+	};
+	announcement
+		.addresses
+		.iter()
+		.filter_map(|address| match address {
+			NetAddress::IPv4 { addr, port } => {
+				Some(SocketAddr::new(Ipv4Addr::from(*addr).into(), *port).into())
+			}
+			NetAddress::IPv6 { addr, port } => {
+				Some(SocketAddr::new(Ipv6Addr::from(*addr).into(), *port).into())
+			}
+			NetAddress::OnionV3 { ed25519_pubkey, checksum, version, port } => Some(PeerAddr {
+				host: tor::onion_host(ed25519_pubkey, *checksum, *version),
+				port: *port,
+			}),
+			// Tor dropped v2 hidden services
+			_ => None,
+		})
+		.collect()
+}
+
+/// Addresses `pubkey` can be reached at, the one it was last connected at first, then the ones it
+/// announced in the gossip
+pub(crate) fn known_addresses(
+	store: &dyn NodeStore, network_graph: &NetworkGraph, pubkey: &PublicKey,
+) -> io::Result<Vec<PeerAddr>> {
+	let mut addresses: Vec<PeerAddr> =
+		disk::read_channel_peer_data(store)?.get(pubkey).cloned().into_iter().collect();
+	for address in announced_addresses(network_graph, pubkey) {
+		if !addresses.contains(&address) {
+			addresses.push(address);
+		}
+	}
+	Ok(addresses)
+}
+
+/// Labels the operator gave to peers
+pub(crate) fn read_peer_labels(store: &dyn NodeStore) -> io::Result<HashMap<PublicKey, String>> {
+	let mut labels = HashMap::new();
+	for (key, value) in store.list(PEER_LABELS_NAMESPACE)? {
+		let pubkey = hex_utils::to_compressed_pubkey(&key).ok_or_else(|| {
+			io::Error::new(io::ErrorKind::InvalidData, format!("invalid peer pubkey {}", key))
+		})?;
+		let label =
+			String::from_utf8(value).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+		labels.insert(pubkey, label);
+	}
+	Ok(labels)
+}
+
+/// Label `pubkey`, or remove its label if `label` is unset
+pub(crate) fn persist_peer_label(
+	store: &dyn NodeStore, pubkey: &PublicKey, label: Option<&str>,
+) -> io::Result<()> {
+	let key = hex_utils::hex_str(&pubkey.serialize());
+	match label {
+		Some(label) => store.write(PEER_LABELS_NAMESPACE, &key, label.as_bytes()),
+		None => store.remove(PEER_LABELS_NAMESPACE, &key),
+	}
+}
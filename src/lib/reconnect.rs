@@ -0,0 +1,160 @@
+use crate::cli;
+use crate::disk;
+use crate::node_var::{ChannelManager, PeerManager};
+use crate::peers::{self, PeerAddr, PeerTracker};
+use crate::storage::NodeStore;
+use bitcoin::secp256k1::PublicKey;
+use lightning::routing::network_graph::NetworkGraph;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+/// Delay before the first reconnection attempt, doubled after each failed one
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(600);
+
+/// Reconnection attempts to a disconnected channel peer
+struct Backoff {
+	failures: u32,
+	next_attempt: Instant,
+	in_flight: bool,
+}
+
+/// Background task reconnecting to the peers the node has channels with whenever their connection
+/// drops, backing off exponentially while they can't be reached. Peers are tried at their
+/// last-known address first, then at the addresses they announced in the gossip, and the address
+/// which worked is persisted for the next time.
+pub(crate) struct Reconnector {
+	pub(crate) channel_manager: Arc<ChannelManager>,
+	pub(crate) peer_manager: Arc<PeerManager>,
+	pub(crate) network_graph: Arc<NetworkGraph>,
+	pub(crate) store: Arc<dyn NodeStore>,
+	pub(crate) peer_tracker: Arc<PeerTracker>,
+	pub(crate) socks_proxy: Option<SocketAddr>,
+	pub(crate) stop: Arc<AtomicBool>,
+}
+
+impl Reconnector {
+	pub(crate) async fn run(self) {
+		let backoffs: Arc<Mutex<HashMap<PublicKey, Backoff>>> =
+			Arc::new(Mutex::new(HashMap::new()));
+		let mut interval = tokio::time::interval(Duration::from_secs(1));
+		loop {
+			interval.tick().await;
+			if self.stop.load(Ordering::Acquire) {
+				return;
+			}
+			let connected = self.peer_manager.get_peer_node_ids();
+			self.peer_tracker.refresh(&connected);
+
+			let mut channel_peers: Vec<PublicKey> = self
+				.channel_manager
+				.list_channels()
+				.iter()
+				.map(|chan| chan.counterparty.node_id)
+				.collect();
+			channel_peers.sort();
+			channel_peers.dedup();
+			let disconnected: Vec<PublicKey> =
+				channel_peers.into_iter().filter(|pubkey| !connected.contains(pubkey)).collect();
+
+			let now = Instant::now();
+			let due: Vec<PublicKey> = {
+				let mut backoffs = backoffs.lock().unwrap();
+				backoffs.retain(|pubkey, _| disconnected.contains(pubkey));
+				disconnected
+					.into_iter()
+					.filter(|pubkey| {
+						let backoff = backoffs.entry(*pubkey).or_insert(Backoff {
+							failures: 0,
+							next_attempt: now,
+							in_flight: false,
+						});
+						if backoff.in_flight || backoff.next_attempt > now {
+							return false;
+						}
+						backoff.in_flight = true;
+						true
+					})
+					.collect()
+			};
+			if due.is_empty() {
+				continue;
+			}
+
+			let known_addresses = match disk::read_channel_peer_data(&*self.store) {
+				Ok(addresses) => addresses,
+				Err(e) => {
+					error!("errored reading channel peer info from disk: {:?}", e);
+					HashMap::new()
+				}
+			};
+			for pubkey in due {
+				let known_address = known_addresses.get(&pubkey).cloned();
+				let mut addresses: Vec<PeerAddr> = known_address.clone().into_iter().collect();
+				for address in peers::announced_addresses(&self.network_graph, &pubkey) {
+					if !addresses.contains(&address) {
+						addresses.push(address);
+					}
+				}
+				let peer_manager = Arc::clone(&self.peer_manager);
+				let store = Arc::clone(&self.store);
+				let peer_tracker = Arc::clone(&self.peer_tracker);
+				let socks_proxy = self.socks_proxy;
+				let backoffs = Arc::clone(&backoffs);
+				tokio::spawn(async move {
+					let mut reconnected = None;
+					for address in addresses {
+						if cli::do_connect_peer(
+							pubkey,
+							address.clone(),
+							Arc::clone(&peer_manager),
+							socks_proxy,
+							&peer_tracker,
+						)
+						.await
+						.is_ok()
+						{
+							reconnected = Some(address);
+							break;
+						}
+					}
+
+					if let Some(address) = &reconnected {
+						info!("reconnected to peer {} at {}", pubkey, address);
+						// The address may have come from the gossip, or the peer may have moved
+						if known_address.as_ref() != Some(address) {
+							if let Err(e) = disk::persist_channel_peer(&*store, &pubkey, address) {
+								warn!("failed to persist the address of peer {}: {}", pubkey, e);
+							}
+						}
+					}
+
+					let mut backoffs = backoffs.lock().unwrap();
+					let backoff = match backoffs.get_mut(&pubkey) {
+						Some(backoff) => backoff,
+						None => return,
+					};
+					backoff.in_flight = false;
+					if reconnected.is_some() {
+						backoff.failures = 0;
+						return;
+					}
+					backoff.failures = backoff.failures.saturating_add(1);
+					let delay = INITIAL_BACKOFF
+						.checked_mul(1 << backoff.failures.min(10))
+						.map_or(MAX_BACKOFF, |delay| delay.min(MAX_BACKOFF));
+					backoff.next_attempt = Instant::now() + delay;
+					warn!(
+						"failed to reconnect to peer {}, retrying in {}s",
+						pubkey,
+						delay.as_secs()
+					);
+				});
+			}
+		}
+	}
+}
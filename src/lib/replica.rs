@@ -0,0 +1,105 @@
+use bitcoin::BlockHash;
+use lightning::chain::chainmonitor::{MonitorUpdateId, Persist};
+use lightning::chain::channelmonitor::{ChannelMonitor, ChannelMonitorUpdate};
+use lightning::chain::keysinterface::{KeysInterface, Sign};
+use lightning::chain::transaction::OutPoint;
+use lightning::chain::ChannelMonitorUpdateErr;
+use lightning::util::ser::Writeable;
+use lightning_persister::FilesystemPersister;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use tracing::error;
+
+/// Persists ChannelMonitors in the node's data directory and mirrors each write to a replica
+/// directory, e.g. on another disk or a mounted network share such as SFTP or S3.
+///
+/// A monitor update fails if either write fails, which LDK treats as a permanent failure:
+/// the channel is force-closed rather than carrying on with a monitor that may be lost.
+pub struct ReplicatedPersister {
+	primary: FilesystemPersister,
+	replica_dir: Option<PathBuf>,
+}
+
+impl ReplicatedPersister {
+	/// Create the persister, copying the monitors already in `ldk_data_dir` to the replica so
+	/// that it is complete before any update is mirrored
+	pub(crate) fn new(ldk_data_dir: String, replica_dir: Option<String>) -> io::Result<Self> {
+		let replica_dir = match replica_dir {
+			Some(dir) => {
+				let replica_monitors = Path::new(&dir).join("monitors");
+				fs::create_dir_all(&replica_monitors)?;
+				let monitors_path = Path::new(&ldk_data_dir).join("monitors");
+				if monitors_path.exists() {
+					for entry in fs::read_dir(&monitors_path)? {
+						let entry = entry?;
+						let name = entry.file_name();
+						if name.to_string_lossy().ends_with(".tmp") || !entry.file_type()?.is_file()
+						{
+							continue;
+						}
+						write_atomically(&replica_monitors.join(&name), &fs::read(entry.path())?)?;
+					}
+				}
+				Some(replica_monitors)
+			}
+			None => None,
+		};
+		Ok(Self { primary: FilesystemPersister::new(ldk_data_dir), replica_dir })
+	}
+
+	pub(crate) fn read_channelmonitors<Signer: Sign, K: Deref>(
+		&self, keys_manager: K,
+	) -> io::Result<Vec<(BlockHash, ChannelMonitor<Signer>)>>
+	where
+		K::Target: KeysInterface<Signer = Signer> + Sized,
+	{
+		self.primary.read_channelmonitors(keys_manager)
+	}
+
+	fn replicate<Signer: Sign>(
+		&self, funding_txo: OutPoint, monitor: &ChannelMonitor<Signer>,
+	) -> Result<(), ChannelMonitorUpdateErr> {
+		if let Some(replica_dir) = &self.replica_dir {
+			// Monitors are named after their funding outpoint, like `FilesystemPersister` does
+			let path = replica_dir.join(format!("{}_{}", funding_txo.txid, funding_txo.index));
+			if let Err(e) = write_atomically(&path, &monitor.encode()) {
+				error!("failed to replicate channel monitor to {:?}: {}", path, e);
+				return Err(ChannelMonitorUpdateErr::PermanentFailure);
+			}
+		}
+		Ok(())
+	}
+}
+
+impl<Signer: Sign> Persist<Signer> for ReplicatedPersister {
+	fn persist_new_channel(
+		&self, funding_txo: OutPoint, monitor: &ChannelMonitor<Signer>, update_id: MonitorUpdateId,
+	) -> Result<(), ChannelMonitorUpdateErr> {
+		self.primary.persist_new_channel(funding_txo, monitor, update_id)?;
+		self.replicate(funding_txo, monitor)
+	}
+
+	fn update_persisted_channel(
+		&self, funding_txo: OutPoint, update: &Option<ChannelMonitorUpdate>,
+		monitor: &ChannelMonitor<Signer>, update_id: MonitorUpdateId,
+	) -> Result<(), ChannelMonitorUpdateErr> {
+		self.primary.update_persisted_channel(funding_txo, update, monitor, update_id)?;
+		self.replicate(funding_txo, monitor)
+	}
+}
+
+/// Write `data` to `path` through a temporary file, so a crash never leaves a partial monitor
+fn write_atomically(path: &Path, data: &[u8]) -> io::Result<()> {
+	let tmp_path = path.with_extension("tmp");
+	let mut tmp_file = fs::File::create(&tmp_path)?;
+	tmp_file.write_all(data)?;
+	tmp_file.sync_all()?;
+	fs::rename(&tmp_path, path)?;
+	if let Some(parent) = path.parent() {
+		fs::File::open(parent)?.sync_all()?;
+	}
+	Ok(())
+}
@@ -0,0 +1,66 @@
+use crate::disk::FilesystemLogger;
+use bitcoin::secp256k1::PublicKey;
+use lightning::ln::channelmanager::ChannelDetails;
+use lightning::ln::msgs::{ErrorAction, LightningError};
+use lightning::ln::PaymentHash;
+use lightning::routing::network_graph::NetworkGraph;
+use lightning::routing::router::{Route, RouteParameters};
+use lightning::routing::scoring::Score;
+use lightning_invoice::payment;
+use lightning_invoice::utils::DefaultRouter;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Limits of a payment, enforced on the route of every attempt to pay it
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PaymentLimits {
+	pub max_fee_msat: Option<u64>,
+	/// Time after which the payment isn't retried anymore
+	pub deadline: Option<Instant>,
+}
+
+pub type PaymentLimitsStorage = Arc<Mutex<HashMap<PaymentHash, PaymentLimits>>>;
+
+/// Router of the `InvoicePayer`, which finds routes like LDK's default router but fails to
+/// route payments over their fee limit or past their deadline. As the `InvoicePayer` finds a
+/// new route for each retry, a payment is given up once no route fits its limits.
+pub struct LimitedRouter {
+	router: DefaultRouter<Arc<NetworkGraph>, Arc<FilesystemLogger>>,
+	limits: PaymentLimitsStorage,
+}
+
+impl LimitedRouter {
+	pub(crate) fn new(
+		router: DefaultRouter<Arc<NetworkGraph>, Arc<FilesystemLogger>>,
+		limits: PaymentLimitsStorage,
+	) -> Self {
+		Self { router, limits }
+	}
+}
+
+impl<S: Score> payment::Router<S> for LimitedRouter {
+	fn find_route(
+		&self, payer: &PublicKey, params: &RouteParameters, payment_hash: &PaymentHash,
+		first_hops: Option<&[&ChannelDetails]>, scorer: &S,
+	) -> Result<Route, LightningError> {
+		let limits = self.limits.lock().unwrap().get(payment_hash).copied().unwrap_or_default();
+		let error = |err: String| LightningError { err, action: ErrorAction::IgnoreError };
+		if let Some(deadline) = limits.deadline {
+			if Instant::now() >= deadline {
+				return Err(error("payment timed out".to_string()));
+			}
+		}
+		let route = self.router.find_route(payer, params, payment_hash, first_hops, scorer)?;
+		if let Some(max_fee_msat) = limits.max_fee_msat {
+			let fee_msat = route.get_total_fees();
+			if fee_msat > max_fee_msat {
+				return Err(error(format!(
+					"the cheapest route found costs {} msat in fees, more than the maximum of {} msat",
+					fee_msat, max_fee_msat
+				)));
+			}
+		}
+		Ok(route)
+	}
+}
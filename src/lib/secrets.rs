@@ -0,0 +1,166 @@
+use chacha20poly1305::aead::{Aead, NewAead};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::{thread_rng, Rng};
+use std::env;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+
+/// Environment variable holding the password the node's secrets are encrypted with
+pub const PASSWORD_ENV: &str = "LNNODE_PASSWORD";
+
+/// Environment variable holding the path of a file containing the password
+pub const PASSWORD_FILE_ENV: &str = "LNNODE_PASSWORD_FILE";
+
+/// Name of the file in the node's data directory holding the salt of the password. Its
+/// presence marks the node's secrets as encrypted.
+pub const SALT_FILE: &str = "secrets_salt";
+
+/// Extension of the encrypted version of a secret file
+const ENCRYPTED_EXTENSION: &str = "enc";
+
+/// Version byte prefixing encrypted secrets, followed by the nonce and the ciphertext
+const ENCRYPTED_SECRET_VERSION: u8 = 1;
+
+/// scrypt cost parameter, as a power of two, so that each password guess takes a while
+const SCRYPT_LOG_N: u8 = 15;
+
+/// Key the secrets in the node's data directory are encrypted with, derived from its password
+#[derive(Clone)]
+pub struct SecretsKey([u8; 32]);
+
+/// Unlock the node's secrets with the password from `LNNODE_PASSWORD`, the file at
+/// `LNNODE_PASSWORD_FILE` or, if the secrets are encrypted or `encrypt` is set, a prompt.
+///
+/// Without a password the secrets are stored in plaintext. Once a password is given, secrets
+/// are encrypted as they are read, so a password is needed on every later startup.
+pub(crate) fn unlock(ldk_data_dir: &str, encrypt: bool) -> io::Result<Option<SecretsKey>> {
+	let salt_path = format!("{}/{}", ldk_data_dir, SALT_FILE);
+	let encrypted = Path::new(&salt_path).exists();
+	let password = match read_password()? {
+		Some(password) => password,
+		None if encrypted => rpassword::read_password_from_tty(Some("Node password: "))?,
+		None if encrypt => {
+			let password = rpassword::read_password_from_tty(Some("New node password: "))?;
+			if rpassword::read_password_from_tty(Some("Confirm node password: "))? != password {
+				return Err(invalid("the passwords don't match"));
+			}
+			password
+		}
+		None => return Ok(None),
+	};
+	if password.is_empty() {
+		return Err(invalid("the password is empty"));
+	}
+
+	let salt = if encrypted {
+		fs::read(&salt_path)?
+	} else {
+		let mut salt = vec![0; 16];
+		thread_rng().fill_bytes(&mut salt);
+		write_private_file(&salt_path, &salt)?;
+		salt
+	};
+	let params = scrypt::Params::new(SCRYPT_LOG_N, 8, 1).map_err(|e| invalid(&e.to_string()))?;
+	let mut key = [0; 32];
+	scrypt::scrypt(password.as_bytes(), &salt, &params, &mut key)
+		.map_err(|e| invalid(&e.to_string()))?;
+	Ok(Some(SecretsKey(key)))
+}
+
+fn read_password() -> io::Result<Option<String>> {
+	if let Ok(password) = env::var(PASSWORD_ENV) {
+		return Ok(Some(password));
+	}
+	match env::var(PASSWORD_FILE_ENV) {
+		Ok(path) => Ok(Some(fs::read_to_string(path)?.trim_end_matches('\n').to_string())),
+		Err(_) => Ok(None),
+	}
+}
+
+/// Whether the secret at `path` exists, encrypted or not
+pub(crate) fn secret_exists(path: &str) -> bool {
+	Path::new(path).exists() || Path::new(&encrypted_path(path)).exists()
+}
+
+/// Read the secret at `path`, if it exists. A plaintext secret is encrypted in place when a key
+/// is given.
+pub(crate) fn read_secret(path: &str, key: Option<&SecretsKey>) -> io::Result<Option<Vec<u8>>> {
+	let encrypted_path = encrypted_path(path);
+	if Path::new(&encrypted_path).exists() {
+		let key = key.ok_or_else(|| invalid(&format!("{} is encrypted, set a password", path)))?;
+		let data = fs::read(&encrypted_path)?;
+		if data.len() < 13 || data[0] != ENCRYPTED_SECRET_VERSION {
+			return Err(invalid(&format!("unsupported encrypted secret {}", encrypted_path)));
+		}
+		let (nonce, ciphertext) = data[1..].split_at(12);
+		let plaintext = ChaCha20Poly1305::new(Key::from_slice(&key.0))
+			.decrypt(Nonce::from_slice(nonce), ciphertext)
+			.map_err(|_| invalid(&format!("unable to decrypt {}, is the password right?", path)))?;
+		return Ok(Some(plaintext));
+	}
+	if !Path::new(path).exists() {
+		return Ok(None);
+	}
+	let plaintext = fs::read(path)?;
+	if key.is_some() {
+		write_secret(path, &plaintext, key)?;
+	}
+	Ok(Some(plaintext))
+}
+
+/// Write the secret at `path`, encrypted if a key is given, only readable by the user running
+/// the node
+pub(crate) fn write_secret(path: &str, data: &[u8], key: Option<&SecretsKey>) -> io::Result<()> {
+	match key {
+		Some(key) => {
+			let mut nonce = [0; 12];
+			thread_rng().fill_bytes(&mut nonce);
+			let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&key.0))
+				.encrypt(Nonce::from_slice(&nonce), data)
+				.map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to encrypt secret"))?;
+			let mut encrypted = vec![ENCRYPTED_SECRET_VERSION];
+			encrypted.extend_from_slice(&nonce);
+			encrypted.extend_from_slice(&ciphertext);
+			write_private_file(&encrypted_path(path), &encrypted)?;
+			// Only remove the plaintext once the encrypted secret is on disk
+			if Path::new(path).exists() {
+				fs::remove_file(path)?;
+			}
+			Ok(())
+		}
+		None => write_private_file(path, data),
+	}
+}
+
+/// Remove the secret at `path`, encrypted or not
+pub(crate) fn remove_secret(path: &str) -> io::Result<()> {
+	for path in [path.to_string(), encrypted_path(path)] {
+		if Path::new(&path).exists() {
+			fs::remove_file(&path)?;
+		}
+	}
+	Ok(())
+}
+
+fn encrypted_path(path: &str) -> String {
+	format!("{}.{}", path, ENCRYPTED_EXTENSION)
+}
+
+fn invalid(msg: &str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn write_private_file(path: &str, data: &[u8]) -> io::Result<()> {
+	let mut options = fs::OpenOptions::new();
+	options.write(true).create(true).truncate(true);
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::OpenOptionsExt;
+		options.mode(0o600);
+	}
+	let mut file = options.open(path)?;
+	file.write_all(data)?;
+	file.sync_all()
+}
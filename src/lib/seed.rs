@@ -0,0 +1,56 @@
+use crate::secrets;
+use crate::secrets::SecretsKey;
+use bip39::Mnemonic;
+use rand::{thread_rng, Rng};
+use std::io;
+
+/// Environment variable holding the optional BIP39 passphrase of the node's mnemonic
+pub const MNEMONIC_PASSPHRASE_ENV: &str = "LNNODE_MNEMONIC_PASSPHRASE";
+
+/// Name of the file in the node's data directory holding the mnemonic of a new node until it
+/// is backed up
+pub const PENDING_MNEMONIC_FILE: &str = "mnemonic";
+
+/// Generate the 24-word mnemonic of a new node, returning its keys seed.
+///
+/// The mnemonic is kept in the data directory until `/seed/backup` displays it.
+pub(crate) fn generate_keys_seed(
+	ldk_data_dir: &str, passphrase: &str, secrets_key: Option<&SecretsKey>,
+) -> io::Result<[u8; 32]> {
+	let mut entropy = [0; 32];
+	thread_rng().fill_bytes(&mut entropy);
+	let mnemonic = Mnemonic::from_entropy(&entropy)
+		.map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+	secrets::write_secret(
+		&format!("{}/{}", ldk_data_dir, PENDING_MNEMONIC_FILE),
+		mnemonic.to_string().as_bytes(),
+		secrets_key,
+	)?;
+	Ok(keys_seed(&mnemonic, passphrase))
+}
+
+/// Derive the keys seed of a node from its mnemonic
+pub(crate) fn keys_seed_from_mnemonic(phrase: &str, passphrase: &str) -> Result<[u8; 32], String> {
+	let mnemonic = Mnemonic::parse(phrase).map_err(|e| format!("invalid mnemonic: {}", e))?;
+	Ok(keys_seed(&mnemonic, passphrase))
+}
+
+/// The KeysManager takes a 32-byte seed, so the first half of the BIP39 seed is used
+fn keys_seed(mnemonic: &Mnemonic, passphrase: &str) -> [u8; 32] {
+	let mut seed = [0; 32];
+	seed.copy_from_slice(&mnemonic.to_seed(passphrase)[..32]);
+	seed
+}
+
+/// Read and delete the mnemonic of the node, so that it is only ever displayed once
+pub(crate) fn take_pending_mnemonic(
+	ldk_data_dir: &str, secrets_key: Option<&SecretsKey>,
+) -> io::Result<Option<String>> {
+	let path = format!("{}/{}", ldk_data_dir, PENDING_MNEMONIC_FILE);
+	let mnemonic = match secrets::read_secret(&path, secrets_key)? {
+		Some(mnemonic) => String::from_utf8_lossy(&mnemonic).trim().to_string(),
+		None => return Ok(None),
+	};
+	secrets::remove_secret(&path)?;
+	Ok(Some(mnemonic))
+}
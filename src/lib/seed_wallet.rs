@@ -0,0 +1,415 @@
+use crate::chain::{ChainSource, ScriptUtxo, TxIndex};
+use crate::storage::NodeStore;
+use crate::wallet::{Utxo, WalletBalance};
+use bitcoin::blockdata::script::Script;
+use bitcoin::blockdata::transaction::{OutPoint, SigHashType, Transaction, TxIn, TxOut};
+use bitcoin::hash_types::Txid;
+use bitcoin::network::constants::Network;
+use bitcoin::secp256k1::{All, Message, Secp256k1};
+use bitcoin::util::address::Address;
+use bitcoin::util::bip143::SigHashCache;
+use bitcoin::util::bip32::{ChildNumber, ExtendedPrivKey};
+use bitcoin::util::ecdsa::{PrivateKey, PublicKey};
+use lightning::chain::chaininterface::{ConfirmationTarget, FeeEstimator};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::sync::{Arc, Mutex};
+
+pub(crate) const SEED_WALLET_NAMESPACE: &str = "seed_wallet";
+const STATE_KEY: &str = "state";
+
+/// Chains of BIP84 addresses, handed out to receive funds and used for change
+const RECEIVE_CHAIN: u32 = 0;
+const CHANGE_CHAIN: u32 = 1;
+
+/// Number of consecutive unused addresses after which no more are looked up, as in BIP44
+const GAP_LIMIT: u32 = 20;
+
+/// Smallest change output worth creating, the dust limit of P2WPKH outputs
+const DUST_LIMIT_SAT: u64 = 294;
+
+/// Weight of the version, locktime, input and output counts, and segwit marker and flag
+const TX_BASE_WEIGHT: u64 = 10 * 4 + 2;
+
+/// Weight of a P2WPKH input: outpoint, empty script, sequence, and a witness of a DER signature
+/// of at most 72 bytes and a compressed public key
+const P2WPKH_INPUT_WEIGHT: u64 = 41 * 4 + 1 + 73 + 34;
+
+/// Wallet of BIP84 P2WPKH keys derived from the node's seed, whose outputs are looked up in the
+/// Esplora or Electrum server's index, so that the node runs without bitcoind.
+///
+/// Only the index of the next address of each chain is persisted, along with the outputs
+/// reserved for channel funding transactions until they are broadcast or discarded. The outputs
+/// are looked up on every address handed out, and on the next `GAP_LIMIT` ones, which are also
+/// used to find the funds of a wallet restored from its seed.
+pub struct SeedWallet {
+	secp: Secp256k1<All>,
+	/// Key of the BIP84 account, at m/84'/<coin type>'/0'
+	account_key: ExtendedPrivKey,
+	network: Network,
+	tx_index: TxIndex,
+	chain_source: Arc<ChainSource>,
+	store: Arc<dyn NodeStore>,
+	state: Mutex<WalletState>,
+	/// Held while selecting the inputs of a transaction, so that concurrent ones don't select the
+	/// same outputs
+	spending: tokio::sync::Mutex<()>,
+}
+
+#[derive(Clone, Default, Deserialize, Serialize)]
+struct WalletState {
+	next_receive_index: u32,
+	next_change_index: u32,
+	reservations: Vec<Reservation>,
+}
+
+/// Inputs of a channel funding transaction, which aren't spent by other transactions until it
+/// is broadcast or discarded
+#[derive(Clone, Deserialize, Serialize)]
+struct Reservation {
+	txid: Txid,
+	outpoints: Vec<OutPoint>,
+}
+
+/// An unspent output of the wallet, paying to the address at `index` of `chain`
+struct WalletUtxo {
+	utxo: ScriptUtxo,
+	chain: u32,
+	index: u32,
+}
+
+impl SeedWallet {
+	pub(crate) fn new(
+		seed: &[u8; 32], network: Network, tx_index: TxIndex, chain_source: Arc<ChainSource>,
+		store: Arc<dyn NodeStore>,
+	) -> io::Result<Self> {
+		let secp = Secp256k1::new();
+		let coin_type = match network {
+			Network::Bitcoin => 0,
+			_ => 1,
+		};
+		let path = [
+			ChildNumber::from_hardened_idx(84).unwrap(),
+			ChildNumber::from_hardened_idx(coin_type).unwrap(),
+			ChildNumber::from_hardened_idx(0).unwrap(),
+		];
+		let account_key = ExtendedPrivKey::new_master(network, seed)
+			.and_then(|master_key| master_key.derive_priv(&secp, &path))
+			.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+		let state = match store.read(SEED_WALLET_NAMESPACE, STATE_KEY)? {
+			Some(value) => serde_json::from_slice(&value)
+				.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+			None => WalletState::default(),
+		};
+		Ok(Self {
+			secp,
+			account_key,
+			network,
+			tx_index,
+			chain_source,
+			store,
+			state: Mutex::new(state),
+			spending: tokio::sync::Mutex::new(()),
+		})
+	}
+
+	/// List the wallet's spendable outputs, including the ones reserved for pending channel
+	/// funding transactions
+	pub(crate) async fn list_utxos(&self) -> io::Result<Vec<Utxo>> {
+		let (_, tip_height) = self.tx_index.get_tip().await?;
+		let utxos = self.scan().await?;
+		let state = self.state.lock().unwrap();
+		Ok(utxos
+			.into_iter()
+			.map(|wallet_utxo| {
+				let utxo = wallet_utxo.utxo;
+				Utxo {
+					txid: utxo.outpoint.txid,
+					vout: utxo.outpoint.vout,
+					amount_sat: utxo.value,
+					confirmations: utxo
+						.height
+						.map_or(0, |height| tip_height.saturating_sub(height).saturating_add(1)),
+					reserved: state.is_reserved(&utxo.outpoint),
+				}
+			})
+			.collect())
+	}
+
+	/// Get the confirmed and unconfirmed balance. The index doesn't tell coinbase outputs apart,
+	/// so none are reported as immature.
+	pub(crate) async fn get_balance(&self) -> io::Result<WalletBalance> {
+		let mut balance = WalletBalance { confirmed_sat: 0, unconfirmed_sat: 0, immature_sat: 0 };
+		for wallet_utxo in self.scan().await? {
+			// Unconfirmed change of our own transactions is as good as confirmed funds
+			if wallet_utxo.utxo.height.is_some() || wallet_utxo.chain == CHANGE_CHAIN {
+				balance.confirmed_sat += wallet_utxo.utxo.value;
+			} else {
+				balance.unconfirmed_sat += wallet_utxo.utxo.value;
+			}
+		}
+		Ok(balance)
+	}
+
+	/// Get a fresh address to receive on-chain funds
+	pub(crate) fn get_new_address(&self) -> io::Result<Address> {
+		let index = self.next_index(RECEIVE_CHAIN)?;
+		let (_, public_key) = self.derive_key(RECEIVE_CHAIN, index)?;
+		Address::p2wpkh(&public_key, self.network)
+			.map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+	}
+
+	/// Send `amount_sat` to `address` at `sat_per_vbyte`, or at the normal fee estimate if none
+	/// is given
+	pub(crate) async fn send_to_address(
+		&self, address: &Address, amount_sat: u64, sat_per_vbyte: Option<f64>,
+	) -> io::Result<Txid> {
+		let sat_per_kw = match sat_per_vbyte {
+			// 1 vbyte is 4 weight units
+			Some(sat_per_vbyte) => (sat_per_vbyte * 250.0).round() as u32,
+			None => self.chain_source.get_est_sat_per_1000_weight(ConfirmationTarget::Normal),
+		};
+		let output = TxOut { value: amount_sat, script_pubkey: address.script_pubkey() };
+		let _spending = self.spending.lock().await;
+		let tx = self.create_transaction(output, sat_per_kw).await?;
+		self.chain_source.send_transaction(&tx).await
+	}
+
+	/// Create and sign a transaction paying `amount_sat` to `output_script`, at `sat_per_kw` or
+	/// at the normal fee estimate, whose inputs are reserved until it is broadcast or discarded
+	pub(crate) async fn fund_transaction(
+		&self, output_script: &Script, amount_sat: u64, sat_per_kw: Option<u32>,
+	) -> io::Result<Transaction> {
+		let sat_per_kw = sat_per_kw.unwrap_or_else(|| {
+			self.chain_source.get_est_sat_per_1000_weight(ConfirmationTarget::Normal)
+		});
+		let output = TxOut { value: amount_sat, script_pubkey: output_script.clone() };
+		let _spending = self.spending.lock().await;
+		let tx = self.create_transaction(output, sat_per_kw).await?;
+		let reservation = Reservation {
+			txid: tx.txid(),
+			outpoints: tx.input.iter().map(|input| input.previous_output).collect(),
+		};
+		self.update_state(|state| state.reservations.push(reservation))?;
+		Ok(tx)
+	}
+
+	/// Release the reservation of `outpoints`, whose funding transaction was discarded
+	pub(crate) fn unlock_unspent(&self, outpoints: &[OutPoint]) -> io::Result<()> {
+		self.update_state(|state| {
+			for reservation in state.reservations.iter_mut() {
+				reservation.outpoints.retain(|outpoint| !outpoints.contains(outpoint));
+			}
+			state.reservations.retain(|reservation| !reservation.outpoints.is_empty());
+		})
+	}
+
+	/// Release the inputs of the funding transaction `txid`, so that they can be spent again
+	pub(crate) fn abandon_transaction(&self, txid: &Txid) -> io::Result<()> {
+		self.update_state(|state| {
+			state.reservations.retain(|reservation| reservation.txid != *txid)
+		})
+	}
+
+	/// Build and sign a transaction paying `output` and any change back to the wallet, spending
+	/// the largest unreserved outputs first
+	async fn create_transaction(&self, output: TxOut, sat_per_kw: u32) -> io::Result<Transaction> {
+		let mut utxos: Vec<WalletUtxo> = {
+			let utxos = self.scan().await?;
+			let state = self.state.lock().unwrap();
+			utxos
+				.into_iter()
+				// Received funds are only spent once confirmed
+				.filter(|wallet_utxo| {
+					wallet_utxo.utxo.height.is_some() || wallet_utxo.chain == CHANGE_CHAIN
+				})
+				.filter(|wallet_utxo| !state.is_reserved(&wallet_utxo.utxo.outpoint))
+				.collect()
+		};
+		utxos.sort_by_key(|wallet_utxo| std::cmp::Reverse(wallet_utxo.utxo.value));
+
+		let fee = |inputs: usize, outputs: &[TxOut]| {
+			let output_weight: u64 =
+				outputs.iter().map(|output| (8 + 1 + output.script_pubkey.len() as u64) * 4).sum();
+			let weight = TX_BASE_WEIGHT + inputs as u64 * P2WPKH_INPUT_WEIGHT + output_weight;
+			(weight * sat_per_kw as u64).div_ceil(1000)
+		};
+		let change_script = {
+			let (_, public_key) = self.derive_key(CHANGE_CHAIN, self.peek_index(CHANGE_CHAIN))?;
+			Script::new_v0_wpkh(&public_key.wpubkey_hash().unwrap())
+		};
+		let mut selected = Vec::new();
+		let mut selected_sat = 0;
+		let mut outputs = None;
+		for wallet_utxo in utxos {
+			selected_sat += wallet_utxo.utxo.value;
+			selected.push(wallet_utxo);
+			let with_change =
+				[output.clone(), TxOut { value: 0, script_pubkey: change_script.clone() }];
+			let change_sat = selected_sat
+				.checked_sub(output.value + fee(selected.len(), &with_change))
+				.filter(|change_sat| *change_sat >= DUST_LIMIT_SAT);
+			if let Some(change_sat) = change_sat {
+				let change = TxOut { value: change_sat, script_pubkey: change_script.clone() };
+				outputs = Some(vec![output.clone(), change]);
+				break;
+			}
+			// Without change, whatever exceeds the amount goes to the fee
+			if selected_sat >= output.value + fee(selected.len(), std::slice::from_ref(&output)) {
+				outputs = Some(vec![output.clone()]);
+				break;
+			}
+		}
+		let outputs = match outputs {
+			Some(outputs) => outputs,
+			None => {
+				return Err(io::Error::new(
+					io::ErrorKind::Other,
+					format!(
+						"insufficient funds to send {} sats at {} sat/kw, {} sats are spendable",
+						output.value, sat_per_kw, selected_sat
+					),
+				))
+			}
+		};
+		if outputs.len() == 2 {
+			self.next_index(CHANGE_CHAIN)?;
+		}
+
+		let mut tx = Transaction {
+			version: 2,
+			lock_time: 0,
+			input: selected
+				.iter()
+				.map(|wallet_utxo| TxIn {
+					previous_output: wallet_utxo.utxo.outpoint,
+					script_sig: Script::new(),
+					// Signals replaceability, as bitcoind's wallet does
+					sequence: 0xfffffffd,
+					witness: Vec::new(),
+				})
+				.collect(),
+			output: outputs,
+		};
+		let mut witnesses = Vec::with_capacity(selected.len());
+		{
+			let mut sighash_cache = SigHashCache::new(&tx);
+			for (input_idx, wallet_utxo) in selected.iter().enumerate() {
+				let (private_key, public_key) =
+					self.derive_key(wallet_utxo.chain, wallet_utxo.index)?;
+				let script_code = Script::new_p2pkh(&public_key.pubkey_hash());
+				let sighash = sighash_cache.signature_hash(
+					input_idx,
+					&script_code,
+					wallet_utxo.utxo.value,
+					SigHashType::All,
+				);
+				let message = Message::from_slice(&sighash[..]).unwrap();
+				let mut signature =
+					self.secp.sign(&message, &private_key.key).serialize_der().to_vec();
+				signature.push(SigHashType::All.as_u32() as u8);
+				witnesses.push(vec![signature, public_key.to_bytes()]);
+			}
+		}
+		for (input, witness) in tx.input.iter_mut().zip(witnesses) {
+			input.witness = witness;
+		}
+		Ok(tx)
+	}
+
+	/// Look up the unspent outputs of the addresses handed out and of the next `GAP_LIMIT` ones
+	/// of each chain, moving past the addresses found to be used. Reservations of outputs which
+	/// were spent are dropped.
+	async fn scan(&self) -> io::Result<Vec<WalletUtxo>> {
+		let mut utxos = Vec::new();
+		for chain in [RECEIVE_CHAIN, CHANGE_CHAIN] {
+			let handed_out = self.peek_index(chain);
+			let mut next_index = handed_out;
+			let mut index = 0;
+			while index < handed_out || index < next_index + GAP_LIMIT {
+				let (_, public_key) = self.derive_key(chain, index)?;
+				let script_pubkey = Script::new_v0_wpkh(&public_key.wpubkey_hash().unwrap());
+				// Addresses past those handed out are only looked up further if they were used
+				if index < handed_out || self.tx_index.has_history(&script_pubkey).await? {
+					for utxo in self.tx_index.get_script_utxos(&script_pubkey).await? {
+						utxos.push(WalletUtxo { utxo, chain, index });
+					}
+					if index >= handed_out {
+						next_index = index + 1;
+					}
+				}
+				index += 1;
+			}
+			if next_index > handed_out {
+				self.update_state(|state| *state.next_index_mut(chain) = next_index)?;
+			}
+		}
+
+		let unspent: Vec<OutPoint> =
+			utxos.iter().map(|wallet_utxo| wallet_utxo.utxo.outpoint).collect();
+		let spent_reservation = self.state.lock().unwrap().reservations.iter().any(|reservation| {
+			reservation.outpoints.iter().any(|outpoint| !unspent.contains(outpoint))
+		});
+		if spent_reservation {
+			self.update_state(|state| {
+				for reservation in state.reservations.iter_mut() {
+					reservation.outpoints.retain(|outpoint| unspent.contains(outpoint));
+				}
+				state.reservations.retain(|reservation| !reservation.outpoints.is_empty());
+			})?;
+		}
+		Ok(utxos)
+	}
+
+	fn derive_key(&self, chain: u32, index: u32) -> io::Result<(PrivateKey, PublicKey)> {
+		let path = [ChildNumber::from_normal_idx(chain).unwrap(), ChildNumber::Normal { index }];
+		let key = self
+			.account_key
+			.derive_priv(&self.secp, &path)
+			.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+		let public_key = PublicKey::from_private_key(&self.secp, &key.private_key);
+		Ok((key.private_key, public_key))
+	}
+
+	/// Index of the next address of `chain`
+	fn peek_index(&self, chain: u32) -> u32 {
+		let mut state = self.state.lock().unwrap();
+		*state.next_index_mut(chain)
+	}
+
+	/// Hand out the next address of `chain`, returning its index
+	fn next_index(&self, chain: u32) -> io::Result<u32> {
+		let mut index = 0;
+		self.update_state(|state| {
+			let next_index = state.next_index_mut(chain);
+			index = *next_index;
+			*next_index += 1;
+		})?;
+		Ok(index)
+	}
+
+	/// Apply `update` to the state of the wallet and persist it
+	fn update_state<F: FnOnce(&mut WalletState)>(&self, update: F) -> io::Result<()> {
+		let mut state = self.state.lock().unwrap();
+		let mut updated = state.clone();
+		update(&mut updated);
+		let value = serde_json::to_vec(&updated)
+			.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+		self.store.write(SEED_WALLET_NAMESPACE, STATE_KEY, &value)?;
+		*state = updated;
+		Ok(())
+	}
+}
+
+impl WalletState {
+	fn next_index_mut(&mut self, chain: u32) -> &mut u32 {
+		match chain {
+			RECEIVE_CHAIN => &mut self.next_receive_index,
+			_ => &mut self.next_change_index,
+		}
+	}
+
+	fn is_reserved(&self, outpoint: &OutPoint) -> bool {
+		self.reservations.iter().any(|reservation| reservation.outpoints.contains(outpoint))
+	}
+}
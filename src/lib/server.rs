@@ -1,31 +1,79 @@
+use crate::acceptance::AcceptancePolicy;
+use crate::approval::{ApprovalQueue, PaymentRequest, PendingPayment};
+use crate::auth::{Access, Caller, ChargeError, SpendingLimits, TokenRegistry, TokenScope};
+use crate::autopilot::Autopilot;
 #[allow(unused_variables, unused_assignments)]
-use crate::bitcoind_client::BitcoindClient;
+use crate::bitcoind_client::{BitcoindClient, RpcHealth};
+use crate::broadcast::{BroadcastKind, BroadcastManager};
+use crate::chain::ChainSource;
 use crate::cli;
 use crate::cli::{connect_peer_if_necessary, parse_peer_info, sanitize_string};
+use crate::events::NodeEvent;
+use crate::export::{self, ExportFormat, ForwardRecord, PaymentRecord};
 use crate::hex_utils;
+use crate::journal::{EventJournal, JournalEntry, JournalFilter};
+use crate::keys::NodeKeysManager;
+use crate::lnurl::LnurlClient;
+use crate::logging::{self, LogFilters};
+use crate::lsp::LspClient;
 use crate::node_var::{
-	ChannelManager, HTLCStatus, InvoicePayer, MillisatAmount, PaymentInfo, PaymentInfoStorage,
-	PeerManager,
+	unix_timestamp, BalanceSample, ChainMonitor, ChannelLabel, ChannelLimits, ChannelManager,
+	ChannelPolicy, ClosedChannelInfo, ForwardInfo, FundingFeerates, HTLCStatus, InvoicePayer,
+	InvoiceState, MillisatAmount, NodeAnnouncementInfo, PaymentHop, PaymentInfo,
+	PaymentInfoStorage, PeerManager, ProbeStorage, RequestIdStorage, Router, ScorerParams,
 };
-use crate::{disk, handle_ldk_events};
-use actix_web::dev::Server;
-use actix_web::{http::header::ContentType, web, App, HttpRequest, HttpResponse, HttpServer};
+use crate::peers::{self, PeerAddr, PeerDirection, PeerTracker};
+use crate::router::{PaymentLimits, PaymentLimitsStorage};
+use crate::secrets::SecretsKey;
+use crate::storage::NodeStore;
+use crate::sweeper::Sweeper;
+use crate::wallet::Wallet;
+use crate::webhooks::WebhookRegistry;
+use crate::{auth, backup, bip21, disk, handle_ldk_events, invoice, openapi, seed};
+use actix_web::dev::{Server, Service};
+use actix_web::error::{InternalError, ResponseError};
+use actix_web::http::header::{self, ContentType};
+use actix_web::http::{Method, StatusCode};
+use actix_web::{web, App, HttpMessage, HttpRequest, HttpResponse, HttpServer};
+use bitcoin::hash_types::BlockHash;
+use bitcoin::hashes::sha256::Hash as Sha256;
 use bitcoin::hashes::Hash;
 use bitcoin::network::constants::Network;
 use bitcoin::secp256k1::PublicKey;
+use futures::future::{ready, Either};
+use lightning::chain::chaininterface::{ConfirmationTarget, FeeEstimator};
+use lightning::chain::channelmonitor::Balance;
 use lightning::chain::keysinterface::KeysInterface;
-use lightning::chain::keysinterface::{KeysManager, Recipient};
-use lightning::ln::PaymentHash;
+use lightning::chain::keysinterface::Recipient;
+use lightning::ln::channelmanager::{
+	PaymentId, PhantomRouteHints, MIN_CLTV_EXPIRY_DELTA, MIN_FINAL_CLTV_EXPIRY,
+};
+use lightning::ln::features::{ChannelFeatures, NodeFeatures};
+use lightning::ln::{PaymentHash, PaymentPreimage, PaymentSecret};
 use lightning::routing::network_graph::NetworkGraph;
 use lightning::routing::network_graph::NodeId;
+use lightning::routing::router::{PaymentParameters, Route, RouteHop, RouteParameters};
+use lightning::routing::scoring::{ProbabilisticScorer, Score};
 use lightning::util::events::{Event, EventHandler};
+use lightning::util::ser::{Readable, Writeable};
+use lightning_invoice::payment;
 use lightning_invoice::payment::PaymentError;
-use lightning_invoice::{utils, Currency, Invoice};
+use lightning_invoice::{Invoice, InvoiceDescription};
+use serde::de::{self, Deserializer};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::Cursor;
+use std::net::SocketAddr;
 use std::ops::Deref;
-use std::path::Path;
+use std::str::FromStr;
 use std::string::String;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{broadcast, mpsc};
+use tracing::{info, info_span, warn, Instrument};
+use utoipa::{IntoParams, ToSchema};
 
 // Node variables passed to application state
 #[derive(Clone)]
@@ -36,78 +84,341 @@ where
 	pub invoice_payer: Arc<InvoicePayer<E>>,
 	pub peer_manager: Arc<PeerManager>,
 	pub channel_manager: Arc<ChannelManager>,
-	pub keys_manager: Arc<KeysManager>,
+	pub chain_monitor: Arc<ChainMonitor>,
+	/// Only connected when LDK is synced from bitcoind
+	pub bitcoind_client: Option<Arc<BitcoindClient>>,
+	pub chain_source: Arc<ChainSource>,
+	pub broadcaster: Arc<BroadcastManager>,
+	pub wallet: Arc<Wallet>,
+	pub keys_manager: Arc<NodeKeysManager>,
 	pub network_graph: Arc<NetworkGraph>,
+	pub router: Arc<Router>,
+	pub scorer: Arc<Mutex<ProbabilisticScorer<Arc<NetworkGraph>>>>,
+	pub scorer_params: Arc<Mutex<ScorerParams>>,
 	pub network: Network,
 	pub inbound_payments: PaymentInfoStorage,
 	pub outbound_payments: PaymentInfoStorage,
+	pub funding_feerates: FundingFeerates,
+	pub probes: ProbeStorage,
+	pub payment_limits: PaymentLimitsStorage,
+	pub request_ids: RequestIdStorage,
+	pub store: Arc<dyn NodeStore>,
+	pub event_sender: broadcast::Sender<NodeEvent>,
+	pub webhooks: Arc<WebhookRegistry>,
 	pub ldk_data_dir: String,
+	pub backup_key: [u8; 32],
+	pub secrets_key: Option<SecretsKey>,
+	pub socks_proxy: Option<SocketAddr>,
+	pub peer_tracker: Arc<PeerTracker>,
+	/// Addresses the node accepts peer connections on
+	pub listen_addresses: Vec<SocketAddr>,
+	pub node_announcement: Arc<Mutex<NodeAnnouncementInfo>>,
+	pub log_filters: Arc<LogFilters>,
+	pub api_tokens: Arc<TokenRegistry>,
+	pub approvals: Arc<ApprovalQueue>,
+	pub autopilot: Arc<Autopilot>,
+	pub lsp_client: Arc<LspClient>,
+	pub sweeper: Arc<Sweeper>,
+	pub event_journal: Arc<EventJournal>,
+	/// Policy of new channels and invoices, as set through the API or in the node's settings
+	pub channel_policy: Arc<Mutex<ChannelPolicy>>,
+	/// HTLC limits of new channels, as set in the node's settings
+	pub channel_limits: ChannelLimits,
 }
 
+/// Handler of LDK's events. It only does the bookkeeping of the router and the scorer itself,
+/// as it is called synchronously by the background processor, and queues the events for the
+/// `EventProcessor` to handle them asynchronously. Spendable outputs are persisted before
+/// returning though, as LDK doesn't hand them over again.
 pub struct ServerEventHandler {
-	pub tokio_handle: tokio::runtime::Handle,
+	pub event_queue: mpsc::UnboundedSender<Event>,
+	pub pending_events: Arc<AtomicUsize>,
+	pub sweeper: Arc<Sweeper>,
+	pub scorer: Arc<Mutex<ProbabilisticScorer<Arc<NetworkGraph>>>>,
+	pub probes: ProbeStorage,
+	pub payment_limits: PaymentLimitsStorage,
+}
+
+/// Task handling the events queued by the `ServerEventHandler` in order, then notifying the
+/// subscribers of the node's events
+pub struct EventProcessor {
 	pub channel_manager: Arc<ChannelManager>,
-	pub bitcoind_client: Arc<BitcoindClient>,
-	pub keys_manager: Arc<KeysManager>,
+	pub wallet: Arc<Wallet>,
+	pub sweeper: Arc<Sweeper>,
+	pub broadcaster: Arc<BroadcastManager>,
 	pub inbound_payments: PaymentInfoStorage,
 	pub outbound_payments: PaymentInfoStorage,
-	pub network: Network,
+	pub funding_feerates: FundingFeerates,
+	pub acceptance_policy: Arc<AcceptancePolicy>,
+	pub store: Arc<dyn NodeStore>,
+	pub event_sender: broadcast::Sender<NodeEvent>,
+	pub request_ids: RequestIdStorage,
+	pub event_journal: Arc<EventJournal>,
+	/// Number of events queued and not handled yet
+	pub pending_events: Arc<AtomicUsize>,
+}
+
+impl EventProcessor {
+	/// Handle the events of `events` until the `ServerEventHandler` is dropped
+	pub async fn run(self, mut events: mpsc::UnboundedReceiver<Event>) {
+		while let Some(event) = events.recv().await {
+			let span = self.event_span(&event);
+			let resolution = handle_ldk_events(
+				self.channel_manager.clone(),
+				self.wallet.clone(),
+				self.sweeper.clone(),
+				self.broadcaster.clone(),
+				self.inbound_payments.clone(),
+				self.outbound_payments.clone(),
+				self.funding_feerates.clone(),
+				self.acceptance_policy.clone(),
+				self.store.clone(),
+				&event,
+			)
+			.instrument(span)
+			.await;
+			if let Err(e) = self.event_journal.record(&event, resolution) {
+				warn!("failed to journal an event, check your disk and permissions: {}", e);
+			}
+			if let Some(node_event) = NodeEvent::from_ldk(&event) {
+				// Sending only fails when nobody is subscribed
+				let _ = self.event_sender.send(node_event);
+			}
+			self.pending_events.fetch_sub(1, Ordering::AcqRel);
+		}
+	}
+
+	/// Span of the handling of `event`, carrying the ID of the request which initiated the payment
+	/// it is about, if any. The ID is forgotten once the payment is resolved.
+	fn event_span(&self, event: &Event) -> tracing::Span {
+		let request_id = match event {
+			Event::PaymentSent { payment_hash, .. } | Event::PaymentFailed { payment_hash, .. } => {
+				self.request_ids.lock().unwrap().remove(payment_hash)
+			}
+			Event::PaymentPathFailed { payment_hash, .. } => {
+				self.request_ids.lock().unwrap().get(payment_hash).cloned()
+			}
+			_ => None,
+		};
+		match request_id {
+			Some(request_id) => info_span!("ldk_event", request_id = %request_id),
+			None => info_span!("ldk_event"),
+		}
+	}
 }
 
 impl EventHandler for ServerEventHandler {
 	fn handle_event(&self, event: &Event) {
-		self.tokio_handle.block_on(handle_ldk_events(
-			self.channel_manager.clone(),
-			self.bitcoind_client.clone(),
-			self.keys_manager.clone(),
-			self.inbound_payments.clone(),
-			self.outbound_payments.clone(),
-			self.network,
-			event,
-		));
+		match event {
+			// A probe rejected by its destination for its unknown payment hash shows that every
+			// channel of its path could forward it. LDK's scorer has no notion of probes, so
+			// this is scored as a failure past the end of the path, which only raises the
+			// liquidity known for each channel of the path.
+			Event::PaymentPathFailed { payment_hash, rejected_by_dest: true, path, .. } => {
+				if self.probes.lock().unwrap().contains(payment_hash) {
+					let path = path.iter().collect::<Vec<_>>();
+					self.scorer.lock().unwrap().payment_path_failed(&path, u64::max_value());
+				}
+			}
+			Event::PaymentSent { payment_hash, .. } => {
+				self.payment_limits.lock().unwrap().remove(payment_hash);
+			}
+			Event::PaymentFailed { payment_hash, .. } => {
+				self.probes.lock().unwrap().remove(payment_hash);
+				self.payment_limits.lock().unwrap().remove(payment_hash);
+			}
+			Event::SpendableOutputs { outputs } => {
+				// Persisted before LDK moves on, so the outputs aren't lost if the node stops
+				// before the event is handled
+				if let Err(e) = self.sweeper.track(outputs) {
+					panic!(
+						"Failed to persist spendable outputs, check your disk and permissions: {}",
+						e
+					);
+				}
+			}
+			_ => {}
+		}
+		self.pending_events.fetch_add(1, Ordering::AcqRel);
+		// Sending only fails once the event processor has stopped, as the node shuts down
+		if self.event_queue.send(event.clone()).is_err() {
+			self.pending_events.fetch_sub(1, Ordering::AcqRel);
+		}
+	}
+}
+
+/// Wait until the `EventProcessor` handled every event queued so far
+pub async fn drain_events(pending_events: &AtomicUsize) {
+	while pending_events.load(Ordering::Acquire) > 0 {
+		tokio::time::sleep(Duration::from_millis(100)).await;
 	}
 }
 
 // NodeInfo struct
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct NodeInfo {
+	pub version: String,
+	#[schema(value_type = String)]
 	pub pubkey: PublicKey,
+	pub alias: String,
+	pub color: String,
+	pub network: String,
+	pub block_height: u32,
+	#[schema(value_type = String)]
+	pub block_hash: BlockHash,
+	/// Whether LDK is synced to the chain backend's best block
+	pub synced_to_chain: bool,
+	/// Whether gossip from a connected peer has filled the network graph
+	pub synced_to_graph: bool,
+	/// Announced listening addresses, as `ip:port` or `<v3 address>.onion:port`
+	pub addresses: Vec<String>,
+	/// Addresses the node accepts peer connections on, empty in client-only mode
+	pub listen_addresses: Vec<String>,
 	// channel_list: Vec<ChannelDetails>,
 	pub channels_number: usize,
 	pub usable_channels_number: usize,
 	pub local_balance_msat: u64,
+	pub onchain_confirmed_balance_sat: Option<u64>,
+	pub onchain_unconfirmed_balance_sat: Option<u64>,
 	pub peers: usize,
 }
 
+// walletbalance struct
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct WalletBalance {
+	pub confirmed_balance_sat: u64,
+	pub unconfirmed_balance_sat: u64,
+	pub immature_balance_sat: u64,
+	pub local_balance_msat: u64,
+	pub total_balance_msat: u64,
+}
+
+// balance struct, summing the funds of the node wherever they are
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct BalanceSummary {
+	pub onchain_confirmed_sat: u64,
+	pub onchain_unconfirmed_sat: u64,
+	pub onchain_immature_sat: u64,
+	pub lightning_local_balance_msat: u64,
+	/// What the usable channels can send and receive right now
+	pub lightning_spendable_msat: u64,
+	pub lightning_receivable_msat: u64,
+	/// Part of the local balance the counterparties require the node to keep in its channels
+	pub channel_reserve_sat: u64,
+	/// Funds of closed channels still claimed on-chain
+	pub pending_close_sat: u64,
+	/// Funds claimed from closed channels and not yet swept to the wallet by a confirmed
+	/// transaction
+	pub pending_sweep_sat: u64,
+	pub total_balance_msat: u64,
+}
+
 // Help command struct
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct Help {
 	pub openchannel: String,
+	pub openchannels: String,
 	pub sendpayment: String,
 	pub getinvoice: String,
+	pub getphantominvoice: String,
+	pub unifiedinvoice: String,
+	pub withdraw: String,
+	pub phantomroutehints: String,
+	pub listinvoices: String,
+	pub cancelinvoice: String,
 	pub connectpeer: String,
 	pub listchannels: String,
 	pub listpayments: String,
 	pub closechannel: String,
 	pub forceclosechannel: String,
+	pub closeallchannels: String,
+	pub abandonchannel: String,
+	pub setpeerlabel: String,
+	pub setchannellabel: String,
+	pub channelhistory: String,
+	pub autopilot: String,
+	pub lspinfo: String,
+	pub lsporder: String,
+	pub lsporderstatus: String,
 	pub nodeinfo: String,
 	pub listpeers: String,
 	pub signmessage: String,
+	pub verifymessage: String,
+	pub diagnosechannel: String,
+	pub keysend: String,
+	pub decodeinvoice: String,
+	pub newaddress: String,
+	pub walletbalance: String,
+	pub balance: String,
+	pub sendonchain: String,
+	pub listutxos: String,
+	pub pendingtxs: String,
+	pub events: String,
+	pub eventhistory: String,
+	pub lookuppayment: String,
+	pub payment: String,
+	pub cancelpayment: String,
+	pub closedchannels: String,
+	pub listforwards: String,
+	pub feereport: String,
+	pub updatechannelpolicy: String,
+	pub setnodeinfo: String,
+	pub addwebhook: String,
+	pub listwebhooks: String,
+	pub removewebhook: String,
+	pub exportbackup: String,
+	pub exportpayments: String,
+	pub exportforwards: String,
+	pub backupseed: String,
+	pub health: String,
+	pub feerates: String,
+	pub queryroutes: String,
+	pub sendtoroute: String,
+	pub rebalance: String,
+	pub probe: String,
+	pub scorerparams: String,
 }
 
 // Struct containing the list of peers a node has
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct ListPeers {
-	pub peers: Vec<PublicKey>,
+	pub peers: Vec<PeerDetails>,
+}
+
+// Struct containing the details of a connected peer, or of a disconnected channel peer
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct PeerDetails {
+	#[schema(value_type = String)]
+	pub pubkey: PublicKey,
+	pub alias: String,
+	/// Label the operator gave to the peer
+	pub label: Option<String>,
+	pub connected: bool,
+	/// Address of the connection, unknown for inbound connections which couldn't be matched to
+	/// the peer
+	pub address: Option<String>,
+	pub direction: Option<PeerDirection>,
+	/// Hex-encoded feature bits of the peer's init message if we have a channel with it, or of its
+	/// node announcement otherwise
+	pub features: Option<String>,
+	pub channels: usize,
+	pub connected_since: Option<u64>,
+	/// Last time a disconnected peer was seen connected since the node started
+	pub last_seen: Option<u64>,
 }
 
 // Struct containing redefined channel details
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct RedefinedChannelDetails {
 	pub channel_id: String,
 	pub tx_id: String,
 	pub peer_pubkey: String,
 	pub peer_alias: String,
+	/// Label the operator gave to the peer
+	pub peer_label: Option<String>,
+	/// Label and tags the operator attached to the channel
+	pub label: Option<String>,
+	pub tags: Vec<String>,
 	pub short_channel_id: u64,
 	pub is_confirmed_onchain: bool,
 	pub local_balance_msat: u64,
@@ -116,613 +427,5885 @@ pub struct RedefinedChannelDetails {
 	pub available_balance_for_recv_msat: u64,
 	pub channel_can_send_payments: bool,
 	pub public: bool,
+	/// Forwarding policy of the counterparty towards us, unset until it sent a channel update
+	pub counterparty_fee_base_msat: Option<u32>,
+	pub counterparty_fee_proportional_millionths: Option<u32>,
+	pub counterparty_cltv_expiry_delta: Option<u16>,
+	/// Our forwarding policy as announced in the gossip, unset for channels not in the network
+	/// graph
+	pub fee_base_msat: Option<u32>,
+	pub fee_proportional_millionths: Option<u32>,
+	pub cltv_expiry_delta: Option<u16>,
+	pub htlc_minimum_msat: Option<u64>,
+	pub htlc_maximum_msat: Option<u64>,
+	/// Reserve the counterparty has to keep in the channel
+	pub counterparty_reserve_satoshis: u64,
+	/// Reserve we have to keep in the channel, unset until the counterparty accepted it
+	pub unspendable_punishment_reserve_satoshis: Option<u64>,
 }
 
 // Struct containing the list of channels a node has
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct ListChannels {
 	pub channels: Vec<RedefinedChannelDetails>,
 }
 
 // openchannel request struct
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct OpenChannel {
-	pubkey: String,
+	#[schema(value_type = String)]
+	pub pubkey: PublicKey,
+	// A host name is resolved when connecting to the peer, so this isn't a socket address
+	pub host: String,
+	#[serde(deserialize_with = "deserialize_number")]
+	pub port: u16,
+	#[serde(deserialize_with = "deserialize_number")]
+	pub channel_amt_satoshis: u64,
+	pub channel_announcement: Option<String>,
+	#[serde(default, deserialize_with = "deserialize_optional_number")]
+	pub push_msat: Option<u64>,
+	pub config: Option<OpenChannelConfig>,
+	pub sat_per_vbyte: Option<String>,
+	pub target_conf: Option<String>,
+}
+
+// openchannel config struct. Unset fields keep the node's channel policy or LDK's defaults.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct OpenChannelConfig {
+	pub announce_channel: Option<String>,
+	pub forwarding_fee_base_msat: Option<String>,
+	pub forwarding_fee_proportional_millionths: Option<String>,
+	pub cltv_expiry_delta: Option<String>,
+	pub max_inflight_htlc_percent: Option<String>,
+	pub max_accepted_htlcs: Option<String>,
+	pub htlc_minimum_msat: Option<String>,
+	pub max_dust_htlc_exposure_msat: Option<String>,
+}
+
+// openchannels entry struct
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct OpenChannelsEntry {
+	#[schema(value_type = String)]
+	pubkey: PublicKey,
 	host: String,
-	port: String,
-	channel_amt_satoshis: String,
+	#[serde(deserialize_with = "deserialize_number")]
+	port: u16,
+	#[serde(deserialize_with = "deserialize_number")]
+	amount: u64,
 	channel_announcement: Option<String>,
+	#[serde(default, deserialize_with = "deserialize_optional_number")]
+	push_msat: Option<u64>,
+}
+
+// openchannels request struct
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct OpenChannels {
+	channels: Vec<OpenChannelsEntry>,
+}
+
+// Outcome of opening one of the channels of an openchannels request
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct OpenChannelResult {
+	pub pubkey: String,
+	pub msg: Option<String>,
+	pub error: Option<ServerError>,
+}
+
+// Struct containing the outcome of each channel of an openchannels request
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct OpenedChannels {
+	pub results: Vec<OpenChannelResult>,
 }
 
 // connectpeer struct
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct ConnectPeer {
+	#[schema(value_type = String)]
 	pubkey: PublicKey,
-	host: String,
-	port: String,
+	/// Looked up in the peer's last-known and announced addresses if unset
+	host: Option<String>,
+	port: Option<String>,
+}
+
+// channel label request struct. The label and tags replace the current ones, and the channel
+// is unlabeled if both are empty.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct SetChannelLabel {
+	pub label: Option<String>,
+	#[serde(default)]
+	pub tags: Vec<String>,
+}
+
+// setpeerlabel request struct
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct SetPeerLabel {
+	#[schema(value_type = String)]
+	pub pubkey: PublicKey,
+	/// The label is removed if unset
+	pub label: Option<String>,
 }
 
 // getinvoice struct
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct GetInvoice {
-	amt_millisatoshis: String,
+	#[serde(deserialize_with = "deserialize_number")]
+	pub amt_millisatoshis: u64,
+	pub description: Option<String>,
+	#[serde(default, deserialize_with = "deserialize_optional_number")]
+	pub expiry_secs: Option<u32>,
+	// Local note stored with the payment, not included in the invoice
+	pub label: Option<String>,
+}
+
+// created invoice struct
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct CreatedInvoice {
+	pub invoice: String,
+	pub payment_hash: String,
+	pub expiry_secs: u64,
+	pub label: Option<String>,
+}
+
+// unified invoice request struct
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct GetUnifiedInvoice {
+	#[serde(deserialize_with = "deserialize_number")]
+	pub amt_millisatoshis: u64,
+	pub description: Option<String>,
+	#[serde(default, deserialize_with = "deserialize_optional_number")]
+	pub expiry_secs: Option<u32>,
+	pub label: Option<String>,
+}
+
+// unified invoice struct. `uri` is the BIP 21 URI offering both `address` and `invoice`.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct UnifiedInvoice {
+	pub uri: String,
+	pub address: String,
+	pub invoice: String,
+	pub payment_hash: String,
+	pub expiry_secs: u64,
+}
+
+// LNURL-withdraw request struct. The maximum amount the service allows is withdrawn unless
+// `amt_msat` is given.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct Withdraw {
+	pub lnurl: String,
+	pub amt_msat: Option<String>,
+}
+
+// phantom invoice request struct. `route_hints` are the hex route hints of the other nodes of
+// the phantom node, as served by their `/phantomroutehints`.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct GetPhantomInvoice {
+	#[serde(deserialize_with = "deserialize_number")]
+	pub amt_millisatoshis: u64,
+	pub description: Option<String>,
+	#[serde(default, deserialize_with = "deserialize_optional_number")]
+	pub expiry_secs: Option<u32>,
+	pub label: Option<String>,
+	#[serde(default)]
+	pub route_hints: Vec<String>,
+}
+
+// phantom route hints struct
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct PhantomRouteHintsInfo {
+	pub route_hints: String,
+}
+
+// Struct containing an invoice created by the node
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct InvoiceDetails {
+	pub payment_hash: String,
+	pub invoice: String,
+	pub state: InvoiceState,
+	pub description: String,
+	pub amt_msat: Option<u64>,
+	pub label: Option<String>,
+	pub created_at: u64,
+	pub expires_at: u64,
+	pub settled_at: Option<u64>,
+}
+
+// Struct containing the invoices created by the node, oldest first
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct Invoices {
+	pub invoices: Vec<InvoiceDetails>,
 }
 
 // invoice/payment request struct
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug, ToSchema)]
 pub struct ServerInvoice {
 	pub invoice: String,
+	// Only used when paying an invoice that doesn't specify an amount
+	pub amount_msat: Option<String>,
+	// Wait for the payment to succeed or fail instead of returning once it's initiated
+	pub wait: Option<String>,
+	// Time after which the payment is neither retried nor waited for anymore
+	pub timeout_secs: Option<String>,
+	// Fee limit of the payment, either in msats or as a percentage of its amount
+	pub max_fee_msat: Option<String>,
+	pub max_fee_percent: Option<String>,
+}
+
+// completed payment struct
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct PaymentResult {
+	pub payment_hash: String,
+	pub payment_preimage: String,
+	pub fee_paid_msat: Option<u64>,
+}
+
+// route hint hop of a decoded invoice
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct DecodedRouteHintHop {
+	pub src_node_id: String,
+	pub short_channel_id: u64,
+	pub fee_base_msat: u32,
+	pub fee_proportional_millionths: u32,
+	pub cltv_expiry_delta: u16,
+	pub htlc_minimum_msat: Option<u64>,
+	pub htlc_maximum_msat: Option<u64>,
+}
+
+// decoded invoice struct
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct DecodedInvoice {
+	pub destination: String,
+	pub amount_msat: Option<u64>,
+	pub payment_hash: String,
+	pub timestamp: u64,
+	pub expiry_secs: u64,
+	pub is_expired: bool,
+	pub description: Option<String>,
+	pub description_hash: Option<String>,
+	pub min_final_cltv_expiry: u64,
+	pub route_hints: Vec<Vec<DecodedRouteHintHop>>,
+}
+
+// keysend request struct
+#[derive(Clone, Serialize, Deserialize, Debug, ToSchema)]
+pub struct Keysend {
+	pub pubkey: String,
+	pub amt_msat: String,
+}
+
+// queryroutes request struct
+#[derive(Serialize, Deserialize, Debug, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct QueryRoutes {
+	pub dest_pubkey: String,
+	pub amt_msat: String,
+	pub max_fee_msat: Option<String>,
+}
+
+// Struct containing a hop of a route, in the terms `lncli queryroutes` uses
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct RouteHopInfo {
+	#[schema(value_type = String)]
+	pub pubkey: PublicKey,
+	pub short_channel_id: u64,
+	/// Amount the hop's node forwards, or receives if it is the destination
+	pub amt_to_forward_msat: u64,
+	/// Fee the hop's node charges for forwarding
+	pub fee_msat: u64,
+	/// CLTV expiry of the HTLC the hop's node forwards, or receives if it is the destination
+	pub expiry: u32,
+}
+
+// Struct containing a path of a route
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct RouteInfo {
+	pub total_amt_msat: u64,
+	pub total_fees_msat: u64,
+	pub total_time_lock: u32,
+	pub hops: Vec<RouteHopInfo>,
+}
+
+// Struct containing the paths of a route found by queryroutes
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct QueriedRoutes {
+	pub routes: Vec<RouteInfo>,
+}
+
+// sendtoroute request struct
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct SendToRoute {
+	pub payment_hash: String,
+	pub payment_secret: Option<String>,
+	pub amt_msat: String,
+	/// Short channel ID, as a number or `<block>x<tx>x<output>`, or public key of each hop
+	pub hops: Vec<String>,
+}
+
+// rebalance request struct
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct Rebalance {
+	/// Short channel ID of the channel the payment leaves through
+	pub out_scid: String,
+	/// Short channel ID of the channel the payment comes back in through
+	pub in_scid: String,
+	pub amt_msat: String,
+	pub max_fee_msat: String,
+}
+
+// probe request struct
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct Probe {
+	pub dest_pubkey: String,
+	pub amt_msat: String,
+}
+
+// Struct containing the outcome of a probe
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct ProbeResult {
+	pub payment_hash: String,
+	/// Whether the probe reached the destination, i.e. every channel of the route could
+	/// forward the amount
+	pub reached_destination: bool,
+	/// Channel which couldn't forward the probe, if it is known
+	pub failed_short_channel_id: Option<u64>,
+	pub route: RouteInfo,
+}
+
+// Struct containing a payment sent over a manually specified route
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct SentToRoute {
+	pub payment_hash: String,
+	pub payment_id: String,
+	pub route: RouteInfo,
 }
 
 // payment struct
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct Payment {
 	pub amount_millisatoshis: String,
 	pub payment_hash: String,
 	pub htlc_direction: String,
 	pub htlc_status: String,
+	pub label: Option<String>,
+	pub fee_paid_msat: Option<u64>,
+	pub created_at: Option<u64>,
+	pub resolved_at: Option<u64>,
+	pub paths: Vec<Vec<PaymentHop>>,
+	pub keysend: bool,
 }
 
-// payments struct
-#[derive(Serialize, Deserialize, Debug)]
+// payments struct. `total_payments` counts the payments matching the filters before paging.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct Payments {
 	pub payments: Vec<Payment>,
+	pub total_payments: usize,
+}
+
+// list payments request struct. Times are in seconds since the UNIX epoch and filter payments
+// by creation time.
+#[derive(Serialize, Deserialize, Debug, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListPayments {
+	// One of inbound or outbound
+	pub direction: Option<String>,
+	// One of pending, succeeded or failed
+	pub status: Option<String>,
+	pub start_time: Option<String>,
+	pub end_time: Option<String>,
+	pub offset: Option<String>,
+	pub limit: Option<String>,
+}
+
+// event history request struct. Times are in seconds since the UNIX epoch and filter events by
+// the time they were handled.
+#[derive(Serialize, Deserialize, Debug, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EventHistory {
+	// e.g. payment_failed
+	pub event_type: Option<String>,
+	pub payment_hash: Option<String>,
+	pub channel_id: Option<String>,
+	pub start_time: Option<String>,
+	pub end_time: Option<String>,
+	pub offset: Option<String>,
+	pub limit: Option<String>,
+}
+
+// Struct containing the journaled events matching an event history request, most recent first
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct JournaledEvents {
+	pub events: Vec<JournalEntry>,
+	// Number of events matching the filters before paging
+	pub total_events: usize,
+}
+
+// cancel invoice request struct
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct CancelInvoice {
+	pub payment_hash: String,
+}
+
+// lookup payment request struct
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct LookupPayment {
+	pub payment_hash: String,
+}
+
+// payment details struct. The preimage is only set once the payment succeeded.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct PaymentDetails {
+	pub payment_hash: String,
+	pub payment_id: Option<String>,
+	pub htlc_direction: String,
+	pub htlc_status: String,
+	pub amount_millisatoshis: String,
+	pub preimage: Option<String>,
+	pub label: Option<String>,
+	pub keysend: bool,
 }
 
 // signmessage struct
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct SignMessage {
 	message: String,
 }
 
+// verifymessage request struct
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct VerifyMessage {
+	pub message: String,
+	/// zbase32 signature, as given by signmessage
+	pub signature: String,
+	/// Node expected to have signed the message
+	pub pubkey: Option<String>,
+}
+
+// Struct containing the result of a message verification
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct VerifiedMessage {
+	pub valid: bool,
+	/// Node recovered from the signature
+	#[schema(value_type = Option<String>)]
+	pub pubkey: Option<PublicKey>,
+}
+
 // channel struct
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct Channel {
 	channel_id: String,
+	// Fee rate of the cooperative closing transaction, given directly or as a confirmation
+	// target. Ignored by forceclosechannel.
+	sat_per_vbyte: Option<String>,
+	target_conf: Option<String>,
 }
 
-// Server Error
-#[derive(Serialize, Deserialize, Debug)]
-pub struct ServerError {
-	pub error: String,
+// close all channels request struct. `confirm` has to be the confirmation token the node
+// answers a request without it with, which changes along with the channels to close.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct CloseAllChannels {
+	pub force: Option<String>,
+	pub confirm: Option<String>,
+	// Fee rate of the cooperative closing transactions. Ignored when force-closing.
+	pub sat_per_vbyte: Option<String>,
+	pub target_conf: Option<String>,
 }
 
-// Server suceess
-#[derive(Serialize, Deserialize, Debug)]
-pub struct ServerSuccess {
-	pub msg: String,
+// Outcome of closing one of the channels of a closeallchannels request
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct CloseChannelResult {
+	pub channel_id: String,
+	pub msg: Option<String>,
+	pub error: Option<ServerError>,
 }
 
-/// Get helpful information on how to interact with the lightning node
-async fn help(_req: HttpRequest) -> HttpResponse {
-	let help = Help {
-		openchannel: "pubkey@host:port <amt_satoshis>".to_string(),
-		sendpayment: "<invoice>".to_string(),
-		getinvoice: "<amt_millisatoshis>".to_string(),
-		connectpeer: "pubkey@host:port".to_string(),
-		listchannels: "".to_string(),
-		listpayments: "".to_string(),
-		closechannel: "<channel_id>".to_string(),
-		forceclosechannel: "<channel_id>".to_string(),
-		nodeinfo: "".to_string(),
-		listpeers: "".to_string(),
-		signmessage: "<message>".to_string(),
-	};
-	HttpResponse::Ok().content_type(ContentType::json()).json(help)
+// Struct containing the outcome of each channel of a closeallchannels request
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct ClosingChannels {
+	pub results: Vec<CloseChannelResult>,
 }
 
-/// Open channel with another node
-async fn open_channel(
-	req: web::Json<OpenChannel>, node_var: web::Data<NodeVar<ServerEventHandler>>,
-) -> HttpResponse {
-	let pubkey = req.pubkey.clone();
-	let host = req.host.clone();
-	let port = req.port.clone();
-	let channel_amt_satoshis = req.channel_amt_satoshis.clone();
-	let channel_announcement = req.channel_announcement.clone();
-	let peer_manager = node_var.peer_manager.clone();
+// on-chain address struct
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct OnchainAddress {
+	pub address: String,
+}
 
-	// Validate critical (required) user arguments
-	if pubkey == "".to_string()
-		|| host == "".to_string()
-		|| port == "".to_string()
-		|| channel_amt_satoshis == "".to_string()
-	{
-		let error = ServerError {
-			error: format!("ERROR: openchannel has 2 required arguments: `openchannel pubkey@host:port channel_amt_satoshis` [--public]").to_string(),
-		};
-		return HttpResponse::BadRequest().content_type(ContentType::json()).json(error);
-	}
+// sendonchain request struct
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct SendOnchain {
+	pub address: String,
+	pub amount_sat: String,
+	pub sat_per_vbyte: Option<String>,
+}
 
-	// Get public key and socket address from supplied parameters
-	let peer_pubkey_and_ip_addr = format!("{}@{}:{}", pubkey, host, port);
-	let pubkey_peeraddr = parse_peer_info(peer_pubkey_and_ip_addr.to_string());
+// on-chain transaction struct
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct OnchainTransaction {
+	pub txid: String,
+}
 
-	match pubkey_peeraddr {
-		Ok(info) => {
-			let chan_amt_sat: Result<u64, _> = channel_amt_satoshis.parse();
-			if chan_amt_sat.is_err() {
-				let error =
-					ServerError { error: format!("ERROR: channel amount must be a number") };
-				return HttpResponse::BadRequest().content_type(ContentType::json()).json(error);
-			}
+// utxo struct
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct Utxo {
+	pub txid: String,
+	pub vout: u32,
+	pub amount_sat: u64,
+	pub confirmations: u32,
+	pub reserved: bool,
+}
 
-			if connect_peer_if_necessary(info.0, info.1, peer_manager.clone()).await.is_err() {
-				let error = ServerError { error: format!("ERROR: cannot connect to peer") };
-				return HttpResponse::BadRequest().content_type(ContentType::json()).json(error);
-			};
+// listutxos struct
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct ListUtxos {
+	pub utxos: Vec<Utxo>,
+}
 
-			let announce_channel = match channel_announcement {
-				Some(val) => {
-					if val.as_str() == "true" {
-						true
-					} else {
-						false
-					}
-				}
-				None => false,
-			};
+// Transaction the node broadcast, which isn't buried under enough blocks to survive a reorg yet
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct PendingTx {
+	pub txid: String,
+	// One of funding, close or sweep. Closes include commitment and claim transactions.
+	pub kind: String,
+	pub first_broadcast_at: u64,
+	pub last_broadcast_at: u64,
+	pub broadcasts: u32,
+	// 0 while the transaction is unconfirmed
+	pub confirmations: u32,
+}
 
-			if cli::open_channel(
-				info.0,
-				chan_amt_sat.unwrap(),
-				announce_channel,
-				node_var.channel_manager.clone(),
-			)
-			.is_ok()
-			{
-				let peer_data_path = format!("{}/channel_peer_data", node_var.ldk_data_dir.clone());
-				let _ = disk::persist_channel_peer(
-					Path::new(&peer_data_path),
-					peer_pubkey_and_ip_addr.as_str(),
-				);
+// pendingtxs struct
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct PendingTxs {
+	pub pending_txs: Vec<PendingTx>,
+}
 
-				let msg = ServerSuccess {
-					msg: format!("EVENT: initiated channel with peer {}. ", info.0),
-				};
-				return HttpResponse::Ok().content_type(ContentType::json()).json(msg);
-			} else {
-				let error =
-					ServerError { error: format!("ERROR: unable to open a channel with peer") };
-				return HttpResponse::BadRequest().content_type(ContentType::json()).json(error);
-			}
-		}
-		Err(e) => {
-			let error = ServerError { error: format!("{:?}", e.into_inner().unwrap()) };
-			return HttpResponse::BadRequest().content_type(ContentType::json()).json(error);
-		}
-	}
+// Balance claimable from a channel's monitor
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct DiagnosedBalance {
+	pub kind: String,
+	pub amount_satoshis: u64,
+	pub height: Option<u32>,
 }
 
-/// Get node information
-async fn nodeinfo(
-	_req: HttpRequest, node_var: web::Data<NodeVar<ServerEventHandler>>,
-) -> HttpResponse {
-	let pubkey = node_var.channel_manager.get_our_node_id();
-	let channel_list = node_var.channel_manager.list_channels();
-	let channels_number = channel_list.len();
-	let usable_channels_number = channel_list.iter().filter(|c| c.is_usable).count();
-	let local_balance_msat = channel_list.iter().map(|c| c.balance_msat).sum::<u64>();
-	let peers = node_var.peer_manager.get_peer_node_ids().len();
+// Channel diagnostics struct
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct ChannelDiagnostics {
+	pub channel_id: String,
+	pub peer_pubkey: String,
+	pub peer_connected: bool,
+	pub peer_address: Option<String>,
+	pub is_outbound: bool,
+	pub is_confirmed_onchain: bool,
+	pub confirmations_required: Option<u32>,
+	pub is_usable: bool,
+	pub claimable_balances: Vec<DiagnosedBalance>,
+	pub feerate_normal_sat_per_kw: u32,
+	pub feerate_high_priority_sat_per_kw: u32,
+	pub issues: Vec<String>,
+}
 
-	// Construct response body and return response
-	let nodeinfo_obj = NodeInfo {
-		pubkey,
-		// channel_list,
-		channels_number,
-		usable_channels_number,
-		local_balance_msat,
-		peers,
-	};
+// Struct containing the channels the node has closed, most recently closed first
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct ClosedChannels {
+	pub channels: Vec<ClosedChannelInfo>,
+}
 
-	HttpResponse::Ok().content_type(ContentType::json()).json(nodeinfo_obj)
+// list forwards request struct. Times are in seconds since the UNIX epoch.
+#[derive(Serialize, Deserialize, Debug, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListForwards {
+	pub start_time: Option<String>,
+	pub end_time: Option<String>,
 }
 
-/// List connected node peers
-async fn list_peers(node_var: web::Data<NodeVar<ServerEventHandler>>) -> HttpResponse {
-	let peers = node_var.peer_manager.get_peer_node_ids();
-	if peers.len() == 0 {
-		let list_peers = ListPeers { peers: Vec::new() };
-		return HttpResponse::Ok().content_type(ContentType::json()).json(list_peers);
-	} else {
-		let list_peers = ListPeers { peers };
-		return HttpResponse::Ok().content_type(ContentType::json()).json(list_peers);
-	}
+// lsp order request struct, following LSPS1's `create_order`. Amounts are in satoshis.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct LspOrder {
+	/// Inbound liquidity bought, i.e. the balance of the LSP in the channel
+	#[serde(deserialize_with = "deserialize_number")]
+	pub lsp_balance_sat: u64,
+	#[serde(default, deserialize_with = "deserialize_optional_number")]
+	pub client_balance_sat: Option<u64>,
+	#[serde(default, deserialize_with = "deserialize_optional_number")]
+	pub funding_confirms_within_blocks: Option<u16>,
+	/// Blocks the LSP keeps the channel open for at least
+	#[serde(default, deserialize_with = "deserialize_optional_number")]
+	pub channel_expiry_blocks: Option<u32>,
+	/// Defaults to a new address of the node's wallet
+	pub refund_onchain_address: Option<String>,
+	pub announce_channel: Option<String>,
 }
 
-///List open node channels
-async fn list_channels(node_var: web::Data<NodeVar<ServerEventHandler>>) -> HttpResponse {
-	let channel_manager = &node_var.channel_manager;
-	let network_graph = &node_var.network_graph;
-	let channels_list = channel_manager.list_channels();
-	let mut channel_vector = Vec::new();
+// Struct containing the result of an LSPS request, as the LSP sent it
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct LspResponse {
+	pub lsp_pubkey: String,
+	#[schema(value_type = Object)]
+	pub result: serde_json::Value,
+}
 
-	if channels_list.len() == 0 {
-		let list_channels = ListChannels { channels: Vec::new() };
-		return HttpResponse::Ok().content_type(ContentType::json()).json(list_channels);
-	} else {
-		for chan_info in channels_list {
-			let chan_id = hex_utils::hex_str(&chan_info.channel_id[..]);
+// channel history request struct. Times are in seconds since the UNIX epoch.
+#[derive(Serialize, Deserialize, Debug, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ChannelHistory {
+	pub start_time: Option<String>,
+	pub end_time: Option<String>,
+}
 
-			let mut txid = String::new();
-			if let Some(funding_txo) = chan_info.funding_txo {
-				txid = format!("{}", funding_txo.txid);
-			}
-			let peer_pubkey = hex_utils::hex_str(&chan_info.counterparty.node_id.serialize());
+// Struct containing the liquidity history of a channel, oldest sample first
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct ChannelBalanceHistory {
+	pub channel_id: String,
+	pub samples: Vec<BalanceSample>,
+}
 
-			let mut peer_alias = String::new();
-			if let Some(node_info) = network_graph
-				.read_only()
-				.nodes()
-				.get(&NodeId::from_pubkey(&chan_info.counterparty.node_id))
-			{
-				if let Some(announcement) = &node_info.announcement_info {
-					peer_alias = sanitize_string(&announcement.alias);
-				}
-			}
+// Struct containing the forwarding history of the node
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct Forwards {
+	pub forwards: Vec<ForwardInfo>,
+	pub total_fee_earned_msat: u64,
+}
 
-			let mut short_channel_id: u64 = 0;
-			if let Some(id) = chan_info.short_channel_id {
-				short_channel_id = id;
-			}
+// fee report struct, mirroring `lncli feereport`. Fee sums are over the last day, week and
+// month (30 days).
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct FeeReport {
+	pub channel_fees: Vec<ChannelFeeReport>,
+	pub day_fee_sum_msat: u64,
+	pub week_fee_sum_msat: u64,
+	pub month_fee_sum_msat: u64,
+}
 
-			let is_confirmed_onchain = chan_info.is_funding_locked;
-			let channel_value_satoshis = chan_info.channel_value_satoshis;
-			let local_balance_msat = chan_info.balance_msat;
+// Struct containing the fees earned by an open channel. Fees are attributed to the channel
+// each forward went out through, and the policy is the one the channel announces.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct ChannelFeeReport {
+	pub channel_id: String,
+	#[schema(value_type = String)]
+	pub peer_pubkey: PublicKey,
+	pub short_channel_id: Option<u64>,
+	pub base_fee_msat: Option<u32>,
+	pub fee_proportional_millionths: Option<u32>,
+	pub day_fee_sum_msat: u64,
+	pub week_fee_sum_msat: u64,
+	pub month_fee_sum_msat: u64,
+}
 
-			let mut available_balance_for_send_msat = 0;
-			let mut available_balance_for_recv_msat = 0;
-			if chan_info.is_usable {
-				available_balance_for_send_msat = chan_info.outbound_capacity_msat;
-				available_balance_for_recv_msat = chan_info.inbound_capacity_msat;
-			}
+// update channel policy request struct. Unset fields keep their current value.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct UpdateChannelPolicy {
+	pub channel_id: Option<String>,
+	pub base_fee_msat: Option<String>,
+	pub fee_proportional_millionths: Option<String>,
+	pub cltv_expiry_delta: Option<String>,
+	pub htlc_minimum_msat: Option<String>,
+	pub min_final_cltv_expiry: Option<String>,
+}
 
-			let channel_can_send_payments = chan_info.is_usable;
-			let public = chan_info.is_public;
+// set node info request struct. Unset fields keep their current value.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct SetNodeInfo {
+	pub alias: Option<String>,
+	pub color: Option<String>,
+	pub addresses: Option<Vec<String>>,
+}
 
-			// Create RedefinedChannelDetails and add to vector
-			let chan_details = RedefinedChannelDetails {
-				channel_id: chan_id,
-				tx_id: txid,
-				peer_pubkey,
-				peer_alias,
-				short_channel_id,
-				is_confirmed_onchain,
-				local_balance_msat,
-				channel_value_satoshis,
-				available_balance_for_send_msat,
-				available_balance_for_recv_msat,
-				channel_can_send_payments,
-				public,
-			};
+// scorer params request struct. Unset fields keep their current value.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct SetScorerParams {
+	pub base_penalty_msat: Option<u64>,
+	pub liquidity_penalty_multiplier_msat: Option<u64>,
+	pub liquidity_offset_half_life_secs: Option<u64>,
+	pub amount_penalty_multiplier_msat: Option<u64>,
+}
 
-			channel_vector.push(chan_details);
-		}
-		let list_channels = ListChannels { channels: channel_vector };
-		return HttpResponse::Ok().content_type(ContentType::json()).json(list_channels);
-	}
+// log levels request struct. Unset fields keep their current value.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct SetLogLevels {
+	pub level: Option<String>,
+	pub ldk: Option<String>,
+	pub http: Option<String>,
+	pub chain: Option<String>,
 }
 
-/// Connect to another peer
-async fn connect_peer(
-	req: web::Json<ConnectPeer>, node_var: web::Data<NodeVar<ServerEventHandler>>,
-) -> HttpResponse {
-	let peer_manager = node_var.peer_manager.clone();
-	let pubkey = format!("{}", req.pubkey);
-	let host = format!("{}", req.host);
-	let port = format!("{}", req.port);
-	let peer_pubkey_host_port = format!("{}@{}:{}", pubkey, host, port);
+// Struct containing a payment awaiting approval. `kind` is sendpayment or keysend.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct PendingApproval {
+	pub id: String,
+	pub amt_msat: u64,
+	pub kind: String,
+	pub invoice: Option<String>,
+	pub pubkey: Option<String>,
+	/// ID of the API token requesting the payment, unset for the node's own token
+	pub token_id: Option<String>,
+	pub requested_at: u64,
+}
 
-	if pubkey == "".to_string() || host == "".to_string() || port == "".to_string() {
-		let error = ServerError {
-			error:
-				"ERROR: connectpeer requires peer connection info: `connectpeer pubkey@host:port`"
-					.to_string(),
-		};
-		return HttpResponse::BadRequest().content_type(ContentType::json()).json(error);
-	} else {
-		let pubkey_peer_addr = parse_peer_info(peer_pubkey_host_port);
-		match pubkey_peer_addr {
-			Ok(info) => {
-				if connect_peer_if_necessary(info.0, info.1, peer_manager).await.is_ok() {
-					let msg =
-						ServerSuccess { msg: format!("SUCCESS: connected to peer {}", info.0) };
-					return HttpResponse::Ok().content_type(ContentType::json()).json(msg);
-				} else {
-					let error = ServerError { error: "Failed to connect to peer".to_string() };
-					return HttpResponse::BadRequest()
-						.content_type(ContentType::json())
-						.json(error);
-				}
-			}
-			Err(e) => {
-				let error = ServerError { error: e.into_inner().unwrap().to_string() };
-				return HttpResponse::BadRequest().content_type(ContentType::json()).json(error);
-			}
+// Struct containing the payments awaiting approval, oldest first
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct PendingApprovals {
+	pub payments: Vec<PendingApproval>,
+}
+
+// issue API token request struct
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct IssueToken {
+	pub scope: TokenScope,
+	pub label: Option<String>,
+	pub max_payment_msat: Option<u64>,
+	pub daily_limit_msat: Option<u64>,
+}
+
+// Struct containing an API token issued through the API. `token` is only set when it is issued.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct ApiTokenInfo {
+	pub id: String,
+	pub scope: TokenScope,
+	pub label: Option<String>,
+	pub created_at: u64,
+	pub limits: SpendingLimits,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub token: Option<String>,
+}
+
+// Struct containing the API tokens issued through the API
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct ListTokens {
+	pub tokens: Vec<ApiTokenInfo>,
+}
+
+// add webhook request struct
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct AddWebhook {
+	pub url: String,
+	pub secret: Option<String>,
+}
+
+// remove webhook request struct
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct RemoveWebhook {
+	pub id: String,
+}
+
+// Registered webhook. The secret is only returned when the webhook is added.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct WebhookInfo {
+	pub id: String,
+	pub url: String,
+	pub secret: Option<String>,
+}
+
+// Struct containing the webhooks notified of node events
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct ListWebhooks {
+	pub webhooks: Vec<WebhookInfo>,
+}
+
+// export request struct. `format` is csv or jsonl, times are in seconds since the UNIX epoch.
+#[derive(Serialize, Deserialize, Debug, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct Export {
+	pub format: Option<String>,
+	pub start_time: Option<String>,
+	pub end_time: Option<String>,
+}
+
+// Encrypted channel backup, encoded in base64
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct ChannelBackupExport {
+	pub backup: String,
+	pub channels_number: usize,
+}
+
+// seedbackup struct, confirming that the mnemonic may be displayed
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct SeedBackup {
+	pub confirm: Option<String>,
+}
+
+// Struct containing the BIP39 mnemonic of the node
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct SeedMnemonic {
+	pub mnemonic: String,
+}
+
+// Struct containing the reachability of bitcoind and how far LDK lags behind it. Without
+// bitcoind, the `bitcoind_*` fields report on the Esplora or Electrum server instead.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct Health {
+	pub bitcoind_reachable: bool,
+	pub bitcoind_last_error: Option<String>,
+	/// Unix time of the last RPC call that reached bitcoind
+	pub bitcoind_last_contact: Option<u64>,
+	pub bitcoind_block_height: Option<u32>,
+	pub ldk_block_height: u32,
+	/// Blocks of bitcoind's chain LDK hasn't synced yet
+	pub sync_lag_blocks: Option<u32>,
+}
+
+// Struct containing a fee rate estimate for a confirmation target
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct FeeRateEstimate {
+	pub target_conf: u16,
+	pub sat_per_kw: u32,
+	pub sat_per_vbyte: f64,
+}
+
+// Struct containing the cached fee rate estimates
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct FeeRates {
+	/// Fee rates LDK uses for its own transactions, from the chain backend
+	pub background_sat_per_kw: u32,
+	pub normal_sat_per_kw: u32,
+	pub high_priority_sat_per_kw: u32,
+	/// Estimates of the chain backend, which transactions given a `target_conf` are funded at
+	pub estimates: Vec<FeeRateEstimate>,
+}
+
+/// Number of a request body, which is also accepted as a string of digits as the CLI sends
+/// every parameter as a string
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NumberOrString<T> {
+	Number(T),
+	String(String),
+}
+
+impl<T: FromStr> NumberOrString<T>
+where
+	T::Err: fmt::Display,
+{
+	fn into_number<E: de::Error>(self) -> Result<T, E> {
+		match self {
+			NumberOrString::Number(number) => Ok(number),
+			NumberOrString::String(string) => string
+				.trim()
+				.parse()
+				.map_err(|e| E::custom(format!("invalid number {:?}: {}", string, e))),
+		}
+	}
+}
+
+fn deserialize_number<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+	D: Deserializer<'de>,
+	T: Deserialize<'de> + FromStr,
+	T::Err: fmt::Display,
+{
+	NumberOrString::<T>::deserialize(deserializer)?.into_number()
+}
+
+fn deserialize_optional_number<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+	D: Deserializer<'de>,
+	T: Deserialize<'de> + FromStr,
+	T::Err: fmt::Display,
+{
+	match Option::<NumberOrString<T>>::deserialize(deserializer)? {
+		Some(number) => number.into_number().map(Some),
+		None => Ok(None),
+	}
+}
+
+/// Machine-readable code of an error, which clients can branch on. Each code is answered
+/// with its own HTTP status.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+	/// The request is malformed or one of its parameters is invalid
+	InvalidRequest,
+	Unauthorized,
+	/// The API token is valid but its scope doesn't cover the endpoint
+	Forbidden,
+	NotFound,
+	/// The request conflicts with the current state, e.g. canceling a settled invoice
+	Conflict,
+	/// No route could be found for a payment, or none within its fee limit
+	NoRoute,
+	/// A payment or a probe couldn't be sent
+	PaymentFailed,
+	/// The peer couldn't be connected to
+	PeerUnreachable,
+	/// A service the node relies on, e.g. bitcoind or an LNURL service, failed
+	UpstreamError,
+	/// The outcome of the request wasn't known in time, e.g. a payment still in flight
+	Timeout,
+	NotImplemented,
+	Internal,
+}
+
+impl ErrorCode {
+	pub fn status(&self) -> StatusCode {
+		match self {
+			ErrorCode::InvalidRequest => StatusCode::BAD_REQUEST,
+			ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+			ErrorCode::Forbidden => StatusCode::FORBIDDEN,
+			ErrorCode::NotFound => StatusCode::NOT_FOUND,
+			ErrorCode::Conflict => StatusCode::CONFLICT,
+			ErrorCode::NoRoute | ErrorCode::PaymentFailed => StatusCode::UNPROCESSABLE_ENTITY,
+			ErrorCode::PeerUnreachable | ErrorCode::UpstreamError => StatusCode::BAD_GATEWAY,
+			ErrorCode::Timeout => StatusCode::GATEWAY_TIMEOUT,
+			ErrorCode::NotImplemented => StatusCode::NOT_IMPLEMENTED,
+			ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+		}
+	}
+}
+
+// Server Error
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct ServerError {
+	pub code: ErrorCode,
+	pub message: String,
+	/// Underlying error, e.g. as reported by LDK
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub detail: Option<String>,
+}
+
+impl fmt::Display for ServerError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "ERROR: {}", self.message)?;
+		if let Some(detail) = &self.detail {
+			write!(f, " ({})", detail)?;
+		}
+		Ok(())
+	}
+}
+
+/// Error of a request, answered with a `ServerError` and the HTTP status of its code
+#[derive(Debug)]
+pub struct ApiError(ServerError);
+
+impl ApiError {
+	pub fn new(code: ErrorCode, message: String) -> Self {
+		ApiError(ServerError { code, message, detail: None })
+	}
+
+	pub fn with_detail(mut self, detail: String) -> Self {
+		self.0.detail = Some(detail);
+		self
+	}
+
+	pub fn code(&self) -> ErrorCode {
+		self.0.code
+	}
+
+	pub fn into_body(self) -> ServerError {
+		self.0
+	}
+}
+
+impl fmt::Display for ApiError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		self.0.fmt(f)
+	}
+}
+
+impl ResponseError for ApiError {
+	fn status_code(&self) -> StatusCode {
+		self.0.code.status()
+	}
+
+	fn error_response(&self) -> HttpResponse {
+		HttpResponse::build(self.status_code()).content_type(ContentType::json()).json(&self.0)
+	}
+}
+
+// Server suceess
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct ServerSuccess {
+	pub msg: String,
+}
+
+/// Get helpful information on how to interact with the lightning node
+#[utoipa::path(
+	get,
+	path = "/v1/help",
+	responses(
+		(status = 200, description = "Usage of the CLI commands", body = Help),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn help(_req: HttpRequest) -> HttpResponse {
+	let help = Help {
+		openchannel: "pubkey@host:port <amt_satoshis> [<push_msat>] [--public] [--basefee <msat>] [--feeppm <millionths>] [--cltvdelta <blocks>] [--minhtlc <msat>] [--satpervbyte <sat_per_vbyte> | --targetconf <blocks>]".to_string(),
+		openchannels: "pubkey@host:port <amt_satoshis> [pubkey@host:port <amt_satoshis> ...]"
+			.to_string(),
+		sendpayment:
+			"<invoice> [<amt_msat>] [--maxfee <msat> | --maxfeepercent <pct>] [--timeout <secs>] [--wait]"
+				.to_string(),
+		getinvoice: "<amt_millisatoshis> [<description>] [<expiry_secs>] [<label>]".to_string(),
+		getphantominvoice: "<amt_millisatoshis> [<route_hints> ...] [--description <description>] [--expiry <secs>]".to_string(),
+		phantomroutehints: "".to_string(),
+		withdraw: "<lnurl> [<amt_msat>]".to_string(),
+		unifiedinvoice: "<amt_millisatoshis> [<description>] [<expiry_secs>] [<label>]"
+			.to_string(),
+		listinvoices: "".to_string(),
+		cancelinvoice: "<payment_hash>".to_string(),
+		connectpeer: "<pubkey>[@host:port]".to_string(),
+		listchannels: "".to_string(),
+		listpayments: "[--direction <inbound|outbound>] [--status <pending|succeeded|failed>] [--start <time>] [--end <time>] [--offset <n>] [--limit <n>]".to_string(),
+		closechannel: "<channel_id> [<sat_per_vbyte>] [--targetconf <blocks>]".to_string(),
+		forceclosechannel: "<channel_id>".to_string(),
+		abandonchannel: "<channel_id>".to_string(),
+		setpeerlabel: "<pubkey> [label]".to_string(),
+		setchannellabel: "<channel_id> [label] [--tag <tag> ...]".to_string(),
+		channelhistory: "<channel_id> [<start_time>] [<end_time>]".to_string(),
+		autopilot: "<status|enable|disable>".to_string(),
+		lspinfo: "".to_string(),
+		lsporder: "<lsp_balance_sat> [--clientbalancesat <sat>] [--channelexpiryblocks <blocks>] [--refundaddress <address>] [--public]".to_string(),
+		lsporderstatus: "<order_id>".to_string(),
+		closeallchannels: "[--force] [--confirm <token>] [--satpervbyte <sat_per_vbyte> | --targetconf <blocks>]".to_string(),
+		nodeinfo: "".to_string(),
+		listpeers: "".to_string(),
+		signmessage: "<message>".to_string(),
+		verifymessage: "<message> <signature> [<pubkey>]".to_string(),
+		diagnosechannel: "<channel_id>".to_string(),
+		keysend: "<dest_pubkey> <amt_msat>".to_string(),
+		decodeinvoice: "<invoice>".to_string(),
+		newaddress: "".to_string(),
+		walletbalance: "".to_string(),
+		balance: "".to_string(),
+		sendonchain: "<address> <amt_satoshis> [<sat_per_vbyte>]".to_string(),
+		listutxos: "".to_string(),
+		pendingtxs: "".to_string(),
+		events: "".to_string(),
+		eventhistory: "[--type <event_type>] [--paymenthash <hash>] [--channelid <id>] [--start <time>] [--end <time>] [--offset <n>] [--limit <n>]".to_string(),
+		lookuppayment: "<payment_hash>".to_string(),
+		payment: "<payment_id>".to_string(),
+		cancelpayment: "<payment_id>".to_string(),
+		closedchannels: "".to_string(),
+		listforwards: "[<start_time>] [<end_time>]".to_string(),
+		feereport: "".to_string(),
+		updatechannelpolicy: "<base_fee_msat> <fee_proportional_millionths> <cltv_expiry_delta> [<channel_id>] [--minhtlc <msat>] [--minfinalcltv <blocks>]".to_string(),
+		setnodeinfo: "[--alias <alias>] [--color <rrggbb>] [--address <ip:port>]*".to_string(),
+		addwebhook: "<url> [<secret>]".to_string(),
+		listwebhooks: "".to_string(),
+		removewebhook: "<webhook_id>".to_string(),
+		exportbackup: "<output_path>".to_string(),
+		exportpayments: "<output_path> [--format <csv|jsonl>] [--start <time>] [--end <time>]"
+			.to_string(),
+		exportforwards: "<output_path> [--format <csv|jsonl>] [--start <time>] [--end <time>]"
+			.to_string(),
+		backupseed: "--confirm".to_string(),
+		health: "".to_string(),
+		feerates: "".to_string(),
+		queryroutes: "<dest_pubkey> <amt_msat> [<max_fee_msat>]".to_string(),
+		sendtoroute: "<payment_hash> <amt_msat> <scid_or_pubkey> [<scid_or_pubkey> ...] [--paymentsecret <secret>]".to_string(),
+		rebalance: "<out_scid> <in_scid> <amt_msat> <max_fee_msat>".to_string(),
+		probe: "<dest_pubkey> <amt_msat>".to_string(),
+		scorerparams: "[--basepenalty <msat>] [--liquiditypenalty <msat>] [--halflife <secs>] [--amountpenalty <msat>]".to_string(),
+	};
+	HttpResponse::Ok().content_type(ContentType::json()).json(help)
+}
+
+/// Connect to the peer at `pubkey@host:port` and initiate a channel of `channel_amt_satoshis`
+/// with it, pushing `push_msat` to the peer, and return the peer's public key. The channel is
+/// funded at `funding_sat_per_kw`, or at the normal priority estimate if unset.
+async fn initiate_channel(
+	node_var: &NodeVar<ServerEventHandler>, pubkey: PublicKey, host: &str, port: u16,
+	chan_amt_sat: u64, push_msat: Option<u64>, announce_channel: bool, policy: &ChannelPolicy,
+	limits: &ChannelLimits, funding_sat_per_kw: Option<u32>,
+) -> Result<PublicKey, ApiError> {
+	let invalid = |msg: &str| ApiError::new(ErrorCode::InvalidRequest, msg.to_string());
+	let peer_addr = PeerAddr::new(host, port).map_err(|e| invalid(&e))?;
+	let info = (pubkey, peer_addr);
+
+	let push_msat = push_msat.unwrap_or(0);
+	if push_msat > chan_amt_sat.saturating_mul(1000) {
+		return Err(invalid("push amount cannot exceed the channel amount"));
+	}
+
+	if connect_peer_if_necessary(
+		info.0,
+		info.1.clone(),
+		node_var.peer_manager.clone(),
+		node_var.socks_proxy,
+		&node_var.peer_tracker,
+	)
+	.await
+	.is_err()
+	{
+		return Err(ApiError::new(
+			ErrorCode::PeerUnreachable,
+			"cannot connect to peer".to_string(),
+		));
+	};
+
+	let temporary_channel_id = match cli::open_channel(
+		info.0,
+		chan_amt_sat,
+		push_msat,
+		announce_channel,
+		policy,
+		limits,
+		node_var.channel_manager.clone(),
+	) {
+		Ok(temporary_channel_id) => temporary_channel_id,
+		Err(()) => return Err(invalid("unable to open a channel with peer")),
+	};
+	// The funding transaction is only built once the peer accepted the channel, which takes a
+	// round trip, so the fee rate is recorded before `Event::FundingGenerationReady` is handled
+	if let Some(sat_per_kw) = funding_sat_per_kw {
+		node_var.funding_feerates.lock().unwrap().insert(temporary_channel_id, sat_per_kw);
+	}
+	let _ = disk::persist_channel_peer(&*node_var.store, &info.0, &info.1);
+	Ok(info.0)
+}
+
+/// Override the node's channel `policy` and `limits` and the announcement of a new channel
+/// with its `config`
+fn apply_open_config(
+	config: &OpenChannelConfig, announce_channel: &mut bool, policy: &mut ChannelPolicy,
+	limits: &mut ChannelLimits,
+) -> Result<(), String> {
+	let parse_error = |field: &str, value: &str| format!("invalid {}: {}", field, value);
+	if config.max_inflight_htlc_percent.is_some() {
+		return Err(format!(
+			"max_inflight_htlc_percent isn't supported yet, the value in flight on channels is fixed by LDK"
+		));
+	}
+	if config.max_accepted_htlcs.is_some() {
+		return Err(format!(
+			"max_accepted_htlcs isn't supported yet, the HTLCs in flight on channels are capped by LDK"
+		));
+	}
+	if let Some(announce) = &config.announce_channel {
+		*announce_channel = announce.as_str() == "true";
+	}
+	if let Some(base_fee_msat) = &config.forwarding_fee_base_msat {
+		policy.forwarding_fee_base_msat = base_fee_msat
+			.parse()
+			.map_err(|_| parse_error("forwarding_fee_base_msat", base_fee_msat))?;
+	}
+	if let Some(fee) = &config.forwarding_fee_proportional_millionths {
+		policy.forwarding_fee_proportional_millionths =
+			fee.parse().map_err(|_| parse_error("forwarding_fee_proportional_millionths", fee))?;
+	}
+	if let Some(cltv_expiry_delta) = &config.cltv_expiry_delta {
+		match cltv_expiry_delta.parse::<u16>() {
+			// LDK silently raises lower deltas to its minimum, so reject them instead
+			Ok(delta) if delta >= MIN_CLTV_EXPIRY_DELTA => policy.cltv_expiry_delta = delta,
+			_ => return Err(parse_error("cltv_expiry_delta", cltv_expiry_delta)),
+		}
+	}
+	if let Some(htlc_minimum_msat) = &config.htlc_minimum_msat {
+		match htlc_minimum_msat.parse::<u64>() {
+			Ok(min) if min > 0 => policy.htlc_minimum_msat = Some(min),
+			_ => return Err(parse_error("htlc_minimum_msat", htlc_minimum_msat)),
+		}
+	}
+	if let Some(max_dust_exposure) = &config.max_dust_htlc_exposure_msat {
+		limits.max_dust_htlc_exposure_msat = max_dust_exposure
+			.parse()
+			.map_err(|_| parse_error("max_dust_htlc_exposure_msat", max_dust_exposure))?;
+	}
+	Ok(())
+}
+
+/// Open channel with another node
+#[utoipa::path(
+	post,
+	path = "/v1/channels",
+	request_body = OpenChannel,
+	responses(
+		(status = 200, description = "Channel opening initiated", body = ServerSuccess),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn open_channel(
+	req: web::Json<OpenChannel>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	match open_requested_channel(&node_var, &req).await {
+		Ok(peer_pubkey) => {
+			let msg = ServerSuccess {
+				msg: format!("EVENT: initiated channel with peer {}. ", peer_pubkey),
+			};
+			HttpResponse::Ok().content_type(ContentType::json()).json(msg)
+		}
+		Err(error) => error.error_response(),
+	}
+}
+
+/// Open the channel of an `openchannel` request, returning the peer once the channel is
+/// initiated
+pub(crate) async fn open_requested_channel(
+	node_var: &NodeVar<ServerEventHandler>, req: &OpenChannel,
+) -> Result<PublicKey, ApiError> {
+	let mut announce_channel = match &req.channel_announcement {
+		Some(val) => val.as_str() == "true",
+		None => false,
+	};
+
+	let mut policy = node_var.channel_policy.lock().unwrap().clone();
+	let mut limits = node_var.channel_limits.clone();
+	if let Some(config) = &req.config {
+		apply_open_config(config, &mut announce_channel, &mut policy, &mut limits)
+			.map_err(|error| ApiError::new(ErrorCode::InvalidRequest, error))?;
+	}
+	let funding_sat_per_kw = parse_feerate(node_var, &req.sat_per_vbyte, &req.target_conf).await?;
+
+	initiate_channel(
+		node_var,
+		req.pubkey,
+		&req.host,
+		req.port,
+		req.channel_amt_satoshis,
+		req.push_msat,
+		announce_channel,
+		&policy,
+		&limits,
+		funding_sat_per_kw,
+	)
+	.await
+}
+
+/// Open channels with several nodes.
+///
+/// Each channel is funded by its own transaction. LDK 0.0.106 broadcasts a funding transaction
+/// as soon as the first of its channels is signed by the peer, so a transaction shared by
+/// several channels would lock the funds of any channel whose peer then fails to sign.
+async fn open_channels(
+	req: web::Json<OpenChannels>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	if req.channels.is_empty() {
+		return ApiError::new(
+			ErrorCode::InvalidRequest,
+			"openchannels requires at least one `pubkey@host:port amount` pair".to_string(),
+		)
+		.error_response();
+	}
+
+	let policy = node_var.channel_policy.lock().unwrap().clone();
+
+	let mut results = Vec::new();
+	for channel in req.channels.iter() {
+		let announce_channel = match &channel.channel_announcement {
+			Some(val) => val.as_str() == "true",
+			None => false,
 		};
+		let res = initiate_channel(
+			&node_var,
+			channel.pubkey,
+			&channel.host,
+			channel.port,
+			channel.amount,
+			channel.push_msat,
+			announce_channel,
+			&policy,
+			&node_var.channel_limits,
+			None,
+		)
+		.await;
+		results.push(OpenChannelResult {
+			pubkey: channel.pubkey.to_string(),
+			msg: res.as_ref().ok().map(|pk| format!("EVENT: initiated channel with peer {}. ", pk)),
+			error: res.err().map(ApiError::into_body),
+		});
 	}
+	HttpResponse::Ok().content_type(ContentType::json()).json(OpenedChannels { results })
 }
 
-/// Get invoice
-async fn get_invoice(
-	req: web::Json<GetInvoice>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+/// Get node information
+#[utoipa::path(
+	get,
+	path = "/v1/node",
+	responses(
+		(status = 200, description = "Information about the node", body = NodeInfo),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn nodeinfo(
+	_req: HttpRequest, node_var: web::Data<NodeVar<ServerEventHandler>>,
 ) -> HttpResponse {
-	let amt_str = format!("{}", req.amt_millisatoshis);
-	if amt_str == "" {
-		let error = ServerError {
-			error: "ERROR: getinvoice requires an amount in millisatoshis".to_string(),
+	let nodeinfo_obj = node_info(&node_var).await;
+	HttpResponse::Ok().content_type(ContentType::json()).json(nodeinfo_obj)
+}
+
+/// Describe the node, as reported by `nodeinfo`
+pub(crate) async fn node_info(node_var: &NodeVar<ServerEventHandler>) -> NodeInfo {
+	let pubkey = node_var.channel_manager.get_our_node_id();
+	let channel_list = node_var.channel_manager.list_channels();
+	let channels_number = channel_list.len();
+	let usable_channels_number = channel_list.iter().filter(|c| c.is_usable).count();
+	let local_balance_msat = channel_list.iter().map(|c| c.balance_msat).sum::<u64>();
+	let peers = node_var.peer_manager.get_peer_node_ids().len();
+	let best_block = node_var.channel_manager.current_best_block();
+	let synced_to_chain = match node_var.chain_source.get_best_block().await {
+		Ok((tip_hash, _)) => tip_hash == best_block.block_hash(),
+		Err(_) => false,
+	};
+	// LDK doesn't track the initial graph sync, so the graph counts as synced once it knows
+	// channels and a peer keeps it up to date
+	let synced_to_graph = peers > 0 && !node_var.network_graph.read_only().channels().is_empty();
+	let announcement = node_var.node_announcement.lock().unwrap().clone();
+	// Node information is still useful if bitcoind can't report the on-chain balance
+	let (onchain_confirmed_balance_sat, onchain_unconfirmed_balance_sat) =
+		match node_var.wallet.get_balance().await {
+			Ok(balance) => (Some(balance.confirmed_sat), Some(balance.unconfirmed_sat)),
+			Err(_) => (None, None),
+		};
+
+	NodeInfo {
+		version: env!("CARGO_PKG_VERSION").to_string(),
+		pubkey,
+		alias: announcement.alias,
+		color: announcement.color,
+		network: node_var.network.to_string(),
+		block_height: best_block.height(),
+		block_hash: best_block.block_hash(),
+		synced_to_chain,
+		synced_to_graph,
+		addresses: announcement.addresses,
+		listen_addresses: node_var
+			.listen_addresses
+			.iter()
+			.map(|address| address.to_string())
+			.collect(),
+		// channel_list,
+		channels_number,
+		usable_channels_number,
+		local_balance_msat,
+		onchain_confirmed_balance_sat,
+		onchain_unconfirmed_balance_sat,
+		peers,
+	}
+}
+
+/// Report whether the chain backend can be reached and how many blocks LDK lags behind it
+#[utoipa::path(
+	get,
+	path = "/v1/health",
+	responses(
+		(status = 200, description = "Health of the node and its chain backend", body = Health),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn health(node_var: web::Data<NodeVar<ServerEventHandler>>) -> HttpResponse {
+	let rpc_health = match &node_var.bitcoind_client {
+		Some(bitcoind_client) => bitcoind_client.health(),
+		// The chain servers are only checked on request
+		None => match node_var.chain_source.get_best_block().await {
+			Ok((_, height)) => RpcHealth {
+				reachable: true,
+				last_error: None,
+				last_contact: Some(SystemTime::now()),
+				block_height: Some(height),
+			},
+			Err(e) => RpcHealth {
+				reachable: false,
+				last_error: Some(e.to_string()),
+				..Default::default()
+			},
+		},
+	};
+	let ldk_block_height = node_var.channel_manager.current_best_block().height();
+	let health = Health {
+		bitcoind_reachable: rpc_health.reachable,
+		bitcoind_last_error: rpc_health.last_error,
+		bitcoind_last_contact: rpc_health
+			.last_contact
+			.and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+			.map(|duration| duration.as_secs()),
+		bitcoind_block_height: rpc_health.block_height,
+		ldk_block_height,
+		sync_lag_blocks: rpc_health
+			.block_height
+			.map(|height| height.saturating_sub(ldk_block_height)),
+	};
+	HttpResponse::Ok().content_type(ContentType::json()).json(health)
+}
+
+/// List the cached fee rate estimates, which are refreshed in the background
+#[utoipa::path(
+	get,
+	path = "/v1/feerates",
+	responses(
+		(status = 200, description = "Fee rate estimates", body = FeeRates),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn fee_rates(node_var: web::Data<NodeVar<ServerEventHandler>>) -> HttpResponse {
+	let estimates = node_var
+		.chain_source
+		.fee_estimates()
+		.into_iter()
+		.map(|(target_conf, sat_per_kw)| FeeRateEstimate {
+			target_conf,
+			sat_per_kw,
+			// 1 vbyte is 4 weight units
+			sat_per_vbyte: sat_per_kw as f64 / 250.0,
+		})
+		.collect();
+	let fee_rates = FeeRates {
+		background_sat_per_kw: node_var
+			.chain_source
+			.get_est_sat_per_1000_weight(ConfirmationTarget::Background),
+		normal_sat_per_kw: node_var
+			.chain_source
+			.get_est_sat_per_1000_weight(ConfirmationTarget::Normal),
+		high_priority_sat_per_kw: node_var
+			.chain_source
+			.get_est_sat_per_1000_weight(ConfirmationTarget::HighPriority),
+		estimates,
+	};
+	HttpResponse::Ok().content_type(ContentType::json()).json(fee_rates)
+}
+
+/// List connected node peers
+#[utoipa::path(
+	get,
+	path = "/v1/peers",
+	responses(
+		(status = 200, description = "Connected peers", body = ListPeers),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn list_peers(node_var: web::Data<NodeVar<ServerEventHandler>>) -> HttpResponse {
+	let connected = node_var.peer_manager.get_peer_node_ids();
+	let channels = node_var.channel_manager.list_channels();
+	let mut pubkeys = connected.clone();
+	for chan in channels.iter() {
+		if !pubkeys.contains(&chan.counterparty.node_id) {
+			pubkeys.push(chan.counterparty.node_id);
+		}
+	}
+
+	let labels = peers::read_peer_labels(&*node_var.store).unwrap_or_else(|e| {
+		warn!("failed to read peer labels: {}", e);
+		HashMap::new()
+	});
+	// Feature bits are encoded after their length
+	let feature_bits = |encoded: Vec<u8>| hex_utils::hex_str(&encoded[2..]);
+	let graph = node_var.network_graph.read_only();
+	let mut peers = Vec::new();
+	for pubkey in pubkeys {
+		let peer_channels: Vec<_> =
+			channels.iter().filter(|chan| chan.counterparty.node_id == pubkey).collect();
+		let announcement = graph
+			.nodes()
+			.get(&NodeId::from_pubkey(&pubkey))
+			.and_then(|node| node.announcement_info.as_ref());
+		let features = match (peer_channels.first(), announcement) {
+			(Some(chan), _) => Some(feature_bits(chan.counterparty.features.encode())),
+			(None, Some(announcement)) => Some(feature_bits(announcement.features.encode())),
+			(None, None) => None,
+		};
+		let connection = node_var.peer_tracker.connection(&pubkey);
+		peers.push(PeerDetails {
+			pubkey,
+			alias: announcement
+				.map(|announcement| sanitize_string(&announcement.alias))
+				.unwrap_or_default(),
+			label: labels.get(&pubkey).cloned(),
+			connected: connected.contains(&pubkey),
+			address: connection
+				.as_ref()
+				.and_then(|connection| connection.address.clone())
+				.map(|address| address.to_string()),
+			direction: connection.as_ref().map(|connection| connection.direction),
+			features,
+			channels: peer_channels.len(),
+			connected_since: connection.as_ref().map(|connection| connection.connected_since),
+			last_seen: node_var.peer_tracker.last_seen(&pubkey),
+		});
+	}
+	HttpResponse::Ok().content_type(ContentType::json()).json(ListPeers { peers })
+}
+
+///List open node channels
+#[utoipa::path(
+	get,
+	path = "/v1/channels",
+	responses(
+		(status = 200, description = "Open channels", body = ListChannels),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn list_channels(node_var: web::Data<NodeVar<ServerEventHandler>>) -> HttpResponse {
+	let list_channels = channel_list(&node_var);
+	HttpResponse::Ok().content_type(ContentType::json()).json(list_channels)
+}
+
+/// Describe the open channels of the node, as reported by `listchannels`
+pub(crate) fn channel_list(node_var: &NodeVar<ServerEventHandler>) -> ListChannels {
+	let channel_manager = &node_var.channel_manager;
+	let network_graph = &node_var.network_graph;
+	let channels_list = channel_manager.list_channels();
+	let mut channel_vector = Vec::new();
+	let labels = peers::read_peer_labels(&*node_var.store).unwrap_or_else(|e| {
+		warn!("failed to read peer labels: {}", e);
+		HashMap::new()
+	});
+	let mut channel_labels = disk::read_channel_labels(&*node_var.store).unwrap_or_else(|e| {
+		warn!("failed to read channel labels: {}", e);
+		HashMap::new()
+	});
+	let our_node_id = NodeId::from_pubkey(&channel_manager.get_our_node_id());
+
+	for chan_info in channels_list {
+		let chan_id = hex_utils::hex_str(&chan_info.channel_id[..]);
+
+		let mut txid = String::new();
+		if let Some(funding_txo) = chan_info.funding_txo {
+			txid = format!("{}", funding_txo.txid);
+		}
+		let peer_pubkey = hex_utils::hex_str(&chan_info.counterparty.node_id.serialize());
+
+		let mut peer_alias = String::new();
+		if let Some(node_info) = network_graph
+			.read_only()
+			.nodes()
+			.get(&NodeId::from_pubkey(&chan_info.counterparty.node_id))
+		{
+			if let Some(announcement) = &node_info.announcement_info {
+				peer_alias = sanitize_string(&announcement.alias);
+			}
+		}
+
+		let peer_label = labels.get(&chan_info.counterparty.node_id).cloned();
+		let channel_label = channel_labels.remove(&chan_id).unwrap_or_default();
+
+		let mut short_channel_id: u64 = 0;
+		if let Some(id) = chan_info.short_channel_id {
+			short_channel_id = id;
+		}
+
+		let is_confirmed_onchain = chan_info.is_funding_locked;
+		let channel_value_satoshis = chan_info.channel_value_satoshis;
+		let local_balance_msat = chan_info.balance_msat;
+
+		let mut available_balance_for_send_msat = 0;
+		let mut available_balance_for_recv_msat = 0;
+		if chan_info.is_usable {
+			available_balance_for_send_msat = chan_info.outbound_capacity_msat;
+			available_balance_for_recv_msat = chan_info.inbound_capacity_msat;
+		}
+
+		let channel_can_send_payments = chan_info.is_usable;
+		let public = chan_info.is_public;
+
+		let forwarding_info = chan_info.counterparty.forwarding_info.as_ref();
+		let graph = network_graph.read_only();
+		let policy = chan_info
+			.short_channel_id
+			.and_then(|scid| graph.channels().get(&scid))
+			.and_then(|info| {
+				if info.node_one == our_node_id {
+					info.one_to_two.as_ref()
+				} else {
+					info.two_to_one.as_ref()
+				}
+			});
+
+		// Create RedefinedChannelDetails and add to vector
+		let chan_details = RedefinedChannelDetails {
+			channel_id: chan_id,
+			tx_id: txid,
+			peer_pubkey,
+			peer_alias,
+			peer_label,
+			label: channel_label.label,
+			tags: channel_label.tags,
+			short_channel_id,
+			is_confirmed_onchain,
+			local_balance_msat,
+			channel_value_satoshis,
+			available_balance_for_send_msat,
+			available_balance_for_recv_msat,
+			channel_can_send_payments,
+			public,
+			counterparty_fee_base_msat: forwarding_info.map(|info| info.fee_base_msat),
+			counterparty_fee_proportional_millionths: forwarding_info
+				.map(|info| info.fee_proportional_millionths),
+			counterparty_cltv_expiry_delta: forwarding_info.map(|info| info.cltv_expiry_delta),
+			fee_base_msat: policy.map(|policy| policy.fees.base_msat),
+			fee_proportional_millionths: policy.map(|policy| policy.fees.proportional_millionths),
+			cltv_expiry_delta: policy.map(|policy| policy.cltv_expiry_delta),
+			htlc_minimum_msat: policy.map(|policy| policy.htlc_minimum_msat),
+			htlc_maximum_msat: policy.and_then(|policy| policy.htlc_maximum_msat),
+			counterparty_reserve_satoshis: chan_info.counterparty.unspendable_punishment_reserve,
+			unspendable_punishment_reserve_satoshis: chan_info.unspendable_punishment_reserve,
 		};
-		return HttpResponse::BadRequest().content_type(ContentType::json()).json(error);
+
+		channel_vector.push(chan_details);
 	}
+	ListChannels { channels: channel_vector }
+}
+
+/// Connect to another peer
+#[utoipa::path(
+	post,
+	path = "/v1/peers",
+	request_body = ConnectPeer,
+	responses(
+		(status = 200, description = "Connected to the peer", body = ServerSuccess),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn connect_peer(
+	req: web::Json<ConnectPeer>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	let peer_manager = node_var.peer_manager.clone();
+	let (host, port) = match (&req.host, &req.port) {
+		(Some(host), Some(port)) => (host.clone(), port.clone()),
+		(None, None) => return connect_known_peer(req.pubkey, &node_var).await,
+		_ => {
+			return ApiError::new(
+				ErrorCode::InvalidRequest,
+				"connectpeer requires both the host and the port of the peer, or neither"
+					.to_string(),
+			)
+			.error_response()
+		}
+	};
+	let pubkey = format!("{}", req.pubkey);
+	let peer_pubkey_host_port = format!("{}@{}:{}", pubkey, host, port);
 
-	let amt_msat: Result<u64, _> = amt_str.parse();
-	if amt_msat.is_err() {
-		let error = ServerError {
-			error: "ERROR: getinvoice provided payment amount was not a number".to_string(),
+	if host == "".to_string() || port == "".to_string() {
+		return ApiError::new(
+			ErrorCode::InvalidRequest,
+			"connectpeer requires peer connection info: `connectpeer pubkey@host:port`".to_string(),
+		)
+		.error_response();
+	} else {
+		let pubkey_peer_addr = parse_peer_info(peer_pubkey_host_port);
+		match pubkey_peer_addr {
+			Ok(info) => {
+				if connect_peer_if_necessary(
+					info.0,
+					info.1.clone(),
+					peer_manager,
+					node_var.socks_proxy,
+					&node_var.peer_tracker,
+				)
+				.await
+				.is_ok()
+				{
+					// Remember the address, host name included, for `connectpeer <pubkey>`
+					if let Err(e) = disk::persist_channel_peer(&*node_var.store, &info.0, &info.1) {
+						warn!("failed to persist the address of peer {}: {}", info.0, e);
+					}
+					let msg =
+						ServerSuccess { msg: format!("SUCCESS: connected to peer {}", info.0) };
+					return HttpResponse::Ok().content_type(ContentType::json()).json(msg);
+				} else {
+					return ApiError::new(
+						ErrorCode::PeerUnreachable,
+						"failed to connect to peer".to_string(),
+					)
+					.error_response();
+				}
+			}
+			Err(e) => {
+				return ApiError::new(
+					ErrorCode::InvalidRequest,
+					e.into_inner().unwrap().to_string(),
+				)
+				.error_response();
+			}
 		};
-		return HttpResponse::BadRequest().content_type(ContentType::json()).json(error);
 	}
+}
+
+/// Connect to `pubkey` at one of the addresses it is known at
+async fn connect_known_peer(
+	pubkey: PublicKey, node_var: &NodeVar<ServerEventHandler>,
+) -> HttpResponse {
+	if node_var.peer_manager.get_peer_node_ids().contains(&pubkey) {
+		let msg = ServerSuccess { msg: format!("SUCCESS: connected to peer {}", pubkey) };
+		return HttpResponse::Ok().content_type(ContentType::json()).json(msg);
+	}
+	let addresses = match peers::known_addresses(&*node_var.store, &node_var.network_graph, &pubkey)
+	{
+		Ok(addresses) => addresses,
+		Err(e) => {
+			return ApiError::new(
+				ErrorCode::Internal,
+				format!("failed to read the addresses of peers: {}", e),
+			)
+			.error_response()
+		}
+	};
+	if addresses.is_empty() {
+		return ApiError::new(
+			ErrorCode::NotFound,
+			format!("no known address of peer {}, connect with `pubkey@host:port`", pubkey),
+		)
+		.error_response();
+	}
+	for address in addresses {
+		if cli::do_connect_peer(
+			pubkey,
+			address.clone(),
+			node_var.peer_manager.clone(),
+			node_var.socks_proxy,
+			&node_var.peer_tracker,
+		)
+		.await
+		.is_ok()
+		{
+			let msg = ServerSuccess {
+				msg: format!("SUCCESS: connected to peer {} at {}", pubkey, address),
+			};
+			return HttpResponse::Ok().content_type(ContentType::json()).json(msg);
+		}
+	}
+	ApiError::new(ErrorCode::PeerUnreachable, "failed to connect to peer".to_string())
+		.error_response()
+}
+
+/// Attach a label and tags to a channel, returned by listchannels
+#[utoipa::path(
+	post,
+	path = "/v1/channels/{channel_id}/label",
+	params(("channel_id" = String, Path, description = "Hex-encoded channel ID")),
+	request_body = SetChannelLabel,
+	responses(
+		(status = 200, description = "The label of the channel", body = ChannelLabel),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn set_channel_label(
+	path: web::Path<String>, req: web::Json<SetChannelLabel>,
+	node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	let channel_id = match hex_utils::to_vec(path.as_str()) {
+		Some(channel_id) if channel_id.len() == 32 => {
+			let mut id = [0; 32];
+			id.copy_from_slice(&channel_id);
+			id
+		}
+		_ => {
+			return ApiError::new(
+				ErrorCode::InvalidRequest,
+				"couldn't parse channel_id".to_string(),
+			)
+			.error_response()
+		}
+	};
+	if !node_var.channel_manager.list_channels().iter().any(|chan| chan.channel_id == channel_id) {
+		return ApiError::new(ErrorCode::NotFound, format!("unknown channel {}", path))
+			.error_response();
+	}
+
+	let mut tags: Vec<String> =
+		req.tags.iter().map(|tag| tag.trim().to_string()).filter(|tag| !tag.is_empty()).collect();
+	tags.sort();
+	tags.dedup();
+	let label = ChannelLabel {
+		label: req
+			.label
+			.as_deref()
+			.map(str::trim)
+			.filter(|label| !label.is_empty())
+			.map(str::to_string),
+		tags,
+	};
+	if let Err(e) = disk::persist_channel_label(&*node_var.store, &channel_id, &label) {
+		return ApiError::new(
+			ErrorCode::Internal,
+			format!("failed to persist channel label: {}", e),
+		)
+		.error_response();
+	}
+	HttpResponse::Ok().content_type(ContentType::json()).json(label)
+}
+
+/// Label a peer, for listpeers and listchannels
+async fn set_peer_label(
+	req: web::Json<SetPeerLabel>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	let label = req.label.as_deref().map(str::trim).filter(|label| !label.is_empty());
+	if let Err(e) = peers::persist_peer_label(&*node_var.store, &req.pubkey, label) {
+		return ApiError::new(ErrorCode::Internal, format!("failed to persist peer label: {}", e))
+			.error_response();
+	}
+	let msg = match label {
+		Some(label) => format!("SUCCESS: labeled peer {} {:?}", req.pubkey, label),
+		None => format!("SUCCESS: removed the label of peer {}", req.pubkey),
+	};
+	HttpResponse::Ok().content_type(ContentType::json()).json(ServerSuccess { msg })
+}
+
+/// Get invoice
+#[utoipa::path(
+	post,
+	path = "/v1/invoices",
+	request_body = GetInvoice,
+	responses(
+		(status = 200, description = "Created invoice", body = CreatedInvoice),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn get_invoice(
+	req: web::Json<GetInvoice>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	match create_requested_invoice(&node_var, &req).await {
+		Ok(created_invoice) => {
+			HttpResponse::Ok().content_type(ContentType::json()).json(created_invoice)
+		}
+		Err(error) => error.error_response(),
+	}
+}
+
+/// Create and record the invoice of a `getinvoice` request
+pub(crate) async fn create_requested_invoice(
+	node_var: &NodeVar<ServerEventHandler>, req: &GetInvoice,
+) -> Result<CreatedInvoice, ApiError> {
+	let (amt_msat, description, expiry_secs) =
+		invoice_params(req.amt_millisatoshis, &req.description, req.expiry_secs)
+			.map_err(|e| ApiError::new(ErrorCode::InvalidRequest, e.to_string()))?;
+
+	let mut payments = node_var.inbound_payments.write().await;
+	let invoice = invoice::create_invoice(
+		&node_var.channel_manager,
+		&node_var.keys_manager,
+		invoice::currency(node_var.network),
+		Some(amt_msat),
+		description.clone(),
+		expiry_secs,
+		node_var.channel_policy.lock().unwrap().min_final_cltv_expiry,
+	);
+
+	match invoice {
+		Ok(inv) => Ok(record_invoice(
+			node_var,
+			&mut payments,
+			&inv,
+			amt_msat,
+			description,
+			req.label.clone(),
+		)),
+		Err(e) => {
+			Err(ApiError::new(ErrorCode::Internal, format!("failed to create invoice: {:?}", e)))
+		}
+	}
+}
+
+/// Parse the amount, description and expiry of an invoice to create, applying their defaults
+fn invoice_params(
+	amt_msat: u64, description: &Option<String>, expiry_secs: Option<u32>,
+) -> Result<(u64, String, u32), String> {
+	let description = description.clone().unwrap_or("ln-node".to_string());
+	if description.len() > invoice::MAX_DESCRIPTION_LEN {
+		return Err(format!(
+			"invoice description can't be longer than {} bytes",
+			invoice::MAX_DESCRIPTION_LEN
+		));
+	}
+	let expiry_secs = match expiry_secs {
+		Some(0) => return Err("invalid invoice expiry_secs: 0".to_string()),
+		Some(expiry_secs) => expiry_secs,
+		None => invoice::DEFAULT_INVOICE_EXPIRY_SECS,
+	};
+	Ok((amt_msat, description, expiry_secs))
+}
+
+/// Track the inbound payment of a created invoice and persist the invoice
+fn record_invoice(
+	node_var: &NodeVar<ServerEventHandler>, payments: &mut HashMap<PaymentHash, PaymentInfo>,
+	inv: &Invoice, amt_msat: u64, description: String, label: Option<String>,
+) -> CreatedInvoice {
+	let payment_hash = invoice::record_invoice(
+		&*node_var.store,
+		payments,
+		inv,
+		amt_msat,
+		description,
+		label.clone(),
+	);
+	CreatedInvoice {
+		invoice: format!("{}", inv),
+		payment_hash: hex_utils::hex_str(&payment_hash.0),
+		expiry_secs: inv.expiry_time().as_secs(),
+		label,
+	}
+}
+
+/// Create a BIP 21 URI which can be paid either to a new on-chain address or to a new invoice
+/// for the same amount
+async fn unified_invoice(
+	req: web::Json<GetUnifiedInvoice>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> Result<HttpResponse, ApiError> {
+	let (amt_msat, description, expiry_secs) =
+		invoice_params(req.amt_millisatoshis, &req.description, req.expiry_secs)
+			.map_err(|e| ApiError::new(ErrorCode::InvalidRequest, e))?;
+	// On-chain payments can't carry fractions of a satoshi
+	if amt_msat == 0 || amt_msat % 1000 != 0 {
+		return Err(ApiError::new(
+			ErrorCode::InvalidRequest,
+			format!("the amount has to be a whole number of satoshis: {} msat", amt_msat),
+		));
+	}
+
+	let address = node_var.wallet.get_new_address().await.map_err(|e| {
+		ApiError::new(ErrorCode::Internal, format!("failed to get new address: {}", e))
+	})?;
+	let mut payments = node_var.inbound_payments.write().await;
+	let inv = invoice::create_invoice(
+		&node_var.channel_manager,
+		&node_var.keys_manager,
+		invoice::currency(node_var.network),
+		Some(amt_msat),
+		description.clone(),
+		expiry_secs,
+		node_var.channel_policy.lock().unwrap().min_final_cltv_expiry,
+	)
+	.map_err(|e| ApiError::new(ErrorCode::Internal, format!("failed to create invoice: {}", e)))?;
+	let message = req.description.as_ref().map(|_| description.clone());
+	let created_invoice =
+		record_invoice(&node_var, &mut payments, &inv, amt_msat, description, req.label.clone());
+	let unified_invoice = UnifiedInvoice {
+		uri: bip21::unified_uri(&address, amt_msat / 1000, &inv, message.as_deref()),
+		address: address.to_string(),
+		invoice: created_invoice.invoice,
+		payment_hash: created_invoice.payment_hash,
+		expiry_secs: created_invoice.expiry_secs,
+	};
+	Ok(HttpResponse::Ok().content_type(ContentType::json()).json(unified_invoice))
+}
+
+/// Withdraw from an LNURL-withdraw service, by creating an invoice for the service to pay
+async fn withdraw(
+	req: web::Json<Withdraw>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> Result<HttpResponse, ApiError> {
+	let client = LnurlClient::new().map_err(|e| ApiError::new(ErrorCode::UpstreamError, e))?;
+	let request = client
+		.fetch_withdraw_request(&req.lnurl)
+		.await
+		.map_err(|e| ApiError::new(ErrorCode::UpstreamError, e))?;
+	let amt_msat = match &req.amt_msat {
+		Some(amt_msat) => amt_msat.parse::<u64>().map_err(|_| {
+			ApiError::new(ErrorCode::InvalidRequest, format!("invalid amount: {}", amt_msat))
+		})?,
+		None => request.max_withdrawable,
+	};
+	if amt_msat < request.min_withdrawable || amt_msat > request.max_withdrawable || amt_msat == 0 {
+		return Err(ApiError::new(
+			ErrorCode::InvalidRequest,
+			format!(
+				"the service allows withdrawing between {} and {} msat",
+				request.min_withdrawable, request.max_withdrawable
+			),
+		));
+	}
+	let description = if request.default_description.is_empty() {
+		"LNURL-withdraw".to_string()
+	} else {
+		request.default_description.clone()
+	};
+	if description.len() > invoice::MAX_DESCRIPTION_LEN {
+		return Err(ApiError::new(
+			ErrorCode::UpstreamError,
+			"the service's invoice description is too long".to_string(),
+		));
+	}
+
+	let created_invoice = {
+		let mut payments = node_var.inbound_payments.write().await;
+		let inv = invoice::create_invoice(
+			&node_var.channel_manager,
+			&node_var.keys_manager,
+			invoice::currency(node_var.network),
+			Some(amt_msat),
+			description.clone(),
+			invoice::DEFAULT_INVOICE_EXPIRY_SECS,
+			node_var.channel_policy.lock().unwrap().min_final_cltv_expiry,
+		)
+		.map_err(|e| {
+			ApiError::new(ErrorCode::Internal, format!("failed to create invoice: {}", e))
+		})?;
+		record_invoice(&node_var, &mut payments, &inv, amt_msat, description, None)
+	};
+	client
+		.submit_withdraw_invoice(&request, &created_invoice.invoice)
+		.await
+		.map_err(|e| ApiError::new(ErrorCode::UpstreamError, e))?;
+
+	let msg = ServerSuccess {
+		msg: format!(
+			"EVENT: requested withdrawal of {} msat, payment hash {}",
+			amt_msat, created_invoice.payment_hash
+		),
+	};
+	Ok(HttpResponse::Ok().content_type(ContentType::json()).json(msg))
+}
+
+/// Serve the route hints other nodes of the phantom node include in their phantom invoices
+#[utoipa::path(
+	get,
+	path = "/v1/phantom/routehints",
+	responses(
+		(status = 200, description = "Route hints of the node", body = PhantomRouteHintsInfo),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn phantom_route_hints(node_var: web::Data<NodeVar<ServerEventHandler>>) -> HttpResponse {
+	let route_hints = node_var.channel_manager.get_phantom_route_hints();
+	let route_hints =
+		PhantomRouteHintsInfo { route_hints: hex_utils::hex_str(&route_hints.encode()) };
+	HttpResponse::Ok().content_type(ContentType::json()).json(route_hints)
+}
+
+/// Create an invoice any node of the phantom node can claim, with route hints to each of them
+async fn get_phantom_invoice(
+	req: web::Json<GetPhantomInvoice>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> Result<HttpResponse, ApiError> {
+	if !node_var.keys_manager.phantom_enabled() {
+		return Err(ApiError::new(
+			ErrorCode::InvalidRequest,
+			"phantom node payments aren't enabled, set phantom.enabled".to_string(),
+		));
+	}
+	let (amt_msat, description, expiry_secs) =
+		invoice_params(req.amt_millisatoshis, &req.description, req.expiry_secs)
+			.map_err(|e| ApiError::new(ErrorCode::InvalidRequest, e))?;
+	let mut route_hints = Vec::new();
+	for hints in req.route_hints.iter() {
+		let decoded = hex_utils::to_vec(hints)
+			.and_then(|bytes| PhantomRouteHints::read(&mut Cursor::new(bytes)).ok())
+			.ok_or_else(|| {
+				ApiError::new(
+					ErrorCode::InvalidRequest,
+					format!("invalid phantom route hints: {}", hints),
+				)
+			})?;
+		route_hints.push(decoded);
+	}
+
+	let mut payments = node_var.inbound_payments.write().await;
+	let inv = invoice::create_phantom_invoice(
+		&node_var.channel_manager,
+		&node_var.keys_manager,
+		invoice::currency(node_var.network),
+		Some(amt_msat),
+		description.clone(),
+		expiry_secs,
+		node_var.channel_policy.lock().unwrap().min_final_cltv_expiry,
+		route_hints,
+	)
+	.map_err(|e| ApiError::new(ErrorCode::Internal, format!("failed to create invoice: {}", e)))?;
+	let created_invoice =
+		record_invoice(&node_var, &mut payments, &inv, amt_msat, description, req.label.clone());
+	Ok(HttpResponse::Ok().content_type(ContentType::json()).json(created_invoice))
+}
+
+/// List the invoices created by the node and their state
+#[utoipa::path(
+	get,
+	path = "/v1/invoices",
+	responses(
+		(status = 200, description = "Invoices created by the node", body = Invoices),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn list_invoices(node_var: web::Data<NodeVar<ServerEventHandler>>) -> HttpResponse {
+	match invoice_list(&node_var) {
+		Ok(invoices) => HttpResponse::Ok().content_type(ContentType::json()).json(invoices),
+		Err(error) => error.error_response(),
+	}
+}
+
+/// Describe the invoices created by the node, as reported by `listinvoices`
+pub(crate) fn invoice_list(node_var: &NodeVar<ServerEventHandler>) -> Result<Invoices, ApiError> {
+	let invoices = disk::read_invoices(&*node_var.store).map_err(|e| {
+		ApiError::new(ErrorCode::Internal, format!("failed to read invoices: {}", e))
+	})?;
+	let now = unix_timestamp();
+	let invoices = invoices
+		.into_iter()
+		.map(|(payment_hash, invoice)| InvoiceDetails {
+			payment_hash: hex_utils::hex_str(&payment_hash.0),
+			state: invoice.state(now),
+			invoice: invoice.bolt11,
+			description: invoice.description,
+			amt_msat: invoice.amt_msat,
+			label: invoice.label,
+			created_at: invoice.created_at,
+			expires_at: invoice.expires_at,
+			settled_at: invoice.settled_at,
+		})
+		.collect();
+	Ok(Invoices { invoices })
+}
+
+/// Cancel an unpaid invoice, so that a payment to it is failed back instead of claimed
+async fn cancel_invoice(
+	req: web::Json<CancelInvoice>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	let payment_hash = match hex_utils::to_vec(req.payment_hash.as_str()) {
+		Some(bytes) if bytes.len() == 32 => {
+			let mut payment_hash = [0; 32];
+			payment_hash.copy_from_slice(&bytes);
+			PaymentHash(payment_hash)
+		}
+		_ => {
+			return ApiError::new(ErrorCode::InvalidRequest, "invalid payment hash".to_string())
+				.error_response();
+		}
+	};
+
+	// Hold the inbound payments so that the invoice isn't settled while it is canceled
+	let mut payments = node_var.inbound_payments.write().await;
+	let mut invoice = match disk::read_invoice(&*node_var.store, &payment_hash) {
+		Ok(Some(invoice)) => invoice,
+		Ok(None) => {
+			return ApiError::new(
+				ErrorCode::NotFound,
+				format!("no invoice with payment hash {}", req.payment_hash),
+			)
+			.error_response();
+		}
+		Err(e) => {
+			return ApiError::new(ErrorCode::Internal, format!("failed to read invoice: {}", e))
+				.error_response();
+		}
+	};
+	match invoice.state(unix_timestamp()) {
+		InvoiceState::Settled => {
+			return ApiError::new(ErrorCode::Conflict, "invoice is already settled".to_string())
+				.error_response();
+		}
+		InvoiceState::Canceled => {
+			return ApiError::new(ErrorCode::Conflict, "invoice is already canceled".to_string())
+				.error_response();
+		}
+		InvoiceState::Open | InvoiceState::Expired => {}
+	}
+
+	invoice.canceled = true;
+	if let Err(e) = disk::persist_invoice(&*node_var.store, &payment_hash, &invoice) {
+		return ApiError::new(ErrorCode::Internal, format!("failed to persist invoice: {}", e))
+			.error_response();
+	}
+	payments.remove(&payment_hash);
+	if disk::remove_payment(&*node_var.store, disk::INBOUND_PAYMENTS_NAMESPACE, &payment_hash)
+		.is_err()
+	{
+		warn!("failed to remove inbound payment, check your disk and permissions");
+	}
+
+	let msg = ServerSuccess {
+		msg: format!("EVENT: canceled invoice with payment hash {}", req.payment_hash),
+	};
+	HttpResponse::Ok().content_type(ContentType::json()).json(msg)
+}
+
+/// Time a payment is waited for when no timeout is given
+const DEFAULT_PAYMENT_WAIT_SECS: u64 = 60;
+
+/// Longest a payment can be waited for, to avoid tying up the HTTP connection indefinitely
+const MAX_PAYMENT_WAIT_SECS: u64 = 600;
+
+/// Send payment
+#[utoipa::path(
+	post,
+	path = "/v1/payments",
+	request_body = ServerInvoice,
+	responses(
+		(status = 200, description = "Payment initiated, or its result if `wait` is set", body = PaymentResult),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn send_payment(
+	req: web::Json<ServerInvoice>, caller: web::ReqData<Caller>,
+	node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> Result<HttpResponse, ApiError> {
+	let wait = match req.wait.as_deref() {
+		Some("true") => true,
+		Some("false") | None => false,
+		Some(wait) => {
+			return Err(ApiError::new(
+				ErrorCode::InvalidRequest,
+				format!("wait must be true or false: {}", wait),
+			))
+		}
+	};
+	let timeout_secs = parse_timeout_secs(&req)?;
+	let invoice = req
+		.invoice
+		.parse::<Invoice>()
+		.map_err(|e| ApiError::new(ErrorCode::InvalidRequest, format!("invalid invoice: {}", e)))?;
+	let amt_msat = requested_amount(&invoice, &req)?;
+	if node_var.approvals.requires_approval(amt_msat) {
+		let request = PaymentRequest::SendPayment(req.into_inner());
+		return hold_for_approval(&node_var, request, amt_msat, caller.into_inner());
+	}
+	// Subscribe before paying so the outcome can't be missed
+	let mut events = node_var.event_sender.subscribe();
+	let payment = initiate_payment(&node_var, &req, &caller, timeout_secs).await?;
+
+	if wait {
+		let timeout_secs = timeout_secs.unwrap_or(DEFAULT_PAYMENT_WAIT_SECS);
+		return Ok(await_payment(&node_var, &mut events, payment.payment_hash, timeout_secs).await);
+	}
+	let payment_msg = ServerSuccess {
+		msg: format!(
+			"EVENT: initiated sending {} msats to {}, payment_id {}",
+			payment.amt_msat,
+			payment.payee_pubkey,
+			hex_utils::hex_str(&payment.payment_id.0)
+		),
+	};
+	Ok(HttpResponse::Ok().content_type(ContentType::json()).json(payment_msg))
+}
+
+/// Payment initiated by `initiate_payment`, whose outcome is reported by node events
+pub(crate) struct InitiatedPayment {
+	pub payment_hash: PaymentHash,
+	pub payment_id: PaymentId,
+	pub amt_msat: u64,
+	pub payee_pubkey: PublicKey,
+}
+
+/// Fail if the `timeout_secs` of a `sendpayment` request is out of bounds
+fn parse_timeout_secs(req: &ServerInvoice) -> Result<Option<u64>, ApiError> {
+	match &req.timeout_secs {
+		Some(timeout) => match timeout.parse::<u64>() {
+			Ok(timeout) if timeout > 0 && timeout <= MAX_PAYMENT_WAIT_SECS => Ok(Some(timeout)),
+			_ => Err(ApiError::new(
+				ErrorCode::InvalidRequest,
+				format!(
+					"timeout_secs must be between 1 and {}: {}",
+					MAX_PAYMENT_WAIT_SECS, timeout
+				),
+			)),
+		},
+		None => Ok(None),
+	}
+}
+
+/// Amount paid by a `sendpayment` request of `invoice`, which is given by the request if the
+/// invoice doesn't specify one
+pub(crate) fn requested_amount(invoice: &Invoice, req: &ServerInvoice) -> Result<u64, ApiError> {
+	let requested_amt_msat = match &req.amount_msat {
+		Some(amt) => Some(amt.parse::<u64>().map_err(|e| {
+			ApiError::new(ErrorCode::InvalidRequest, format!("couldn't parse amount_msat: {}", e))
+		})?),
+		None => None,
+	};
+
+	match (invoice.amount_milli_satoshis(), requested_amt_msat) {
+		(Some(invoice_amt), Some(amt)) if invoice_amt != amt => Err(ApiError::new(
+			ErrorCode::InvalidRequest,
+			format!("invoice is for {} msats but {} msats were requested", invoice_amt, amt),
+		)),
+		(Some(invoice_amt), _) => Ok(invoice_amt),
+		(None, Some(amt)) => Ok(amt),
+		(None, None) => Err(ApiError::new(
+			ErrorCode::InvalidRequest,
+			"invoice has no amount, an amount_msat must be provided".to_string(),
+		)),
+	}
+}
+
+/// Queue a payment above the approval threshold until an operator approves it, answering the
+/// request with 202 Accepted
+fn hold_for_approval(
+	node_var: &NodeVar<ServerEventHandler>, request: PaymentRequest, amt_msat: u64, caller: Caller,
+) -> Result<HttpResponse, ApiError> {
+	let pending = node_var.approvals.queue(request, amt_msat, caller).map_err(|e| {
+		ApiError::new(ErrorCode::Internal, format!("failed to queue the payment: {}", e))
+	})?;
+	info!(approval_id = %pending.id, amt_msat, "queued a payment for approval");
+	let msg = ServerSuccess {
+		msg: format!(
+			"EVENT: payment of {} msats awaits approval, approval_id {}",
+			amt_msat, pending.id
+		),
+	};
+	Ok(HttpResponse::Accepted().content_type(ContentType::json()).json(msg))
+}
+
+/// Count the payment of `amt_msat` with hash `payment_hash` against the spending limits of
+/// `caller`
+fn charge_payment(
+	node_var: &NodeVar<ServerEventHandler>, caller: &Caller, payment_hash: &PaymentHash,
+	amt_msat: u64,
+) -> Result<(), ApiError> {
+	node_var.api_tokens.charge(caller, payment_hash, amt_msat).map_err(|e| match e {
+		ChargeError::LimitExceeded(msg) => ApiError::new(ErrorCode::Forbidden, msg),
+		ChargeError::Persistence(e) => ApiError::new(
+			ErrorCode::Internal,
+			format!("failed to persist the API token's spending: {}", e),
+		),
+	})
+}
+
+/// Undo the charge of the payment with hash `payment_hash`, which wasn't sent
+fn refund_payment(
+	node_var: &NodeVar<ServerEventHandler>, caller: &Caller, payment_hash: &PaymentHash,
+) {
+	if let Err(e) = node_var.api_tokens.refund(caller, payment_hash) {
+		warn!("failed to persist the API token's spending: {}", e);
+	}
+}
+
+/// Pay the invoice of a `sendpayment` request on behalf of `caller`, within its spending limits,
+/// giving up on retries after `timeout_secs`. The `wait` and `timeout_secs` fields of the request
+/// are left to the caller.
+pub(crate) async fn initiate_payment(
+	node_var: &NodeVar<ServerEventHandler>, req: &ServerInvoice, caller: &Caller,
+	timeout_secs: Option<u64>,
+) -> Result<InitiatedPayment, ApiError> {
+	let invoice = req
+		.invoice
+		.parse::<Invoice>()
+		.map_err(|e| ApiError::new(ErrorCode::InvalidRequest, format!("invalid invoice: {}", e)))?;
+	let invoice_payer = node_var.invoice_payer.clone();
+	let payment_storage = node_var.outbound_payments.clone();
+	let amt_msat = requested_amount(&invoice, req)?;
+
+	let max_fee_msat = match (&req.max_fee_msat, &req.max_fee_percent) {
+		(Some(_), Some(_)) => {
+			return Err(ApiError::new(
+				ErrorCode::InvalidRequest,
+				"only one of max_fee_msat and max_fee_percent can be given".to_string(),
+			));
+		}
+		(Some(max_fee_msat), None) => Some(max_fee_msat.parse::<u64>().map_err(|e| {
+			ApiError::new(ErrorCode::InvalidRequest, format!("couldn't parse max_fee_msat: {}", e))
+		})?),
+		(None, Some(max_fee_percent)) => match max_fee_percent.parse::<f64>() {
+			Ok(percent) if percent >= 0.0 && percent.is_finite() => {
+				Some((amt_msat as f64 * percent / 100.0) as u64)
+			}
+			_ => {
+				return Err(ApiError::new(
+					ErrorCode::InvalidRequest,
+					format!("max_fee_percent must be a non-negative number: {}", max_fee_percent),
+				));
+			}
+		},
+		(None, None) => None,
+	};
+
+	let payment_hash = PaymentHash(invoice.payment_hash().clone().into_inner());
+	charge_payment(node_var, caller, &payment_hash, amt_msat)?;
+	// The limits are enforced by the InvoicePayer's router on the first attempt and every retry
+	let limits = PaymentLimits {
+		max_fee_msat,
+		deadline: timeout_secs.map(|timeout| Instant::now() + Duration::from_secs(timeout)),
+	};
+	node_var.payment_limits.lock().unwrap().insert(payment_hash, limits);
+	let payment_id = match invoice.amount_milli_satoshis() {
+		Some(_) => invoice_payer.pay_invoice(&invoice),
+		None => invoice_payer.pay_zero_value_invoice(&invoice, amt_msat),
+	};
+	if let Err(e) = &payment_id {
+		node_var.payment_limits.lock().unwrap().remove(&payment_hash);
+		// A payment failing to send may have been partially sent, so it stays charged
+		if !matches!(e, PaymentError::Sending(_)) {
+			refund_payment(node_var, caller, &payment_hash);
+		}
+	}
+	let payment_id = payment_id.map_err(|e| match e {
+		PaymentError::Invoice(e) => {
+			ApiError::new(ErrorCode::InvalidRequest, format!("invalid invoice: {}", e))
+		}
+		PaymentError::Routing(e) => {
+			ApiError::new(ErrorCode::NoRoute, format!("failed to find route: {}", e.err))
+		}
+		PaymentError::Sending(e) => {
+			ApiError::new(ErrorCode::PaymentFailed, "failed to send payment".to_string())
+				.with_detail(format!("{:?}", e))
+		}
+	})?;
+
+	let payee_pubkey = invoice.recover_payee_pub_key();
+	let payment_secret = Some(invoice.payment_secret().clone());
+	record_request_id(node_var, payment_hash);
+	let mut payments = payment_storage.write().await;
+	payments.insert(
+		payment_hash,
+		PaymentInfo {
+			preimage: None,
+			secret: payment_secret,
+			status: HTLCStatus::Pending,
+			amt_msat: MillisatAmount(Some(amt_msat)),
+			label: None,
+			payment_id: Some(payment_id),
+			fee_paid_msat: None,
+			created_at: Some(unix_timestamp()),
+			resolved_at: None,
+			paths: Vec::new(),
+			keysend: false,
+		},
+	);
+	if disk::persist_payment(
+		&*node_var.store,
+		disk::OUTBOUND_PAYMENTS_NAMESPACE,
+		&payment_hash,
+		&payments[&payment_hash],
+	)
+	.is_err()
+	{
+		warn!("failed to persist outbound payment, check your disk and permissions");
+	}
+	drop(payments);
+
+	Ok(InitiatedPayment { payment_hash, payment_id, amt_msat, payee_pubkey })
+}
+
+/// Outcome of an outbound payment as recorded by the event handler, if it is resolved
+async fn stored_payment_outcome(
+	node_var: &NodeVar<ServerEventHandler>, payment_hash: &PaymentHash,
+) -> Option<Result<(String, Option<u64>), ()>> {
+	let payments = node_var.outbound_payments.read().await;
+	match payments.get(payment_hash) {
+		Some(PaymentInfo { status: HTLCStatus::Succeeded, preimage: Some(preimage), .. }) => {
+			Some(Ok((hex_utils::hex_str(&preimage.0), None)))
+		}
+		Some(PaymentInfo { status: HTLCStatus::Failed, .. }) => Some(Err(())),
+		_ => None,
+	}
+}
+
+/// Wait for the `PaymentSent` or `PaymentFailed` event of an outbound payment, responding with
+/// its preimage and fee on success
+async fn await_payment(
+	node_var: &NodeVar<ServerEventHandler>, events: &mut broadcast::Receiver<NodeEvent>,
+	payment_hash: PaymentHash, timeout_secs: u64,
+) -> HttpResponse {
+	let payment_hash_hex = hex_utils::hex_str(&payment_hash.0);
+	let outcome = tokio::time::timeout(Duration::from_secs(timeout_secs), async {
+		loop {
+			match events.recv().await {
+				Ok(NodeEvent::PaymentSent { payment_hash, payment_preimage, fee_paid_msat })
+					if payment_hash == payment_hash_hex =>
+				{
+					return Ok((payment_preimage, fee_paid_msat))
+				}
+				Ok(NodeEvent::PaymentFailed { payment_hash })
+					if payment_hash == payment_hash_hex =>
+				{
+					return Err(())
+				}
+				Ok(_) => {}
+				// The outcome may have been among the missed events, so fall back to the
+				// status recorded by the event handler
+				Err(broadcast::error::RecvError::Lagged(_)) => {
+					if let Some(outcome) = stored_payment_outcome(node_var, &payment_hash).await {
+						return outcome;
+					}
+				}
+				Err(broadcast::error::RecvError::Closed) => return Err(()),
+			}
+		}
+	})
+	.await;
+
+	match outcome {
+		Ok(Ok((payment_preimage, fee_paid_msat))) => {
+			let result =
+				PaymentResult { payment_hash: payment_hash_hex, payment_preimage, fee_paid_msat };
+			HttpResponse::Ok().content_type(ContentType::json()).json(result)
+		}
+		Ok(Err(())) => ApiError::new(
+			ErrorCode::PaymentFailed,
+			format!("payment {} failed after exhausting retries", payment_hash_hex),
+		)
+		.error_response(),
+		Err(_) => ApiError::new(
+			ErrorCode::Timeout,
+			format!(
+				"payment {} still pending after {} seconds, check it with lookuppayment",
+				payment_hash_hex, timeout_secs
+			),
+		)
+		.error_response(),
+	}
+}
+
+/// Decode a BOLT11 invoice without paying it
+async fn decode_invoice(req: web::Json<ServerInvoice>) -> HttpResponse {
+	let invoice = match req.invoice.parse::<Invoice>() {
+		Ok(inv) => inv,
+		Err(e) => {
+			return ApiError::new(ErrorCode::InvalidRequest, format!("invalid invoice: {}", e))
+				.error_response();
+		}
+	};
+
+	let (description, description_hash) = match invoice.description() {
+		InvoiceDescription::Direct(desc) => (Some(desc.clone().into_inner()), None),
+		InvoiceDescription::Hash(hash) => (None, Some(hex_utils::hex_str(&hash.0.into_inner()))),
+	};
+
+	let mut route_hints = Vec::new();
+	for hint in invoice.route_hints() {
+		let hops = hint
+			.0
+			.iter()
+			.map(|hop| DecodedRouteHintHop {
+				src_node_id: hex_utils::hex_str(&hop.src_node_id.serialize()),
+				short_channel_id: hop.short_channel_id,
+				fee_base_msat: hop.fees.base_msat,
+				fee_proportional_millionths: hop.fees.proportional_millionths,
+				cltv_expiry_delta: hop.cltv_expiry_delta,
+				htlc_minimum_msat: hop.htlc_minimum_msat,
+				htlc_maximum_msat: hop.htlc_maximum_msat,
+			})
+			.collect();
+		route_hints.push(hops);
+	}
+
+	let timestamp = invoice
+		.timestamp()
+		.duration_since(SystemTime::UNIX_EPOCH)
+		.map(|since_epoch| since_epoch.as_secs())
+		.unwrap_or(0);
+
+	let decoded = DecodedInvoice {
+		destination: hex_utils::hex_str(&invoice.recover_payee_pub_key().serialize()),
+		amount_msat: invoice.amount_milli_satoshis(),
+		payment_hash: hex_utils::hex_str(&invoice.payment_hash().into_inner()),
+		timestamp,
+		expiry_secs: invoice.expiry_time().as_secs(),
+		is_expired: invoice.is_expired(),
+		description,
+		description_hash,
+		min_final_cltv_expiry: invoice.min_final_cltv_expiry(),
+		route_hints,
+	};
+	HttpResponse::Ok().content_type(ContentType::json()).json(decoded)
+}
+
+/// Describe each path of `route` hop by hop, for HTLCs sent at `best_block_height`
+pub(crate) fn route_info(route: &Route, best_block_height: u32) -> Vec<RouteInfo> {
+	let mut routes = Vec::new();
+	for path in &route.paths {
+		// Each hop's `fee_msat` is its node's fee for forwarding over the next hop, except the last
+		// hop's, which is the amount paid, and likewise for `cltv_expiry_delta`
+		let mut hops = Vec::new();
+		let mut amt_to_forward_msat = 0;
+		let mut expiry = best_block_height;
+		for (idx, hop) in path.iter().enumerate().rev() {
+			let (fee_msat, cltv_expiry_delta) = if idx == path.len() - 1 {
+				amt_to_forward_msat = hop.fee_msat;
+				expiry += hop.cltv_expiry_delta;
+				(0, 0)
+			} else {
+				(hop.fee_msat, hop.cltv_expiry_delta)
+			};
+			hops.push(RouteHopInfo {
+				pubkey: hop.pubkey,
+				short_channel_id: hop.short_channel_id,
+				amt_to_forward_msat,
+				fee_msat,
+				expiry,
+			});
+			amt_to_forward_msat += fee_msat;
+			expiry += cltv_expiry_delta;
+		}
+		hops.reverse();
+		let total_amt_msat = path.iter().map(|hop| hop.fee_msat).sum::<u64>();
+		let amt_msat = path.last().map(|hop| hop.fee_msat).unwrap_or(0);
+		routes.push(RouteInfo {
+			total_amt_msat,
+			total_fees_msat: total_amt_msat - amt_msat,
+			total_time_lock: expiry,
+			hops,
+		});
+	}
+	routes
+}
+
+/// Find a route paying `amt_msat` to a node with LDK's router and scorer, without paying it
+async fn query_routes(
+	req: web::Json<QueryRoutes>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	let bad_request = |msg: String| ApiError::new(ErrorCode::InvalidRequest, msg).error_response();
+	let dest_pubkey = match hex_utils::to_compressed_pubkey(req.dest_pubkey.as_str()) {
+		Some(pk) => pk,
+		None => return bad_request("couldn't parse destination pubkey".to_string()),
+	};
+	let amt_msat: u64 = match req.amt_msat.parse() {
+		Ok(amt) => amt,
+		Err(e) => return bad_request(format!("couldn't parse amt_msat: {}", e)),
+	};
+	let max_fee_msat: Option<u64> = match &req.max_fee_msat {
+		Some(max_fee_msat) => match max_fee_msat.parse() {
+			Ok(max_fee_msat) => Some(max_fee_msat),
+			Err(e) => return bad_request(format!("couldn't parse max_fee_msat: {}", e)),
+		},
+		None => None,
+	};
+
+	let route_params = RouteParameters {
+		payment_params: PaymentParameters::from_node_id(dest_pubkey),
+		final_value_msat: amt_msat,
+		final_cltv_expiry_delta: 40,
+	};
+	let first_hops = node_var.channel_manager.list_usable_channels();
+	// The payment hash is only used to log the search
+	let route = payment::Router::find_route(
+		&*node_var.router,
+		&node_var.channel_manager.get_our_node_id(),
+		&route_params,
+		&PaymentHash([0; 32]),
+		Some(&first_hops.iter().collect::<Vec<_>>()),
+		&*node_var.scorer.lock().unwrap(),
+	);
+	let route = match route {
+		Ok(route) => route,
+		Err(e) => {
+			return ApiError::new(ErrorCode::NoRoute, format!("failed to find route: {}", e.err))
+				.error_response();
+		}
+	};
+	let routes = route_info(&route, node_var.channel_manager.current_best_block().height());
+	let total_fees_msat = routes.iter().map(|route| route.total_fees_msat).sum::<u64>();
+	if let Some(max_fee_msat) = max_fee_msat {
+		if total_fees_msat > max_fee_msat {
+			return ApiError::new(
+				ErrorCode::NoRoute,
+				format!(
+					"the cheapest route found costs {} msat in fees, more than the maximum of {} msat",
+					total_fees_msat, max_fee_msat
+				),
+			)
+			.error_response();
+		}
+	}
+	HttpResponse::Ok().content_type(ContentType::json()).json(QueriedRoutes { routes })
+}
+
+/// Parse a short channel ID given as a number or as `<block>x<tx>x<output>`
+fn parse_short_channel_id(scid: &str) -> Option<u64> {
+	let parts: Vec<&str> = scid.split('x').collect();
+	match parts[..] {
+		[scid] => scid.parse().ok(),
+		[block, tx, output] => {
+			let block: u64 = block.parse().ok()?;
+			let tx: u64 = tx.parse().ok()?;
+			let output: u64 = output.parse().ok()?;
+			if block >= 1 << 24 || tx >= 1 << 24 || output >= 1 << 16 {
+				return None;
+			}
+			Some(block << 40 | tx << 16 | output)
+		}
+		_ => None,
+	}
+}
+
+/// A hop of a manually specified route, with the forwarding policy its channel's source node
+/// announced, unless the channel is one of ours
+struct ManualHop {
+	short_channel_id: u64,
+	pubkey: PublicKey,
+	node_features: NodeFeatures,
+	channel_features: ChannelFeatures,
+	/// Base fee, proportional fee and CLTV delta of the forward into the hop
+	policy: Option<(u32, u32, u16)>,
+}
+
+/// Build a route paying `amt_msat` over `hops`, each given by the short channel ID of the
+/// channel to take or the public key of the node it leads to. The first hop is one of the
+/// node's usable channels, the others are looked up in the network graph.
+fn build_route(
+	node_var: &NodeVar<ServerEventHandler>, hops: &[String], amt_msat: u64,
+	final_cltv_expiry_delta: u32,
+) -> Result<Route, String> {
+	if hops.is_empty() {
+		return Err("a route needs at least one hop".to_string());
+	}
+	let first_hops = node_var.channel_manager.list_usable_channels();
+	let graph = node_var.network_graph.read_only();
+	let node_features = |pubkey: &PublicKey| match graph.nodes().get(&NodeId::from_pubkey(pubkey)) {
+		Some(node) => match &node.announcement_info {
+			Some(announcement) => announcement.features.clone(),
+			None => NodeFeatures::known(),
+		},
+		// Unannounced nodes are assumed to support what LDK does, like LDK's router assumes
+		None => NodeFeatures::known(),
+	};
+
+	let mut manual_hops: Vec<ManualHop> = Vec::new();
+	let mut prev_pubkey = node_var.channel_manager.get_our_node_id();
+	for hop in hops {
+		let scid = parse_short_channel_id(hop);
+		let pubkey = hex_utils::to_compressed_pubkey(hop);
+		if scid.is_none() && pubkey.is_none() {
+			return Err(format!("hop {} is neither a short channel ID nor a public key", hop));
+		}
+		let (short_channel_id, hop_pubkey, policy) = if manual_hops.is_empty() {
+			let channel = first_hops.iter().find(|channel| match (scid, pubkey) {
+				(Some(scid), _) => channel.short_channel_id == Some(scid),
+				(None, Some(pubkey)) => channel.counterparty.node_id == pubkey,
+				(None, None) => false,
+			});
+			match channel {
+				Some(channel) => {
+					(channel.short_channel_id.unwrap(), channel.counterparty.node_id, None)
+				}
+				None => return Err(format!("no usable channel of the node matches hop {}", hop)),
+			}
+		} else {
+			let prev_node_id = NodeId::from_pubkey(&prev_pubkey);
+			let scids: Vec<u64> = match scid {
+				Some(scid) => vec![scid],
+				None => match graph.nodes().get(&prev_node_id) {
+					Some(node) => node.channels.clone(),
+					None => Vec::new(),
+				},
+			};
+			let channel = scids.iter().find_map(|scid| {
+				let channel = graph.channels().get(scid)?;
+				let (other_node_id, policy) = if channel.node_one == prev_node_id {
+					(channel.node_two, &channel.one_to_two)
+				} else if channel.node_two == prev_node_id {
+					(channel.node_one, &channel.two_to_one)
+				} else {
+					return None;
+				};
+				let other_pubkey = PublicKey::from_slice(other_node_id.as_slice()).ok()?;
+				match pubkey {
+					Some(pubkey) if pubkey != other_pubkey => None,
+					_ => Some((*scid, other_pubkey, policy)),
+				}
+			});
+			match channel {
+				Some((short_channel_id, hop_pubkey, Some(policy))) => (
+					short_channel_id,
+					hop_pubkey,
+					Some((
+						policy.fees.base_msat,
+						policy.fees.proportional_millionths,
+						policy.cltv_expiry_delta,
+					)),
+				),
+				Some((short_channel_id, _, None)) => {
+					return Err(format!(
+						"no channel update of {} is known for channel {}",
+						prev_pubkey, short_channel_id
+					))
+				}
+				None => {
+					return Err(format!("no known channel of {} matches hop {}", prev_pubkey, hop))
+				}
+			}
+		};
+		let channel_features = match graph.channels().get(&short_channel_id) {
+			Some(channel) => channel.features.clone(),
+			None => ChannelFeatures::empty(),
+		};
+		manual_hops.push(ManualHop {
+			short_channel_id,
+			pubkey: hop_pubkey,
+			node_features: node_features(&hop_pubkey),
+			channel_features,
+			policy,
+		});
+		prev_pubkey = hop_pubkey;
+	}
+
+	// Each hop's fee and CLTV delta are those of the next hop's forward, or the amount paid and
+	// the final CLTV delta for the last hop, so the route is built from the destination
+	let mut path = Vec::with_capacity(manual_hops.len());
+	let mut amt_to_forward_msat = amt_msat;
+	for idx in (0..manual_hops.len()).rev() {
+		let (fee_msat, cltv_expiry_delta) = match manual_hops.get(idx + 1) {
+			Some(next_hop) => {
+				let (base_msat, proportional_millionths, cltv_expiry_delta) =
+					next_hop.policy.unwrap();
+				let fee_msat = base_msat as u64
+					+ amt_to_forward_msat * proportional_millionths as u64 / 1_000_000;
+				(fee_msat, cltv_expiry_delta as u32)
+			}
+			None => (amt_msat, final_cltv_expiry_delta),
+		};
+		let hop = &manual_hops[idx];
+		path.push(RouteHop {
+			pubkey: hop.pubkey,
+			node_features: hop.node_features.clone(),
+			short_channel_id: hop.short_channel_id,
+			channel_features: hop.channel_features.clone(),
+			fee_msat,
+			cltv_expiry_delta,
+		});
+		if idx + 1 < manual_hops.len() {
+			amt_to_forward_msat += fee_msat;
+		}
+	}
+	path.reverse();
+	Ok(Route {
+		paths: vec![path],
+		payment_params: Some(PaymentParameters::from_node_id(prev_pubkey)),
+	})
+}
+
+/// Send a payment over a manually specified route, e.g. to rebalance channels
+async fn send_to_route(
+	req: web::Json<SendToRoute>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	let bad_request = |msg: String| ApiError::new(ErrorCode::InvalidRequest, msg).error_response();
+	let payment_hash = match hex_utils::to_vec(&req.payment_hash) {
+		Some(hash) if hash.len() == 32 => {
+			let mut payment_hash = [0; 32];
+			payment_hash.copy_from_slice(&hash);
+			PaymentHash(payment_hash)
+		}
+		_ => return bad_request(format!("invalid payment hash: {}", req.payment_hash)),
+	};
+	let payment_secret = match &req.payment_secret {
+		Some(secret) => match hex_utils::to_vec(secret) {
+			Some(bytes) if bytes.len() == 32 => {
+				let mut payment_secret = [0; 32];
+				payment_secret.copy_from_slice(&bytes);
+				Some(PaymentSecret(payment_secret))
+			}
+			_ => return bad_request(format!("invalid payment secret: {}", secret)),
+		},
+		None => None,
+	};
+	let amt_msat: u64 = match req.amt_msat.parse() {
+		Ok(amt) => amt,
+		Err(e) => return bad_request(format!("couldn't parse amt_msat: {}", e)),
+	};
+
+	let route = match build_route(&node_var, &req.hops, amt_msat, 40) {
+		Ok(route) => route,
+		Err(e) => return bad_request(e),
+	};
+	send_route(&node_var, &route, payment_hash, payment_secret, amt_msat, None).await
+}
+
+/// Remember the request initiating the outbound payment `payment_hash`, so that the logs of the
+/// payment's events carry its ID
+fn record_request_id(node_var: &NodeVar<ServerEventHandler>, payment_hash: PaymentHash) {
+	if let Some(request_id) = logging::current_request_id() {
+		node_var.request_ids.lock().unwrap().insert(payment_hash, request_id);
+	}
+}
+
+/// Send a payment over `route` and track it as a pending outbound payment
+async fn send_route(
+	node_var: &NodeVar<ServerEventHandler>, route: &Route, payment_hash: PaymentHash,
+	payment_secret: Option<PaymentSecret>, amt_msat: u64, label: Option<String>,
+) -> HttpResponse {
+	let payment_id =
+		match node_var.channel_manager.send_payment(route, payment_hash, &payment_secret) {
+			Ok(payment_id) => payment_id,
+			Err(e) => {
+				return ApiError::new(
+					ErrorCode::PaymentFailed,
+					"failed to send payment".to_string(),
+				)
+				.with_detail(format!("{:?}", e))
+				.error_response();
+			}
+		};
+
+	record_request_id(node_var, payment_hash);
+	let mut payments = node_var.outbound_payments.write().await;
+	payments.insert(
+		payment_hash,
+		PaymentInfo {
+			preimage: None,
+			secret: payment_secret,
+			status: HTLCStatus::Pending,
+			amt_msat: MillisatAmount(Some(amt_msat)),
+			label,
+			payment_id: Some(payment_id),
+			fee_paid_msat: None,
+			created_at: Some(unix_timestamp()),
+			resolved_at: None,
+			paths: Vec::new(),
+			keysend: false,
+		},
+	);
+	if disk::persist_payment(
+		&*node_var.store,
+		disk::OUTBOUND_PAYMENTS_NAMESPACE,
+		&payment_hash,
+		&payments[&payment_hash],
+	)
+	.is_err()
+	{
+		warn!("failed to persist outbound payment, check your disk and permissions");
+	}
+	let sent = SentToRoute {
+		payment_hash: hex_utils::hex_str(&payment_hash.0),
+		payment_id: hex_utils::hex_str(&payment_id.0),
+		route: route_info(route, node_var.channel_manager.current_best_block().height()).remove(0),
+	};
+	HttpResponse::Ok().content_type(ContentType::json()).json(sent)
+}
+
+/// Move `amt_msat` of liquidity from one of the node's channels to another by paying the node
+/// itself, out through the first channel and back in through the second. The route between
+/// the two peers is found by LDK's router, and the payment is only sent if its fees fit in
+/// `max_fee_msat`.
+async fn rebalance(
+	req: web::Json<Rebalance>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	let bad_request = |msg: String| ApiError::new(ErrorCode::InvalidRequest, msg).error_response();
+	let (out_scid, in_scid) =
+		match (parse_short_channel_id(&req.out_scid), parse_short_channel_id(&req.in_scid)) {
+			(Some(out_scid), Some(in_scid)) if out_scid != in_scid => (out_scid, in_scid),
+			(Some(_), Some(_)) => {
+				return bad_request("the payment has to come back through another channel".into())
+			}
+			_ => return bad_request("invalid short channel ID".to_string()),
+		};
+	let amt_msat: u64 = match req.amt_msat.parse() {
+		Ok(amt) => amt,
+		Err(e) => return bad_request(format!("couldn't parse amt_msat: {}", e)),
+	};
+	let max_fee_msat: u64 = match req.max_fee_msat.parse() {
+		Ok(max_fee_msat) => max_fee_msat,
+		Err(e) => return bad_request(format!("couldn't parse max_fee_msat: {}", e)),
+	};
+
+	let our_node_id = node_var.channel_manager.get_our_node_id();
+	let channels = node_var.channel_manager.list_channels();
+	let peer_of = |scid: u64| {
+		channels
+			.iter()
+			.find(|channel| channel.short_channel_id == Some(scid))
+			.map(|channel| channel.counterparty.node_id)
+	};
+	let (out_peer, in_peer) = match (peer_of(out_scid), peer_of(in_scid)) {
+		(Some(out_peer), Some(in_peer)) => (out_peer, in_peer),
+		_ => return bad_request("both channels have to be channels of the node".to_string()),
+	};
+
+	// The hops between the two peers, if the channels aren't with the same peer
+	let mut hops = vec![out_scid.to_string()];
+	if out_peer != in_peer {
+		let route_params = RouteParameters {
+			payment_params: PaymentParameters::from_node_id(in_peer),
+			final_value_msat: amt_msat,
+			final_cltv_expiry_delta: 40,
+		};
+		let route = payment::Router::find_route(
+			&*node_var.router,
+			&out_peer,
+			&route_params,
+			&PaymentHash([0; 32]),
+			None,
+			&*node_var.scorer.lock().unwrap(),
+		);
+		let path = match route {
+			Ok(mut route) => route.paths.remove(0),
+			Err(e) => {
+				return ApiError::new(
+					ErrorCode::NoRoute,
+					format!("failed to find route from {} to {}: {}", out_peer, in_peer, e.err),
+				)
+				.error_response();
+			}
+		};
+		// LDK doesn't send payments which go through the node more than once
+		if path.iter().any(|hop| hop.pubkey == our_node_id) {
+			return ApiError::new(ErrorCode::NoRoute, format!("the only route found from {} to {} goes through this node, try another pair of channels", out_peer, in_peer)).error_response();
+		}
+		hops.extend(path.iter().map(|hop| hop.short_channel_id.to_string()));
+	}
+	hops.push(in_scid.to_string());
+
+	let route = match build_route(&node_var, &hops, amt_msat, 40) {
+		Ok(route) => route,
+		Err(e) => return bad_request(e),
+	};
+	let total_fees_msat = route.paths[0].iter().map(|hop| hop.fee_msat).sum::<u64>() - amt_msat;
+	if total_fees_msat > max_fee_msat {
+		return ApiError::new(
+			ErrorCode::NoRoute,
+			format!(
+				"the route found costs {} msat in fees, more than the maximum of {} msat",
+				total_fees_msat, max_fee_msat
+			),
+		)
+		.error_response();
+	}
+
+	let (payment_hash, payment_secret) =
+		match node_var.channel_manager.create_inbound_payment(Some(amt_msat), 3600) {
+			Ok(payment) => payment,
+			Err(()) => {
+				return ApiError::new(
+					ErrorCode::InvalidRequest,
+					format!("invalid rebalance amount: {}", amt_msat),
+				)
+				.error_response()
+			}
+		};
+	{
+		let mut payments = node_var.inbound_payments.write().await;
+		payments.insert(
+			payment_hash,
+			PaymentInfo {
+				preimage: None,
+				secret: Some(payment_secret),
+				status: HTLCStatus::Pending,
+				amt_msat: MillisatAmount(Some(amt_msat)),
+				label: Some("rebalance".to_string()),
+				payment_id: None,
+				fee_paid_msat: None,
+				created_at: Some(unix_timestamp()),
+				resolved_at: None,
+				paths: Vec::new(),
+				keysend: false,
+			},
+		);
+		if disk::persist_payment(
+			&*node_var.store,
+			disk::INBOUND_PAYMENTS_NAMESPACE,
+			&payment_hash,
+			&payments[&payment_hash],
+		)
+		.is_err()
+		{
+			warn!("failed to persist inbound payment, check your disk and permissions");
+		}
+	}
+	send_route(
+		&node_var,
+		&route,
+		payment_hash,
+		Some(payment_secret),
+		amt_msat,
+		Some("rebalance".to_string()),
+	)
+	.await
+}
+
+/// Time a probe is waited for before giving up on its outcome
+const PROBE_TIMEOUT_SECS: u64 = 60;
+
+/// Test whether a route can carry `amt_msat` to a node by sending it a payment with a random
+/// payment hash. No node knows its preimage, so the probe can only fail, at the destination if
+/// the route had the liquidity. Its outcome is fed into the scorer either way.
+async fn probe(
+	req: web::Json<Probe>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	let bad_request = |msg: String| ApiError::new(ErrorCode::InvalidRequest, msg).error_response();
+	let dest_pubkey = match hex_utils::to_compressed_pubkey(req.dest_pubkey.as_str()) {
+		Some(pk) => pk,
+		None => return bad_request("couldn't parse destination pubkey".to_string()),
+	};
+	let amt_msat: u64 = match req.amt_msat.parse() {
+		Ok(amt) => amt,
+		Err(e) => return bad_request(format!("couldn't parse amt_msat: {}", e)),
+	};
+
+	let payment_hash = PaymentHash(node_var.keys_manager.get_secure_random_bytes());
+	let payment_secret = PaymentSecret(node_var.keys_manager.get_secure_random_bytes());
+	let route_params = RouteParameters {
+		payment_params: PaymentParameters::from_node_id(dest_pubkey),
+		final_value_msat: amt_msat,
+		final_cltv_expiry_delta: 40,
+	};
+	let first_hops = node_var.channel_manager.list_usable_channels();
+	let route = payment::Router::find_route(
+		&*node_var.router,
+		&node_var.channel_manager.get_our_node_id(),
+		&route_params,
+		&payment_hash,
+		Some(&first_hops.iter().collect::<Vec<_>>()),
+		&*node_var.scorer.lock().unwrap(),
+	);
+	let route = match route {
+		Ok(route) => route,
+		Err(e) => {
+			return ApiError::new(ErrorCode::NoRoute, format!("failed to find route: {}", e.err))
+				.error_response();
+		}
+	};
+
+	// Subscribe before probing so the outcome can't be missed
+	let mut events = node_var.event_sender.subscribe();
+	node_var.probes.lock().unwrap().insert(payment_hash);
+	if let Err(e) =
+		node_var.channel_manager.send_payment(&route, payment_hash, &Some(payment_secret))
+	{
+		node_var.probes.lock().unwrap().remove(&payment_hash);
+		return ApiError::new(ErrorCode::PaymentFailed, "failed to send probe".to_string())
+			.with_detail(format!("{:?}", e))
+			.error_response();
+	}
+
+	let payment_hash_hex = hex_utils::hex_str(&payment_hash.0);
+	let outcome = tokio::time::timeout(Duration::from_secs(PROBE_TIMEOUT_SECS), async {
+		loop {
+			match events.recv().await {
+				Ok(NodeEvent::PaymentPathFailed {
+					payment_hash,
+					rejected_by_dest,
+					all_paths_failed,
+					short_channel_id,
+				}) if payment_hash == payment_hash_hex => {
+					if rejected_by_dest {
+						return Some((true, None));
+					}
+					if all_paths_failed {
+						return Some((false, short_channel_id));
+					}
+				}
+				Ok(_) => {}
+				Err(broadcast::error::RecvError::Lagged(_)) => {}
+				Err(broadcast::error::RecvError::Closed) => return None,
+			}
+		}
+	})
+	.await;
+	node_var.probes.lock().unwrap().remove(&payment_hash);
+
+	match outcome {
+		Ok(Some((reached_destination, failed_short_channel_id))) => {
+			let result = ProbeResult {
+				payment_hash: payment_hash_hex,
+				reached_destination,
+				failed_short_channel_id,
+				route: route_info(&route, node_var.channel_manager.current_best_block().height())
+					.remove(0),
+			};
+			HttpResponse::Ok().content_type(ContentType::json()).json(result)
+		}
+		_ => ApiError::new(
+			ErrorCode::Timeout,
+			format!(
+				"probe {} didn't resolve within {} seconds",
+				payment_hash_hex, PROBE_TIMEOUT_SECS
+			),
+		)
+		.error_response(),
+	}
+}
+
+/// Send a spontaneous (keysend) payment
+async fn keysend(
+	req: web::Json<Keysend>, caller: web::ReqData<Caller>,
+	node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> Result<HttpResponse, ApiError> {
+	let amt_msat = keysend_amount(&req)?;
+	if node_var.approvals.requires_approval(amt_msat) {
+		let request = PaymentRequest::Keysend(req.into_inner());
+		return hold_for_approval(&node_var, request, amt_msat, caller.into_inner());
+	}
+	let payment = send_keysend(&node_var, &req, &caller).await?;
+	let payment_msg = ServerSuccess {
+		msg: format!(
+			"EVENT: initiated sending {} msats to {}, payment_id {}",
+			payment.amt_msat,
+			payment.payee_pubkey,
+			hex_utils::hex_str(&payment.payment_id.0)
+		),
+	};
+	Ok(HttpResponse::Ok().content_type(ContentType::json()).json(payment_msg))
+}
+
+fn keysend_amount(req: &Keysend) -> Result<u64, ApiError> {
+	req.amt_msat.parse().map_err(|e| {
+		ApiError::new(ErrorCode::InvalidRequest, format!("couldn't parse amt_msat: {}", e))
+	})
+}
+
+/// Send the keysend payment of a `keysend` request on behalf of `caller`, within its spending
+/// limits
+async fn send_keysend(
+	node_var: &NodeVar<ServerEventHandler>, req: &Keysend, caller: &Caller,
+) -> Result<InitiatedPayment, ApiError> {
+	let payee_pubkey = hex_utils::to_compressed_pubkey(req.pubkey.as_str()).ok_or_else(|| {
+		ApiError::new(ErrorCode::InvalidRequest, "couldn't parse destination pubkey".to_string())
+	})?;
+	let amt_msat = keysend_amount(req)?;
+
+	let payment_preimage = node_var.keys_manager.get_secure_random_bytes();
+	let payment_hash = PaymentHash(Sha256::hash(&payment_preimage).into_inner());
+	charge_payment(node_var, caller, &payment_hash, amt_msat)?;
+	let payment_id = match node_var.invoice_payer.pay_pubkey(
+		payee_pubkey,
+		PaymentPreimage(payment_preimage),
+		amt_msat,
+		40,
+	) {
+		Ok(payment_id) => payment_id,
+		Err(PaymentError::Invoice(e)) => {
+			refund_payment(node_var, caller, &payment_hash);
+			return Err(ApiError::new(ErrorCode::PaymentFailed, format!("invalid payee: {}", e)));
+		}
+		Err(PaymentError::Routing(e)) => {
+			refund_payment(node_var, caller, &payment_hash);
+			return Err(ApiError::new(
+				ErrorCode::NoRoute,
+				format!("failed to find route: {}", e.err),
+			));
+		}
+		Err(PaymentError::Sending(e)) => {
+			// The payment may have been partially sent, so keep track of it as failed, and
+			// charged to the caller
+			let mut payments = node_var.outbound_payments.write().await;
+			payments.insert(
+				payment_hash,
+				PaymentInfo {
+					preimage: Some(PaymentPreimage(payment_preimage)),
+					secret: None,
+					status: HTLCStatus::Failed,
+					amt_msat: MillisatAmount(Some(amt_msat)),
+					label: None,
+					payment_id: None,
+					fee_paid_msat: None,
+					created_at: Some(unix_timestamp()),
+					resolved_at: Some(unix_timestamp()),
+					paths: Vec::new(),
+					keysend: false,
+				},
+			);
+			if disk::persist_payment(
+				&*node_var.store,
+				disk::OUTBOUND_PAYMENTS_NAMESPACE,
+				&payment_hash,
+				&payments[&payment_hash],
+			)
+			.is_err()
+			{
+				warn!("failed to persist outbound payment, check your disk and permissions");
+			}
+			return Err(ApiError::new(
+				ErrorCode::PaymentFailed,
+				"failed to send payment".to_string(),
+			)
+			.with_detail(format!("{:?}", e)));
+		}
+	};
+
+	record_request_id(node_var, payment_hash);
+	// The preimage was generated here, so it is kept as proof of payment from the start
+	let mut payments = node_var.outbound_payments.write().await;
+	payments.insert(
+		payment_hash,
+		PaymentInfo {
+			preimage: Some(PaymentPreimage(payment_preimage)),
+			secret: None,
+			status: HTLCStatus::Pending,
+			amt_msat: MillisatAmount(Some(amt_msat)),
+			label: None,
+			payment_id: Some(payment_id),
+			fee_paid_msat: None,
+			created_at: Some(unix_timestamp()),
+			resolved_at: None,
+			paths: Vec::new(),
+			keysend: false,
+		},
+	);
+	if disk::persist_payment(
+		&*node_var.store,
+		disk::OUTBOUND_PAYMENTS_NAMESPACE,
+		&payment_hash,
+		&payments[&payment_hash],
+	)
+	.is_err()
+	{
+		warn!("failed to persist outbound payment, check your disk and permissions");
+	}
+	Ok(InitiatedPayment { payment_hash, payment_id, amt_msat, payee_pubkey })
+}
+
+/// Payments are paged through at most this many at a time
+const MAX_LIST_PAYMENTS_LIMIT: usize = 1000;
+
+/// List payments matching the filters of the request, ordered by creation time and then by
+/// payment hash, so that pages are stable as new payments are made
+async fn list_payments(
+	req: web::Json<ListPayments>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	match payment_list(&node_var, &req).await {
+		Ok(payments) => HttpResponse::Ok().content_type(ContentType::json()).json(payments),
+		Err(error) => error.error_response(),
+	}
+}
+
+/// Find the payments matching the filters of a `listpayments` request
+pub(crate) async fn payment_list(
+	node_var: &NodeVar<ServerEventHandler>, req: &ListPayments,
+) -> Result<Payments, ApiError> {
+	let bad_request = |msg: String| ApiError::new(ErrorCode::InvalidRequest, msg);
+	let direction = match req.direction.as_deref() {
+		Some(direction @ ("inbound" | "outbound")) => Some(direction),
+		Some(direction) => {
+			return Err(bad_request(format!(
+				"direction must be inbound or outbound: {}",
+				direction
+			)))
+		}
+		None => None,
+	};
+	let status = match req.status.as_deref() {
+		Some(status @ ("pending" | "succeeded" | "failed")) => Some(status),
+		Some(status) => {
+			return Err(bad_request(format!(
+				"status must be pending, succeeded or failed: {}",
+				status
+			)))
+		}
+		None => None,
+	};
+	let parse_number = |field: &str, value: &Option<String>| match value {
+		Some(value) => value
+			.parse::<u64>()
+			.map(Some)
+			.map_err(|_| format!("{} must be a number: {}", field, value)),
+		None => Ok(None),
+	};
+	let (start_time, end_time, offset, limit) = match (
+		parse_number("start_time", &req.start_time),
+		parse_number("end_time", &req.end_time),
+		parse_number("offset", &req.offset),
+		parse_number("limit", &req.limit),
+	) {
+		(Ok(start_time), Ok(end_time), Ok(offset), Ok(limit)) => {
+			(start_time, end_time, offset.unwrap_or(0) as usize, limit.map(|limit| limit as usize))
+		}
+		(Err(e), _, _, _) | (_, Err(e), _, _) | (_, _, Err(e), _) | (_, _, _, Err(e)) => {
+			return Err(bad_request(e))
+		}
+	};
+	let limit = match limit {
+		Some(limit) if limit == 0 || limit > MAX_LIST_PAYMENTS_LIMIT => {
+			return Err(bad_request(format!(
+				"limit must be between 1 and {}: {}",
+				MAX_LIST_PAYMENTS_LIMIT, limit
+			)))
+		}
+		Some(limit) => limit,
+		None => MAX_LIST_PAYMENTS_LIMIT,
+	};
+
+	let inbound = node_var.inbound_payments.read().await;
+	let outbound = node_var.outbound_payments.read().await;
+
+	// 1. create payments vector
+	let mut payments_vec: Vec<Payment> = Vec::new();
+	// 2. loop through inbound and outbound payments and append the matching payments to vec
+	for (payments_direction, payments) in
+		[("inbound", inbound.deref()), ("outbound", outbound.deref())]
+	{
+		if direction.map_or(false, |direction| direction != payments_direction) {
+			continue;
+		}
+		for (payment_hash, payment_info) in payments {
+			// Payments recorded before they were timestamped only match unbounded time ranges
+			let in_time_range = match payment_info.created_at {
+				Some(created_at) => {
+					start_time.map_or(true, |start| created_at >= start)
+						&& end_time.map_or(true, |end| created_at <= end)
+				}
+				None => start_time.is_none() && end_time.is_none(),
+			};
+			if !in_time_range {
+				continue;
+			}
+			let payment = Payment {
+				amount_millisatoshis: format!("{}", payment_info.amt_msat),
+				payment_hash: hex_utils::hex_str(&payment_hash.0),
+				htlc_direction: payments_direction.to_string(),
+				htlc_status: match payment_info.status {
+					HTLCStatus::Pending => "pending".to_string(),
+					HTLCStatus::Succeeded => "succeeded".to_string(),
+					HTLCStatus::Failed => "failed".to_string(),
+				},
+				label: payment_info.label.clone(),
+				fee_paid_msat: payment_info.fee_paid_msat,
+				created_at: payment_info.created_at,
+				resolved_at: payment_info.resolved_at,
+				paths: payment_info.paths.clone(),
+				keysend: payment_info.keysend,
+			};
+			if status.map_or(false, |status| status != payment.htlc_status) {
+				continue;
+			}
+			payments_vec.push(payment);
+		}
+	}
+	// Most recent payments last, older records without a timestamp first
+	payments_vec.sort_by(|a, b| {
+		(a.created_at, &a.payment_hash, &a.htlc_direction).cmp(&(
+			b.created_at,
+			&b.payment_hash,
+			&b.htlc_direction,
+		))
+	});
+	let total_payments = payments_vec.len();
+	let payments_vec = payments_vec.into_iter().skip(offset).take(limit).collect();
+	Ok(Payments { payments: payments_vec, total_payments })
+}
+
+/// Look up an inbound or outbound payment by its payment hash
+async fn lookup_payment(
+	req: web::Json<LookupPayment>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	let payment_hash = match hex_utils::to_vec(req.payment_hash.as_str()) {
+		Some(bytes) if bytes.len() == 32 => {
+			let mut payment_hash = [0; 32];
+			payment_hash.copy_from_slice(&bytes);
+			PaymentHash(payment_hash)
+		}
+		_ => {
+			return ApiError::new(ErrorCode::InvalidRequest, "invalid payment hash".to_string())
+				.error_response();
+		}
+	};
+
+	for (direction, payments) in
+		[("inbound", &node_var.inbound_payments), ("outbound", &node_var.outbound_payments)]
+	{
+		let payments = payments.read().await;
+		if let Some(payment_info) = payments.get(&payment_hash) {
+			let details = payment_details(&payment_hash, direction, payment_info);
+			return HttpResponse::Ok().content_type(ContentType::json()).json(details);
+		}
+	}
+
+	ApiError::new(ErrorCode::NotFound, format!("no payment with hash {}", req.payment_hash))
+		.error_response()
+}
+
+fn payment_details(
+	payment_hash: &PaymentHash, direction: &str, payment_info: &PaymentInfo,
+) -> PaymentDetails {
+	PaymentDetails {
+		payment_hash: hex_utils::hex_str(&payment_hash.0),
+		payment_id: payment_info.payment_id.map(|payment_id| hex_utils::hex_str(&payment_id.0)),
+		htlc_direction: direction.to_string(),
+		htlc_status: match payment_info.status {
+			HTLCStatus::Pending => "pending".to_string(),
+			HTLCStatus::Succeeded => "succeeded".to_string(),
+			HTLCStatus::Failed => "failed".to_string(),
+		},
+		amount_millisatoshis: format!("{}", payment_info.amt_msat),
+		preimage: match payment_info.status {
+			HTLCStatus::Succeeded => {
+				payment_info.preimage.map(|preimage| hex_utils::hex_str(&preimage.0))
+			}
+			_ => None,
+		},
+		label: payment_info.label.clone(),
+		keysend: payment_info.keysend,
+	}
+}
+
+fn parse_payment_id(payment_id: &str) -> Option<PaymentId> {
+	match hex_utils::to_vec(payment_id) {
+		Some(bytes) if bytes.len() == 32 => {
+			let mut id = [0; 32];
+			id.copy_from_slice(&bytes);
+			Some(PaymentId(id))
+		}
+		_ => None,
+	}
+}
+
+/// Look up an outbound payment by the ID LDK tracks it by
+#[utoipa::path(
+	get,
+	path = "/v1/payments/{payment_id}",
+	params(("payment_id" = String, Path, description = "Hex-encoded payment ID")),
+	responses(
+		(status = 200, description = "Payment details", body = PaymentDetails),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn payment_status(
+	path: web::Path<String>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	let payment_id = match parse_payment_id(path.as_str()) {
+		Some(payment_id) => payment_id,
+		None => {
+			return ApiError::new(ErrorCode::InvalidRequest, "invalid payment ID".to_string())
+				.error_response();
+		}
+	};
+
+	let payments = node_var.outbound_payments.read().await;
+	match payments.iter().find(|(_, payment_info)| payment_info.payment_id == Some(payment_id)) {
+		Some((payment_hash, payment_info)) => {
+			let details = payment_details(payment_hash, "outbound", payment_info);
+			HttpResponse::Ok().content_type(ContentType::json()).json(details)
+		}
+		None => ApiError::new(ErrorCode::NotFound, format!("no payment with ID {}", path))
+			.error_response(),
+	}
+}
+
+/// Abandon an in-flight outbound payment so that it isn't retried anymore. HTLCs already sent
+/// may still be claimed by the payee, the payment only fails once all of them have failed.
+#[utoipa::path(
+	post,
+	path = "/v1/payments/{payment_id}/cancel",
+	params(("payment_id" = String, Path, description = "Hex-encoded payment ID")),
+	responses(
+		(status = 200, description = "Payment abandoned", body = ServerSuccess),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn cancel_payment(
+	path: web::Path<String>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	let payment_id = match parse_payment_id(path.as_str()) {
+		Some(payment_id) => payment_id,
+		None => {
+			return ApiError::new(ErrorCode::InvalidRequest, "invalid payment ID".to_string())
+				.error_response();
+		}
+	};
+
+	let payments = node_var.outbound_payments.read().await;
+	match payments.values().find(|payment_info| payment_info.payment_id == Some(payment_id)) {
+		Some(PaymentInfo { status: HTLCStatus::Pending, .. }) => {}
+		Some(_) => {
+			return ApiError::new(
+				ErrorCode::Conflict,
+				format!("payment {} is no longer in flight", path),
+			)
+			.error_response();
+		}
+		None => {
+			return ApiError::new(ErrorCode::NotFound, format!("no payment with ID {}", path))
+				.error_response();
+		}
+	}
+	drop(payments);
+
+	node_var.channel_manager.abandon_payment(payment_id);
+	let msg = ServerSuccess {
+		msg: format!(
+			"EVENT: abandoned payment {}, it fails once its pending HTLCs are resolved",
+			path
+		),
+	};
+	HttpResponse::Ok().content_type(ContentType::json()).json(msg)
+}
+
+/// List the payments above the approval threshold awaiting approval
+#[utoipa::path(
+	get,
+	path = "/v1/payments/pending-approval",
+	responses(
+		(status = 200, description = "Payments awaiting approval", body = PendingApprovals),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn pending_approvals(node_var: web::Data<NodeVar<ServerEventHandler>>) -> HttpResponse {
+	let payments = node_var
+		.approvals
+		.list()
+		.into_iter()
+		.map(|pending| {
+			let (kind, invoice, pubkey) = match pending.request {
+				PaymentRequest::SendPayment(req) => ("sendpayment", Some(req.invoice), None),
+				PaymentRequest::Keysend(req) => ("keysend", None, Some(req.pubkey)),
+			};
+			PendingApproval {
+				id: pending.id,
+				amt_msat: pending.amt_msat,
+				kind: kind.to_string(),
+				invoice,
+				pubkey,
+				token_id: pending.caller.token_id,
+				requested_at: pending.requested_at,
+			}
+		})
+		.collect();
+	HttpResponse::Ok().content_type(ContentType::json()).json(PendingApprovals { payments })
+}
+
+/// Send a payment awaiting approval, as it was requested
+#[utoipa::path(
+	post,
+	path = "/v1/payments/{approval_id}/approve",
+	params(("approval_id" = String, Path, description = "ID of the payment awaiting approval")),
+	responses(
+		(status = 200, description = "Payment initiated", body = ServerSuccess),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn approve_payment(
+	path: web::Path<String>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> Result<HttpResponse, ApiError> {
+	// Taking the payment from the queue first keeps concurrent approvals from sending it twice
+	let pending = take_pending_approval(&node_var, &path)?;
+	let payment = match &pending.request {
+		PaymentRequest::SendPayment(req) => match parse_timeout_secs(req) {
+			Ok(timeout_secs) => {
+				initiate_payment(&node_var, req, &pending.caller, timeout_secs).await
+			}
+			Err(e) => Err(e),
+		},
+		PaymentRequest::Keysend(req) => send_keysend(&node_var, req, &pending.caller).await,
+	};
+	let payment = match payment {
+		Ok(payment) => payment,
+		Err(e) => {
+			// A payment failing to send may have been partially sent, so it isn't approved again.
+			// Any other failure left it unsent, so it awaits approval until it's retried.
+			if e.code() != ErrorCode::PaymentFailed {
+				if let Err(e) = node_var.approvals.restore(pending.clone()) {
+					warn!(approval_id = %pending.id, "failed to requeue a payment: {}", e);
+				}
+			}
+			return Err(e);
+		}
+	};
+	info!(approval_id = %pending.id, "approved a payment");
+	let msg = ServerSuccess {
+		msg: format!(
+			"EVENT: approved payment {}, initiated sending {} msats to {}, payment_id {}",
+			pending.id,
+			payment.amt_msat,
+			payment.payee_pubkey,
+			hex_utils::hex_str(&payment.payment_id.0)
+		),
+	};
+	Ok(HttpResponse::Ok().content_type(ContentType::json()).json(msg))
+}
+
+/// Drop a payment awaiting approval without sending it
+#[utoipa::path(
+	post,
+	path = "/v1/payments/{approval_id}/reject",
+	params(("approval_id" = String, Path, description = "ID of the payment awaiting approval")),
+	responses(
+		(status = 200, description = "Payment rejected", body = ServerSuccess),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn reject_payment(
+	path: web::Path<String>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> Result<HttpResponse, ApiError> {
+	let pending = take_pending_approval(&node_var, &path)?;
+	info!(approval_id = %pending.id, "rejected a payment");
+	let msg = ServerSuccess { msg: format!("EVENT: rejected payment {}", pending.id) };
+	Ok(HttpResponse::Ok().content_type(ContentType::json()).json(msg))
+}
+
+fn take_pending_approval(
+	node_var: &NodeVar<ServerEventHandler>, id: &str,
+) -> Result<PendingPayment, ApiError> {
+	match node_var.approvals.take(id) {
+		Ok(Some(pending)) => Ok(pending),
+		Ok(None) => {
+			Err(ApiError::new(ErrorCode::NotFound, format!("no payment {} awaiting approval", id)))
+		}
+		Err(e) => Err(ApiError::new(
+			ErrorCode::Internal,
+			format!("failed to remove the payment from the approval queue: {}", e),
+		)),
+	}
+}
+
+/// Report the budget, channels and next candidates of the autopilot
+#[utoipa::path(
+	get,
+	path = "/v1/autopilot/status",
+	responses(
+		(status = 200, description = "State of the autopilot", body = AutopilotStatus),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn autopilot_status(node_var: web::Data<NodeVar<ServerEventHandler>>) -> HttpResponse {
+	HttpResponse::Ok().content_type(ContentType::json()).json(node_var.autopilot.status())
+}
+
+/// Let the autopilot open channels, within its budget
+#[utoipa::path(
+	post,
+	path = "/v1/autopilot/enable",
+	responses(
+		(status = 200, description = "Autopilot enabled", body = ServerSuccess),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn enable_autopilot(node_var: web::Data<NodeVar<ServerEventHandler>>) -> HttpResponse {
+	set_autopilot(&node_var, true)
+}
+
+/// Stop the autopilot from opening channels. The channels it opened are left open.
+#[utoipa::path(
+	post,
+	path = "/v1/autopilot/disable",
+	responses(
+		(status = 200, description = "Autopilot disabled", body = ServerSuccess),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn disable_autopilot(node_var: web::Data<NodeVar<ServerEventHandler>>) -> HttpResponse {
+	set_autopilot(&node_var, false)
+}
+
+fn set_autopilot(node_var: &NodeVar<ServerEventHandler>, enabled: bool) -> HttpResponse {
+	let state = if enabled { "enabled" } else { "disabled" };
+	match node_var.autopilot.set_enabled(enabled) {
+		Ok(()) => {
+			info!("{} the autopilot", state);
+			let msg = ServerSuccess { msg: format!("SUCCESS: {} the autopilot", state) };
+			HttpResponse::Ok().content_type(ContentType::json()).json(msg)
+		}
+		Err(e) => ApiError::new(
+			ErrorCode::Internal,
+			format!("failed to persist the state of the autopilot: {}", e),
+		)
+		.error_response(),
+	}
+}
+
+/// How long the LSP is given to answer a request
+const LSP_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_LSP_FUNDING_CONFIRMS_WITHIN_BLOCKS: u16 = 6;
+/// About three months
+const DEFAULT_LSP_CHANNEL_EXPIRY_BLOCKS: u32 = 13_140;
+
+/// Send an LSPS request to the configured LSP, connecting to it first, and wait for its result
+async fn lsp_request(
+	node_var: &NodeVar<ServerEventHandler>, method: &str, params: serde_json::Value,
+) -> Result<LspResponse, ApiError> {
+	let (lsp_pubkey, lsp_addr) = node_var.lsp_client.lsp().ok_or_else(|| {
+		ApiError::new(
+			ErrorCode::InvalidRequest,
+			"no LSP is configured, set `node` in the `[lsp]` section of the config file"
+				.to_string(),
+		)
+	})?;
+	if connect_peer_if_necessary(
+		lsp_pubkey,
+		lsp_addr,
+		node_var.peer_manager.clone(),
+		node_var.socks_proxy,
+		&node_var.peer_tracker,
+	)
+	.await
+	.is_err()
+	{
+		return Err(ApiError::new(
+			ErrorCode::PeerUnreachable,
+			"cannot connect to the LSP".to_string(),
+		));
+	}
+
+	let (id, response) = node_var
+		.lsp_client
+		.send_request(method, params)
+		.map_err(|e| ApiError::new(ErrorCode::Internal, e))?;
+	// Requests are only sent to the LSP as the peer manager processes its events
+	node_var.peer_manager.process_events();
+	match tokio::time::timeout(LSP_REQUEST_TIMEOUT, response).await {
+		Ok(Ok(Ok(result))) => Ok(LspResponse { lsp_pubkey: lsp_pubkey.to_string(), result }),
+		Ok(Ok(Err(error))) => Err(ApiError::new(
+			ErrorCode::UpstreamError,
+			format!("the LSP rejected {}: {}", method, error),
+		)),
+		Ok(Err(_)) | Err(_) => {
+			node_var.lsp_client.forget_request(&id);
+			Err(ApiError::new(
+				ErrorCode::Timeout,
+				format!("the LSP didn't answer {} in time", method),
+			))
+		}
+	}
+}
+
+/// Ask the configured LSP for the channels it sells, following LSPS1's `get_info`
+#[utoipa::path(
+	get,
+	path = "/v1/lsp/info",
+	responses(
+		(status = 200, description = "Options and limits of the LSP's channels", body = LspResponse),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn lsp_info(
+	node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> Result<HttpResponse, ApiError> {
+	let info = lsp_request(&node_var, "lsps1.get_info", serde_json::json!({})).await?;
+	Ok(HttpResponse::Ok().content_type(ContentType::json()).json(info))
+}
+
+/// Buy an inbound channel from the configured LSP, following LSPS1's `create_order`. The order
+/// is answered with the invoice and on-chain address the LSP is paid at, and the LSP opens the
+/// channel once paid.
+#[utoipa::path(
+	post,
+	path = "/v1/lsp/order",
+	request_body = LspOrder,
+	responses(
+		(status = 200, description = "The order, with its payment details", body = LspResponse),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn create_lsp_order(
+	req: web::Json<LspOrder>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> Result<HttpResponse, ApiError> {
+	let refund_onchain_address = match &req.refund_onchain_address {
+		Some(address) => node_var
+			.wallet
+			.parse_address(address)
+			.map_err(|e| ApiError::new(ErrorCode::InvalidRequest, e))?,
+		None => node_var.wallet.get_new_address().await.map_err(|e| {
+			ApiError::new(
+				ErrorCode::UpstreamError,
+				format!("failed to generate a refund address: {}", e),
+			)
+		})?,
+	};
+	let mut params = serde_json::json!({
+		"lsp_balance_sat": req.lsp_balance_sat.to_string(),
+		"client_balance_sat": req.client_balance_sat.unwrap_or(0).to_string(),
+		// LDK 0.0.106 doesn't use channels before they confirm anyway
+		"required_channel_confirmations": 1,
+		"funding_confirms_within_blocks": req
+			.funding_confirms_within_blocks
+			.unwrap_or(DEFAULT_LSP_FUNDING_CONFIRMS_WITHIN_BLOCKS),
+		"channel_expiry_blocks": req
+			.channel_expiry_blocks
+			.unwrap_or(DEFAULT_LSP_CHANNEL_EXPIRY_BLOCKS),
+		"refund_onchain_address": refund_onchain_address.to_string(),
+		"announce_channel": req.announce_channel.as_deref() == Some("true"),
+	});
+	if let Some(token) = node_var.lsp_client.token() {
+		params["token"] = token.into();
+	}
+	let order = lsp_request(&node_var, "lsps1.create_order", params).await?;
+	info!(lsp = %order.lsp_pubkey, "placed an order for an inbound channel");
+	Ok(HttpResponse::Ok().content_type(ContentType::json()).json(order))
+}
+
+/// Check on an order placed with the configured LSP, following LSPS1's `get_order`
+#[utoipa::path(
+	get,
+	path = "/v1/lsp/order/{order_id}",
+	params(("order_id" = String, Path, description = "ID the LSP gave the order")),
+	responses(
+		(status = 200, description = "The order, with its payment and channel", body = LspResponse),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn lsp_order(
+	path: web::Path<String>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> Result<HttpResponse, ApiError> {
+	let params = serde_json::json!({ "order_id": path.as_str() });
+	let order = lsp_request(&node_var, "lsps1.get_order", params).await?;
+	Ok(HttpResponse::Ok().content_type(ContentType::json()).json(order))
+}
+
+/// Sign a message
+async fn sign_message(
+	req: web::Json<SignMessage>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	let message = req.message.clone();
+	if message.len() == 0 {
+		return ApiError::new(ErrorCode::InvalidRequest, "signmsg requires a message".to_string())
+			.error_response();
+	} else {
+		let signed_msg = lightning::util::message_signing::sign(
+			message.as_bytes(),
+			&node_var.keys_manager.get_node_secret(Recipient::Node).unwrap(),
+		);
+
+		match signed_msg {
+			Ok(signature) => {
+				let msg = ServerSuccess { msg: signature };
+				return HttpResponse::Ok().content_type(ContentType::json()).json(msg);
+			}
+			Err(e) => {
+				return ApiError::new(
+					ErrorCode::Internal,
+					format!("failed to sign message. {}", e),
+				)
+				.error_response();
+			}
+		}
+	}
+}
+
+/// Verify a message signed by signmessage, recovering the node which signed it. The signature
+/// is only valid from the expected node if one is given.
+async fn verify_message(req: web::Json<VerifyMessage>) -> HttpResponse {
+	let expected_pubkey = match &req.pubkey {
+		Some(pubkey) => match hex_utils::to_compressed_pubkey(pubkey) {
+			Some(pubkey) => Some(pubkey),
+			None => {
+				return ApiError::new(
+					ErrorCode::InvalidRequest,
+					format!("invalid pubkey: {}", pubkey),
+				)
+				.error_response();
+			}
+		},
+		None => None,
+	};
+	let message = req.message.as_bytes();
+	let pubkey = lightning::util::message_signing::recover_pk(message, &req.signature).ok();
+	let valid = match (&pubkey, &expected_pubkey) {
+		(Some(_), Some(expected_pubkey)) => {
+			lightning::util::message_signing::verify(message, &req.signature, expected_pubkey)
+		}
+		(Some(_), None) => true,
+		(None, _) => false,
+	};
+	let verified = VerifiedMessage { valid, pubkey };
+	HttpResponse::Ok().content_type(ContentType::json()).json(verified)
+}
+
+/// closechannel
+async fn close_channel(
+	req: web::Json<Channel>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	let channel_id_string = req.channel_id.clone();
+	if channel_id_string.len() == 0 {
+		return ApiError::new(
+			ErrorCode::InvalidRequest,
+			"closechannel requires a channel ID: `closechannel <channel_id>`".to_string(),
+		)
+		.error_response();
+	}
+
+	let channel_id_vec = hex_utils::to_vec(channel_id_string.as_str());
+	if channel_id_vec.is_none() || channel_id_vec.as_ref().unwrap().len() != 32 {
+		return ApiError::new(ErrorCode::InvalidRequest, "couldn't parse channel_id".to_string())
+			.error_response();
+	}
+
+	let mut channel_id = [0; 32];
+	channel_id.copy_from_slice(&channel_id_vec.unwrap());
+	let feerate = match parse_feerate(&node_var, &req.sat_per_vbyte, &req.target_conf).await {
+		Ok(feerate) => feerate,
+		Err(error) => return error.error_response(),
+	};
+	let close_channel_res = match feerate {
+		Some(feerate_sat_per_kw) => node_var
+			.channel_manager
+			.close_channel_with_target_feerate(&channel_id, feerate_sat_per_kw),
+		None => cli::close_channel(channel_id, node_var.channel_manager.clone()),
+	};
+
+	match close_channel_res {
+		Err(e) => {
+			return ApiError::new(ErrorCode::InvalidRequest, "failed to close channel".to_string())
+				.with_detail(format!("{:?}", e))
+				.error_response();
+		}
+		Ok(_) => {
+			let msg = ServerSuccess { msg: format!("EVENT: initiating channel close") };
+			return HttpResponse::Ok().content_type(ContentType::json()).json(msg);
+		}
+	}
+}
+
+/// Parse a feerate given either in sat/vbyte or as a confirmation target, into satoshis per KW
+/// as expected by LDK. `None` means the caller should fall back on the default estimate.
+async fn parse_feerate(
+	node_var: &NodeVar<ServerEventHandler>, sat_per_vbyte: &Option<String>,
+	target_conf: &Option<String>,
+) -> Result<Option<u32>, ApiError> {
+	let bad_request = |msg: String| ApiError::new(ErrorCode::InvalidRequest, msg);
+	match (sat_per_vbyte, target_conf) {
+		(Some(_), Some(_)) => {
+			Err(bad_request("only one of sat_per_vbyte and target_conf can be given".to_string()))
+		}
+		(Some(sat_per_vbyte), None) => match sat_per_vbyte.parse::<f64>() {
+			// 1 vbyte is 4 weight units
+			Ok(fee_rate) if fee_rate >= 1.0 => Ok(Some((fee_rate * 250.0).round() as u32)),
+			_ => Err(bad_request(format!(
+				"sat_per_vbyte must be a number of at least 1: {}",
+				sat_per_vbyte
+			))),
+		},
+		(None, Some(target_conf)) => match target_conf.parse::<u16>() {
+			Ok(target) if target >= 1 && target <= 1008 => {
+				match node_var.chain_source.estimate_fee_sat_per_kw(target) {
+					Ok(feerate) => Ok(Some(feerate)),
+					Err(e) => Err(bad_request(format!("failed to estimate fee rate: {}", e))),
+				}
+			}
+			_ => Err(bad_request(format!(
+				"target_conf must be a number of blocks between 1 and 1008: {}",
+				target_conf
+			))),
+		},
+		(None, None) => Ok(None),
+	}
+}
+
+/// Export the ChannelMonitors and peers of every channel in a bundle encrypted with a key
+/// derived from the node seed, from which `--recover` restores a node which lost its data
+async fn export_backup(node_var: web::Data<NodeVar<ServerEventHandler>>) -> HttpResponse {
+	match backup::export_channel_backup(
+		&node_var.ldk_data_dir,
+		&*node_var.store,
+		&node_var.backup_key,
+	) {
+		Ok((bundle, channels_number)) => {
+			let backup = ChannelBackupExport { backup: base64::encode(bundle), channels_number };
+			HttpResponse::Ok().content_type(ContentType::json()).json(backup)
+		}
+		Err(e) => {
+			ApiError::new(ErrorCode::Internal, format!("failed to export channel backup: {}", e))
+				.error_response()
+		}
+	}
+}
+
+/// Parse the format and time range of an export request
+fn parse_export(req: &Export) -> Result<(ExportFormat, Option<u64>, Option<u64>), String> {
+	let format = ExportFormat::parse(req.format.as_deref())?;
+	let parse_time = |time: &Option<String>| match time {
+		Some(time) => time
+			.parse::<u64>()
+			.map(Some)
+			.map_err(|_| format!("times must be seconds since the UNIX epoch, got {}", time)),
+		None => Ok(None),
+	};
+	Ok((format, parse_time(&req.start_time)?, parse_time(&req.end_time)?))
+}
+
+/// Respond with an export as a file to download, named `<name>.<extension>`
+fn export_response(name: &str, format: ExportFormat, data: String) -> HttpResponse {
+	HttpResponse::Ok()
+		.content_type(format.content_type())
+		.insert_header((
+			header::CONTENT_DISPOSITION,
+			format!("attachment; filename=\"{}.{}\"", name, format.extension()),
+		))
+		.body(data)
+}
+
+/// Export the node's payments for accounting, ordered by creation time
+async fn export_payments(
+	req: web::Json<Export>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	let (format, start_time, end_time) = match parse_export(&req) {
+		Ok(export) => export,
+		Err(e) => {
+			return ApiError::new(ErrorCode::InvalidRequest, e.to_string()).error_response();
+		}
+	};
+
+	let mut records = Vec::new();
+	for (direction, payments) in
+		[("inbound", &node_var.inbound_payments), ("outbound", &node_var.outbound_payments)]
+	{
+		for (payment_hash, payment_info) in payments.read().await.iter() {
+			// Payments recorded before they were timestamped are only exported without a range
+			let in_time_range = match payment_info.created_at {
+				Some(created_at) => {
+					start_time.map_or(true, |start| created_at >= start)
+						&& end_time.map_or(true, |end| created_at <= end)
+				}
+				None => start_time.is_none() && end_time.is_none(),
+			};
+			if in_time_range {
+				records.push(PaymentRecord::new(payment_hash, direction, payment_info));
+			}
+		}
+	}
+	records.sort_by(|a, b| {
+		(a.created_at, &a.payment_hash, &a.direction).cmp(&(
+			b.created_at,
+			&b.payment_hash,
+			&b.direction,
+		))
+	});
+	export_response("payments", format, export::render(&records, format))
+}
+
+/// Export the node's forwarding history for accounting, with the peers of the channels of each
+/// forward when known
+async fn export_forwards(
+	req: web::Json<Export>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	let (format, start_time, end_time) = match parse_export(&req) {
+		Ok(export) => export,
+		Err(e) => {
+			return ApiError::new(ErrorCode::InvalidRequest, e.to_string()).error_response();
+		}
+	};
+	let forwards = match disk::read_forwards(&*node_var.store, start_time, end_time) {
+		Ok(forwards) => forwards,
+		Err(e) => {
+			return ApiError::new(ErrorCode::Internal, format!("failed to read forwards: {}", e))
+				.error_response();
+		}
+	};
+
+	let mut channel_peers = HashMap::new();
+	if let Ok(closed_channels) = disk::read_closed_channels(&*node_var.store) {
+		for closed_channel in closed_channels {
+			if let Some(state) = closed_channel.last_state {
+				channel_peers.insert(closed_channel.channel_id, state.peer_pubkey);
+			}
+		}
+	}
+	for channel in node_var.channel_manager.list_channels() {
+		channel_peers.insert(
+			hex_utils::hex_str(&channel.channel_id),
+			channel.counterparty.node_id.to_string(),
+		);
+	}
+
+	let records: Vec<ForwardRecord> =
+		forwards.iter().map(|forward| ForwardRecord::new(forward, &channel_peers)).collect();
+	export_response("forwards", format, export::render(&records, format))
+}
+
+/// Display the mnemonic of the node's keys seed. The mnemonic is deleted from the node once
+/// displayed, so this only succeeds once per node.
+async fn backup_seed(
+	req: web::Json<SeedBackup>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	if req.confirm.as_deref() != Some("true") {
+		return ApiError::new(
+			ErrorCode::InvalidRequest,
+			"the mnemonic is only displayed once, confirm with `backupseed --confirm`".to_string(),
+		)
+		.error_response();
+	}
+	match seed::take_pending_mnemonic(&node_var.ldk_data_dir, node_var.secrets_key.as_ref()) {
+		Ok(Some(mnemonic)) => {
+			HttpResponse::Ok().content_type(ContentType::json()).json(SeedMnemonic { mnemonic })
+		}
+		Ok(None) => ApiError::new(
+			ErrorCode::NotFound,
+			"the mnemonic was already displayed, or the node's keys seed wasn't generated from one"
+				.to_string(),
+		)
+		.error_response(),
+		Err(e) => ApiError::new(ErrorCode::Internal, format!("failed to read the mnemonic: {}", e))
+			.error_response(),
+	}
+}
+
+/// forceclosechannel
+async fn force_close_channel(
+	req: web::Json<Channel>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	let channel_id_string = req.channel_id.clone();
+	if channel_id_string.len() == 0 {
+		return ApiError::new(
+			ErrorCode::InvalidRequest,
+			"closechannel requires a channel ID: `closechannel <channel_id>`".to_string(),
+		)
+		.error_response();
+	}
+
+	let channel_id_vec = hex_utils::to_vec(channel_id_string.as_str());
+	if channel_id_vec.is_none() || channel_id_vec.as_ref().unwrap().len() != 32 {
+		return ApiError::new(ErrorCode::InvalidRequest, "couldn't parse channel_id".to_string())
+			.error_response();
+	}
+
+	let mut channel_id = [0; 32];
+	channel_id.copy_from_slice(&channel_id_vec.unwrap());
+
+	// Snapshot the channel state before broadcasting our latest commitment transaction. A failed
+	// snapshot shouldn't stop an operator from getting their funds out, so it's only reported.
+	match backup::snapshot(&node_var.ldk_data_dir, "forceclosechannel") {
+		Ok(snapshot_id) => info!("created backup snapshot {}", snapshot_id),
+		Err(e) => warn!("failed to snapshot node state before force-close: {}", e),
+	}
+
+	let close_channel_res = cli::force_close_channel(channel_id, node_var.channel_manager.clone());
+
+	match close_channel_res {
+		Err(e) => {
+			return ApiError::new(
+				ErrorCode::InvalidRequest,
+				"failed to force-close channel".to_string(),
+			)
+			.with_detail(format!("{:?}", e))
+			.error_response();
+		}
+		Ok(_) => {
+			let msg = ServerSuccess { msg: format!("EVENT: initiating channel force-close") };
+			return HttpResponse::Ok().content_type(ContentType::json()).json(msg);
+		}
+	}
+}
+
+/// abandonchannel, for channels whose funding transaction never confirms. The channel is
+/// force-closed and its funding transaction abandoned in bitcoind's wallet, so that its inputs
+/// can be spent again.
+async fn abandon_channel(
+	req: web::Json<Channel>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	let channel_id = match hex_utils::to_vec(&req.channel_id) {
+		Some(channel_id) if channel_id.len() == 32 => {
+			let mut id = [0; 32];
+			id.copy_from_slice(&channel_id);
+			id
+		}
+		_ => {
+			return ApiError::new(
+				ErrorCode::InvalidRequest,
+				"couldn't parse channel_id".to_string(),
+			)
+			.error_response()
+		}
+	};
+	let channel = node_var
+		.channel_manager
+		.list_channels()
+		.into_iter()
+		.find(|chan| chan.channel_id == channel_id);
+	let channel = match channel {
+		Some(channel) => channel,
+		None => {
+			return ApiError::new(ErrorCode::NotFound, format!("no channel {}", req.channel_id))
+				.error_response()
+		}
+	};
+	let confirmed_elsewhere = || {
+		ApiError::new(
+			ErrorCode::InvalidRequest,
+			format!(
+				"the funding transaction of channel {} is confirmed, close it with closechannel or forceclosechannel",
+				req.channel_id
+			),
+		)
+		.error_response()
+	};
+	if channel.is_funding_locked {
+		return confirmed_elsewhere();
+	}
+	// LDK waits for several confirmations before locking the channel in, so check that the
+	// funding transaction doesn't have any
+	if let Some(funding_txo) = channel.funding_txo {
+		let outpoint = funding_txo.into_bitcoin_outpoint();
+		match node_var.chain_source.is_output_confirmed(&outpoint).await {
+			Ok(true) => return confirmed_elsewhere(),
+			Ok(false) => {}
+			Err(e) => {
+				return ApiError::new(
+					ErrorCode::Internal,
+					format!("failed to look up the funding transaction: {}", e),
+				)
+				.error_response()
+			}
+		}
+	}
+
+	match backup::snapshot(&node_var.ldk_data_dir, "abandonchannel") {
+		Ok(snapshot_id) => info!("created backup snapshot {}", snapshot_id),
+		Err(e) => warn!("failed to snapshot node state before abandoning channel: {}", e),
+	}
+	// LDK 0.0.106 can't force-close without broadcasting the latest commitment transaction, which
+	// can't confirm without the funding transaction anyway
+	if let Err(e) = cli::force_close_channel(channel_id, node_var.channel_manager.clone()) {
+		return ApiError::new(ErrorCode::InvalidRequest, "failed to abandon channel".to_string())
+			.with_detail(format!("{:?}", e))
+			.error_response();
+	}
+	// Inputs of funding transactions which were never broadcast are unlocked on
+	// `Event::DiscardFunding`
+	let funding_txid = match channel.funding_txo {
+		Some(funding_txo) => funding_txo.txid,
+		None => {
+			let msg = ServerSuccess {
+				msg: format!("EVENT: abandoned channel {}, which wasn't funded", req.channel_id),
+			};
+			return HttpResponse::Ok().content_type(ContentType::json()).json(msg);
+		}
+	};
+	let msg = match node_var.wallet.abandon_transaction(&funding_txid).await {
+		Ok(()) => format!(
+			"EVENT: abandoned channel {} and its funding transaction {}, whose inputs are spendable again",
+			req.channel_id, funding_txid
+		),
+		Err(e) => {
+			warn!("failed to abandon funding transaction {}: {}", funding_txid, e);
+			// bitcoind refuses to abandon transactions which are still in its mempool
+			let retry = match node_var.bitcoind_client {
+				Some(_) => format!(
+					". Retry with `bitcoin-cli abandontransaction {}` once it left the mempool",
+					funding_txid
+				),
+				None => String::new(),
+			};
+			format!(
+				"EVENT: abandoned channel {}, but the wallet couldn't abandon its funding transaction {} yet: {}{}",
+				req.channel_id, funding_txid, e, retry
+			)
+		}
+	};
+	HttpResponse::Ok().content_type(ContentType::json()).json(ServerSuccess { msg })
+}
+
+/// Confirmation token of closing `channel_ids`, cooperatively or by force
+fn close_all_confirmation(node_id: &PublicKey, channel_ids: &[[u8; 32]], force: bool) -> String {
+	let mut preimage = node_id.serialize().to_vec();
+	for channel_id in channel_ids {
+		preimage.extend_from_slice(channel_id);
+	}
+	preimage.push(force as u8);
+	hex_utils::hex_str(&Sha256::hash(&preimage).into_inner()[..8])
+}
+
+/// closeallchannels, for winding the node down. A request without the confirmation token is
+/// answered with it.
+async fn close_all_channels(
+	req: web::Json<CloseAllChannels>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	let force = req.force.as_deref() == Some("true");
+	let mut channel_ids: Vec<[u8; 32]> =
+		node_var.channel_manager.list_channels().iter().map(|chan| chan.channel_id).collect();
+	channel_ids.sort_unstable();
+	if channel_ids.is_empty() {
+		return ApiError::new(ErrorCode::NotFound, "the node has no channels to close".to_string())
+			.error_response();
+	}
+	let node_id = node_var.channel_manager.get_our_node_id();
+	let confirmation = close_all_confirmation(&node_id, &channel_ids, force);
+	if req.confirm.as_deref() != Some(confirmation.as_str()) {
+		let close = if force { "force-close" } else { "close" };
+		return ApiError::new(
+			ErrorCode::InvalidRequest,
+			format!(
+				"this will {} all {} channels of the node, confirm with `closeallchannels{} --confirm {}`",
+				close,
+				channel_ids.len(),
+				if force { " --force" } else { "" },
+				confirmation
+			),
+		)
+		.error_response();
+	}
+
+	let feerate = match parse_feerate(&node_var, &req.sat_per_vbyte, &req.target_conf).await {
+		Ok(feerate) => feerate,
+		Err(error) => return error.error_response(),
+	};
+	if force {
+		// As for forceclosechannel, a failed snapshot is only reported
+		match backup::snapshot(&node_var.ldk_data_dir, "closeallchannels") {
+			Ok(snapshot_id) => info!("created backup snapshot {}", snapshot_id),
+			Err(e) => warn!("failed to snapshot node state before force-closes: {}", e),
+		}
+	}
+
+	let mut results = Vec::new();
+	for channel_id in channel_ids {
+		let res = match (force, feerate) {
+			(true, _) => cli::force_close_channel(channel_id, node_var.channel_manager.clone()),
+			(false, Some(feerate_sat_per_kw)) => node_var
+				.channel_manager
+				.close_channel_with_target_feerate(&channel_id, feerate_sat_per_kw),
+			(false, None) => cli::close_channel(channel_id, node_var.channel_manager.clone()),
+		};
+		results.push(CloseChannelResult {
+			channel_id: hex_utils::hex_str(&channel_id),
+			msg: res.as_ref().ok().map(|_| match force {
+				true => "EVENT: initiating channel force-close".to_string(),
+				false => "EVENT: initiating channel close".to_string(),
+			}),
+			error: res.err().map(|e| {
+				ApiError::new(ErrorCode::InvalidRequest, "failed to close channel".to_string())
+					.with_detail(format!("{:?}", e))
+					.into_body()
+			}),
+		});
+	}
+	HttpResponse::Ok().content_type(ContentType::json()).json(ClosingChannels { results })
+}
+
+/// Get a new on-chain address owned by the node's wallet
+#[utoipa::path(
+	post,
+	path = "/v1/wallet/address",
+	responses(
+		(status = 200, description = "New on-chain address", body = OnchainAddress),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn new_address(node_var: web::Data<NodeVar<ServerEventHandler>>) -> HttpResponse {
+	match node_var.wallet.get_new_address().await {
+		Ok(address) => {
+			let address = OnchainAddress { address: address.to_string() };
+			HttpResponse::Ok().content_type(ContentType::json()).json(address)
+		}
+		Err(e) => ApiError::new(ErrorCode::Internal, format!("failed to get new address: {}", e))
+			.error_response(),
+	}
+}
+
+/// Get the on-chain balance alongside the Lightning balance
+#[utoipa::path(
+	get,
+	path = "/v1/wallet/balance",
+	responses(
+		(status = 200, description = "On-chain and Lightning balances", body = WalletBalance),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn wallet_balance(node_var: web::Data<NodeVar<ServerEventHandler>>) -> HttpResponse {
+	let balance = match node_var.wallet.get_balance().await {
+		Ok(balance) => balance,
+		Err(e) => {
+			return ApiError::new(
+				ErrorCode::Internal,
+				format!("failed to get wallet balance: {}", e),
+			)
+			.error_response();
+		}
+	};
+	let local_balance_msat =
+		node_var.channel_manager.list_channels().iter().map(|c| c.balance_msat).sum::<u64>();
+
+	let wallet_balance = WalletBalance {
+		confirmed_balance_sat: balance.confirmed_sat,
+		unconfirmed_balance_sat: balance.unconfirmed_sat,
+		immature_balance_sat: balance.immature_sat,
+		local_balance_msat,
+		total_balance_msat: (balance.confirmed_sat + balance.unconfirmed_sat) * 1000
+			+ local_balance_msat,
+	};
+	HttpResponse::Ok().content_type(ContentType::json()).json(wallet_balance)
+}
+
+/// Get a summary of the node's funds, on-chain, in channels and in closed channels
+///
+/// Funds of closed channels are first claimed by LDK, then handed over to the sweeper, so they
+/// count as pending closes and then as pending sweeps. A sweep also shows in the unconfirmed
+/// on-chain balance until it confirms.
+#[utoipa::path(
+	get,
+	path = "/v1/balance",
+	responses(
+		(status = 200, description = "Summary of the node's funds", body = BalanceSummary),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn balance(
+	node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> Result<HttpResponse, ApiError> {
+	let onchain = node_var.wallet.get_balance().await.map_err(|e| {
+		ApiError::new(ErrorCode::Internal, format!("failed to get wallet balance: {}", e))
+	})?;
+	let pending_sweep_sat = node_var.sweeper.pending_sat().map_err(|e| {
+		ApiError::new(ErrorCode::Internal, format!("unable to read the swept outputs: {}", e))
+	})?;
+
+	let channels = node_var.channel_manager.list_channels();
+	let mut lightning_local_balance_msat = 0;
+	let mut lightning_spendable_msat = 0;
+	let mut lightning_receivable_msat = 0;
+	let mut channel_reserve_sat = 0;
+	for channel in channels.iter() {
+		lightning_local_balance_msat += channel.balance_msat;
+		if channel.is_usable {
+			lightning_spendable_msat += channel.outbound_capacity_msat;
+			lightning_receivable_msat += channel.inbound_capacity_msat;
+		}
+		channel_reserve_sat += channel.unspendable_punishment_reserve.unwrap_or(0);
+	}
+	// Monitors of open channels report their balance as claimable on close, which the channel
+	// balances already count
+	let pending_close_sat = node_var
+		.chain_monitor
+		.get_claimable_balances(&channels.iter().collect::<Vec<_>>())
+		.iter()
+		.map(|balance| match balance {
+			Balance::ClaimableOnChannelClose { claimable_amount_satoshis }
+			| Balance::ClaimableAwaitingConfirmations { claimable_amount_satoshis, .. }
+			| Balance::ContentiousClaimable { claimable_amount_satoshis, .. }
+			| Balance::MaybeClaimableHTLCAwaitingTimeout { claimable_amount_satoshis, .. } => {
+				*claimable_amount_satoshis
+			}
+		})
+		.sum::<u64>();
+
+	let total_balance_msat =
+		(onchain.confirmed_sat + onchain.unconfirmed_sat + pending_close_sat + pending_sweep_sat)
+			* 1000 + lightning_local_balance_msat;
+	let summary = BalanceSummary {
+		onchain_confirmed_sat: onchain.confirmed_sat,
+		onchain_unconfirmed_sat: onchain.unconfirmed_sat,
+		onchain_immature_sat: onchain.immature_sat,
+		lightning_local_balance_msat,
+		lightning_spendable_msat,
+		lightning_receivable_msat,
+		channel_reserve_sat,
+		pending_close_sat,
+		pending_sweep_sat,
+		total_balance_msat,
+	};
+	Ok(HttpResponse::Ok().content_type(ContentType::json()).json(summary))
+}
+
+/// Send on-chain funds from the node's wallet
+async fn send_onchain(
+	req: web::Json<SendOnchain>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	let address = match node_var.wallet.parse_address(req.address.as_str()) {
+		Ok(address) => address,
+		Err(e) => {
+			return ApiError::new(ErrorCode::InvalidRequest, e.to_string()).error_response();
+		}
+	};
+	let amount_sat: u64 = match req.amount_sat.parse() {
+		Ok(amt) if amt > 0 => amt,
+		_ => {
+			return ApiError::new(
+				ErrorCode::InvalidRequest,
+				"amount_sat must be a positive number".to_string(),
+			)
+			.error_response();
+		}
+	};
+	let sat_per_vbyte = match &req.sat_per_vbyte {
+		Some(fee_rate) => match fee_rate.parse::<f64>() {
+			Ok(fee_rate) if fee_rate >= 1.0 => Some(fee_rate),
+			_ => {
+				return ApiError::new(
+					ErrorCode::InvalidRequest,
+					"sat_per_vbyte must be a number of at least 1".to_string(),
+				)
+				.error_response();
+			}
+		},
+		None => None,
+	};
+
+	match node_var.wallet.send_to_address(&address, amount_sat, sat_per_vbyte).await {
+		Ok(txid) => {
+			let tx = OnchainTransaction { txid: txid.to_string() };
+			HttpResponse::Ok().content_type(ContentType::json()).json(tx)
+		}
+		Err(e) => {
+			ApiError::new(ErrorCode::UpstreamError, format!("failed to send on-chain: {}", e))
+				.error_response()
+		}
+	}
+}
+
+/// List the on-chain outputs the node can spend
+#[utoipa::path(
+	get,
+	path = "/v1/wallet/utxos",
+	responses(
+		(status = 200, description = "Spendable on-chain outputs", body = ListUtxos),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn list_utxos(node_var: web::Data<NodeVar<ServerEventHandler>>) -> HttpResponse {
+	match node_var.wallet.list_utxos().await {
+		Ok(utxos) => {
+			let utxos = utxos
+				.into_iter()
+				.map(|utxo| Utxo {
+					txid: utxo.txid.to_string(),
+					vout: utxo.vout,
+					amount_sat: utxo.amount_sat,
+					confirmations: utxo.confirmations,
+					reserved: utxo.reserved,
+				})
+				.collect();
+			let list_utxos = ListUtxos { utxos };
+			HttpResponse::Ok().content_type(ContentType::json()).json(list_utxos)
+		}
+		Err(e) => ApiError::new(ErrorCode::Internal, format!("failed to list utxos: {}", e))
+			.error_response(),
+	}
+}
+
+/// List the transactions the node broadcast which are unconfirmed or not buried under enough
+/// blocks yet, in the order they were first broadcast
+#[utoipa::path(
+	get,
+	path = "/v1/pendingtxs",
+	responses(
+		(status = 200, description = "Transactions in flight", body = PendingTxs),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn pending_txs(node_var: web::Data<NodeVar<ServerEventHandler>>) -> HttpResponse {
+	let txs = match node_var.broadcaster.pending_txs() {
+		Ok(txs) => txs,
+		Err(e) => {
+			return ApiError::new(
+				ErrorCode::Internal,
+				format!("unable to read the broadcast transactions: {}", e),
+			)
+			.error_response()
+		}
+	};
+	// LDK doesn't say what it broadcasts, so funding transactions are told apart by the channels
+	let funding_txids: HashSet<String> = node_var
+		.channel_manager
+		.list_channels()
+		.iter()
+		.filter_map(|channel| channel.funding_txo.map(|txo| txo.txid.to_string()))
+		.collect();
+	let tip_height = node_var.broadcaster.tip_height();
+	let pending_txs = txs
+		.into_iter()
+		.map(|tx| {
+			let kind = match tx.kind {
+				BroadcastKind::Sweep => "sweep",
+				BroadcastKind::Channel if funding_txids.contains(&tx.txid) => "funding",
+				BroadcastKind::Channel => "close",
+			};
+			let confirmations = match (tx.confirmed_height, tip_height) {
+				(Some(height), Some(tip_height)) => (tip_height + 1).saturating_sub(height),
+				_ => 0,
+			};
+			PendingTx {
+				txid: tx.txid,
+				kind: kind.to_string(),
+				first_broadcast_at: tx.first_broadcast_at,
+				last_broadcast_at: tx.last_broadcast_at,
+				broadcasts: tx.broadcasts,
+				confirmations,
+			}
+		})
+		.collect();
+	HttpResponse::Ok().content_type(ContentType::json()).json(PendingTxs { pending_txs })
+}
+
+/// Aggregate everything we know about a channel that may be stuck into a single report.
+///
+/// LDK does not expose pending HTLCs, the outcome of the last `channel_reestablish` or pending
+/// monitor updates for open channels, so the report relies on peer connectivity, confirmation
+/// state and the balances the channel monitor considers claimable. Monitors are persisted
+/// synchronously, so no update is ever left pending.
+#[utoipa::path(
+	get,
+	path = "/v1/channels/{channel_id}",
+	params(("channel_id" = String, Path, description = "Hex-encoded channel ID")),
+	responses(
+		(status = 200, description = "Diagnostics of the channel", body = ChannelDiagnostics),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn diagnose_channel(
+	path: web::Path<String>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	let channel_id_vec = hex_utils::to_vec(path.as_str());
+	if channel_id_vec.is_none() || channel_id_vec.as_ref().unwrap().len() != 32 {
+		return ApiError::new(ErrorCode::InvalidRequest, "couldn't parse channel_id".to_string())
+			.error_response();
+	}
+	let mut channel_id = [0; 32];
+	channel_id.copy_from_slice(&channel_id_vec.unwrap());
 
-	let inbound_payments = node_var.inbound_payments.clone();
-	let channel_manager = node_var.channel_manager.clone();
-	let keys_manager = node_var.keys_manager.clone();
-	let network = node_var.network;
+	let chan_info = match node_var
+		.channel_manager
+		.list_channels()
+		.into_iter()
+		.find(|chan| chan.channel_id == channel_id)
+	{
+		Some(chan) => chan,
+		None => {
+			return ApiError::new(ErrorCode::NotFound, format!("unknown channel {}", path))
+				.error_response();
+		}
+	};
 
-	let mut payments = inbound_payments.lock().unwrap();
-	let currency = match network {
-		Network::Bitcoin => Currency::Bitcoin,
-		Network::Testnet => Currency::BitcoinTestnet,
-		Network::Regtest => Currency::Regtest,
-		Network::Signet => Currency::Signet,
+	let peer_pubkey = chan_info.counterparty.node_id;
+	let peer_connected = node_var.peer_manager.get_peer_node_ids().contains(&peer_pubkey);
+	let peer_address = match disk::read_channel_peer_data(&*node_var.store) {
+		Ok(info) => info.get(&peer_pubkey).map(|addr| addr.to_string()),
+		Err(_) => None,
 	};
 
-	let amt_msat = amt_msat.unwrap();
-	let invoice = utils::create_invoice_from_channelmanager(
-		&channel_manager,
-		keys_manager,
-		currency,
-		Some(amt_msat),
-		"ln-node".to_string(),
-	);
+	let mut claimable_balances = Vec::new();
+	if let Some(funding_txo) = chan_info.funding_txo {
+		if let Ok(monitor) = node_var.chain_monitor.get_monitor(funding_txo) {
+			for balance in monitor.get_claimable_balances() {
+				let (kind, amount_satoshis, height) = match balance {
+					Balance::ClaimableOnChannelClose { claimable_amount_satoshis } => {
+						("claimable_on_channel_close", claimable_amount_satoshis, None)
+					}
+					Balance::ClaimableAwaitingConfirmations {
+						claimable_amount_satoshis,
+						confirmation_height,
+					} => (
+						"claimable_awaiting_confirmations",
+						claimable_amount_satoshis,
+						Some(confirmation_height),
+					),
+					Balance::ContentiousClaimable { claimable_amount_satoshis, timeout_height } => {
+						("contentious_claimable", claimable_amount_satoshis, Some(timeout_height))
+					}
+					Balance::MaybeClaimableHTLCAwaitingTimeout {
+						claimable_amount_satoshis,
+						claimable_height,
+					} => (
+						"maybe_claimable_htlc_awaiting_timeout",
+						claimable_amount_satoshis,
+						Some(claimable_height),
+					),
+				};
+				claimable_balances.push(DiagnosedBalance {
+					kind: kind.to_string(),
+					amount_satoshis,
+					height,
+				});
+			}
+		}
+	}
 
-	match invoice {
-		Ok(inv) => {
-			let payment_hash = PaymentHash(inv.payment_hash().clone().into_inner());
-			payments.insert(
-				payment_hash,
-				PaymentInfo {
-					preimage: None,
-					secret: Some(inv.payment_secret().clone()),
-					status: HTLCStatus::Pending,
-					amt_msat: MillisatAmount(Some(amt_msat)),
-				},
+	let feerate_normal_sat_per_kw =
+		node_var.chain_source.get_est_sat_per_1000_weight(ConfirmationTarget::Normal);
+	let feerate_high_priority_sat_per_kw =
+		node_var.chain_source.get_est_sat_per_1000_weight(ConfirmationTarget::HighPriority);
+
+	let mut issues = Vec::new();
+	if !peer_connected {
+		issues.push(format!(
+			"peer {} is not connected; the channel is unusable until it reconnects and the channel is reestablished",
+			peer_pubkey
+		));
+		if peer_address.is_none() {
+			issues.push(
+				"no known address for the peer, it will not be reconnected automatically"
+					.to_string(),
 			);
+		}
+	}
+	if !chan_info.is_funding_locked {
+		match chan_info.confirmations_required {
+			Some(confs) => issues.push(format!(
+				"funding transaction has not yet reached the {} confirmations required",
+				confs
+			)),
+			None => issues.push("funding transaction has not been broadcast yet".to_string()),
+		}
+	}
+	if peer_connected && chan_info.is_funding_locked && !chan_info.is_usable {
+		issues.push(
+			"channel is not usable although the peer is connected; it may be shutting down"
+				.to_string(),
+		);
+	}
+
+	let diagnostics = ChannelDiagnostics {
+		channel_id: hex_utils::hex_str(&chan_info.channel_id[..]),
+		peer_pubkey: hex_utils::hex_str(&peer_pubkey.serialize()),
+		peer_connected,
+		peer_address,
+		is_outbound: chan_info.is_outbound,
+		is_confirmed_onchain: chan_info.is_funding_locked,
+		confirmations_required: chan_info.confirmations_required,
+		is_usable: chan_info.is_usable,
+		claimable_balances,
+		feerate_normal_sat_per_kw,
+		feerate_high_priority_sat_per_kw,
+		issues,
+	};
+	HttpResponse::Ok().content_type(ContentType::json()).json(diagnostics)
+}
 
-			let inv_str = ServerInvoice { invoice: format!("{}", inv) };
-			return HttpResponse::Ok().content_type(ContentType::json()).json(inv_str);
+/// List the channels the node has closed
+#[utoipa::path(
+	get,
+	path = "/v1/channels/closed",
+	responses(
+		(status = 200, description = "Closed channels", body = ClosedChannels),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn closed_channels(node_var: web::Data<NodeVar<ServerEventHandler>>) -> HttpResponse {
+	match disk::read_closed_channels(&*node_var.store) {
+		Ok(channels) => {
+			HttpResponse::Ok().content_type(ContentType::json()).json(ClosedChannels { channels })
 		}
 		Err(e) => {
-			let error = ServerError { error: format!("ERROR: failed to create invoice: {:?}", e) };
-			return HttpResponse::Ok().content_type(ContentType::json()).json(error);
+			ApiError::new(ErrorCode::Internal, format!("failed to read closed channels: {}", e))
+				.error_response()
 		}
 	}
 }
 
-/// Send payment
-async fn send_payment(
-	req: web::Json<ServerInvoice>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+/// List the payments forwarded by the node, optionally within a time range
+async fn list_forwards(
+	req: web::Json<ListForwards>, node_var: web::Data<NodeVar<ServerEventHandler>>,
 ) -> HttpResponse {
-	let invoice = req.invoice.parse::<Invoice>().unwrap();
-	let invoice_payer = node_var.invoice_payer.clone();
-	let payment_storage = node_var.outbound_payments.clone();
-
-	let payment_id = invoice_payer.pay_invoice(&invoice);
-	match payment_id {
-		Ok(_payment_id) => {
-			let payee_pubkey = invoice.recover_payee_pub_key();
-			let amt_msat = invoice.amount_milli_satoshis().unwrap();
-
-			let status = HTLCStatus::Pending;
+	let parse_time = |time: &Option<String>| match time {
+		Some(time) => time.parse::<u64>().map(Some).map_err(|_| time.clone()),
+		None => Ok(None),
+	};
+	let (start_time, end_time) = match (parse_time(&req.start_time), parse_time(&req.end_time)) {
+		(Ok(start_time), Ok(end_time)) => (start_time, end_time),
+		(Err(time), _) | (_, Err(time)) => {
+			return ApiError::new(
+				ErrorCode::InvalidRequest,
+				format!("times must be seconds since the UNIX epoch, got {}", time),
+			)
+			.error_response();
+		}
+	};
 
-			let payment_hash = PaymentHash(invoice.payment_hash().clone().into_inner());
-			let payment_secret = Some(invoice.payment_secret().clone());
+	match disk::read_forwards(&*node_var.store, start_time, end_time) {
+		Ok(forwards) => {
+			let total_fee_earned_msat = forwards.iter().filter_map(|f| f.fee_earned_msat).sum();
+			let forwards = Forwards { forwards, total_fee_earned_msat };
+			HttpResponse::Ok().content_type(ContentType::json()).json(forwards)
+		}
+		Err(e) => ApiError::new(ErrorCode::Internal, format!("failed to read forwards: {}", e))
+			.error_response(),
+	}
+}
 
-			let mut payments = payment_storage.lock().unwrap();
-			payments.insert(
-				payment_hash,
-				PaymentInfo {
-					preimage: None,
-					secret: payment_secret,
-					status,
-					amt_msat: MillisatAmount(invoice.amount_milli_satoshis()),
-				},
-			);
-			let payment_msg = ServerSuccess {
-				msg: format!("EVENT: initiated sending {} msats to {}", amt_msat, payee_pubkey),
-			};
-			return HttpResponse::Ok().content_type(ContentType::json()).json(payment_msg);
+/// Show the balances of a channel sampled over time, optionally within a time range. Samples
+/// are taken every 10 minutes while the channel is open and kept once it closes.
+async fn channel_history(
+	path: web::Path<String>, req: web::Json<ChannelHistory>,
+	node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	let channel_id = match hex_utils::to_vec(path.as_str()) {
+		Some(channel_id) if channel_id.len() == 32 => {
+			let mut id = [0; 32];
+			id.copy_from_slice(&channel_id);
+			id
 		}
-		Err(PaymentError::Invoice(e)) => {
-			let error = ServerError { error: format!("ERROR: invalid invoice: {}", e) };
-			return HttpResponse::ExpectationFailed().content_type(ContentType::json()).json(error);
+		_ => {
+			return ApiError::new(
+				ErrorCode::InvalidRequest,
+				"couldn't parse channel_id".to_string(),
+			)
+			.error_response()
 		}
-		Err(PaymentError::Routing(e)) => {
-			let error = ServerError { error: format!("ERROR: failed to find route: {}", e.err) };
-			return HttpResponse::ExpectationFailed().content_type(ContentType::json()).json(error);
+	};
+	let parse_time = |time: &Option<String>| match time {
+		Some(time) => time.parse::<u64>().map(Some).map_err(|_| time.clone()),
+		None => Ok(None),
+	};
+	let (start_time, end_time) = match (parse_time(&req.start_time), parse_time(&req.end_time)) {
+		(Ok(start_time), Ok(end_time)) => (start_time, end_time),
+		(Err(time), _) | (_, Err(time)) => {
+			return ApiError::new(
+				ErrorCode::InvalidRequest,
+				format!("times must be seconds since the UNIX epoch, got {}", time),
+			)
+			.error_response();
 		}
-		Err(PaymentError::Sending(e)) => {
-			let error = ServerError { error: format!("ERROR: failed to send payment: {:?}", e) };
-			return HttpResponse::ExpectationFailed().content_type(ContentType::json()).json(error);
+	};
+
+	let samples =
+		match disk::read_channel_history(&*node_var.store, &channel_id, start_time, end_time) {
+			Ok(samples) => samples,
+			Err(e) => {
+				return ApiError::new(
+					ErrorCode::Internal,
+					format!("failed to read the channel history: {}", e),
+				)
+				.error_response()
+			}
+		};
+	if samples.is_empty()
+		&& !node_var
+			.channel_manager
+			.list_channels()
+			.iter()
+			.any(|chan| chan.channel_id == channel_id)
+	{
+		// A closed channel's samples may just be outside the time range
+		let known = match (start_time, end_time) {
+			(None, None) => false,
+			_ => disk::read_channel_history(&*node_var.store, &channel_id, None, None)
+				.map_or(false, |samples| !samples.is_empty()),
+		};
+		if !known {
+			return ApiError::new(ErrorCode::NotFound, format!("unknown channel {}", path))
+				.error_response();
 		}
 	}
+	let history = ChannelBalanceHistory { channel_id: path.into_inner(), samples };
+	HttpResponse::Ok().content_type(ContentType::json()).json(history)
 }
 
-/// List payments
-async fn list_payments(node_var: web::Data<NodeVar<ServerEventHandler>>) -> HttpResponse {
-	let inbound = node_var.inbound_payments.lock().unwrap();
-	let outbound = node_var.outbound_payments.lock().unwrap();
+/// Summarize the routing fees earned over the last day, week and month from the forwarding
+/// ledger, in total and per open channel.
+///
+/// Forwards recorded without their outgoing channel, which LDK 0.0.106 doesn't report, only
+/// count towards the totals.
+#[utoipa::path(
+	get,
+	path = "/v1/feereport",
+	responses(
+		(status = 200, description = "Fees earned by forwarding", body = FeeReport),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn fee_report(node_var: web::Data<NodeVar<ServerEventHandler>>) -> HttpResponse {
+	const DAY_SECS: u64 = 24 * 60 * 60;
+	let now = unix_timestamp();
+	let forwards = match disk::read_forwards(
+		&*node_var.store,
+		Some(now.saturating_sub(30 * DAY_SECS)),
+		None,
+	) {
+		Ok(forwards) => forwards,
+		Err(e) => {
+			return ApiError::new(ErrorCode::Internal, format!("failed to read forwards: {}", e))
+				.error_response();
+		}
+	};
+
+	// Day, week and month sums of the fees of `forwards`, which are at most a month old
+	let fee_sums = |forwards: Vec<&ForwardInfo>| {
+		let mut sums = (0, 0, 0);
+		for forward in forwards {
+			let fee_msat = forward.fee_earned_msat.unwrap_or(0);
+			let age = now.saturating_sub(forward.timestamp);
+			if age <= DAY_SECS {
+				sums.0 += fee_msat;
+			}
+			if age <= 7 * DAY_SECS {
+				sums.1 += fee_msat;
+			}
+			sums.2 += fee_msat;
+		}
+		sums
+	};
 
-	// 1. create payments vector
-	let mut payments_vec: Vec<Payment> = Vec::new();
-	// 2. loop through inbound and outbound payments and append payments to vec
-	for (payment_hash, payment_info) in inbound.deref() {
-		let payment = Payment {
-			amount_millisatoshis: format!("{}", payment_info.amt_msat),
-			payment_hash: hex_utils::hex_str(&payment_hash.0),
-			htlc_direction: "inbound".to_string(),
-			htlc_status: match payment_info.status {
-				HTLCStatus::Pending => "pending".to_string(),
-				HTLCStatus::Succeeded => "succeeded".to_string(),
-				HTLCStatus::Failed => "failed".to_string(),
-			},
-		};
-		payments_vec.push(payment);
+	let our_node_id = NodeId::from_pubkey(&node_var.channel_manager.get_our_node_id());
+	let graph = node_var.network_graph.read_only();
+	let mut channel_fees = Vec::new();
+	for channel in node_var.channel_manager.list_channels() {
+		let channel_id = hex_utils::hex_str(&channel.channel_id);
+		let policy = channel
+			.short_channel_id
+			.and_then(|scid| graph.channels().get(&scid))
+			.and_then(|info| {
+				if info.node_one == our_node_id {
+					info.one_to_two.as_ref()
+				} else {
+					info.two_to_one.as_ref()
+				}
+			});
+		let (day_fee_sum_msat, week_fee_sum_msat, month_fee_sum_msat) = fee_sums(
+			forwards
+				.iter()
+				.filter(|forward| forward.next_channel_id.as_ref() == Some(&channel_id))
+				.collect(),
+		);
+		channel_fees.push(ChannelFeeReport {
+			channel_id,
+			peer_pubkey: channel.counterparty.node_id,
+			short_channel_id: channel.short_channel_id,
+			base_fee_msat: policy.map(|policy| policy.fees.base_msat),
+			fee_proportional_millionths: policy.map(|policy| policy.fees.proportional_millionths),
+			day_fee_sum_msat,
+			week_fee_sum_msat,
+			month_fee_sum_msat,
+		});
 	}
 
-	for (payment_hash, payment_info) in outbound.deref() {
-		let payment = Payment {
-			amount_millisatoshis: format!("{}", payment_info.amt_msat),
-			payment_hash: hex_utils::hex_str(&payment_hash.0),
-			htlc_direction: "outbound".to_string(),
-			htlc_status: match payment_info.status {
-				HTLCStatus::Pending => "pending".to_string(),
-				HTLCStatus::Succeeded => "succeeded".to_string(),
-				HTLCStatus::Failed => "failed".to_string(),
-			},
-		};
-		payments_vec.push(payment);
-	}
-	let payments = Payments { payments: payments_vec };
-	return HttpResponse::Ok().content_type(ContentType::json()).json(payments);
+	let (day_fee_sum_msat, week_fee_sum_msat, month_fee_sum_msat) =
+		fee_sums(forwards.iter().collect());
+	let report =
+		FeeReport { channel_fees, day_fee_sum_msat, week_fee_sum_msat, month_fee_sum_msat };
+	HttpResponse::Ok().content_type(ContentType::json()).json(report)
 }
 
-/// Sign a message
-async fn sign_message(
-	req: web::Json<SignMessage>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+/// Update the forwarding policy of the node's channels and the HTLCs they and its invoices
+/// accept
+///
+/// LDK 0.0.106 can't change the config of an existing channel, so the policy only applies to
+/// channels opened after the update (inbound ones after the next restart). Invoices created
+/// after the update follow it right away.
+async fn update_channel_policy(
+	req: web::Json<UpdateChannelPolicy>, node_var: web::Data<NodeVar<ServerEventHandler>>,
 ) -> HttpResponse {
-	let message = req.message.clone();
-	if message.len() == 0 {
-		let error = ServerError { error: format!("ERROR: signmsg requires a message") };
-		return HttpResponse::BadRequest().content_type(ContentType::json()).json(error);
-	} else {
-		let signed_msg = lightning::util::message_signing::sign(
-			message.as_bytes(),
-			&node_var.keys_manager.get_node_secret(Recipient::Node).unwrap(),
-		);
+	if let Some(channel_id) = &req.channel_id {
+		return ApiError::new(ErrorCode::NotImplemented, format!(
+				"cannot update the policy of channel {}: updating existing channels isn't supported yet, only the policy of new channels can be set",
+				channel_id
+			)).error_response();
+	}
 
-		match signed_msg {
-			Ok(signature) => {
-				let msg = ServerSuccess { msg: signature };
-				return HttpResponse::Ok().content_type(ContentType::json()).json(msg);
-			}
-			Err(e) => {
-				let error = ServerError { error: format!("ERROR: failed to sign message. {}", e) };
-				return HttpResponse::BadRequest().content_type(ContentType::json()).json(error);
+	let mut policy = node_var.channel_policy.lock().unwrap().clone();
+	let parse_error = |field: &str, value: &str| {
+		ApiError::new(ErrorCode::InvalidRequest, format!("invalid {}: {}", field, value))
+			.error_response()
+	};
+	if let Some(base_fee_msat) = &req.base_fee_msat {
+		match base_fee_msat.parse() {
+			Ok(base_fee_msat) => policy.forwarding_fee_base_msat = base_fee_msat,
+			Err(_) => return parse_error("base_fee_msat", base_fee_msat),
+		}
+	}
+	if let Some(fee_proportional_millionths) = &req.fee_proportional_millionths {
+		match fee_proportional_millionths.parse() {
+			Ok(fee) => policy.forwarding_fee_proportional_millionths = fee,
+			Err(_) => {
+				return parse_error("fee_proportional_millionths", fee_proportional_millionths)
 			}
 		}
 	}
+	if let Some(cltv_expiry_delta) = &req.cltv_expiry_delta {
+		match cltv_expiry_delta.parse::<u16>() {
+			// LDK silently raises lower deltas to its minimum, so reject them instead
+			Ok(delta) if delta >= MIN_CLTV_EXPIRY_DELTA => policy.cltv_expiry_delta = delta,
+			_ => return parse_error("cltv_expiry_delta", cltv_expiry_delta),
+		}
+	}
+	if let Some(htlc_minimum_msat) = &req.htlc_minimum_msat {
+		match htlc_minimum_msat.parse::<u64>() {
+			Ok(min) if min > 0 => policy.htlc_minimum_msat = Some(min),
+			_ => return parse_error("htlc_minimum_msat", htlc_minimum_msat),
+		}
+	}
+	if let Some(min_final_cltv_expiry) = &req.min_final_cltv_expiry {
+		match min_final_cltv_expiry.parse::<u32>() {
+			// LDK fails the HTLCs of received payments expiring sooner
+			Ok(expiry) if expiry >= MIN_FINAL_CLTV_EXPIRY => policy.min_final_cltv_expiry = expiry,
+			_ => return parse_error("min_final_cltv_expiry", min_final_cltv_expiry),
+		}
+	}
+
+	match disk::persist_channel_policy(&*node_var.store, &policy) {
+		Ok(()) => {
+			*node_var.channel_policy.lock().unwrap() = policy.clone();
+			HttpResponse::Ok().content_type(ContentType::json()).json(policy)
+		}
+		Err(e) => ApiError::new(
+			ErrorCode::Internal,
+			format!("failed to persist the channel policy: {}", e),
+		)
+		.error_response(),
+	}
 }
 
-/// closechannel
-async fn close_channel(
-	req: web::Json<Channel>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+/// Set the alias, color and public addresses the node announces, and broadcast the new node
+/// announcement right away
+async fn set_node_info(
+	req: web::Json<SetNodeInfo>, node_var: web::Data<NodeVar<ServerEventHandler>>,
 ) -> HttpResponse {
-	let channel_id_string = req.channel_id.clone();
-	if channel_id_string.len() == 0 {
-		let error = ServerError {
-			error: format!(
-				"ERROR: closechannel requires a channel ID: `closechannel <channel_id>`"
-			),
-		};
-		return HttpResponse::BadRequest().content_type(ContentType::json()).json(error);
+	let req = req.into_inner();
+	let mut current_info = node_var.node_announcement.lock().unwrap();
+	let mut info = current_info.clone();
+	if let Some(alias) = req.alias {
+		info.alias = alias;
+	}
+	if let Some(color) = req.color {
+		info.color = color.trim_start_matches('#').to_string();
 	}
+	if let Some(addresses) = req.addresses {
+		info.addresses = addresses;
+	}
+	let (color, alias, addresses) = match info.to_announcement() {
+		Ok(announcement) => announcement,
+		Err(e) => {
+			return ApiError::new(ErrorCode::InvalidRequest, e.to_string()).error_response();
+		}
+	};
 
-	let channel_id_vec = hex_utils::to_vec(channel_id_string.as_str());
-	if channel_id_vec.is_none() || channel_id_vec.as_ref().unwrap().len() != 32 {
-		let error = ServerError { error: format!("ERROR: couldn't parse channel_id") };
-		return HttpResponse::BadRequest().content_type(ContentType::json()).json(error);
+	if let Err(e) = disk::persist_node_announcement(&*node_var.store, &info) {
+		return ApiError::new(
+			ErrorCode::Internal,
+			format!("failed to persist the node announcement: {}", e),
+		)
+		.error_response();
 	}
+	node_var.channel_manager.broadcast_node_announcement(color, alias, addresses);
+	*current_info = info.clone();
+	HttpResponse::Ok().content_type(ContentType::json()).json(info)
+}
 
-	let mut channel_id = [0; 32];
-	channel_id.copy_from_slice(&channel_id_vec.unwrap());
-	let close_channel_res = cli::close_channel(channel_id, node_var.channel_manager.clone());
+/// Update the parameters of the routing scorer, responding with the parameters in use. The
+/// scorer keeps what it learned about channel liquidity.
+async fn scorer_params(
+	req: web::Json<SetScorerParams>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	let req = req.into_inner();
+	let mut current_params = node_var.scorer_params.lock().unwrap();
+	let mut params = current_params.clone();
+	if let Some(base_penalty_msat) = req.base_penalty_msat {
+		params.base_penalty_msat = base_penalty_msat;
+	}
+	if let Some(liquidity_penalty_multiplier_msat) = req.liquidity_penalty_multiplier_msat {
+		params.liquidity_penalty_multiplier_msat = liquidity_penalty_multiplier_msat;
+	}
+	if let Some(liquidity_offset_half_life_secs) = req.liquidity_offset_half_life_secs {
+		params.liquidity_offset_half_life_secs = liquidity_offset_half_life_secs;
+	}
+	if let Some(amount_penalty_multiplier_msat) = req.amount_penalty_multiplier_msat {
+		params.amount_penalty_multiplier_msat = amount_penalty_multiplier_msat;
+	}
+	let unchanged = req.base_penalty_msat.is_none()
+		&& req.liquidity_penalty_multiplier_msat.is_none()
+		&& req.liquidity_offset_half_life_secs.is_none()
+		&& req.amount_penalty_multiplier_msat.is_none();
+	if unchanged {
+		return HttpResponse::Ok().content_type(ContentType::json()).json(params);
+	}
 
-	match close_channel_res {
+	if let Err(e) = disk::persist_scorer_params(&*node_var.store, &params) {
+		return ApiError::new(
+			ErrorCode::Internal,
+			format!("failed to persist the scorer parameters: {}", e),
+		)
+		.error_response();
+	}
+	let mut scorer = node_var.scorer.lock().unwrap();
+	match disk::rebuild_scorer(&scorer, Arc::clone(&node_var.network_graph), &params) {
+		Ok(rebuilt) => *scorer = rebuilt,
 		Err(e) => {
-			let error = ServerError { error: format!("ERROR: failed to close channel => {:?}", e) };
-			return HttpResponse::BadRequest().content_type(ContentType::json()).json(error);
+			return ApiError::new(
+				ErrorCode::Internal,
+				format!("failed to rebuild the scorer: {}", e),
+			)
+			.error_response();
 		}
-		Ok(_) => {
-			let msg = ServerSuccess { msg: format!("EVENT: initiating channel close") };
-			return HttpResponse::Ok().content_type(ContentType::json()).json(msg);
+	}
+	*current_params = params.clone();
+	HttpResponse::Ok().content_type(ContentType::json()).json(params)
+}
+
+/// Change the levels of the logs without restarting the node, and return them. An empty request
+/// only returns them.
+async fn log_levels(
+	req: web::Json<SetLogLevels>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	let req = req.into_inner();
+	let mut levels = node_var.log_filters.levels();
+	if req.level.is_none() && req.ldk.is_none() && req.http.is_none() && req.chain.is_none() {
+		return HttpResponse::Ok().content_type(ContentType::json()).json(levels);
+	}
+	if let Some(level) = req.level {
+		levels.level = level;
+	}
+	if req.ldk.is_some() {
+		levels.ldk = req.ldk;
+	}
+	if req.http.is_some() {
+		levels.http = req.http;
+	}
+	if req.chain.is_some() {
+		levels.chain = req.chain;
+	}
+	if let Err(e) = node_var.log_filters.set_levels(levels.clone()) {
+		return ApiError::new(ErrorCode::InvalidRequest, e).error_response();
+	}
+	info!(?levels, "changed the log levels");
+	HttpResponse::Ok().content_type(ContentType::json()).json(levels)
+}
+
+/// Issue an API token with a scope, e.g. invoice-only for a web shop
+async fn issue_token(
+	req: web::Json<IssueToken>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	let req = req.into_inner();
+	let limits = SpendingLimits {
+		max_payment_msat: req.max_payment_msat,
+		daily_limit_msat: req.daily_limit_msat,
+	};
+	match node_var.api_tokens.issue(req.scope, req.label, limits) {
+		Ok((issued, token)) => {
+			info!(id = %issued.id, scope = ?issued.scope, "issued an API token");
+			HttpResponse::Ok().content_type(ContentType::json()).json(ApiTokenInfo {
+				id: issued.id,
+				scope: issued.scope,
+				label: issued.label,
+				created_at: issued.created_at,
+				limits: issued.limits,
+				token: Some(token),
+			})
+		}
+		Err(e) => {
+			ApiError::new(ErrorCode::Internal, format!("failed to issue the API token: {}", e))
+				.error_response()
 		}
 	}
 }
 
-/// forceclosechannel
-async fn force_close_channel(
-	req: web::Json<Channel>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+/// List the API tokens issued through the API, without the tokens themselves
+async fn list_tokens(node_var: web::Data<NodeVar<ServerEventHandler>>) -> HttpResponse {
+	let tokens = node_var
+		.api_tokens
+		.list()
+		.into_iter()
+		.map(|issued| ApiTokenInfo {
+			id: issued.id,
+			scope: issued.scope,
+			label: issued.label,
+			created_at: issued.created_at,
+			limits: issued.limits,
+			token: None,
+		})
+		.collect();
+	HttpResponse::Ok().content_type(ContentType::json()).json(ListTokens { tokens })
+}
+
+/// Revoke an API token issued through the API
+async fn revoke_token(
+	id: web::Path<String>, node_var: web::Data<NodeVar<ServerEventHandler>>,
 ) -> HttpResponse {
-	let channel_id_string = req.channel_id.clone();
-	if channel_id_string.len() == 0 {
-		let error = ServerError {
-			error: format!(
-				"ERROR: closechannel requires a channel ID: `closechannel <channel_id>`"
-			),
-		};
-		return HttpResponse::BadRequest().content_type(ContentType::json()).json(error);
+	match node_var.api_tokens.revoke(&id) {
+		Ok(true) => {
+			info!(id = %id, "revoked an API token");
+			let msg = ServerSuccess { msg: format!("EVENT: revoked API token {}", id) };
+			HttpResponse::Ok().content_type(ContentType::json()).json(msg)
+		}
+		Ok(false) => {
+			ApiError::new(ErrorCode::NotFound, format!("no API token {}", id)).error_response()
+		}
+		Err(e) => {
+			ApiError::new(ErrorCode::Internal, format!("failed to revoke the API token: {}", e))
+				.error_response()
+		}
 	}
+}
 
-	let channel_id_vec = hex_utils::to_vec(channel_id_string.as_str());
-	if channel_id_vec.is_none() || channel_id_vec.as_ref().unwrap().len() != 32 {
-		let error = ServerError { error: format!("ERROR: couldn't parse channel_id") };
-		return HttpResponse::BadRequest().content_type(ContentType::json()).json(error);
+/// Register a webhook notified of payment and channel events
+async fn add_webhook(
+	req: web::Json<AddWebhook>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	match reqwest::Url::parse(&req.url) {
+		Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {}
+		_ => {
+			return ApiError::new(
+				ErrorCode::InvalidRequest,
+				format!("invalid webhook URL {}", req.url),
+			)
+			.error_response();
+		}
 	}
 
-	let mut channel_id = [0; 32];
-	channel_id.copy_from_slice(&channel_id_vec.unwrap());
+	let req = req.into_inner();
+	match node_var.webhooks.add(req.url, req.secret) {
+		Ok(webhook) => {
+			let webhook =
+				WebhookInfo { id: webhook.id, url: webhook.url, secret: Some(webhook.secret) };
+			HttpResponse::Ok().content_type(ContentType::json()).json(webhook)
+		}
+		Err(e) => ApiError::new(ErrorCode::Internal, format!("failed to persist webhook: {}", e))
+			.error_response(),
+	}
+}
 
-	let close_channel_res = cli::force_close_channel(channel_id, node_var.channel_manager.clone());
+/// List the webhooks notified of node events
+#[utoipa::path(
+	get,
+	path = "/v1/webhooks",
+	responses(
+		(status = 200, description = "Registered webhooks", body = ListWebhooks),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn list_webhooks(node_var: web::Data<NodeVar<ServerEventHandler>>) -> HttpResponse {
+	match node_var.webhooks.list() {
+		Ok(webhooks) => {
+			let webhooks = webhooks
+				.into_iter()
+				.map(|webhook| WebhookInfo { id: webhook.id, url: webhook.url, secret: None })
+				.collect();
+			HttpResponse::Ok().content_type(ContentType::json()).json(ListWebhooks { webhooks })
+		}
+		Err(e) => ApiError::new(ErrorCode::Internal, format!("failed to read webhooks: {}", e))
+			.error_response(),
+	}
+}
 
-	match close_channel_res {
+/// Remove a webhook registered through the API
+async fn remove_webhook(
+	req: web::Json<RemoveWebhook>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	match node_var.webhooks.remove(&req.id) {
+		Ok(true) => {
+			let msg = ServerSuccess { msg: format!("EVENT: removed webhook {}", req.id) };
+			HttpResponse::Ok().content_type(ContentType::json()).json(msg)
+		}
+		Ok(false) => {
+			ApiError::new(ErrorCode::NotFound, format!(
+					"no webhook {} registered through the API. Webhooks from the config file can only be removed there",
+					req.id
+				)).error_response()
+		}
 		Err(e) => {
-			let error =
-				ServerError { error: format!("ERROR: failed to force-close channel => {:?}", e) };
-			return HttpResponse::BadRequest().content_type(ContentType::json()).json(error);
+			ApiError::new(ErrorCode::Internal, format!("failed to remove webhook: {}", e)).error_response()
 		}
-		Ok(_) => {
-			let msg = ServerSuccess { msg: format!("EVENT: initiating channel force-close") };
-			return HttpResponse::Ok().content_type(ContentType::json()).json(msg);
+	}
+}
+
+/// Stream node events to the subscriber as server-sent events
+#[utoipa::path(
+	get,
+	path = "/v1/events",
+	responses(
+		(status = 200, description = "Stream of node events", body = String, content_type = "text/event-stream"),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn events(node_var: web::Data<NodeVar<ServerEventHandler>>) -> HttpResponse {
+	let receiver = node_var.event_sender.subscribe();
+	let stream = futures::stream::unfold(receiver, |mut receiver| async move {
+		let message = match receiver.recv().await {
+			Ok(event) => format!("data: {}\n\n", serde_json::to_string(&event).unwrap()),
+			// Let the subscriber know it missed events instead of silently skipping them
+			Err(broadcast::error::RecvError::Lagged(missed)) => {
+				format!("event: lagged\ndata: {}\n\n", missed)
+			}
+			Err(broadcast::error::RecvError::Closed) => return None,
+		};
+		Some((Ok::<_, actix_web::Error>(web::Bytes::from(message)), receiver))
+	});
+	HttpResponse::Ok().content_type("text/event-stream").streaming(stream)
+}
+
+/// List the LDK events the node handled, with what it did about them
+async fn event_history(
+	req: web::Json<EventHistory>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> Result<HttpResponse, ApiError> {
+	let bad_request = |msg: String| ApiError::new(ErrorCode::InvalidRequest, msg);
+	let parse_number = |field: &str, value: &Option<String>| match value {
+		Some(value) => value
+			.parse::<u64>()
+			.map(Some)
+			.map_err(|_| bad_request(format!("{} must be a number: {}", field, value))),
+		None => Ok(None),
+	};
+	let filter = JournalFilter {
+		event_type: req.event_type.clone(),
+		payment_hash: req.payment_hash.as_ref().map(|hash| hash.to_lowercase()),
+		channel_id: req.channel_id.as_ref().map(|id| id.to_lowercase()),
+		start_time: parse_number("start_time", &req.start_time)?,
+		end_time: parse_number("end_time", &req.end_time)?,
+	};
+	let offset = parse_number("offset", &req.offset)?.unwrap_or(0) as usize;
+	let limit = match parse_number("limit", &req.limit)? {
+		Some(limit) if limit == 0 || limit as usize > MAX_LIST_PAYMENTS_LIMIT => {
+			return Err(bad_request(format!(
+				"limit must be between 1 and {}: {}",
+				MAX_LIST_PAYMENTS_LIMIT, limit
+			)))
+		}
+		Some(limit) => limit as usize,
+		None => MAX_LIST_PAYMENTS_LIMIT,
+	};
+
+	let entries = node_var.event_journal.entries(&filter).map_err(|e| {
+		ApiError::new(ErrorCode::Internal, format!("unable to read the event journal: {}", e))
+	})?;
+	let total_events = entries.len();
+	let events = entries.into_iter().skip(offset).take(limit).collect();
+	Ok(HttpResponse::Ok()
+		.content_type(ContentType::json())
+		.json(JournaledEvents { events, total_events }))
+}
+
+// The v1 API reads with GET, taking the parameters of the legacy endpoints from the query string
+// and naming resources in the path. These adapt the legacy handlers to that.
+
+#[utoipa::path(
+	get,
+	path = "/v1/events/history",
+	params(EventHistory),
+	responses(
+		(status = 200, description = "Handled events matching the filters", body = JournaledEvents),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn v1_event_history(
+	req: web::Query<EventHistory>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> Result<HttpResponse, ApiError> {
+	event_history(web::Json(req.into_inner()), node_var).await
+}
+
+#[utoipa::path(
+	get,
+	path = "/v1/payments",
+	params(ListPayments),
+	responses(
+		(status = 200, description = "Payments matching the filters", body = Payments),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn v1_list_payments(
+	req: web::Query<ListPayments>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	list_payments(web::Json(req.into_inner()), node_var).await
+}
+
+#[utoipa::path(
+	get,
+	path = "/v1/payments/hash/{payment_hash}",
+	params(("payment_hash" = String, Path, description = "Hex-encoded payment hash")),
+	responses(
+		(status = 200, description = "Payment details", body = PaymentDetails),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn v1_lookup_payment(
+	path: web::Path<String>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	let req = LookupPayment { payment_hash: path.into_inner() };
+	lookup_payment(web::Json(req), node_var).await
+}
+
+#[utoipa::path(
+	get,
+	path = "/v1/invoices/decode",
+	params(("invoice" = String, Query, description = "BOLT 11 invoice")),
+	responses(
+		(status = 200, description = "Decoded invoice", body = DecodedInvoice),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn v1_decode_invoice(req: web::Query<ServerInvoice>) -> HttpResponse {
+	decode_invoice(web::Json(req.into_inner())).await
+}
+
+#[utoipa::path(
+	post,
+	path = "/v1/invoices/{payment_hash}/cancel",
+	params(("payment_hash" = String, Path, description = "Hex-encoded payment hash")),
+	responses(
+		(status = 200, description = "Invoice canceled", body = ServerSuccess),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn v1_cancel_invoice(
+	path: web::Path<String>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	let req = CancelInvoice { payment_hash: path.into_inner() };
+	cancel_invoice(web::Json(req), node_var).await
+}
+
+#[utoipa::path(
+	get,
+	path = "/v1/routes",
+	params(QueryRoutes),
+	responses(
+		(status = 200, description = "Routes to the destination", body = QueriedRoutes),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn v1_query_routes(
+	req: web::Query<QueryRoutes>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	query_routes(web::Json(req.into_inner()), node_var).await
+}
+
+#[utoipa::path(
+	get,
+	path = "/v1/forwards",
+	params(ListForwards),
+	responses(
+		(status = 200, description = "Forwarded payments", body = Forwards),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn v1_list_forwards(
+	req: web::Query<ListForwards>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	list_forwards(web::Json(req.into_inner()), node_var).await
+}
+
+#[utoipa::path(
+	get,
+	path = "/v1/channels/{channel_id}/history",
+	params(("channel_id" = String, Path, description = "Hex-encoded channel ID"), ChannelHistory),
+	responses(
+		(status = 200, description = "Balances of the channel over time", body = ChannelBalanceHistory),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn v1_channel_history(
+	path: web::Path<String>, req: web::Query<ChannelHistory>,
+	node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	channel_history(path, web::Json(req.into_inner()), node_var).await
+}
+
+#[utoipa::path(
+	get,
+	path = "/v1/export/payments",
+	params(Export),
+	responses(
+		(status = 200, description = "Payments as CSV or JSON lines", body = String, content_type = "text/csv"),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn v1_export_payments(
+	req: web::Query<Export>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	export_payments(web::Json(req.into_inner()), node_var).await
+}
+
+#[utoipa::path(
+	get,
+	path = "/v1/export/forwards",
+	params(Export),
+	responses(
+		(status = 200, description = "Forwards as CSV or JSON lines", body = String, content_type = "text/csv"),
+		(status = "4XX", description = "The request was rejected", body = ServerError),
+		(status = "5XX", description = "The request failed", body = ServerError),
+	)
+)]
+async fn v1_export_forwards(
+	req: web::Query<Export>, node_var: web::Data<NodeVar<ServerEventHandler>>,
+) -> HttpResponse {
+	export_forwards(web::Json(req.into_inner()), node_var).await
+}
+
+/// Routes of the v1 API, served under `/v1`. Static segments are registered before the
+/// parameters they'd otherwise match, as `/channels/closed` before `/channels/{channel_id}`.
+fn v1_routes(cfg: &mut web::ServiceConfig) {
+	cfg.route("/help", web::get().to(help))
+		.route("/node", web::get().to(nodeinfo))
+		.route("/health", web::get().to(health))
+		.route("/feerates", web::get().to(fee_rates))
+		.route("/peers", web::get().to(list_peers))
+		.route("/peers", web::post().to(connect_peer))
+		.route("/channels", web::get().to(list_channels))
+		.route("/channels", web::post().to(open_channel))
+		.route("/channels/closed", web::get().to(closed_channels))
+		.route("/channels/{channel_id}", web::get().to(diagnose_channel))
+		.route("/channels/{channel_id}/label", web::post().to(set_channel_label))
+		.route("/channels/{channel_id}/history", web::get().to(v1_channel_history))
+		.route("/invoices", web::get().to(list_invoices))
+		.route("/invoices", web::post().to(get_invoice))
+		.route("/invoices/decode", web::get().to(v1_decode_invoice))
+		.route("/invoices/{payment_hash}/cancel", web::post().to(v1_cancel_invoice))
+		.route("/payments", web::get().to(v1_list_payments))
+		.route("/payments", web::post().to(send_payment))
+		.route("/payments/hash/{payment_hash}", web::get().to(v1_lookup_payment))
+		.route("/payments/pending-approval", web::get().to(pending_approvals))
+		.route("/autopilot/status", web::get().to(autopilot_status))
+		.route("/autopilot/enable", web::post().to(enable_autopilot))
+		.route("/autopilot/disable", web::post().to(disable_autopilot))
+		.route("/lsp/info", web::get().to(lsp_info))
+		.route("/lsp/order", web::post().to(create_lsp_order))
+		.route("/lsp/order/{order_id}", web::get().to(lsp_order))
+		.route("/payments/{approval_id}/approve", web::post().to(approve_payment))
+		.route("/payments/{approval_id}/reject", web::post().to(reject_payment))
+		.route("/payments/{payment_id}", web::get().to(payment_status))
+		.route("/payments/{payment_id}/cancel", web::post().to(cancel_payment))
+		.route("/routes", web::get().to(v1_query_routes))
+		.route("/forwards", web::get().to(v1_list_forwards))
+		.route("/feereport", web::get().to(fee_report))
+		.route("/export/payments", web::get().to(v1_export_payments))
+		.route("/export/forwards", web::get().to(v1_export_forwards))
+		.route("/balance", web::get().to(balance))
+		.route("/wallet/balance", web::get().to(wallet_balance))
+		.route("/wallet/utxos", web::get().to(list_utxos))
+		.route("/pendingtxs", web::get().to(pending_txs))
+		.route("/wallet/address", web::post().to(new_address))
+		.route("/webhooks", web::get().to(list_webhooks))
+		.route("/events", web::get().to(events))
+		.route("/events/history", web::get().to(v1_event_history))
+		.route("/phantom/routehints", web::get().to(phantom_route_hints));
+}
+
+/// What the endpoint at `pattern` does, deciding which token scopes may call it. Endpoints which
+/// aren't listed move funds or change the node's configuration, and need full access.
+fn route_access(method: &Method, pattern: &str) -> Access {
+	match pattern {
+		"/getinvoice"
+		| "/getphantominvoice"
+		| "/unifiedinvoice"
+		| "/cancelinvoice"
+		| "/v1/invoices/{payment_hash}/cancel" => Access::InvoiceWrite,
+		"/v1/invoices" if method == Method::POST => Access::InvoiceWrite,
+		"/listinvoices" | "/decodeinvoice" | "/v1/invoices" | "/v1/invoices/decode" => {
+			Access::InvoiceRead
 		}
+		"/help"
+		| "/nodeinfo"
+		| "/health"
+		| "/feerates"
+		| "/listpeers"
+		| "/listchannels"
+		| "/closedchannels"
+		| "/diagnose/channel/{channel_id}"
+		| "/channels/{channel_id}/history"
+		| "/phantomroutehints"
+		| "/listpayments"
+		| "/lookuppayment"
+		| "/payments/{payment_id}"
+		| "/payments/pending-approval"
+		| "/autopilot/status"
+		| "/lsp/info"
+		| "/lsp/order/{order_id}"
+		| "/queryroutes"
+		| "/listforwards"
+		| "/feereport"
+		| "/export/payments"
+		| "/export/forwards"
+		| "/walletbalance"
+		| "/balance"
+		| "/listutxos"
+		| "/pendingtxs"
+		| "/listwebhooks"
+		| "/verifymessage"
+		| "/events"
+		| "/events/history" => Access::Read,
+		_ if pattern.starts_with("/v1/") && method == Method::GET => Access::Read,
+		_ => Access::Write,
+	}
+}
+
+/// Whether the endpoint at `pattern` moves funds without the amounts being counted against
+/// spending limits, or could lift them, so that tokens with spending limits can't call it
+fn escapes_spending_limits(method: &Method, pattern: &str) -> bool {
+	match pattern {
+		"/openchannel"
+		| "/openchannels"
+		| "/closechannel"
+		| "/forceclosechannel"
+		| "/closeallchannels"
+		| "/abandonchannel"
+		| "/autopilot/enable"
+		| "/v1/autopilot/enable"
+		| "/sendonchain"
+		| "/sendtoroute"
+		| "/rebalance"
+		| "/seed/backup"
+		| "/tokens"
+		| "/tokens/{id}"
+		| "/payments/{approval_id}/approve"
+		| "/v1/payments/{approval_id}/approve" => true,
+		"/v1/channels" => method == Method::POST,
+		_ => false,
 	}
 }
 
 /// Run the server
-pub fn run(node_var: NodeVar<ServerEventHandler>, addr: &str) -> Result<Server, std::io::Error> {
-	let node_var = web::Data::new(node_var);
+pub fn run(
+	node_var: Arc<NodeVar<ServerEventHandler>>, addr: &str,
+	tls_config: Option<rustls::ServerConfig>,
+) -> Result<Server, std::io::Error> {
+	let node_var = web::Data::from(node_var);
 	// let listener = TcpListener::bind(addr).expect("Failed to bind on random port");
 	// let port = listener.local_addr().unwrap().port();
 
-	println!("Server port: {}", addr);
+	info!("server port: {}", addr);
 
 	let server = HttpServer::new(move || {
+		let api_tokens = Arc::clone(&node_var.api_tokens);
 		App::new()
+			// Reject requests without an API token whose scope covers the endpoint. Only the
+			// description of the API is public. Every request is logged under its own ID, which is
+			// also sent back as the X-Request-Id header.
+			.wrap_fn(move |req, srv| {
+				let request_id = logging::new_request_id();
+				let span = info_span!(
+					"request",
+					request_id = %request_id,
+					method = %req.method(),
+					path = %req.path()
+				);
+				let caller = auth::request_caller(&req, &api_tokens);
+				let pattern = req.match_pattern().unwrap_or_else(|| req.path().to_string());
+				let access = route_access(req.method(), &pattern);
+				let error = match &caller {
+					_ if openapi::is_public_path(req.path()) => None,
+					Some(caller) if !caller.scope.allows(access) => {
+						warn!(parent: &span, scope = ?caller.scope, "rejected request outside the token's scope");
+						Some(ApiError::new(
+							ErrorCode::Forbidden,
+							format!("the API token's scope doesn't allow {}", req.path()),
+						))
+					}
+					Some(caller)
+						if caller.limits.is_limited()
+							&& escapes_spending_limits(req.method(), &pattern) =>
+					{
+						warn!(parent: &span, "rejected request escaping the token's spending limits");
+						Some(ApiError::new(
+							ErrorCode::Forbidden,
+							format!("tokens with spending limits can't call {}", req.path()),
+						))
+					}
+					Some(_) => None,
+					None => {
+						warn!(parent: &span, "rejected request without a valid API token");
+						Some(ApiError::new(
+							ErrorCode::Unauthorized,
+							"missing or invalid API token".to_string(),
+						))
+					}
+				};
+				let response = match error {
+					None => {
+						// Handlers which spend funds check the caller's spending limits
+						if let Some(caller) = caller {
+							req.extensions_mut().insert(caller);
+						}
+						Either::Left(srv.call(req))
+					}
+					Some(error) => Either::Right(ready(Err(InternalError::from_response(
+						"unauthorized",
+						error.error_response(),
+					)
+					.into()))),
+				};
+				logging::with_request_id(request_id.clone(), async move {
+					let mut res = response.await?;
+					info!(status = res.status().as_u16(), "served request");
+					res.headers_mut().insert(
+						header::HeaderName::from_static("x-request-id"),
+						header::HeaderValue::from_str(&request_id)
+							.expect("hex request IDs are valid header values"),
+					);
+					Ok::<_, actix_web::Error>(res)
+				})
+				.instrument(span)
+			})
+			// Malformed request bodies are answered like the handlers' own errors
+			.app_data(web::JsonConfig::default().error_handler(|err, _req| {
+				ApiError::new(ErrorCode::InvalidRequest, format!("invalid request body: {}", err))
+					.into()
+			}))
+			.app_data(web::QueryConfig::default().error_handler(|err, _req| {
+				ApiError::new(ErrorCode::InvalidRequest, format!("invalid query string: {}", err))
+					.into()
+			}))
+			.service(web::scope("/v1").configure(v1_routes))
+			.configure(openapi::configure)
 			.route("/nodeinfo", web::post().to(nodeinfo))
 			.route("/connectpeer", web::post().to(connect_peer))
 			.route("/openchannel", web::post().to(open_channel))
+			.route("/openchannels", web::post().to(open_channels))
 			.route("/help", web::post().to(help))
 			.route("/listchannels", web::post().to(list_channels))
+			.route("/closedchannels", web::post().to(closed_channels))
 			.route("/listpeers", web::post().to(list_peers))
 			.route("/getinvoice", web::post().to(get_invoice))
+			.route("/getphantominvoice", web::post().to(get_phantom_invoice))
+			.route("/phantomroutehints", web::post().to(phantom_route_hints))
+			.route("/withdraw", web::post().to(withdraw))
+			.route("/unifiedinvoice", web::post().to(unified_invoice))
+			.route("/listinvoices", web::post().to(list_invoices))
+			.route("/cancelinvoice", web::post().to(cancel_invoice))
 			.route("/sendpayment", web::post().to(send_payment))
+			.route("/keysend", web::post().to(keysend))
+			.route("/decodeinvoice", web::post().to(decode_invoice))
 			.route("/listpayments", web::post().to(list_payments))
+			.route("/lookuppayment", web::post().to(lookup_payment))
+			.route("/payments/pending-approval", web::post().to(pending_approvals))
+			.route("/autopilot/status", web::post().to(autopilot_status))
+			.route("/autopilot/enable", web::post().to(enable_autopilot))
+			.route("/autopilot/disable", web::post().to(disable_autopilot))
+			.route("/lsp/info", web::post().to(lsp_info))
+			.route("/lsp/order", web::post().to(create_lsp_order))
+			.route("/lsp/order/{order_id}", web::post().to(lsp_order))
+			.route("/payments/{approval_id}/approve", web::post().to(approve_payment))
+			.route("/payments/{approval_id}/reject", web::post().to(reject_payment))
+			.route("/payments/{payment_id}", web::post().to(payment_status))
+			.route("/payments/{payment_id}/cancel", web::post().to(cancel_payment))
 			.route("/signmessage", web::post().to(sign_message))
+			.route("/verifymessage", web::post().to(verify_message))
 			.route("/closechannel", web::post().to(close_channel))
 			.route("/forceclosechannel", web::post().to(force_close_channel))
+			.route("/closeallchannels", web::post().to(close_all_channels))
+			.route("/abandonchannel", web::post().to(abandon_channel))
+			.route("/setpeerlabel", web::post().to(set_peer_label))
+			.route("/channels/{channel_id}/label", web::post().to(set_channel_label))
+			.route("/channels/{channel_id}/history", web::post().to(channel_history))
+			.route("/diagnose/channel/{channel_id}", web::post().to(diagnose_channel))
+			.route("/newaddress", web::post().to(new_address))
+			.route("/walletbalance", web::post().to(wallet_balance))
+			.route("/balance", web::post().to(balance))
+			.route("/sendonchain", web::post().to(send_onchain))
+			.route("/listutxos", web::post().to(list_utxos))
+			.route("/pendingtxs", web::post().to(pending_txs))
+			.route("/events", web::post().to(events))
+			.route("/events/history", web::post().to(event_history))
+			.route("/listforwards", web::post().to(list_forwards))
+			.route("/feereport", web::post().to(fee_report))
+			.route("/updatechannelpolicy", web::post().to(update_channel_policy))
+			.route("/setnodeinfo", web::post().to(set_node_info))
+			.route("/addwebhook", web::post().to(add_webhook))
+			.route("/listwebhooks", web::post().to(list_webhooks))
+			.route("/removewebhook", web::post().to(remove_webhook))
+			.route("/backup/export", web::post().to(export_backup))
+			.route("/export/payments", web::post().to(export_payments))
+			.route("/export/forwards", web::post().to(export_forwards))
+			.route("/seed/backup", web::post().to(backup_seed))
+			.route("/health", web::post().to(health))
+			.route("/feerates", web::post().to(fee_rates))
+			.route("/queryroutes", web::post().to(query_routes))
+			.route("/sendtoroute", web::post().to(send_to_route))
+			.route("/rebalance", web::post().to(rebalance))
+			.route("/probe", web::post().to(probe))
+			.route("/scorer/params", web::post().to(scorer_params))
+			.route("/admin/loglevel", web::post().to(log_levels))
+			.route("/tokens", web::get().to(list_tokens))
+			.route("/tokens", web::post().to(issue_token))
+			.route("/tokens/{id}", web::delete().to(revoke_token))
 			.app_data(node_var.clone())
-	})
-	.bind(addr)?
+	});
+	let server = match tls_config {
+		Some(tls_config) => server.bind_rustls(addr, tls_config)?,
+		None => server.bind(addr)?,
+	}
 	.run();
 
 	Ok(server)
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn route_access_separates_invoice_read_and_write_endpoints() {
+		assert_eq!(route_access(&Method::POST, "/getinvoice"), Access::InvoiceWrite);
+		assert_eq!(route_access(&Method::POST, "/v1/invoices"), Access::InvoiceWrite);
+		assert_eq!(route_access(&Method::GET, "/v1/invoices"), Access::InvoiceRead);
+		assert_eq!(route_access(&Method::POST, "/listinvoices"), Access::InvoiceRead);
+		assert_eq!(
+			route_access(&Method::POST, "/v1/invoices/{payment_hash}/cancel"),
+			Access::InvoiceWrite
+		);
+	}
+
+	#[test]
+	fn route_access_defaults_to_write() {
+		assert_eq!(route_access(&Method::POST, "/nodeinfo"), Access::Read);
+		assert_eq!(route_access(&Method::GET, "/v1/channels"), Access::Read);
+		assert_eq!(route_access(&Method::POST, "/v1/channels"), Access::Write);
+		assert_eq!(route_access(&Method::POST, "/sendpayment"), Access::Write);
+		// Endpoints nobody classified yet must not be open to read-only tokens
+		assert_eq!(route_access(&Method::POST, "/someday"), Access::Write);
+		assert!(!TokenScope::ReadOnly.allows(route_access(&Method::POST, "/tokens")));
+		assert!(!TokenScope::Invoice.allows(route_access(&Method::POST, "/walletbalance")));
+	}
+}
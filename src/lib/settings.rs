@@ -0,0 +1,476 @@
+use config::{Config, Environment, File};
+use serde::Deserialize;
+use std::io;
+
+/// Name of the optional config file in the node's data directory, without its extension. Any
+/// format supported by the `config` crate can be used, e.g. `lnnode.toml`.
+pub const CONFIG_FILE_NAME: &str = "lnnode";
+
+/// Prefix of the environment variables overriding settings, e.g. `LNNODE_BITCOIND__RPC_PORT`
+/// for the `rpc_port` of the `[bitcoind]` section
+pub const ENV_PREFIX: &str = "LNNODE";
+
+/// Node settings read from `<ldk_data_dir>/lnnode.<ext>`, or from the file given with
+/// `--config`. The bitcoind credentials and the data directory have to be set by the file, the
+/// environment or startup flags, every other setting has a default.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+	pub node: NodeSettings,
+	pub bitcoind: BitcoindSettings,
+	pub chain: ChainSettings,
+	pub fees: FeeSettings,
+	pub server: ServerSettings,
+	pub webhooks: Vec<WebhookSettings>,
+	pub channel_acceptance: ChannelAcceptanceSettings,
+	pub sweeper: SweeperSettings,
+	pub monitor_replica: MonitorReplicaSettings,
+	pub tor: TorSettings,
+	pub p2p: P2pSettings,
+	pub announcement: AnnouncementSettings,
+	pub scorer: ScorerSettings,
+	pub phantom: PhantomSettings,
+	pub zaps: ZapSettings,
+	pub grpc: GrpcSettings,
+	pub logging: LoggingSettings,
+	pub approval: ApprovalSettings,
+	pub autopilot: AutopilotSettings,
+	pub lsp: LspSettings,
+	pub channels: ChannelSettings,
+}
+
+/// Settings of the node itself
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct NodeSettings {
+	/// Directory the node's data is stored in, under `.ldk`
+	pub data_dir: Option<String>,
+	/// One of `testnet`, `regtest` or `signet`
+	pub network: String,
+	/// Port peers connect to
+	pub listening_port: u16,
+}
+
+impl Default for NodeSettings {
+	fn default() -> Self {
+		Self { data_dir: None, network: "testnet".to_string(), listening_port: 9735 }
+	}
+}
+
+/// Settings of the connection to bitcoind's RPC server
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct BitcoindSettings {
+	pub rpc_host: String,
+	/// Defaults to bitcoind's RPC port on the node's network
+	pub rpc_port: Option<u16>,
+	pub rpc_username: Option<String>,
+	pub rpc_password: Option<String>,
+}
+
+impl Default for BitcoindSettings {
+	fn default() -> Self {
+		Self {
+			rpc_host: "127.0.0.1".to_string(),
+			rpc_port: None,
+			rpc_username: None,
+			rpc_password: None,
+		}
+	}
+}
+
+/// Backend LDK is synced from, which also estimates fees and broadcasts transactions. The
+/// on-chain wallet is bitcoind's when syncing from bitcoind, and is otherwise derived from the
+/// node's seed and looked up in the Esplora or Electrum server's index, so that bitcoind isn't
+/// needed at all.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ChainSettings {
+	pub backend: ChainBackend,
+	/// Base URL of the Esplora API, e.g. `https://blockstream.info/testnet/api`
+	pub esplora_url: Option<String>,
+	/// Address of the Electrum server's TCP port, e.g. `127.0.0.1:50001`
+	pub electrum_url: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChainBackend {
+	Bitcoind,
+	Esplora,
+	Electrum,
+}
+
+impl Default for ChainBackend {
+	fn default() -> Self {
+		ChainBackend::Bitcoind
+	}
+}
+
+/// Fee rates, in satoshis per 1000 weight units, used until bitcoind, or the Esplora or
+/// Electrum server, has enough data to estimate them
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct FeeSettings {
+	pub background_sat_per_kw: u32,
+	pub normal_sat_per_kw: u32,
+	pub high_priority_sat_per_kw: u32,
+	/// Interval at which the fee estimates are refreshed
+	pub poll_interval_secs: u64,
+}
+
+impl Default for FeeSettings {
+	fn default() -> Self {
+		Self {
+			background_sat_per_kw: 253,
+			normal_sat_per_kw: 2000,
+			high_priority_sat_per_kw: 5000,
+			poll_interval_secs: 60,
+		}
+	}
+}
+
+/// Settings of the HTTP API server
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ServerSettings {
+	/// Address the API is served on, only reachable from localhost by default
+	pub listen_address: String,
+	/// Serve the API over HTTPS. Only disable this if the API is never reached beyond localhost.
+	pub tls_enabled: bool,
+	/// PEM certificate to serve. A self-signed certificate is generated in the data directory
+	/// if neither the certificate nor the key are configured.
+	pub tls_cert_path: Option<String>,
+	/// PEM PKCS#8 private key of `tls_cert_path`
+	pub tls_key_path: Option<String>,
+}
+
+impl Default for ServerSettings {
+	fn default() -> Self {
+		Self {
+			listen_address: "127.0.0.1:33335".to_string(),
+			tls_enabled: true,
+			tls_cert_path: None,
+			tls_key_path: None,
+		}
+	}
+}
+
+/// A webhook notified of payment and channel events, configured as a `[[webhooks]]` entry
+#[derive(Debug, Deserialize)]
+pub struct WebhookSettings {
+	pub url: String,
+	/// Key of the HMAC-SHA256 signature of each notification
+	pub secret: String,
+}
+
+/// Policy for channels opened by peers. Without any setting, every inbound channel is accepted.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ChannelAcceptanceSettings {
+	pub min_channel_size_sat: Option<u64>,
+	pub max_channels_per_peer: Option<usize>,
+	/// Public keys of the only peers allowed to open channels, unless empty
+	pub allowlist: Vec<String>,
+	/// Public keys of the peers never allowed to open channels
+	pub blocklist: Vec<String>,
+	pub private_channels: PrivateChannels,
+}
+
+/// Whether peers may open private, i.e. unannounced, channels
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PrivateChannels {
+	/// Accept both private and announced channels
+	Accept,
+	/// Only accept private channels
+	Require,
+	/// Only accept announced channels
+	Reject,
+}
+
+impl Default for PrivateChannels {
+	fn default() -> Self {
+		PrivateChannels::Accept
+	}
+}
+
+/// Settings of the sweeping of outputs claimable from closed channels
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SweeperSettings {
+	/// Address outputs are swept to instead of the node's wallet
+	pub destination_address: Option<String>,
+}
+
+/// Settings of the replication of channel monitors
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct MonitorReplicaSettings {
+	/// Directory every channel monitor write is mirrored to, ideally on another disk. Remote
+	/// storage such as SFTP or S3 can be used by mounting it, e.g. with sshfs or s3fs.
+	pub directory: Option<String>,
+}
+
+/// Settings for running the node over Tor
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TorSettings {
+	/// SOCKS5 proxy every outbound peer connection goes through, e.g. `127.0.0.1:9050`
+	pub socks_proxy: Option<String>,
+	/// Hidden service forwarding to the node's peer port, e.g. `<v3 address>.onion:9735`,
+	/// announced along with the listen addresses given at startup
+	pub onion_address: Option<String>,
+}
+
+/// Settings of the connections peers open to the node
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct P2pSettings {
+	/// Addresses peers connect to, e.g. `0.0.0.0:9735` and `[::]:9735`, defaulting to every
+	/// IPv4 address at the node's `listening_port`
+	pub listen_addresses: Vec<String>,
+	/// Accept connections from peers. Without, the node runs in client-only mode and only
+	/// connects out to peers.
+	pub accept_inbound: bool,
+	/// Maximum number of inbound connections at once
+	pub max_inbound_connections: Option<usize>,
+	/// Maximum number of inbound connections from a single IP address at once
+	pub max_inbound_connections_per_ip: Option<usize>,
+}
+
+impl Default for P2pSettings {
+	fn default() -> Self {
+		Self {
+			listen_addresses: Vec::new(),
+			accept_inbound: true,
+			max_inbound_connections: None,
+			max_inbound_connections_per_ip: None,
+		}
+	}
+}
+
+/// Settings of the node announcement, overriding the alias and listen addresses given at
+/// startup. Once set through `/setnodeinfo`, the announcement is taken from the node store.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct AnnouncementSettings {
+	pub alias: Option<String>,
+	/// RGB color as 6 hex digits, e.g. `3399ff`
+	pub color: Option<String>,
+	/// Public listening addresses, as `ip:port` or `<v3 address>.onion:port`
+	pub addresses: Vec<String>,
+}
+
+/// Parameters of the routing scorer, defaulting to LDK's. Once set through `/scorer/params`,
+/// the parameters are taken from the node store.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ScorerSettings {
+	pub base_penalty_msat: Option<u64>,
+	pub liquidity_penalty_multiplier_msat: Option<u64>,
+	pub liquidity_offset_half_life_secs: Option<u64>,
+	pub amount_penalty_multiplier_msat: Option<u64>,
+}
+
+/// Settings of phantom node payments, which let every node configured with the same secret
+/// claim the invoices created with `/getphantominvoice` by any of them
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PhantomSettings {
+	/// Enabling or disabling phantom node payments invalidates the unpaid invoices created before
+	pub enabled: bool,
+	/// 32 bytes as 64 hex digits, the node's own seed if unset. Changing it invalidates the unpaid
+	/// invoices created before.
+	pub secret: Option<String>,
+}
+
+/// Settings of the Nostr zap (NIP-57) receiver, which serves a lightning address over LNURL-pay
+/// and publishes zap receipts. It is enabled by setting `base_url` and `nostr_secret_key`.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ZapSettings {
+	/// Public URL the zap server is reached at, e.g. `https://example.com` behind a reverse proxy
+	/// forwarding to `listen_address`. The lightning address is `<username>@<host>`.
+	pub base_url: Option<String>,
+	/// Address the zap server listens on. It doesn't serve TLS, nor any of the node's API.
+	pub listen_address: String,
+	pub username: String,
+	/// Key zap receipts are signed with, 32 bytes as 64 hex digits
+	pub nostr_secret_key: Option<String>,
+	/// Relays zap receipts are published to, besides the relays listed by each zap request
+	pub relays: Vec<String>,
+	pub min_sendable_msat: u64,
+	pub max_sendable_msat: u64,
+}
+
+impl Default for ZapSettings {
+	fn default() -> Self {
+		Self {
+			base_url: None,
+			listen_address: "127.0.0.1:33336".to_string(),
+			username: "zap".to_string(),
+			nostr_secret_key: None,
+			relays: Vec::new(),
+			min_sendable_msat: 1000,
+			max_sendable_msat: 1_000_000_000,
+		}
+	}
+}
+
+/// Settings of the gRPC server, which is served with the `grpc` feature once `listen_address`
+/// is set. It shares the API token and the TLS settings of the HTTP API server.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct GrpcSettings {
+	pub listen_address: Option<String>,
+}
+
+/// Settings of the node's logs, which are written to the standard output and to
+/// `logs/logs.txt` in the data directory
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct LoggingSettings {
+	pub format: LogFormat,
+	/// Filter of the logs, e.g. `info` or `info,lnnode::zaps=debug`, overridden by `RUST_LOG`
+	pub level: String,
+	/// Level of LDK's logs. The log file gets all of them if unset.
+	pub ldk: Option<String>,
+	/// Level of the HTTP and gRPC servers' logs
+	pub http: Option<String>,
+	/// Level of the chain sources' logs
+	pub chain: Option<String>,
+	/// Size the log file is rotated at. It grows without limit if unset.
+	pub max_file_size_mb: Option<u64>,
+	/// Number of rotated log files kept
+	pub max_files: usize,
+}
+
+impl Default for LoggingSettings {
+	fn default() -> Self {
+		Self {
+			format: LogFormat::default(),
+			level: "info".to_string(),
+			ldk: None,
+			http: None,
+			chain: None,
+			max_file_size_mb: None,
+			max_files: 5,
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+	Text,
+	/// One JSON object per line, with the fields of the log and of its spans
+	Json,
+}
+
+impl Default for LogFormat {
+	fn default() -> Self {
+		LogFormat::Text
+	}
+}
+
+/// Settings of the approval of large payments, which is off unless `threshold_msat` is set
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ApprovalSettings {
+	/// Amount above which payments are queued until an operator approves them
+	pub threshold_msat: Option<u64>,
+}
+
+/// Settings of the autopilot, which opens channels on its own once enabled, here or through
+/// the API, and given a budget
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct AutopilotSettings {
+	pub enabled: bool,
+	/// Total size of the open channels the autopilot opened
+	pub budget_sat: u64,
+	/// Size of each channel, lowered to what is left of the budget and of the on-chain funds
+	pub channel_size_sat: u64,
+	/// Size below which no channel is opened
+	pub min_channel_size_sat: u64,
+	pub max_channels: usize,
+	/// Confirmed on-chain balance the autopilot leaves untouched
+	pub onchain_reserve_sat: u64,
+	pub interval_secs: u64,
+	/// Whether the channels are announced
+	pub public: bool,
+}
+
+impl Default for AutopilotSettings {
+	fn default() -> Self {
+		Self {
+			enabled: false,
+			budget_sat: 0,
+			channel_size_sat: 1_000_000,
+			min_channel_size_sat: 100_000,
+			max_channels: 5,
+			onchain_reserve_sat: 50_000,
+			interval_secs: 600,
+			public: true,
+		}
+	}
+}
+
+/// Settings of the LSP the node buys inbound channels from
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct LspSettings {
+	/// `pubkey@host:port` of the LSP
+	pub node: Option<String>,
+	/// Token the LSP gave the node, e.g. for a discount
+	pub token: Option<String>,
+}
+
+/// Settings of the HTLCs the node's new channels and invoices accept, including limits of the
+/// value at risk if a channel is force-closed. Unset settings keep LDK's defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ChannelSettings {
+	/// Blocks the node asks to claim a forwarded HTLC back, overridden by the policy set at
+	/// runtime like the two following settings
+	pub cltv_expiry_delta: Option<u16>,
+	/// Minimum value of the HTLCs the counterparty may send
+	pub htlc_minimum_msat: Option<u64>,
+	/// Blocks the node asks to claim the HTLCs of payments it receives, set in its invoices
+	pub min_final_cltv_expiry: Option<u32>,
+	/// Total value of the HTLCs too small to be claimed on-chain which a channel carries at
+	/// once, above which further ones are failed
+	pub max_dust_htlc_exposure_msat: Option<u64>,
+	/// Percentage of the channel value the counterparty may have in flight towards us
+	pub max_inflight_htlc_percent: Option<u8>,
+	/// Number of HTLCs the counterparty may have in flight towards us
+	pub max_accepted_htlcs: Option<u16>,
+}
+
+/// Load the node settings from the config file at `config_path`, which has to exist, or else
+/// from the optional config file of the data directory, if known.
+///
+/// Settings are overridden by `LNNODE_`-prefixed environment variables, which are overridden
+/// by `overrides`, given as `section.setting` keys.
+pub(crate) fn load_settings(
+	config_path: Option<&str>, data_dir: Option<&str>, overrides: &[(String, String)],
+) -> io::Result<Settings> {
+	let to_io_error = |e: config::ConfigError| io::Error::new(io::ErrorKind::InvalidData, e);
+	let mut builder = Config::builder();
+	match (config_path, data_dir) {
+		(Some(config_path), _) => builder = builder.add_source(File::with_name(config_path)),
+		(None, Some(data_dir)) => {
+			builder = builder.add_source(
+				File::with_name(&format!("{}/.ldk/{}", data_dir, CONFIG_FILE_NAME)).required(false),
+			)
+		}
+		(None, None) => {}
+	}
+	builder = builder
+		.add_source(Environment::with_prefix(ENV_PREFIX).prefix_separator("_").separator("__"));
+	for (key, value) in overrides {
+		builder = builder.set_override(key.as_str(), value.as_str()).map_err(to_io_error)?;
+	}
+	builder.build().map_err(to_io_error)?.try_deserialize().map_err(to_io_error)
+}
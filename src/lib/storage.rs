@@ -0,0 +1,361 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Storage for all node data other than channel monitors, which are always persisted by
+/// `FilesystemPersister`.
+///
+/// Data is organized as values stored under a key within a namespace, e.g. a payment is stored
+/// under its payment hash in the `inbound_payments` namespace.
+pub trait NodeStore: Send + Sync {
+	/// Store `value` under `key` in `namespace`, replacing any previous value
+	fn write(&self, namespace: &str, key: &str, value: &[u8]) -> io::Result<()>;
+
+	/// Read the value stored under `key` in `namespace`, if any
+	fn read(&self, namespace: &str, key: &str) -> io::Result<Option<Vec<u8>>>;
+
+	/// Remove the value stored under `key` in `namespace`, if any
+	fn remove(&self, namespace: &str, key: &str) -> io::Result<()>;
+
+	/// List all keys and values in `namespace`, ordered by key
+	fn list(&self, namespace: &str) -> io::Result<Vec<(String, Vec<u8>)>>;
+
+	/// List the keys starting with `prefix` in `namespace` and their values, ordered by key.
+	/// Backends override it to avoid reading the values of the other keys.
+	fn list_prefix(&self, namespace: &str, prefix: &str) -> io::Result<Vec<(String, Vec<u8>)>> {
+		let mut entries = self.list(namespace)?;
+		entries.retain(|(key, _)| key.starts_with(prefix));
+		Ok(entries)
+	}
+}
+
+/// The storage backends the node can be started with
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StorageBackend {
+	Filesystem,
+	Sqlite,
+}
+
+/// Open the store for `backend` in the node's data directory
+pub(crate) fn open_store(
+	backend: StorageBackend, ldk_data_dir: &str,
+) -> io::Result<Arc<dyn NodeStore>> {
+	match backend {
+		StorageBackend::Filesystem => {
+			Ok(Arc::new(FilesystemStore::new(format!("{}/store", ldk_data_dir))?))
+		}
+		#[cfg(feature = "sqlite")]
+		StorageBackend::Sqlite => {
+			Ok(Arc::new(sqlite::SqliteStore::new(format!("{}/lnnode.sqlite", ldk_data_dir))?))
+		}
+		#[cfg(not(feature = "sqlite"))]
+		StorageBackend::Sqlite => Err(io::Error::new(
+			io::ErrorKind::Other,
+			"the sqlite storage backend requires building with `--features sqlite`",
+		)),
+	}
+}
+
+/// Stores each value in its own file at `<root>/<namespace>/<key>`
+pub struct FilesystemStore {
+	root: PathBuf,
+}
+
+impl FilesystemStore {
+	pub fn new(root: String) -> io::Result<Self> {
+		fs::create_dir_all(&root)?;
+		Ok(Self { root: PathBuf::from(root) })
+	}
+
+	fn path(&self, namespace: &str, key: &str) -> PathBuf {
+		self.root.join(namespace).join(key)
+	}
+}
+
+impl NodeStore for FilesystemStore {
+	fn write(&self, namespace: &str, key: &str, value: &[u8]) -> io::Result<()> {
+		fs::create_dir_all(self.root.join(namespace))?;
+		let path = self.path(namespace, key);
+		let mut tmp_path = path.clone().into_os_string();
+		tmp_path.push(".tmp");
+		if let Err(e) = fs::write(&tmp_path, value).and_then(|_| fs::rename(&tmp_path, &path)) {
+			let _ = fs::remove_file(&tmp_path);
+			return Err(e);
+		}
+		Ok(())
+	}
+
+	fn read(&self, namespace: &str, key: &str) -> io::Result<Option<Vec<u8>>> {
+		match fs::read(self.path(namespace, key)) {
+			Ok(value) => Ok(Some(value)),
+			Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+			Err(e) => Err(e),
+		}
+	}
+
+	fn remove(&self, namespace: &str, key: &str) -> io::Result<()> {
+		match fs::remove_file(self.path(namespace, key)) {
+			Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+			_ => Ok(()),
+		}
+	}
+
+	fn list(&self, namespace: &str) -> io::Result<Vec<(String, Vec<u8>)>> {
+		self.list_prefix(namespace, "")
+	}
+
+	fn list_prefix(&self, namespace: &str, prefix: &str) -> io::Result<Vec<(String, Vec<u8>)>> {
+		let dir = self.root.join(namespace);
+		if !Path::new(&dir).exists() {
+			return Ok(Vec::new());
+		}
+		let mut entries = Vec::new();
+		for entry in fs::read_dir(&dir)? {
+			let entry = entry?;
+			let key = match entry.file_name().into_string() {
+				Ok(key) => key,
+				Err(_) => continue,
+			};
+			// Skip the other keys and leftovers of interrupted writes
+			if !key.starts_with(prefix) || key.ends_with(".tmp") || !entry.file_type()?.is_file() {
+				continue;
+			}
+			entries.push((key, fs::read(entry.path())?));
+		}
+		entries.sort_by(|a, b| a.0.cmp(&b.0));
+		Ok(entries)
+	}
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+	use super::NodeStore;
+	use rusqlite::{params, Connection, OptionalExtension};
+	use std::io;
+	use std::sync::Mutex;
+
+	/// Schema migrations, applied in order. The number of applied migrations is tracked in the
+	/// database's `user_version`, so migrations must never be modified or reordered once released.
+	pub(super) const MIGRATIONS: [&str; 1] = ["CREATE TABLE kv (
+			namespace TEXT NOT NULL,
+			key TEXT NOT NULL,
+			value BLOB NOT NULL,
+			updated_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+			PRIMARY KEY (namespace, key)
+		)"];
+
+	fn to_io_error(e: rusqlite::Error) -> io::Error {
+		io::Error::new(io::ErrorKind::Other, e)
+	}
+
+	/// Stores all values in a single SQLite database, so node data can be queried with standard
+	/// tooling.
+	pub struct SqliteStore {
+		connection: Mutex<Connection>,
+	}
+
+	impl SqliteStore {
+		pub fn new(path: String) -> io::Result<Self> {
+			let mut connection = Connection::open(path).map_err(to_io_error)?;
+			Self::migrate(&mut connection).map_err(to_io_error)?;
+			Ok(Self { connection: Mutex::new(connection) })
+		}
+
+		fn migrate(connection: &mut Connection) -> rusqlite::Result<()> {
+			let version: usize =
+				connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+			for (idx, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+				let tx = connection.transaction()?;
+				tx.execute_batch(migration)?;
+				tx.execute_batch(&format!("PRAGMA user_version = {}", idx + 1))?;
+				tx.commit()?;
+			}
+			Ok(())
+		}
+	}
+
+	impl NodeStore for SqliteStore {
+		fn write(&self, namespace: &str, key: &str, value: &[u8]) -> io::Result<()> {
+			let connection = self.connection.lock().unwrap();
+			connection
+				.execute(
+					"INSERT INTO kv (namespace, key, value) VALUES (?1, ?2, ?3)
+					ON CONFLICT (namespace, key) DO UPDATE
+					SET value = excluded.value, updated_at = strftime('%s', 'now')",
+					params![namespace, key, value],
+				)
+				.map_err(to_io_error)?;
+			Ok(())
+		}
+
+		fn read(&self, namespace: &str, key: &str) -> io::Result<Option<Vec<u8>>> {
+			let connection = self.connection.lock().unwrap();
+			connection
+				.query_row(
+					"SELECT value FROM kv WHERE namespace = ?1 AND key = ?2",
+					params![namespace, key],
+					|row| row.get(0),
+				)
+				.optional()
+				.map_err(to_io_error)
+		}
+
+		fn remove(&self, namespace: &str, key: &str) -> io::Result<()> {
+			let connection = self.connection.lock().unwrap();
+			connection
+				.execute(
+					"DELETE FROM kv WHERE namespace = ?1 AND key = ?2",
+					params![namespace, key],
+				)
+				.map_err(to_io_error)?;
+			Ok(())
+		}
+
+		fn list(&self, namespace: &str) -> io::Result<Vec<(String, Vec<u8>)>> {
+			let connection = self.connection.lock().unwrap();
+			let mut stmt = connection
+				.prepare("SELECT key, value FROM kv WHERE namespace = ?1 ORDER BY key")
+				.map_err(to_io_error)?;
+			let rows = stmt
+				.query_map(params![namespace], |row| Ok((row.get(0)?, row.get(1)?)))
+				.map_err(to_io_error)?;
+			rows.collect::<rusqlite::Result<Vec<_>>>().map_err(to_io_error)
+		}
+
+		fn list_prefix(&self, namespace: &str, prefix: &str) -> io::Result<Vec<(String, Vec<u8>)>> {
+			let connection = self.connection.lock().unwrap();
+			// A range over the primary key rather than `LIKE`, so that the index is used. Keys
+			// starting with the prefix sort before it followed by the last Unicode code point.
+			let mut stmt = connection
+				.prepare(
+					"SELECT key, value FROM kv
+					WHERE namespace = ?1 AND key >= ?2 AND key < ?2 || char(1114111)
+					ORDER BY key",
+				)
+				.map_err(to_io_error)?;
+			let rows = stmt
+				.query_map(params![namespace, prefix], |row| Ok((row.get(0)?, row.get(1)?)))
+				.map_err(to_io_error)?;
+			rows.collect::<rusqlite::Result<Vec<_>>>().map_err(to_io_error)
+		}
+	}
+}
+
+/// Keeps all values in memory, for the tests of the modules persisting to a `NodeStore`
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct MemoryStore(
+	std::sync::Mutex<std::collections::BTreeMap<(String, String), Vec<u8>>>,
+);
+
+#[cfg(test)]
+impl NodeStore for MemoryStore {
+	fn write(&self, namespace: &str, key: &str, value: &[u8]) -> io::Result<()> {
+		self.0.lock().unwrap().insert((namespace.to_string(), key.to_string()), value.to_vec());
+		Ok(())
+	}
+
+	fn read(&self, namespace: &str, key: &str) -> io::Result<Option<Vec<u8>>> {
+		Ok(self.0.lock().unwrap().get(&(namespace.to_string(), key.to_string())).cloned())
+	}
+
+	fn remove(&self, namespace: &str, key: &str) -> io::Result<()> {
+		self.0.lock().unwrap().remove(&(namespace.to_string(), key.to_string()));
+		Ok(())
+	}
+
+	fn list(&self, namespace: &str) -> io::Result<Vec<(String, Vec<u8>)>> {
+		Ok(self
+			.0
+			.lock()
+			.unwrap()
+			.iter()
+			.filter(|((value_namespace, _), _)| value_namespace == namespace)
+			.map(|((_, key), value)| (key.clone(), value.clone()))
+			.collect())
+	}
+}
+
+/// Create an empty directory under the system's temporary directory, for the tests writing to
+/// disk
+#[cfg(test)]
+pub(crate) fn test_dir(name: &str) -> String {
+	use rand::{thread_rng, Rng};
+	let dir =
+		std::env::temp_dir().join(format!("lnnode-{}-{:016x}", name, thread_rng().gen::<u64>()));
+	fs::create_dir_all(&dir).unwrap();
+	dir.to_str().unwrap().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn check_store(store: &dyn NodeStore) {
+		assert_eq!(store.read("payments", "a").unwrap(), None);
+		assert!(store.list("payments").unwrap().is_empty());
+
+		store.write("payments", "b", b"2").unwrap();
+		store.write("payments", "a", b"1").unwrap();
+		store.write("payments", "ab", b"3").unwrap();
+		store.write("peers", "a", b"peer").unwrap();
+		store.write("payments", "a", b"4").unwrap();
+		assert_eq!(store.read("payments", "a").unwrap(), Some(b"4".to_vec()));
+		assert_eq!(
+			store.list("payments").unwrap(),
+			[
+				("a".to_string(), b"4".to_vec()),
+				("ab".to_string(), b"3".to_vec()),
+				("b".to_string(), b"2".to_vec())
+			]
+		);
+		assert_eq!(
+			store.list_prefix("payments", "a").unwrap(),
+			[("a".to_string(), b"4".to_vec()), ("ab".to_string(), b"3".to_vec())]
+		);
+		assert!(store.list_prefix("payments", "c").unwrap().is_empty());
+
+		store.remove("payments", "a").unwrap();
+		// Removing a missing key isn't an error
+		store.remove("payments", "a").unwrap();
+		assert_eq!(store.read("payments", "a").unwrap(), None);
+		assert_eq!(store.read("peers", "a").unwrap(), Some(b"peer".to_vec()));
+	}
+
+	#[test]
+	fn filesystem_store() {
+		let dir = test_dir("filesystem-store");
+		let store = FilesystemStore::new(format!("{}/store", dir)).unwrap();
+		check_store(&store);
+		// Leftovers of interrupted writes aren't values
+		fs::write(format!("{}/store/payments/c.tmp", dir), b"partial").unwrap();
+		assert!(store.list("payments").unwrap().iter().all(|(key, _)| key != "c.tmp"));
+		let _ = fs::remove_dir_all(dir);
+	}
+
+	#[test]
+	fn memory_store() {
+		check_store(&MemoryStore::default());
+	}
+
+	#[cfg(feature = "sqlite")]
+	#[test]
+	fn sqlite_store() {
+		let dir = test_dir("sqlite-store");
+		let path = format!("{}/lnnode.sqlite", dir);
+		let store = sqlite::SqliteStore::new(path.clone()).unwrap();
+		check_store(&store);
+		drop(store);
+
+		// Reopening the database keeps its values and doesn't apply the migrations again
+		let store = sqlite::SqliteStore::new(path.clone()).unwrap();
+		assert_eq!(store.read("payments", "b").unwrap(), Some(b"2".to_vec()));
+		drop(store);
+		let connection = rusqlite::Connection::open(path).unwrap();
+		let version: usize =
+			connection.query_row("PRAGMA user_version", [], |row| row.get(0)).unwrap();
+		assert_eq!(version, sqlite::MIGRATIONS.len());
+		let _ = fs::remove_dir_all(dir);
+	}
+}
@@ -0,0 +1,166 @@
+use crate::broadcast::{BroadcastKind, BroadcastManager};
+use crate::chain::ChainSource;
+use crate::keys::NodeKeysManager;
+use crate::storage::NodeStore;
+use crate::wallet::Wallet;
+use bitcoin::blockdata::script::Script;
+use bitcoin::blockdata::transaction::TxOut;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::OutPoint;
+use lightning::chain::chaininterface::{ConfirmationTarget, FeeEstimator};
+use lightning::chain::keysinterface::SpendableOutputDescriptor;
+use lightning::util::ser::{Readable, Writeable};
+use std::io;
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+use tracing::info;
+
+pub(crate) const SPENDABLE_OUTPUTS_NAMESPACE: &str = "spendable_outputs";
+
+/// Fee rate increase of each sweep attempt over the previous one, in percent
+const FEERATE_INCREASE_PERCENT: u32 = 25;
+
+/// Cap on sweep fee rates, as a multiple of the normal priority estimate
+const MAX_FEERATE_MULTIPLIER: u32 = 4;
+
+/// Sweeps the outputs LDK hands over on `Event::SpendableOutputs` to the wallet or to a
+/// configured address.
+///
+/// Outputs are persisted when handed over, as LDK doesn't replay the event, and are all spent
+/// by a single transaction. The transaction is rebroadcast at an increasing fee rate until the
+/// outputs are spent by a confirmed transaction.
+pub struct Sweeper {
+	store: Arc<dyn NodeStore>,
+	keys_manager: Arc<NodeKeysManager>,
+	chain_source: Arc<ChainSource>,
+	wallet: Arc<Wallet>,
+	broadcaster: Arc<BroadcastManager>,
+	destination: Mutex<Option<Script>>,
+	attempts: Mutex<u32>,
+}
+
+impl Sweeper {
+	/// Create a sweeper paying to `destination_address`, or to a new wallet address if none is
+	/// configured
+	pub(crate) fn new(
+		store: Arc<dyn NodeStore>, keys_manager: Arc<NodeKeysManager>,
+		chain_source: Arc<ChainSource>, wallet: Arc<Wallet>, broadcaster: Arc<BroadcastManager>,
+		destination_address: &Option<String>,
+	) -> Result<Self, String> {
+		let destination = match destination_address {
+			Some(address) => Some(wallet.parse_address(address)?.script_pubkey()),
+			None => None,
+		};
+		Ok(Self {
+			store,
+			keys_manager,
+			chain_source,
+			wallet,
+			broadcaster,
+			destination: Mutex::new(destination),
+			attempts: Mutex::new(0),
+		})
+	}
+
+	/// Persist `outputs` so they are swept even if the node restarts before they are spent
+	pub(crate) fn track(&self, outputs: &[SpendableOutputDescriptor]) -> io::Result<()> {
+		for output in outputs {
+			let outpoint = outpoint(output);
+			let key = format!("{}_{}", outpoint.txid, outpoint.vout);
+			self.store.write(SPENDABLE_OUTPUTS_NAMESPACE, &key, &output.encode())?;
+		}
+		Ok(())
+	}
+
+	/// Value of the outputs tracked until a confirmed transaction spends them
+	pub(crate) fn pending_sat(&self) -> io::Result<u64> {
+		let mut pending_sat = 0;
+		for (_, value) in self.store.list(SPENDABLE_OUTPUTS_NAMESPACE)? {
+			let output = SpendableOutputDescriptor::read(&mut Cursor::new(value))
+				.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e)))?;
+			pending_sat += txout(&output).value;
+		}
+		Ok(pending_sat)
+	}
+
+	/// Broadcast a transaction spending every output not already spent on chain
+	pub(crate) async fn sweep(&self) -> io::Result<()> {
+		let mut outputs = Vec::new();
+		for (key, value) in self.store.list(SPENDABLE_OUTPUTS_NAMESPACE)? {
+			let output = SpendableOutputDescriptor::read(&mut Cursor::new(value))
+				.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e)))?;
+			// Outputs spent by a mempool transaction are swept again until it confirms
+			let spent = self
+				.chain_source
+				.is_spent_on_chain(&outpoint(&output), &txout(&output).script_pubkey)
+				.await?;
+			if spent {
+				self.store.remove(SPENDABLE_OUTPUTS_NAMESPACE, &key)?;
+				continue;
+			}
+			outputs.push(output);
+		}
+		if outputs.is_empty() {
+			*self.attempts.lock().unwrap() = 0;
+			return Ok(());
+		}
+
+		let cached_destination = self.destination.lock().unwrap().clone();
+		let destination = match cached_destination {
+			Some(destination) => destination,
+			None => {
+				let destination = self.wallet.get_new_address().await?.script_pubkey();
+				*self.destination.lock().unwrap() = Some(destination.clone());
+				destination
+			}
+		};
+		let feerate = {
+			let mut attempts = self.attempts.lock().unwrap();
+			let normal = self.chain_source.get_est_sat_per_1000_weight(ConfirmationTarget::Normal);
+			let increase = FEERATE_INCREASE_PERCENT.saturating_mul(*attempts);
+			*attempts += 1;
+			std::cmp::min(
+				normal.saturating_mul(100 + increase) / 100,
+				normal.saturating_mul(MAX_FEERATE_MULTIPLIER),
+			)
+		};
+		let spending_tx = self
+			.keys_manager
+			.spend_spendable_outputs(
+				&outputs.iter().collect::<Vec<_>>(),
+				Vec::new(),
+				destination,
+				feerate,
+				&Secp256k1::new(),
+			)
+			.map_err(|()| {
+				io::Error::new(io::ErrorKind::Other, "outputs are worth less than the sweep fee")
+			})?;
+		let txid = self.chain_source.send_transaction(&spending_tx).await?;
+		self.broadcaster.record(&spending_tx, BroadcastKind::Sweep);
+		info!(
+			"sweeping {} spendable outputs in transaction {} at {} sat/kw",
+			outputs.len(),
+			txid,
+			feerate
+		);
+		Ok(())
+	}
+}
+
+fn outpoint(output: &SpendableOutputDescriptor) -> OutPoint {
+	let outpoint = match output {
+		SpendableOutputDescriptor::StaticOutput { outpoint, .. } => outpoint,
+		SpendableOutputDescriptor::DelayedPaymentOutput(descriptor) => &descriptor.outpoint,
+		SpendableOutputDescriptor::StaticPaymentOutput(descriptor) => &descriptor.outpoint,
+	};
+	outpoint.into_bitcoin_outpoint()
+}
+
+fn txout(output: &SpendableOutputDescriptor) -> &TxOut {
+	match output {
+		SpendableOutputDescriptor::StaticOutput { output, .. } => output,
+		SpendableOutputDescriptor::DelayedPaymentOutput(descriptor) => &descriptor.output,
+		SpendableOutputDescriptor::StaticPaymentOutput(descriptor) => &descriptor.output,
+	}
+}
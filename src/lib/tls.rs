@@ -0,0 +1,91 @@
+use crate::secrets;
+use crate::secrets::SecretsKey;
+use crate::settings::ServerSettings;
+use rcgen::{CertificateParams, SanType};
+use rustls::{Certificate, PrivateKey, ServerConfig};
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::Path;
+
+/// Name of the self-signed certificate generated in the node's data directory
+pub const TLS_CERT_FILE: &str = "tls.cert";
+
+/// Name of the private key of the self-signed certificate
+pub const TLS_KEY_FILE: &str = "tls.key";
+
+/// Build the rustls config of the API server from the configured certificate and key, or from
+/// a self-signed certificate in `ldk_data_dir` that is generated on first use. The key of the
+/// self-signed certificate is stored with the node's other secrets.
+pub(crate) fn load_server_config(
+	ldk_data_dir: &str, settings: &ServerSettings, secrets_key: Option<&SecretsKey>,
+) -> io::Result<ServerConfig> {
+	let (cert_pem, key_pem) = load_cert_and_key(ldk_data_dir, settings, secrets_key)?;
+	let certs =
+		rustls_pemfile::certs(&mut cert_pem.as_slice())?.into_iter().map(Certificate).collect();
+	let key = match rustls_pemfile::pkcs8_private_keys(&mut key_pem.as_slice())?.pop() {
+		Some(key) => PrivateKey(key),
+		None => {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidData,
+				"no PKCS#8 private key found for the TLS certificate",
+			))
+		}
+	};
+
+	ServerConfig::builder()
+		.with_safe_defaults()
+		.with_no_client_auth()
+		.with_single_cert(certs, key)
+		.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Read the PEM certificate and private key `load_server_config` serves, generating the
+/// self-signed certificate if needed
+pub(crate) fn load_cert_and_key(
+	ldk_data_dir: &str, settings: &ServerSettings, secrets_key: Option<&SecretsKey>,
+) -> io::Result<(Vec<u8>, Vec<u8>)> {
+	let (cert_path, key_path) = match (&settings.tls_cert_path, &settings.tls_key_path) {
+		(Some(cert_path), Some(key_path)) => (cert_path.clone(), key_path.clone()),
+		(None, None) => {
+			let cert_path = format!("{}/{}", ldk_data_dir, TLS_CERT_FILE);
+			let key_path = format!("{}/{}", ldk_data_dir, TLS_KEY_FILE);
+			if !Path::new(&cert_path).exists() || !secrets::secret_exists(&key_path) {
+				generate_self_signed(&cert_path, &key_path, &settings.listen_address, secrets_key)?;
+			}
+			(cert_path, key_path)
+		}
+		_ => {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				"tls_cert_path and tls_key_path must be configured together",
+			))
+		}
+	};
+
+	let cert_pem = fs::read(&cert_path)?;
+	let key_pem = match secrets::read_secret(&key_path, secrets_key)? {
+		Some(key_pem) => key_pem,
+		None => return Err(io::Error::new(io::ErrorKind::NotFound, key_path)),
+	};
+	Ok((cert_pem, key_pem))
+}
+
+/// Generate a self-signed certificate valid for the local API address, and for the IP address
+/// the API listens on if it is a specific one
+fn generate_self_signed(
+	cert_path: &str, key_path: &str, listen_address: &str, secrets_key: Option<&SecretsKey>,
+) -> io::Result<()> {
+	let mut params = CertificateParams::new(vec!["localhost".to_string()]);
+	params.subject_alt_names.push(SanType::IpAddress(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+	if let Ok(address) = listen_address.parse::<SocketAddr>() {
+		if !address.ip().is_loopback() && !address.ip().is_unspecified() {
+			params.subject_alt_names.push(SanType::IpAddress(address.ip()));
+		}
+	}
+	let cert = rcgen::Certificate::from_params(params)
+		.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+	let cert_pem = cert.serialize_pem().map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+	secrets::write_secret(key_path, cert.serialize_private_key_pem().as_bytes(), secrets_key)?;
+	fs::write(cert_path, cert_pem)
+}
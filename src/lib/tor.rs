@@ -0,0 +1,160 @@
+use crate::peers::PeerAddr;
+use lightning::ln::msgs::NetAddress;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Time allowed to set up an outbound connection, matching `lightning_net_tokio`
+const CONNECT_TIMEOUT_SECS: u64 = 10;
+
+/// Open a TCP connection to `peer_addr`, through the SOCKS5 proxy at `socks_proxy` if any, e.g.
+/// Tor's, so that peers don't learn the node's IP address. Host names are resolved by the proxy
+/// if there's one, so that they don't leak through DNS either, and onion addresses can only be
+/// reached through it.
+pub(crate) async fn connect(
+	peer_addr: &PeerAddr, socks_proxy: Option<SocketAddr>,
+) -> io::Result<std::net::TcpStream> {
+	let connection = async {
+		let stream = match socks_proxy {
+			Some(proxy) => {
+				let mut stream = TcpStream::connect(proxy).await?;
+				socks5_connect(&mut stream, peer_addr).await?;
+				stream
+			}
+			None if peer_addr.is_onion() => {
+				return Err(io::Error::new(
+					io::ErrorKind::InvalidInput,
+					format!("{} can only be reached through the Tor socks_proxy", peer_addr),
+				))
+			}
+			None => TcpStream::connect((peer_addr.host.as_str(), peer_addr.port)).await?,
+		};
+		stream.into_std()
+	};
+	match tokio::time::timeout(Duration::from_secs(CONNECT_TIMEOUT_SECS), connection).await {
+		Ok(stream) => stream,
+		Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "connection timed out")),
+	}
+}
+
+/// Ask the SOCKS5 proxy at the other end of `stream` to connect to `addr`, without
+/// authentication (RFC 1928)
+async fn socks5_connect(stream: &mut TcpStream, addr: &PeerAddr) -> io::Result<()> {
+	let socks_error = |msg: String| io::Error::new(io::ErrorKind::Other, msg);
+
+	stream.write_all(&[5, 1, 0]).await?;
+	let mut method = [0; 2];
+	stream.read_exact(&mut method).await?;
+	if method != [5, 0] {
+		return Err(socks_error("SOCKS5 proxy requires authentication".to_string()));
+	}
+
+	let mut request = vec![5, 1, 0];
+	match addr.ip() {
+		Some(IpAddr::V4(ip)) => {
+			request.push(1);
+			request.extend_from_slice(&ip.octets());
+		}
+		Some(IpAddr::V6(ip)) => {
+			request.push(4);
+			request.extend_from_slice(&ip.octets());
+		}
+		None => {
+			if addr.host.len() > 255 {
+				return Err(socks_error(format!("host name {} is too long", addr.host)));
+			}
+			request.push(3);
+			request.push(addr.host.len() as u8);
+			request.extend_from_slice(addr.host.as_bytes());
+		}
+	}
+	request.extend_from_slice(&addr.port.to_be_bytes());
+	stream.write_all(&request).await?;
+
+	let mut reply = [0; 4];
+	stream.read_exact(&mut reply).await?;
+	if reply[1] != 0 {
+		return Err(socks_error(format!("SOCKS5 proxy refused to connect, reply {}", reply[1])));
+	}
+	// Skip the address the proxy bound, which we have no use for
+	let bound_addr_len = match reply[3] {
+		1 => 4,
+		4 => 16,
+		3 => stream.read_u8().await? as usize,
+		atyp => return Err(socks_error(format!("unknown SOCKS5 address type {}", atyp))),
+	};
+	let mut bound_addr = vec![0; bound_addr_len + 2];
+	stream.read_exact(&mut bound_addr).await?;
+	Ok(())
+}
+
+/// Parse a Tor v3 hidden service address, `<56 base32 characters>.onion:<port>`, into the
+/// address announced in node announcements
+pub(crate) fn parse_onion_address(onion_address: &str) -> Result<NetAddress, String> {
+	let invalid =
+		|| format!("invalid onion address {}, expected <v3 address>.onion:<port>", onion_address);
+	let (host, port) = onion_address.rsplit_once(":").ok_or_else(invalid)?;
+	let port: u16 = port.parse().map_err(|_| invalid())?;
+	let encoded = host.strip_suffix(".onion").ok_or_else(invalid)?;
+	let decoded = base32_decode(encoded).ok_or_else(invalid)?;
+	// A v3 address encodes the service's ed25519 public key, a checksum and the version
+	if decoded.len() != 35 || decoded[34] != 3 {
+		return Err(invalid());
+	}
+	let mut ed25519_pubkey = [0; 32];
+	ed25519_pubkey.copy_from_slice(&decoded[..32]);
+	Ok(NetAddress::OnionV3 {
+		ed25519_pubkey,
+		checksum: u16::from_be_bytes([decoded[32], decoded[33]]),
+		version: decoded[34],
+		port,
+	})
+}
+
+/// Host name of the Tor v3 hidden service announced as `ed25519_pubkey`, `checksum` and `version`
+pub(crate) fn onion_host(ed25519_pubkey: &[u8; 32], checksum: u16, version: u8) -> String {
+	let mut decoded = ed25519_pubkey.to_vec();
+	decoded.extend_from_slice(&checksum.to_be_bytes());
+	decoded.push(version);
+	format!("{}.onion", base32_encode(&decoded))
+}
+
+/// Encode `data` as unpadded lowercase RFC 4648 base32
+fn base32_encode(data: &[u8]) -> String {
+	const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz234567";
+	let mut encoded = String::with_capacity((data.len() * 8 + 4) / 5);
+	let (mut buffer, mut bits) = (0u32, 0);
+	for byte in data {
+		buffer = (buffer << 8) | *byte as u32;
+		bits += 8;
+		while bits >= 5 {
+			bits -= 5;
+			encoded.push(ALPHABET[((buffer >> bits) & 31) as usize] as char);
+		}
+		buffer &= (1 << bits) - 1;
+	}
+	if bits > 0 {
+		encoded.push(ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+	}
+	encoded
+}
+
+/// Decode unpadded RFC 4648 base32, as used by onion addresses
+fn base32_decode(encoded: &str) -> Option<Vec<u8>> {
+	const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz234567";
+	let mut decoded = Vec::with_capacity(encoded.len() * 5 / 8);
+	let (mut buffer, mut bits) = (0u32, 0);
+	for c in encoded.to_lowercase().bytes() {
+		let value = ALPHABET.iter().position(|a| *a == c)? as u32;
+		buffer = (buffer << 5) | value;
+		bits += 5;
+		if bits >= 8 {
+			bits -= 8;
+			decoded.push((buffer >> bits) as u8);
+			buffer &= (1 << bits) - 1;
+		}
+	}
+	Some(decoded)
+}
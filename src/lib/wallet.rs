@@ -0,0 +1,213 @@
+use crate::bitcoind_client::BitcoindClient;
+use crate::hex_utils;
+use crate::seed_wallet::SeedWallet;
+use bitcoin::blockdata::script::Script;
+use bitcoin::blockdata::transaction::{OutPoint, Transaction};
+use bitcoin::consensus::encode;
+use bitcoin::hash_types::Txid;
+use bitcoin::network::constants::Network;
+use bitcoin::util::address::Address;
+use bitcoin_bech32::WitnessProgram;
+use std::collections::HashMap;
+use std::io;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Label attached to every address handed out by the node's on-chain wallet.
+const WALLET_ADDRESS_LABEL: &str = "LN-Node wallet";
+
+/// On-chain balance of the wallet, in satoshis
+pub struct WalletBalance {
+	/// Confirmed funds, plus unconfirmed change from our own transactions
+	pub confirmed_sat: u64,
+	/// Unconfirmed funds received from others
+	pub unconfirmed_sat: u64,
+	/// Coinbase outputs that have not matured yet
+	pub immature_sat: u64,
+}
+
+/// An output the wallet can spend
+pub struct Utxo {
+	pub txid: Txid,
+	pub vout: u32,
+	pub amount_sat: u64,
+	pub confirmations: u32,
+	/// Whether the output is locked as an input of a pending channel funding transaction
+	pub reserved: bool,
+}
+
+/// The node's on-chain wallet.
+///
+/// Channel funding transactions are funded and signed by the same wallet, so funds received on
+/// its addresses are immediately available for opening channels. When LDK is synced from
+/// bitcoind, the wallet is a thin layer over the bitcoind wallet. Otherwise it holds keys derived
+/// from the node's seed, whose outputs are looked up in the Esplora or Electrum server's index.
+pub struct Wallet {
+	backend: WalletBackend,
+	network: Network,
+}
+
+enum WalletBackend {
+	Bitcoind(Arc<BitcoindClient>),
+	Seed(Box<SeedWallet>),
+}
+
+impl Wallet {
+	pub fn new(bitcoind_client: Arc<BitcoindClient>, network: Network) -> Self {
+		Self { backend: WalletBackend::Bitcoind(bitcoind_client), network }
+	}
+
+	pub fn from_seed_wallet(seed_wallet: SeedWallet, network: Network) -> Self {
+		Self { backend: WalletBackend::Seed(Box::new(seed_wallet)), network }
+	}
+
+	/// Parse an address, making sure it belongs to the network the node runs on
+	pub fn parse_address(&self, address: &str) -> Result<Address, String> {
+		let address = Address::from_str(address).map_err(|e| format!("invalid address: {}", e))?;
+		// Signet shares its address prefixes with testnet
+		let valid = match self.network {
+			Network::Signet => address.network == Network::Testnet,
+			network => address.network == network,
+		};
+		if !valid {
+			return Err(format!("address is not valid for {}", self.network));
+		}
+		Ok(address)
+	}
+
+	/// List the wallet's spendable outputs, including the ones reserved for pending channel
+	/// funding transactions.
+	pub async fn list_utxos(&self) -> std::io::Result<Vec<Utxo>> {
+		let bitcoind_client = match &self.backend {
+			WalletBackend::Bitcoind(bitcoind_client) => bitcoind_client,
+			WalletBackend::Seed(seed_wallet) => return seed_wallet.list_utxos().await,
+		};
+		let mut utxos: Vec<Utxo> = bitcoind_client
+			.list_unspent()
+			.await?
+			.into_iter()
+			.filter(|utxo| utxo.spendable)
+			.map(|utxo| Utxo {
+				txid: utxo.txid,
+				vout: utxo.vout,
+				amount_sat: utxo.amount_sat,
+				confirmations: utxo.confirmations,
+				reserved: false,
+			})
+			.collect();
+
+		// bitcoind omits locked outputs from `listunspent`, so look them up individually
+		for outpoint in bitcoind_client.list_lock_unspent().await? {
+			if let Some(txout) = bitcoind_client.get_tx_out(&outpoint, true).await? {
+				utxos.push(Utxo {
+					txid: outpoint.txid,
+					vout: outpoint.vout,
+					amount_sat: txout.amount_sat,
+					confirmations: txout.confirmations,
+					reserved: true,
+				});
+			}
+		}
+		Ok(utxos)
+	}
+
+	/// Send `amount_sat` to `address`, at `sat_per_vbyte` or the normal fee estimate if none is
+	/// given.
+	pub async fn send_to_address(
+		&self, address: &Address, amount_sat: u64, sat_per_vbyte: Option<f64>,
+	) -> std::io::Result<Txid> {
+		match &self.backend {
+			WalletBackend::Bitcoind(bitcoind_client) => {
+				bitcoind_client.send_to_address(address, amount_sat, sat_per_vbyte).await
+			}
+			WalletBackend::Seed(seed_wallet) => {
+				seed_wallet.send_to_address(address, amount_sat, sat_per_vbyte).await
+			}
+		}
+	}
+
+	/// Get a fresh bech32 address to receive on-chain funds
+	pub async fn get_new_address(&self) -> std::io::Result<Address> {
+		match &self.backend {
+			WalletBackend::Bitcoind(bitcoind_client) => {
+				bitcoind_client.get_new_bech32_address(WALLET_ADDRESS_LABEL).await
+			}
+			WalletBackend::Seed(seed_wallet) => seed_wallet.get_new_address(),
+		}
+	}
+
+	/// Get the confirmed and unconfirmed on-chain balance
+	pub async fn get_balance(&self) -> std::io::Result<WalletBalance> {
+		let bitcoind_client = match &self.backend {
+			WalletBackend::Bitcoind(bitcoind_client) => bitcoind_client,
+			WalletBackend::Seed(seed_wallet) => return seed_wallet.get_balance().await,
+		};
+		let balances = bitcoind_client.get_balances().await?;
+		Ok(WalletBalance {
+			confirmed_sat: balances.trusted_sat,
+			unconfirmed_sat: balances.untrusted_pending_sat,
+			immature_sat: balances.immature_sat,
+		})
+	}
+
+	/// Create, fund and sign a transaction paying `amount_sat` to `output_script`, at `sat_per_kw`
+	/// or the normal fee estimate if none is given. Its inputs stay locked until it's broadcast,
+	/// or released with `unlock_unspent`.
+	pub async fn fund_transaction(
+		&self, output_script: &Script, amount_sat: u64, sat_per_kw: Option<u32>,
+	) -> io::Result<Transaction> {
+		let bitcoind_client = match &self.backend {
+			WalletBackend::Bitcoind(bitcoind_client) => bitcoind_client,
+			WalletBackend::Seed(seed_wallet) => {
+				return seed_wallet.fund_transaction(output_script, amount_sat, sat_per_kw).await
+			}
+		};
+		let addr = WitnessProgram::from_scriptpubkey(
+			&output_script[..],
+			match self.network {
+				Network::Bitcoin => bitcoin_bech32::constants::Network::Bitcoin,
+				Network::Testnet => bitcoin_bech32::constants::Network::Testnet,
+				Network::Regtest => bitcoin_bech32::constants::Network::Regtest,
+				Network::Signet => bitcoin_bech32::constants::Network::Signet,
+			},
+		)
+		.map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("{:?}", e)))?
+		.to_address();
+		let mut outputs = vec![HashMap::with_capacity(1)];
+		outputs[0].insert(addr, amount_sat as f64 / 100_000_000.0);
+		let raw_tx = bitcoind_client.create_raw_transaction(outputs).await?;
+		let funded_tx = bitcoind_client.fund_raw_transaction(raw_tx, sat_per_kw).await?;
+
+		let signed_tx = bitcoind_client.sign_raw_transaction_with_wallet(funded_tx.hex).await?;
+		if !signed_tx.complete {
+			return Err(io::Error::new(
+				io::ErrorKind::Other,
+				"bitcoind wallet was unable to fully sign the transaction",
+			));
+		}
+		let tx_bytes = hex_utils::to_vec(&signed_tx.hex).ok_or_else(|| {
+			io::Error::new(io::ErrorKind::InvalidData, "bitcoind returned an invalid transaction")
+		})?;
+		encode::deserialize(&tx_bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+	}
+
+	/// Release the locked `outpoints`, inputs of a funding transaction that won't be broadcast
+	pub async fn unlock_unspent(&self, outpoints: &[OutPoint]) -> io::Result<()> {
+		match &self.backend {
+			WalletBackend::Bitcoind(bitcoind_client) => {
+				bitcoind_client.unlock_unspent(outpoints).await.map(|_| ())
+			}
+			WalletBackend::Seed(seed_wallet) => seed_wallet.unlock_unspent(outpoints),
+		}
+	}
+
+	/// Forget the unconfirmed transaction `txid`, so that its inputs can be spent again
+	pub async fn abandon_transaction(&self, txid: &Txid) -> io::Result<()> {
+		match &self.backend {
+			WalletBackend::Bitcoind(bitcoind_client) => {
+				bitcoind_client.abandon_transaction(txid).await
+			}
+			WalletBackend::Seed(seed_wallet) => seed_wallet.abandon_transaction(txid),
+		}
+	}
+}
@@ -0,0 +1,169 @@
+use crate::events::NodeEvent;
+use crate::hex_utils;
+use crate::settings::WebhookSettings;
+use crate::storage::NodeStore;
+use bitcoin::hashes::{hmac, sha256, Hash, HashEngine};
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::warn;
+
+pub(crate) const WEBHOOKS_NAMESPACE: &str = "webhooks";
+
+/// Header carrying the hex HMAC-SHA256 of the request body, keyed with the webhook secret
+pub const SIGNATURE_HEADER: &str = "X-LNNode-Signature";
+
+/// Number of delivery attempts before a notification is dropped
+const MAX_DELIVERY_ATTEMPTS: u32 = 5;
+
+/// A URL notified of payment and channel events
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Webhook {
+	pub id: String,
+	pub url: String,
+	pub secret: String,
+}
+
+/// The webhooks registered through the API, which are persisted in the node store, and those
+/// configured in the config file
+pub struct WebhookRegistry {
+	store: Arc<dyn NodeStore>,
+	configured: Vec<Webhook>,
+}
+
+impl WebhookRegistry {
+	pub(crate) fn new(store: Arc<dyn NodeStore>, settings: &[WebhookSettings]) -> Self {
+		let configured = settings
+			.iter()
+			.enumerate()
+			.map(|(idx, hook)| Webhook {
+				id: format!("config-{}", idx),
+				url: hook.url.clone(),
+				secret: hook.secret.clone(),
+			})
+			.collect();
+		Self { store, configured }
+	}
+
+	/// Register `url`, generating a secret if none is given
+	pub(crate) fn add(&self, url: String, secret: Option<String>) -> io::Result<Webhook> {
+		let mut id = [0; 8];
+		thread_rng().fill_bytes(&mut id);
+		let secret = secret.unwrap_or_else(|| {
+			let mut secret = [0; 32];
+			thread_rng().fill_bytes(&mut secret);
+			hex_utils::hex_str(&secret)
+		});
+		let webhook = Webhook { id: hex_utils::hex_str(&id), url, secret };
+		let value = serde_json::to_vec(&webhook)
+			.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+		self.store.write(WEBHOOKS_NAMESPACE, &webhook.id, &value)?;
+		Ok(webhook)
+	}
+
+	/// Remove a webhook registered through the API, returning whether it existed
+	pub(crate) fn remove(&self, id: &str) -> io::Result<bool> {
+		if self.store.read(WEBHOOKS_NAMESPACE, id)?.is_none() {
+			return Ok(false);
+		}
+		self.store.remove(WEBHOOKS_NAMESPACE, id)?;
+		Ok(true)
+	}
+
+	pub(crate) fn list(&self) -> io::Result<Vec<Webhook>> {
+		let mut webhooks = self.configured.clone();
+		for (_, value) in self.store.list(WEBHOOKS_NAMESPACE)? {
+			webhooks.push(
+				serde_json::from_slice(&value)
+					.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+			);
+		}
+		Ok(webhooks)
+	}
+}
+
+/// Whether webhooks are notified of `event`: invoice settlement, payment success or failure,
+/// and channel opening or closing
+fn is_notified(event: &NodeEvent) -> bool {
+	match event {
+		NodeEvent::PaymentReceived { .. }
+		| NodeEvent::PaymentSent { .. }
+		| NodeEvent::PaymentFailed { .. }
+		| NodeEvent::FundingGenerationReady { .. }
+		| NodeEvent::ChannelClosed { .. } => true,
+		_ => false,
+	}
+}
+
+/// Hex HMAC-SHA256 of `body` keyed with `secret`, so receivers can authenticate notifications
+pub fn sign(secret: &str, body: &[u8]) -> String {
+	let mut engine = hmac::HmacEngine::<sha256::Hash>::new(secret.as_bytes());
+	engine.input(body);
+	hex_utils::hex_str(&hmac::Hmac::<sha256::Hash>::from_engine(engine).into_inner())
+}
+
+/// POST `body` to `webhook`, retrying with exponential backoff on failure
+async fn deliver(client: reqwest::Client, webhook: Webhook, body: Vec<u8>) {
+	let signature = sign(&webhook.secret, &body);
+	for attempt in 0..MAX_DELIVERY_ATTEMPTS {
+		if attempt > 0 {
+			tokio::time::sleep(Duration::from_secs(1 << (attempt - 1))).await;
+		}
+		let res = client
+			.post(&webhook.url)
+			.header(reqwest::header::CONTENT_TYPE, "application/json")
+			.header(SIGNATURE_HEADER, signature.as_str())
+			.timeout(Duration::from_secs(10))
+			.body(body.clone())
+			.send()
+			.await;
+		match res {
+			Ok(resp) if resp.status().is_success() => return,
+			Ok(resp) => warn!(
+				"webhook {} responded with {} (attempt {})",
+				webhook.id,
+				resp.status(),
+				attempt + 1
+			),
+			Err(e) => {
+				warn!("failed to notify webhook {}: {} (attempt {})", webhook.id, e, attempt + 1)
+			}
+		}
+	}
+	warn!("giving up notifying webhook {} of an event", webhook.id);
+}
+
+/// Notify every webhook of the events published on `events` until the node shuts down
+pub(crate) async fn run_dispatcher(
+	registry: Arc<WebhookRegistry>, mut events: broadcast::Receiver<NodeEvent>,
+) {
+	let client = reqwest::Client::new();
+	loop {
+		let event = match events.recv().await {
+			Ok(event) => event,
+			Err(broadcast::error::RecvError::Lagged(missed)) => {
+				warn!("{} events were not sent to webhooks", missed);
+				continue;
+			}
+			Err(broadcast::error::RecvError::Closed) => return,
+		};
+		if !is_notified(&event) {
+			continue;
+		}
+		let webhooks = match registry.list() {
+			Ok(webhooks) => webhooks,
+			Err(e) => {
+				warn!("failed to read webhooks: {}", e);
+				continue;
+			}
+		};
+		let body = serde_json::to_vec(&event).unwrap();
+		// Each delivery retries on its own so a slow endpoint doesn't delay the others
+		for webhook in webhooks {
+			tokio::spawn(deliver(client.clone(), webhook, body.clone()));
+		}
+	}
+}
@@ -0,0 +1,418 @@
+use crate::events::NodeEvent;
+use crate::keys::NodeKeysManager;
+use crate::node_var::{ChannelManager, ChannelPolicy, PaymentInfoStorage, ZapRequestInfo};
+use crate::settings::ZapSettings;
+use crate::storage::NodeStore;
+use crate::{disk, hex_utils, invoice};
+use actix_web::dev::Server;
+use actix_web::http::header::ContentType;
+use actix_web::{web, App, HttpResponse, HttpServer};
+use bitcoin::hashes::sha256::Hash as Sha256;
+use bitcoin::hashes::Hash;
+use bitcoin::network::constants::Network;
+use bitcoin::secp256k1::schnorrsig::{KeyPair, PublicKey as XOnlyPublicKey, Signature};
+use bitcoin::secp256k1::{All, Message, Secp256k1};
+use futures::{SinkExt, StreamExt};
+use lightning::ln::PaymentHash;
+use lightning_invoice::Currency;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+use tracing::warn;
+
+const ZAP_REQUEST_KIND: u64 = 9734;
+const ZAP_RECEIPT_KIND: u64 = 9735;
+
+/// Time a relay is given to accept a connection, and then a zap receipt
+const RELAY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A Nostr event, as defined by NIP-01
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct NostrEvent {
+	pub id: String,
+	pub pubkey: String,
+	pub created_at: u64,
+	pub kind: u64,
+	pub tags: Vec<Vec<String>>,
+	pub content: String,
+	pub sig: String,
+}
+
+impl NostrEvent {
+	/// Create an event signed with `keypair`
+	fn sign(
+		secp: &Secp256k1<All>, keypair: &KeyPair, created_at: u64, kind: u64,
+		tags: Vec<Vec<String>>, content: String,
+	) -> Self {
+		let pubkey = hex_utils::hex_str(&XOnlyPublicKey::from_keypair(secp, keypair).serialize());
+		let id = event_id(&pubkey, created_at, kind, &tags, &content);
+		let sig = secp.schnorrsig_sign_no_aux_rand(&Message::from_slice(&id).unwrap(), keypair);
+		Self {
+			id: hex_utils::hex_str(&id),
+			pubkey,
+			created_at,
+			kind,
+			tags,
+			content,
+			sig: hex_utils::hex_str(&sig[..]),
+		}
+	}
+
+	/// Check that the event's ID is the hash of its content, and that it is signed by its pubkey
+	fn verify(&self, secp: &Secp256k1<All>) -> Result<(), String> {
+		let id = event_id(&self.pubkey, self.created_at, self.kind, &self.tags, &self.content);
+		if hex_utils::to_vec(&self.id) != Some(id.to_vec()) {
+			return Err("the event ID doesn't match its content".to_string());
+		}
+		let pubkey = hex_utils::to_vec(&self.pubkey)
+			.and_then(|pubkey| XOnlyPublicKey::from_slice(&pubkey).ok())
+			.ok_or_else(|| "invalid event pubkey".to_string())?;
+		let sig = hex_utils::to_vec(&self.sig)
+			.and_then(|sig| Signature::from_slice(&sig).ok())
+			.ok_or_else(|| "invalid event signature".to_string())?;
+		secp.schnorrsig_verify(&sig, &Message::from_slice(&id).unwrap(), &pubkey)
+			.map_err(|_| "the event's signature is invalid".to_string())
+	}
+
+	/// Values of the tags named `name`
+	fn tags(&self, name: &str) -> Vec<&[String]> {
+		self.tags
+			.iter()
+			.filter(|tag| tag.first().map(String::as_str) == Some(name))
+			.map(|tag| &tag[1..])
+			.collect()
+	}
+}
+
+/// Hash of the serialization of an event, which identifies it and is signed
+fn event_id(
+	pubkey: &str, created_at: u64, kind: u64, tags: &Vec<Vec<String>>, content: &str,
+) -> [u8; 32] {
+	let serialized = json!([0, pubkey, created_at, kind, tags, content]).to_string();
+	Sha256::hash(serialized.as_bytes()).into_inner()
+}
+
+/// Receiver of Nostr zaps: an LNURL-pay service whose invoices commit to zap requests, and the
+/// publisher of the receipts of the zaps paid
+pub struct ZapService {
+	channel_manager: Arc<ChannelManager>,
+	keys_manager: Arc<NodeKeysManager>,
+	inbound_payments: PaymentInfoStorage,
+	store: Arc<dyn NodeStore>,
+	channel_policy: Arc<Mutex<ChannelPolicy>>,
+	currency: Currency,
+	secp: Secp256k1<All>,
+	keypair: KeyPair,
+	base_url: String,
+	username: String,
+	relays: Vec<String>,
+	min_sendable_msat: u64,
+	max_sendable_msat: u64,
+}
+
+impl ZapService {
+	/// Create the zap receiver configured by `settings`, or `None` if it isn't enabled
+	pub(crate) fn new(
+		settings: &ZapSettings, channel_manager: Arc<ChannelManager>,
+		keys_manager: Arc<NodeKeysManager>, inbound_payments: PaymentInfoStorage,
+		store: Arc<dyn NodeStore>, channel_policy: Arc<Mutex<ChannelPolicy>>, network: Network,
+	) -> Result<Option<Self>, String> {
+		let (base_url, secret_key) = match (&settings.base_url, &settings.nostr_secret_key) {
+			(Some(base_url), Some(secret_key)) => (base_url, secret_key),
+			(None, None) => return Ok(None),
+			_ => return Err("zaps require both base_url and nostr_secret_key".to_string()),
+		};
+		if settings.min_sendable_msat > settings.max_sendable_msat {
+			return Err("min_sendable_msat is above max_sendable_msat".to_string());
+		}
+		let secp = Secp256k1::new();
+		let keypair = hex_utils::to_vec(secret_key)
+			.and_then(|secret_key| KeyPair::from_seckey_slice(&secp, &secret_key).ok())
+			.ok_or_else(|| "nostr_secret_key must be a secret key as 64 hex digits".to_string())?;
+		Ok(Some(Self {
+			channel_manager,
+			keys_manager,
+			inbound_payments,
+			store,
+			channel_policy,
+			currency: invoice::currency(network),
+			secp,
+			keypair,
+			base_url: base_url.trim_end_matches('/').to_string(),
+			username: settings.username.clone(),
+			relays: settings.relays.clone(),
+			min_sendable_msat: settings.min_sendable_msat,
+			max_sendable_msat: settings.max_sendable_msat,
+		}))
+	}
+
+	fn nostr_pubkey(&self) -> String {
+		hex_utils::hex_str(&XOnlyPublicKey::from_keypair(&self.secp, &self.keypair).serialize())
+	}
+
+	/// LUD-06 metadata, whose hash the invoices of payments without a zap request commit to
+	fn metadata(&self) -> String {
+		json!([["text/plain", format!("Zap {}", self.username)]]).to_string()
+	}
+
+	/// Check `zap_request` as NIP-57 requires of the receiver of a zap of `amount_msat`
+	fn validate_zap_request(
+		&self, zap_request: &str, amount_msat: u64,
+	) -> Result<NostrEvent, String> {
+		let event: NostrEvent =
+			serde_json::from_str(zap_request).map_err(|e| format!("invalid zap request: {}", e))?;
+		if event.kind != ZAP_REQUEST_KIND {
+			return Err(format!("a zap request has kind {}, not {}", ZAP_REQUEST_KIND, event.kind));
+		}
+		event.verify(&self.secp).map_err(|e| format!("invalid zap request: {}", e))?;
+		let recipients = event.tags("p");
+		if recipients.len() != 1 || recipients[0].is_empty() {
+			return Err("a zap request must have exactly one p tag".to_string());
+		}
+		if event.tags("e").len() > 1 {
+			return Err("a zap request must have at most one e tag".to_string());
+		}
+		for amount in event.tags("amount") {
+			if amount.first().map(String::as_str) != Some(amount_msat.to_string().as_str()) {
+				return Err("the zap request's amount doesn't match the amount paid".to_string());
+			}
+		}
+		Ok(event)
+	}
+
+	/// Create an invoice of `amount_msat`, committing to `zap_request` if there is one
+	async fn create_invoice(
+		&self, amount_msat: u64, zap_request: Option<&str>,
+	) -> Result<String, String> {
+		if amount_msat < self.min_sendable_msat || amount_msat > self.max_sendable_msat {
+			return Err(format!(
+				"the amount has to be between {} and {} msat",
+				self.min_sendable_msat, self.max_sendable_msat
+			));
+		}
+		// The invoice commits to the zap request, or else to the metadata, as LUD-06 requires.
+		// The description recorded with the invoice is readable either way.
+		let (committed, description, label) = match zap_request {
+			Some(zap_request) => {
+				let event = self.validate_zap_request(zap_request, amount_msat)?;
+				let description = if event.content.is_empty() {
+					format!("Zap from {}", event.pubkey)
+				} else {
+					event.content
+				};
+				(zap_request.to_string(), description, "zap")
+			}
+			None => (self.metadata(), format!("Zap {}", self.username), "lnurl-pay"),
+		};
+
+		let mut payments = self.inbound_payments.write().await;
+		let inv = invoice::create_invoice_with_description_hash(
+			&self.channel_manager,
+			&self.keys_manager,
+			self.currency.clone(),
+			Some(amount_msat),
+			Sha256::hash(committed.as_bytes()),
+			invoice::DEFAULT_INVOICE_EXPIRY_SECS,
+			self.channel_policy.lock().unwrap().min_final_cltv_expiry,
+		)?;
+		let payment_hash = invoice::record_invoice(
+			&*self.store,
+			&mut payments,
+			&inv,
+			amount_msat,
+			description,
+			Some(label.to_string()),
+		);
+		if let Some(zap_request) = zap_request {
+			let zap_request =
+				ZapRequestInfo { zap_request: zap_request.to_string(), bolt11: inv.to_string() };
+			disk::persist_zap_request(&*self.store, &payment_hash, &zap_request)
+				.map_err(|e| format!("failed to persist the zap request: {}", e))?;
+		}
+		Ok(inv.to_string())
+	}
+
+	/// Zap receipt of a zap paid at `paid_at`, and the relays it is published to
+	fn zap_receipt(
+		&self, zap_request: &ZapRequestInfo, paid_at: u64,
+	) -> Result<(NostrEvent, Vec<String>), String> {
+		let request: NostrEvent = serde_json::from_str(&zap_request.zap_request)
+			.map_err(|e| format!("invalid zap request: {}", e))?;
+		let mut tags = Vec::new();
+		for name in ["p", "e", "a"] {
+			for values in request.tags(name) {
+				if let Some(value) = values.first() {
+					tags.push(vec![name.to_string(), value.clone()]);
+				}
+			}
+		}
+		tags.push(vec!["P".to_string(), request.pubkey.clone()]);
+		tags.push(vec!["bolt11".to_string(), zap_request.bolt11.clone()]);
+		tags.push(vec!["description".to_string(), zap_request.zap_request.clone()]);
+		let receipt = NostrEvent::sign(
+			&self.secp,
+			&self.keypair,
+			paid_at,
+			ZAP_RECEIPT_KIND,
+			tags,
+			String::new(),
+		);
+
+		let mut relays = self.relays.clone();
+		for values in request.tags("relays") {
+			relays.extend(values.iter().cloned());
+		}
+		relays.sort();
+		relays.dedup();
+		Ok((receipt, relays))
+	}
+}
+
+/// Error response, as LNURL services give them
+fn lnurl_error(reason: String) -> HttpResponse {
+	HttpResponse::BadRequest()
+		.content_type(ContentType::json())
+		.json(json!({ "status": "ERROR", "reason": reason }))
+}
+
+/// Serve the LNURL-pay parameters of the lightning address `<username>@<host>`
+async fn pay_request(path: web::Path<String>, zaps: web::Data<ZapService>) -> HttpResponse {
+	if path.as_str() != zaps.username {
+		return HttpResponse::NotFound()
+			.content_type(ContentType::json())
+			.json(json!({ "status": "ERROR", "reason": "unknown user" }));
+	}
+	HttpResponse::Ok().content_type(ContentType::json()).json(json!({
+		"tag": "payRequest",
+		"callback": format!("{}/lnurlp/callback", zaps.base_url),
+		"minSendable": zaps.min_sendable_msat,
+		"maxSendable": zaps.max_sendable_msat,
+		"metadata": zaps.metadata(),
+		"allowsNostr": true,
+		"nostrPubkey": zaps.nostr_pubkey(),
+	}))
+}
+
+// LNURL-pay callback query. `nostr` is the zap request of a zap.
+#[derive(Deserialize)]
+struct PayCallback {
+	amount: Option<u64>,
+	nostr: Option<String>,
+}
+
+/// Create the invoice of an LNURL-pay payment or of a zap
+async fn pay_callback(query: web::Query<PayCallback>, zaps: web::Data<ZapService>) -> HttpResponse {
+	let amount_msat = match query.amount {
+		Some(amount_msat) => amount_msat,
+		None => return lnurl_error("an amount in millisatoshis is required".to_string()),
+	};
+	match zaps.create_invoice(amount_msat, query.nostr.as_deref()).await {
+		Ok(invoice) => HttpResponse::Ok()
+			.content_type(ContentType::json())
+			.json(json!({ "pr": invoice, "routes": [] })),
+		Err(e) => lnurl_error(e),
+	}
+}
+
+/// Run the zap server on `addr`. It only serves the lightning address, without the node's API
+/// token, so it can be exposed.
+pub(crate) fn run_server(zaps: Arc<ZapService>, addr: &str) -> io::Result<Server> {
+	let zaps = web::Data::from(zaps);
+	let server = HttpServer::new(move || {
+		App::new()
+			.route("/.well-known/lnurlp/{username}", web::get().to(pay_request))
+			.route("/lnurlp/callback", web::get().to(pay_callback))
+			.app_data(zaps.clone())
+	})
+	.bind(addr)?
+	.run();
+	Ok(server)
+}
+
+/// Publish the receipt of each zap paid, as notified on `events`, until the node shuts down
+pub(crate) async fn run_publisher(
+	zaps: Arc<ZapService>, mut events: broadcast::Receiver<NodeEvent>,
+) {
+	loop {
+		let payment_hash = match events.recv().await {
+			Ok(NodeEvent::PaymentReceived { payment_hash, .. }) => payment_hash,
+			Ok(_) => continue,
+			Err(broadcast::error::RecvError::Lagged(missed)) => {
+				warn!("{} events were missed by the zap receipt publisher", missed);
+				continue;
+			}
+			Err(broadcast::error::RecvError::Closed) => return,
+		};
+		let payment_hash = match hex_utils::to_vec(&payment_hash) {
+			Some(bytes) if bytes.len() == 32 => {
+				let mut hash = [0; 32];
+				hash.copy_from_slice(&bytes);
+				PaymentHash(hash)
+			}
+			_ => continue,
+		};
+		let zap_request = match disk::read_zap_request(&*zaps.store, &payment_hash) {
+			Ok(Some(zap_request)) => zap_request,
+			Ok(None) => continue,
+			Err(e) => {
+				warn!("failed to read zap request: {}", e);
+				continue;
+			}
+		};
+		// The event is also notified for payments which couldn't be claimed
+		let paid_at = match disk::read_invoice(&*zaps.store, &payment_hash) {
+			Ok(Some(invoice)) => match invoice.settled_at {
+				Some(settled_at) => settled_at,
+				None => continue,
+			},
+			_ => continue,
+		};
+		match zaps.zap_receipt(&zap_request, paid_at) {
+			Ok((receipt, relays)) => {
+				for relay in relays {
+					let receipt = receipt.clone();
+					tokio::spawn(async move {
+						if let Err(e) = publish(&relay, &receipt).await {
+							warn!("failed to publish zap receipt to {}: {}", relay, e);
+						}
+					});
+				}
+			}
+			Err(e) => warn!("failed to create zap receipt: {}", e),
+		}
+		if disk::remove_zap_request(&*zaps.store, &payment_hash).is_err() {
+			warn!("failed to remove zap request, check your disk and permissions");
+		}
+	}
+}
+
+/// Send `event` to `relay`, a `wss://` URL, waiting for the relay to accept it
+async fn publish(relay: &str, event: &NostrEvent) -> Result<(), String> {
+	let (mut socket, _) =
+		tokio::time::timeout(RELAY_TIMEOUT, tokio_tungstenite::connect_async(relay))
+			.await
+			.map_err(|_| "timed out connecting".to_string())?
+			.map_err(|e| e.to_string())?;
+	let message = json!(["EVENT", event]).to_string();
+	socket.send(WsMessage::Text(message)).await.map_err(|e| e.to_string())?;
+	// Relays answer `["OK", <event id>, <accepted>, <message>]`, as in NIP-20
+	let reply = tokio::time::timeout(RELAY_TIMEOUT, socket.next()).await;
+	let _ = socket.close(None).await;
+	match reply {
+		Ok(Some(Ok(WsMessage::Text(reply)))) => {
+			match serde_json::from_str::<serde_json::Value>(&reply) {
+				Ok(reply) if reply[0] == "OK" && reply[2] == false => {
+					Err(format!("the relay rejected it: {}", reply[3]))
+				}
+				_ => Ok(()),
+			}
+		}
+		Ok(Some(Err(e))) => Err(e.to_string()),
+		Ok(_) => Ok(()),
+		Err(_) => Err("timed out waiting for the relay".to_string()),
+	}
+}